@@ -139,6 +139,22 @@ impl BenchProfile {
             Self::Large => 80_000,
         }
     }
+
+    fn parallel_read_rows(self) -> usize {
+        match self {
+            Self::Smoke => 1_000,
+            Self::Default => 5_000,
+            Self::Large => 20_000,
+        }
+    }
+
+    fn parallel_reads_per_thread(self) -> usize {
+        match self {
+            Self::Smoke => 5,
+            Self::Default => 20,
+            Self::Large => 50,
+        }
+    }
 }
 
 impl fmt::Display for BenchProfile {
@@ -292,6 +308,26 @@ fn bench_definitions() -> Vec<BenchDefinition> {
             description: "Memory benchmark for grouped aggregation with ROLLUP.",
             prepare: prepare_rollup,
         },
+        BenchDefinition {
+            name: "parallel_reads_1t",
+            description: "Memory benchmark for read throughput from 1 thread, the baseline for the parallel_reads cases.",
+            prepare: prepare_parallel_reads_1t,
+        },
+        BenchDefinition {
+            name: "parallel_reads_2t",
+            description: "Memory benchmark for read throughput from 2 threads with their own sessions.",
+            prepare: prepare_parallel_reads_2t,
+        },
+        BenchDefinition {
+            name: "parallel_reads_4t",
+            description: "Memory benchmark for read throughput from 4 threads with their own sessions.",
+            prepare: prepare_parallel_reads_4t,
+        },
+        BenchDefinition {
+            name: "parallel_reads_8t",
+            description: "Memory benchmark for read throughput from 8 threads with their own sessions.",
+            prepare: prepare_parallel_reads_8t,
+        },
     ]
 }
 
@@ -657,14 +693,77 @@ fn prepare_rollup(profile: BenchProfile) -> PreparedBench {
     }
 }
 
+fn prepare_parallel_reads_1t(profile: BenchProfile) -> PreparedBench {
+    prepare_parallel_reads(profile, 1)
+}
+
+fn prepare_parallel_reads_2t(profile: BenchProfile) -> PreparedBench {
+    prepare_parallel_reads(profile, 2)
+}
+
+fn prepare_parallel_reads_4t(profile: BenchProfile) -> PreparedBench {
+    prepare_parallel_reads(profile, 4)
+}
+
+fn prepare_parallel_reads_8t(profile: BenchProfile) -> PreparedBench {
+    prepare_parallel_reads(profile, 8)
+}
+
+/// Every thread runs the same number of queries, so a sample time that stays
+/// flat as threads are added means read throughput scales with them.
+fn prepare_parallel_reads(profile: BenchProfile, threads: usize) -> PreparedBench {
+    let rows = profile.parallel_read_rows();
+    let queries = profile.parallel_reads_per_thread();
+    let engine = open_memory_engine();
+
+    {
+        let mut session = engine.session();
+        session
+            .execute_script(
+                "
+                CREATE TABLE bench_events (
+                    id INTEGER,
+                    kind INTEGER,
+                    amount INTEGER
+                );
+                ",
+            )
+            .unwrap();
+        insert_rows(&mut session, "bench_events", rows, |index| {
+            format!("({}, {}, {})", index, index % 32, (index * 37) % 1_000)
+        });
+    }
+
+    let query = "SELECT kind, SUM(amount) AS total \
+                 FROM bench_events \
+                 WHERE amount >= 100 \
+                 GROUP BY kind";
+    PreparedBench {
+        scale: format!("{rows} rows, {threads} threads"),
+        run: Box::new(move || {
+            std::thread::scope(|scope| {
+                for _ in 0..threads {
+                    let mut session = engine.session();
+                    scope.spawn(move || {
+                        for _ in 0..queries {
+                            black_box(session.execute_one(query).unwrap());
+                        }
+                    });
+                }
+            });
+        }),
+    }
+}
+
 fn open_memory_engine() -> Engine {
     Engine::open(EngineOptions {
         storage: StorageMode::Memory,
+        ..EngineOptions::default()
     })
     .unwrap()
 }
 
-fn insert_rows<F>(session: &mut Session<'_>, table: &str, row_count: usize, row_sql: F)
+fn insert_rows<F>(session: &mut Session, table: &str, row_count: usize, row_sql: F)
 where
     F: FnMut(usize) -> String,
 {
@@ -672,7 +771,7 @@ where
 }
 
 fn insert_rows_with_batch<F>(
-    session: &mut Session<'_>,
+    session: &mut Session,
     table: &str,
     row_count: usize,
     batch_size: usize,
@@ -693,7 +792,7 @@ fn insert_rows_with_batch<F>(
     }
 }
 
-fn flush_insert_batch(session: &mut Session<'_>, table: &str, batch: &mut Vec<String>) {
+fn flush_insert_batch(session: &mut Session, table: &str, batch: &mut Vec<String>) {
     let sql = format!("INSERT INTO {table} VALUES {}", batch.join(", "));
     session.execute_one(&sql).unwrap();
     batch.clear();
@@ -36,4 +36,7 @@ fn unique_temp_path(label: &str) -> PathBuf {
 fn cleanup_storage_files(path: &Path) {
     let _ = std::fs::remove_file(path);
     let _ = std::fs::remove_file(wal_path(path));
+    let mut lock = path.as_os_str().to_os_string();
+    lock.push(".lock");
+    let _ = std::fs::remove_file(PathBuf::from(lock));
 }
@@ -1,3 +1,5 @@
+use crate::decimal::Decimal;
+use crate::interval::Interval;
 use serde::{Deserialize, Serialize};
 use std::{cmp::Ordering, fmt};
 
@@ -15,19 +17,32 @@ pub enum Statement {
     TruncateTable { table_name: String },
     CreateView { name: String, query_sql: String },
     DropView { name: String, if_exists: bool },
-    BeginTransaction,
+    BeginTransaction(TransactionModes),
+    SetTransaction(TransactionModes),
+    SetConstraints { deferred: bool },
+    SetStatementTimeout { milliseconds: u64 },
     CommitTransaction,
     RollbackTransaction,
     Savepoint(String),
     ReleaseSavepoint(String),
     RollbackToSavepoint(String),
+    PrepareTransaction(String),
+    CommitPrepared(String),
+    RollbackPrepared(String),
     Explain(SelectStatement),
     ExplainAnalyze(SelectStatement),
     Describe(String),
     ShowTables,
+    ShowStorage,
     Analyze(String),
     Merge(MergeStatement),
     Do { statements: Vec<Statement> },
+    Checkpoint,
+    Backup { path: String },
+    Vacuum,
+    CheckDatabase,
+    Attach { path: String, name: String },
+    Detach(String),
 }
 
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
@@ -171,6 +186,8 @@ pub enum AggregateFunctionType {
     Mode,
     PercentileCont,
     PercentileDisc,
+    JsonAgg,
+    JsonObjectAgg,
 }
 
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
@@ -241,6 +258,8 @@ pub enum TableConstraint {
     Unique {
         name: Option<String>,
         columns: Vec<String>,
+        #[serde(default)]
+        deferrable: Deferrable,
     },
 }
 
@@ -282,6 +301,21 @@ pub struct ForeignKeyConstraint {
     pub referenced_column: String,
     pub on_delete: ForeignKeyAction,
     pub on_update: ForeignKeyAction,
+    #[serde(default)]
+    pub deferrable: Deferrable,
+}
+
+/// When a transaction checks a `UNIQUE` or `FOREIGN KEY` constraint.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Deferrable {
+    /// Checked by every statement.
+    #[default]
+    #[serde(rename = "NotDeferrable")]
+    No,
+    /// Checked by every statement until `SET CONSTRAINTS ALL DEFERRED`.
+    InitiallyImmediate,
+    /// Checked at `COMMIT` until `SET CONSTRAINTS ALL IMMEDIATE`.
+    InitiallyDeferred,
 }
 
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
@@ -296,11 +330,43 @@ pub enum ForeignKeyAction {
 pub enum DataType {
     Integer,
     Float,
+    /// `DECIMAL(precision, scale)`: exact numbers of at most `precision`
+    /// digits, `scale` of them after the decimal point.
+    Decimal {
+        precision: u32,
+        scale: u32,
+    },
     Text,
     Boolean,
     Date,
     Time,
     DateTime,
+    /// `TIMESTAMP WITH TIME ZONE`: an instant, stored as UTC.
+    TimestampTz,
+    Interval,
+    Blob,
+    Json,
+}
+
+impl fmt::Display for DataType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DataType::Integer => f.write_str("INTEGER"),
+            DataType::Float => f.write_str("FLOAT"),
+            DataType::Decimal { precision, scale } => {
+                write!(f, "DECIMAL({},{})", precision, scale)
+            }
+            DataType::Text => f.write_str("TEXT"),
+            DataType::Boolean => f.write_str("BOOLEAN"),
+            DataType::Date => f.write_str("DATE"),
+            DataType::Time => f.write_str("TIME"),
+            DataType::DateTime => f.write_str("DATETIME"),
+            DataType::TimestampTz => f.write_str("TIMESTAMPTZ"),
+            DataType::Interval => f.write_str("INTERVAL"),
+            DataType::Blob => f.write_str("BLOB"),
+            DataType::Json => f.write_str("JSON"),
+        }
+    }
 }
 
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
@@ -340,6 +406,36 @@ pub struct DropIndexStatement {
     pub if_exists: bool,
 }
 
+/// The modes named by `BEGIN` or `SET TRANSACTION`; `None` leaves a mode as
+/// it was.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct TransactionModes {
+    pub isolation_level: Option<IsolationLevel>,
+    pub read_only: Option<bool>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum IsolationLevel {
+    /// Every statement sees the rows committed before it started.
+    ReadCommitted,
+    /// Every statement sees the snapshot taken at `BEGIN`.
+    #[default]
+    RepeatableRead,
+    /// Like `RepeatableRead`, and a commit also fails when a concurrent
+    /// transaction changed anything the transaction read.
+    Serializable,
+}
+
+impl fmt::Display for IsolationLevel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            IsolationLevel::ReadCommitted => "READ COMMITTED",
+            IsolationLevel::RepeatableRead => "REPEATABLE READ",
+            IsolationLevel::Serializable => "SERIALIZABLE",
+        })
+    }
+}
+
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct WindowDefinition {
     pub name: String,
@@ -524,6 +620,19 @@ pub enum ScalarFunctionType {
     Translate,
     RegexpMatch,
     RegexpReplace,
+    Encode,
+    Decode,
+    JsonExtract,
+    JsonSet,
+    JsonArrayLength,
+    JsonTypeof,
+    JsonBuildObject,
+    JustifyDays,
+    JustifyHours,
+    JustifyInterval,
+    Age,
+    /// `value AT TIME ZONE zone`, with the zone as the first argument.
+    Timezone,
 }
 
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
@@ -584,6 +693,14 @@ pub enum BinaryOperator {
     Between,
     In,
     Concat,
+    /// `->`: the member or element of a JSON value.
+    JsonField,
+    /// `->>`: the member or element of a JSON value, as text.
+    JsonFieldText,
+    /// `#>`: the JSON value at a path such as `'{a,0}'`.
+    JsonPath,
+    /// `@>`: whether a JSON value contains another.
+    JsonContains,
 }
 
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
@@ -597,11 +714,18 @@ pub enum Value {
     Null,
     Integer(i64),
     Float(f64),
+    Decimal(Decimal),
     Text(String),
     Boolean(bool),
     Date(String),
     Time(String),
     DateTime(String),
+    /// An instant as its UTC `YYYY-MM-DD HH:MM:SS[.ffffff]` text.
+    TimestampTz(String),
+    Interval(Interval),
+    Blob(#[serde(with = "crate::blob::base64_serde")] Vec<u8>),
+    /// A JSON document in normalized text form.
+    Json(String),
 }
 
 impl Value {
@@ -610,11 +734,16 @@ impl Value {
             Value::Null => 0,
             Value::Integer(_) => 1,
             Value::Float(_) => 2,
-            Value::Text(_) => 3,
-            Value::Boolean(_) => 4,
-            Value::Date(_) => 5,
-            Value::Time(_) => 6,
-            Value::DateTime(_) => 7,
+            Value::Decimal(_) => 3,
+            Value::Text(_) => 4,
+            Value::Boolean(_) => 5,
+            Value::Date(_) => 6,
+            Value::Time(_) => 7,
+            Value::DateTime(_) => 8,
+            Value::Blob(_) => 9,
+            Value::Json(_) => 10,
+            Value::Interval(_) => 11,
+            Value::TimestampTz(_) => 12,
         }
     }
 }
@@ -625,11 +754,16 @@ impl fmt::Display for Value {
             Value::Null => f.write_str("NULL"),
             Value::Integer(value) => write!(f, "{}", value),
             Value::Float(value) => write!(f, "{}", value),
+            Value::Decimal(value) => write!(f, "{}", value),
             Value::Text(value) => f.write_str(value),
             Value::Boolean(value) => write!(f, "{}", value),
             Value::Date(value) => f.write_str(value),
             Value::Time(value) => f.write_str(value),
             Value::DateTime(value) => f.write_str(value),
+            Value::TimestampTz(value) => write!(f, "{}+00", value),
+            Value::Interval(value) => write!(f, "{}", value),
+            Value::Blob(bytes) => write!(f, "\\x{}", crate::blob::encode_hex(bytes)),
+            Value::Json(value) => f.write_str(value),
         }
     }
 }
@@ -661,11 +795,16 @@ impl Ord for Value {
             (Value::Null, Value::Null) => Ordering::Equal,
             (Value::Integer(a), Value::Integer(b)) => a.cmp(b),
             (Value::Float(a), Value::Float(b)) => compare_floats(*a, *b),
+            (Value::Decimal(a), Value::Decimal(b)) => a.cmp(b),
             (Value::Text(a), Value::Text(b)) => a.cmp(b),
             (Value::Boolean(a), Value::Boolean(b)) => a.cmp(b),
             (Value::Date(a), Value::Date(b)) => a.cmp(b),
             (Value::Time(a), Value::Time(b)) => a.cmp(b),
             (Value::DateTime(a), Value::DateTime(b)) => a.cmp(b),
+            (Value::TimestampTz(a), Value::TimestampTz(b)) => a.cmp(b),
+            (Value::Interval(a), Value::Interval(b)) => a.cmp(b),
+            (Value::Blob(a), Value::Blob(b)) => a.cmp(b),
+            (Value::Json(a), Value::Json(b)) => a.cmp(b),
             _ => self.sort_rank().cmp(&other.sort_rank()),
         }
     }
@@ -14,8 +14,8 @@ use aggregates::{aggregate_from_bound, group_by_from_bound};
 use scope::{bound_column, ensure_single_column_subquery};
 use types::{
     bound_type_or_text, column_definition, column_ref_type, common_numeric_type, common_type,
-    ensure_boolean, ensure_comparable, ensure_integer, ensure_numeric, ensure_text,
-    scalar_function_type, value_type, window_function_type,
+    ensure_boolean, ensure_comparable, ensure_integer, ensure_json, ensure_numeric, ensure_text,
+    scalar_function_type, temporal_arithmetic_type, value_type, window_function_type,
 };
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -177,19 +177,32 @@ pub enum BoundStatement {
     TruncateTable { table_name: String },
     CreateView { name: String, query_sql: String },
     DropView { name: String, if_exists: bool },
-    BeginTransaction,
+    BeginTransaction(TransactionModes),
+    SetTransaction(TransactionModes),
+    SetConstraints { deferred: bool },
+    SetStatementTimeout { milliseconds: u64 },
     CommitTransaction,
     RollbackTransaction,
     Savepoint(String),
     ReleaseSavepoint(String),
     RollbackToSavepoint(String),
+    PrepareTransaction(String),
+    CommitPrepared(String),
+    RollbackPrepared(String),
     Explain(BoundSelectStatement),
     ExplainAnalyze(BoundSelectStatement),
     Describe(String),
     ShowTables,
+    ShowStorage,
     Analyze(String),
     Merge(MergeStatement),
     Do { statements: Vec<Statement> },
+    Checkpoint,
+    Backup { path: String },
+    Vacuum,
+    CheckDatabase,
+    Attach { path: String, name: String },
+    Detach(String),
 }
 
 impl BoundStatement {
@@ -209,19 +222,34 @@ impl BoundStatement {
                 Statement::CreateView { name, query_sql }
             }
             BoundStatement::DropView { name, if_exists } => Statement::DropView { name, if_exists },
-            BoundStatement::BeginTransaction => Statement::BeginTransaction,
+            BoundStatement::BeginTransaction(modes) => Statement::BeginTransaction(modes),
+            BoundStatement::SetTransaction(modes) => Statement::SetTransaction(modes),
+            BoundStatement::SetConstraints { deferred } => Statement::SetConstraints { deferred },
+            BoundStatement::SetStatementTimeout { milliseconds } => {
+                Statement::SetStatementTimeout { milliseconds }
+            }
             BoundStatement::CommitTransaction => Statement::CommitTransaction,
             BoundStatement::RollbackTransaction => Statement::RollbackTransaction,
             BoundStatement::Savepoint(name) => Statement::Savepoint(name),
             BoundStatement::ReleaseSavepoint(name) => Statement::ReleaseSavepoint(name),
             BoundStatement::RollbackToSavepoint(name) => Statement::RollbackToSavepoint(name),
+            BoundStatement::PrepareTransaction(gid) => Statement::PrepareTransaction(gid),
+            BoundStatement::CommitPrepared(gid) => Statement::CommitPrepared(gid),
+            BoundStatement::RollbackPrepared(gid) => Statement::RollbackPrepared(gid),
             BoundStatement::Explain(stmt) => Statement::Explain(stmt.statement),
             BoundStatement::ExplainAnalyze(stmt) => Statement::ExplainAnalyze(stmt.statement),
             BoundStatement::Describe(name) => Statement::Describe(name),
             BoundStatement::ShowTables => Statement::ShowTables,
+            BoundStatement::ShowStorage => Statement::ShowStorage,
             BoundStatement::Analyze(name) => Statement::Analyze(name),
             BoundStatement::Merge(stmt) => Statement::Merge(stmt),
             BoundStatement::Do { statements } => Statement::Do { statements },
+            BoundStatement::Checkpoint => Statement::Checkpoint,
+            BoundStatement::Backup { path } => Statement::Backup { path },
+            BoundStatement::Vacuum => Statement::Vacuum,
+            BoundStatement::CheckDatabase => Statement::CheckDatabase,
+            BoundStatement::Attach { path, name } => Statement::Attach { path, name },
+            BoundStatement::Detach(name) => Statement::Detach(name),
         }
     }
 }
@@ -388,21 +416,38 @@ impl<'a> Binder<'a> {
             Statement::DropView { name, if_exists } => {
                 Ok(BoundStatement::DropView { name, if_exists })
             }
-            Statement::BeginTransaction => Ok(BoundStatement::BeginTransaction),
+            Statement::BeginTransaction(modes) => Ok(BoundStatement::BeginTransaction(modes)),
+            Statement::SetTransaction(modes) => Ok(BoundStatement::SetTransaction(modes)),
+            Statement::SetConstraints { deferred } => {
+                Ok(BoundStatement::SetConstraints { deferred })
+            }
+            Statement::SetStatementTimeout { milliseconds } => {
+                Ok(BoundStatement::SetStatementTimeout { milliseconds })
+            }
             Statement::CommitTransaction => Ok(BoundStatement::CommitTransaction),
             Statement::RollbackTransaction => Ok(BoundStatement::RollbackTransaction),
             Statement::Savepoint(name) => Ok(BoundStatement::Savepoint(name)),
             Statement::ReleaseSavepoint(name) => Ok(BoundStatement::ReleaseSavepoint(name)),
             Statement::RollbackToSavepoint(name) => Ok(BoundStatement::RollbackToSavepoint(name)),
+            Statement::PrepareTransaction(gid) => Ok(BoundStatement::PrepareTransaction(gid)),
+            Statement::CommitPrepared(gid) => Ok(BoundStatement::CommitPrepared(gid)),
+            Statement::RollbackPrepared(gid) => Ok(BoundStatement::RollbackPrepared(gid)),
             Statement::Explain(stmt) => Ok(BoundStatement::Explain(self.bind_select(&stmt)?)),
             Statement::ExplainAnalyze(stmt) => {
                 Ok(BoundStatement::ExplainAnalyze(self.bind_select(&stmt)?))
             }
             Statement::Describe(name) => Ok(BoundStatement::Describe(name)),
             Statement::ShowTables => Ok(BoundStatement::ShowTables),
+            Statement::ShowStorage => Ok(BoundStatement::ShowStorage),
             Statement::Analyze(name) => Ok(BoundStatement::Analyze(name)),
             Statement::Merge(stmt) => Ok(BoundStatement::Merge(self.bind_merge(stmt)?)),
             Statement::Do { statements } => Ok(BoundStatement::Do { statements }),
+            Statement::Checkpoint => Ok(BoundStatement::Checkpoint),
+            Statement::Backup { path } => Ok(BoundStatement::Backup { path }),
+            Statement::Vacuum => Ok(BoundStatement::Vacuum),
+            Statement::CheckDatabase => Ok(BoundStatement::CheckDatabase),
+            Statement::Attach { path, name } => Ok(BoundStatement::Attach { path, name }),
+            Statement::Detach(name) => Ok(BoundStatement::Detach(name)),
         }
     }
 
@@ -773,7 +818,9 @@ impl<'a> Binder<'a> {
                         BoundType::Known(DataType::Boolean)
                     }
                     UnaryOperator::Minus => {
-                        ensure_numeric(&bound, "Unary minus")?;
+                        if bound.data_type != BoundType::Known(DataType::Interval) {
+                            ensure_numeric(&bound, "Unary minus")?;
+                        }
                         bound.data_type.clone()
                     }
                 };
@@ -1125,7 +1172,9 @@ impl<'a> Binder<'a> {
                         BoundType::Known(DataType::Boolean)
                     }
                     UnaryOperator::Minus => {
-                        ensure_numeric(&expr, "ORDER BY expression")?;
+                        if expr.data_type != BoundType::Known(DataType::Interval) {
+                            ensure_numeric(&expr, "ORDER BY expression")?;
+                        }
                         expr.data_type.clone()
                     }
                 };
@@ -1198,11 +1247,49 @@ impl<'a> Binder<'a> {
             | BinaryOperator::Minus
             | BinaryOperator::Multiply
             | BinaryOperator::Divide => {
+                if let Some(data_type) =
+                    temporal_arithmetic_type(op, &left.data_type, &right.data_type)?
+                {
+                    return Ok(data_type);
+                }
                 ensure_numeric(left, "Arithmetic expression")?;
                 ensure_numeric(right, "Arithmetic expression")?;
                 Ok(common_numeric_type(&left.data_type, &right.data_type))
             }
-            BinaryOperator::Concat => Ok(BoundType::Known(DataType::Text)),
+            BinaryOperator::Concat => Ok(BoundType::Known(
+                match (&left.data_type, &right.data_type) {
+                    (BoundType::Known(DataType::Blob), BoundType::Known(DataType::Blob)) => {
+                        DataType::Blob
+                    }
+                    _ => DataType::Text,
+                },
+            )),
+            BinaryOperator::JsonField | BinaryOperator::JsonFieldText => {
+                ensure_json(left, "JSON field operator")?;
+                if !matches!(
+                    right.data_type,
+                    BoundType::Known(DataType::Text | DataType::Integer) | BoundType::Unknown
+                ) {
+                    return Err(RustqlError::TypeMismatch(
+                        "JSON field operator requires a text key or an integer index".to_string(),
+                    ));
+                }
+                Ok(BoundType::Known(if *op == BinaryOperator::JsonField {
+                    DataType::Json
+                } else {
+                    DataType::Text
+                }))
+            }
+            BinaryOperator::JsonPath => {
+                ensure_json(left, "JSON path operator")?;
+                ensure_text(right, "JSON path operator")?;
+                Ok(BoundType::Known(DataType::Json))
+            }
+            BinaryOperator::JsonContains => {
+                ensure_json(left, "JSON containment operator")?;
+                ensure_json(right, "JSON containment operator")?;
+                Ok(BoundType::Known(DataType::Boolean))
+            }
             BinaryOperator::Like | BinaryOperator::ILike => {
                 ensure_text(left, "LIKE operator")?;
                 ensure_text(right, "LIKE operator")?;
@@ -248,9 +248,44 @@ impl<'a> Binder<'a> {
                     ));
                 }
 
-                for arg in &function.args {
-                    let bound = self.bind_expr(arg, &empty_scope)?;
-                    ensure_integer(&bound, "GENERATE_SERIES")?;
+                let bound = function
+                    .args
+                    .iter()
+                    .map(|arg| self.bind_expr(arg, &empty_scope))
+                    .collect::<Result<Vec<_>, _>>()?;
+                let temporal = matches!(
+                    bound[0].data_type,
+                    BoundType::Known(DataType::Date | DataType::DateTime | DataType::TimestampTz)
+                );
+                if temporal {
+                    let step = bound.get(2).ok_or_else(|| {
+                        RustqlError::TypeMismatch(
+                            "GENERATE_SERIES over dates requires an interval step".to_string(),
+                        )
+                    })?;
+                    if !matches!(
+                        bound[1].data_type,
+                        BoundType::Known(
+                            DataType::Date | DataType::DateTime | DataType::TimestampTz
+                        ) | BoundType::Unknown
+                    ) || !matches!(
+                        step.data_type,
+                        BoundType::Known(DataType::Interval) | BoundType::Unknown
+                    ) {
+                        return Err(RustqlError::TypeMismatch(
+                            "GENERATE_SERIES over dates requires a date or datetime stop and an interval step"
+                                .to_string(),
+                        ));
+                    }
+                    // The planner types the output column from this cast.
+                    function.args[0] = Expression::Cast {
+                        expr: Box::new(function.args[0].clone()),
+                        data_type: DataType::DateTime,
+                    };
+                } else {
+                    for arg in &bound {
+                        ensure_integer(arg, "GENERATE_SERIES")?;
+                    }
                 }
 
                 let relation_label = function.alias.as_deref().unwrap_or(&function.name);
@@ -259,11 +294,35 @@ impl<'a> Binder<'a> {
                         .alias
                         .clone()
                         .unwrap_or_else(|| "generate_series".to_string()),
-                    DataType::Integer,
+                    if temporal {
+                        DataType::DateTime
+                    } else {
+                        DataType::Integer
+                    },
                     false,
                 );
                 Ok(vec![bound_column(relation_label, &column, false)])
             }
+            "json_each" | "json_array_elements" => {
+                let [document] = function.args.as_slice() else {
+                    return Err(RustqlError::TypeMismatch(format!(
+                        "{} expects 1 argument",
+                        function.name.to_uppercase()
+                    )));
+                };
+                let bound = self.bind_expr(document, &empty_scope)?;
+                ensure_json(&bound, &function.name.to_uppercase())?;
+
+                let relation_label = function.alias.as_deref().unwrap_or(&function.name);
+                Ok(crate::json::table_function_columns(&function.name)
+                    .unwrap_or_default()
+                    .into_iter()
+                    .map(|(name, data_type)| {
+                        let column = column_definition(name.to_string(), data_type, true);
+                        bound_column(relation_label, &column, false)
+                    })
+                    .collect())
+            }
             other => Err(RustqlError::TypeMismatch(format!(
                 "Unsupported table function '{}'",
                 other
@@ -1,4 +1,5 @@
 use super::*;
+use crate::decimal::MAX_DECIMAL_PRECISION;
 
 impl BoundType {
     fn known(self) -> Option<DataType> {
@@ -46,14 +47,26 @@ pub(super) fn value_type(value: &Value) -> BoundType {
         Value::Null => BoundType::Unknown,
         Value::Integer(_) => BoundType::Known(DataType::Integer),
         Value::Float(_) => BoundType::Known(DataType::Float),
+        Value::Decimal(value) => decimal_type(value.scale()),
         Value::Text(_) => BoundType::Known(DataType::Text),
         Value::Boolean(_) => BoundType::Known(DataType::Boolean),
         Value::Date(_) => BoundType::Known(DataType::Date),
         Value::Time(_) => BoundType::Known(DataType::Time),
         Value::DateTime(_) => BoundType::Known(DataType::DateTime),
+        Value::TimestampTz(_) => BoundType::Known(DataType::TimestampTz),
+        Value::Interval(_) => BoundType::Known(DataType::Interval),
+        Value::Blob(_) => BoundType::Known(DataType::Blob),
+        Value::Json(_) => BoundType::Known(DataType::Json),
     }
 }
 
+fn decimal_type(scale: u32) -> BoundType {
+    BoundType::Known(DataType::Decimal {
+        precision: MAX_DECIMAL_PRECISION,
+        scale,
+    })
+}
+
 pub(super) fn constant_expression_type(expr: &Expression) -> Option<DataType> {
     if let Expression::Value(value) = expr
         && let BoundType::Known(data_type) = value_type(value)
@@ -95,7 +108,13 @@ fn syntactic_expr_type(expr: &Expression) -> Option<DataType> {
             | BinaryOperator::Divide => {
                 let left = syntactic_expr_type(left)?;
                 let right = syntactic_expr_type(right)?;
-                if matches!(left, DataType::Float) || matches!(right, DataType::Float) {
+                if let Ok(Some(data_type)) = temporal_arithmetic_type(
+                    op,
+                    &BoundType::Known(left.clone()),
+                    &BoundType::Known(right.clone()),
+                ) {
+                    data_type.known()
+                } else if matches!(left, DataType::Float) || matches!(right, DataType::Float) {
                     Some(DataType::Float)
                 } else if matches!(left, DataType::Integer) && matches!(right, DataType::Integer) {
                     Some(DataType::Integer)
@@ -103,8 +122,14 @@ fn syntactic_expr_type(expr: &Expression) -> Option<DataType> {
                     None
                 }
             }
-            BinaryOperator::Concat => Some(DataType::Text),
+            BinaryOperator::Concat => match (syntactic_expr_type(left), syntactic_expr_type(right))
+            {
+                (Some(DataType::Blob), Some(DataType::Blob)) => Some(DataType::Blob),
+                _ => Some(DataType::Text),
+            },
             BinaryOperator::Escape => Some(DataType::Text),
+            BinaryOperator::JsonField | BinaryOperator::JsonPath => Some(DataType::Json),
+            BinaryOperator::JsonFieldText => Some(DataType::Text),
             BinaryOperator::And
             | BinaryOperator::Or
             | BinaryOperator::Equal
@@ -116,7 +141,8 @@ fn syntactic_expr_type(expr: &Expression) -> Option<DataType> {
             | BinaryOperator::Like
             | BinaryOperator::ILike
             | BinaryOperator::Between
-            | BinaryOperator::In => Some(DataType::Boolean),
+            | BinaryOperator::In
+            | BinaryOperator::JsonContains => Some(DataType::Boolean),
         },
         Expression::UnaryOp { op, expr } => match op {
             UnaryOperator::Minus => syntactic_expr_type(expr),
@@ -148,10 +174,14 @@ pub(super) fn aggregate_type(function: &AggregateFunctionType, expr: &BoundExpr)
         AggregateFunctionType::Sum => match expr.data_type {
             BoundType::Known(DataType::Integer) => BoundType::Known(DataType::Integer),
             BoundType::Known(DataType::Float) => BoundType::Known(DataType::Float),
+            BoundType::Known(DataType::Decimal { scale, .. }) => decimal_type(scale),
             _ => BoundType::Unknown,
         },
-        AggregateFunctionType::Avg
-        | AggregateFunctionType::Stddev
+        AggregateFunctionType::Avg => match expr.data_type {
+            BoundType::Known(DataType::Decimal { .. }) => BoundType::Unknown,
+            _ => BoundType::Known(DataType::Float),
+        },
+        AggregateFunctionType::Stddev
         | AggregateFunctionType::Variance
         | AggregateFunctionType::Median
         | AggregateFunctionType::PercentileCont
@@ -163,11 +193,22 @@ pub(super) fn aggregate_type(function: &AggregateFunctionType, expr: &BoundExpr)
         AggregateFunctionType::BoolAnd | AggregateFunctionType::BoolOr => {
             BoundType::Known(DataType::Boolean)
         }
+        AggregateFunctionType::JsonAgg | AggregateFunctionType::JsonObjectAgg => {
+            BoundType::Known(DataType::Json)
+        }
     }
 }
 
 pub(super) fn scalar_function_type(name: &ScalarFunctionType, args: &[BoundExpr]) -> BoundType {
     match name {
+        ScalarFunctionType::Substring
+            if matches!(
+                args.first().map(|arg| &arg.data_type),
+                Some(BoundType::Known(DataType::Blob))
+            ) =>
+        {
+            BoundType::Known(DataType::Blob)
+        }
         ScalarFunctionType::Upper
         | ScalarFunctionType::Lower
         | ScalarFunctionType::Substring
@@ -187,7 +228,13 @@ pub(super) fn scalar_function_type(name: &ScalarFunctionType, args: &[BoundExpr]
         | ScalarFunctionType::Initcap
         | ScalarFunctionType::SplitPart
         | ScalarFunctionType::Translate
-        | ScalarFunctionType::RegexpReplace => BoundType::Known(DataType::Text),
+        | ScalarFunctionType::RegexpReplace
+        | ScalarFunctionType::Encode
+        | ScalarFunctionType::JsonTypeof => BoundType::Known(DataType::Text),
+        ScalarFunctionType::Decode => BoundType::Known(DataType::Blob),
+        ScalarFunctionType::JsonExtract
+        | ScalarFunctionType::JsonSet
+        | ScalarFunctionType::JsonBuildObject => BoundType::Known(DataType::Json),
         ScalarFunctionType::Length
         | ScalarFunctionType::Position
         | ScalarFunctionType::Instr
@@ -200,8 +247,20 @@ pub(super) fn scalar_function_type(name: &ScalarFunctionType, args: &[BoundExpr]
         | ScalarFunctionType::DayOfWeek
         | ScalarFunctionType::Quarter
         | ScalarFunctionType::Gcd
-        | ScalarFunctionType::Lcm => BoundType::Known(DataType::Integer),
-        ScalarFunctionType::Now => BoundType::Known(DataType::DateTime),
+        | ScalarFunctionType::Lcm
+        | ScalarFunctionType::JsonArrayLength => BoundType::Known(DataType::Integer),
+        ScalarFunctionType::Now => BoundType::Known(DataType::TimestampTz),
+        ScalarFunctionType::Timezone => match args.get(1).map(|arg| &arg.data_type) {
+            Some(BoundType::Known(DataType::TimestampTz)) => BoundType::Known(DataType::DateTime),
+            Some(BoundType::Known(DataType::Date | DataType::DateTime)) => {
+                BoundType::Known(DataType::TimestampTz)
+            }
+            _ => BoundType::Unknown,
+        },
+        ScalarFunctionType::JustifyDays
+        | ScalarFunctionType::JustifyHours
+        | ScalarFunctionType::JustifyInterval
+        | ScalarFunctionType::Age => BoundType::Known(DataType::Interval),
         ScalarFunctionType::Random
         | ScalarFunctionType::Pi
         | ScalarFunctionType::Log
@@ -284,7 +343,8 @@ pub(super) fn ensure_boolean(expr: &BoundExpr, context: &str) -> Result<(), Rust
 
 pub(super) fn ensure_numeric(expr: &BoundExpr, context: &str) -> Result<(), RustqlError> {
     match expr.data_type {
-        BoundType::Known(DataType::Integer | DataType::Float) | BoundType::Unknown => Ok(()),
+        BoundType::Known(DataType::Integer | DataType::Float | DataType::Decimal { .. })
+        | BoundType::Unknown => Ok(()),
         _ => Err(RustqlError::TypeMismatch(format!(
             "{} requires numeric values",
             context
@@ -312,6 +372,17 @@ pub(super) fn ensure_text(expr: &BoundExpr, context: &str) -> Result<(), RustqlE
     }
 }
 
+/// JSON operands also accept TEXT, which is parsed when evaluated.
+pub(super) fn ensure_json(expr: &BoundExpr, context: &str) -> Result<(), RustqlError> {
+    match expr.data_type {
+        BoundType::Known(DataType::Json | DataType::Text) | BoundType::Unknown => Ok(()),
+        _ => Err(RustqlError::TypeMismatch(format!(
+            "{} requires JSON values",
+            context
+        ))),
+    }
+}
+
 pub(super) fn ensure_comparable(
     left: &BoundType,
     right: &BoundType,
@@ -333,6 +404,10 @@ fn types_comparable(left: &BoundType, right: &BoundType) -> bool {
     match (left, right) {
         (BoundType::Unknown, _) | (_, BoundType::Unknown) => true,
         (BoundType::Known(left), BoundType::Known(right)) if left == right => true,
+        (
+            BoundType::Known(DataType::DateTime | DataType::TimestampTz),
+            BoundType::Known(DataType::DateTime | DataType::TimestampTz),
+        ) => true,
         (BoundType::Known(left), BoundType::Known(right)) => {
             is_numeric_type(left) && is_numeric_type(right)
         }
@@ -351,6 +426,74 @@ pub(super) fn common_numeric_type(left: &BoundType, right: &BoundType) -> BoundT
     }
 }
 
+/// The type of `+`, `-`, `*` or `/` when either operand is a date, time
+/// or interval, `Ok(None)` when neither is so the numeric rules apply, and
+/// an error for a combination with no meaning such as `DATE * INTEGER`.
+pub(super) fn temporal_arithmetic_type(
+    op: &BinaryOperator,
+    left: &BoundType,
+    right: &BoundType,
+) -> Result<Option<BoundType>, RustqlError> {
+    let is_temporal = |data_type: &BoundType| {
+        matches!(
+            data_type,
+            BoundType::Known(
+                DataType::Date
+                    | DataType::Time
+                    | DataType::DateTime
+                    | DataType::TimestampTz
+                    | DataType::Interval
+            )
+        )
+    };
+    if !is_temporal(left) && !is_temporal(right) {
+        return Ok(None);
+    }
+    let (BoundType::Known(left_type), BoundType::Known(right_type)) = (left, right) else {
+        return Ok(Some(BoundType::Unknown));
+    };
+    match temporal_result_type(op, left_type, right_type) {
+        Some(data_type) => Ok(Some(BoundType::Known(data_type))),
+        None => Err(RustqlError::TypeMismatch(format!(
+            "Arithmetic expression cannot apply {} to {} and {}",
+            match op {
+                BinaryOperator::Plus => "+",
+                BinaryOperator::Minus => "-",
+                BinaryOperator::Multiply => "*",
+                _ => "/",
+            },
+            display_bound_type(left),
+            display_bound_type(right)
+        ))),
+    }
+}
+
+fn temporal_result_type(
+    op: &BinaryOperator,
+    left: &DataType,
+    right: &DataType,
+) -> Option<DataType> {
+    use BinaryOperator::{Divide, Minus, Multiply, Plus};
+    use DataType::{Date, DateTime, Integer, Interval, Time, TimestampTz};
+    match (left, op, right) {
+        (Date, Plus | Minus, Integer) | (Integer, Plus, Date) => Some(Date),
+        (Date, Minus, Date) => Some(Integer),
+        (Date | DateTime, Plus | Minus, Interval) | (Interval, Plus, Date | DateTime) => {
+            Some(DateTime)
+        }
+        (TimestampTz, Plus | Minus, Interval) | (Interval, Plus, TimestampTz) => Some(TimestampTz),
+        (Date | DateTime | TimestampTz, Minus, Date | DateTime | TimestampTz) => Some(Interval),
+        (Time, Plus | Minus, Interval) | (Interval, Plus, Time) => Some(Time),
+        (Time, Minus, Time) | (Interval, Plus | Minus, Interval) => Some(Interval),
+        (Interval, Multiply | Divide, number) | (number, Multiply, Interval)
+            if is_numeric_type(number) =>
+        {
+            Some(Interval)
+        }
+        _ => None,
+    }
+}
+
 pub(super) fn common_type(left: &BoundType, right: &BoundType) -> BoundType {
     match (left, right) {
         (BoundType::Unknown, data_type) | (data_type, BoundType::Unknown) => data_type.clone(),
@@ -363,7 +506,10 @@ pub(super) fn common_type(left: &BoundType, right: &BoundType) -> BoundType {
 }
 
 fn is_numeric_type(data_type: &DataType) -> bool {
-    matches!(data_type, DataType::Integer | DataType::Float)
+    matches!(
+        data_type,
+        DataType::Integer | DataType::Float | DataType::Decimal { .. }
+    )
 }
 
 fn display_bound_type(data_type: &BoundType) -> &'static str {
@@ -371,10 +517,15 @@ fn display_bound_type(data_type: &BoundType) -> &'static str {
         BoundType::Unknown => "UNKNOWN",
         BoundType::Known(DataType::Integer) => "INTEGER",
         BoundType::Known(DataType::Float) => "FLOAT",
+        BoundType::Known(DataType::Decimal { .. }) => "DECIMAL",
         BoundType::Known(DataType::Text) => "TEXT",
         BoundType::Known(DataType::Boolean) => "BOOLEAN",
         BoundType::Known(DataType::Date) => "DATE",
         BoundType::Known(DataType::Time) => "TIME",
         BoundType::Known(DataType::DateTime) => "DATETIME",
+        BoundType::Known(DataType::TimestampTz) => "TIMESTAMPTZ",
+        BoundType::Known(DataType::Interval) => "INTERVAL",
+        BoundType::Known(DataType::Blob) => "BLOB",
+        BoundType::Known(DataType::Json) => "JSON",
     }
 }
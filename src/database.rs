@@ -1,7 +1,19 @@
 use crate::ast::*;
+use crate::error::RustqlError;
+use crate::interval::Interval;
+use crate::wal::{RowChange, WriteSet};
 use serde::{Deserialize, Serialize};
-use std::collections::{BTreeMap, HashMap};
-
+use std::borrow::Cow;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::ops::{Deref, DerefMut};
+use std::sync::{Arc, OnceLock};
+
+/// The tables, indexes, and views of a database.
+///
+/// Storage engines receive and return the database whole. Its fields are
+/// internal; a custom [`StorageEngine`](crate::StorageEngine) stores it with
+/// [`Database::to_json`] and reads it back with [`Database::from_json`], or
+/// through its serde implementations, which produce the same format.
 #[derive(Serialize, Deserialize, Default, Clone)]
 pub struct Database {
     pub tables: HashMap<String, Table>,
@@ -10,11 +22,141 @@ pub struct Database {
     pub views: HashMap<String, View>,
     #[serde(default)]
     pub composite_indexes: HashMap<String, CompositeIndex>,
+    /// Transactions saved by `PREPARE TRANSACTION` until a `COMMIT PREPARED`
+    /// or `ROLLBACK PREPARED` names them.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub(crate) prepared_transactions: BTreeMap<String, Shared<PreparedTransaction>>,
+    /// The rows of [`PREPARED_XACTS_VIEW`], built when it is first read.
+    #[serde(skip)]
+    prepared_xacts: OnceLock<Box<Table>>,
+}
+
+/// The catalog view listing prepared transactions. A table or view of the
+/// same name hides it.
+pub(crate) const PREPARED_XACTS_VIEW: &str = "pg_prepared_xacts";
+
+/// A transaction `PREPARE TRANSACTION` took out of its session and saved with
+/// the database, so that any session, in this process or a later one, can
+/// commit or roll it back.
+#[derive(Serialize, Deserialize, Clone)]
+pub(crate) struct PreparedTransaction {
+    pub(crate) gid: String,
+    /// When the transaction was prepared, as `YYYY-MM-DD HH:MM:SS` in UTC.
+    pub(crate) prepared_at: String,
+    pub(crate) writes: WriteSet,
+    /// The part of the transaction's snapshot its writes name: tables it
+    /// created or altered whole, only the written rows of other tables, and
+    /// the indexes and views it wrote.
+    pub(crate) changes: Database,
+    /// The row changes subscribers receive once it commits, under the ids
+    /// its snapshot gave the rows.
+    pub(crate) row_changes: Vec<RowChange>,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
 pub struct RowId(pub u64);
 
+mod chunked;
+
+pub use chunked::{SharedMap, SharedVec};
+
+/// A value shared between versions of a database until one of them changes
+/// it.
+///
+/// Cloning a `Database` only clones these handles, so a transaction snapshot
+/// costs one pointer per table and index. The first write through
+/// [`DerefMut`] copies the value if another version still holds it.
+#[derive(Default, PartialEq)]
+pub struct Shared<T>(Arc<T>);
+
+impl<T> Shared<T> {
+    pub fn new(value: T) -> Self {
+        Self(Arc::new(value))
+    }
+
+    /// Returns the value, copying it if another version still shares it.
+    pub fn into_inner(self) -> T
+    where
+        T: Clone,
+    {
+        Arc::unwrap_or_clone(self.0)
+    }
+}
+
+impl<T> Clone for Shared<T> {
+    fn clone(&self) -> Self {
+        Self(Arc::clone(&self.0))
+    }
+}
+
+impl<T> From<T> for Shared<T> {
+    fn from(value: T) -> Self {
+        Self::new(value)
+    }
+}
+
+impl<T> Deref for Shared<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.0
+    }
+}
+
+impl<T: Clone> DerefMut for Shared<T> {
+    fn deref_mut(&mut self) -> &mut T {
+        Arc::make_mut(&mut self.0)
+    }
+}
+
+impl<T: PartialEq> PartialEq<T> for Shared<T> {
+    fn eq(&self, other: &T) -> bool {
+        *self.0 == *other
+    }
+}
+
+impl<T: std::fmt::Debug> std::fmt::Debug for Shared<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl<'a, T> IntoIterator for &'a Shared<T>
+where
+    &'a T: IntoIterator,
+{
+    type Item = <&'a T as IntoIterator>::Item;
+    type IntoIter = <&'a T as IntoIterator>::IntoIter;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.0.as_ref().into_iter()
+    }
+}
+
+impl<'a, T: Clone> IntoIterator for &'a mut Shared<T>
+where
+    &'a mut T: IntoIterator,
+{
+    type Item = <&'a mut T as IntoIterator>::Item;
+    type IntoIter = <&'a mut T as IntoIterator>::IntoIter;
+
+    fn into_iter(self) -> Self::IntoIter {
+        Arc::make_mut(&mut self.0).into_iter()
+    }
+}
+
+impl<T: Serialize> Serialize for Shared<T> {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.0.serialize(serializer)
+    }
+}
+
+impl<'de, T: Deserialize<'de>> Deserialize<'de> for Shared<T> {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        T::deserialize(deserializer).map(Self::new)
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct View {
     pub name: String,
@@ -27,24 +169,31 @@ pub struct Index {
     pub table: String,
     pub column: String,
     #[serde(with = "index_entries")]
-    pub entries: BTreeMap<Value, Vec<RowId>>,
+    pub entries: SharedMap<Value, Vec<RowId>>,
     #[serde(default, with = "optional_filter_expression")]
     pub filter_expr: Option<Expression>,
+    /// The name the entries are stored under while the index is paged with
+    /// its table. `entries` then only holds the entries of the rows the
+    /// table wrote since it was paged out.
+    #[serde(skip)]
+    pub paged: Option<String>,
 }
 
 mod index_entries {
     use super::*;
+    use crate::blob;
+    use crate::decimal::Decimal;
     use serde::{Deserialize, Deserializer, Serialize, Serializer};
 
     pub fn serialize<S>(
-        entries: &BTreeMap<Value, Vec<RowId>>,
+        entries: &SharedMap<Value, Vec<RowId>>,
         serializer: S,
     ) -> Result<S::Ok, S::Error>
     where
         S: Serializer,
     {
         let mut map = serde_json::Map::new();
-        for (key, value) in entries {
+        for (key, value) in entries.iter() {
             let key_str = value_to_string(key);
             map.insert(
                 key_str,
@@ -54,7 +203,7 @@ mod index_entries {
         map.serialize(serializer)
     }
 
-    pub fn deserialize<'de, D>(deserializer: D) -> Result<BTreeMap<Value, Vec<RowId>>, D::Error>
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<SharedMap<Value, Vec<RowId>>, D::Error>
     where
         D: Deserializer<'de>,
     {
@@ -67,7 +216,7 @@ mod index_entries {
                 serde_json::from_value(value).map_err(serde::de::Error::custom)?;
             entries.insert(key, indices);
         }
-        Ok(entries)
+        Ok(SharedMap::from(entries))
     }
 
     fn value_to_string(v: &Value) -> String {
@@ -75,11 +224,16 @@ mod index_entries {
             Value::Null => "NULL".to_string(),
             Value::Integer(i) => format!("I:{}", i),
             Value::Float(f) => format!("F:{}", f),
+            Value::Decimal(d) => format!("N:{}", d),
             Value::Text(s) => format!("S:{}", s),
             Value::Boolean(b) => format!("B:{}", b),
             Value::Date(d) => format!("D:{}", d),
             Value::Time(t) => format!("TM:{}", t),
             Value::DateTime(dt) => format!("DT:{}", dt),
+            Value::TimestampTz(ts) => format!("TZ:{}", ts),
+            Value::Interval(interval) => format!("IV:{}", interval),
+            Value::Blob(bytes) => format!("X:{}", blob::encode_hex(bytes)),
+            Value::Json(json) => format!("J:{}", json),
         }
     }
 
@@ -97,9 +251,27 @@ mod index_entries {
         {
             return Value::Float(f);
         }
+        if let Some(rest) = s.strip_prefix("N:")
+            && let Some(d) = Decimal::parse(rest)
+        {
+            return Value::Decimal(d);
+        }
         if let Some(rest) = s.strip_prefix("S:") {
             return Value::Text(rest.to_string());
         }
+        if let Some(rest) = s.strip_prefix("IV:")
+            && let Some(interval) = Interval::parse(rest)
+        {
+            return Value::Interval(interval);
+        }
+        if let Some(rest) = s.strip_prefix("J:") {
+            return Value::Json(rest.to_string());
+        }
+        if let Some(rest) = s.strip_prefix("X:")
+            && let Some(bytes) = blob::decode_hex(rest)
+        {
+            return Value::Blob(bytes);
+        }
         if let Some(rest) = s.strip_prefix("B:")
             && let Ok(b) = rest.parse::<bool>()
         {
@@ -114,6 +286,9 @@ mod index_entries {
         if let Some(rest) = s.strip_prefix("DT:") {
             return Value::DateTime(rest.to_string());
         }
+        if let Some(rest) = s.strip_prefix("TZ:") {
+            return Value::TimestampTz(rest.to_string());
+        }
         Value::Text(s.to_string())
     }
 }
@@ -121,21 +296,272 @@ mod index_entries {
 #[derive(Serialize, Deserialize, Clone)]
 pub struct Table {
     pub columns: Vec<ColumnDefinition>,
-    pub rows: Vec<Vec<Value>>,
+    pub rows: SharedVec<Vec<Value>>,
     #[serde(default)]
-    pub row_ids: Vec<RowId>,
+    pub row_ids: SharedVec<RowId>,
     #[serde(default = "default_next_row_id")]
     pub next_row_id: u64,
     #[serde(default)]
     pub constraints: Vec<TableConstraint>,
     #[serde(skip)]
-    row_id_positions: HashMap<RowId, usize>,
+    paged: Option<PagedRows>,
+}
+
+impl std::fmt::Debug for Table {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Table")
+            .field("columns", &self.columns)
+            .field("row_count", &self.row_count())
+            .field("next_row_id", &self.next_row_id)
+            .field("constraints", &self.constraints)
+            .field("paged", &self.is_paged())
+            .finish_non_exhaustive()
+    }
 }
 
 fn default_next_row_id() -> u64 {
     1
 }
 
+/// Reads the stored rows of tables that a storage engine opened without
+/// loading them into memory.
+pub trait RowSource: Send + Sync {
+    /// Calls `visit` with each stored row of `table` in row id order until it
+    /// returns `false`.
+    fn scan_rows(
+        &self,
+        table: &str,
+        visit: &mut dyn FnMut(RowId, Vec<Value>) -> Result<bool, RustqlError>,
+    ) -> Result<(), RustqlError>;
+
+    fn fetch_row(&self, table: &str, row_id: RowId) -> Result<Option<Vec<Value>>, RustqlError>;
+
+    /// Calls `visit` with the key and row id of each stored entry of `index`
+    /// whose key starts with `prefix`, in key order, until it returns
+    /// `false`. An empty prefix visits every entry.
+    fn scan_index(
+        &self,
+        index: &str,
+        composite: bool,
+        prefix: &[Value],
+        visit: &mut dyn FnMut(Vec<Value>, RowId) -> Result<bool, RustqlError>,
+    ) -> Result<(), RustqlError>;
+
+    /// The generation the stored rows are at now. Tables paged out from the
+    /// source read through it.
+    fn generation(&self) -> Arc<RowGeneration>;
+}
+
+/// The rows a commit replaced in a [`RowSource`], by stored table name: the
+/// values each row had before the commit, or `None` for a row it inserted.
+pub type ReplacedRows = HashMap<String, BTreeMap<RowId, Option<Vec<Value>>>>;
+
+/// One state of the rows a [`RowSource`] stores.
+///
+/// A paged table reads its rows as of the generation it was paged out at.
+/// A commit that changes stored rows supersedes the newest generation with
+/// the rows it replaced, before anyone can read the changed rows, so a
+/// table paged out earlier finds the rows it started with along the chain
+/// of later generations instead of in the source.
+#[derive(Default)]
+pub struct RowGeneration {
+    superseded: OnceLock<Superseded>,
+}
+
+struct Superseded {
+    replaced: ReplacedRows,
+    next: Arc<RowGeneration>,
+}
+
+/// What the chain of generations after a table's says about one row.
+enum Replaced<'a> {
+    /// A later commit replaced the row; these are its values as of the
+    /// table's generation.
+    Row(Option<&'a [Value]>),
+    /// No commit replaced the row; the source still holds it as of this
+    /// generation, the newest one.
+    Unchanged(&'a RowGeneration),
+}
+
+impl RowGeneration {
+    /// Records the rows a commit replaced and returns the generation that
+    /// follows. Only the newest generation may be superseded.
+    pub fn supersede(&self, replaced: ReplacedRows) -> Arc<RowGeneration> {
+        let superseded = self.superseded.get_or_init(|| Superseded {
+            replaced,
+            next: Arc::new(RowGeneration::default()),
+        });
+        Arc::clone(&superseded.next)
+    }
+
+    fn replaced_row(&self, table: &str, row_id: RowId) -> Replaced<'_> {
+        let mut generation = self;
+        while let Some(superseded) = generation.superseded.get() {
+            if let Some(row) = superseded
+                .replaced
+                .get(table)
+                .and_then(|rows| rows.get(&row_id))
+            {
+                return Replaced::Row(row.as_deref());
+            }
+            generation = &superseded.next;
+        }
+        Replaced::Unchanged(generation)
+    }
+
+    /// Adds the rows of `table` replaced after this generation to `rows`,
+    /// keeping the values already there, and returns the newest generation.
+    fn extend_replaced<'a>(
+        &'a self,
+        table: &str,
+        rows: &mut BTreeMap<RowId, Option<&'a [Value]>>,
+    ) -> &'a RowGeneration {
+        let mut generation = self;
+        while let Some(superseded) = generation.superseded.get() {
+            if let Some(replaced) = superseded.replaced.get(table) {
+                for (row_id, row) in replaced {
+                    rows.entry(*row_id).or_insert(row.as_deref());
+                }
+            }
+            generation = &superseded.next;
+        }
+        generation
+    }
+}
+
+impl Drop for RowGeneration {
+    /// Unlinks the chain one generation at a time, so dropping a long chain
+    /// does not recurse through it.
+    fn drop(&mut self) {
+        let mut next = self.superseded.take().map(|superseded| superseded.next);
+        while let Some(generation) = next {
+            next = Arc::try_unwrap(generation)
+                .ok()
+                .and_then(|mut generation| generation.superseded.take())
+                .map(|superseded| superseded.next);
+        }
+    }
+}
+
+/// Where a paged table's rows live while they are not in memory.
+///
+/// `changed` holds the rows this version of the table wrote since it was
+/// paged out, or `None` for the ones it deleted; the other rows are read
+/// from `source` as of `generation`.
+#[derive(Clone)]
+struct PagedRows {
+    source: Arc<dyn RowSource>,
+    table: String,
+    row_count: usize,
+    generation: Arc<RowGeneration>,
+    changed: SharedMap<RowId, Option<Vec<Value>>>,
+}
+
+impl PagedRows {
+    fn fetch(&self, row_id: RowId) -> Result<Option<Cow<'_, [Value]>>, RustqlError> {
+        if let Some(row) = self.changed.get(&row_id) {
+            return Ok(row.as_deref().map(Cow::Borrowed));
+        }
+        let newest = match self.generation.replaced_row(&self.table, row_id) {
+            Replaced::Row(row) => return Ok(row.map(Cow::Borrowed)),
+            Replaced::Unchanged(newest) => newest,
+        };
+        let row = self.source.fetch_row(&self.table, row_id)?;
+        // A commit that changed the row before the read superseded the
+        // generation first.
+        match newest.replaced_row(&self.table, row_id) {
+            Replaced::Row(row) => Ok(row.map(Cow::Borrowed)),
+            Replaced::Unchanged(_) => Ok(row.map(Cow::Owned)),
+        }
+    }
+
+    /// Streams the rows to `visit` in row id order.
+    ///
+    /// Other sessions can commit while the scan runs. Before each stored row
+    /// is used, the rows replaced by commits so far are merged in, so every
+    /// row is visited with the values it had when the table was paged out.
+    fn scan<F>(&self, mut visit: F) -> Result<(), RustqlError>
+    where
+        F: FnMut(RowId, &[Value]) -> Result<bool, RustqlError>,
+    {
+        let mut pending: BTreeMap<RowId, Option<&[Value]>> = self
+            .changed
+            .iter()
+            .map(|(row_id, row)| (*row_id, row.as_deref()))
+            .collect();
+        let mut newest = self.generation.extend_replaced(&self.table, &mut pending);
+        let mut last_visited = None;
+        let mut finished = false;
+        self.source.scan_rows(&self.table, &mut |row_id, row| {
+            newest = newest.extend_replaced(&self.table, &mut pending);
+            if !visit_pending(&mut pending, Some(row_id), &mut last_visited, &mut visit)? {
+                finished = true;
+                return Ok(false);
+            }
+            if last_visited.is_some_and(|last| row_id <= last) {
+                return Ok(true);
+            }
+            last_visited = Some(row_id);
+            let keep = match pending.remove(&row_id) {
+                Some(Some(replaced)) => visit(row_id, replaced)?,
+                Some(None) => true,
+                None => visit(row_id, &row)?,
+            };
+            finished = !keep;
+            Ok(keep)
+        })?;
+        if finished {
+            return Ok(());
+        }
+        // Checked again after the scan: a commit that removed the remaining
+        // rows leaves nothing to read in which to notice it.
+        newest.extend_replaced(&self.table, &mut pending);
+        visit_pending(&mut pending, None, &mut last_visited, &mut visit)?;
+        Ok(())
+    }
+
+    fn changed_row_ids(&self) -> HashSet<RowId> {
+        let mut replaced = BTreeMap::new();
+        self.generation.extend_replaced(&self.table, &mut replaced);
+        self.changed
+            .keys()
+            .chain(replaced.keys())
+            .copied()
+            .collect()
+    }
+}
+
+/// Visits the rows of `pending` with ids below `before`, or all of them,
+/// skipping ids at or below the last row visited. Returns `false` once
+/// `visit` does.
+fn visit_pending<F>(
+    pending: &mut BTreeMap<RowId, Option<&[Value]>>,
+    before: Option<RowId>,
+    last_visited: &mut Option<RowId>,
+    visit: &mut F,
+) -> Result<bool, RustqlError>
+where
+    F: FnMut(RowId, &[Value]) -> Result<bool, RustqlError>,
+{
+    while let Some(entry) = pending.first_entry() {
+        let row_id = *entry.key();
+        if before.is_some_and(|before| row_id >= before) {
+            break;
+        }
+        let row = entry.remove();
+        if last_visited.is_some_and(|last| row_id <= last) {
+            continue;
+        }
+        *last_visited = Some(row_id);
+        if let Some(row) = row
+            && !visit(row_id, row)?
+        {
+            return Ok(false);
+        }
+    }
+    Ok(true)
+}
+
 impl Table {
     pub fn with_rows_and_ids(
         columns: Vec<ColumnDefinition>,
@@ -146,16 +572,168 @@ impl Table {
     ) -> Self {
         let mut table = Self {
             columns,
-            rows,
-            row_ids,
+            rows: SharedVec::from(rows),
+            row_ids: SharedVec::from(row_ids),
             next_row_id,
             constraints,
-            row_id_positions: HashMap::new(),
+            paged: None,
         };
         table.ensure_row_ids();
         table
     }
 
+    /// Creates a table whose `row_count` rows stay in `source` until
+    /// [`Table::load_paged_rows`] brings them into memory.
+    pub fn paged(
+        columns: Vec<ColumnDefinition>,
+        next_row_id: u64,
+        constraints: Vec<TableConstraint>,
+        source: Arc<dyn RowSource>,
+        table: &str,
+        row_count: usize,
+    ) -> Self {
+        let mut paged =
+            Self::with_rows_and_ids(columns, Vec::new(), Vec::new(), next_row_id, constraints);
+        paged.paged = Some(PagedRows {
+            generation: source.generation(),
+            source,
+            table: table.to_string(),
+            row_count,
+            changed: SharedMap::default(),
+        });
+        paged
+    }
+
+    /// Returns `true` while the rows are only available through
+    /// [`Table::for_each_row`] and [`Table::fetch_row`]; `rows` and `row_ids`
+    /// are empty until the table is loaded.
+    pub fn is_paged(&self) -> bool {
+        self.paged.is_some()
+    }
+
+    pub fn row_count(&self) -> usize {
+        match &self.paged {
+            Some(paged) => paged.row_count,
+            None => self.rows.len(),
+        }
+    }
+
+    /// Visits every row in row id order, reading paged rows from their
+    /// source. Stops early when `visit` returns `false`.
+    pub fn for_each_row<F>(&self, mut visit: F) -> Result<(), RustqlError>
+    where
+        F: FnMut(RowId, &[Value]) -> Result<bool, RustqlError>,
+    {
+        match &self.paged {
+            Some(paged) => paged.scan(visit),
+            None => {
+                for (row_id, row) in self.iter_rows_with_ids() {
+                    if !visit(row_id, row)? {
+                        break;
+                    }
+                }
+                Ok(())
+            }
+        }
+    }
+
+    pub fn fetch_row(&self, row_id: RowId) -> Result<Option<Cow<'_, [Value]>>, RustqlError> {
+        match &self.paged {
+            Some(paged) => paged.fetch(row_id),
+            None => Ok(self
+                .row_by_id(row_id)
+                .map(|row| Cow::Borrowed(row.as_slice()))),
+        }
+    }
+
+    /// Reads all rows of a paged table into memory. Does nothing for a table
+    /// that is already loaded.
+    pub fn load_paged_rows(&mut self) -> Result<(), RustqlError> {
+        let Some(paged) = &self.paged else {
+            return Ok(());
+        };
+        let mut rows = Vec::with_capacity(paged.row_count);
+        let mut row_ids = Vec::with_capacity(paged.row_count);
+        paged.scan(|row_id, row| {
+            row_ids.push(row_id);
+            rows.push(row.to_vec());
+            Ok(true)
+        })?;
+        self.rows = SharedVec::from(rows);
+        self.row_ids = SharedVec::from(row_ids);
+        self.paged = None;
+        self.ensure_row_ids();
+        Ok(())
+    }
+
+    /// Drops the rows from memory once `source` stores them under `table`,
+    /// keeping them available through [`Table::for_each_row`] and
+    /// [`Table::fetch_row`].
+    pub fn page_out(&mut self, source: Arc<dyn RowSource>, table: &str) {
+        let row_count = self.row_count();
+        self.paged = Some(PagedRows {
+            generation: source.generation(),
+            source,
+            table: table.to_string(),
+            row_count,
+            changed: SharedMap::default(),
+        });
+        self.rows = SharedVec::default();
+        self.row_ids = SharedVec::default();
+    }
+
+    /// The values this version wrote for `row_id` since the table was paged
+    /// out: `Some(None)` for a row it deleted, `None` for a row it did not
+    /// write. Every row of a table in memory counts as written.
+    pub fn written_row(&self, row_id: RowId) -> Option<Option<&[Value]>> {
+        match &self.paged {
+            Some(paged) => paged.changed.get(&row_id).map(Option::as_deref),
+            None => Some(self.row_by_id(row_id).map(Vec::as_slice)),
+        }
+    }
+
+    /// The rows held in memory: every row of a loaded table, and the rows a
+    /// paged table wrote since it was paged out.
+    pub fn resident_rows(&self) -> Vec<(RowId, &[Value])> {
+        match &self.paged {
+            Some(paged) => paged
+                .changed
+                .iter()
+                .filter_map(|(row_id, row)| Some((*row_id, row.as_deref()?)))
+                .collect(),
+            None => self
+                .iter_rows_with_ids()
+                .map(|(row_id, row)| (row_id, row.as_slice()))
+                .collect(),
+        }
+    }
+
+    /// The ids of the rows of a paged table whose stored entries may not
+    /// describe this version: those it wrote, and those commits changed
+    /// after it was paged out. Empty for a table in memory.
+    pub fn changed_row_ids(&self) -> HashSet<RowId> {
+        self.paged
+            .as_ref()
+            .map(PagedRows::changed_row_ids)
+            .unwrap_or_default()
+    }
+
+    /// Reads the stored entries of the index stored as `index` on a paged
+    /// table; see [`RowSource::scan_index`]. Does nothing for a table in
+    /// memory.
+    pub fn scan_stored_index(
+        &self,
+        index: &str,
+        composite: bool,
+        prefix: &[Value],
+        visit: &mut dyn FnMut(Vec<Value>, RowId) -> Result<bool, RustqlError>,
+    ) -> Result<(), RustqlError> {
+        match &self.paged {
+            Some(paged) => paged.source.scan_index(index, composite, prefix, visit),
+            None => Ok(()),
+        }
+    }
+
     pub fn new(
         columns: Vec<ColumnDefinition>,
         rows: Vec<Vec<Value>>,
@@ -170,31 +748,32 @@ impl Table {
         )
     }
 
-    fn rebuild_row_id_positions(&mut self) {
-        self.row_id_positions.clear();
-        for (position, row_id) in self.row_ids.iter().copied().enumerate() {
-            self.row_id_positions.insert(row_id, position);
-        }
-    }
-
+    /// Gives rows without ids the ids `1..`, and puts the rows in row id
+    /// order, which lookups by id rely on.
     pub fn ensure_row_ids(&mut self) {
         if self.row_ids.len() != self.rows.len() {
             self.row_ids = (1..=self.rows.len() as u64).map(RowId).collect();
         }
+        if !self.row_ids.iter().is_sorted() {
+            let mut rows: Vec<(RowId, Vec<Value>)> = self
+                .row_ids
+                .iter()
+                .copied()
+                .zip(self.rows.iter().cloned())
+                .collect();
+            rows.sort_by_key(|(row_id, _)| *row_id);
+            let (row_ids, rows): (Vec<_>, Vec<_>) = rows.into_iter().unzip();
+            self.row_ids = SharedVec::from(row_ids);
+            self.rows = SharedVec::from(rows);
+        }
 
-        let max_existing = self
-            .row_ids
-            .iter()
-            .map(|row_id| row_id.0)
-            .max()
-            .unwrap_or(0);
+        let max_existing = self.row_ids.last().map(|row_id| row_id.0).unwrap_or(0);
         if self.next_row_id <= max_existing {
             self.next_row_id = max_existing + 1;
         }
         if self.next_row_id == 0 {
             self.next_row_id = default_next_row_id();
         }
-        self.rebuild_row_id_positions();
     }
 
     pub fn iter_rows_with_ids(&self) -> impl Iterator<Item = (RowId, &Vec<Value>)> {
@@ -206,11 +785,14 @@ impl Table {
     }
 
     pub fn position_of_row_id(&self, row_id: RowId) -> Option<usize> {
-        self.row_id_positions.get(&row_id).copied().or_else(|| {
-            self.row_ids
-                .iter()
-                .position(|candidate| *candidate == row_id)
-        })
+        self.row_ids.binary_search(&row_id).ok()
+    }
+
+    /// Gives ids to rows pushed onto `rows` directly.
+    fn sync_row_ids(&mut self) {
+        if self.row_ids.len() != self.rows.len() {
+            self.ensure_row_ids();
+        }
     }
 
     pub fn row_by_id(&self, row_id: RowId) -> Option<&Vec<Value>> {
@@ -224,39 +806,81 @@ impl Table {
     }
 
     pub fn insert_row(&mut self, row: Vec<Value>) -> RowId {
-        self.ensure_row_ids();
+        if let Some(paged) = &mut self.paged {
+            let row_id = RowId(self.next_row_id);
+            self.next_row_id += 1;
+            paged.changed.insert(row_id, Some(row));
+            paged.row_count += 1;
+            return row_id;
+        }
+        self.sync_row_ids();
         let row_id = RowId(self.next_row_id);
         self.next_row_id += 1;
         self.rows.push(row);
         self.row_ids.push(row_id);
-        self.row_id_positions.insert(row_id, self.rows.len() - 1);
         row_id
     }
 
-    pub fn insert_row_at(&mut self, position: usize, row_id: RowId, row: Vec<Value>) {
-        self.ensure_row_ids();
-        let position = position.min(self.rows.len());
-        self.rows.insert(position, row);
-        self.row_ids.insert(position, row_id);
+    /// Puts back a row removed under `row_id`, in row id order.
+    pub fn restore_row(&mut self, row_id: RowId, row: Vec<Value>) {
         if self.next_row_id <= row_id.0 {
             self.next_row_id = row_id.0 + 1;
         }
-        self.rebuild_row_id_positions();
+        if let Some(paged) = &mut self.paged {
+            paged.changed.insert(row_id, Some(row));
+            paged.row_count += 1;
+            return;
+        }
+        self.sync_row_ids();
+        let position = self
+            .row_ids
+            .partition_point(|candidate| *candidate < row_id);
+        self.rows.insert(position, row);
+        self.row_ids.insert(position, row_id);
     }
 
-    pub fn remove_row_by_id(&mut self, row_id: RowId) -> Option<(usize, Vec<Value>)> {
-        self.ensure_row_ids();
-        let position = self.position_of_row_id(row_id)?;
+    /// Removes the row and returns its values, or `None` if the table has no
+    /// row with that id.
+    pub fn remove_row_by_id(&mut self, row_id: RowId) -> Result<Option<Vec<Value>>, RustqlError> {
+        if self.paged.is_some() {
+            let old_row = self.fetch_row(row_id)?.map(Cow::into_owned);
+            if old_row.is_some()
+                && let Some(paged) = &mut self.paged
+            {
+                paged.changed.insert(row_id, None);
+                paged.row_count -= 1;
+            }
+            return Ok(old_row);
+        }
+        self.sync_row_ids();
+        let Some(position) = self.position_of_row_id(row_id) else {
+            return Ok(None);
+        };
         self.row_ids.remove(position);
         let row = self.rows.remove(position);
-        self.rebuild_row_id_positions();
-        Some((position, row))
+        Ok(Some(row))
     }
 
-    pub fn set_row_by_id(&mut self, row_id: RowId, row: Vec<Value>) -> Option<()> {
-        let position = self.position_of_row_id(row_id)?;
-        self.rows[position] = row;
-        Some(())
+    /// Replaces the values of the row and returns the old ones, or `None`,
+    /// changing nothing, if the table has no row with that id.
+    pub fn set_row_by_id(
+        &mut self,
+        row_id: RowId,
+        row: Vec<Value>,
+    ) -> Result<Option<Vec<Value>>, RustqlError> {
+        if self.paged.is_some() {
+            let old_row = self.fetch_row(row_id)?.map(Cow::into_owned);
+            if old_row.is_some()
+                && let Some(paged) = &mut self.paged
+            {
+                paged.changed.insert(row_id, Some(row));
+            }
+            return Ok(old_row);
+        }
+        let Some(position) = self.position_of_row_id(row_id) else {
+            return Ok(None);
+        };
+        Ok(Some(std::mem::replace(&mut self.rows[position], row)))
     }
 }
 
@@ -266,9 +890,14 @@ pub struct CompositeIndex {
     pub table: String,
     pub columns: Vec<String>,
     #[serde(with = "composite_index_entries")]
-    pub entries: BTreeMap<Vec<Value>, Vec<RowId>>,
+    pub entries: SharedMap<Vec<Value>, Vec<RowId>>,
     #[serde(default, with = "optional_filter_expression")]
     pub filter_expr: Option<Expression>,
+    /// The name the entries are stored under while the index is paged with
+    /// its table. `entries` then only holds the entries of the rows the
+    /// table wrote since it was paged out.
+    #[serde(skip)]
+    pub paged: Option<String>,
 }
 
 mod optional_filter_expression {
@@ -304,10 +933,9 @@ mod composite_index_entries {
     use super::*;
     use serde::{Deserialize, Deserializer, Serialize, Serializer};
 
-    pub fn serialize<S>(
-        entries: &BTreeMap<Vec<Value>, Vec<RowId>>,
-        serializer: S,
-    ) -> Result<S::Ok, S::Error>
+    type Entries = SharedMap<Vec<Value>, Vec<RowId>>;
+
+    pub fn serialize<S>(entries: &Entries, serializer: S) -> Result<S::Ok, S::Error>
     where
         S: Serializer,
     {
@@ -318,9 +946,7 @@ mod composite_index_entries {
         converted.serialize(serializer)
     }
 
-    pub fn deserialize<'de, D>(
-        deserializer: D,
-    ) -> Result<BTreeMap<Vec<Value>, Vec<RowId>>, D::Error>
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<Entries, D::Error>
     where
         D: Deserializer<'de>,
     {
@@ -334,10 +960,167 @@ impl Database {
         Self::default()
     }
 
+    /// Serializes the database as a JSON snapshot, the format JSON storage
+    /// files use. Rows of tables still paged from a B-tree file are read in
+    /// first.
+    pub fn to_json(&self) -> Result<String, RustqlError> {
+        let mut db = self.clone();
+        db.load_all_paged_tables()?;
+        db.normalize_row_ids();
+        serde_json::to_string_pretty(&db)
+            .map_err(|e| RustqlError::StorageError(format!("Failed to serialize database: {}", e)))
+    }
+
+    /// Reads a snapshot written by [`Database::to_json`].
+    pub fn from_json(data: &str) -> Result<Self, RustqlError> {
+        let mut db: Database = serde_json::from_str(data).map_err(|e| {
+            RustqlError::StorageError(format!("Failed to parse database snapshot: {}", e))
+        })?;
+        db.normalize_row_ids();
+        Ok(db)
+    }
+
     pub fn normalize_row_ids(&mut self) {
         for table in self.tables.values_mut() {
             table.ensure_row_ids();
         }
+        for prepared in self.prepared_transactions.values_mut() {
+            prepared.changes.normalize_row_ids();
+        }
+    }
+
+    pub(crate) fn prepared_transaction(&self, gid: &str) -> Option<&PreparedTransaction> {
+        self.prepared_transactions
+            .get(gid)
+            .map(|prepared| &**prepared)
+    }
+
+    pub(crate) fn insert_prepared_transaction(&mut self, prepared: PreparedTransaction) {
+        self.prepared_transactions
+            .insert(prepared.gid.clone(), Shared::new(prepared));
+        self.prepared_xacts = OnceLock::new();
+    }
+
+    /// Replaces the prepared transactions with those of `other`.
+    pub(crate) fn copy_prepared_transactions(&mut self, other: &Database) {
+        self.prepared_transactions = other.prepared_transactions.clone();
+        self.prepared_xacts = OnceLock::new();
+    }
+
+    pub(crate) fn remove_prepared_transaction(
+        &mut self,
+        gid: &str,
+    ) -> Option<Shared<PreparedTransaction>> {
+        let removed = self.prepared_transactions.remove(gid);
+        self.prepared_xacts = OnceLock::new();
+        removed
+    }
+
+    /// Looks up a table, or the catalog view of prepared transactions when
+    /// nothing else has its name.
+    fn table_or_catalog(&self, name: &str) -> Option<&Table> {
+        if let Some(table) = self.tables.get(name) {
+            return Some(table);
+        }
+        if name != PREPARED_XACTS_VIEW || self.views.contains_key(name) {
+            return None;
+        }
+        Some(self.prepared_xacts.get_or_init(|| {
+            let column = |name: &str, data_type| ColumnDefinition {
+                name: name.to_string(),
+                data_type,
+                nullable: false,
+                primary_key: false,
+                unique: false,
+                default_value: None,
+                foreign_key: None,
+                check: None,
+                auto_increment: false,
+                generated: None,
+            };
+            let rows: Vec<Vec<Value>> = self
+                .prepared_transactions
+                .values()
+                .map(|prepared| {
+                    vec![
+                        Value::Text(prepared.gid.clone()),
+                        Value::DateTime(prepared.prepared_at.clone()),
+                    ]
+                })
+                .collect();
+            let row_ids = (1..=rows.len() as u64).map(RowId).collect();
+            Box::new(Table::with_rows_and_ids(
+                vec![
+                    column("gid", DataType::Text),
+                    column("prepared", DataType::DateTime),
+                ],
+                rows,
+                row_ids,
+                self.prepared_transactions.len() as u64 + 1,
+                Vec::new(),
+            ))
+        }))
+    }
+
+    pub fn has_paged_tables(&self) -> bool {
+        self.tables.values().any(Table::is_paged)
+    }
+
+    /// Loads the rows of the named tables into memory, for statements that
+    /// rewrite every row, and rebuilds their indexes from them.
+    pub fn load_paged_tables<'a>(
+        &mut self,
+        names: impl IntoIterator<Item = &'a str>,
+    ) -> Result<(), RustqlError> {
+        let mut loaded = BTreeSet::new();
+        for name in names {
+            if let Some(table) = self.tables.get_mut(name)
+                && table.is_paged()
+            {
+                table.load_paged_rows()?;
+                loaded.insert(name.to_string());
+            }
+        }
+        crate::wal::rebuild_table_indexes(self, &loaded)
+    }
+
+    pub fn load_all_paged_tables(&mut self) -> Result<(), RustqlError> {
+        let names: Vec<String> = self.tables.keys().cloned().collect();
+        self.load_paged_tables(names.iter().map(String::as_str))
+    }
+
+    /// Pages out the tables `source` stores, along with their indexes, once
+    /// they have been saved to it. `stored_name` maps the name of a table or
+    /// index to the name `source` stores it under, or `None` for one it does
+    /// not store.
+    pub fn page_out(
+        &mut self,
+        source: &Arc<dyn RowSource>,
+        stored_name: impl Fn(&str) -> Option<String>,
+    ) {
+        let mut paged = BTreeSet::new();
+        for (name, table) in &mut self.tables {
+            if let Some(stored) = stored_name(name) {
+                table.page_out(Arc::clone(source), &stored);
+                paged.insert(name.clone());
+            }
+        }
+        for index in self.indexes.values_mut() {
+            if paged.contains(&index.table)
+                && let Some(stored) = stored_name(&index.name)
+            {
+                index.paged = Some(stored);
+                index.entries = SharedMap::default();
+            }
+        }
+        for index in self.composite_indexes.values_mut() {
+            if paged.contains(&index.table)
+                && let Some(stored) = stored_name(&index.name)
+            {
+                index.paged = Some(stored);
+                index.entries = SharedMap::default();
+            }
+        }
     }
 }
 
@@ -360,7 +1143,7 @@ pub trait DatabaseCatalog {
 
 impl DatabaseCatalog for Database {
     fn get_table(&self, name: &str) -> Option<&Table> {
-        self.tables.get(name)
+        self.table_or_catalog(name)
     }
 
     fn get_index(&self, name: &str) -> Option<&Index> {
@@ -386,7 +1169,7 @@ impl DatabaseCatalog for Database {
 
 impl DatabaseCatalog for std::sync::RwLockReadGuard<'_, Database> {
     fn get_table(&self, name: &str) -> Option<&Table> {
-        self.tables.get(name)
+        self.table_or_catalog(name)
     }
 
     fn get_index(&self, name: &str) -> Option<&Index> {
@@ -412,7 +1195,7 @@ impl DatabaseCatalog for std::sync::RwLockReadGuard<'_, Database> {
 
 impl DatabaseCatalog for std::sync::RwLockWriteGuard<'_, Database> {
     fn get_table(&self, name: &str) -> Option<&Table> {
-        self.tables.get(name)
+        self.table_or_catalog(name)
     }
 
     fn get_index(&self, name: &str) -> Option<&Index> {
@@ -0,0 +1,505 @@
+use super::Shared;
+use serde::{Deserialize, Serialize};
+use std::borrow::Borrow;
+use std::collections::BTreeMap;
+use std::collections::btree_map::Entry;
+use std::ops::{Index, IndexMut, RangeBounds};
+
+/// Number of elements a chunk holds before it is split, or before a push
+/// starts a new one.
+const CHUNK_LEN: usize = 512;
+
+/// A vector whose elements versions of a database share a chunk at a time.
+///
+/// Cloning it clones one handle per chunk, and the first write to an element
+/// copies only the chunk holding it, so a statement that changes a few rows
+/// of a large table does not copy the table.
+pub struct SharedVec<T> {
+    chunks: Vec<Shared<Vec<T>>>,
+    /// The position of the first element of each chunk.
+    starts: Vec<usize>,
+    len: usize,
+}
+
+impl<T> SharedVec<T> {
+    pub fn new() -> Self {
+        Self {
+            chunks: Vec::new(),
+            starts: Vec::new(),
+            len: 0,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// The chunk holding `position`, which is below `len`.
+    fn chunk_of(&self, position: usize) -> usize {
+        self.starts.partition_point(|start| *start <= position) - 1
+    }
+
+    pub fn get(&self, position: usize) -> Option<&T> {
+        if position >= self.len {
+            return None;
+        }
+        let chunk = self.chunk_of(position);
+        self.chunks[chunk].get(position - self.starts[chunk])
+    }
+
+    pub fn first(&self) -> Option<&T> {
+        self.get(0)
+    }
+
+    pub fn last(&self) -> Option<&T> {
+        self.chunks.last().and_then(|chunk| chunk.last())
+    }
+
+    pub fn iter(&self) -> impl DoubleEndedIterator<Item = &T> + Clone + '_ {
+        self.chunks.iter().flat_map(|chunk| chunk.iter())
+    }
+
+    /// Returns the number of leading elements for which `pred` holds, which
+    /// must hold for a prefix of the vector, as [`slice::partition_point`].
+    pub fn partition_point(&self, mut pred: impl FnMut(&T) -> bool) -> usize {
+        let chunk = self
+            .chunks
+            .partition_point(|chunk| chunk.last().is_some_and(&mut pred));
+        match self.chunks.get(chunk) {
+            Some(elements) => self.starts[chunk] + elements.partition_point(pred),
+            None => self.len,
+        }
+    }
+
+    /// Searches a sorted vector for `value`, as [`slice::binary_search`].
+    pub fn binary_search(&self, value: &T) -> Result<usize, usize>
+    where
+        T: Ord,
+    {
+        let position = self.partition_point(|element| element < value);
+        match self.get(position) {
+            Some(element) if element == value => Ok(position),
+            _ => Err(position),
+        }
+    }
+}
+
+impl<T: Clone> SharedVec<T> {
+    pub fn get_mut(&mut self, position: usize) -> Option<&mut T> {
+        if position >= self.len {
+            return None;
+        }
+        let chunk = self.chunk_of(position);
+        let offset = position - self.starts[chunk];
+        self.chunks[chunk].get_mut(offset)
+    }
+
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = &mut T> + '_ {
+        self.chunks.iter_mut().flat_map(|chunk| chunk.iter_mut())
+    }
+
+    pub fn push(&mut self, value: T) {
+        match self.chunks.last_mut() {
+            Some(chunk) if chunk.len() < CHUNK_LEN => chunk.push(value),
+            _ => {
+                self.starts.push(self.len);
+                self.chunks.push(Shared::new(vec![value]));
+            }
+        }
+        self.len += 1;
+    }
+
+    /// Inserts `value` at `position`, shifting the elements after it.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `position > len`.
+    pub fn insert(&mut self, position: usize, value: T) {
+        assert!(
+            position <= self.len,
+            "insertion index (is {position}) should be <= len (is {})",
+            self.len
+        );
+        if position == self.len {
+            self.push(value);
+            return;
+        }
+        let chunk = self.chunk_of(position);
+        self.chunks[chunk].insert(position - self.starts[chunk], value);
+        for start in &mut self.starts[chunk + 1..] {
+            *start += 1;
+        }
+        self.len += 1;
+        if self.chunks[chunk].len() > 2 * CHUNK_LEN {
+            let upper = self.chunks[chunk].split_off(CHUNK_LEN);
+            self.chunks.insert(chunk + 1, Shared::new(upper));
+            self.starts
+                .insert(chunk + 1, self.starts[chunk] + CHUNK_LEN);
+        }
+    }
+
+    /// Removes and returns the element at `position`, shifting the elements
+    /// after it.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `position >= len`.
+    pub fn remove(&mut self, position: usize) -> T {
+        assert!(
+            position < self.len,
+            "removal index (is {position}) should be < len (is {})",
+            self.len
+        );
+        let chunk = self.chunk_of(position);
+        let value = self.chunks[chunk].remove(position - self.starts[chunk]);
+        if self.chunks[chunk].is_empty() {
+            self.chunks.remove(chunk);
+            self.starts.remove(chunk);
+        }
+        for start in &mut self.starts[chunk..] {
+            if *start > position {
+                *start -= 1;
+            }
+        }
+        self.len -= 1;
+        value
+    }
+
+    pub fn clear(&mut self) {
+        self.chunks.clear();
+        self.starts.clear();
+        self.len = 0;
+    }
+
+    pub fn to_vec(&self) -> Vec<T> {
+        self.iter().cloned().collect()
+    }
+}
+
+impl<T> Default for SharedVec<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> Clone for SharedVec<T> {
+    fn clone(&self) -> Self {
+        Self {
+            chunks: self.chunks.clone(),
+            starts: self.starts.clone(),
+            len: self.len,
+        }
+    }
+}
+
+impl<T> From<Vec<T>> for SharedVec<T> {
+    fn from(elements: Vec<T>) -> Self {
+        let mut vec = Self::new();
+        let mut elements = elements.into_iter();
+        loop {
+            let chunk: Vec<T> = elements.by_ref().take(CHUNK_LEN).collect();
+            if chunk.is_empty() {
+                return vec;
+            }
+            vec.starts.push(vec.len);
+            vec.len += chunk.len();
+            vec.chunks.push(Shared::new(chunk));
+        }
+    }
+}
+
+impl<T> FromIterator<T> for SharedVec<T> {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        Self::from(iter.into_iter().collect::<Vec<_>>())
+    }
+}
+
+impl<T> Index<usize> for SharedVec<T> {
+    type Output = T;
+
+    fn index(&self, position: usize) -> &T {
+        match self.get(position) {
+            Some(element) => element,
+            None => panic!(
+                "index out of bounds: the len is {} but the index is {position}",
+                self.len
+            ),
+        }
+    }
+}
+
+impl<T: Clone> IndexMut<usize> for SharedVec<T> {
+    fn index_mut(&mut self, position: usize) -> &mut T {
+        let len = self.len;
+        match self.get_mut(position) {
+            Some(element) => element,
+            None => panic!("index out of bounds: the len is {len} but the index is {position}"),
+        }
+    }
+}
+
+impl<'a, T> IntoIterator for &'a SharedVec<T> {
+    type Item = &'a T;
+    type IntoIter = Box<dyn DoubleEndedIterator<Item = &'a T> + 'a>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        Box::new(self.iter())
+    }
+}
+
+impl<'a, T: Clone> IntoIterator for &'a mut SharedVec<T> {
+    type Item = &'a mut T;
+    type IntoIter = Box<dyn Iterator<Item = &'a mut T> + 'a>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        Box::new(self.iter_mut())
+    }
+}
+
+impl<T: PartialEq> PartialEq for SharedVec<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.len == other.len && self.iter().eq(other.iter())
+    }
+}
+
+impl<T: PartialEq> PartialEq<Vec<T>> for SharedVec<T> {
+    fn eq(&self, other: &Vec<T>) -> bool {
+        self.len == other.len() && self.iter().eq(other.iter())
+    }
+}
+
+impl<T: std::fmt::Debug> std::fmt::Debug for SharedVec<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+impl<T: Serialize> Serialize for SharedVec<T> {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(self.iter())
+    }
+}
+
+impl<'de, T: Deserialize<'de>> Deserialize<'de> for SharedVec<T> {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        Vec::deserialize(deserializer).map(Self::from)
+    }
+}
+
+/// An ordered map whose entries versions of a database share a chunk of
+/// neighbouring keys at a time, so that changing one entry of a large index
+/// copies only its chunk.
+///
+/// Every key of a chunk sorts before the keys of the chunks after it, and
+/// only a map's sole chunk can be empty.
+pub struct SharedMap<K, V> {
+    chunks: Vec<Shared<BTreeMap<K, V>>>,
+}
+
+impl<K: Ord, V> SharedMap<K, V> {
+    pub fn new() -> Self {
+        Self { chunks: Vec::new() }
+    }
+
+    pub fn len(&self) -> usize {
+        self.chunks.iter().map(|chunk| chunk.len()).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.chunks.iter().all(|chunk| chunk.is_empty())
+    }
+
+    /// The chunk `key` belongs in: the last one whose first key is not
+    /// above it.
+    fn chunk_for<Q>(&self, key: &Q) -> usize
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        self.chunks
+            .partition_point(|chunk| {
+                chunk
+                    .first_key_value()
+                    .is_some_and(|(first, _)| first.borrow() <= key)
+            })
+            .saturating_sub(1)
+    }
+
+    pub fn get<Q>(&self, key: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        self.chunks.get(self.chunk_for(key))?.get(key)
+    }
+
+    pub fn contains_key<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        self.get(key).is_some()
+    }
+
+    pub fn iter(&self) -> impl DoubleEndedIterator<Item = (&K, &V)> + Clone + '_ {
+        self.chunks.iter().flat_map(|chunk| chunk.iter())
+    }
+
+    pub fn keys(&self) -> impl DoubleEndedIterator<Item = &K> + Clone + '_ {
+        self.iter().map(|(key, _)| key)
+    }
+
+    pub fn values(&self) -> impl DoubleEndedIterator<Item = &V> + Clone + '_ {
+        self.iter().map(|(_, value)| value)
+    }
+
+    pub fn range<Q, R>(&self, range: R) -> impl DoubleEndedIterator<Item = (&K, &V)>
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+        R: RangeBounds<Q> + Clone,
+    {
+        self.chunks
+            .iter()
+            .flat_map(move |chunk| chunk.range::<Q, R>(range.clone()))
+    }
+}
+
+impl<K: Ord + Clone, V: Clone> SharedMap<K, V> {
+    pub fn get_mut<Q>(&mut self, key: &Q) -> Option<&mut V>
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        let chunk = self.chunk_for(key);
+        let chunk = self.chunks.get_mut(chunk)?;
+        if !chunk.contains_key(key) {
+            return None;
+        }
+        chunk.get_mut(key)
+    }
+
+    /// The chunk `key` belongs in, split first if it is full.
+    fn chunk_for_insert(&mut self, key: &K) -> &mut BTreeMap<K, V> {
+        if self.chunks.is_empty() {
+            self.chunks.push(Shared::default());
+        }
+        let mut chunk = self.chunk_for(key);
+        if self.chunks[chunk].len() >= 2 * CHUNK_LEN {
+            let middle = self.chunks[chunk].keys().nth(CHUNK_LEN).cloned();
+            if let Some(middle) = middle {
+                let upper = self.chunks[chunk].split_off(&middle);
+                self.chunks.insert(chunk + 1, Shared::new(upper));
+                if *key >= middle {
+                    chunk += 1;
+                }
+            }
+        }
+        &mut self.chunks[chunk]
+    }
+
+    pub fn entry(&mut self, key: K) -> Entry<'_, K, V> {
+        self.chunk_for_insert(&key).entry(key)
+    }
+
+    pub fn insert(&mut self, key: K, value: V) -> Option<V> {
+        let chunk = self.chunk_for_insert(&key);
+        chunk.insert(key, value)
+    }
+
+    pub fn remove<Q>(&mut self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        let chunk = self.chunk_for(key);
+        if !self.chunks.get(chunk)?.contains_key(key) {
+            return None;
+        }
+        let value = self.chunks[chunk].remove(key);
+        if self.chunks[chunk].is_empty() && self.chunks.len() > 1 {
+            self.chunks.remove(chunk);
+        }
+        value
+    }
+}
+
+impl<K, V> Default for SharedMap<K, V> {
+    fn default() -> Self {
+        Self { chunks: Vec::new() }
+    }
+}
+
+impl<K, V> Clone for SharedMap<K, V> {
+    fn clone(&self) -> Self {
+        Self {
+            chunks: self.chunks.clone(),
+        }
+    }
+}
+
+impl<K: Ord, V> From<BTreeMap<K, V>> for SharedMap<K, V> {
+    fn from(entries: BTreeMap<K, V>) -> Self {
+        let mut chunks = Vec::new();
+        let mut chunk = BTreeMap::new();
+        for (key, value) in entries {
+            chunk.insert(key, value);
+            if chunk.len() == CHUNK_LEN {
+                chunks.push(Shared::new(std::mem::take(&mut chunk)));
+            }
+        }
+        if !chunk.is_empty() {
+            chunks.push(Shared::new(chunk));
+        }
+        Self { chunks }
+    }
+}
+
+impl<K: Ord, V> FromIterator<(K, V)> for SharedMap<K, V> {
+    fn from_iter<I: IntoIterator<Item = (K, V)>>(iter: I) -> Self {
+        Self::from(iter.into_iter().collect::<BTreeMap<_, _>>())
+    }
+}
+
+impl<K, Q, V> Index<&Q> for SharedMap<K, V>
+where
+    K: Borrow<Q> + Ord,
+    Q: Ord + ?Sized,
+{
+    type Output = V;
+
+    fn index(&self, key: &Q) -> &V {
+        self.get(key).expect("no entry found for key")
+    }
+}
+
+impl<'a, K: Ord, V> IntoIterator for &'a SharedMap<K, V> {
+    type Item = (&'a K, &'a V);
+    type IntoIter = Box<dyn DoubleEndedIterator<Item = (&'a K, &'a V)> + 'a>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        Box::new(self.iter())
+    }
+}
+
+impl<K: Ord + PartialEq, V: PartialEq> PartialEq for SharedMap<K, V> {
+    fn eq(&self, other: &Self) -> bool {
+        self.iter().eq(other.iter())
+    }
+}
+
+impl<K: Ord + PartialEq, V: PartialEq> PartialEq<BTreeMap<K, V>> for SharedMap<K, V> {
+    fn eq(&self, other: &BTreeMap<K, V>) -> bool {
+        self.iter().eq(other.iter())
+    }
+}
+
+impl<K: Ord + std::fmt::Debug, V: std::fmt::Debug> std::fmt::Debug for SharedMap<K, V> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_map().entries(self.iter()).finish()
+    }
+}
@@ -0,0 +1,334 @@
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::cmp::Ordering;
+use std::fmt;
+
+/// Most digits a `DECIMAL` value can hold, before and after the point.
+pub const MAX_DECIMAL_PRECISION: u32 = 38;
+
+/// Fewest digits after the point a quotient keeps, unless its operands
+/// have more.
+const MIN_DIVISION_SCALE: u32 = 16;
+
+/// One more than the largest mantissa of [`MAX_DECIMAL_PRECISION`] digits.
+const MANTISSA_LIMIT: i128 = 10i128.pow(MAX_DECIMAL_PRECISION);
+
+/// An exact decimal number of up to 38 digits: an integer mantissa and the
+/// number of digits after the decimal point.
+///
+/// A value keeps its scale, so `1.50` displays as written, but compares
+/// equal to `1.5`. Arithmetic returns `None` when the result does not fit.
+#[derive(Debug, Clone, Copy)]
+pub struct Decimal {
+    mantissa: i128,
+    scale: u32,
+}
+
+impl Decimal {
+    /// The value `mantissa * 10^-scale`, or `None` when it has more than
+    /// 38 digits.
+    pub fn new(mantissa: i128, scale: u32) -> Option<Decimal> {
+        (scale <= MAX_DECIMAL_PRECISION && mantissa.unsigned_abs() < MANTISSA_LIMIT as u128)
+            .then_some(Decimal { mantissa, scale })
+    }
+
+    pub fn from_i64(value: i64) -> Decimal {
+        Decimal {
+            mantissa: value as i128,
+            scale: 0,
+        }
+    }
+
+    /// The decimal a finite float displays as, so `0.1` becomes exactly
+    /// `0.1` rather than the binary fraction nearest to it.
+    pub fn from_f64(value: f64) -> Option<Decimal> {
+        if value.is_finite() {
+            Decimal::parse(&value.to_string())
+        } else {
+            None
+        }
+    }
+
+    /// Reads an optionally signed number such as `-12.50`. Digits past the
+    /// 38th after the point are rounded off.
+    pub fn parse(text: &str) -> Option<Decimal> {
+        let (negative, digits) = match text.as_bytes().first()? {
+            b'-' => (true, &text[1..]),
+            b'+' => (false, &text[1..]),
+            _ => (false, text),
+        };
+        let (whole, fraction) = digits.split_once('.').unwrap_or((digits, ""));
+        if (whole.is_empty() && fraction.is_empty())
+            || !whole
+                .bytes()
+                .chain(fraction.bytes())
+                .all(|b| b.is_ascii_digit())
+        {
+            return None;
+        }
+
+        let kept = fraction.len().min(MAX_DECIMAL_PRECISION as usize);
+        let mut mantissa: i128 = 0;
+        for digit in whole.bytes().chain(fraction[..kept].bytes()) {
+            mantissa = mantissa
+                .checked_mul(10)?
+                .checked_add(i128::from(digit - b'0'))?;
+        }
+        if fraction
+            .as_bytes()
+            .get(kept)
+            .is_some_and(|digit| *digit >= b'5')
+        {
+            mantissa = mantissa.checked_add(1)?;
+        }
+        Decimal::new(if negative { -mantissa } else { mantissa }, kept as u32)
+    }
+
+    pub fn mantissa(&self) -> i128 {
+        self.mantissa
+    }
+
+    /// Number of digits after the decimal point.
+    pub fn scale(&self) -> u32 {
+        self.scale
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.mantissa == 0
+    }
+
+    /// Number of digits in the mantissa, counting none for zero.
+    pub fn digits(&self) -> u32 {
+        self.mantissa
+            .unsigned_abs()
+            .checked_ilog10()
+            .map_or(0, |log| log + 1)
+    }
+
+    /// The same value with `scale` digits after the point, rounding half
+    /// away from zero when digits are dropped.
+    pub fn rescale(&self, scale: u32) -> Option<Decimal> {
+        if scale >= self.scale {
+            let factor = 10i128.checked_pow(scale - self.scale)?;
+            Decimal::new(self.mantissa.checked_mul(factor)?, scale)
+        } else {
+            let factor = 10i128.checked_pow(self.scale - scale)?;
+            Decimal::new(round_div(self.mantissa, factor), scale)
+        }
+    }
+
+    /// Whether the value fits `DECIMAL(precision, scale)` once rounded to
+    /// `scale` digits, and if so the rounded value.
+    pub fn fit(&self, precision: u32, scale: u32) -> Option<Decimal> {
+        self.rescale(scale)
+            .filter(|rounded| rounded.digits() <= precision)
+    }
+
+    pub fn checked_add(&self, other: &Decimal) -> Option<Decimal> {
+        let scale = self.scale.max(other.scale);
+        let (left, right) = (self.rescale(scale)?, other.rescale(scale)?);
+        Decimal::new(left.mantissa.checked_add(right.mantissa)?, scale)
+    }
+
+    pub fn checked_sub(&self, other: &Decimal) -> Option<Decimal> {
+        self.checked_add(&other.negate())
+    }
+
+    /// The exact product, rounded to 38 digits after the point.
+    pub fn checked_mul(&self, other: &Decimal) -> Option<Decimal> {
+        let mantissa = self.mantissa.checked_mul(other.mantissa)?;
+        let scale = self.scale + other.scale;
+        if scale > MAX_DECIMAL_PRECISION {
+            let factor = 10i128.checked_pow(scale - MAX_DECIMAL_PRECISION)?;
+            Decimal::new(round_div(mantissa, factor), MAX_DECIMAL_PRECISION)
+        } else {
+            Decimal::new(mantissa, scale)
+        }
+    }
+
+    /// The quotient rounded to at least 16 digits after the point, or as
+    /// many as either operand has, then stripped of trailing zeros down to
+    /// the larger operand scale. Returns `None` for a zero divisor.
+    pub fn checked_div(&self, other: &Decimal) -> Option<Decimal> {
+        if other.is_zero() {
+            return None;
+        }
+        let operand_scale = self.scale.max(other.scale);
+        let mut scale = operand_scale.max(MIN_DIVISION_SCALE);
+        let numerator = loop {
+            // The quotient of mantissas has scale `self.scale - other.scale`.
+            let shift = (scale + other.scale).checked_sub(self.scale)?;
+            if let Some(numerator) = 10i128
+                .checked_pow(shift)
+                .and_then(|factor| self.mantissa.checked_mul(factor))
+            {
+                break numerator;
+            }
+            scale = scale.checked_sub(1)?;
+        };
+        let quotient = Decimal::new(round_div(numerator, other.mantissa), scale)?;
+        Some(if quotient.normalize().scale < operand_scale {
+            quotient.rescale(operand_scale)?
+        } else {
+            quotient.normalize()
+        })
+    }
+
+    /// The same value without trailing zeros after the point, so that equal
+    /// values have one form.
+    pub fn normalize(&self) -> Decimal {
+        let mut normalized = *self;
+        while normalized.scale > 0 && normalized.mantissa % 10 == 0 {
+            normalized.mantissa /= 10;
+            normalized.scale -= 1;
+        }
+        normalized
+    }
+
+    pub fn negate(&self) -> Decimal {
+        Decimal {
+            mantissa: -self.mantissa,
+            scale: self.scale,
+        }
+    }
+
+    pub fn abs(&self) -> Decimal {
+        Decimal {
+            mantissa: self.mantissa.abs(),
+            scale: self.scale,
+        }
+    }
+
+    /// Rounds half away from zero to `digits` places after the point, or
+    /// to tens, hundreds and so on when `digits` is negative. A value that
+    /// already has no more digits is returned unchanged.
+    pub fn round_to(&self, digits: i32) -> Option<Decimal> {
+        self.drop_digits(digits, round_div)
+    }
+
+    /// Like [`Decimal::round_to`], but discards the dropped digits.
+    pub fn trunc_to(&self, digits: i32) -> Option<Decimal> {
+        self.drop_digits(digits, |numerator, denominator| numerator / denominator)
+    }
+
+    fn drop_digits(&self, digits: i32, divide: fn(i128, i128) -> i128) -> Option<Decimal> {
+        if digits >= 0 {
+            let digits = digits as u32;
+            if digits >= self.scale {
+                return Some(*self);
+            }
+            let factor = 10i128.pow(self.scale - digits);
+            return Decimal::new(divide(self.mantissa, factor), digits);
+        }
+        let places = digits.unsigned_abs();
+        let Some(factor) = 10i128.checked_pow(self.scale + places) else {
+            return Some(Decimal::from_i64(0));
+        };
+        let whole = divide(self.mantissa, factor);
+        Decimal::new(whole.checked_mul(10i128.checked_pow(places)?)?, 0)
+    }
+
+    /// The smallest whole number not below the value.
+    pub fn ceil(&self) -> Option<Decimal> {
+        let factor = 10i128.pow(self.scale);
+        let whole = self.mantissa / factor;
+        let whole = if self.mantissa % factor > 0 {
+            whole + 1
+        } else {
+            whole
+        };
+        Decimal::new(whole, 0)
+    }
+
+    /// The largest whole number not above the value.
+    pub fn floor(&self) -> Option<Decimal> {
+        let factor = 10i128.pow(self.scale);
+        let whole = self.mantissa / factor;
+        let whole = if self.mantissa % factor < 0 {
+            whole - 1
+        } else {
+            whole
+        };
+        Decimal::new(whole, 0)
+    }
+
+    /// The integer part, or `None` outside the `i64` range.
+    pub fn trunc_to_i64(&self) -> Option<i64> {
+        let whole = self.mantissa / 10i128.pow(self.scale);
+        i64::try_from(whole).ok()
+    }
+
+    /// The nearest float.
+    pub fn to_f64(&self) -> f64 {
+        self.to_string().parse().unwrap_or(f64::NAN)
+    }
+}
+
+/// `numerator / denominator`, rounding half away from zero.
+fn round_div(numerator: i128, denominator: i128) -> i128 {
+    let quotient = numerator / denominator;
+    let remainder = numerator % denominator;
+    if remainder.unsigned_abs() * 2 >= denominator.unsigned_abs() {
+        if (numerator < 0) == (denominator < 0) {
+            quotient + 1
+        } else {
+            quotient - 1
+        }
+    } else {
+        quotient
+    }
+}
+
+impl PartialEq for Decimal {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Decimal {}
+
+impl PartialOrd for Decimal {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Decimal {
+    fn cmp(&self, other: &Self) -> Ordering {
+        let scale = self.scale.max(other.scale);
+        match (self.rescale(scale), other.rescale(scale)) {
+            (Some(left), Some(right)) => left.mantissa.cmp(&right.mantissa),
+            // Only a value whose whole part is too long to take more
+            // digits overflows, and it outweighs the other.
+            (None, _) => self.mantissa.signum().cmp(&0),
+            (_, None) => 0.cmp(&other.mantissa.signum()),
+        }
+    }
+}
+
+impl fmt::Display for Decimal {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let digits = self.mantissa.unsigned_abs().to_string();
+        let scale = self.scale as usize;
+        let sign = if self.mantissa < 0 { "-" } else { "" };
+        if scale == 0 {
+            return write!(f, "{}{}", sign, digits);
+        }
+        let digits = format!("{:0>width$}", digits, width = scale + 1);
+        let (whole, fraction) = digits.split_at(digits.len() - scale);
+        write!(f, "{}{}.{}", sign, whole, fraction)
+    }
+}
+
+impl Serialize for Decimal {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for Decimal {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let text = String::deserialize(deserializer)?;
+        Decimal::parse(&text)
+            .ok_or_else(|| serde::de::Error::custom(format!("invalid decimal '{}'", text)))
+    }
+}
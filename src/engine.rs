@@ -1,15 +1,21 @@
 use crate::ast::{DataType, Statement, Value};
 use crate::database::Database;
-use crate::error::RustqlError;
-use crate::storage::StorageEngine;
+use crate::error::{IntegrityProblem, RustqlError};
+use crate::storage::{
+    BTreeStorageEngine, EncryptionKey, ReplicationSource, ReplicationTarget, StorageEngine,
+};
 use crate::{executor, lexer, parser};
-#[cfg(not(feature = "testing-api"))]
 use std::fmt;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::Receiver;
+use std::time::Duration;
 
 const ENV_STORAGE_KIND: &str = "RUSTQL_STORAGE";
 const ENV_STORAGE_PATH: &str = "RUSTQL_STORAGE_PATH";
+const ENV_ENCRYPTION_KEY: &str = "RUSTQL_ENCRYPTION_KEY";
+const ENV_BUSY_TIMEOUT: &str = "RUSTQL_BUSY_TIMEOUT";
 const DEFAULT_JSON_PATH: &str = "rustql_data.json";
 const DEFAULT_BTREE_PATH: &str = "rustql_btree.dat";
 
@@ -50,17 +56,17 @@ pub(crate) fn plan_tree_from_node(node: crate::planner::PlanNode) -> PlanTree {
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Clone)]
 pub enum StorageMode {
     Memory,
     Json {
         path: PathBuf,
     },
-    /// Page-formatted snapshot storage.
+    /// Page-formatted storage updated in place.
     ///
-    /// Saves write a complete database image to a temporary file and atomically
-    /// replace the storage file. The `.wal` file is a commit-recovery journal
-    /// for prepared snapshots, not an incremental mutation log.
+    /// Commits append the pages they change to a redo log in the `.wal` file,
+    /// which checkpoints fold into the main file. Table rows are read on
+    /// demand through a page cache bounded by [`EngineOptions::page_cache_size`].
     BTree {
         path: PathBuf,
     },
@@ -68,11 +74,49 @@ pub enum StorageMode {
     Disk {
         path: PathBuf,
     },
+    /// A storage backend supplied by the caller.
+    ///
+    /// The engine loads the database from it once when opened and hands it
+    /// every commit; see [`StorageEngine`] for the calls it receives.
+    Custom(Arc<dyn StorageEngine>),
+}
+
+#[allow(deprecated)]
+impl fmt::Debug for StorageMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StorageMode::Memory => f.write_str("Memory"),
+            StorageMode::Json { path } => f.debug_struct("Json").field("path", path).finish(),
+            StorageMode::BTree { path } => f.debug_struct("BTree").field("path", path).finish(),
+            StorageMode::Disk { path } => f.debug_struct("Disk").field("path", path).finish(),
+            StorageMode::Custom(_) => f.debug_tuple("Custom").finish_non_exhaustive(),
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
 pub struct EngineOptions {
     pub storage: StorageMode,
+    /// Maximum number of 4 KiB pages the B-tree page cache keeps in memory.
+    /// Ignored by the other storage modes.
+    pub page_cache_size: usize,
+    /// Number of pages the B-tree redo log may hold before a commit folds it
+    /// into the main file. `CHECKPOINT` does the same on demand.
+    pub checkpoint_pages: usize,
+    /// Key the B-tree file is encrypted with. A new file is created
+    /// encrypted; an existing file must have been written with the same key.
+    /// Setting a key for the other storage modes is an error.
+    pub encryption_key: Option<EncryptionKey>,
+    /// How long JSON and B-tree storage wait for another process to release
+    /// the file before failing with [`RustqlError::DatabaseLocked`]. Zero
+    /// fails at once.
+    pub busy_timeout: Duration,
+    /// Where a B-tree engine ships its commits for a follower to apply. See
+    /// [`EngineOptions::with_log_shipping`].
+    pub ship_log_to: Option<ReplicationTarget>,
+    /// The shipped log a B-tree engine follows as a read-only replica. See
+    /// [`EngineOptions::follower`].
+    pub follow: Option<ReplicationSource>,
 }
 
 impl Default for EngineOptions {
@@ -81,6 +125,12 @@ impl Default for EngineOptions {
             storage: StorageMode::Json {
                 path: PathBuf::from(DEFAULT_JSON_PATH),
             },
+            page_cache_size: crate::storage::DEFAULT_PAGE_CACHE_SIZE,
+            checkpoint_pages: crate::storage::DEFAULT_CHECKPOINT_PAGES,
+            encryption_key: None,
+            busy_timeout: crate::storage::DEFAULT_BUSY_TIMEOUT,
+            ship_log_to: None,
+            follow: None,
         }
     }
 }
@@ -89,32 +139,87 @@ impl EngineOptions {
     pub fn memory() -> Self {
         Self {
             storage: StorageMode::Memory,
+            ..Self::default()
         }
     }
 
     pub fn json(path: impl Into<PathBuf>) -> Self {
         Self {
             storage: StorageMode::Json { path: path.into() },
+            ..Self::default()
         }
     }
 
     pub fn btree(path: impl Into<PathBuf>) -> Self {
         Self {
             storage: StorageMode::BTree { path: path.into() },
+            ..Self::default()
         }
     }
 
+    /// Opens the B-tree file at `path` as a read-only follower of the primary
+    /// that ships its log to `source`.
+    ///
+    /// The follower's file is brought up to the primary's state from the
+    /// latest image the primary shipped and kept there by applying every
+    /// commit shipped after it, in the background. Its sessions see each
+    /// commit once it has been applied, and statements that write fail until
+    /// [`Engine::promote`] turns the follower into a standalone database.
+    pub fn follower(path: impl Into<PathBuf>, source: ReplicationSource) -> Self {
+        Self {
+            follow: Some(source),
+            ..Self::btree(path)
+        }
+    }
+
+    pub fn with_page_cache_size(mut self, pages: usize) -> Self {
+        self.page_cache_size = pages;
+        self
+    }
+
+    pub fn with_checkpoint_pages(mut self, pages: usize) -> Self {
+        self.checkpoint_pages = pages;
+        self
+    }
+
+    pub fn with_encryption_key(mut self, key: EncryptionKey) -> Self {
+        self.encryption_key = Some(key);
+        self
+    }
+
+    pub fn with_busy_timeout(mut self, timeout: Duration) -> Self {
+        self.busy_timeout = timeout;
+        self
+    }
+
+    /// Ships every commit to `target` for a follower opened with
+    /// [`EngineOptions::follower`].
+    ///
+    /// Opening the engine ships an image of the whole file to start from.
+    /// After that each commit ships the pages it wrote once the redo log has
+    /// made it durable, and `VACUUM` and rekeying ship a new image. A commit
+    /// that cannot be shipped still stands but stays queued, and the next
+    /// commit fails with a storage error unless the queued ones can be
+    /// shipped first. A log directory only keeps the records from the latest
+    /// image on.
+    pub fn with_log_shipping(mut self, target: ReplicationTarget) -> Self {
+        self.ship_log_to = Some(target);
+        self
+    }
+
     pub fn from_env() -> Result<Self, RustqlError> {
         let storage = match std::env::var(ENV_STORAGE_KIND) {
             Ok(value) if value.eq_ignore_ascii_case("btree") => Ok(Self {
                 storage: StorageMode::BTree {
                     path: storage_path_from_env(DEFAULT_BTREE_PATH)?,
                 },
+                ..Self::default()
             }),
             Ok(value) if value.eq_ignore_ascii_case("json") => Ok(Self {
                 storage: StorageMode::Json {
                     path: storage_path_from_env(DEFAULT_JSON_PATH)?,
                 },
+                ..Self::default()
             }),
             Ok(value) => Err(RustqlError::StorageError(format!(
                 "Unsupported RUSTQL_STORAGE value '{}'. Expected 'json' or 'btree'",
@@ -124,6 +229,7 @@ impl EngineOptions {
                 storage: StorageMode::Json {
                     path: storage_path_from_env(DEFAULT_JSON_PATH)?,
                 },
+                ..Self::default()
             }),
             Err(err) => Err(RustqlError::StorageError(format!(
                 "Failed to read RUSTQL_STORAGE: {}",
@@ -131,10 +237,41 @@ impl EngineOptions {
             ))),
         }?;
 
-        Ok(storage)
+        let storage = match std::env::var(ENV_BUSY_TIMEOUT) {
+            Ok(millis) => storage.with_busy_timeout(busy_timeout_from_env(&millis)?),
+            Err(std::env::VarError::NotPresent) => storage,
+            Err(err) => {
+                return Err(RustqlError::StorageError(format!(
+                    "Failed to read {}: {}",
+                    ENV_BUSY_TIMEOUT, err
+                )));
+            }
+        };
+
+        match std::env::var(ENV_ENCRYPTION_KEY) {
+            Ok(hex) => Ok(storage.with_encryption_key(EncryptionKey::from_hex(&hex)?)),
+            Err(std::env::VarError::NotPresent) => Ok(storage),
+            Err(err) => Err(RustqlError::StorageError(format!(
+                "Failed to read {}: {}",
+                ENV_ENCRYPTION_KEY, err
+            ))),
+        }
     }
 }
 
+fn busy_timeout_from_env(millis: &str) -> Result<Duration, RustqlError> {
+    millis
+        .trim()
+        .parse()
+        .map(Duration::from_millis)
+        .map_err(|_| {
+            RustqlError::StorageError(format!(
+                "{} must be a number of milliseconds, got '{}'",
+                ENV_BUSY_TIMEOUT, millis
+            ))
+        })
+}
+
 fn storage_path_from_env(default_path: &str) -> Result<PathBuf, RustqlError> {
     match std::env::var_os(ENV_STORAGE_PATH) {
         Some(path) if path.is_empty() => Err(RustqlError::StorageError(format!(
@@ -146,12 +283,83 @@ fn storage_path_from_env(default_path: &str) -> Result<PathBuf, RustqlError> {
     }
 }
 
-/// An open RustQL connection.
+/// Stops running statements from another thread.
+///
+/// [`interrupt`](Self::interrupt) makes every statement running on a session
+/// of the engine at that moment fail with [`RustqlError::Cancelled`] at its
+/// next check; statements started later run normally. Queries check between
+/// rows of their scans, joins, sorts and aggregates. A cancelled write
+/// statement is rolled back, and an open transaction stays open.
+#[derive(Debug, Clone)]
+pub struct InterruptHandle {
+    interrupts: Arc<AtomicU64>,
+}
+
+impl InterruptHandle {
+    pub fn interrupt(&self) {
+        self.interrupts.fetch_add(1, Ordering::SeqCst);
+    }
+}
+
+/// What a [`ChangeEvent`] did to its row.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChangeKind {
+    Insert,
+    Update,
+    Delete,
+}
+
+/// A committed change to one row.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChangeEvent {
+    /// The commit that made the change. Every change of a commit carries the
+    /// same id, and later commits of the engine have higher ones.
+    pub transaction_id: u64,
+    pub table: String,
+    pub row_id: u64,
+    pub kind: ChangeKind,
+    /// The row's values before the change; `None` for an insert.
+    pub old: Option<Row>,
+    /// The row's values after the change; `None` for a deletion.
+    pub new: Option<Row>,
+}
+
+/// The row changes committed on an engine since the stream was opened with
+/// [`Engine::subscribe_changes`].
+///
+/// Changes arrive in commit order, and those of one commit in the order its
+/// statements made them. Iterating blocks until the next change is
+/// committed and ends once the engine and all its sessions are dropped.
+#[derive(Debug)]
+pub struct ChangeStream {
+    receiver: Receiver<ChangeEvent>,
+}
+
+impl ChangeStream {
+    /// Returns the next change if one has been committed already.
+    pub fn try_next(&self) -> Option<ChangeEvent> {
+        self.receiver.try_recv().ok()
+    }
+
+    /// Waits up to `timeout` for the next change.
+    pub fn next_timeout(&self, timeout: Duration) -> Option<ChangeEvent> {
+        self.receiver.recv_timeout(timeout).ok()
+    }
+}
+
+impl Iterator for ChangeStream {
+    type Item = ChangeEvent;
+
+    fn next(&mut self) -> Option<ChangeEvent> {
+        self.receiver.recv().ok()
+    }
+}
+
+/// An open RustQL database.
 ///
-/// `Engine` is the transaction boundary: database state, transaction state, and
-/// optional storage are owned by the engine's execution context. Sessions
-/// created from the same engine share that state. Open a separate `Engine` when
-/// you need an independent transaction context.
+/// The engine owns the committed database and its storage. SQL runs through
+/// [`Session`]s, each an independent connection with its own transaction;
+/// sessions may be moved to other threads and used concurrently.
 pub struct Engine {
     context: executor::ExecutionContext,
 }
@@ -171,57 +379,200 @@ impl Engine {
 
     #[allow(deprecated)]
     pub fn open(options: EngineOptions) -> Result<Self, RustqlError> {
-        let (database, storage) = match &options.storage {
-            StorageMode::Memory => (Database::new(), None),
+        let btree = matches!(
+            options.storage,
+            StorageMode::BTree { .. } | StorageMode::Disk { .. }
+        );
+        if options.encryption_key.is_some() && !btree {
+            return Err(RustqlError::StorageError(
+                "Encryption requires B-tree storage".to_string(),
+            ));
+        }
+        if (options.ship_log_to.is_some() || options.follow.is_some()) && !btree {
+            return Err(RustqlError::StorageError(
+                "Log shipping requires B-tree storage".to_string(),
+            ));
+        }
+        if options.ship_log_to.is_some() && options.follow.is_some() {
+            return Err(RustqlError::StorageError(
+                "A follower cannot ship its log; promote it first".to_string(),
+            ));
+        }
+        let (database, storage, pager) = match &options.storage {
+            StorageMode::Memory => (Database::new(), None, None),
             StorageMode::Json { path } => {
-                let storage = Arc::new(crate::storage::JsonStorageEngine::new(path.clone()));
+                let storage = Arc::new(
+                    crate::storage::JsonStorageEngine::new(path.clone())
+                        .with_busy_timeout(options.busy_timeout),
+                );
                 let database = storage.load()?;
                 (
                     database,
                     Some(storage as Arc<dyn crate::storage::StorageEngine>),
+                    None,
                 )
             }
             StorageMode::BTree { path } | StorageMode::Disk { path } => {
-                let storage = Arc::new(crate::storage::BTreeStorageEngine::new(path.clone()));
+                let mut storage =
+                    BTreeStorageEngine::with_page_cache_size(path.clone(), options.page_cache_size)
+                        .with_checkpoint_pages(options.checkpoint_pages)
+                        .with_busy_timeout(options.busy_timeout);
+                if let Some(key) = options.encryption_key.clone() {
+                    storage = storage.with_encryption_key(key);
+                }
+                if let Some(source) = options.follow.clone() {
+                    return Ok(Self {
+                        context: executor::ExecutionContext::follower(
+                            Arc::new(storage),
+                            source,
+                            executor::AttachOptions::from(&options),
+                        )?,
+                    });
+                }
+                if let Some(target) = options.ship_log_to.clone() {
+                    storage = storage.with_log_shipping(target);
+                }
+                let storage = Arc::new(storage);
                 let database = storage.load()?;
+                storage.ship_base_image()?;
                 (
                     database,
-                    Some(storage as Arc<dyn crate::storage::StorageEngine>),
+                    Some(Arc::clone(&storage) as Arc<dyn crate::storage::StorageEngine>),
+                    Some(storage),
                 )
             }
+            StorageMode::Custom(storage) => (storage.load()?, Some(Arc::clone(storage)), None),
         };
 
         Ok(Self {
-            context: executor::ExecutionContext::new(database, storage),
+            context: executor::ExecutionContext::new(
+                database,
+                storage,
+                pager,
+                executor::AttachOptions::from(&options),
+            ),
         })
     }
 
-    /// Creates a lightweight session handle for executing SQL against this
-    /// engine.
+    /// Opens a new connection to this engine.
+    ///
+    /// Each session has its own transaction. `BEGIN` gives it a snapshot of
+    /// the committed database: it sees its own changes and the commits made
+    /// before it began, never those of other sessions' open or later
+    /// transactions. See [`Session`] for how commits that collide are
+    /// resolved.
+    pub fn session(&self) -> Session {
+        Session {
+            context: self.context.new_session(),
+        }
+    }
+
+    /// Returns a handle that stops the statements running on this engine
+    /// from another thread.
+    pub fn interrupt_handle(&self) -> InterruptHandle {
+        InterruptHandle {
+            interrupts: self.context.interrupt_counter(),
+        }
+    }
+
+    /// Opens a stream of the row changes committed from now on.
+    ///
+    /// Each insert, update and delete shows up once its statement commits,
+    /// or with its transaction's `COMMIT`; rolled back changes never do.
+    /// `TRUNCATE` and `DROP TABLE` show up as a deletion of every row, and
+    /// other schema changes are not streamed. Changes are kept for a stream
+    /// until it reads them, so a stream that is no longer read should be
+    /// dropped.
+    pub fn subscribe_changes(&self) -> ChangeStream {
+        ChangeStream {
+            receiver: self.context.subscribe_changes(),
+        }
+    }
+
+    /// Writes a consistent copy of the committed database to a new B-tree
+    /// file at `path`. Fails with [`RustqlError::StorageError`] when a file
+    /// or redo log already exists there.
+    ///
+    /// Changes of a transaction that is still open are left out. With B-tree
+    /// storage the copy is taken from the storage file and its redo log, so
+    /// other statements keep running while it is written. Open the backup
+    /// with [`EngineOptions::btree`] to restore it.
+    pub fn backup_to(&self, path: impl AsRef<Path>) -> Result<(), RustqlError> {
+        executor::backup(&self.context, path.as_ref())
+    }
+
+    /// Checks the database for damage and returns every problem found; an
+    /// empty list means the database is sound.
+    ///
+    /// With B-tree storage this verifies each page checksum and the ordering
+    /// and links of the tree. For every storage mode it checks that row ids
+    /// are unique, that index entries match the rows they point at, and that
+    /// the stored rows satisfy their NOT NULL, PRIMARY KEY, UNIQUE and
+    /// FOREIGN KEY constraints. `CHECK DATABASE` returns the same list as
+    /// rows.
+    pub fn check_database(&self) -> Result<Vec<IntegrityProblem>, RustqlError> {
+        executor::check_database(&self.context)
+    }
+
+    /// Rewrites the B-tree file encrypted with `key`, or unencrypted when
+    /// `key` is `None`. Later opens must pass the new key in
+    /// [`EngineOptions::encryption_key`].
+    pub fn rekey(&self, key: Option<EncryptionKey>) -> Result<(), RustqlError> {
+        executor::rekey(&self.context, key)
+    }
+
+    /// Whether the engine follows a primary and rejects writes.
+    pub fn is_follower(&self) -> bool {
+        self.context.is_following()
+    }
+
+    /// Turns a follower into a standalone database that accepts writes.
     ///
-    /// A session borrows the engine; it does not own separate transaction
-    /// state. `BEGIN`, `COMMIT`, `ROLLBACK`, and savepoints apply to the
-    /// engine, so other sessions created from this engine observe the same
-    /// transaction.
-    pub fn session(&self) -> Session<'_> {
-        Session { engine: self }
+    /// Everything the primary shipped so far is applied first; records
+    /// shipped later are ignored. Fails if the engine was not opened with
+    /// [`EngineOptions::follower`] or has been promoted already.
+    pub fn promote(&self) -> Result<(), RustqlError> {
+        self.context.promote()
     }
 
     #[cfg(feature = "testing-api")]
     pub fn snapshot_database(&self) -> Database {
+        self.try_snapshot_database().unwrap_or_else(|err| {
+            panic!("failed to load paged tables for a database snapshot: {err}")
+        })
+    }
+
+    /// Like `snapshot_database`, but returns the error instead of panicking
+    /// when a paged table cannot be read.
+    #[cfg(feature = "testing-api")]
+    pub fn try_snapshot_database(&self) -> Result<Database, RustqlError> {
         self.context.database_snapshot()
     }
 }
 
-/// A lightweight SQL execution handle borrowed from an [`Engine`].
+/// A connection to an [`Engine`], with its own transaction state.
+///
+/// Outside a transaction every statement runs on a snapshot of the
+/// committed database and commits when it finishes. Read-only statements
+/// run in parallel with those of other sessions; statements that write wait
+/// for each other, and their changes become visible only once they commit.
+/// Inside a transaction, reads see the snapshot taken at `BEGIN` plus the session's own
+/// writes, and `COMMIT` publishes the writes at once. Tables are shared
+/// copy-on-write between the committed database and open snapshots, so a
+/// snapshot is cheap to take and a table is copied only when one side
+/// writes to it.
 ///
-/// `Session` is not an independent connection. Transaction ownership remains
-/// with the engine that created the session.
-pub struct Session<'e> {
-    engine: &'e Engine,
+/// When another transaction has committed since the snapshot was taken,
+/// `COMMIT` applies this transaction's writes on top of it. If both changed
+/// the same row, or one altered a table the other wrote to, the commit fails
+/// with [`RustqlError::SerializationFailure`] and the transaction is rolled
+/// back so it can be retried. Dropping a session rolls back its open
+/// transaction.
+pub struct Session {
+    context: executor::ExecutionContext,
 }
 
-impl Session<'_> {
+impl Session {
     pub fn execute(&mut self, sql: &str) -> Result<Vec<QueryResult>, RustqlError> {
         self.execute_script(sql)
     }
@@ -246,6 +597,19 @@ impl Session<'_> {
         self.execute_statement_inner(statement)
     }
 
+    /// Runs one statement with `params` bound to its `$1`, `$2`, ...
+    /// placeholders, so values such as [`Value::Blob`] reach the query
+    /// without being spelled out in SQL.
+    pub fn execute_one_with_params(
+        &mut self,
+        sql: &str,
+        params: &[Value],
+    ) -> Result<QueryResult, RustqlError> {
+        let tokens = lexer::bind_parameters(lexer::tokenize_spanned(sql)?, params)?;
+        let statement = parser::parse_spanned(tokens)?;
+        self.execute_statement_inner(statement)
+    }
+
     #[cfg(feature = "testing-api")]
     pub fn execute_statement(&mut self, statement: Statement) -> Result<QueryResult, RustqlError> {
         self.execute_statement_inner(statement)
@@ -255,7 +619,35 @@ impl Session<'_> {
         &mut self,
         statement: Statement,
     ) -> Result<QueryResult, RustqlError> {
-        executor::execute(&self.engine.context, statement)
+        executor::execute(&self.context, statement)
+    }
+
+    /// Whether the engine follows a primary and rejects writes.
+    pub fn is_follower(&self) -> bool {
+        self.context.is_following()
+    }
+
+    /// Turns a follower into a standalone database that accepts writes.
+    ///
+    /// Everything the primary shipped so far is applied first; records
+    /// shipped later are ignored. Fails if the engine was not opened with
+    /// [`EngineOptions::follower`] or has been promoted already.
+    pub fn promote(&self) -> Result<(), RustqlError> {
+        self.context.promote()
+    }
+
+    #[cfg(feature = "testing-api")]
+    pub fn snapshot_database(&self) -> Database {
+        self.try_snapshot_database().unwrap_or_else(|err| {
+            panic!("failed to load paged tables for a database snapshot: {err}")
+        })
+    }
+
+    /// Like `snapshot_database`, but returns the error instead of panicking
+    /// when a paged table cannot be read.
+    #[cfg(feature = "testing-api")]
+    pub fn try_snapshot_database(&self) -> Result<Database, RustqlError> {
+        self.context.database_snapshot()
     }
 }
 
@@ -283,17 +675,27 @@ pub enum CommandTag {
     Update,
     Delete,
     BeginTransaction,
+    SetTransaction,
+    SetConstraints,
+    SetStatementTimeout,
     CommitTransaction,
     RollbackTransaction,
     Savepoint,
     ReleaseSavepoint,
     RollbackToSavepoint,
+    PrepareTransaction,
+    CommitPrepared,
+    RollbackPrepared,
     Analyze,
     TruncateTable,
     CreateView,
     DropView,
     Merge,
     Do,
+    Checkpoint,
+    Backup,
+    Attach,
+    Detach,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
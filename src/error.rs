@@ -33,6 +33,82 @@ impl fmt::Display for ConstraintKind {
     }
 }
 
+/// A problem found by `CHECK DATABASE` or
+/// [`Engine::check_database`](crate::Engine::check_database).
+#[derive(Debug, Clone, PartialEq)]
+pub enum IntegrityProblem {
+    /// The storage file header is unreadable or the file is not made of
+    /// whole pages.
+    StorageFile { message: String },
+    /// A page that does not match its checksum or cannot be decoded.
+    CorruptPage { page_id: u64, message: String },
+    /// A page that breaks the ordering or linking rules of the B-tree.
+    PageStructure { page_id: u64, message: String },
+    /// A row id stored for more than one row of a table.
+    DuplicateRowId { table: String, row_id: u64 },
+    /// An index entry naming a missing row or a key its row does not hold,
+    /// or a row missing from an index.
+    IndexEntry { index: String, message: String },
+    /// Stored rows that break a NOT NULL, UNIQUE, PRIMARY KEY or FOREIGN KEY
+    /// constraint.
+    Constraint {
+        kind: ConstraintKind,
+        table: String,
+        message: String,
+    },
+}
+
+impl IntegrityProblem {
+    /// The name `CHECK DATABASE` reports in its `problem` column.
+    pub fn kind_name(&self) -> &'static str {
+        match self {
+            IntegrityProblem::StorageFile { .. } => "storage_file",
+            IntegrityProblem::CorruptPage { .. } => "corrupt_page",
+            IntegrityProblem::PageStructure { .. } => "page_structure",
+            IntegrityProblem::DuplicateRowId { .. } => "duplicate_row_id",
+            IntegrityProblem::IndexEntry { .. } => "index_entry",
+            IntegrityProblem::Constraint { .. } => "constraint",
+        }
+    }
+
+    /// The page, table or index the problem was found in.
+    pub fn object(&self) -> Option<String> {
+        match self {
+            IntegrityProblem::StorageFile { .. } => None,
+            IntegrityProblem::CorruptPage { page_id, .. }
+            | IntegrityProblem::PageStructure { page_id, .. } => Some(format!("page {}", page_id)),
+            IntegrityProblem::DuplicateRowId { table, .. }
+            | IntegrityProblem::Constraint { table, .. } => Some(table.clone()),
+            IntegrityProblem::IndexEntry { index, .. } => Some(index.clone()),
+        }
+    }
+
+    /// Describes the problem without naming its object.
+    pub fn detail(&self) -> String {
+        match self {
+            IntegrityProblem::StorageFile { message }
+            | IntegrityProblem::CorruptPage { message, .. }
+            | IntegrityProblem::PageStructure { message, .. }
+            | IntegrityProblem::IndexEntry { message, .. } => message.clone(),
+            IntegrityProblem::DuplicateRowId { row_id, .. } => {
+                format!("Row id {} is stored more than once", row_id)
+            }
+            IntegrityProblem::Constraint { kind, message, .. } => {
+                format!("{} constraint violated: {}", kind, message)
+            }
+        }
+    }
+}
+
+impl fmt::Display for IntegrityProblem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.object() {
+            Some(object) => write!(f, "{}: {}", object, self.detail()),
+            None => write!(f, "{}", self.detail()),
+        }
+    }
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum QueryClause {
     Having,
@@ -74,12 +150,26 @@ pub enum RustqlError {
     StorageError(String),
     TypeMismatch(String),
     TransactionError(String),
+    /// A transaction could not commit because a transaction that committed
+    /// after its snapshot was taken changed the same data. The transaction
+    /// has been rolled back and can be retried.
+    SerializationFailure(String),
+    /// Another process holds the lock on the storage file and did not
+    /// release it within the busy timeout. Nothing was read or written.
+    DatabaseLocked(String),
     AggregateError(String),
     IndexError(String),
     IndexNotFound {
         name: String,
     },
     DivisionByZero,
+    /// The statement was stopped through an
+    /// [`InterruptHandle`](crate::InterruptHandle). A write statement's
+    /// changes were rolled back.
+    Cancelled,
+    /// The statement ran longer than the session's `statement_timeout`. A
+    /// write statement's changes were rolled back.
+    Timeout,
     Internal(String),
 }
 
@@ -119,12 +209,18 @@ impl fmt::Display for RustqlError {
             RustqlError::StorageError(msg) => write!(f, "{}", msg),
             RustqlError::TypeMismatch(msg) => write!(f, "{}", msg),
             RustqlError::TransactionError(msg) => write!(f, "{}", msg),
+            RustqlError::SerializationFailure(msg) => write!(f, "{}", msg),
+            RustqlError::DatabaseLocked(path) => {
+                write!(f, "Database '{}' is locked by another process", path)
+            }
             RustqlError::AggregateError(msg) => write!(f, "{}", msg),
             RustqlError::IndexError(msg) => write!(f, "{}", msg),
             RustqlError::IndexNotFound { name } => {
                 write!(f, "Index '{}' does not exist", name)
             }
             RustqlError::DivisionByZero => write!(f, "Division by zero"),
+            RustqlError::Cancelled => write!(f, "Statement was cancelled"),
+            RustqlError::Timeout => write!(f, "Statement timed out"),
             RustqlError::Internal(msg) => write!(f, "{}", msg),
         }
     }
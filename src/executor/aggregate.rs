@@ -1,10 +1,11 @@
 use crate::ast::*;
+use crate::decimal::Decimal;
 use crate::error::RustqlError;
 use std::cmp::Ordering;
 use std::collections::BTreeMap;
 
 use super::expr::{
-    compare_order_values, compare_values_for_sort, evaluate_value_expression,
+    compare_order_values, compare_values_for_sort, decimal_out_of_range, evaluate_value_expression,
     row_has_finite_numeric_value, rows_equal_for_sql_identity,
 };
 
@@ -30,15 +31,27 @@ pub(crate) fn format_aggregate_header(agg: &AggregateFunction) -> String {
         AggregateFunctionType::Mode => "Mode",
         AggregateFunctionType::PercentileCont => "PercentileCont",
         AggregateFunctionType::PercentileDisc => "PercentileDisc",
+        AggregateFunctionType::JsonAgg => "JsonAgg",
+        AggregateFunctionType::JsonObjectAgg => "JsonObjectAgg",
     };
 
     let distinct_str = if agg.distinct { "DISTINCT " } else { "" };
 
-    let expr_str = match &*agg.expr {
-        Expression::Column(name) if name == "*" => "*".to_string(),
+    let argument = |expr: &Expression| match expr {
         Expression::Column(name) => name.clone(),
         _ => "*".to_string(),
     };
+    // JSON_OBJECT_AGG(key, value) aggregates JSON_BUILD_OBJECT(key, value).
+    let expr_str = match (&agg.function, &*agg.expr) {
+        (
+            AggregateFunctionType::JsonObjectAgg,
+            Expression::ScalarFunction {
+                name: ScalarFunctionType::JsonBuildObject,
+                args,
+            },
+        ) => args.iter().map(argument).collect::<Vec<_>>().join(", "),
+        (_, expr) => argument(expr),
+    };
 
     format!("{}({}{})", func_name, distinct_str, expr_str)
 }
@@ -150,6 +163,11 @@ fn compute_windowed_aggregate(
             }
         }
         AggregateFunctionType::Sum => {
+            match decimal_sum(&values) {
+                Ok(Some(sum)) => return Value::Decimal(sum),
+                Ok(None) => {}
+                Err(_) => return Value::Null,
+            }
             let nums = numeric_values(&values);
             if nums.is_empty() {
                 Value::Null
@@ -158,6 +176,16 @@ fn compute_windowed_aggregate(
             }
         }
         AggregateFunctionType::Avg => {
+            match decimal_sum(&values) {
+                Ok(Some(sum)) => {
+                    let count = values.iter().filter(|v| !matches!(v, Value::Null)).count();
+                    return sum
+                        .checked_div(&Decimal::from_i64(count as i64))
+                        .map_or(Value::Null, Value::Decimal);
+                }
+                Ok(None) => {}
+                Err(_) => return Value::Null,
+            }
             let nums = numeric_values(&values);
             if nums.is_empty() {
                 Value::Null
@@ -360,6 +388,8 @@ fn compute_windowed_aggregate(
             let idx = idx.min(sorted_vals.len() - 1);
             sorted_vals[idx].clone()
         }
+        AggregateFunctionType::JsonAgg => crate::json::aggregate_array(&values),
+        AggregateFunctionType::JsonObjectAgg => crate::json::aggregate_object(&values),
     }
 }
 
@@ -369,11 +399,34 @@ fn numeric_values(values: &[Value]) -> Vec<f64> {
         .filter_map(|value| match value {
             Value::Integer(n) => Some(*n as f64),
             Value::Float(f) => Some(*f),
+            Value::Decimal(d) => Some(d.to_f64()),
             _ => None,
         })
         .collect()
 }
 
+/// The exact sum of `values` when they hold a decimal and otherwise only
+/// integers, skipping NULLs; `None` leaves the sum to floating point.
+pub(crate) fn decimal_sum(values: &[Value]) -> Result<Option<Decimal>, RustqlError> {
+    if !values
+        .iter()
+        .any(|value| matches!(value, Value::Decimal(_)))
+    {
+        return Ok(None);
+    }
+    let mut sum = Decimal::from_i64(0);
+    for value in values {
+        let addend = match value {
+            Value::Decimal(d) => *d,
+            Value::Integer(i) => Decimal::from_i64(*i),
+            Value::Null => continue,
+            _ => return Ok(None),
+        };
+        sum = sum.checked_add(&addend).ok_or_else(decimal_out_of_range)?;
+    }
+    Ok(Some(sum))
+}
+
 fn variance(nums: &[f64]) -> f64 {
     let mean = nums.iter().sum::<f64>() / nums.len() as f64;
     nums.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / nums.len() as f64
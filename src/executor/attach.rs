@@ -0,0 +1,632 @@
+use super::{ExecutionContext, command_result};
+use crate::database::{CompositeIndex, Database, Index, Table, View};
+use crate::engine::{CommandTag, EngineOptions, QueryResult};
+use crate::error::RustqlError;
+use crate::storage::{
+    BTreeStorageEngine, ChangeSet, EncryptionKey, JsonStorageEngine, StorageEngine, commit_linked,
+};
+use crate::wal::WriteSet;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// A database file opened by `ATTACH 'path' AS name`.
+///
+/// Its tables, indexes, and views are part of the engine's database under
+/// names qualified by the attachment name, such as `archive.orders`, so
+/// statements read and write them like any other. Each commit saves them to
+/// this storage instead of the engine's own.
+pub(super) struct Attachment {
+    path: PathBuf,
+    storage: Arc<dyn StorageEngine>,
+    /// The storage again when it is a B-tree file, which saved tables are
+    /// paged out to.
+    pager: Option<Arc<BTreeStorageEngine>>,
+}
+
+/// The files attached to an engine, by attachment name.
+#[derive(Default)]
+pub(super) struct Attachments {
+    files: BTreeMap<String, Attachment>,
+    /// The committed database as each file stores it, by attachment name and
+    /// `None` for the engine's own storage, so that a commit only copies the
+    /// objects it changes. Built when a commit first needs it.
+    stored: HashMap<Option<String>, Database>,
+}
+
+impl Attachments {
+    pub(super) fn is_empty(&self) -> bool {
+        self.files.is_empty()
+    }
+}
+
+/// The settings of the engine's own storage that attached files are opened
+/// with, so that an encrypted engine can attach files encrypted with its key.
+#[derive(Clone)]
+pub(crate) struct AttachOptions {
+    page_cache_size: usize,
+    checkpoint_pages: usize,
+    encryption_key: Option<EncryptionKey>,
+    busy_timeout: Duration,
+}
+
+impl From<&EngineOptions> for AttachOptions {
+    fn from(options: &EngineOptions) -> Self {
+        AttachOptions {
+            page_cache_size: options.page_cache_size,
+            checkpoint_pages: options.checkpoint_pages,
+            encryption_key: options.encryption_key.clone(),
+            busy_timeout: options.busy_timeout,
+        }
+    }
+}
+
+/// Opens the file at `path` and adds its objects to the committed database
+/// under names qualified by `name`.
+pub(super) fn execute_attach(
+    context: &ExecutionContext,
+    path: String,
+    name: String,
+) -> Result<QueryResult, RustqlError> {
+    if context.with_wal_state(|state| state.is_in_transaction()) {
+        return Err(RustqlError::TransactionError(
+            "ATTACH cannot run inside a transaction".to_string(),
+        ));
+    }
+    let mut attached = context.attachments();
+    if attached.files.contains_key(&name) {
+        return Err(RustqlError::StorageError(format!(
+            "Database '{}' is already attached",
+            name
+        )));
+    }
+    let mut database = context.committed_snapshot();
+    if let Some(object) = owned_names(&database, &name).into_iter().next() {
+        return Err(RustqlError::StorageError(format!(
+            "Cannot attach a database as '{}' while '{}' exists",
+            name, object
+        )));
+    }
+
+    let path = PathBuf::from(path);
+    let (storage, pager) = open_storage(&path, context.attach_options());
+    let file = storage.load()?;
+    if !file.prepared_transactions.is_empty() {
+        return Err(RustqlError::StorageError(format!(
+            "Cannot attach '{}' while it holds prepared transactions",
+            path.display()
+        )));
+    }
+    mount(&mut database, &name, file);
+    let writes = written_objects(&database, &name);
+    attached.stored.remove(&Some(name.clone()));
+    attached.files.insert(
+        name,
+        Attachment {
+            path,
+            storage,
+            pager,
+        },
+    );
+    context.publish(database, writes, Vec::new());
+    Ok(command_result(CommandTag::Attach, 0))
+}
+
+/// Removes the objects of the database attached as `name` and closes its
+/// file. Open transactions that wrote to them fail to commit.
+pub(super) fn execute_detach(
+    context: &ExecutionContext,
+    name: String,
+) -> Result<QueryResult, RustqlError> {
+    if context.with_wal_state(|state| state.is_in_transaction()) {
+        return Err(RustqlError::TransactionError(
+            "DETACH cannot run inside a transaction".to_string(),
+        ));
+    }
+    let mut attached = context.attachments();
+    if !attached.files.contains_key(&name) {
+        return Err(RustqlError::StorageError(format!(
+            "Database '{}' is not attached",
+            name
+        )));
+    }
+    let mut database = context.committed_snapshot();
+    let writes = written_objects(&database, &name);
+    database
+        .tables
+        .retain(|table, _| owner(table) != Some(name.as_str()));
+    database
+        .views
+        .retain(|view, _| owner(view) != Some(name.as_str()));
+    database
+        .indexes
+        .retain(|_, index| owner(&index.table) != Some(name.as_str()));
+    database
+        .composite_indexes
+        .retain(|_, index| owner(&index.table) != Some(name.as_str()));
+    attached.stored.remove(&Some(name.clone()));
+    attached.files.remove(&name);
+    context.publish(database, writes, Vec::new());
+    Ok(command_result(CommandTag::Detach, 0))
+}
+
+/// Saves `db` to the engine's storage and to the attached files, with the
+/// changes in `changes` or as a whole when it is `None`. `previous` is the
+/// committed database `db` replaces.
+///
+/// Only the files whose objects changed are written. When that is more than
+/// one file they are written by [`commit_linked`], so that a crash cannot
+/// leave some of them committed and others not.
+pub(super) fn persist(
+    main: Option<&Arc<dyn StorageEngine>>,
+    attached: &mut Attachments,
+    db: &Database,
+    previous: &Database,
+    changes: Option<&ChangeSet>,
+) -> Result<(), RustqlError> {
+    let mut remaining = changes.cloned();
+    let mut parts = Vec::new();
+    for name in attached.files.keys() {
+        let part_changes = remaining.as_mut().map(|changes| {
+            changes.split_off(
+                |object| local_name(name, object),
+                |index| {
+                    let table = index_table(db, index).or_else(|| index_table(previous, index));
+                    (owner(table.unwrap_or(index)) == Some(name))
+                        .then(|| local_name(name, index).unwrap_or_else(|| index.to_string()))
+                },
+            )
+        });
+        if part_changes
+            .as_ref()
+            .is_none_or(|changes| !changes.is_empty())
+        {
+            parts.push((Some(name.clone()), part_changes));
+        }
+    }
+    if main.is_some()
+        && (parts.is_empty() || remaining.as_ref().is_none_or(|changes| !changes.is_empty()))
+    {
+        parts.push((None, remaining));
+    }
+
+    for (name, part_changes) in &parts {
+        let name = name.as_deref();
+        let stored = match part_changes {
+            Some(part_changes) => {
+                let mut stored = attached
+                    .stored
+                    .remove(&name.map(str::to_string))
+                    .unwrap_or_else(|| file_database(previous, attached, name));
+                refresh_file_database(&mut stored, db, name, part_changes);
+                stored
+            }
+            None => file_database(db, attached, name),
+        };
+        attached.stored.insert(name.map(str::to_string), stored);
+    }
+
+    let result = save_parts(main, attached, &parts);
+    if result.is_err() {
+        // The stored copies are ahead of the files now, so they are rebuilt
+        // from the committed database by the next commit.
+        for (name, _) in &parts {
+            attached.stored.remove(name);
+        }
+    }
+    result
+}
+
+/// Pages out the tables of `db` that the engine's storage, through `main`,
+/// and the attached B-tree files store, along with the stored copies of
+/// them, once `db` has been saved. A file that cannot serve its rows keeps
+/// its tables in memory.
+pub(super) fn page_out(
+    main: Option<&Arc<BTreeStorageEngine>>,
+    attached: &mut Attachments,
+    db: &mut Database,
+) {
+    let Attachments { files, stored } = attached;
+    let row_source = |pager: Option<&Arc<BTreeStorageEngine>>| {
+        pager.and_then(|pager| pager.row_source().ok().flatten())
+    };
+    if let Some(source) = row_source(main) {
+        db.page_out(&source, |object| {
+            owner(object)
+                .is_none_or(|owner| !files.contains_key(owner))
+                .then(|| object.to_string())
+        });
+        if let Some(file) = stored.get_mut(&None) {
+            file.page_out(&source, |object| Some(object.to_string()));
+        }
+    }
+    for (name, attachment) in files.iter() {
+        if let Some(source) = row_source(attachment.pager.as_ref()) {
+            db.page_out(&source, |object| local_name(name, object));
+            if let Some(file) = stored.get_mut(&Some(name.clone())) {
+                file.page_out(&source, |object| Some(object.to_string()));
+            }
+        }
+    }
+}
+
+fn save_parts(
+    main: Option<&Arc<dyn StorageEngine>>,
+    attached: &Attachments,
+    parts: &[(Option<String>, Option<ChangeSet>)],
+) -> Result<(), RustqlError> {
+    let parts: Vec<_> = parts
+        .iter()
+        .filter_map(|(name, changes)| {
+            let (storage, path) = match name {
+                Some(name) => {
+                    let attachment = &attached.files[name];
+                    (attachment.storage.as_ref(), Some(attachment.path.as_path()))
+                }
+                None => (main?.as_ref(), None),
+            };
+            Some((storage, path, &attached.stored[name], changes.as_ref()))
+        })
+        .collect();
+
+    match &parts[..] {
+        [] => Ok(()),
+        [(storage, _, part, Some(changes))] => storage.save_changes(part, changes),
+        [(storage, _, part, None)] => storage.save(part),
+        _ => {
+            let anchor = parts
+                .iter()
+                .find_map(|(_, path, _, _)| *path)
+                .expect("a commit across files writes an attached file");
+            let parts: Vec<(&dyn StorageEngine, &Database, Option<&ChangeSet>)> = parts
+                .iter()
+                .map(|(storage, _, part, changes)| (*storage, *part, *changes))
+                .collect();
+            commit_linked(&parts, anchor)
+        }
+    }
+}
+
+/// The part of `db` the engine's own storage holds: everything not stored in
+/// an attached file.
+pub(super) fn main_database(db: &Database, attached: &Attachments) -> Database {
+    if attached.is_empty() {
+        return db.clone();
+    }
+    file_database(db, attached, None)
+}
+
+/// Fails when `writes` change objects of an attached database, which a
+/// prepared transaction, saved only with the engine's own storage, cannot
+/// hold.
+pub(super) fn check_prepared_writes(
+    attached: &Attachments,
+    writes: &WriteSet,
+) -> Result<(), RustqlError> {
+    let tables = writes
+        .rows
+        .keys()
+        .chain(writes.inserted.keys())
+        .chain(&writes.tables)
+        .chain(&writes.views);
+    for object in tables.chain(&writes.indexes) {
+        if let Some(name) = owner(object).filter(|name| attached.files.contains_key(*name)) {
+            return Err(RustqlError::TransactionError(format!(
+                "PREPARE TRANSACTION cannot include changes to attached database '{}'",
+                name
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// JSON storage for `.json` files, B-tree storage for anything else, with
+/// the settings of the engine's own storage, along with the B-tree storage
+/// as a pager. JSON files are never encrypted.
+fn open_storage(
+    path: &Path,
+    options: &AttachOptions,
+) -> (Arc<dyn StorageEngine>, Option<Arc<BTreeStorageEngine>>) {
+    if path
+        .extension()
+        .is_some_and(|extension| extension.eq_ignore_ascii_case("json"))
+    {
+        let storage = JsonStorageEngine::new(path).with_busy_timeout(options.busy_timeout);
+        (Arc::new(storage), None)
+    } else {
+        let mut storage = BTreeStorageEngine::with_page_cache_size(path, options.page_cache_size)
+            .with_checkpoint_pages(options.checkpoint_pages)
+            .with_busy_timeout(options.busy_timeout);
+        if let Some(key) = options.encryption_key.clone() {
+            storage = storage.with_encryption_key(key);
+        }
+        let storage = Arc::new(storage);
+        (
+            Arc::clone(&storage) as Arc<dyn StorageEngine>,
+            Some(storage),
+        )
+    }
+}
+
+/// The attachment name an object name is qualified by, if any.
+fn owner(object: &str) -> Option<&str> {
+    object.split_once('.').map(|(name, _)| name)
+}
+
+/// The name `object` has in the file attached as `name`, if it belongs to
+/// it.
+fn local_name(name: &str, object: &str) -> Option<String> {
+    object
+        .strip_prefix(name)
+        .and_then(|rest| rest.strip_prefix('.'))
+        .map(str::to_string)
+}
+
+fn qualified_name(name: &str, object: &str) -> String {
+    format!("{}.{}", name, object)
+}
+
+fn index_table<'a>(db: &'a Database, index: &str) -> Option<&'a str> {
+    db.indexes
+        .get(index)
+        .map(|index| index.table.as_str())
+        .or_else(|| {
+            db.composite_indexes
+                .get(index)
+                .map(|index| index.table.as_str())
+        })
+}
+
+/// The tables and views of `db` qualified by `name`.
+fn owned_names(db: &Database, name: &str) -> Vec<String> {
+    db.tables
+        .keys()
+        .chain(db.views.keys())
+        .filter(|object| owner(object) == Some(name))
+        .cloned()
+        .collect()
+}
+
+/// Every object of the database attached as `name`, as changed by
+/// attaching or detaching it.
+fn written_objects(db: &Database, name: &str) -> WriteSet {
+    WriteSet {
+        tables: db
+            .tables
+            .keys()
+            .filter(|table| owner(table) == Some(name))
+            .cloned()
+            .collect(),
+        indexes: db
+            .indexes
+            .iter()
+            .map(|(index, definition)| (index, &definition.table))
+            .chain(
+                db.composite_indexes
+                    .iter()
+                    .map(|(index, definition)| (index, &definition.table)),
+            )
+            .filter(|(_, table)| owner(table) == Some(name))
+            .map(|(index, _)| index.clone())
+            .collect(),
+        views: db
+            .views
+            .keys()
+            .filter(|view| owner(view) == Some(name))
+            .cloned()
+            .collect(),
+        ..WriteSet::default()
+    }
+}
+
+/// Adds the objects of `file` to `db` under names qualified by `name`.
+fn mount(db: &mut Database, name: &str, file: Database) {
+    for (table_name, mut table) in file.tables {
+        rename_references(&mut table, |referenced| {
+            Some(qualified_name(name, referenced))
+        });
+        db.tables.insert(qualified_name(name, &table_name), table);
+    }
+    for (index_name, mut index) in file.indexes {
+        index.name = qualified_name(name, &index.name);
+        index.table = qualified_name(name, &index.table);
+        db.indexes.insert(qualified_name(name, &index_name), index);
+    }
+    for (index_name, mut index) in file.composite_indexes {
+        index.name = qualified_name(name, &index.name);
+        index.table = qualified_name(name, &index.table);
+        db.composite_indexes
+            .insert(qualified_name(name, &index_name), index);
+    }
+    for (view_name, mut view) in file.views {
+        view.name = qualified_name(name, &view.name);
+        db.views.insert(qualified_name(name, &view_name), view);
+    }
+}
+
+/// The database stored in the file attached as `name`, under the names it
+/// has there, or for `None` the one the engine's own storage holds.
+fn file_database(db: &Database, attached: &Attachments, name: Option<&str>) -> Database {
+    let belongs = |object: &str| match name {
+        Some(name) => owner(object) == Some(name),
+        None => owner(object).is_none_or(|owner| !attached.files.contains_key(owner)),
+    };
+
+    let mut file = Database::new();
+    if name.is_none() {
+        file.prepared_transactions = db.prepared_transactions.clone();
+    }
+    for (table_name, table) in &db.tables {
+        if belongs(table_name) {
+            file.tables
+                .insert(stored_name(name, table_name), stored_table(name, table));
+        }
+    }
+    for (index_name, index) in &db.indexes {
+        if belongs(&index.table) {
+            file.indexes
+                .insert(stored_name(name, index_name), stored_index(name, index));
+        }
+    }
+    for (index_name, index) in &db.composite_indexes {
+        if belongs(&index.table) {
+            file.composite_indexes.insert(
+                stored_name(name, index_name),
+                stored_composite_index(name, index),
+            );
+        }
+    }
+    for (view_name, view) in &db.views {
+        if belongs(view_name) {
+            file.views
+                .insert(stored_name(name, view_name), stored_view(name, view));
+        }
+    }
+    file
+}
+
+/// Brings `file`, the database stored in the file attached as `name` or for
+/// `None` the engine's own, up to date with the objects of `db` that
+/// `changes`, in the names the file has for them, changed.
+fn refresh_file_database(
+    file: &mut Database,
+    db: &Database,
+    name: Option<&str>,
+    changes: &ChangeSet,
+) {
+    let tables: BTreeSet<&String> = changes
+        .tables()
+        .iter()
+        .chain(changes.schemas())
+        .chain(changes.rows().keys())
+        .collect();
+    for &table_name in &tables {
+        match db.tables.get(&engine_name(name, table_name)) {
+            Some(table) => {
+                file.tables
+                    .insert(table_name.clone(), stored_table(name, table));
+            }
+            None => {
+                file.tables.remove(table_name);
+            }
+        }
+    }
+
+    // The entries of an index change with the rows of its table.
+    let on_changed_table = |table: &str| tables.contains(&stored_name(name, table));
+    let mut indexes = changes.indexes().clone();
+    indexes.extend(
+        file.indexes
+            .iter()
+            .filter(|(_, index)| tables.contains(&index.table))
+            .map(|(index_name, _)| index_name.clone()),
+    );
+    indexes.extend(
+        file.composite_indexes
+            .iter()
+            .filter(|(_, index)| tables.contains(&index.table))
+            .map(|(index_name, _)| index_name.clone()),
+    );
+    indexes.extend(
+        db.indexes
+            .iter()
+            .filter(|(_, index)| on_changed_table(&index.table))
+            .map(|(index_name, _)| stored_name(name, index_name)),
+    );
+    indexes.extend(
+        db.composite_indexes
+            .iter()
+            .filter(|(_, index)| on_changed_table(&index.table))
+            .map(|(index_name, _)| stored_name(name, index_name)),
+    );
+    for index_name in indexes {
+        let engine_index = engine_name(name, &index_name);
+        file.indexes.remove(&index_name);
+        file.composite_indexes.remove(&index_name);
+        if let Some(index) = db.indexes.get(&engine_index) {
+            file.indexes.insert(index_name, stored_index(name, index));
+        } else if let Some(index) = db.composite_indexes.get(&engine_index) {
+            file.composite_indexes
+                .insert(index_name, stored_composite_index(name, index));
+        }
+    }
+
+    for view_name in changes.views() {
+        match db.views.get(&engine_name(name, view_name)) {
+            Some(view) => {
+                file.views
+                    .insert(view_name.clone(), stored_view(name, view));
+            }
+            None => {
+                file.views.remove(view_name);
+            }
+        }
+    }
+
+    for gid in changes.prepared() {
+        match db.prepared_transactions.get(gid) {
+            Some(prepared) => {
+                file.prepared_transactions
+                    .insert(gid.clone(), prepared.clone());
+            }
+            None => {
+                file.prepared_transactions.remove(gid);
+            }
+        }
+    }
+}
+
+/// The name `object` of the engine's database has in the file attached as
+/// `name`, or for `None` in the engine's own storage.
+fn stored_name(name: Option<&str>, object: &str) -> String {
+    name.and_then(|name| local_name(name, object))
+        .unwrap_or_else(|| object.to_string())
+}
+
+/// The name `object` of the file attached as `name` has in the engine's
+/// database.
+fn engine_name(name: Option<&str>, object: &str) -> String {
+    match name {
+        Some(name) => qualified_name(name, object),
+        None => object.to_string(),
+    }
+}
+
+fn stored_table(name: Option<&str>, table: &Table) -> Table {
+    let mut table = table.clone();
+    if let Some(name) = name {
+        rename_references(&mut table, |referenced| local_name(name, referenced));
+    }
+    table
+}
+
+fn stored_index(name: Option<&str>, index: &Index) -> Index {
+    let mut index = index.clone();
+    index.name = stored_name(name, &index.name);
+    index.table = stored_name(name, &index.table);
+    index
+}
+
+fn stored_composite_index(name: Option<&str>, index: &CompositeIndex) -> CompositeIndex {
+    let mut index = index.clone();
+    index.name = stored_name(name, &index.name);
+    index.table = stored_name(name, &index.table);
+    index
+}
+
+fn stored_view(name: Option<&str>, view: &View) -> View {
+    let mut view = view.clone();
+    view.name = stored_name(name, &view.name);
+    view
+}
+
+fn rename_references(table: &mut Table, rename: impl Fn(&str) -> Option<String>) {
+    for column in &mut table.columns {
+        if let Some(foreign_key) = &mut column.foreign_key
+            && let Some(renamed) = rename(&foreign_key.referenced_table)
+        {
+            foreign_key.referenced_table = renamed;
+        }
+    }
+}
@@ -0,0 +1,58 @@
+use crate::engine::{ChangeEvent, ChangeKind};
+use crate::wal::RowChange;
+use std::sync::Mutex;
+use std::sync::mpsc::{self, Receiver, Sender};
+
+/// The subscribers to an engine's committed row changes.
+///
+/// Commits hand their changes over while they hold the commit log, so every
+/// subscriber receives them in commit order. The channels are unbounded: a
+/// subscriber that stops reading only costs memory, never blocks a commit.
+#[derive(Default)]
+pub(crate) struct ChangeFeed {
+    subscribers: Mutex<Vec<Sender<ChangeEvent>>>,
+}
+
+impl ChangeFeed {
+    pub(crate) fn subscribe(&self) -> Receiver<ChangeEvent> {
+        let (sender, receiver) = mpsc::channel();
+        self.subscribers().push(sender);
+        receiver
+    }
+
+    /// Whether a commit has to work out its row changes at all.
+    pub(crate) fn has_subscribers(&self) -> bool {
+        !self.subscribers().is_empty()
+    }
+
+    /// Sends the changes of the commit numbered `transaction_id` to every
+    /// subscriber, forgetting those whose stream was dropped.
+    pub(crate) fn publish(&self, transaction_id: u64, changes: Vec<RowChange>) {
+        let events: Vec<ChangeEvent> = changes
+            .into_iter()
+            .map(|change| ChangeEvent {
+                transaction_id,
+                kind: match (&change.old_row, &change.new_row) {
+                    (None, _) => ChangeKind::Insert,
+                    (Some(_), Some(_)) => ChangeKind::Update,
+                    (Some(_), None) => ChangeKind::Delete,
+                },
+                table: change.table,
+                row_id: change.row_id.0,
+                old: change.old_row,
+                new: change.new_row,
+            })
+            .collect();
+        self.subscribers().retain(|subscriber| {
+            events
+                .iter()
+                .all(|event| subscriber.send(event.clone()).is_ok())
+        });
+    }
+
+    fn subscribers(&self) -> std::sync::MutexGuard<'_, Vec<Sender<ChangeEvent>>> {
+        self.subscribers
+            .lock()
+            .unwrap_or_else(|err| err.into_inner())
+    }
+}
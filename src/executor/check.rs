@@ -0,0 +1,701 @@
+use crate::ast::*;
+use crate::database::{Database, RowId, Table};
+use crate::engine::QueryResult;
+use crate::error::{ConstraintKind, IntegrityProblem, RustqlError};
+use crate::wal::RowChange;
+use std::borrow::Cow;
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+
+use super::ddl::{composite_index_entries, index_entries, row_matches_index_filter};
+use super::dml::{find_row_with_key, find_row_with_value};
+use super::expr::{
+    compare_values_for_sort, rows_equal_for_sql_identity, values_equal_for_sql_identity,
+};
+use super::{ExecutionContext, SelectResult, rows_result};
+
+pub fn execute_check_database(context: &ExecutionContext) -> Result<QueryResult, RustqlError> {
+    let rows = context
+        .check_integrity()?
+        .into_iter()
+        .map(|problem| {
+            vec![
+                Value::Text(problem.kind_name().to_string()),
+                problem.object().map_or(Value::Null, Value::Text),
+                Value::Text(problem.detail()),
+            ]
+        })
+        .collect();
+    Ok(rows_result(SelectResult {
+        headers: vec![
+            "problem".to_string(),
+            "object".to_string(),
+            "detail".to_string(),
+        ],
+        rows,
+    }))
+}
+
+/// Checks the tables and indexes of `db` against each other: row ids are
+/// unique, every index entry names an existing row holding its key, every
+/// indexed row has an entry, and the stored rows satisfy their NOT NULL,
+/// PRIMARY KEY, UNIQUE and FOREIGN KEY constraints.
+pub(crate) fn check_database(db: &Database) -> Result<Vec<IntegrityProblem>, RustqlError> {
+    let mut rows = HashMap::new();
+    for (name, table) in &db.tables {
+        rows.insert(name.as_str(), table_rows(table)?);
+    }
+
+    let mut problems = Vec::new();
+    let table_names: BTreeMap<&str, &Table> = db
+        .tables
+        .iter()
+        .map(|(name, table)| (name.as_str(), table))
+        .collect();
+    for (name, table) in &table_names {
+        let table_rows = &rows[name];
+        check_row_ids(name, table_rows, &mut problems);
+        check_not_null(name, table, table_rows, &mut problems);
+        check_unique_keys(name, table, table_rows, &mut problems);
+        check_foreign_keys(name, table, table_rows, &rows, db, &mut problems);
+    }
+
+    let indexes: BTreeMap<&str, _> = db
+        .indexes
+        .values()
+        .map(|index| (index.name.as_str(), index))
+        .collect();
+    for (name, index) in indexes {
+        let columns = std::slice::from_ref(&index.column);
+        let entries = match db.tables.get(&index.table) {
+            Some(table) => index_entries(db, table, index)?,
+            None => Cow::Borrowed(&index.entries),
+        };
+        let entries = entries
+            .iter()
+            .map(|(key, row_ids)| (std::slice::from_ref(key), row_ids.as_slice()));
+        let indexed = IndexedRows {
+            name,
+            table: &index.table,
+            columns,
+            filter: index.filter_expr.as_ref(),
+        };
+        indexed.check(db, &rows, entries, &mut problems)?;
+    }
+    let composite_indexes: BTreeMap<&str, _> = db
+        .composite_indexes
+        .values()
+        .map(|index| (index.name.as_str(), index))
+        .collect();
+    for (name, index) in composite_indexes {
+        let entries = match db.tables.get(&index.table) {
+            Some(table) => composite_index_entries(db, table, index)?,
+            None => Cow::Borrowed(&index.entries),
+        };
+        let entries = entries
+            .iter()
+            .map(|(key, row_ids)| (key.as_slice(), row_ids.as_slice()));
+        let indexed = IndexedRows {
+            name,
+            table: &index.table,
+            columns: &index.columns,
+            filter: index.filter_expr.as_ref(),
+        };
+        indexed.check(db, &rows, entries, &mut problems)?;
+    }
+    Ok(problems)
+}
+
+/// Checks the NOT NULL, PRIMARY KEY, UNIQUE and FOREIGN KEY constraints of
+/// the named tables and of every table whose foreign keys reference them.
+pub(crate) fn check_constraints(
+    db: &Database,
+    tables: &BTreeSet<String>,
+) -> Result<Vec<IntegrityProblem>, RustqlError> {
+    let checked: BTreeMap<&str, &Table> = db
+        .tables
+        .iter()
+        .filter(|(name, table)| {
+            tables.contains(*name)
+                || table.columns.iter().any(|column| {
+                    column
+                        .foreign_key
+                        .as_ref()
+                        .is_some_and(|fk| tables.contains(&fk.referenced_table))
+                })
+        })
+        .map(|(name, table)| (name.as_str(), table))
+        .collect();
+
+    let mut rows = HashMap::new();
+    for (name, table) in &checked {
+        if !rows.contains_key(name) {
+            rows.insert(*name, table_rows(table)?);
+        }
+        for column in &table.columns {
+            if let Some(fk) = &column.foreign_key
+                && !rows.contains_key(fk.referenced_table.as_str())
+                && let Some((referenced_name, referenced)) =
+                    db.tables.get_key_value(&fk.referenced_table)
+            {
+                rows.insert(referenced_name.as_str(), table_rows(referenced)?);
+            }
+        }
+    }
+
+    let mut problems = Vec::new();
+    for (name, table) in &checked {
+        let table_rows = &rows[name];
+        check_not_null(name, table, table_rows, &mut problems);
+        check_unique_keys(name, table, table_rows, &mut problems);
+        check_foreign_keys(name, table, table_rows, &rows, db, &mut problems);
+    }
+    Ok(problems)
+}
+
+/// Checks the NOT NULL, PRIMARY KEY, UNIQUE and FOREIGN KEY constraints
+/// that `changes` could have broken in `db`, the state they led to: the
+/// columns, keys and references of each changed row as it now stands, and
+/// the rows of other tables still referring to a value a change took away.
+/// Keys are looked up through an index where one covers them, so only the
+/// changed rows are read in full.
+pub(crate) fn check_changed_rows(
+    db: &Database,
+    changes: &[RowChange],
+) -> Result<Vec<IntegrityProblem>, RustqlError> {
+    let mut problems = Vec::new();
+    let mut checked = HashSet::new();
+    for change in changes {
+        let Some(table) = db.tables.get(&change.table) else {
+            continue;
+        };
+        if checked.insert((change.table.as_str(), change.row_id))
+            && let Some(row) = table.fetch_row(change.row_id)?
+        {
+            check_row(db, &change.table, table, change.row_id, &row, &mut problems)?;
+        }
+        if let Some(old_row) = &change.old_row {
+            check_references_to(db, &change.table, table, old_row, &mut problems)?;
+        }
+    }
+    Ok(problems)
+}
+
+/// Fails with the first problem [`check_changed_rows`] finds in the named
+/// tables, as the constraint violation a statement would have raised.
+pub(crate) fn validate_constraints(
+    db: &Database,
+    changes: &[RowChange],
+    tables: &BTreeSet<String>,
+) -> Result<(), RustqlError> {
+    let problem = check_changed_rows(db, changes)?
+        .into_iter()
+        .find(|problem| matches!(problem, IntegrityProblem::Constraint { table, .. } if tables.contains(table)));
+    match problem {
+        None => Ok(()),
+        Some(problem) => {
+            let kind = match &problem {
+                IntegrityProblem::Constraint { kind, .. } => kind.clone(),
+                _ => return Err(RustqlError::Internal(problem.to_string())),
+            };
+            Err(RustqlError::ConstraintViolation {
+                kind,
+                message: format!("Deferred constraint violation: {}", problem),
+            })
+        }
+    }
+}
+
+fn table_rows(table: &Table) -> Result<Vec<(RowId, Vec<Value>)>, RustqlError> {
+    let mut rows = Vec::with_capacity(table.row_count());
+    table.for_each_row(|row_id, row| {
+        rows.push((row_id, row.to_vec()));
+        Ok(true)
+    })?;
+    Ok(rows)
+}
+
+fn check_row_ids(table: &str, rows: &[(RowId, Vec<Value>)], problems: &mut Vec<IntegrityProblem>) {
+    let mut seen = HashSet::new();
+    let mut reported = HashSet::new();
+    for (row_id, _) in rows {
+        if !seen.insert(*row_id) && reported.insert(*row_id) {
+            problems.push(IntegrityProblem::DuplicateRowId {
+                table: table.to_string(),
+                row_id: row_id.0,
+            });
+        }
+    }
+}
+
+fn check_not_null(
+    table_name: &str,
+    table: &Table,
+    rows: &[(RowId, Vec<Value>)],
+    problems: &mut Vec<IntegrityProblem>,
+) {
+    for (col_idx, column) in table.columns.iter().enumerate() {
+        if column.nullable && !column.primary_key {
+            continue;
+        }
+        let nulls = rows
+            .iter()
+            .filter(|(_, row)| matches!(row.get(col_idx), None | Some(Value::Null)))
+            .count();
+        if nulls > 0 {
+            let kind = if column.primary_key {
+                ConstraintKind::PrimaryKey
+            } else {
+                ConstraintKind::NotNull
+            };
+            problems.push(IntegrityProblem::Constraint {
+                kind,
+                table: table_name.to_string(),
+                message: format!("Column '{}' is NULL in {} rows", column.name, nulls),
+            });
+        }
+    }
+}
+
+fn check_unique_keys(
+    table_name: &str,
+    table: &Table,
+    rows: &[(RowId, Vec<Value>)],
+    problems: &mut Vec<IntegrityProblem>,
+) {
+    for (kind, columns) in unique_keys(table) {
+        let Some(positions) = column_positions(table, &columns) else {
+            problems.push(IntegrityProblem::Constraint {
+                kind,
+                table: table_name.to_string(),
+                message: format!("Constraint names missing columns {:?}", columns),
+            });
+            continue;
+        };
+        if kind == ConstraintKind::PrimaryKey && columns.len() > 1 {
+            let nulls = rows
+                .iter()
+                .filter(|(_, row)| key_for_row(row, &positions).contains(&Value::Null))
+                .count();
+            if nulls > 0 {
+                problems.push(IntegrityProblem::Constraint {
+                    kind: kind.clone(),
+                    table: table_name.to_string(),
+                    message: format!("Key {:?} holds NULL in {} rows", columns, nulls),
+                });
+            }
+        }
+
+        // NULL keys never collide. Sorting brings equal keys together, so
+        // one pass over neighbours finds every duplicate.
+        let mut sorted: Vec<Vec<Value>> = rows
+            .iter()
+            .map(|(_, row)| key_for_row(row, &positions))
+            .filter(|key| !key.contains(&Value::Null))
+            .collect();
+        sorted.sort_by(|left, right| compare_keys(left, right));
+        let mut duplicates = 0;
+        let mut first_duplicate = None;
+        for pair in sorted.windows(2) {
+            if rows_equal_for_sql_identity(&pair[0], &pair[1]) {
+                duplicates += 1;
+                first_duplicate.get_or_insert_with(|| pair[1].clone());
+            }
+        }
+        if let Some(key) = first_duplicate {
+            problems.push(IntegrityProblem::Constraint {
+                kind,
+                table: table_name.to_string(),
+                message: format!(
+                    "Key {:?} holds {} duplicate values, first {}",
+                    columns,
+                    duplicates,
+                    format_key(&key)
+                ),
+            });
+        }
+    }
+}
+
+fn check_foreign_keys(
+    table_name: &str,
+    table: &Table,
+    rows: &[(RowId, Vec<Value>)],
+    all_rows: &HashMap<&str, Vec<(RowId, Vec<Value>)>>,
+    db: &Database,
+    problems: &mut Vec<IntegrityProblem>,
+) {
+    for (col_idx, column) in table.columns.iter().enumerate() {
+        let Some(fk) = &column.foreign_key else {
+            continue;
+        };
+        let mut problem = |message: String| {
+            problems.push(IntegrityProblem::Constraint {
+                kind: ConstraintKind::ForeignKey,
+                table: table_name.to_string(),
+                message,
+            })
+        };
+        let ref_idx = match referenced_column(db, column, fk) {
+            Ok((_, ref_idx)) => ref_idx,
+            Err(message) => {
+                problem(message);
+                continue;
+            }
+        };
+
+        let mut targets: Vec<&Value> = all_rows[fk.referenced_table.as_str()]
+            .iter()
+            .filter_map(|(_, row)| row.get(ref_idx))
+            .collect();
+        targets.sort_by(|left, right| compare_values_for_sort(left, right));
+        let dangling: Vec<&Value> = rows
+            .iter()
+            .filter_map(|(_, row)| row.get(col_idx))
+            .filter(|value| !matches!(value, Value::Null))
+            .filter(|value| {
+                targets
+                    .binary_search_by(|target| compare_values_for_sort(target, value))
+                    .map_or(true, |found| {
+                        !values_equal_for_sql_identity(targets[found], value)
+                    })
+            })
+            .collect();
+        if let Some(first) = dangling.first() {
+            problem(format!(
+                "Column '{}' holds {} values missing from '{}'.'{}', first {}",
+                column.name,
+                dangling.len(),
+                fk.referenced_table,
+                fk.referenced_column,
+                first
+            ));
+        }
+    }
+}
+
+/// The PRIMARY KEY and UNIQUE keys of `table`, each with its columns.
+fn unique_keys(table: &Table) -> Vec<(ConstraintKind, Vec<String>)> {
+    let mut keys: Vec<(ConstraintKind, Vec<String>)> = table
+        .columns
+        .iter()
+        .filter_map(|column| {
+            let kind = if column.primary_key {
+                ConstraintKind::PrimaryKey
+            } else if column.unique {
+                ConstraintKind::Unique
+            } else {
+                return None;
+            };
+            Some((kind, vec![column.name.clone()]))
+        })
+        .collect();
+    for constraint in &table.constraints {
+        keys.push(match constraint {
+            TableConstraint::PrimaryKey { columns, .. } => {
+                (ConstraintKind::PrimaryKey, columns.clone())
+            }
+            TableConstraint::Unique { columns, .. } => (ConstraintKind::Unique, columns.clone()),
+        });
+    }
+    keys
+}
+
+/// The table and column position `column`'s foreign key refers to, or why
+/// it cannot be found.
+fn referenced_column<'a>(
+    db: &'a Database,
+    column: &ColumnDefinition,
+    fk: &ForeignKeyConstraint,
+) -> Result<(&'a Table, usize), String> {
+    let Some(referenced) = db.tables.get(&fk.referenced_table) else {
+        return Err(format!(
+            "Column '{}' references missing table '{}'",
+            column.name, fk.referenced_table
+        ));
+    };
+    let Some(ref_idx) = referenced
+        .columns
+        .iter()
+        .position(|c| c.name == fk.referenced_column)
+    else {
+        return Err(format!(
+            "Column '{}' references missing column '{}'.'{}'",
+            column.name, fk.referenced_table, fk.referenced_column
+        ));
+    };
+    Ok((referenced, ref_idx))
+}
+
+/// Checks one stored row of `table` against the table's constraints.
+fn check_row(
+    db: &Database,
+    table_name: &str,
+    table: &Table,
+    row_id: RowId,
+    row: &[Value],
+    problems: &mut Vec<IntegrityProblem>,
+) -> Result<(), RustqlError> {
+    let mut problem = |kind: ConstraintKind, message: String| {
+        problems.push(IntegrityProblem::Constraint {
+            kind,
+            table: table_name.to_string(),
+            message,
+        })
+    };
+    for (col_idx, column) in table.columns.iter().enumerate() {
+        if (!column.nullable || column.primary_key)
+            && matches!(row.get(col_idx), None | Some(Value::Null))
+        {
+            let kind = if column.primary_key {
+                ConstraintKind::PrimaryKey
+            } else {
+                ConstraintKind::NotNull
+            };
+            problem(
+                kind,
+                format!("Column '{}' is NULL in row {}", column.name, row_id.0),
+            );
+        }
+    }
+
+    for (kind, columns) in unique_keys(table) {
+        let Some(positions) = column_positions(table, &columns) else {
+            problem(
+                kind,
+                format!("Constraint names missing columns {:?}", columns),
+            );
+            continue;
+        };
+        let key = key_for_row(row, &positions);
+        if key.contains(&Value::Null) {
+            if kind == ConstraintKind::PrimaryKey && columns.len() > 1 {
+                problem(
+                    kind,
+                    format!("Key {:?} holds NULL in row {}", columns, row_id.0),
+                );
+            }
+            continue;
+        }
+        if let Some(other) =
+            find_row_with_key(db, table_name, table, &positions, &key, Some(row_id))?
+        {
+            problem(
+                kind,
+                format!(
+                    "Key {:?} holds {} in rows {} and {}",
+                    columns,
+                    format_key(&key),
+                    other.0.min(row_id.0),
+                    other.0.max(row_id.0)
+                ),
+            );
+        }
+    }
+
+    for (col_idx, column) in table.columns.iter().enumerate() {
+        let Some(fk) = &column.foreign_key else {
+            continue;
+        };
+        let Some(value) = row
+            .get(col_idx)
+            .filter(|value| !matches!(value, Value::Null))
+        else {
+            continue;
+        };
+        match referenced_column(db, column, fk) {
+            Ok((referenced, ref_idx)) => {
+                if find_row_with_value(db, &fk.referenced_table, referenced, ref_idx, value, None)?
+                    .is_none()
+                {
+                    problem(
+                        ConstraintKind::ForeignKey,
+                        format!(
+                            "Column '{}' of row {} holds {}, missing from '{}'.'{}'",
+                            column.name, row_id.0, value, fk.referenced_table, fk.referenced_column
+                        ),
+                    );
+                }
+            }
+            Err(message) => problem(ConstraintKind::ForeignKey, message),
+        }
+    }
+    Ok(())
+}
+
+/// Checks that no row still refers to a value `old_row` held in `table`,
+/// unless a row of `table` holds that value now.
+fn check_references_to(
+    db: &Database,
+    table_name: &str,
+    table: &Table,
+    old_row: &[Value],
+    problems: &mut Vec<IntegrityProblem>,
+) -> Result<(), RustqlError> {
+    for (child_name, child) in &db.tables {
+        for (col_idx, column) in child.columns.iter().enumerate() {
+            let Some(fk) = column
+                .foreign_key
+                .as_ref()
+                .filter(|fk| fk.referenced_table == table_name)
+            else {
+                continue;
+            };
+            let Some(ref_idx) = table
+                .columns
+                .iter()
+                .position(|c| c.name == fk.referenced_column)
+            else {
+                continue;
+            };
+            let Some(value) = old_row
+                .get(ref_idx)
+                .filter(|value| !matches!(value, Value::Null))
+            else {
+                continue;
+            };
+            if find_row_with_value(db, table_name, table, ref_idx, value, None)?.is_some() {
+                continue;
+            }
+            if let Some(child_row) =
+                find_row_with_value(db, child_name, child, col_idx, value, None)?
+            {
+                problems.push(IntegrityProblem::Constraint {
+                    kind: ConstraintKind::ForeignKey,
+                    table: child_name.clone(),
+                    message: format!(
+                        "Column '{}' of row {} holds {}, missing from '{}'.'{}'",
+                        column.name, child_row.0, value, fk.referenced_table, fk.referenced_column
+                    ),
+                });
+            }
+        }
+    }
+    Ok(())
+}
+
+/// An index reduced to what the entry checks need, so single-column and
+/// composite indexes share them.
+struct IndexedRows<'a> {
+    name: &'a str,
+    table: &'a str,
+    columns: &'a [String],
+    filter: Option<&'a Expression>,
+}
+
+impl IndexedRows<'_> {
+    fn check<'e>(
+        &self,
+        db: &Database,
+        rows: &HashMap<&str, Vec<(RowId, Vec<Value>)>>,
+        entries: impl Iterator<Item = (&'e [Value], &'e [RowId])>,
+        problems: &mut Vec<IntegrityProblem>,
+    ) -> Result<(), RustqlError> {
+        let mut problem = |message: String| {
+            problems.push(IntegrityProblem::IndexEntry {
+                index: self.name.to_string(),
+                message,
+            })
+        };
+        let (Some(table), Some(table_rows)) = (db.tables.get(self.table), rows.get(self.table))
+        else {
+            problem(format!("Index table '{}' does not exist", self.table));
+            return Ok(());
+        };
+        let Some(positions) = column_positions(table, self.columns) else {
+            problem(format!(
+                "Index columns {:?} do not exist in table '{}'",
+                self.columns, self.table
+            ));
+            return Ok(());
+        };
+
+        let by_id: HashMap<RowId, &Vec<Value>> = table_rows
+            .iter()
+            .map(|(row_id, row)| (*row_id, row))
+            .collect();
+        let mut indexed = HashSet::new();
+        for (key, row_ids) in entries {
+            for row_id in row_ids {
+                indexed.insert(*row_id);
+                match by_id.get(row_id) {
+                    None => problem(format!(
+                        "Entry {} points at missing row {}",
+                        format_key(key),
+                        row_id.0
+                    )),
+                    Some(row)
+                        if !rows_equal_for_sql_identity(&key_for_row(row, &positions), key) =>
+                    {
+                        problem(format!(
+                            "Entry {} points at row {} holding {}",
+                            format_key(key),
+                            row_id.0,
+                            format_key(&key_for_row(row, &positions))
+                        ))
+                    }
+                    Some(_) => {}
+                }
+            }
+        }
+
+        let mut missing = Vec::new();
+        for (row_id, row) in table_rows {
+            if indexed.contains(row_id) {
+                continue;
+            }
+            match row_matches_index_filter(db, table, self.filter, row) {
+                Ok(true) => missing.push(row_id.0),
+                Ok(false) => {}
+                Err(e) => {
+                    problem(format!("Cannot evaluate index filter: {}", e));
+                    return Ok(());
+                }
+            }
+        }
+        if let Some(first) = missing.first() {
+            problem(format!(
+                "{} rows have no entry, first row {}",
+                missing.len(),
+                first
+            ));
+        }
+        Ok(())
+    }
+}
+
+fn column_positions(table: &Table, columns: &[String]) -> Option<Vec<usize>> {
+    columns
+        .iter()
+        .map(|name| table.columns.iter().position(|column| column.name == *name))
+        .collect()
+}
+
+fn key_for_row(row: &[Value], positions: &[usize]) -> Vec<Value> {
+    positions
+        .iter()
+        .map(|&idx| row.get(idx).cloned().unwrap_or(Value::Null))
+        .collect()
+}
+
+fn compare_keys(left: &[Value], right: &[Value]) -> Ordering {
+    left.iter()
+        .zip(right)
+        .map(|(left, right)| compare_values_for_sort(left, right))
+        .find(|ordering| ordering.is_ne())
+        .unwrap_or_else(|| left.len().cmp(&right.len()))
+}
+
+fn format_key(key: &[Value]) -> String {
+    match key {
+        [value] => value.to_string(),
+        values => format!(
+            "({})",
+            values
+                .iter()
+                .map(Value::to_string)
+                .collect::<Vec<_>>()
+                .join(", ")
+        ),
+    }
+}
@@ -1,9 +1,13 @@
 use crate::ast::*;
-use crate::database::{CompositeIndex, Database, DatabaseCatalog, Index, RowId, Table};
+use crate::database::{CompositeIndex, Database, DatabaseCatalog, Index, RowId, SharedMap, Table};
+use crate::decimal::Decimal;
 use crate::engine::{CommandTag, QueryResult};
 use crate::error::RustqlError;
+use crate::interval::Interval;
+use crate::storage::PageUsageKind;
 use crate::wal::WalEntry;
-use std::collections::{BTreeMap, HashMap, HashSet};
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet};
 
 use super::expr::SqlRowSet;
 use super::{
@@ -62,10 +66,18 @@ fn execute_create_table_as_select(
                 .map(|val| match val {
                     crate::ast::Value::Integer(_) => crate::ast::DataType::Integer,
                     crate::ast::Value::Float(_) => crate::ast::DataType::Float,
+                    crate::ast::Value::Decimal(d) => crate::ast::DataType::Decimal {
+                        precision: crate::decimal::MAX_DECIMAL_PRECISION,
+                        scale: d.scale(),
+                    },
                     crate::ast::Value::Boolean(_) => crate::ast::DataType::Boolean,
                     crate::ast::Value::Date(_) => crate::ast::DataType::Date,
                     crate::ast::Value::Time(_) => crate::ast::DataType::Time,
                     crate::ast::Value::DateTime(_) => crate::ast::DataType::DateTime,
+                    crate::ast::Value::TimestampTz(_) => crate::ast::DataType::TimestampTz,
+                    crate::ast::Value::Interval(_) => crate::ast::DataType::Interval,
+                    crate::ast::Value::Blob(_) => crate::ast::DataType::Blob,
+                    crate::ast::Value::Json(_) => crate::ast::DataType::Json,
                     _ => crate::ast::DataType::Text,
                 })
                 .unwrap_or(crate::ast::DataType::Text);
@@ -108,11 +120,7 @@ pub fn execute_drop_table(
             context,
             WalEntry::DropTable {
                 name: stmt.name.clone(),
-                columns: removed.columns,
-                rows: removed.rows,
-                row_ids: removed.row_ids,
-                next_row_id: removed.next_row_id,
-                constraints: removed.constraints,
+                table: removed,
                 indexes: removed_indexes,
                 composite_indexes: removed_composite_indexes,
             },
@@ -132,6 +140,16 @@ pub fn execute_alter_table(
 ) -> Result<QueryResult, RustqlError> {
     let mut db = get_database_write(context);
 
+    // These rewrite every row, or store them under a new name.
+    if matches!(
+        stmt.operation,
+        AlterOperation::RenameTable(_)
+            | AlterOperation::AddColumn(_)
+            | AlterOperation::DropColumn(_)
+    ) {
+        db.load_paged_tables([stmt.table.as_str()])?;
+    }
+
     if let AlterOperation::RenameTable(ref new_name) = stmt.operation {
         let table_data = db
             .tables
@@ -175,11 +193,18 @@ pub fn execute_alter_table(
             let default_value = match col_def.data_type {
                 DataType::Integer => Value::Integer(0),
                 DataType::Float => Value::Float(0.0),
+                DataType::Decimal { scale, .. } => {
+                    Value::Decimal(Decimal::new(0, scale).unwrap_or(Decimal::from_i64(0)))
+                }
                 DataType::Text => Value::Text(String::new()),
                 DataType::Boolean => Value::Boolean(false),
                 DataType::Date => Value::Date("1970-01-01".to_string()),
                 DataType::Time => Value::Time("00:00:00".to_string()),
                 DataType::DateTime => Value::DateTime("1970-01-01 00:00:00".to_string()),
+                DataType::TimestampTz => Value::TimestampTz("1970-01-01 00:00:00".to_string()),
+                DataType::Interval => Value::Interval(Interval::ZERO),
+                DataType::Blob => Value::Blob(Vec::new()),
+                DataType::Json => Value::Json("null".to_string()),
             };
             for row in &mut table.rows {
                 row.push(default_value.clone());
@@ -272,7 +297,7 @@ pub fn execute_alter_table(
             let is_primary_key =
                 matches!(&constraint, crate::ast::TableConstraint::PrimaryKey { .. });
             let mut seen = SqlRowSet::new();
-            for row in &table.rows {
+            table.for_each_row(|_, row| {
                 let key: Vec<Value> = col_indices.iter().map(|&i| row[i].clone()).collect();
                 if key.iter().any(|v| matches!(v, Value::Null)) {
                     if is_primary_key {
@@ -281,14 +306,15 @@ pub fn execute_alter_table(
                                 .to_string(),
                         ));
                     }
-                    continue;
+                    return Ok(true);
                 }
                 if !seen.insert(key) {
                     return Err(RustqlError::Internal(
                         "Cannot add constraint: duplicate values exist".to_string(),
                     ));
                 }
-            }
+                Ok(true)
+            })?;
             table.constraints.push(constraint.clone());
             super::record_wal_entry(
                 context,
@@ -364,24 +390,25 @@ pub fn execute_create_index(
                 name: stmt.name.clone(),
                 table: stmt.table.clone(),
                 column: stmt.columns[0].clone(),
-                entries: BTreeMap::new(),
+                entries: SharedMap::default(),
                 filter_expr: stmt.where_clause.clone(),
+                paged: None,
             };
 
             let first_column_position = column_positions.first().copied().ok_or_else(|| {
                 RustqlError::Internal("CREATE INDEX requires at least one column".to_string())
             })?;
 
-            for (row_id, row) in table.iter_rows_with_ids() {
-                if !row_matches_index_filter(&db, table, index.filter_expr.as_ref(), row)? {
-                    continue;
+            table.for_each_row(|row_id, row| {
+                if row_matches_index_filter(&db, table, index.filter_expr.as_ref(), row)? {
+                    let value = row
+                        .get(first_column_position)
+                        .cloned()
+                        .unwrap_or(Value::Null);
+                    index.entries.entry(value).or_default().push(row_id);
                 }
-                let value = row
-                    .get(first_column_position)
-                    .cloned()
-                    .unwrap_or(Value::Null);
-                index.entries.entry(value).or_default().push(row_id);
-            }
+                Ok(true)
+            })?;
 
             db.indexes.insert(stmt.name.clone(), index);
         } else {
@@ -389,17 +416,18 @@ pub fn execute_create_index(
                 name: stmt.name.clone(),
                 table: stmt.table.clone(),
                 columns: stmt.columns.clone(),
-                entries: BTreeMap::new(),
+                entries: SharedMap::default(),
                 filter_expr: stmt.where_clause.clone(),
+                paged: None,
             };
 
-            for (row_id, row) in table.iter_rows_with_ids() {
-                if !row_matches_index_filter(&db, table, index.filter_expr.as_ref(), row)? {
-                    continue;
+            table.for_each_row(|row_id, row| {
+                if row_matches_index_filter(&db, table, index.filter_expr.as_ref(), row)? {
+                    let key = composite_key_for_row(row, &column_positions);
+                    index.entries.entry(key).or_default().push(row_id);
                 }
-                let key = composite_key_for_row(row, &column_positions);
-                index.entries.entry(key).or_default().push(row_id);
-            }
+                Ok(true)
+            })?;
 
             db.composite_indexes.insert(stmt.name.clone(), index);
         }
@@ -494,15 +522,7 @@ pub fn execute_describe(
     let mut rows = Vec::new();
 
     for col in &table.columns {
-        let type_str = match col.data_type {
-            DataType::Integer => "INTEGER",
-            DataType::Float => "FLOAT",
-            DataType::Text => "TEXT",
-            DataType::Boolean => "BOOLEAN",
-            DataType::Date => "DATE",
-            DataType::Time => "TIME",
-            DataType::DateTime => "DATETIME",
-        };
+        let type_str = col.data_type.to_string();
 
         let nullable_str = if col.nullable { "YES" } else { "NO" };
         let pk_str = if col.primary_key { "YES" } else { "NO" };
@@ -515,7 +535,7 @@ pub fn execute_describe(
 
         rows.push(vec![
             Value::Text(col.name.clone()),
-            Value::Text(type_str.to_string()),
+            Value::Text(type_str),
             Value::Text(nullable_str.to_string()),
             Value::Text(pk_str.to_string()),
             Value::Text(unique_str.to_string()),
@@ -540,6 +560,34 @@ pub fn execute_show_tables(context: &ExecutionContext) -> Result<QueryResult, Ru
     }))
 }
 
+pub fn execute_show_storage(context: &ExecutionContext) -> Result<QueryResult, RustqlError> {
+    let usage = match context.storage() {
+        Some(storage) => storage.page_usage()?,
+        None => Vec::new(),
+    };
+    let rows = usage
+        .into_iter()
+        .map(|usage| {
+            let kind = match usage.kind {
+                PageUsageKind::Table => "table",
+                PageUsageKind::Index => "index",
+                PageUsageKind::Internal => "internal",
+                PageUsageKind::Free => "free",
+                PageUsageKind::File => "file",
+            };
+            vec![
+                Value::Text(kind.to_string()),
+                usage.name.map_or(Value::Null, Value::Text),
+                Value::Integer(usage.pages as i64),
+            ]
+        })
+        .collect();
+    Ok(rows_result(SelectResult {
+        headers: vec!["kind".to_string(), "name".to_string(), "pages".to_string()],
+        rows,
+    }))
+}
+
 pub fn execute_analyze(
     context: &ExecutionContext,
     table_name: String,
@@ -550,7 +598,10 @@ pub fn execute_analyze(
         .get(&table_name)
         .ok_or_else(|| RustqlError::TableNotFound(table_name.clone()))?;
 
-    Ok(command_result(CommandTag::Analyze, table.rows.len() as u64))
+    Ok(command_result(
+        CommandTag::Analyze,
+        table.row_count() as u64,
+    ))
 }
 
 pub fn update_indexes_on_insert(
@@ -602,25 +653,40 @@ pub fn update_indexes_on_delete(
     table_name: &str,
     deleted_row_ids: &[RowId],
 ) -> Result<(), RustqlError> {
+    let deleted: HashSet<RowId> = deleted_row_ids.iter().copied().collect();
     for index in db.indexes.values_mut() {
         if index.table == table_name {
-            for entry in index.entries.values_mut() {
-                entry.retain(|row_id| !deleted_row_ids.contains(row_id));
-            }
+            remove_rows_from_entries(&mut index.entries, &deleted);
         }
     }
 
     for index in db.composite_indexes.values_mut() {
         if index.table == table_name {
-            for entry in index.entries.values_mut() {
-                entry.retain(|row_id| !deleted_row_ids.contains(row_id));
-            }
+            remove_rows_from_entries(&mut index.entries, &deleted);
         }
     }
 
     Ok(())
 }
 
+/// Drops `deleted` from the entries that list them. Only the chunks holding
+/// such entries are copied.
+fn remove_rows_from_entries<K: Ord + Clone>(
+    entries: &mut SharedMap<K, Vec<RowId>>,
+    deleted: &HashSet<RowId>,
+) {
+    let keys: Vec<K> = entries
+        .iter()
+        .filter(|(_, row_ids)| row_ids.iter().any(|row_id| deleted.contains(row_id)))
+        .map(|(key, _)| key.clone())
+        .collect();
+    for key in keys {
+        if let Some(row_ids) = entries.get_mut(&key) {
+            row_ids.retain(|row_id| !deleted.contains(row_id));
+        }
+    }
+}
+
 pub fn update_indexes_on_update(
     db: &mut Database,
     table_name: &str,
@@ -1055,7 +1121,7 @@ pub(crate) fn row_matches_index_filter(
 }
 
 fn remove_row_from_entries<K: Ord + Clone>(
-    entries: &mut BTreeMap<K, Vec<RowId>>,
+    entries: &mut SharedMap<K, Vec<RowId>>,
     key: &K,
     row_id: RowId,
 ) {
@@ -1229,12 +1295,284 @@ pub fn get_indexed_rows(
         }
     }
 
+    if table.is_paged() {
+        if let Some(index) = PagedIndex::for_usage(db, table, usage)? {
+            let probes = stored_index_probes(usage);
+            index.visit_entries(db, table, &probes, &mut |key, row_id| {
+                if index_key_matches(usage, &key) {
+                    row_ids.insert(row_id);
+                }
+            })?;
+        }
+        return Ok(row_ids);
+    }
     Ok(row_ids
         .into_iter()
         .filter(|row_id| table.position_of_row_id(*row_id).is_some())
         .collect())
 }
 
+/// Every entry of the single-column index `index` as `table` sees it,
+/// reading the stored entries of a paged index.
+pub(crate) fn index_entries<'a>(
+    db: &dyn DatabaseCatalog,
+    table: &Table,
+    index: &'a Index,
+) -> Result<Cow<'a, SharedMap<Value, Vec<RowId>>>, RustqlError> {
+    let Some(paged) = PagedIndex::for_index(table, index)? else {
+        return Ok(Cow::Borrowed(&index.entries));
+    };
+    let mut entries = index.entries.clone();
+    paged.visit_entries(db, table, &[Vec::new()], &mut |mut key, row_id| {
+        if let Some(value) = key.pop() {
+            entries.entry(value).or_default().push(row_id);
+        }
+    })?;
+    Ok(Cow::Owned(entries))
+}
+
+type CompositeIndexEntries = SharedMap<Vec<Value>, Vec<RowId>>;
+
+/// Every entry of the composite index `index` as `table` sees it, reading
+/// the stored entries of a paged index.
+pub(crate) fn composite_index_entries<'a>(
+    db: &dyn DatabaseCatalog,
+    table: &Table,
+    index: &'a CompositeIndex,
+) -> Result<Cow<'a, CompositeIndexEntries>, RustqlError> {
+    let Some(paged) = PagedIndex::for_composite(table, index)? else {
+        return Ok(Cow::Borrowed(&index.entries));
+    };
+    let mut entries = index.entries.clone();
+    paged.visit_entries(db, table, &[Vec::new()], &mut |key, row_id| {
+        entries.entry(key).or_default().push(row_id);
+    })?;
+    Ok(Cow::Owned(entries))
+}
+
+/// An index stored with the paged table it covers. Its entries in memory
+/// only cover the rows the table wrote since it was paged out; the others
+/// are read from storage.
+struct PagedIndex<'a> {
+    stored: &'a str,
+    composite: bool,
+    positions: Vec<usize>,
+    filter: Option<&'a Expression>,
+}
+
+impl<'a> PagedIndex<'a> {
+    fn for_usage(
+        db: &'a dyn DatabaseCatalog,
+        table: &Table,
+        usage: &IndexUsage,
+    ) -> Result<Option<Self>, RustqlError> {
+        let name = usage.index_name();
+        let not_found = || RustqlError::IndexNotFound {
+            name: name.to_string(),
+        };
+        match usage {
+            IndexUsage::CompositePrefix { .. } => {
+                Self::for_composite(table, db.get_composite_index(name).ok_or_else(not_found)?)
+            }
+            _ => Self::for_index(table, db.get_index(name).ok_or_else(not_found)?),
+        }
+    }
+
+    fn for_index(table: &Table, index: &'a Index) -> Result<Option<Self>, RustqlError> {
+        let Some(stored) = index.paged.as_deref().filter(|_| table.is_paged()) else {
+            return Ok(None);
+        };
+        Ok(Some(PagedIndex {
+            stored,
+            composite: false,
+            positions: get_column_positions(table, std::slice::from_ref(&index.column))?,
+            filter: index.filter_expr.as_ref(),
+        }))
+    }
+
+    fn for_composite(
+        table: &Table,
+        index: &'a CompositeIndex,
+    ) -> Result<Option<Self>, RustqlError> {
+        let Some(stored) = index.paged.as_deref().filter(|_| table.is_paged()) else {
+            return Ok(None);
+        };
+        Ok(Some(PagedIndex {
+            stored,
+            composite: true,
+            positions: get_column_positions(table, &index.columns)?,
+            filter: index.filter_expr.as_ref(),
+        }))
+    }
+
+    /// Visits the entries missing from memory whose keys start with one of
+    /// `prefixes`: the stored entries of rows that did not change since
+    /// `table` was paged out, and the entries of the rows later commits
+    /// changed, as `table` still sees them.
+    fn visit_entries(
+        &self,
+        db: &dyn DatabaseCatalog,
+        table: &Table,
+        prefixes: &[Vec<Value>],
+        visit: &mut dyn FnMut(Vec<Value>, RowId),
+    ) -> Result<(), RustqlError> {
+        let changed = table.changed_row_ids();
+        for prefix in prefixes {
+            table.scan_stored_index(self.stored, self.composite, prefix, &mut |key, row_id| {
+                if !changed.contains(&row_id) {
+                    visit(key, row_id);
+                }
+                Ok(true)
+            })?;
+        }
+        for row_id in changed {
+            // The entries of rows the table wrote itself are in memory.
+            if table.written_row(row_id).is_some() {
+                continue;
+            }
+            if let Some(row) = table.fetch_row(row_id)?
+                && row_matches_index_filter(db, table, self.filter, &row)?
+            {
+                let key = composite_key_for_row(&row, &self.positions);
+                if prefixes.iter().any(|prefix| key.starts_with(prefix)) {
+                    visit(key, row_id);
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The key prefixes to read from the stored entries of a paged index for
+/// `usage`. Keys are stored by their exact values, so a lookup that has to
+/// match numbers of other types probes each form an equal number can take,
+/// or reads every entry when there are too many.
+fn stored_index_probes(usage: &IndexUsage) -> Vec<Vec<Value>> {
+    let probes = match usage {
+        IndexUsage::Equality { value, .. } => {
+            equal_key_values(value).map(|values| values.into_iter().map(|v| vec![v]).collect())
+        }
+        IndexUsage::In { values, .. } => values
+            .iter()
+            .map(equal_key_values)
+            .collect::<Option<Vec<_>>>()
+            .map(|values| values.into_iter().flatten().map(|v| vec![v]).collect()),
+        IndexUsage::CompositePrefix { values, .. } => {
+            values.iter().try_fold(vec![Vec::new()], |prefixes, value| {
+                let equal = equal_key_values(value)?;
+                Some(
+                    prefixes
+                        .iter()
+                        .flat_map(|prefix| {
+                            equal.iter().map(move |value| {
+                                let mut prefix = prefix.clone();
+                                prefix.push(value.clone());
+                                prefix
+                            })
+                        })
+                        .collect(),
+                )
+            })
+        }
+        IndexUsage::RangeGreater { .. }
+        | IndexUsage::RangeLess { .. }
+        | IndexUsage::RangeBetween { .. } => None,
+    };
+    probes.unwrap_or_else(|| vec![Vec::new()])
+}
+
+/// The stored key values an index lookup for `value` matches, or `None` when
+/// they cannot be listed. Numbers compare with a tolerance, but an integer
+/// of magnitude 1 to 2^53 only equals its own integer, float and decimal
+/// forms.
+fn equal_key_values(value: &Value) -> Option<Vec<Value>> {
+    const MAX_EXACT_FLOAT: f64 = 9_007_199_254_740_992.0;
+    let number = match value {
+        Value::Integer(i) => *i as f64,
+        Value::Float(f) => *f,
+        Value::Decimal(d) => d.to_f64(),
+        _ => return Some(vec![value.clone()]),
+    };
+    if number.fract() != 0.0 || !(1.0..=MAX_EXACT_FLOAT).contains(&number.abs()) {
+        return None;
+    }
+    let integer = Value::Integer(number as i64);
+    if !values_equal_for_index_lookup(&integer, value) {
+        return None;
+    }
+    Some(vec![
+        integer,
+        Value::Float(number),
+        Value::Decimal(Decimal::from_i64(number as i64)),
+    ])
+}
+
+/// Whether an index entry with `key` answers `usage`, as the lookups on the
+/// entries in memory decide.
+fn index_key_matches(usage: &IndexUsage, key: &[Value]) -> bool {
+    let Some(first) = key.first() else {
+        return false;
+    };
+    let equal = |value: &Value| {
+        if is_numeric_value(value) {
+            values_equal_for_index_lookup(first, value)
+        } else {
+            first == value
+        }
+    };
+    match usage {
+        IndexUsage::Equality { value, .. } => equal(value),
+        IndexUsage::In { values, .. } => values.iter().any(equal),
+        IndexUsage::RangeGreater {
+            value, inclusive, ..
+        } => {
+            if is_numeric_value(value) {
+                let op = if *inclusive {
+                    BinaryOperator::GreaterThanOrEqual
+                } else {
+                    BinaryOperator::GreaterThan
+                };
+                compare_index_values(first, &op, value)
+            } else if *inclusive {
+                first >= value
+            } else {
+                first > value
+            }
+        }
+        IndexUsage::RangeLess {
+            value, inclusive, ..
+        } => {
+            if is_numeric_value(value) {
+                let op = if *inclusive {
+                    BinaryOperator::LessThanOrEqual
+                } else {
+                    BinaryOperator::LessThan
+                };
+                compare_index_values(first, &op, value)
+            } else if *inclusive {
+                first <= value
+            } else {
+                first < value
+            }
+        }
+        IndexUsage::RangeBetween { lower, upper, .. } => {
+            if is_numeric_value(lower) || is_numeric_value(upper) {
+                compare_index_values(first, &BinaryOperator::GreaterThanOrEqual, lower)
+                    && compare_index_values(first, &BinaryOperator::LessThanOrEqual, upper)
+            } else {
+                first >= lower && first <= upper
+            }
+        }
+        IndexUsage::CompositePrefix { values, .. } => {
+            if values.iter().any(is_numeric_value) {
+                composite_key_matches_prefix(key, values)
+            } else {
+                key.starts_with(values)
+            }
+        }
+    }
+}
+
 fn extend_matching_index_entries(index: &Index, value: &Value, row_ids: &mut HashSet<RowId>) {
     if is_numeric_value(value) {
         for (key, rows) in &index.entries {
@@ -1264,7 +1602,10 @@ fn compare_index_values(left: &Value, op: &BinaryOperator, right: &Value) -> boo
 }
 
 fn is_numeric_value(value: &Value) -> bool {
-    matches!(value, Value::Integer(_) | Value::Float(_))
+    matches!(
+        value,
+        Value::Integer(_) | Value::Float(_) | Value::Decimal(_)
+    )
 }
 
 pub fn execute_truncate_table(
@@ -1276,29 +1617,33 @@ pub fn execute_truncate_table(
         .tables
         .get_mut(&table_name)
         .ok_or_else(|| RustqlError::TableNotFound(table_name.clone()))?;
-    let old_rows = std::mem::take(&mut table.rows);
-    let old_row_ids = std::mem::take(&mut table.row_ids);
-    let old_next_row_id = table.next_row_id;
-    table.next_row_id = 1;
-    super::record_wal_entry(
-        context,
-        WalEntry::TruncateTable {
-            name: table_name.clone(),
-            old_rows,
-            old_row_ids,
-            old_next_row_id,
-        },
-    );
+    let emptied = Table::new(table.columns.clone(), Vec::new(), table.constraints.clone());
+    let old_table = std::mem::replace(table, emptied);
+    let mut old_indexes = Vec::new();
     for index in db.indexes.values_mut() {
         if index.table == table_name {
-            index.entries.clear();
+            old_indexes.push(index.clone());
+            index.entries = SharedMap::default();
+            index.paged = None;
         }
     }
+    let mut old_composite_indexes = Vec::new();
     for index in db.composite_indexes.values_mut() {
         if index.table == table_name {
-            index.entries.clear();
+            old_composite_indexes.push(index.clone());
+            index.entries = SharedMap::default();
+            index.paged = None;
         }
     }
+    super::record_wal_entry(
+        context,
+        WalEntry::TruncateTable {
+            name: table_name.clone(),
+            table: old_table,
+            indexes: old_indexes,
+            composite_indexes: old_composite_indexes,
+        },
+    );
     save_if_not_in_transaction(context, &db)?;
     Ok(command_result(CommandTag::TruncateTable, 0))
 }
@@ -1,9 +1,8 @@
 use crate::ast::*;
-use crate::database::Database;
+use crate::database::{Database, RowId, Table};
 use crate::engine::{CommandTag, QueryResult};
 use crate::error::{ConstraintKind, RustqlError};
 use crate::wal::WalEntry;
-use std::collections::HashSet;
 
 mod constraints;
 mod delete;
@@ -17,6 +16,8 @@ pub(crate) use insert::execute_insert;
 pub(crate) use merge::execute_merge;
 pub(crate) use update::execute_update;
 
+pub(crate) use constraints::{find_row_with_key, find_row_with_value};
+
 use constraints::{
     find_conflict_row, handle_foreign_keys_for_delete, handle_foreign_keys_for_update,
     validate_check_constraints, validate_foreign_keys_for_insert, validate_foreign_keys_for_update,
@@ -31,6 +32,7 @@ use super::expr::{
     coerce_value_for_type, evaluate_expression, evaluate_value_expression,
     evaluate_value_expression_with_db, rows_equal_for_sql_identity, values_equal_for_sql_identity,
 };
+use super::interrupt::check_interrupt;
 use super::{
     ExecutionContext, SelectResult, command_result, ddl, get_database_read, get_database_write,
     record_wal_entry, rows_result, save_if_not_in_transaction, select,
@@ -65,6 +67,44 @@ fn evaluate_merge_insert_value(
     }
 }
 
+/// Visits the rows of `table` that `where_clause` may match in row id order:
+/// the rows an index lookup returns when an index applies, or every row.
+fn for_each_candidate_row(
+    db: &Database,
+    table_name: &str,
+    table: &Table,
+    where_clause: Option<&Expression>,
+    mut visit: impl FnMut(RowId, &[Value]) -> Result<bool, RustqlError>,
+) -> Result<(), RustqlError> {
+    let index_usage =
+        where_clause.and_then(|where_expr| ddl::find_index_usage(db, table_name, where_expr));
+    let Some(index_usage) = index_usage else {
+        return table.for_each_row(visit);
+    };
+    let mut candidate_ids: Vec<RowId> = ddl::get_indexed_rows(db, table, &index_usage)?
+        .into_iter()
+        .collect();
+    candidate_ids.sort();
+    for row_id in candidate_ids {
+        if let Some(row) = table.fetch_row(row_id)?
+            && !visit(row_id, &row)?
+        {
+            break;
+        }
+    }
+    Ok(())
+}
+
+/// Copies every row of `table`, reading paged rows from storage.
+fn collect_rows(table: &Table) -> Result<Vec<Vec<Value>>, RustqlError> {
+    let mut rows = Vec::with_capacity(table.row_count());
+    table.for_each_row(|_, row| {
+        rows.push(row.to_vec());
+        Ok(true)
+    })?;
+    Ok(rows)
+}
+
 #[derive(Clone)]
 struct DmlJoinedSource {
     columns: Vec<ColumnDefinition>,
@@ -85,7 +125,7 @@ fn build_joined_dml_source(
     let base_label = alias.unwrap_or(table);
     let mut source = DmlJoinedSource {
         columns: qualify_columns(&base_table.columns, base_label),
-        rows: base_table.rows.clone(),
+        rows: collect_rows(base_table)?,
     };
 
     for join in joins {
@@ -125,7 +165,7 @@ fn apply_joined_dml_source_join(
         .ok_or_else(|| RustqlError::TableNotFound(join.table.clone()))?;
     let right_label = join.table_alias.as_deref().unwrap_or(&join.table);
     let right_columns = qualify_columns(&right_table.columns, right_label);
-    let right_rows = right_table.rows.clone();
+    let right_rows = collect_rows(right_table)?;
 
     let mut joined_columns = left.columns.clone();
     joined_columns.extend(right_columns.clone());
@@ -1,14 +1,127 @@
 use super::generated::parse_wrapped_select;
 use super::*;
+use crate::database::{RowId, Table};
+
+/// Returns the id of the first row of `table`, other than `exclude_row`, that
+/// `matches` accepts. Paged rows are read from storage a batch at a time.
+fn find_row(
+    table: &Table,
+    exclude_row: Option<RowId>,
+    mut matches: impl FnMut(&[Value]) -> bool,
+) -> Result<Option<RowId>, RustqlError> {
+    let mut found = None;
+    table.for_each_row(|row_id, row| {
+        if exclude_row != Some(row_id) && matches(row) {
+            found = Some(row_id);
+            return Ok(false);
+        }
+        Ok(true)
+    })?;
+    Ok(found)
+}
+
+/// Returns the id of a row of `table`, other than `exclude_row`, whose
+/// `column` holds `value`. A full index on the column is probed when there
+/// is one, so keys of paged tables are checked without reading every row.
+pub(crate) fn find_row_with_value(
+    db: &Database,
+    table_name: &str,
+    table: &Table,
+    column: usize,
+    value: &Value,
+    exclude_row: Option<RowId>,
+) -> Result<Option<RowId>, RustqlError> {
+    find_row_with_key(
+        db,
+        table_name,
+        table,
+        &[column],
+        std::slice::from_ref(value),
+        exclude_row,
+    )
+}
+
+/// Returns the id of a row of `table`, other than `exclude_row`, whose
+/// `columns` hold `key`. The first of the columns with a full index is
+/// probed, and only the rows the index lists are read.
+pub(crate) fn find_row_with_key(
+    db: &Database,
+    table_name: &str,
+    table: &Table,
+    columns: &[usize],
+    key: &[Value],
+    exclude_row: Option<RowId>,
+) -> Result<Option<RowId>, RustqlError> {
+    let holds_key = |row: &[Value]| {
+        columns.iter().zip(key).all(|(&column, value)| {
+            row.get(column)
+                .is_some_and(|v| values_equal_for_sql_identity(v, value))
+        })
+    };
+    // A NaN key equals itself as a row identity but not as an index lookup.
+    let probe = columns.iter().zip(key).find_map(|(&column, value)| {
+        if matches!(value, Value::Float(f) if !f.is_finite()) {
+            return None;
+        }
+        let column_name = &table.columns[column].name;
+        db.indexes
+            .values()
+            .find(|index| {
+                index.table == table_name
+                    && index.column == *column_name
+                    && index.filter_expr.is_none()
+            })
+            .map(|index| (index, value))
+    });
+    let Some((index, value)) = probe else {
+        return find_row(table, exclude_row, holds_key);
+    };
+    let usage = ddl::IndexUsage::Equality {
+        index_name: index.name.clone(),
+        value: value.clone(),
+    };
+    let mut candidates: Vec<RowId> = ddl::get_indexed_rows(db, table, &usage)?
+        .into_iter()
+        .filter(|row_id| exclude_row != Some(*row_id))
+        .collect();
+    candidates.sort();
+    for row_id in candidates {
+        if let Some(row) = table.fetch_row(row_id)?
+            && holds_key(&row)
+        {
+            return Ok(Some(row_id));
+        }
+    }
+    Ok(None)
+}
+
+/// Returns the rows of `table` whose `column` holds `value`.
+fn rows_referencing(
+    table: &Table,
+    column: usize,
+    value: &Value,
+) -> Result<Vec<(RowId, Vec<Value>)>, RustqlError> {
+    let mut rows = Vec::new();
+    table.for_each_row(|row_id, row| {
+        if row
+            .get(column)
+            .is_some_and(|v| values_equal_for_sql_identity(v, value))
+        {
+            rows.push((row_id, row.to_vec()));
+        }
+        Ok(true)
+    })?;
+    Ok(rows)
+}
 
 enum CascadedIndexAction {
     Delete {
         table: String,
-        row_ids: Vec<crate::database::RowId>,
+        row_ids: Vec<RowId>,
     },
     Update {
         table: String,
-        row_id: crate::database::RowId,
+        row_id: RowId,
         old_row: Vec<Value>,
         new_row: Vec<Value>,
     },
@@ -79,16 +192,14 @@ pub(super) fn validate_primary_keys_for_insert(
                 .get(table_name)
                 .ok_or_else(|| RustqlError::TableNotFound(table_name.to_string()))?;
 
-            for existing_row in &table.rows {
-                if values_equal_for_sql_identity(&existing_row[col_idx], pk_value) {
-                    return Err(RustqlError::ConstraintViolation {
-                        kind: ConstraintKind::PrimaryKey,
-                        message: format!(
-                            "Primary key constraint violation: Duplicate value for column '{}'",
-                            col_def.name
-                        ),
-                    });
-                }
+            if find_row_with_value(db, table_name, table, col_idx, pk_value, None)?.is_some() {
+                return Err(RustqlError::ConstraintViolation {
+                    kind: ConstraintKind::PrimaryKey,
+                    message: format!(
+                        "Primary key constraint violation: Duplicate value for column '{}'",
+                        col_def.name
+                    ),
+                });
             }
         }
     }
@@ -100,7 +211,7 @@ pub(super) fn validate_unique_constraints_for_insert(
     columns: &[ColumnDefinition],
     row: &[Value],
     table_name: &str,
-    exclude_row_idx: Option<usize>,
+    exclude_row: Option<RowId>,
 ) -> Result<(), RustqlError> {
     for (col_idx, col_def) in columns.iter().enumerate() {
         if col_def.unique {
@@ -115,21 +226,16 @@ pub(super) fn validate_unique_constraints_for_insert(
                 .get(table_name)
                 .ok_or_else(|| RustqlError::TableNotFound(table_name.to_string()))?;
 
-            for (row_idx, existing_row) in table.rows.iter().enumerate() {
-                if let Some(exclude_idx) = exclude_row_idx
-                    && row_idx == exclude_idx
-                {
-                    continue;
-                }
-                if values_equal_for_sql_identity(&existing_row[col_idx], unique_value) {
-                    return Err(RustqlError::ConstraintViolation {
-                        kind: ConstraintKind::Unique,
-                        message: format!(
-                            "Unique constraint violation: Duplicate value for column '{}'",
-                            col_def.name
-                        ),
-                    });
-                }
+            if find_row_with_value(db, table_name, table, col_idx, unique_value, exclude_row)?
+                .is_some()
+            {
+                return Err(RustqlError::ConstraintViolation {
+                    kind: ConstraintKind::Unique,
+                    message: format!(
+                        "Unique constraint violation: Duplicate value for column '{}'",
+                        col_def.name
+                    ),
+                });
             }
         }
     }
@@ -137,7 +243,9 @@ pub(super) fn validate_unique_constraints_for_insert(
 }
 
 pub(super) fn validate_foreign_keys_for_insert(
+    context: &ExecutionContext,
     db: &Database,
+    table_name: &str,
     columns: &[ColumnDefinition],
     row: &[Value],
 ) -> Result<(), RustqlError> {
@@ -145,7 +253,7 @@ pub(super) fn validate_foreign_keys_for_insert(
         if let Some(ref fk) = col_def.foreign_key {
             let fk_value = &row[col_idx];
 
-            if matches!(fk_value, Value::Null) {
+            if matches!(fk_value, Value::Null) || context.defer_check(fk.deferrable, table_name) {
                 continue;
             }
 
@@ -167,12 +275,15 @@ pub(super) fn validate_foreign_keys_for_insert(
                     )
                 })?;
 
-            let value_exists = ref_table.rows.iter().any(|ref_row| {
-                ref_row
-                    .get(ref_col_idx)
-                    .map(|v| values_equal_for_sql_identity(v, fk_value))
-                    .unwrap_or(false)
-            });
+            let value_exists = find_row_with_value(
+                db,
+                &fk.referenced_table,
+                ref_table,
+                ref_col_idx,
+                fk_value,
+                None,
+            )?
+            .is_some();
 
             if !value_exists {
                 return Err(RustqlError::ConstraintViolation {
@@ -189,11 +300,13 @@ pub(super) fn validate_foreign_keys_for_insert(
 }
 
 pub(super) fn validate_foreign_keys_for_update(
+    context: &ExecutionContext,
     db: &Database,
+    table_name: &str,
     columns: &[ColumnDefinition],
     row: &[Value],
 ) -> Result<(), RustqlError> {
-    validate_foreign_keys_for_insert(db, columns, row)
+    validate_foreign_keys_for_insert(context, db, table_name, columns, row)
 }
 
 pub(super) fn handle_foreign_keys_for_delete(
@@ -231,20 +344,13 @@ pub(super) fn handle_foreign_keys_for_delete(
 
                 let ref_value = &row_to_delete[ref_col_idx];
 
-                let mut rows_to_modify: Vec<usize> = Vec::new();
-                for (row_idx, other_row) in other_table.rows.iter().enumerate() {
-                    if other_row
-                        .get(col_idx)
-                        .map(|v| values_equal_for_sql_identity(v, ref_value))
-                        .unwrap_or(false)
-                    {
-                        rows_to_modify.push(row_idx);
-                    }
-                }
+                let rows_to_modify = rows_referencing(other_table, col_idx, ref_value)?;
 
                 match fk.on_delete {
                     ForeignKeyAction::Restrict | ForeignKeyAction::NoAction => {
-                        if !rows_to_modify.is_empty() {
+                        if !rows_to_modify.is_empty()
+                            && !context.defer_check(fk.deferrable, other_table_name)
+                        {
                             return Err(RustqlError::ConstraintViolation {
                                 kind: ConstraintKind::ForeignKey,
                                 message: format!(
@@ -255,27 +361,20 @@ pub(super) fn handle_foreign_keys_for_delete(
                         }
                     }
                     ForeignKeyAction::Cascade => {
-                        rows_to_modify.sort();
-                        rows_to_modify.reverse();
                         let mut deleted_row_ids = Vec::with_capacity(rows_to_modify.len());
-                        for row_idx in &rows_to_modify {
-                            let row_id = other_table.row_id_at(*row_idx).ok_or_else(|| {
-                                RustqlError::Internal(
-                                    "Missing row id for cascading delete".to_string(),
-                                )
-                            })?;
-                            deleted_row_ids.push(row_id);
-                            let old_row = other_table.rows[*row_idx].clone();
+                        for (row_id, _) in &rows_to_modify {
+                            let Some(old_row) = other_table.remove_row_by_id(*row_id)? else {
+                                continue;
+                            };
+                            deleted_row_ids.push(*row_id);
                             record_wal_entry(
                                 context,
                                 WalEntry::DeleteRow {
                                     table: other_table_name.clone(),
-                                    row_id,
-                                    position: *row_idx,
+                                    row_id: *row_id,
                                     old_row,
                                 },
                             );
-                            let _ = other_table.remove_row_by_id(row_id);
                         }
                         if !deleted_row_ids.is_empty() {
                             index_actions.push(CascadedIndexAction::Delete {
@@ -285,30 +384,29 @@ pub(super) fn handle_foreign_keys_for_delete(
                         }
                     }
                     ForeignKeyAction::SetNull => {
-                        for row_idx in rows_to_modify {
-                            let row_id = other_table.row_id_at(row_idx).ok_or_else(|| {
-                                RustqlError::Internal(
-                                    "Missing row id for foreign key update".to_string(),
-                                )
-                            })?;
-                            if let Some(row) = other_table.rows.get_mut(row_idx) {
-                                let old_row = row.clone();
-                                record_wal_entry(
-                                    context,
-                                    WalEntry::UpdateRow {
-                                        table: other_table_name.clone(),
-                                        row_id,
-                                        old_row: old_row.clone(),
-                                    },
-                                );
-                                row[col_idx] = Value::Null;
-                                index_actions.push(CascadedIndexAction::Update {
+                        for (row_id, old_row) in rows_to_modify {
+                            let mut new_row = old_row.clone();
+                            new_row[col_idx] = Value::Null;
+                            if other_table
+                                .set_row_by_id(row_id, new_row.clone())?
+                                .is_none()
+                            {
+                                continue;
+                            }
+                            record_wal_entry(
+                                context,
+                                WalEntry::UpdateRow {
                                     table: other_table_name.clone(),
                                     row_id,
-                                    old_row,
-                                    new_row: row.clone(),
-                                });
-                            }
+                                    old_row: old_row.clone(),
+                                },
+                            );
+                            index_actions.push(CascadedIndexAction::Update {
+                                table: other_table_name.clone(),
+                                row_id,
+                                old_row,
+                                new_row,
+                            });
                         }
                     }
                 }
@@ -358,20 +456,13 @@ pub(super) fn handle_foreign_keys_for_update(
 
                 let old_value = &old_row[ref_col_idx];
 
-                let mut rows_to_modify: Vec<usize> = Vec::new();
-                for (row_idx, other_row) in other_table.rows.iter().enumerate() {
-                    if other_row
-                        .get(col_idx)
-                        .map(|v| values_equal_for_sql_identity(v, old_value))
-                        .unwrap_or(false)
-                    {
-                        rows_to_modify.push(row_idx);
-                    }
-                }
+                let rows_to_modify = rows_referencing(other_table, col_idx, old_value)?;
 
                 match fk.on_update {
                     ForeignKeyAction::Restrict | ForeignKeyAction::NoAction => {
-                        if !rows_to_modify.is_empty() {
+                        if !rows_to_modify.is_empty()
+                            && !context.defer_check(fk.deferrable, other_table_name)
+                        {
                             return Err(RustqlError::ConstraintViolation {
                                 kind: ConstraintKind::ForeignKey,
                                 message: format!(
@@ -383,57 +474,55 @@ pub(super) fn handle_foreign_keys_for_update(
                     }
                     ForeignKeyAction::Cascade => {
                         let new_value = new_row[ref_col_idx].clone();
-                        for row_idx in rows_to_modify {
-                            let row_id = other_table.row_id_at(row_idx).ok_or_else(|| {
-                                RustqlError::Internal(
-                                    "Missing row id for cascading update".to_string(),
-                                )
-                            })?;
-                            if let Some(row) = other_table.rows.get_mut(row_idx) {
-                                let old_row = row.clone();
-                                record_wal_entry(
-                                    context,
-                                    WalEntry::UpdateRow {
-                                        table: other_table_name.clone(),
-                                        row_id,
-                                        old_row: old_row.clone(),
-                                    },
-                                );
-                                row[col_idx] = new_value.clone();
-                                index_actions.push(CascadedIndexAction::Update {
+                        for (row_id, old_row) in rows_to_modify {
+                            let mut new_row = old_row.clone();
+                            new_row[col_idx] = new_value.clone();
+                            if other_table
+                                .set_row_by_id(row_id, new_row.clone())?
+                                .is_none()
+                            {
+                                continue;
+                            }
+                            record_wal_entry(
+                                context,
+                                WalEntry::UpdateRow {
                                     table: other_table_name.clone(),
                                     row_id,
-                                    old_row,
-                                    new_row: row.clone(),
-                                });
-                            }
+                                    old_row: old_row.clone(),
+                                },
+                            );
+                            index_actions.push(CascadedIndexAction::Update {
+                                table: other_table_name.clone(),
+                                row_id,
+                                old_row,
+                                new_row,
+                            });
                         }
                     }
                     ForeignKeyAction::SetNull => {
-                        for row_idx in rows_to_modify {
-                            let row_id = other_table.row_id_at(row_idx).ok_or_else(|| {
-                                RustqlError::Internal(
-                                    "Missing row id for foreign key set null".to_string(),
-                                )
-                            })?;
-                            if let Some(row) = other_table.rows.get_mut(row_idx) {
-                                let old_row = row.clone();
-                                record_wal_entry(
-                                    context,
-                                    WalEntry::UpdateRow {
-                                        table: other_table_name.clone(),
-                                        row_id,
-                                        old_row: old_row.clone(),
-                                    },
-                                );
-                                row[col_idx] = Value::Null;
-                                index_actions.push(CascadedIndexAction::Update {
+                        for (row_id, old_row) in rows_to_modify {
+                            let mut new_row = old_row.clone();
+                            new_row[col_idx] = Value::Null;
+                            if other_table
+                                .set_row_by_id(row_id, new_row.clone())?
+                                .is_none()
+                            {
+                                continue;
+                            }
+                            record_wal_entry(
+                                context,
+                                WalEntry::UpdateRow {
                                     table: other_table_name.clone(),
                                     row_id,
-                                    old_row,
-                                    new_row: row.clone(),
-                                });
-                            }
+                                    old_row: old_row.clone(),
+                                },
+                            );
+                            index_actions.push(CascadedIndexAction::Update {
+                                table: other_table_name.clone(),
+                                row_id,
+                                old_row,
+                                new_row,
+                            });
                         }
                     }
                 }
@@ -449,33 +538,32 @@ pub(super) fn find_conflict_row(
     columns: &[ColumnDefinition],
     conflict_columns: &[String],
     new_row: &[Value],
-) -> Option<usize> {
-    let table = db.tables.get(table_name)?;
+) -> Result<Option<RowId>, RustqlError> {
+    let Some(table) = db.tables.get(table_name) else {
+        return Ok(None);
+    };
     let conflict_indices: Vec<usize> = conflict_columns
         .iter()
         .filter_map(|name| columns.iter().position(|c| c.name == *name))
         .collect();
 
-    for (row_idx, existing_row) in table.rows.iter().enumerate() {
-        let matches = conflict_indices.iter().all(|&col_idx| {
+    find_row(table, None, |existing_row| {
+        conflict_indices.iter().all(|&col_idx| {
             col_idx < existing_row.len()
                 && col_idx < new_row.len()
                 && values_equal_for_sql_identity(&existing_row[col_idx], &new_row[col_idx])
                 && !matches!(existing_row[col_idx], Value::Null)
-        });
-        if matches {
-            return Some(row_idx);
-        }
-    }
-    None
+        })
+    })
 }
 
 pub(super) fn validate_table_constraints_for_insert(
+    context: &ExecutionContext,
     db: &Database,
     columns: &[ColumnDefinition],
     row: &[Value],
     table_name: &str,
-    exclude_row_idx: Option<usize>,
+    exclude_row: Option<RowId>,
 ) -> Result<(), RustqlError> {
     let table = db
         .tables
@@ -504,29 +592,27 @@ pub(super) fn validate_table_constraints_for_insert(
                         ),
                     });
                 }
-                for (row_idx, existing_row) in table.rows.iter().enumerate() {
-                    if let Some(exclude_idx) = exclude_row_idx
-                        && row_idx == exclude_idx
-                    {
-                        continue;
-                    }
+                let duplicate = find_row(table, exclude_row, |existing_row| {
                     let existing_key: Vec<Value> = col_indices
                         .iter()
                         .map(|&i| existing_row[i].clone())
                         .collect();
-                    if rows_equal_for_sql_identity(&existing_key, &key) {
-                        return Err(RustqlError::ConstraintViolation {
-                            kind: crate::error::ConstraintKind::PrimaryKey,
-                            message: format!(
-                                "Composite PRIMARY KEY constraint violation: duplicate value for columns {:?}",
-                                pk_cols
-                            ),
-                        });
-                    }
+                    rows_equal_for_sql_identity(&existing_key, &key)
+                })?;
+                if duplicate.is_some() {
+                    return Err(RustqlError::ConstraintViolation {
+                        kind: crate::error::ConstraintKind::PrimaryKey,
+                        message: format!(
+                            "Composite PRIMARY KEY constraint violation: duplicate value for columns {:?}",
+                            pk_cols
+                        ),
+                    });
                 }
             }
             crate::ast::TableConstraint::Unique {
-                columns: uq_cols, ..
+                columns: uq_cols,
+                deferrable,
+                ..
             } => {
                 let col_indices: Vec<usize> = uq_cols
                     .iter()
@@ -536,28 +622,26 @@ pub(super) fn validate_table_constraints_for_insert(
                     continue;
                 }
                 let key: Vec<Value> = col_indices.iter().map(|&i| row[i].clone()).collect();
-                if key.iter().any(|v| matches!(v, Value::Null)) {
+                if key.iter().any(|v| matches!(v, Value::Null))
+                    || context.defer_check(*deferrable, table_name)
+                {
                     continue;
                 }
-                for (row_idx, existing_row) in table.rows.iter().enumerate() {
-                    if let Some(exclude_idx) = exclude_row_idx
-                        && row_idx == exclude_idx
-                    {
-                        continue;
-                    }
+                let duplicate = find_row(table, exclude_row, |existing_row| {
                     let existing_key: Vec<Value> = col_indices
                         .iter()
                         .map(|&i| existing_row[i].clone())
                         .collect();
-                    if rows_equal_for_sql_identity(&existing_key, &key) {
-                        return Err(RustqlError::ConstraintViolation {
-                            kind: crate::error::ConstraintKind::Unique,
-                            message: format!(
-                                "Composite UNIQUE constraint violation: duplicate value for columns {:?}",
-                                uq_cols
-                            ),
-                        });
-                    }
+                    rows_equal_for_sql_identity(&existing_key, &key)
+                })?;
+                if duplicate.is_some() {
+                    return Err(RustqlError::ConstraintViolation {
+                        kind: crate::error::ConstraintKind::Unique,
+                        message: format!(
+                            "Composite UNIQUE constraint violation: duplicate value for columns {:?}",
+                            uq_cols
+                        ),
+                    });
                 }
             }
         }
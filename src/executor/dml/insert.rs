@@ -1,4 +1,5 @@
 use super::*;
+use std::collections::HashMap;
 
 fn evaluate_insert_value(
     expr: &Expression,
@@ -102,22 +103,33 @@ pub(crate) fn execute_insert(
     };
 
     let mut mapped_values = mapped_values;
+    let mut auto_increment_max: HashMap<usize, i64> = HashMap::new();
     for values in &mut mapped_values {
         for (col_idx, col_def) in table_ref.columns.iter().enumerate() {
             if col_def.auto_increment
                 && col_idx < values.len()
                 && matches!(values[col_idx], Value::Null)
             {
-                let max_val = table_ref
-                    .rows
-                    .iter()
-                    .filter_map(|row| match row.get(col_idx) {
-                        Some(Value::Integer(i)) => Some(*i),
-                        _ => None,
-                    })
-                    .max()
-                    .unwrap_or(0);
-                values[col_idx] = Value::Integer(max_val + 1);
+                let max_val = match auto_increment_max.get(&col_idx) {
+                    Some(max_val) => *max_val,
+                    None => {
+                        let mut max_val = None;
+                        table_ref.for_each_row(|_, row| {
+                            if let Some(Value::Integer(i)) = row.get(col_idx) {
+                                max_val = max_val.max(Some(*i));
+                            }
+                            Ok(true)
+                        })?;
+                        let max_val = max_val.unwrap_or(0);
+                        auto_increment_max.insert(col_idx, max_val);
+                        max_val
+                    }
+                };
+                values[col_idx] = Value::Integer(context.next_auto_increment(
+                    &stmt.table,
+                    &col_def.name,
+                    max_val,
+                ));
             }
         }
     }
@@ -135,9 +147,16 @@ pub(crate) fn execute_insert(
 
     for values in &mapped_values {
         validate_not_null_constraints(&columns_snapshot, values)?;
-        validate_foreign_keys_for_insert(&db, &columns_snapshot, values)?;
+        validate_foreign_keys_for_insert(context, &db, &stmt.table, &columns_snapshot, values)?;
         validate_check_constraints(&columns_snapshot, values)?;
-        validate_table_constraints_for_insert(&db, &columns_snapshot, values, &stmt.table, None)?;
+        validate_table_constraints_for_insert(
+            context,
+            &db,
+            &columns_snapshot,
+            values,
+            &stmt.table,
+            None,
+        )?;
 
         let pk_result =
             validate_primary_keys_for_insert(&db, &columns_snapshot, values, &stmt.table);
@@ -158,20 +177,24 @@ pub(crate) fn execute_insert(
                         continue;
                     }
                     OnConflictAction::DoUpdate { assignments } => {
-                        let conflict_row_idx = find_conflict_row(
+                        let conflict_row = find_conflict_row(
                             &db,
                             &stmt.table,
                             &columns_snapshot,
                             &on_conflict.columns,
                             values,
-                        );
-                        if let Some(row_idx) = conflict_row_idx {
+                        )?;
+                        if let Some(row_id) = conflict_row {
                             let table = db
                                 .tables
                                 .get(&stmt.table)
                                 .ok_or_else(|| RustqlError::TableNotFound(stmt.table.clone()))?;
-                            let existing_row = table.rows[row_idx].clone();
-                            let mut updated_row = existing_row.clone();
+                            let mut updated_row = table
+                                .fetch_row(row_id)?
+                                .ok_or_else(|| {
+                                    RustqlError::Internal("Missing conflicting row".to_string())
+                                })?
+                                .into_owned();
                             for assignment in assignments {
                                 if let Some(idx) = columns_snapshot
                                     .iter()
@@ -194,28 +217,33 @@ pub(crate) fn execute_insert(
                                 &columns_snapshot,
                                 &updated_row,
                                 &stmt.table,
-                                Some(row_idx),
+                                Some(row_id),
+                            )?;
+                            validate_foreign_keys_for_update(
+                                context,
+                                &db,
+                                &stmt.table,
+                                &columns_snapshot,
+                                &updated_row,
                             )?;
-                            validate_foreign_keys_for_update(&db, &columns_snapshot, &updated_row)?;
                             validate_check_constraints(&columns_snapshot, &updated_row)?;
                             validate_table_constraints_for_insert(
+                                context,
                                 &db,
                                 &columns_snapshot,
                                 &updated_row,
                                 &stmt.table,
-                                Some(row_idx),
+                                Some(row_id),
                             )?;
                             let table = db
                                 .tables
                                 .get_mut(&stmt.table)
                                 .ok_or_else(|| RustqlError::TableNotFound(stmt.table.clone()))?;
-                            let row_id = table.row_id_at(row_idx).ok_or_else(|| {
-                                RustqlError::Internal(
-                                    "Missing row id for conflicting row".to_string(),
-                                )
-                            })?;
-                            let old_row = table.rows[row_idx].clone();
-                            table.rows[row_idx] = updated_row.clone();
+                            let old_row = table
+                                .set_row_by_id(row_id, updated_row.clone())?
+                                .ok_or_else(|| {
+                                    RustqlError::Internal("Missing conflicting row".to_string())
+                                })?;
                             record_wal_entry(
                                 context,
                                 WalEntry::UpdateRow {
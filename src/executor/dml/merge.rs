@@ -24,7 +24,7 @@ pub(crate) fn execute_merge(
                 .tables
                 .get(name)
                 .ok_or_else(|| RustqlError::TableNotFound(name.clone()))?;
-            source_rows = table.rows.clone();
+            source_rows = collect_rows(table)?;
             source_columns = table.columns.clone();
             source_alias = alias.clone();
         }
@@ -78,16 +78,9 @@ pub(crate) fn execute_merge(
             .tables
             .get(&stmt.target_table)
             .ok_or_else(|| RustqlError::TableNotFound(stmt.target_table.clone()))?;
-        let target_rows_snapshot: Vec<(usize, Vec<Value>)> = target_table
-            .rows
-            .iter()
-            .enumerate()
-            .map(|(i, r)| (i, r.clone()))
-            .collect();
-
-        let mut matched_indices: Vec<usize> = Vec::new();
-        for (row_idx, target_row) in &target_rows_snapshot {
-            let mut combined_row: Vec<Value> = target_row.clone();
+        let mut matched_rows: Vec<(RowId, Vec<Value>)> = Vec::new();
+        target_table.for_each_row(|row_id, target_row| {
+            let mut combined_row: Vec<Value> = target_row.to_vec();
             combined_row.extend(source_row.clone());
             if evaluate_expression(
                 Some(&*db),
@@ -95,25 +88,18 @@ pub(crate) fn execute_merge(
                 &combined_columns,
                 &combined_row,
             )? {
-                matched_indices.push(*row_idx);
+                matched_rows.push((row_id, target_row.to_vec()));
             }
-        }
+            Ok(true)
+        })?;
 
-        let is_matched = !matched_indices.is_empty();
+        let is_matched = !matched_rows.is_empty();
 
         for when_clause in &stmt.when_clauses {
             match when_clause {
                 MergeWhenClause::Matched { condition, action } if is_matched => {
-                    for &row_idx in &matched_indices {
-                        let target_row = target_rows_snapshot
-                            .iter()
-                            .find(|(i, _)| *i == row_idx)
-                            .map(|(_, r)| r.clone())
-                            .ok_or_else(|| {
-                                RustqlError::Internal(
-                                    "Missing matched target row for merge".to_string(),
-                                )
-                            })?;
+                    for (row_id, target_row) in &matched_rows {
+                        let row_id = *row_id;
                         let mut combined_row: Vec<Value> = target_row.clone();
                         combined_row.extend(source_row.clone());
 
@@ -155,32 +141,35 @@ pub(crate) fn execute_merge(
                                     &target_columns,
                                     &updated_row,
                                     &stmt.target_table,
-                                    Some(row_idx),
+                                    Some(row_id),
                                 )?;
                                 validate_foreign_keys_for_update(
+                                    context,
                                     &db,
+                                    &stmt.target_table,
                                     &target_columns,
                                     &updated_row,
                                 )?;
                                 validate_check_constraints(&target_columns, &updated_row)?;
                                 validate_table_constraints_for_insert(
+                                    context,
                                     &db,
                                     &target_columns,
                                     &updated_row,
                                     &stmt.target_table,
-                                    Some(row_idx),
+                                    Some(row_id),
                                 )?;
                                 let table =
                                     db.tables.get_mut(&stmt.target_table).ok_or_else(|| {
                                         RustqlError::TableNotFound(stmt.target_table.clone())
                                     })?;
-                                let row_id = table.row_id_at(row_idx).ok_or_else(|| {
-                                    RustqlError::Internal(
-                                        "Missing row id for merge update".to_string(),
-                                    )
-                                })?;
-                                let old_row = table.rows[row_idx].clone();
-                                table.rows[row_idx] = updated_row.clone();
+                                let old_row = table
+                                    .set_row_by_id(row_id, updated_row.clone())?
+                                    .ok_or_else(|| {
+                                        RustqlError::Internal(
+                                            "Missing target row for merge update".to_string(),
+                                        )
+                                    })?;
                                 record_wal_entry(
                                     context,
                                     WalEntry::UpdateRow {
@@ -212,28 +201,25 @@ pub(crate) fn execute_merge(
                                     &mut db,
                                     &stmt.target_table,
                                     &target_columns,
-                                    &target_row,
+                                    target_row,
                                 )?;
                                 let table =
                                     db.tables.get_mut(&stmt.target_table).ok_or_else(|| {
                                         RustqlError::TableNotFound(stmt.target_table.clone())
                                     })?;
-                                let row_id = table.row_id_at(row_idx).ok_or_else(|| {
+                                let old_row = table.remove_row_by_id(row_id)?.ok_or_else(|| {
                                     RustqlError::Internal(
-                                        "Missing row id for merge delete".to_string(),
+                                        "Missing target row for merge delete".to_string(),
                                     )
                                 })?;
-                                let old_row = table.rows[row_idx].clone();
                                 record_wal_entry(
                                     context,
                                     WalEntry::DeleteRow {
                                         table: stmt.target_table.clone(),
                                         row_id,
-                                        position: row_idx,
                                         old_row,
                                     },
                                 );
-                                let _ = table.remove_row_by_id(row_id);
                                 ddl::update_indexes_on_delete(
                                     &mut db,
                                     &stmt.target_table,
@@ -294,6 +280,7 @@ pub(crate) fn execute_merge(
                                 }
                             }
                             apply_merge_auto_increment_values(
+                                context,
                                 &db,
                                 &stmt.target_table,
                                 &target_columns,
@@ -302,9 +289,16 @@ pub(crate) fn execute_merge(
                             evaluate_generated_columns(&target_columns, &mut new_row, columns)?;
                             coerce_row_to_column_types(&target_columns, &mut new_row)?;
                             validate_not_null_constraints(&target_columns, &new_row)?;
-                            validate_foreign_keys_for_insert(&db, &target_columns, &new_row)?;
+                            validate_foreign_keys_for_insert(
+                                context,
+                                &db,
+                                &stmt.target_table,
+                                &target_columns,
+                                &new_row,
+                            )?;
                             validate_check_constraints(&target_columns, &new_row)?;
                             validate_table_constraints_for_insert(
+                                context,
                                 &db,
                                 &target_columns,
                                 &new_row,
@@ -356,6 +350,7 @@ pub(crate) fn execute_merge(
 }
 
 fn apply_merge_auto_increment_values(
+    context: &ExecutionContext,
     db: &Database,
     table_name: &str,
     columns: &[ColumnDefinition],
@@ -368,16 +363,18 @@ fn apply_merge_auto_increment_values(
 
     for (col_idx, col_def) in columns.iter().enumerate() {
         if col_def.auto_increment && col_idx < row.len() && matches!(row[col_idx], Value::Null) {
-            let max_val = table
-                .rows
-                .iter()
-                .filter_map(|existing_row| match existing_row.get(col_idx) {
-                    Some(Value::Integer(value)) => Some(*value),
-                    _ => None,
-                })
-                .max()
-                .unwrap_or(0);
-            row[col_idx] = Value::Integer(max_val + 1);
+            let mut max_val = None;
+            table.for_each_row(|_, existing_row| {
+                if let Some(Value::Integer(value)) = existing_row.get(col_idx) {
+                    max_val = max_val.max(Some(*value));
+                }
+                Ok(true)
+            })?;
+            row[col_idx] = Value::Integer(context.next_auto_increment(
+                table_name,
+                &col_def.name,
+                max_val.unwrap_or(0),
+            ));
         }
     }
 
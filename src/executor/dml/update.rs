@@ -1,6 +1,6 @@
 use super::*;
 
-type PendingUpdate = (usize, crate::database::RowId, Vec<Value>);
+type PendingUpdate = (RowId, Vec<Value>);
 
 pub(crate) fn execute_update(
     context: &ExecutionContext,
@@ -9,9 +9,9 @@ pub(crate) fn execute_update(
     let mut db = get_database_write(context);
 
     let rows_to_update = if stmt.from.is_some() {
-        collect_update_from_rows(&db, &stmt)?
+        collect_update_from_rows(context, &db, &stmt)?
     } else {
-        collect_simple_update_rows(&db, &stmt)?
+        collect_simple_update_rows(context, &db, &stmt)?
     };
 
     let updated_count = rows_to_update.len();
@@ -27,9 +27,12 @@ pub(crate) fn execute_update(
             .tables
             .get_mut(&stmt.table)
             .ok_or_else(|| RustqlError::TableNotFound(stmt.table.clone()))?;
-        for (row_idx, row_id, updated_row) in rows_to_update {
-            let old_row = table.rows[row_idx].clone();
-            table.rows[row_idx] = updated_row.clone();
+        for (row_id, updated_row) in rows_to_update {
+            let old_row = table
+                .set_row_by_id(row_id, updated_row.clone())?
+                .ok_or_else(|| {
+                    RustqlError::Internal("Missing row for updated row id".to_string())
+                })?;
             record_wal_entry(
                 context,
                 WalEntry::UpdateRow {
@@ -68,67 +71,56 @@ pub(crate) fn execute_update(
 }
 
 fn collect_simple_update_rows(
+    context: &ExecutionContext,
     db: &Database,
     stmt: &UpdateStatement,
 ) -> Result<Vec<PendingUpdate>, RustqlError> {
-    let candidate_indices: Option<HashSet<crate::database::RowId>> = {
-        let table_ref_immut = db
-            .tables
-            .get(&stmt.table)
-            .ok_or_else(|| RustqlError::TableNotFound(stmt.table.clone()))?;
-
-        if let Some(ref where_expr) = stmt.where_clause {
-            if let Some(index_usage) = ddl::find_index_usage(db, &stmt.table, where_expr) {
-                Some(ddl::get_indexed_rows(db, table_ref_immut, &index_usage)?)
-            } else {
-                None
-            }
-        } else {
-            None
-        }
-    };
-
     let table_ref = db
         .tables
         .get(&stmt.table)
         .ok_or_else(|| RustqlError::TableNotFound(stmt.table.clone()))?;
 
-    let mut rows_to_update: Vec<(usize, crate::database::RowId, Vec<Value>)> = Vec::new();
+    let mut rows_to_update: Vec<PendingUpdate> = Vec::new();
 
-    let rows_to_check: Vec<(usize, crate::database::RowId, &Vec<Value>)> =
-        if let Some(ref candidate_set) = candidate_indices {
-            table_ref
-                .iter_rows_with_ids()
-                .enumerate()
-                .filter(|(_, (row_id, _))| candidate_set.contains(row_id))
-                .map(|(idx, (row_id, row))| (idx, row_id, row))
-                .collect()
-        } else {
-            table_ref
-                .iter_rows_with_ids()
-                .enumerate()
-                .map(|(idx, (row_id, row))| (idx, row_id, row))
-                .collect()
-        };
-
-    for (row_idx, row_id, row) in rows_to_check {
-        let should_update = if let Some(ref where_expr) = stmt.where_clause {
-            evaluate_expression(Some(db), where_expr, &table_ref.columns, row)?
-        } else {
-            true
-        };
-        if should_update {
-            let mut updated_row =
-                apply_update_assignments(db, stmt, &table_ref.columns, &table_ref.columns, row)?;
-            validate_updated_row(db, stmt, &table_ref.columns, row_idx, &mut updated_row)?;
-            rows_to_update.push((row_idx, row_id, updated_row));
-        }
-    }
+    for_each_candidate_row(
+        db,
+        &stmt.table,
+        table_ref,
+        stmt.where_clause.as_ref(),
+        |row_id, row| {
+            check_interrupt()?;
+            let should_update = if let Some(ref where_expr) = stmt.where_clause {
+                evaluate_expression(Some(db), where_expr, &table_ref.columns, row)?
+            } else {
+                true
+            };
+            if should_update {
+                let mut updated_row = apply_update_assignments(
+                    db,
+                    stmt,
+                    &table_ref.columns,
+                    &table_ref.columns,
+                    row,
+                )?;
+                validate_updated_row(
+                    context,
+                    db,
+                    stmt,
+                    &table_ref.columns,
+                    row_id,
+                    &mut updated_row,
+                )?;
+                rows_to_update.push((row_id, updated_row));
+            }
+            Ok(true)
+        },
+    )?;
 
     Ok(rows_to_update)
 }
 
 fn collect_update_from_rows(
+    context: &ExecutionContext,
     db: &Database,
     stmt: &UpdateStatement,
 ) -> Result<Vec<PendingUpdate>, RustqlError> {
@@ -152,17 +144,12 @@ fn collect_update_from_rows(
     let mut combined_columns = qualify_columns(&target_columns, &stmt.table);
     combined_columns.extend(source.columns.clone());
 
-    let target_rows: Vec<(usize, crate::database::RowId, Vec<Value>)> = target_table
-        .iter_rows_with_ids()
-        .enumerate()
-        .map(|(idx, (row_id, row))| (idx, row_id, row.clone()))
-        .collect();
-
     let mut rows_to_update = Vec::new();
-    for (row_idx, row_id, target_row) in target_rows {
+    target_table.for_each_row(|row_id, target_row| {
         let mut matched_row: Option<Vec<Value>> = None;
         for source_row in &source.rows {
-            let mut combined_row = target_row.clone();
+            check_interrupt()?;
+            let mut combined_row = target_row.to_vec();
             combined_row.extend(source_row.clone());
             let matches = if let Some(ref where_expr) = stmt.where_clause {
                 evaluate_expression(Some(db), where_expr, &combined_columns, &combined_row)?
@@ -183,10 +170,11 @@ fn collect_update_from_rows(
                 &combined_columns,
                 &combined_row,
             )?;
-            validate_updated_row(db, stmt, &target_columns, row_idx, &mut updated_row)?;
-            rows_to_update.push((row_idx, row_id, updated_row));
+            validate_updated_row(context, db, stmt, &target_columns, row_id, &mut updated_row)?;
+            rows_to_update.push((row_id, updated_row));
         }
-    }
+        Ok(true)
+    })?;
 
     Ok(rows_to_update)
 }
@@ -223,10 +211,11 @@ fn apply_update_assignments(
 }
 
 fn validate_updated_row(
+    context: &ExecutionContext,
     db: &Database,
     stmt: &UpdateStatement,
     target_columns: &[ColumnDefinition],
-    row_idx: usize,
+    row_id: RowId,
     updated_row: &mut [Value],
 ) -> Result<(), RustqlError> {
     evaluate_generated_columns_update(target_columns, updated_row)?;
@@ -237,16 +226,17 @@ fn validate_updated_row(
         target_columns,
         updated_row,
         &stmt.table,
-        Some(row_idx),
+        Some(row_id),
     )?;
-    validate_foreign_keys_for_update(db, target_columns, updated_row)?;
+    validate_foreign_keys_for_update(context, db, &stmt.table, target_columns, updated_row)?;
     validate_check_constraints(target_columns, updated_row)?;
     validate_table_constraints_for_insert(
+        context,
         db,
         target_columns,
         updated_row,
         &stmt.table,
-        Some(row_idx),
+        Some(row_id),
     )?;
     Ok(())
 }
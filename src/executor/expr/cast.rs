@@ -1,4 +1,6 @@
 use super::*;
+use crate::decimal::Decimal;
+use crate::interval::Interval;
 
 pub(super) fn execute_cast(val: Value, target_type: &DataType) -> Result<Value, RustqlError> {
     coerce_value_for_type(val, target_type)
@@ -11,11 +13,28 @@ pub(crate) fn coerce_value_for_type(
     if matches!(val, Value::Null) {
         return Ok(Value::Null);
     }
+    if let Value::Json(json) = &val
+        && !matches!(target_type, DataType::Json | DataType::Text)
+    {
+        return match crate::json::to_sql(&crate::json::parse(json)?) {
+            Value::Json(_) => Err(RustqlError::TypeMismatch(format!(
+                "Cannot cast JSON {} to {}",
+                json, target_type
+            ))),
+            scalar => coerce_value_for_type(scalar, target_type),
+        };
+    }
     match target_type {
         DataType::Integer => {
             match &val {
                 Value::Integer(_) => Ok(val),
                 Value::Float(f) => cast_float_to_integer(*f),
+                Value::Decimal(d) => d.trunc_to_i64().map(Value::Integer).ok_or_else(|| {
+                    RustqlError::TypeMismatch(format!(
+                        "Cannot cast '{}' to INTEGER; value is outside the i64 range",
+                        d
+                    ))
+                }),
                 Value::Text(s) => s.trim().parse::<i64>().map(Value::Integer).map_err(|_| {
                     RustqlError::TypeMismatch(format!("Cannot cast '{}' to INTEGER", s))
                 }),
@@ -29,6 +48,7 @@ pub(crate) fn coerce_value_for_type(
         DataType::Float => match &val {
             Value::Float(f) => finite_float(*f, &f.to_string()).map(Value::Float),
             Value::Integer(i) => Ok(Value::Float(*i as f64)),
+            Value::Decimal(d) => Ok(Value::Float(d.to_f64())),
             Value::Text(s) => s
                 .trim()
                 .parse::<f64>()
@@ -39,6 +59,29 @@ pub(crate) fn coerce_value_for_type(
                 val
             ))),
         },
+        DataType::Decimal { precision, scale } => {
+            let decimal = match &val {
+                Value::Decimal(d) => Some(*d),
+                Value::Integer(i) => Some(Decimal::from_i64(*i)),
+                Value::Float(f) => Some(
+                    Decimal::from_f64(*f).ok_or_else(|| decimal_range_error(&val, target_type))?,
+                ),
+                Value::Text(s) => Some(Decimal::parse(s.trim()).ok_or_else(|| {
+                    RustqlError::TypeMismatch(format!("Cannot cast '{}' to {}", s, target_type))
+                })?),
+                _ => None,
+            };
+            match decimal {
+                Some(decimal) => decimal
+                    .fit(*precision, *scale)
+                    .map(Value::Decimal)
+                    .ok_or_else(|| decimal_range_error(&val, target_type)),
+                None => Err(RustqlError::TypeMismatch(format!(
+                    "Cannot cast {:?} to {}",
+                    val, target_type
+                ))),
+            }
+        }
         DataType::Text => Ok(Value::Text(super::compare::format_value(&val))),
         DataType::Boolean => match &val {
             Value::Boolean(_) => Ok(val),
@@ -61,7 +104,7 @@ pub(crate) fn coerce_value_for_type(
                 .map(Value::Date)
                 .or_else(|| super::date::datetime_date_part(d).map(Value::Date))
                 .ok_or_else(|| temporal_cast_error(d, "DATE", super::date::CANONICAL_DATE_FORMAT)),
-            Value::DateTime(dt) => super::date::datetime_date_part(dt)
+            Value::DateTime(dt) | Value::TimestampTz(dt) => super::date::datetime_date_part(dt)
                 .map(Value::Date)
                 .ok_or_else(|| {
                     temporal_cast_error(dt, "DATE", super::date::CANONICAL_DATETIME_FORMAT)
@@ -72,11 +115,13 @@ pub(crate) fn coerce_value_for_type(
             ))),
         },
         DataType::DateTime => match &val {
-            Value::DateTime(dt) | Value::Text(dt) => super::date::normalize_datetime(dt)
-                .map(Value::DateTime)
-                .ok_or_else(|| {
-                    temporal_cast_error(dt, "DATETIME", super::date::CANONICAL_DATETIME_FORMAT)
-                }),
+            Value::DateTime(dt) | Value::TimestampTz(dt) | Value::Text(dt) => {
+                super::date::normalize_datetime(dt)
+                    .map(Value::DateTime)
+                    .ok_or_else(|| {
+                        temporal_cast_error(dt, "DATETIME", super::date::CANONICAL_DATETIME_FORMAT)
+                    })
+            }
             Value::Date(d) => super::date::normalize_date(d)
                 .map(|date| Value::DateTime(format!("{} 00:00:00", date)))
                 .ok_or_else(|| {
@@ -87,11 +132,33 @@ pub(crate) fn coerce_value_for_type(
                 val
             ))),
         },
+        DataType::TimestampTz => match &val {
+            Value::TimestampTz(_) => Ok(val),
+            Value::Text(s) => super::timezone::normalize_timestamptz(s)
+                .map(Value::TimestampTz)
+                .ok_or_else(|| {
+                    temporal_cast_error(s, "TIMESTAMPTZ", super::date::CANONICAL_TIMESTAMPTZ_FORMAT)
+                }),
+            Value::DateTime(dt) => super::date::normalize_datetime(dt)
+                .map(Value::TimestampTz)
+                .ok_or_else(|| {
+                    temporal_cast_error(dt, "TIMESTAMPTZ", super::date::CANONICAL_DATETIME_FORMAT)
+                }),
+            Value::Date(d) => super::date::normalize_date(d)
+                .map(|date| Value::TimestampTz(format!("{} 00:00:00", date)))
+                .ok_or_else(|| {
+                    temporal_cast_error(d, "TIMESTAMPTZ", super::date::CANONICAL_DATE_FORMAT)
+                }),
+            _ => Err(RustqlError::TypeMismatch(format!(
+                "Cannot cast {:?} to TIMESTAMPTZ",
+                val
+            ))),
+        },
         DataType::Time => match &val {
             Value::Time(t) | Value::Text(t) => super::date::normalize_time(t)
                 .map(Value::Time)
                 .ok_or_else(|| temporal_cast_error(t, "TIME", super::date::CANONICAL_TIME_FORMAT)),
-            Value::DateTime(dt) => super::date::datetime_time_part(dt)
+            Value::DateTime(dt) | Value::TimestampTz(dt) => super::date::datetime_time_part(dt)
                 .map(Value::Time)
                 .ok_or_else(|| {
                     temporal_cast_error(dt, "TIME", super::date::CANONICAL_DATETIME_FORMAT)
@@ -101,6 +168,39 @@ pub(crate) fn coerce_value_for_type(
                 val
             ))),
         },
+        DataType::Interval => match &val {
+            Value::Interval(_) => Ok(val),
+            Value::Text(s) => Interval::parse(s).map(Value::Interval).ok_or_else(|| {
+                RustqlError::TypeMismatch(format!("Cannot cast '{}' to INTERVAL", s))
+            }),
+            _ => Err(RustqlError::TypeMismatch(format!(
+                "Cannot cast {:?} to INTERVAL",
+                val
+            ))),
+        },
+        DataType::Blob => match &val {
+            Value::Blob(_) => Ok(val),
+            Value::Text(s) => match s.strip_prefix("\\x") {
+                Some(hex) => crate::blob::decode_hex(hex)
+                    .map(Value::Blob)
+                    .ok_or_else(|| {
+                        RustqlError::TypeMismatch(format!(
+                            "Cannot cast '{}' to BLOB; expected hex digits after \\x",
+                            s
+                        ))
+                    }),
+                None => Ok(Value::Blob(s.as_bytes().to_vec())),
+            },
+            _ => Err(RustqlError::TypeMismatch(format!(
+                "Cannot cast {:?} to BLOB",
+                val
+            ))),
+        },
+        DataType::Json => match &val {
+            Value::Json(_) => Ok(val),
+            Value::Text(s) => crate::json::parse(s).map(|json| crate::json::to_value(&json)),
+            _ => Ok(crate::json::to_value(&crate::json::from_sql(&val))),
+        },
     }
 }
 
@@ -137,6 +237,13 @@ fn cast_float_to_integer(value: f64) -> Result<Value, RustqlError> {
     Ok(Value::Integer(truncated as i64))
 }
 
+fn decimal_range_error(value: &Value, target_type: &DataType) -> RustqlError {
+    RustqlError::TypeMismatch(format!(
+        "Cannot cast '{}' to {}; value does not fit its digits",
+        value, target_type
+    ))
+}
+
 fn temporal_cast_error(value: &str, target: &str, expected_format: &str) -> RustqlError {
     RustqlError::TypeMismatch(format!(
         "Cannot cast '{}' to {}; expected {}",
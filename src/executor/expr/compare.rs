@@ -1,4 +1,5 @@
 use super::*;
+use crate::decimal::Decimal;
 
 pub fn compare_values(
     left: &Value,
@@ -9,8 +10,25 @@ pub fn compare_values(
         return Ok(false);
     }
 
+    if let Some((l, r)) = decimal_operands(left, right) {
+        let ordering = l.cmp(&r);
+        return match op {
+            BinaryOperator::Equal => Ok(ordering == Ordering::Equal),
+            BinaryOperator::NotEqual => Ok(ordering != Ordering::Equal),
+            BinaryOperator::LessThan => Ok(ordering == Ordering::Less),
+            BinaryOperator::LessThanOrEqual => Ok(ordering != Ordering::Greater),
+            BinaryOperator::GreaterThan => Ok(ordering == Ordering::Greater),
+            BinaryOperator::GreaterThanOrEqual => Ok(ordering != Ordering::Less),
+            _ => Err(RustqlError::TypeMismatch(
+                "Invalid operator for numeric comparison".to_string(),
+            )),
+        };
+    }
+
     let (left_num, right_num) = match (left, right) {
         (Value::Integer(l), Value::Integer(r)) => (Some(*l as f64), Some(*r as f64)),
+        (Value::Decimal(l), Value::Float(r)) => (Some(l.to_f64()), Some(*r)),
+        (Value::Float(l), Value::Decimal(r)) => (Some(*l), Some(r.to_f64())),
         (Value::Float(l), Value::Float(r)) => (Some(*l), Some(*r)),
         (Value::Integer(l), Value::Float(r)) => (Some(*l as f64), Some(*r)),
         (Value::Float(l), Value::Integer(r)) => (Some(*l), Some(*r as f64)),
@@ -80,7 +98,12 @@ pub fn compare_values(
                     ));
                 }
             }),
-            (Value::DateTime(l), Value::DateTime(r), op) => Ok(match op {
+            // A DATETIME compares with a TIMESTAMPTZ as a time in UTC.
+            (
+                Value::DateTime(l) | Value::TimestampTz(l),
+                Value::DateTime(r) | Value::TimestampTz(r),
+                op,
+            ) => Ok(match op {
                 BinaryOperator::Equal => l == r,
                 BinaryOperator::NotEqual => l != r,
                 BinaryOperator::LessThan => l < r,
@@ -93,6 +116,41 @@ pub fn compare_values(
                     ));
                 }
             }),
+            (Value::Interval(l), Value::Interval(r), op) => Ok(match op {
+                BinaryOperator::Equal => l == r,
+                BinaryOperator::NotEqual => l != r,
+                BinaryOperator::LessThan => l < r,
+                BinaryOperator::LessThanOrEqual => l <= r,
+                BinaryOperator::GreaterThan => l > r,
+                BinaryOperator::GreaterThanOrEqual => l >= r,
+                _ => {
+                    return Err(RustqlError::TypeMismatch(
+                        "Invalid operator for intervals".to_string(),
+                    ));
+                }
+            }),
+            (Value::Blob(l), Value::Blob(r), op) => Ok(match op {
+                BinaryOperator::Equal => l == r,
+                BinaryOperator::NotEqual => l != r,
+                BinaryOperator::LessThan => l < r,
+                BinaryOperator::LessThanOrEqual => l <= r,
+                BinaryOperator::GreaterThan => l > r,
+                BinaryOperator::GreaterThanOrEqual => l >= r,
+                _ => {
+                    return Err(RustqlError::TypeMismatch(
+                        "Invalid operator for blobs".to_string(),
+                    ));
+                }
+            }),
+            (Value::Json(l), Value::Json(r), op) => Ok(match op {
+                BinaryOperator::Equal => l == r,
+                BinaryOperator::NotEqual => l != r,
+                _ => {
+                    return Err(RustqlError::TypeMismatch(
+                        "Invalid operator for JSON values".to_string(),
+                    ));
+                }
+            }),
             _ => Err(RustqlError::TypeMismatch(
                 "Type mismatch in comparison".to_string(),
             )),
@@ -113,10 +171,33 @@ pub fn apply_arithmetic(
         return Ok(Value::Null);
     }
 
+    if let Some(value) = super::date::apply_temporal_arithmetic(left, right, op)? {
+        return Ok(value);
+    }
+
+    if let Some((l, r)) = decimal_operands(left, right) {
+        if matches!(op, BinaryOperator::Divide) && r.is_zero() {
+            return Err(RustqlError::DivisionByZero);
+        }
+        let result = match op {
+            BinaryOperator::Plus => l.checked_add(&r),
+            BinaryOperator::Minus => l.checked_sub(&r),
+            BinaryOperator::Multiply => l.checked_mul(&r),
+            BinaryOperator::Divide => l.checked_div(&r),
+            _ => {
+                return Err(RustqlError::Internal(
+                    "Invalid arithmetic operator".to_string(),
+                ));
+            }
+        };
+        return result.map(Value::Decimal).ok_or_else(decimal_out_of_range);
+    }
+
     let to_float = |value: &Value| -> Result<f64, RustqlError> {
         match value {
             Value::Integer(i) => Ok(*i as f64),
             Value::Float(f) => Ok(*f),
+            Value::Decimal(d) => Ok(d.to_f64()),
             _ => Err(RustqlError::TypeMismatch(
                 "Arithmetic requires numeric values".to_string(),
             )),
@@ -176,11 +257,25 @@ pub fn compare_values_for_sort(left: &Value, right: &Value) -> Ordering {
         (Value::Float(l), Value::Float(r)) => compare_floats_for_sort(*l, *r),
         (Value::Integer(l), Value::Float(r)) => compare_floats_for_sort(*l as f64, *r),
         (Value::Float(l), Value::Integer(r)) => compare_floats_for_sort(*l, *r as f64),
+        (Value::Decimal(_), _) | (_, Value::Decimal(_))
+            if sort_rank(left) == 0 && sort_rank(right) == 0 =>
+        {
+            match decimal_operands(left, right) {
+                Some((l, r)) => l.cmp(&r),
+                None => compare_floats_for_sort(numeric_f64(left), numeric_f64(right)),
+            }
+        }
         (Value::Text(l), Value::Text(r)) => l.cmp(r),
         (Value::Boolean(l), Value::Boolean(r)) => l.cmp(r),
         (Value::Date(l), Value::Date(r)) => l.cmp(r),
         (Value::Time(l), Value::Time(r)) => l.cmp(r),
-        (Value::DateTime(l), Value::DateTime(r)) => l.cmp(r),
+        (
+            Value::DateTime(l) | Value::TimestampTz(l),
+            Value::DateTime(r) | Value::TimestampTz(r),
+        ) => l.cmp(r),
+        (Value::Interval(l), Value::Interval(r)) => l.cmp(r),
+        (Value::Blob(l), Value::Blob(r)) => l.cmp(r),
+        (Value::Json(l), Value::Json(r)) => l.cmp(r),
         _ => sort_rank(left).cmp(&sort_rank(right)),
     }
 }
@@ -199,13 +294,16 @@ pub fn compare_order_values(left: &Value, right: &Value, order_expr: &OrderByExp
 
 fn sort_rank(value: &Value) -> u8 {
     match value {
-        Value::Integer(_) | Value::Float(_) => 0,
+        Value::Integer(_) | Value::Float(_) | Value::Decimal(_) => 0,
         Value::Text(_) => 1,
         Value::Boolean(_) => 2,
         Value::Date(_) => 3,
         Value::Time(_) => 4,
-        Value::DateTime(_) => 5,
-        Value::Null => 6,
+        Value::DateTime(_) | Value::TimestampTz(_) => 5,
+        Value::Interval(_) => 6,
+        Value::Blob(_) => 7,
+        Value::Json(_) => 8,
+        Value::Null => 9,
     }
 }
 
@@ -221,3 +319,35 @@ fn compare_floats_for_sort(left: f64, right: f64) -> Ordering {
         _ => Ordering::Greater,
     }
 }
+
+/// Both operands as decimals when at least one is a decimal and the other
+/// is a number a decimal can hold. A float counts as the decimal it
+/// displays as, so `price = 19.99` compares exactly.
+fn decimal_operands(left: &Value, right: &Value) -> Option<(Decimal, Decimal)> {
+    if !matches!(left, Value::Decimal(_)) && !matches!(right, Value::Decimal(_)) {
+        return None;
+    }
+    Some((as_decimal(left)?, as_decimal(right)?))
+}
+
+fn as_decimal(value: &Value) -> Option<Decimal> {
+    match value {
+        Value::Decimal(d) => Some(*d),
+        Value::Integer(i) => Some(Decimal::from_i64(*i)),
+        Value::Float(f) => Decimal::from_f64(*f),
+        _ => None,
+    }
+}
+
+fn numeric_f64(value: &Value) -> f64 {
+    match value {
+        Value::Integer(i) => *i as f64,
+        Value::Float(f) => *f,
+        Value::Decimal(d) => d.to_f64(),
+        _ => f64::NAN,
+    }
+}
+
+pub(crate) fn decimal_out_of_range() -> RustqlError {
+    RustqlError::TypeMismatch("Decimal value out of range".to_string())
+}
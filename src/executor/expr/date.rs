@@ -1,23 +1,23 @@
+use crate::ast::{BinaryOperator, Value};
+use crate::error::RustqlError;
+use crate::interval::{Interval, MICROS_PER_DAY, MICROS_PER_SECOND};
+
 pub(super) const CANONICAL_DATE_FORMAT: &str = "YYYY-MM-DD";
-pub(super) const CANONICAL_TIME_FORMAT: &str = "HH:MM:SS";
-pub(super) const CANONICAL_DATETIME_FORMAT: &str = "YYYY-MM-DD HH:MM:SS";
+pub(super) const CANONICAL_TIME_FORMAT: &str = "HH:MM:SS[.ffffff]";
+pub(super) const CANONICAL_DATETIME_FORMAT: &str = "YYYY-MM-DD HH:MM:SS[.ffffff]";
+pub(super) const CANONICAL_TIMESTAMPTZ_FORMAT: &str =
+    "YYYY-MM-DD HH:MM:SS[.ffffff] with an optional UTC offset or zone name";
 
 pub(super) fn normalize_date(s: &str) -> Option<String> {
     parse_canonical_date(s).map(|(y, m, d)| format!("{:04}-{:02}-{:02}", y, m, d))
 }
 
 pub(super) fn normalize_time(s: &str) -> Option<String> {
-    parse_canonical_time(s).map(|(h, m, sec)| format!("{:02}:{:02}:{:02}", h, m, sec))
+    time_micros(s).map(micros_to_time)
 }
 
 pub(super) fn normalize_datetime(s: &str) -> Option<String> {
-    let (date_part, time_part) = s.split_once(' ')?;
-    let (y, month, day) = parse_canonical_date(date_part)?;
-    let (hour, minute, second) = parse_canonical_time(time_part)?;
-    Some(format!(
-        "{:04}-{:02}-{:02} {:02}:{:02}:{:02}",
-        y, month, day, hour, minute, second
-    ))
+    datetime_micros(s).and_then(micros_to_datetime)
 }
 
 pub(super) fn datetime_date_part(s: &str) -> Option<String> {
@@ -34,7 +34,7 @@ pub(super) fn parse_date_components(s: &str) -> Option<(i64, i64, i64)> {
     }
 
     let (date_part, time_part) = s.split_once(' ')?;
-    parse_canonical_time(time_part)?;
+    time_micros(time_part)?;
     parse_canonical_date(date_part)
 }
 
@@ -58,8 +58,14 @@ fn parse_canonical_date(s: &str) -> Option<(i64, i64, i64)> {
     Some((y, m, d))
 }
 
-fn parse_canonical_time(s: &str) -> Option<(i64, i64, i64)> {
-    let bytes = s.as_bytes();
+/// Microseconds since midnight for `HH:MM:SS` with an optional fraction
+/// of one to six digits.
+fn time_micros(s: &str) -> Option<i64> {
+    let (clock, fraction) = match s.split_once('.') {
+        Some((clock, fraction)) => (clock, Some(fraction)),
+        None => (s, None),
+    };
+    let bytes = clock.as_bytes();
     if bytes.len() != 8 || bytes[2] != b':' || bytes[5] != b':' {
         return None;
     }
@@ -72,10 +78,27 @@ fn parse_canonical_time(s: &str) -> Option<(i64, i64, i64)> {
         return None;
     }
 
-    Some((h, m, sec))
+    let fraction = match fraction {
+        None => 0,
+        Some(digits) if (1..=6).contains(&digits.len()) => {
+            parse_fixed_digits(digits.as_bytes())? as i64 * 10i64.pow(6 - digits.len() as u32)
+        }
+        Some(_) => return None,
+    };
+
+    Some(((h * 60 + m) * 60 + sec) * MICROS_PER_SECOND + fraction)
 }
 
-fn parse_fixed_digits(bytes: &[u8]) -> Option<u64> {
+/// Microseconds since 1970-01-01 00:00:00 for DATETIME text.
+pub(super) fn datetime_micros(s: &str) -> Option<i64> {
+    let (date, time) = s.split_once(' ')?;
+    let (y, m, d) = parse_canonical_date(date)?;
+    ymd_to_days(y, m, d)
+        .checked_mul(MICROS_PER_DAY)?
+        .checked_add(time_micros(time)?)
+}
+
+pub(super) fn parse_fixed_digits(bytes: &[u8]) -> Option<u64> {
     let mut value = 0u64;
     for byte in bytes {
         if !byte.is_ascii_digit() {
@@ -86,7 +109,7 @@ fn parse_fixed_digits(bytes: &[u8]) -> Option<u64> {
     Some(value)
 }
 
-fn days_in_month(year: i64, month: i64) -> i64 {
+pub(super) fn days_in_month(year: i64, month: i64) -> i64 {
     match month {
         1 | 3 | 5 | 7 | 8 | 10 | 12 => 31,
         4 | 6 | 9 | 11 => 30,
@@ -131,3 +154,301 @@ pub(super) fn days_to_ymd(days: i64) -> (i64, i64, i64) {
     let y = if m <= 2 { y + 1 } else { y };
     (y, m, d)
 }
+
+/// The current UTC time as `YYYY-MM-DD HH:MM:SS`.
+pub(crate) fn current_datetime() -> String {
+    let micros = current_micros();
+    micros_to_datetime(micros - micros % MICROS_PER_SECOND).unwrap_or_default()
+}
+
+/// The current UTC time with its fraction of a second, as `NOW()` reports.
+pub(super) fn current_timestamp() -> String {
+    micros_to_datetime(current_micros()).unwrap_or_default()
+}
+
+fn current_micros() -> i64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_micros() as i64
+}
+
+/// Microseconds since 1970-01-01 00:00:00 UTC for a DATE, DATETIME or
+/// TIMESTAMPTZ value.
+pub(crate) fn temporal_micros(value: &Value) -> Option<i64> {
+    match value {
+        Value::Date(date) => {
+            let (y, m, d) = parse_canonical_date(date)?;
+            ymd_to_days(y, m, d).checked_mul(MICROS_PER_DAY)
+        }
+        Value::DateTime(datetime) | Value::TimestampTz(datetime) => datetime_micros(datetime),
+        _ => None,
+    }
+}
+
+/// The DATETIME text for microseconds since the epoch, or `None` outside
+/// years 0 to 9999.
+pub(crate) fn micros_to_datetime(micros: i64) -> Option<String> {
+    let (y, m, d) = days_to_ymd(micros.div_euclid(MICROS_PER_DAY));
+    if !(0..=9999).contains(&y) {
+        return None;
+    }
+    Some(format!(
+        "{:04}-{:02}-{:02} {}",
+        y,
+        m,
+        d,
+        micros_to_time(micros)
+    ))
+}
+
+/// The TIME text for the time of day of `micros`, with the fraction of a
+/// second only when it is not zero and without trailing zeros.
+fn micros_to_time(micros: i64) -> String {
+    let micros = micros.rem_euclid(MICROS_PER_DAY);
+    let seconds = micros / MICROS_PER_SECOND;
+    let mut time = format!(
+        "{:02}:{:02}:{:02}",
+        seconds / 3600,
+        (seconds % 3600) / 60,
+        seconds % 60
+    );
+    let fraction = micros % MICROS_PER_SECOND;
+    if fraction != 0 {
+        time.push_str(format!(".{:06}", fraction).trim_end_matches('0'));
+    }
+    time
+}
+
+fn days_to_date(days: i64) -> Option<String> {
+    let (y, m, d) = days_to_ymd(days);
+    (0..=9999)
+        .contains(&y)
+        .then(|| format!("{:04}-{:02}-{:02}", y, m, d))
+}
+
+/// `micros` moved by `interval`: months first, keeping the day of the
+/// month unless the target month is shorter, then days, then time.
+pub(crate) fn add_interval(micros: i64, interval: &Interval) -> Option<i64> {
+    let days = micros.div_euclid(MICROS_PER_DAY);
+    let time = micros.rem_euclid(MICROS_PER_DAY);
+    let (y, m, d) = days_to_ymd(days);
+    let month_index = (y * 12 + m - 1).checked_add(i64::from(interval.months()))?;
+    let (y, m) = (month_index.div_euclid(12), month_index.rem_euclid(12) + 1);
+    let days = ymd_to_days(y, m, d.min(days_in_month(y, m))) + i64::from(interval.days());
+    days.checked_mul(MICROS_PER_DAY)?
+        .checked_add(time)?
+        .checked_add(interval.micros())
+}
+
+/// The symbolic difference `later - earlier` that `AGE` reports: years,
+/// months and days counted on the calendar rather than as 30-day months.
+pub(super) fn age(later: i64, earlier: i64) -> Option<Interval> {
+    let negative = later < earlier;
+    let fields = |micros: i64| {
+        let (y, m, d) = days_to_ymd(micros.div_euclid(MICROS_PER_DAY));
+        (y, m, d, micros.rem_euclid(MICROS_PER_DAY))
+    };
+    let (y1, m1, d1, t1) = fields(later);
+    let (y2, m2, d2, t2) = fields(earlier);
+    let sign = if negative { -1 } else { 1 };
+    let (mut years, mut months, mut days, mut time) = (
+        (y1 - y2) * sign,
+        (m1 - m2) * sign,
+        (d1 - d2) * sign,
+        (t1 - t2) * sign,
+    );
+
+    while time < 0 {
+        time += MICROS_PER_DAY;
+        days -= 1;
+    }
+    // A borrowed month is as long as the month of the earlier timestamp.
+    let (borrow_year, borrow_month) = if negative { (y1, m1) } else { (y2, m2) };
+    while days < 0 {
+        days += days_in_month(borrow_year, borrow_month);
+        months -= 1;
+    }
+    while months < 0 {
+        months += 12;
+        years -= 1;
+    }
+
+    Some(Interval::new(
+        i32::try_from((years * 12 + months) * sign).ok()?,
+        i32::try_from(days * sign).ok()?,
+        time * sign,
+    ))
+}
+
+/// The result of `+`, `-`, `*` or `/` when either operand is a date,
+/// time or interval, or `None` when neither is so the numeric rules
+/// apply.
+pub(super) fn apply_temporal_arithmetic(
+    left: &Value,
+    right: &Value,
+    op: &BinaryOperator,
+) -> Result<Option<Value>, RustqlError> {
+    let out_of_range = || RustqlError::TypeMismatch("Date/time value out of range".to_string());
+    let interval_out_of_range =
+        || RustqlError::TypeMismatch("Interval value out of range".to_string());
+    let invalid = || {
+        RustqlError::TypeMismatch(format!(
+            "Cannot apply {} to {} and {}",
+            match op {
+                BinaryOperator::Plus => "+",
+                BinaryOperator::Minus => "-",
+                BinaryOperator::Multiply => "*",
+                _ => "/",
+            },
+            describe(left),
+            describe(right)
+        ))
+    };
+    let date_days = |date: &str| {
+        parse_canonical_date(date)
+            .map(|(y, m, d)| ymd_to_days(y, m, d))
+            .ok_or_else(|| RustqlError::TypeMismatch(format!("Invalid date '{}'", date)))
+    };
+    let micros = |value: &Value| {
+        temporal_micros(value).ok_or_else(|| {
+            RustqlError::TypeMismatch(format!("Invalid date/time value '{}'", value))
+        })
+    };
+    let time = |value: &str| {
+        time_micros(value)
+            .ok_or_else(|| RustqlError::TypeMismatch(format!("Invalid time '{}'", value)))
+    };
+
+    let value = match (left, op, right) {
+        (Value::Date(date), BinaryOperator::Plus | BinaryOperator::Minus, Value::Integer(n))
+        | (Value::Integer(n), BinaryOperator::Plus, Value::Date(date)) => {
+            let offset = if matches!(op, BinaryOperator::Minus) {
+                n.checked_neg().ok_or_else(out_of_range)?
+            } else {
+                *n
+            };
+            let days = date_days(date)?
+                .checked_add(offset)
+                .ok_or_else(out_of_range)?;
+            Value::Date(days_to_date(days).ok_or_else(out_of_range)?)
+        }
+        (Value::Date(l), BinaryOperator::Minus, Value::Date(r)) => {
+            Value::Integer(date_days(l)? - date_days(r)?)
+        }
+        (
+            Value::Date(_) | Value::DateTime(_) | Value::TimestampTz(_),
+            BinaryOperator::Plus | BinaryOperator::Minus,
+            Value::Interval(interval),
+        ) => {
+            let interval = if matches!(op, BinaryOperator::Minus) {
+                interval.checked_neg().ok_or_else(interval_out_of_range)?
+            } else {
+                *interval
+            };
+            let moved = add_interval(micros(left)?, &interval).ok_or_else(out_of_range)?;
+            let moved = micros_to_datetime(moved).ok_or_else(out_of_range)?;
+            match left {
+                Value::TimestampTz(_) => Value::TimestampTz(moved),
+                _ => Value::DateTime(moved),
+            }
+        }
+        (
+            Value::Interval(interval),
+            BinaryOperator::Plus,
+            Value::Date(_) | Value::DateTime(_) | Value::TimestampTz(_),
+        ) => {
+            let moved = add_interval(micros(right)?, interval).ok_or_else(out_of_range)?;
+            let moved = micros_to_datetime(moved).ok_or_else(out_of_range)?;
+            match right {
+                Value::TimestampTz(_) => Value::TimestampTz(moved),
+                _ => Value::DateTime(moved),
+            }
+        }
+        (
+            Value::Date(_) | Value::DateTime(_) | Value::TimestampTz(_),
+            BinaryOperator::Minus,
+            Value::Date(_) | Value::DateTime(_) | Value::TimestampTz(_),
+        ) => Value::Interval(
+            Interval::new(0, 0, micros(left)? - micros(right)?)
+                .justify_hours()
+                .ok_or_else(interval_out_of_range)?,
+        ),
+        (
+            Value::Time(t),
+            BinaryOperator::Plus | BinaryOperator::Minus,
+            Value::Interval(interval),
+        )
+        | (Value::Interval(interval), BinaryOperator::Plus, Value::Time(t)) => {
+            let offset = interval.micros() % MICROS_PER_DAY;
+            let offset = if matches!(op, BinaryOperator::Minus) {
+                -offset
+            } else {
+                offset
+            };
+            Value::Time(micros_to_time(time(t)? + offset))
+        }
+        (Value::Time(l), BinaryOperator::Minus, Value::Time(r)) => {
+            Value::Interval(Interval::new(0, 0, time(l)? - time(r)?))
+        }
+        (Value::Interval(l), BinaryOperator::Plus, Value::Interval(r)) => {
+            Value::Interval(l.checked_add(r).ok_or_else(interval_out_of_range)?)
+        }
+        (Value::Interval(l), BinaryOperator::Minus, Value::Interval(r)) => {
+            Value::Interval(l.checked_sub(r).ok_or_else(interval_out_of_range)?)
+        }
+        (Value::Interval(interval), BinaryOperator::Multiply, factor)
+        | (factor, BinaryOperator::Multiply, Value::Interval(interval)) => {
+            let product = match factor {
+                Value::Integer(n) => interval.checked_mul_integer(*n),
+                Value::Float(f) => interval.checked_mul_float(*f),
+                Value::Decimal(d) => interval.checked_mul_float(d.to_f64()),
+                _ => return Err(invalid()),
+            };
+            Value::Interval(product.ok_or_else(interval_out_of_range)?)
+        }
+        (Value::Interval(interval), BinaryOperator::Divide, divisor) => {
+            let divisor = match divisor {
+                Value::Integer(n) => *n as f64,
+                Value::Float(f) => *f,
+                Value::Decimal(d) => d.to_f64(),
+                _ => return Err(invalid()),
+            };
+            if divisor == 0.0 {
+                return Err(RustqlError::DivisionByZero);
+            }
+            Value::Interval(
+                interval
+                    .checked_div_float(divisor)
+                    .ok_or_else(interval_out_of_range)?,
+            )
+        }
+        _ if [left, right].iter().any(|value| {
+            matches!(
+                value,
+                Value::Date(_)
+                    | Value::Time(_)
+                    | Value::DateTime(_)
+                    | Value::TimestampTz(_)
+                    | Value::Interval(_)
+            )
+        }) =>
+        {
+            return Err(invalid());
+        }
+        _ => return Ok(None),
+    };
+    Ok(Some(value))
+}
+
+fn describe(value: &Value) -> &'static str {
+    match value {
+        Value::Date(_) => "DATE",
+        Value::Time(_) => "TIME",
+        Value::DateTime(_) => "DATETIME",
+        Value::TimestampTz(_) => "TIMESTAMPTZ",
+        Value::Interval(_) => "INTERVAL",
+        Value::Integer(_) | Value::Float(_) | Value::Decimal(_) => "a number",
+        _ => "a non-numeric value",
+    }
+}
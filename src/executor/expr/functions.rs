@@ -1,5 +1,11 @@
-use super::date::{days_to_ymd, parse_date_components, ymd_to_days};
+use super::date::{
+    age, current_datetime, current_timestamp, days_to_ymd, parse_date_components, temporal_micros,
+    ymd_to_days,
+};
 use super::*;
+use crate::decimal::Decimal;
+use crate::json;
+use serde_json::Value as JsonValue;
 
 pub(super) fn evaluate_scalar_function(
     name: &ScalarFunctionType,
@@ -29,48 +35,33 @@ pub(super) fn evaluate_scalar_function(
         },
         ScalarFunctionType::Length => match evaluated_args.first() {
             Some(Value::Text(s)) => Ok(Value::Integer(s.len() as i64)),
+            Some(Value::Blob(bytes)) => Ok(Value::Integer(bytes.len() as i64)),
             Some(Value::Null) => Ok(Value::Null),
             _ => Err(RustqlError::TypeMismatch(
-                "LENGTH requires a text argument".to_string(),
+                "LENGTH requires a text or blob argument".to_string(),
             )),
         },
-        ScalarFunctionType::Substring => {
-            let s = match evaluated_args.first() {
-                Some(Value::Text(s)) => s.clone(),
-                Some(Value::Null) => return Ok(Value::Null),
-                _ => {
-                    return Err(RustqlError::TypeMismatch(
-                        "SUBSTRING requires a text first argument".to_string(),
-                    ));
-                }
-            };
-            let start = match evaluated_args.get(1) {
-                Some(Value::Integer(i)) => (*i as usize).saturating_sub(1),
-                _ => {
-                    return Err(RustqlError::TypeMismatch(
-                        "SUBSTRING requires an integer start position".to_string(),
-                    ));
-                }
-            };
-            let chars: Vec<char> = s.chars().collect();
-            if start >= chars.len() {
-                return Ok(Value::Text(String::new()));
+        ScalarFunctionType::Substring => match evaluated_args.first() {
+            Some(Value::Text(s)) => {
+                let chars: Vec<char> = s.chars().collect();
+                let (start, len) = substring_bounds(&evaluated_args, chars.len())?;
+                Ok(Value::Text(chars.iter().skip(start).take(len).collect()))
             }
-            let len = match evaluated_args.get(2) {
-                Some(Value::Integer(l)) => *l as usize,
-                None => chars.len() - start,
-                _ => {
-                    return Err(RustqlError::TypeMismatch(
-                        "SUBSTRING length must be an integer".to_string(),
-                    ));
-                }
-            };
-            let result: String = chars.iter().skip(start).take(len).collect();
-            Ok(Value::Text(result))
-        }
+            Some(Value::Blob(bytes)) => {
+                let (start, len) = substring_bounds(&evaluated_args, bytes.len())?;
+                Ok(Value::Blob(
+                    bytes.iter().skip(start).take(len).copied().collect(),
+                ))
+            }
+            Some(Value::Null) => Ok(Value::Null),
+            _ => Err(RustqlError::TypeMismatch(
+                "SUBSTRING requires a text or blob first argument".to_string(),
+            )),
+        },
         ScalarFunctionType::Abs => match evaluated_args.first() {
             Some(Value::Integer(i)) => Ok(Value::Integer(i.abs())),
             Some(Value::Float(f)) => Ok(Value::Float(f.abs())),
+            Some(Value::Decimal(d)) => Ok(Value::Decimal(d.abs())),
             Some(Value::Null) => Ok(Value::Null),
             _ => Err(RustqlError::TypeMismatch(
                 "ABS requires a numeric argument".to_string(),
@@ -79,6 +70,7 @@ pub(super) fn evaluate_scalar_function(
         ScalarFunctionType::Round => {
             let val = match evaluated_args.first() {
                 Some(Value::Float(f)) => *f,
+                Some(Value::Decimal(d)) => d.to_f64(),
                 Some(Value::Integer(i)) => *i as f64,
                 Some(Value::Null) => return Ok(Value::Null),
                 _ => {
@@ -96,6 +88,12 @@ pub(super) fn evaluate_scalar_function(
                     ));
                 }
             };
+            if let Some(Value::Decimal(d)) = evaluated_args.first() {
+                return d
+                    .round_to(decimals)
+                    .map(Value::Decimal)
+                    .ok_or_else(decimal_out_of_range);
+            }
             let factor = 10f64.powi(decimals);
             Ok(Value::Float((val * factor).round() / factor))
         }
@@ -202,6 +200,7 @@ pub(super) fn evaluate_scalar_function(
         }
         ScalarFunctionType::Ceil => match evaluated_args.first() {
             Some(Value::Float(f)) => Ok(Value::Integer(f.ceil() as i64)),
+            Some(Value::Decimal(d)) => decimal_to_integer(d.ceil()),
             Some(Value::Integer(i)) => Ok(Value::Integer(*i)),
             Some(Value::Null) => Ok(Value::Null),
             _ => Err(RustqlError::TypeMismatch(
@@ -210,6 +209,7 @@ pub(super) fn evaluate_scalar_function(
         },
         ScalarFunctionType::Floor => match evaluated_args.first() {
             Some(Value::Float(f)) => Ok(Value::Integer(f.floor() as i64)),
+            Some(Value::Decimal(d)) => decimal_to_integer(d.floor()),
             Some(Value::Integer(i)) => Ok(Value::Integer(*i)),
             Some(Value::Null) => Ok(Value::Null),
             _ => Err(RustqlError::TypeMismatch(
@@ -236,6 +236,15 @@ pub(super) fn evaluate_scalar_function(
                     Ok(Value::Float(f.sqrt()))
                 }
             }
+            Some(Value::Decimal(d)) => {
+                if d.mantissa() < 0 {
+                    Err(RustqlError::TypeMismatch(
+                        "SQRT of a negative number".to_string(),
+                    ))
+                } else {
+                    Ok(Value::Float(d.to_f64().sqrt()))
+                }
+            }
             Some(Value::Null) => Ok(Value::Null),
             _ => Err(RustqlError::TypeMismatch(
                 "SQRT requires a numeric argument".to_string(),
@@ -244,6 +253,7 @@ pub(super) fn evaluate_scalar_function(
         ScalarFunctionType::Power => {
             let base = match evaluated_args.first() {
                 Some(Value::Float(f)) => *f,
+                Some(Value::Decimal(d)) => d.to_f64(),
                 Some(Value::Integer(i)) => *i as f64,
                 Some(Value::Null) => return Ok(Value::Null),
                 _ => {
@@ -254,6 +264,7 @@ pub(super) fn evaluate_scalar_function(
             };
             let exp = match evaluated_args.get(1) {
                 Some(Value::Float(f)) => *f,
+                Some(Value::Decimal(d)) => d.to_f64(),
                 Some(Value::Integer(i)) => *i as f64,
                 Some(Value::Null) => return Ok(Value::Null),
                 _ => {
@@ -289,25 +300,9 @@ pub(super) fn evaluate_scalar_function(
                 Ok(Value::Integer(left % right))
             }
         }
-        ScalarFunctionType::Now => {
-            let now = std::time::SystemTime::now()
-                .duration_since(std::time::UNIX_EPOCH)
-                .unwrap_or_default();
-            let secs = now.as_secs();
-            let days = secs / 86400;
-            let remaining = secs % 86400;
-            let hours = remaining / 3600;
-            let minutes = (remaining % 3600) / 60;
-            let seconds = remaining % 60;
-
-            let (year, month, day) = days_to_ymd(days as i64);
-            Ok(Value::DateTime(format!(
-                "{:04}-{:02}-{:02} {:02}:{:02}:{:02}",
-                year, month, day, hours, minutes, seconds
-            )))
-        }
+        ScalarFunctionType::Now => Ok(Value::TimestampTz(current_timestamp())),
         ScalarFunctionType::Year => match evaluated_args.first() {
-            Some(Value::Date(d)) | Some(Value::DateTime(d)) | Some(Value::Text(d)) => {
+            Some(Value::Date(d) | Value::DateTime(d) | Value::TimestampTz(d) | Value::Text(d)) => {
                 match parse_date_components(d) {
                     Some((y, _, _)) => Ok(Value::Integer(y)),
                     None => Err(RustqlError::TypeMismatch(
@@ -321,7 +316,7 @@ pub(super) fn evaluate_scalar_function(
             )),
         },
         ScalarFunctionType::Month => match evaluated_args.first() {
-            Some(Value::Date(d)) | Some(Value::DateTime(d)) | Some(Value::Text(d)) => {
+            Some(Value::Date(d) | Value::DateTime(d) | Value::TimestampTz(d) | Value::Text(d)) => {
                 match parse_date_components(d) {
                     Some((_, m, _)) => Ok(Value::Integer(m)),
                     None => Err(RustqlError::TypeMismatch(
@@ -335,7 +330,7 @@ pub(super) fn evaluate_scalar_function(
             )),
         },
         ScalarFunctionType::Day => match evaluated_args.first() {
-            Some(Value::Date(d)) | Some(Value::DateTime(d)) | Some(Value::Text(d)) => {
+            Some(Value::Date(d) | Value::DateTime(d) | Value::TimestampTz(d) | Value::Text(d)) => {
                 match parse_date_components(d) {
                     Some((_, _, d)) => Ok(Value::Integer(d)),
                     None => Err(RustqlError::TypeMismatch(
@@ -350,7 +345,9 @@ pub(super) fn evaluate_scalar_function(
         },
         ScalarFunctionType::DateAdd => {
             let date_str = match evaluated_args.first() {
-                Some(Value::Date(d)) | Some(Value::DateTime(d)) | Some(Value::Text(d)) => d.clone(),
+                Some(
+                    Value::Date(d) | Value::DateTime(d) | Value::TimestampTz(d) | Value::Text(d),
+                ) => d.clone(),
                 Some(Value::Null) => return Ok(Value::Null),
                 _ => {
                     return Err(RustqlError::TypeMismatch(
@@ -376,7 +373,9 @@ pub(super) fn evaluate_scalar_function(
         }
         ScalarFunctionType::Datediff => {
             let date1_str = match evaluated_args.first() {
-                Some(Value::Date(d)) | Some(Value::DateTime(d)) | Some(Value::Text(d)) => d.clone(),
+                Some(
+                    Value::Date(d) | Value::DateTime(d) | Value::TimestampTz(d) | Value::Text(d),
+                ) => d.clone(),
                 Some(Value::Null) => return Ok(Value::Null),
                 _ => {
                     return Err(RustqlError::TypeMismatch(
@@ -385,7 +384,9 @@ pub(super) fn evaluate_scalar_function(
                 }
             };
             let date2_str = match evaluated_args.get(1) {
-                Some(Value::Date(d)) | Some(Value::DateTime(d)) | Some(Value::Text(d)) => d.clone(),
+                Some(
+                    Value::Date(d) | Value::DateTime(d) | Value::TimestampTz(d) | Value::Text(d),
+                ) => d.clone(),
                 Some(Value::Null) => return Ok(Value::Null),
                 _ => {
                     return Err(RustqlError::TypeMismatch(
@@ -605,6 +606,7 @@ pub(super) fn evaluate_scalar_function(
             if evaluated_args.len() == 1 {
                 match evaluated_args.first() {
                     Some(Value::Float(f)) => Ok(Value::Float(f.ln())),
+                    Some(Value::Decimal(d)) => Ok(Value::Float(d.to_f64().ln())),
                     Some(Value::Integer(i)) => Ok(Value::Float((*i as f64).ln())),
                     Some(Value::Null) => Ok(Value::Null),
                     _ => Err(RustqlError::TypeMismatch(
@@ -614,6 +616,7 @@ pub(super) fn evaluate_scalar_function(
             } else {
                 let base = match evaluated_args.first() {
                     Some(Value::Float(f)) => *f,
+                    Some(Value::Decimal(d)) => d.to_f64(),
                     Some(Value::Integer(i)) => *i as f64,
                     Some(Value::Null) => return Ok(Value::Null),
                     _ => {
@@ -624,6 +627,7 @@ pub(super) fn evaluate_scalar_function(
                 };
                 let x = match evaluated_args.get(1) {
                     Some(Value::Float(f)) => *f,
+                    Some(Value::Decimal(d)) => d.to_f64(),
                     Some(Value::Integer(i)) => *i as f64,
                     Some(Value::Null) => return Ok(Value::Null),
                     _ => {
@@ -637,6 +641,7 @@ pub(super) fn evaluate_scalar_function(
         }
         ScalarFunctionType::Exp => match evaluated_args.first() {
             Some(Value::Float(f)) => Ok(Value::Float(f.exp())),
+            Some(Value::Decimal(d)) => Ok(Value::Float(d.to_f64().exp())),
             Some(Value::Integer(i)) => Ok(Value::Float((*i as f64).exp())),
             Some(Value::Null) => Ok(Value::Null),
             _ => Err(RustqlError::TypeMismatch(
@@ -653,6 +658,7 @@ pub(super) fn evaluate_scalar_function(
                     Ok(Value::Integer(0))
                 }
             }
+            Some(Value::Decimal(d)) => Ok(Value::Integer(d.mantissa().signum() as i64)),
             Some(Value::Float(f)) => {
                 if *f > 0.0 {
                     Ok(Value::Integer(1))
@@ -677,7 +683,9 @@ pub(super) fn evaluate_scalar_function(
                 }
             };
             let date_str = match evaluated_args.get(1) {
-                Some(Value::Date(d)) | Some(Value::DateTime(d)) | Some(Value::Text(d)) => d.clone(),
+                Some(
+                    Value::Date(d) | Value::DateTime(d) | Value::TimestampTz(d) | Value::Text(d),
+                ) => d.clone(),
                 Some(Value::Null) => return Ok(Value::Null),
                 _ => {
                     return Err(RustqlError::TypeMismatch(
@@ -707,7 +715,9 @@ pub(super) fn evaluate_scalar_function(
                 }
             };
             let date_str = match evaluated_args.get(1) {
-                Some(Value::Date(d)) | Some(Value::DateTime(d)) | Some(Value::Text(d)) => d.clone(),
+                Some(
+                    Value::Date(d) | Value::DateTime(d) | Value::TimestampTz(d) | Value::Text(d),
+                ) => d.clone(),
                 Some(Value::Null) => return Ok(Value::Null),
                 _ => {
                     return Err(RustqlError::TypeMismatch(
@@ -734,7 +744,8 @@ pub(super) fn evaluate_scalar_function(
                     })?;
                     Ok(Value::Integer(d))
                 }
-                "hour" | "minute" | "second" => {
+                "hour" | "minute" | "second" | "millisecond" | "milliseconds" | "microsecond"
+                | "microseconds" => {
                     let time_part = if let Some(space_pos) = date_str.find(' ') {
                         &date_str[space_pos + 1..]
                     } else {
@@ -759,10 +770,25 @@ pub(super) fn evaluate_scalar_function(
                         "second" => {
                             let s = parts
                                 .get(2)
+                                .and_then(|s| s.split('.').next())
                                 .and_then(|s| s.parse::<i64>().ok())
                                 .unwrap_or(0);
                             Ok(Value::Integer(s))
                         }
+                        // Like PostgreSQL, these count the seconds as well
+                        // as their fraction.
+                        "millisecond" | "milliseconds" | "microsecond" | "microseconds" => {
+                            let (whole, fraction) = parts
+                                .get(2)
+                                .map_or(("0", ""), |s| s.split_once('.').unwrap_or((s, "")));
+                            let micros = whole.parse::<i64>().unwrap_or(0) * 1_000_000
+                                + format!("{:0<6}", fraction)[..6].parse::<i64>().unwrap_or(0);
+                            Ok(Value::Integer(if part.starts_with("milli") {
+                                micros / 1000
+                            } else {
+                                micros
+                            }))
+                        }
                         _ => Err(RustqlError::TypeMismatch(format!(
                             "Unsupported EXTRACT part '{}'",
                             part
@@ -831,6 +857,7 @@ pub(super) fn evaluate_scalar_function(
         },
         ScalarFunctionType::Sin => match evaluated_args.first() {
             Some(Value::Float(f)) => Ok(Value::Float(f.sin())),
+            Some(Value::Decimal(d)) => Ok(Value::Float(d.to_f64().sin())),
             Some(Value::Integer(i)) => Ok(Value::Float((*i as f64).sin())),
             Some(Value::Null) => Ok(Value::Null),
             _ => Err(RustqlError::TypeMismatch(
@@ -839,6 +866,7 @@ pub(super) fn evaluate_scalar_function(
         },
         ScalarFunctionType::Cos => match evaluated_args.first() {
             Some(Value::Float(f)) => Ok(Value::Float(f.cos())),
+            Some(Value::Decimal(d)) => Ok(Value::Float(d.to_f64().cos())),
             Some(Value::Integer(i)) => Ok(Value::Float((*i as f64).cos())),
             Some(Value::Null) => Ok(Value::Null),
             _ => Err(RustqlError::TypeMismatch(
@@ -847,6 +875,7 @@ pub(super) fn evaluate_scalar_function(
         },
         ScalarFunctionType::Tan => match evaluated_args.first() {
             Some(Value::Float(f)) => Ok(Value::Float(f.tan())),
+            Some(Value::Decimal(d)) => Ok(Value::Float(d.to_f64().tan())),
             Some(Value::Integer(i)) => Ok(Value::Float((*i as f64).tan())),
             Some(Value::Null) => Ok(Value::Null),
             _ => Err(RustqlError::TypeMismatch(
@@ -855,6 +884,7 @@ pub(super) fn evaluate_scalar_function(
         },
         ScalarFunctionType::Asin => match evaluated_args.first() {
             Some(Value::Float(f)) => Ok(Value::Float(f.asin())),
+            Some(Value::Decimal(d)) => Ok(Value::Float(d.to_f64().asin())),
             Some(Value::Integer(i)) => Ok(Value::Float((*i as f64).asin())),
             Some(Value::Null) => Ok(Value::Null),
             _ => Err(RustqlError::TypeMismatch(
@@ -863,6 +893,7 @@ pub(super) fn evaluate_scalar_function(
         },
         ScalarFunctionType::Acos => match evaluated_args.first() {
             Some(Value::Float(f)) => Ok(Value::Float(f.acos())),
+            Some(Value::Decimal(d)) => Ok(Value::Float(d.to_f64().acos())),
             Some(Value::Integer(i)) => Ok(Value::Float((*i as f64).acos())),
             Some(Value::Null) => Ok(Value::Null),
             _ => Err(RustqlError::TypeMismatch(
@@ -871,6 +902,7 @@ pub(super) fn evaluate_scalar_function(
         },
         ScalarFunctionType::Atan => match evaluated_args.first() {
             Some(Value::Float(f)) => Ok(Value::Float(f.atan())),
+            Some(Value::Decimal(d)) => Ok(Value::Float(d.to_f64().atan())),
             Some(Value::Integer(i)) => Ok(Value::Float((*i as f64).atan())),
             Some(Value::Null) => Ok(Value::Null),
             _ => Err(RustqlError::TypeMismatch(
@@ -880,6 +912,7 @@ pub(super) fn evaluate_scalar_function(
         ScalarFunctionType::Atan2 => {
             let y = match evaluated_args.first() {
                 Some(Value::Float(f)) => *f,
+                Some(Value::Decimal(d)) => d.to_f64(),
                 Some(Value::Integer(i)) => *i as f64,
                 Some(Value::Null) => return Ok(Value::Null),
                 _ => {
@@ -890,6 +923,7 @@ pub(super) fn evaluate_scalar_function(
             };
             let x = match evaluated_args.get(1) {
                 Some(Value::Float(f)) => *f,
+                Some(Value::Decimal(d)) => d.to_f64(),
                 Some(Value::Integer(i)) => *i as f64,
                 Some(Value::Null) => return Ok(Value::Null),
                 _ => {
@@ -912,6 +946,7 @@ pub(super) fn evaluate_scalar_function(
         }
         ScalarFunctionType::Degrees => match evaluated_args.first() {
             Some(Value::Float(f)) => Ok(Value::Float(f.to_degrees())),
+            Some(Value::Decimal(d)) => Ok(Value::Float(d.to_f64().to_degrees())),
             Some(Value::Integer(i)) => Ok(Value::Float((*i as f64).to_degrees())),
             Some(Value::Null) => Ok(Value::Null),
             _ => Err(RustqlError::TypeMismatch(
@@ -920,6 +955,7 @@ pub(super) fn evaluate_scalar_function(
         },
         ScalarFunctionType::Radians => match evaluated_args.first() {
             Some(Value::Float(f)) => Ok(Value::Float(f.to_radians())),
+            Some(Value::Decimal(d)) => Ok(Value::Float(d.to_f64().to_radians())),
             Some(Value::Integer(i)) => Ok(Value::Float((*i as f64).to_radians())),
             Some(Value::Null) => Ok(Value::Null),
             _ => Err(RustqlError::TypeMismatch(
@@ -927,7 +963,7 @@ pub(super) fn evaluate_scalar_function(
             )),
         },
         ScalarFunctionType::Quarter => match evaluated_args.first() {
-            Some(Value::Date(d)) | Some(Value::DateTime(d)) | Some(Value::Text(d)) => {
+            Some(Value::Date(d) | Value::DateTime(d) | Value::TimestampTz(d) | Value::Text(d)) => {
                 match parse_date_components(d) {
                     Some((_, m, _)) => Ok(Value::Integer((m - 1) / 3 + 1)),
                     None => Err(RustqlError::TypeMismatch(
@@ -941,7 +977,7 @@ pub(super) fn evaluate_scalar_function(
             )),
         },
         ScalarFunctionType::Week => match evaluated_args.first() {
-            Some(Value::Date(d)) | Some(Value::DateTime(d)) | Some(Value::Text(d)) => {
+            Some(Value::Date(d) | Value::DateTime(d) | Value::TimestampTz(d) | Value::Text(d)) => {
                 match parse_date_components(d) {
                     Some((y, m, d_val)) => {
                         let day_of_year = ymd_to_days(y, m, d_val) - ymd_to_days(y, 1, 1);
@@ -958,7 +994,7 @@ pub(super) fn evaluate_scalar_function(
             )),
         },
         ScalarFunctionType::DayOfWeek => match evaluated_args.first() {
-            Some(Value::Date(d)) | Some(Value::DateTime(d)) | Some(Value::Text(d)) => {
+            Some(Value::Date(d) | Value::DateTime(d) | Value::TimestampTz(d) | Value::Text(d)) => {
                 match parse_date_components(d) {
                     Some((y, m, d_val)) => {
                         let days = ymd_to_days(y, m, d_val);
@@ -991,6 +1027,10 @@ pub(super) fn evaluate_scalar_function(
                     let factor = 10f64.powi(precision);
                     Ok(Value::Float((f * factor).trunc() / factor))
                 }
+                Value::Decimal(d) => d
+                    .trunc_to(precision)
+                    .map(Value::Decimal)
+                    .ok_or_else(decimal_out_of_range),
                 Value::Integer(n) => {
                     if precision >= 0 {
                         Ok(Value::Integer(n))
@@ -1006,6 +1046,7 @@ pub(super) fn evaluate_scalar_function(
             let val = evaluate_value_expression_with_db(&args[0], columns, row, db)?;
             match val {
                 Value::Float(f) => Ok(Value::Float(f.log10())),
+                Value::Decimal(d) => Ok(Value::Float(d.to_f64().log10())),
                 Value::Integer(n) => Ok(Value::Float((n as f64).log10())),
                 _ => Ok(Value::Null),
             }
@@ -1014,6 +1055,7 @@ pub(super) fn evaluate_scalar_function(
             let val = evaluate_value_expression_with_db(&args[0], columns, row, db)?;
             match val {
                 Value::Float(f) => Ok(Value::Float(f.log2())),
+                Value::Decimal(d) => Ok(Value::Float(d.to_f64().log2())),
                 Value::Integer(n) => Ok(Value::Float((n as f64).log2())),
                 _ => Ok(Value::Null),
             }
@@ -1022,6 +1064,7 @@ pub(super) fn evaluate_scalar_function(
             let val = evaluate_value_expression_with_db(&args[0], columns, row, db)?;
             match val {
                 Value::Float(f) => Ok(Value::Float(f.cbrt())),
+                Value::Decimal(d) => Ok(Value::Float(d.to_f64().cbrt())),
                 Value::Integer(n) => Ok(Value::Float((n as f64).cbrt())),
                 _ => Ok(Value::Null),
             }
@@ -1160,5 +1203,201 @@ pub(super) fn evaluate_scalar_function(
                 _ => Ok(Value::Null),
             }
         }
+        ScalarFunctionType::Encode => match (evaluated_args.first(), evaluated_args.get(1)) {
+            (Some(Value::Null), _) | (_, Some(Value::Null)) => Ok(Value::Null),
+            (Some(Value::Blob(bytes)), Some(Value::Text(format))) => {
+                match format.to_ascii_lowercase().as_str() {
+                    "hex" => Ok(Value::Text(crate::blob::encode_hex(bytes))),
+                    "base64" => Ok(Value::Text(crate::blob::encode_base64(bytes))),
+                    _ => Err(unknown_binary_format("ENCODE", format)),
+                }
+            }
+            _ => Err(RustqlError::TypeMismatch(
+                "ENCODE requires a blob and a format name".to_string(),
+            )),
+        },
+        ScalarFunctionType::Decode => match (evaluated_args.first(), evaluated_args.get(1)) {
+            (Some(Value::Null), _) | (_, Some(Value::Null)) => Ok(Value::Null),
+            (Some(Value::Text(text)), Some(Value::Text(format))) => {
+                let bytes = match format.to_ascii_lowercase().as_str() {
+                    "hex" => crate::blob::decode_hex(text),
+                    "base64" => crate::blob::decode_base64(text),
+                    _ => return Err(unknown_binary_format("DECODE", format)),
+                };
+                bytes.map(Value::Blob).ok_or_else(|| {
+                    RustqlError::TypeMismatch(format!(
+                        "DECODE input is not valid {}",
+                        format.to_ascii_lowercase()
+                    ))
+                })
+            }
+            _ => Err(RustqlError::TypeMismatch(
+                "DECODE requires a text value and a format name".to_string(),
+            )),
+        },
+        ScalarFunctionType::JsonExtract => {
+            let (Some(document), Some(path)) = (evaluated_args.first(), evaluated_args.get(1))
+            else {
+                return Err(RustqlError::TypeMismatch(
+                    "JSON_EXTRACT requires a JSON value and a path".to_string(),
+                ));
+            };
+            let (Some(json), Some(path)) =
+                (json::operand(document, "JSON_EXTRACT")?, json_path(path)?)
+            else {
+                return Ok(Value::Null);
+            };
+            Ok(json::follow(&json, &path).map_or(Value::Null, json::to_value))
+        }
+        ScalarFunctionType::JsonSet => {
+            let (Some(document), Some(path), Some(value)) = (
+                evaluated_args.first(),
+                evaluated_args.get(1),
+                evaluated_args.get(2),
+            ) else {
+                return Err(RustqlError::TypeMismatch(
+                    "JSON_SET requires a JSON value, a path and a new value".to_string(),
+                ));
+            };
+            let (Some(mut json), Some(path)) =
+                (json::operand(document, "JSON_SET")?, json_path(path)?)
+            else {
+                return Ok(Value::Null);
+            };
+            json::set_path(&mut json, &path, json::from_sql(value));
+            Ok(json::to_value(&json))
+        }
+        ScalarFunctionType::JsonArrayLength => {
+            let document = evaluated_args.first().unwrap_or(&Value::Null);
+            match json::operand(document, "JSON_ARRAY_LENGTH")? {
+                None => Ok(Value::Null),
+                Some(JsonValue::Array(items)) => Ok(Value::Integer(items.len() as i64)),
+                Some(other) => Err(RustqlError::TypeMismatch(format!(
+                    "JSON_ARRAY_LENGTH requires an array, got {}",
+                    json::type_name(&other)
+                ))),
+            }
+        }
+        ScalarFunctionType::JsonTypeof => {
+            let document = evaluated_args.first().unwrap_or(&Value::Null);
+            Ok(
+                json::operand(document, "JSON_TYPEOF")?.map_or(Value::Null, |json| {
+                    Value::Text(json::type_name(&json).to_string())
+                }),
+            )
+        }
+        ScalarFunctionType::JsonBuildObject => json::build_object(&evaluated_args),
+        ScalarFunctionType::JustifyDays
+        | ScalarFunctionType::JustifyHours
+        | ScalarFunctionType::JustifyInterval => {
+            let function = match name {
+                ScalarFunctionType::JustifyDays => "JUSTIFY_DAYS",
+                ScalarFunctionType::JustifyHours => "JUSTIFY_HOURS",
+                _ => "JUSTIFY_INTERVAL",
+            };
+            let interval = match evaluated_args.as_slice() {
+                [Value::Interval(interval)] => interval,
+                [Value::Null] => return Ok(Value::Null),
+                _ => {
+                    return Err(RustqlError::TypeMismatch(format!(
+                        "{} requires one interval argument",
+                        function
+                    )));
+                }
+            };
+            let justified = match name {
+                ScalarFunctionType::JustifyDays => interval.justify_days(),
+                ScalarFunctionType::JustifyHours => interval.justify_hours(),
+                _ => interval.justify_interval(),
+            };
+            justified.map(Value::Interval).ok_or_else(|| {
+                RustqlError::TypeMismatch(format!("{} result is out of range", function))
+            })
+        }
+        ScalarFunctionType::Age => {
+            if evaluated_args.iter().any(|arg| matches!(arg, Value::Null)) {
+                return Ok(Value::Null);
+            }
+            let (later, earlier) = match evaluated_args.as_slice() {
+                [later, earlier] => (temporal_micros(later), temporal_micros(earlier)),
+                // With one argument AGE measures from midnight today.
+                [earlier] => (
+                    temporal_micros(&Value::Date(current_datetime()[..10].to_string())),
+                    temporal_micros(earlier),
+                ),
+                _ => {
+                    return Err(RustqlError::TypeMismatch(
+                        "AGE expects 1 or 2 arguments".to_string(),
+                    ));
+                }
+            };
+            let (Some(later), Some(earlier)) = (later, earlier) else {
+                return Err(RustqlError::TypeMismatch(
+                    "AGE requires date or datetime arguments".to_string(),
+                ));
+            };
+            age(later, earlier)
+                .map(Value::Interval)
+                .ok_or_else(|| RustqlError::TypeMismatch("AGE result is out of range".to_string()))
+        }
+        ScalarFunctionType::Timezone => match evaluated_args.as_slice() {
+            [Value::Null, _] | [_, Value::Null] => Ok(Value::Null),
+            [Value::Text(zone), value] => super::timezone::at_time_zone(value, zone),
+            _ => Err(RustqlError::TypeMismatch(
+                "AT TIME ZONE requires a text zone name".to_string(),
+            )),
+        },
     }
 }
+
+/// The steps of a JSON_EXTRACT or JSON_SET path, or `None` for NULL.
+fn json_path(path: &Value) -> Result<Option<Vec<json::PathStep>>, RustqlError> {
+    match path {
+        Value::Null => Ok(None),
+        Value::Text(path) => json::parse_path(path).map(Some),
+        other => Err(RustqlError::TypeMismatch(format!(
+            "JSON path must be text, got {}",
+            other
+        ))),
+    }
+}
+
+fn unknown_binary_format(function: &str, format: &str) -> RustqlError {
+    RustqlError::TypeMismatch(format!(
+        "{} format must be 'hex' or 'base64', got '{}'",
+        function, format
+    ))
+}
+
+fn decimal_to_integer(value: Option<Decimal>) -> Result<Value, RustqlError> {
+    value
+        .and_then(|value| value.trunc_to_i64())
+        .map(Value::Integer)
+        .ok_or_else(|| RustqlError::TypeMismatch("Value is outside the INTEGER range".to_string()))
+}
+
+/// The zero-based start and the length that SUBSTRING's one-based start
+/// and optional length select from `total` characters or bytes.
+fn substring_bounds(args: &[Value], total: usize) -> Result<(usize, usize), RustqlError> {
+    let start = match args.get(1) {
+        Some(Value::Integer(i)) => (*i as usize).saturating_sub(1),
+        _ => {
+            return Err(RustqlError::TypeMismatch(
+                "SUBSTRING requires an integer start position".to_string(),
+            ));
+        }
+    };
+    if start >= total {
+        return Ok((start, 0));
+    }
+    let len = match args.get(2) {
+        Some(Value::Integer(l)) => *l as usize,
+        None => total - start,
+        _ => {
+            return Err(RustqlError::TypeMismatch(
+                "SUBSTRING length must be an integer".to_string(),
+            ));
+        }
+    };
+    Ok((start, len))
+}
@@ -9,13 +9,16 @@ mod date;
 mod functions;
 mod predicate;
 mod row_identity;
+mod timezone;
 mod value;
 
 pub(crate) use cast::coerce_value_for_type;
+pub(crate) use compare::decimal_out_of_range;
 pub use compare::{
     apply_arithmetic, compare_order_values, compare_values, compare_values_for_sort,
     compare_values_same_type, format_value,
 };
+pub(crate) use date::{add_interval, current_datetime, micros_to_datetime, temporal_micros};
 pub use predicate::{evaluate_expression, evaluate_predicate_value};
 pub(crate) use row_identity::{
     SqlRowMultiset, SqlRowSet, row_has_finite_numeric_value, rows_equal_for_sql_identity,
@@ -91,6 +91,30 @@ pub fn evaluate_predicate_value(
                     }
                 }
             }
+            BinaryOperator::JsonContains => {
+                let left_val = evaluate_value_expression_with_db(left, columns, row, db)?;
+                let right_val = evaluate_value_expression_with_db(right, columns, row, db)?;
+                match (
+                    crate::json::operand(&left_val, "@>")?,
+                    crate::json::operand(&right_val, "@>")?,
+                ) {
+                    (Some(container), Some(contained)) => Ok(Value::Boolean(
+                        crate::json::contains(&container, &contained),
+                    )),
+                    _ => Ok(Value::Null),
+                }
+            }
+            BinaryOperator::JsonField
+            | BinaryOperator::JsonFieldText
+            | BinaryOperator::JsonPath => {
+                match evaluate_value_expression_with_db(expr, columns, row, db)? {
+                    Value::Boolean(value) => Ok(Value::Boolean(value)),
+                    Value::Null => Ok(Value::Null),
+                    _ => Err(RustqlError::TypeMismatch(
+                        "Predicate expression must evaluate to BOOLEAN".to_string(),
+                    )),
+                }
+            }
             _ => {
                 let left_val = evaluate_value_expression_with_db(left, columns, row, db)?;
                 let right_val = evaluate_value_expression_with_db(right, columns, row, db)?;
@@ -484,6 +508,15 @@ fn is_between(val: &Value, lower: &Value, upper: &Value) -> bool {
         (Value::Float(v), Value::Float(l), Value::Integer(u)) => *v >= *l && *v <= *u as f64,
         (Value::Integer(v), Value::Float(l), Value::Float(u)) => *v as f64 >= *l && *v as f64 <= *u,
         (Value::Text(v), Value::Text(l), Value::Text(u)) => v >= l && v <= u,
+        (Value::Decimal(_), _, _) | (_, Value::Decimal(_), _) | (_, _, Value::Decimal(_)) => {
+            matches!(
+                compare_values(val, &BinaryOperator::GreaterThanOrEqual, lower),
+                Ok(true)
+            ) && matches!(
+                compare_values(val, &BinaryOperator::LessThanOrEqual, upper),
+                Ok(true)
+            )
+        }
         _ => false,
     }
 }
@@ -108,7 +108,7 @@ pub(crate) fn values_equal_for_sql_identity(left: &Value, right: &Value) -> bool
 
 fn is_finite_numeric_value(value: &Value) -> bool {
     match value {
-        Value::Integer(_) => true,
+        Value::Integer(_) | Value::Decimal(_) => true,
         Value::Float(value) => value.is_finite(),
         _ => false,
     }
@@ -0,0 +1,329 @@
+use super::date::{
+    datetime_micros, days_in_month, days_to_ymd, micros_to_datetime, parse_fixed_digits,
+    temporal_micros, ymd_to_days,
+};
+use crate::ast::Value;
+use crate::error::RustqlError;
+use crate::interval::{MICROS_PER_DAY, MICROS_PER_SECOND};
+
+const MICROS_PER_MINUTE: i64 = 60 * MICROS_PER_SECOND;
+const MICROS_PER_HOUR: i64 = 60 * MICROS_PER_MINUTE;
+
+/// A zone for `AT TIME ZONE` and TIMESTAMPTZ input: a fixed offset from
+/// UTC, or a named zone whose current daylight saving rules are applied
+/// to every year.
+#[derive(Clone, Copy)]
+pub(super) struct TimeZone {
+    /// Offset east of UTC outside daylight saving time, in microseconds.
+    standard: i64,
+    dst: Option<&'static DstRule>,
+}
+
+/// Daylight saving time moves clocks forward one hour from `start` until
+/// `end`. In the southern hemisphere `end` comes first in the year.
+struct DstRule {
+    start: Transition,
+    end: Transition,
+}
+
+struct Transition {
+    month: i64,
+    sunday: Sunday,
+    /// Seconds after midnight that the clocks change.
+    at: i64,
+    /// Whether `at` is UTC rather than local standard time.
+    utc: bool,
+}
+
+enum Sunday {
+    Nth(i64),
+    Last,
+}
+
+const US: DstRule = DstRule {
+    start: Transition {
+        month: 3,
+        sunday: Sunday::Nth(2),
+        at: 2 * 3600,
+        utc: false,
+    },
+    end: Transition {
+        month: 11,
+        sunday: Sunday::Nth(1),
+        at: 3600,
+        utc: false,
+    },
+};
+
+const EU: DstRule = DstRule {
+    start: Transition {
+        month: 3,
+        sunday: Sunday::Last,
+        at: 3600,
+        utc: true,
+    },
+    end: Transition {
+        month: 10,
+        sunday: Sunday::Last,
+        at: 3600,
+        utc: true,
+    },
+};
+
+const AUSTRALIA: DstRule = DstRule {
+    start: Transition {
+        month: 10,
+        sunday: Sunday::Nth(1),
+        at: 2 * 3600,
+        utc: false,
+    },
+    end: Transition {
+        month: 4,
+        sunday: Sunday::Nth(1),
+        at: 2 * 3600,
+        utc: false,
+    },
+};
+
+const NEW_ZEALAND: DstRule = DstRule {
+    start: Transition {
+        month: 9,
+        sunday: Sunday::Last,
+        at: 2 * 3600,
+        utc: false,
+    },
+    end: Transition {
+        month: 4,
+        sunday: Sunday::Nth(1),
+        at: 2 * 3600,
+        utc: false,
+    },
+};
+
+/// Named zones with their standard offset in minutes east of UTC.
+const ZONES: &[(&str, i64, Option<&DstRule>)] = &[
+    ("UTC", 0, None),
+    ("GMT", 0, None),
+    ("Z", 0, None),
+    ("Etc/UTC", 0, None),
+    ("EST", -300, None),
+    ("EDT", -240, None),
+    ("CST", -360, None),
+    ("CDT", -300, None),
+    ("MST", -420, None),
+    ("MDT", -360, None),
+    ("PST", -480, None),
+    ("PDT", -420, None),
+    ("America/Halifax", -240, Some(&US)),
+    ("America/New_York", -300, Some(&US)),
+    ("America/Toronto", -300, Some(&US)),
+    ("America/Chicago", -360, Some(&US)),
+    ("America/Mexico_City", -360, None),
+    ("America/Denver", -420, Some(&US)),
+    ("America/Phoenix", -420, None),
+    ("America/Los_Angeles", -480, Some(&US)),
+    ("America/Vancouver", -480, Some(&US)),
+    ("America/Anchorage", -540, Some(&US)),
+    ("Pacific/Honolulu", -600, None),
+    ("America/Sao_Paulo", -180, None),
+    ("America/Argentina/Buenos_Aires", -180, None),
+    ("Europe/London", 0, Some(&EU)),
+    ("Europe/Dublin", 0, Some(&EU)),
+    ("Europe/Lisbon", 0, Some(&EU)),
+    ("Europe/Paris", 60, Some(&EU)),
+    ("Europe/Berlin", 60, Some(&EU)),
+    ("Europe/Madrid", 60, Some(&EU)),
+    ("Europe/Rome", 60, Some(&EU)),
+    ("Europe/Amsterdam", 60, Some(&EU)),
+    ("Europe/Brussels", 60, Some(&EU)),
+    ("Europe/Vienna", 60, Some(&EU)),
+    ("Europe/Zurich", 60, Some(&EU)),
+    ("Europe/Stockholm", 60, Some(&EU)),
+    ("Europe/Oslo", 60, Some(&EU)),
+    ("Europe/Copenhagen", 60, Some(&EU)),
+    ("Europe/Warsaw", 60, Some(&EU)),
+    ("Europe/Prague", 60, Some(&EU)),
+    ("Europe/Athens", 120, Some(&EU)),
+    ("Europe/Helsinki", 120, Some(&EU)),
+    ("Europe/Kyiv", 120, Some(&EU)),
+    ("Europe/Bucharest", 120, Some(&EU)),
+    ("Europe/Istanbul", 180, None),
+    ("Europe/Moscow", 180, None),
+    ("Africa/Lagos", 60, None),
+    ("Africa/Johannesburg", 120, None),
+    ("Africa/Nairobi", 180, None),
+    ("Asia/Dubai", 240, None),
+    ("Asia/Karachi", 300, None),
+    ("Asia/Kolkata", 330, None),
+    ("Asia/Kathmandu", 345, None),
+    ("Asia/Dhaka", 360, None),
+    ("Asia/Bangkok", 420, None),
+    ("Asia/Jakarta", 420, None),
+    ("Asia/Shanghai", 480, None),
+    ("Asia/Hong_Kong", 480, None),
+    ("Asia/Singapore", 480, None),
+    ("Asia/Taipei", 480, None),
+    ("Asia/Seoul", 540, None),
+    ("Asia/Tokyo", 540, None),
+    ("Australia/Perth", 480, None),
+    ("Australia/Adelaide", 570, Some(&AUSTRALIA)),
+    ("Australia/Brisbane", 600, None),
+    ("Australia/Sydney", 600, Some(&AUSTRALIA)),
+    ("Australia/Melbourne", 600, Some(&AUSTRALIA)),
+    ("Australia/Hobart", 600, Some(&AUSTRALIA)),
+    ("Pacific/Auckland", 720, Some(&NEW_ZEALAND)),
+];
+
+impl TimeZone {
+    const UTC: TimeZone = TimeZone {
+        standard: 0,
+        dst: None,
+    };
+
+    /// A zone name from the table above, matched case-insensitively, or
+    /// an offset such as `+05:30`, `-08` or `+0100`, east of UTC.
+    pub(super) fn parse(name: &str) -> Option<TimeZone> {
+        if let Some(offset) = parse_offset(name) {
+            return Some(TimeZone {
+                standard: offset,
+                dst: None,
+            });
+        }
+        ZONES
+            .iter()
+            .find(|(zone, _, _)| zone.eq_ignore_ascii_case(name))
+            .map(|(_, minutes, dst)| TimeZone {
+                standard: minutes * MICROS_PER_MINUTE,
+                dst: *dst,
+            })
+    }
+
+    /// The offset east of UTC in effect at the UTC instant `utc`.
+    fn offset_at(&self, utc: i64) -> i64 {
+        match self.dst {
+            Some(rule) if rule.in_effect(utc, self.standard) => self.standard + MICROS_PER_HOUR,
+            _ => self.standard,
+        }
+    }
+
+    /// The offset that makes `local` a wall-clock time in this zone. A
+    /// time skipped when the clocks go forward, or repeated when they go
+    /// back, takes the standard offset as PostgreSQL does.
+    fn offset_for_local(&self, local: i64) -> i64 {
+        let daylight = self.standard + MICROS_PER_HOUR;
+        if self.dst.is_some()
+            && self.offset_at(local - daylight) == daylight
+            && self.offset_at(local - self.standard) == daylight
+        {
+            daylight
+        } else {
+            self.standard
+        }
+    }
+}
+
+impl DstRule {
+    fn in_effect(&self, utc: i64, standard: i64) -> bool {
+        let (year, _, _) = days_to_ymd((utc + standard).div_euclid(MICROS_PER_DAY));
+        let start = self.start.instant(year, standard);
+        let end = self.end.instant(year, standard);
+        if start < end {
+            start <= utc && utc < end
+        } else {
+            utc >= start || utc < end
+        }
+    }
+}
+
+impl Transition {
+    /// The UTC instant of this transition in `year`.
+    fn instant(&self, year: i64, standard: i64) -> i64 {
+        // The day of the week for days since the epoch, with Sunday as 0.
+        let weekday = |days: i64| (days + 4).rem_euclid(7);
+        let first = ymd_to_days(year, self.month, 1);
+        let day = match self.sunday {
+            Sunday::Nth(n) => first + (7 - weekday(first)) % 7 + 7 * (n - 1),
+            Sunday::Last => {
+                let last = first + days_in_month(year, self.month) - 1;
+                last - weekday(last)
+            }
+        };
+        let instant = day * MICROS_PER_DAY + self.at * MICROS_PER_SECOND;
+        if self.utc {
+            instant
+        } else {
+            instant - standard
+        }
+    }
+}
+
+fn parse_offset(text: &str) -> Option<i64> {
+    let (sign, rest) = match text.as_bytes().first()? {
+        b'+' => (1, &text[1..]),
+        b'-' => (-1, &text[1..]),
+        _ => return None,
+    };
+    let (hours, minutes) = match rest.split_once(':') {
+        Some((hours, minutes)) => (hours, Some(minutes)),
+        None if rest.len() == 4 => (&rest[..2], Some(&rest[2..])),
+        None => (rest, None),
+    };
+    if !(1..=2).contains(&hours.len()) || minutes.is_some_and(|minutes| minutes.len() != 2) {
+        return None;
+    }
+    let hours = parse_fixed_digits(hours.as_bytes())? as i64;
+    let minutes = match minutes {
+        Some(minutes) => parse_fixed_digits(minutes.as_bytes())? as i64,
+        None => 0,
+    };
+    if hours > 15 || minutes > 59 {
+        return None;
+    }
+    Some(sign * (hours * MICROS_PER_HOUR + minutes * MICROS_PER_MINUTE))
+}
+
+/// The UTC text for TIMESTAMPTZ input: a DATETIME followed by an optional
+/// offset or zone name, read as UTC when there is neither.
+pub(super) fn normalize_timestamptz(s: &str) -> Option<String> {
+    let time_end = s
+        .get(11..)?
+        .find(|c: char| !(c.is_ascii_digit() || c == ':' || c == '.'))
+        .map_or(s.len(), |end| end + 11);
+    let local = datetime_micros(&s[..time_end])?;
+    let zone = match s[time_end..].trim_start() {
+        "" => TimeZone::UTC,
+        zone => TimeZone::parse(zone)?,
+    };
+    micros_to_datetime(local - zone.offset_for_local(local))
+}
+
+/// `value AT TIME ZONE zone`: a TIMESTAMPTZ becomes the DATETIME shown on
+/// clocks in the zone, and a DATE or DATETIME read as a time on those
+/// clocks becomes a TIMESTAMPTZ.
+pub(super) fn at_time_zone(value: &Value, zone: &str) -> Result<Value, RustqlError> {
+    let tz = TimeZone::parse(zone)
+        .ok_or_else(|| RustqlError::TypeMismatch(format!("Unknown time zone '{}'", zone)))?;
+    let out_of_range = || RustqlError::TypeMismatch("Date/time value out of range".to_string());
+    let micros = |value: &Value| {
+        temporal_micros(value).ok_or_else(|| {
+            RustqlError::TypeMismatch(format!("Invalid date/time value '{}'", value))
+        })
+    };
+    match value {
+        Value::TimestampTz(_) => {
+            let utc = micros(value)?;
+            micros_to_datetime(utc + tz.offset_at(utc))
+                .map(Value::DateTime)
+                .ok_or_else(out_of_range)
+        }
+        Value::Date(_) | Value::DateTime(_) => {
+            let local = micros(value)?;
+            micros_to_datetime(local - tz.offset_for_local(local))
+                .map(Value::TimestampTz)
+                .ok_or_else(out_of_range)
+        }
+        _ => Err(RustqlError::TypeMismatch(
+            "AT TIME ZONE requires a DATE, DATETIME or TIMESTAMPTZ value".to_string(),
+        )),
+    }
+}
@@ -54,10 +54,20 @@ pub fn evaluate_value_expression_with_db(
             BinaryOperator::Concat => {
                 let left_val = evaluate_value_expression_with_db(left, columns, row, db)?;
                 let right_val = evaluate_value_expression_with_db(right, columns, row, db)?;
+                if let (Value::Blob(l), Value::Blob(r)) = (&left_val, &right_val) {
+                    return Ok(Value::Blob([l.as_slice(), r.as_slice()].concat()));
+                }
                 let l = format_value(&left_val);
                 let r = format_value(&right_val);
                 Ok(Value::Text(format!("{}{}", l, r)))
             }
+            BinaryOperator::JsonField
+            | BinaryOperator::JsonFieldText
+            | BinaryOperator::JsonPath => {
+                let left_val = evaluate_value_expression_with_db(left, columns, row, db)?;
+                let right_val = evaluate_value_expression_with_db(right, columns, row, db)?;
+                apply_json_operator(&left_val, op, &right_val)
+            }
             BinaryOperator::Escape => Err(RustqlError::Internal(
                 "LIKE ESCAPE marker must be evaluated by LIKE".to_string(),
             )),
@@ -69,6 +79,12 @@ pub fn evaluate_value_expression_with_db(
                 match val {
                     Value::Integer(n) => Ok(Value::Integer(-n)),
                     Value::Float(f) => Ok(Value::Float(-f)),
+                    Value::Decimal(d) => Ok(Value::Decimal(d.negate())),
+                    Value::Interval(interval) => {
+                        interval.checked_neg().map(Value::Interval).ok_or_else(|| {
+                            RustqlError::TypeMismatch("Interval value out of range".to_string())
+                        })
+                    }
                     _ => Err(RustqlError::Internal(
                         "Unary minus only supported for numeric types".to_string(),
                     )),
@@ -143,3 +159,33 @@ pub fn evaluate_value_expression_with_db(
         )),
     }
 }
+
+/// Applies `->`, `->>` or `#>`. A NULL operand or a path that leads
+/// nowhere gives NULL.
+fn apply_json_operator(
+    left: &Value,
+    op: &BinaryOperator,
+    right: &Value,
+) -> Result<Value, RustqlError> {
+    let Some(json) = crate::json::operand(left, "JSON operator")? else {
+        return Ok(Value::Null);
+    };
+    let found = match (op, right) {
+        (_, Value::Null) => None,
+        (BinaryOperator::JsonPath, Value::Text(path)) => {
+            crate::json::follow_text_path(&json, path)?
+        }
+        (BinaryOperator::JsonPath, other) => {
+            return Err(RustqlError::TypeMismatch(format!(
+                "#> requires a text path such as '{{a,0}}', got {}",
+                other
+            )));
+        }
+        _ => crate::json::step(&json, &crate::json::PathStep::from_operand(right)?),
+    };
+    Ok(match (found, op) {
+        (None, _) => Value::Null,
+        (Some(found), BinaryOperator::JsonFieldText) => crate::json::to_text(found),
+        (Some(found), _) => crate::json::to_value(found),
+    })
+}
@@ -0,0 +1,159 @@
+use super::{AttachOptions, EngineState, ExecutionContext};
+use crate::database::Database;
+use crate::error::RustqlError;
+use crate::storage::{BTreeStorageEngine, LogFollower, ReplicationSource, StorageEngine};
+use crate::wal::WriteSet;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard, Weak};
+use std::time::Duration;
+
+/// How long the follower thread waits for the primary to ship a record
+/// before it checks whether the engine is still open.
+const POLL_INTERVAL: Duration = Duration::from_millis(20);
+
+/// The replication state of an engine opened as a follower.
+///
+/// A background thread applies the records its primary ships to the storage
+/// file and installs the database they lead to as the committed one, the way
+/// a commit would. Until [`promote`](ExecutionContext::promote) stops it,
+/// statements that write are rejected.
+pub(super) struct Follower {
+    storage: Arc<BTreeStorageEngine>,
+    log: Mutex<LogFollower>,
+    following: AtomicBool,
+}
+
+impl Follower {
+    pub(super) fn is_following(&self) -> bool {
+        self.following.load(Ordering::SeqCst)
+    }
+
+    fn log(&self) -> MutexGuard<'_, LogFollower> {
+        self.log.lock().unwrap_or_else(|err| err.into_inner())
+    }
+
+    /// Reads the committed database from the follower's file. Every row is
+    /// read up front: a later record may rewrite the pages a paged table
+    /// would read them from while a snapshot still uses it.
+    fn load(&self) -> Result<Database, RustqlError> {
+        let mut database = self.storage.load()?;
+        database.load_all_paged_tables()?;
+        Ok(database)
+    }
+}
+
+impl ExecutionContext {
+    /// Opens an engine that follows the primary shipping to `source`,
+    /// starting with the records shipped so far.
+    pub(crate) fn follower(
+        storage: Arc<BTreeStorageEngine>,
+        source: ReplicationSource,
+        attach_options: AttachOptions,
+    ) -> Result<Self, RustqlError> {
+        let mut log = LogFollower::new(source);
+        log.poll(&storage, Duration::ZERO)?;
+        let follower = Arc::new(Follower {
+            storage: Arc::clone(&storage),
+            log: Mutex::new(log),
+            following: AtomicBool::new(true),
+        });
+        let database = follower.load()?;
+        let engine = Arc::new(EngineState::new(
+            database,
+            Some(storage as Arc<dyn StorageEngine>),
+            // Shipped records rewrite pages without recording the rows they
+            // replace, so the follower keeps its tables in memory.
+            None,
+            attach_options,
+            Some(follower),
+        ));
+        let weak = Arc::downgrade(&engine);
+        std::thread::Builder::new()
+            .name("rustql-follower".to_string())
+            .spawn(move || follow(weak))
+            .map_err(|e| {
+                RustqlError::StorageError(format!("Failed to start follower thread: {}", e))
+            })?;
+        Ok(Self::with_engine(engine))
+    }
+
+    /// Whether writes are rejected because the engine follows a primary.
+    pub(crate) fn is_following(&self) -> bool {
+        self.engine
+            .follower
+            .as_ref()
+            .is_some_and(|follower| follower.is_following())
+    }
+
+    /// Applies what the primary shipped so far, stops following it, and
+    /// starts accepting writes.
+    pub(crate) fn promote(&self) -> Result<(), RustqlError> {
+        let Some(follower) = &self.engine.follower else {
+            return Err(RustqlError::StorageError(
+                "Only a follower engine can be promoted".to_string(),
+            ));
+        };
+        let mut log = follower.log();
+        if !follower.is_following() {
+            return Err(RustqlError::StorageError(
+                "The follower has already been promoted".to_string(),
+            ));
+        }
+        if log.poll(&follower.storage, Duration::ZERO)? {
+            install(&self.engine, follower.load()?);
+        }
+        follower.following.store(false, Ordering::SeqCst);
+        Ok(())
+    }
+}
+
+/// The follower thread: applies shipped records until the engine is promoted
+/// or dropped. A record that fails to apply is retried on the next round.
+fn follow(engine: Weak<EngineState>) {
+    loop {
+        let Some(engine) = engine.upgrade() else {
+            return;
+        };
+        let Some(follower) = engine.follower.as_ref() else {
+            return;
+        };
+        let mut log = follower.log();
+        if !follower.is_following() {
+            return;
+        }
+        let applied = log
+            .poll(&follower.storage, POLL_INTERVAL)
+            .and_then(|applied| applied.then(|| follower.load()).transpose());
+        match applied {
+            Ok(Some(database)) => install(&engine, database),
+            Ok(None) => {}
+            Err(_) => {
+                drop(log);
+                drop(engine);
+                std::thread::sleep(POLL_INTERVAL);
+            }
+        }
+    }
+}
+
+/// Makes `database` the committed database. The tables and views of the old
+/// and new database count as written, so `READ COMMITTED` statements pick up
+/// the change.
+fn install(engine: &EngineState, database: Database) {
+    let _writer_guard = engine
+        .writer_lock
+        .lock()
+        .unwrap_or_else(|err| err.into_inner());
+    let mut commits = engine.commits.lock().unwrap_or_else(|err| err.into_inner());
+    let mut committed = engine
+        .database
+        .write()
+        .unwrap_or_else(|err| err.into_inner());
+    let mut writes = WriteSet::default();
+    for db in [&*committed, &database] {
+        writes.tables.extend(db.tables.keys().cloned());
+        writes.views.extend(db.views.keys().cloned());
+    }
+    *committed = database;
+    commits.record_commit(writes);
+}
@@ -0,0 +1,75 @@
+use crate::error::RustqlError;
+use std::cell::RefCell;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+/// How often, in checks, the statement timeout reads the clock.
+const CLOCK_CHECK_INTERVAL: u32 = 64;
+
+thread_local! {
+    static RUNNING_STATEMENT: RefCell<Option<RunningStatement>> = const { RefCell::new(None) };
+}
+
+struct RunningStatement {
+    interrupts: Arc<AtomicU64>,
+    interrupts_at_start: u64,
+    deadline: Option<Instant>,
+    checks_until_clock: u32,
+}
+
+/// Registers a statement as running on this thread until the guard drops.
+///
+/// Long loops in the plan executor call [`check_interrupt`], which fails once
+/// the engine's interrupt counter has moved past the value it had when the
+/// statement started, or once the statement timeout has run out. The
+/// statement returns that error like any other, so the savepoint of a write
+/// statement is rolled back.
+#[must_use]
+pub(crate) struct StatementWatch {
+    previous: Option<RunningStatement>,
+}
+
+impl StatementWatch {
+    pub(crate) fn start(interrupts: &Arc<AtomicU64>, timeout: Option<Duration>) -> Self {
+        let running = RunningStatement {
+            interrupts: Arc::clone(interrupts),
+            interrupts_at_start: interrupts.load(Ordering::SeqCst),
+            deadline: timeout.map(|timeout| Instant::now() + timeout),
+            checks_until_clock: 0,
+        };
+        StatementWatch {
+            previous: RUNNING_STATEMENT.with_borrow_mut(|slot| slot.replace(running)),
+        }
+    }
+}
+
+impl Drop for StatementWatch {
+    fn drop(&mut self) {
+        let previous = self.previous.take();
+        RUNNING_STATEMENT.with_borrow_mut(|slot| *slot = previous);
+    }
+}
+
+/// Fails with [`RustqlError::Cancelled`] or [`RustqlError::Timeout`] when the
+/// statement running on this thread should stop.
+pub(crate) fn check_interrupt() -> Result<(), RustqlError> {
+    RUNNING_STATEMENT.with_borrow_mut(|slot| {
+        let Some(running) = slot else {
+            return Ok(());
+        };
+        if running.interrupts.load(Ordering::Relaxed) != running.interrupts_at_start {
+            return Err(RustqlError::Cancelled);
+        }
+        if let Some(deadline) = running.deadline {
+            if running.checks_until_clock == 0 {
+                if Instant::now() >= deadline {
+                    return Err(RustqlError::Timeout);
+                }
+                running.checks_until_clock = CLOCK_CHECK_INTERVAL;
+            }
+            running.checks_until_clock -= 1;
+        }
+        Ok(())
+    })
+}
@@ -1,90 +1,722 @@
 pub(crate) mod aggregate;
+mod attach;
+pub(crate) mod changes;
+pub(crate) mod check;
 pub(crate) mod ddl;
 pub(crate) mod dml;
 pub(crate) mod expr;
+mod follower;
+pub(crate) mod interrupt;
+pub(crate) mod mvcc;
 pub(crate) mod select;
 
 use crate::ast::*;
-use crate::database::Database;
+use crate::database::{Database, PreparedTransaction};
+use crate::decimal::MAX_DECIMAL_PRECISION;
 use crate::engine::{
     ColumnMeta, CommandResult, CommandTag, ExplainAnalyzeResult, QueryResult, RowBatch,
     plan_tree_from_node,
 };
-use crate::error::RustqlError;
+use crate::error::{IntegrityProblem, RustqlError};
 use crate::plan_executor::PlanExecutor;
 use crate::planner::QueryPlanner;
-use crate::storage::StorageEngine;
-use crate::wal::{self, WalState};
+use crate::storage::{
+    BTreeStorageEngine, ChangeSet, EncryptionKey, StorageEngine, VacuumStats, write_snapshot_backup,
+};
+use crate::wal::{self, RowChange, WalState, WriteSet};
+use changes::ChangeFeed;
+use mvcc::{AutoIncrements, CommitLog, RowIdMap, TransactionState};
+use std::collections::BTreeSet;
+use std::path::Path;
+use std::sync::atomic::AtomicU64;
+use std::sync::mpsc::Receiver;
 use std::sync::{Arc, Mutex, RwLock};
-use std::time::Instant;
+use std::time::{Duration, Instant};
+
+pub(crate) use attach::AttachOptions;
 
-/// Per-engine execution state.
+/// State shared by every session of an engine: the committed database, the
+/// storage it is saved to and, for a B-tree file, the pager its saved tables
+/// are read back through, the files attached to it and the settings they
+/// are opened with, the commits open transactions must check against, the
+/// AUTO_INCREMENT values handed out, the subscribers to committed changes,
+/// and the counter that interrupts running statements.
 ///
-/// The WAL state lives here because an `Engine` is the connection and
-/// transaction boundary. Public `Session` values are borrowed execution
-/// handles over this shared context.
-pub(crate) struct ExecutionContext {
+/// Statements never change `database` in place. A writer builds the next
+/// committed state in its own copy and swaps it in when it commits, so the
+/// lock on `database` is only ever held for a moment. `writer_lock`
+/// serializes the writers among themselves.
+struct EngineState {
     database: RwLock<Database>,
-    wal_state: Mutex<WalState>,
-    statement_lock: Mutex<()>,
+    writer_lock: Mutex<()>,
     storage: Option<Arc<dyn StorageEngine>>,
+    pager: Option<Arc<BTreeStorageEngine>>,
+    attached: Mutex<attach::Attachments>,
+    attach_options: attach::AttachOptions,
+    commits: Mutex<CommitLog>,
+    auto_increments: Mutex<AutoIncrements>,
+    changes: ChangeFeed,
+    interrupts: Arc<AtomicU64>,
+    follower: Option<Arc<follower::Follower>>,
+}
+
+impl EngineState {
+    fn new(
+        database: Database,
+        storage: Option<Arc<dyn StorageEngine>>,
+        pager: Option<Arc<BTreeStorageEngine>>,
+        attach_options: attach::AttachOptions,
+        follower: Option<Arc<follower::Follower>>,
+    ) -> Self {
+        EngineState {
+            database: RwLock::new(database),
+            writer_lock: Mutex::new(()),
+            storage,
+            pager,
+            attached: Mutex::new(attach::Attachments::default()),
+            attach_options,
+            commits: Mutex::new(CommitLog::default()),
+            auto_increments: Mutex::new(AutoIncrements::default()),
+            changes: ChangeFeed::default(),
+            interrupts: Arc::new(AtomicU64::new(0)),
+            follower,
+        }
+    }
+}
+
+/// Which copy of the database a session's statements run on.
+#[derive(Debug, Clone, Copy)]
+enum View {
+    /// The committed database, for engine-level work between statements.
+    Committed,
+    /// A snapshot taken for the running statement outside a transaction.
+    Statement,
+    /// The snapshot of an open transaction, taken at the given commit
+    /// sequence number.
+    Transaction(u64),
+}
+
+/// Per-session execution state.
+///
+/// Each session has its own WAL state and transaction. Every statement runs
+/// on `workspace`, a copy-on-write snapshot of the committed database, so
+/// readers never wait for writers or for each other. Outside a transaction
+/// the snapshot is taken for one statement, and a write statement replaces
+/// the committed database with it when it finishes. `BEGIN` takes a snapshot
+/// that the transaction reads and writes until `COMMIT` applies its writes
+/// to the committed database.
+pub(crate) struct ExecutionContext {
+    engine: Arc<EngineState>,
+    wal_state: Mutex<WalState>,
+    view: Mutex<View>,
+    workspace: RwLock<Database>,
+    transaction: Mutex<TransactionState>,
+    statement_timeout: Mutex<Option<Duration>>,
 }
 
 impl ExecutionContext {
-    pub(crate) fn new(database: Database, storage: Option<Arc<dyn StorageEngine>>) -> Self {
+    pub(crate) fn new(
+        database: Database,
+        storage: Option<Arc<dyn StorageEngine>>,
+        pager: Option<Arc<BTreeStorageEngine>>,
+        attach_options: AttachOptions,
+    ) -> Self {
+        Self::with_engine(Arc::new(EngineState::new(
+            database,
+            storage,
+            pager,
+            attach_options,
+            None,
+        )))
+    }
+
+    fn with_engine(engine: Arc<EngineState>) -> Self {
         Self {
-            database: RwLock::new(database),
+            engine,
             wal_state: Mutex::new(WalState::default()),
-            statement_lock: Mutex::new(()),
-            storage,
+            view: Mutex::new(View::Committed),
+            workspace: RwLock::new(Database::new()),
+            transaction: Mutex::new(TransactionState::default()),
+            statement_timeout: Mutex::new(None),
         }
     }
 
+    /// Opens another session on the same engine.
+    pub(crate) fn new_session(&self) -> Self {
+        Self::with_engine(Arc::clone(&self.engine))
+    }
+
+    /// The counter an [`InterruptHandle`](crate::InterruptHandle) bumps to
+    /// stop the statements running on every session of the engine.
+    pub(crate) fn interrupt_counter(&self) -> Arc<AtomicU64> {
+        Arc::clone(&self.engine.interrupts)
+    }
+
+    pub(crate) fn subscribe_changes(&self) -> Receiver<crate::engine::ChangeEvent> {
+        self.engine.changes.subscribe()
+    }
+
+    fn statement_timeout(&self) -> Option<Duration> {
+        *self
+            .statement_timeout
+            .lock()
+            .unwrap_or_else(|err| err.into_inner())
+    }
+
     #[cfg_attr(not(feature = "testing-api"), allow(dead_code))]
-    pub(crate) fn database_snapshot(&self) -> Database {
-        self.database
+    pub(crate) fn database_snapshot(&self) -> Result<Database, RustqlError> {
+        let mut snapshot = self.database_read().clone();
+        snapshot.load_all_paged_tables()?;
+        Ok(snapshot)
+    }
+
+    /// The database this session sees: the snapshot of the running statement
+    /// or open transaction, or the committed database between statements.
+    pub(crate) fn database_read(&self) -> std::sync::RwLockReadGuard<'_, Database> {
+        let database = match self.view() {
+            View::Committed => &self.engine.database,
+            View::Statement | View::Transaction(_) => &self.workspace,
+        };
+        database.read().unwrap_or_else(|err| err.into_inner())
+    }
+
+    pub(crate) fn database_write(&self) -> std::sync::RwLockWriteGuard<'_, Database> {
+        let database = match self.view() {
+            View::Committed => &self.engine.database,
+            View::Statement | View::Transaction(_) => &self.workspace,
+        };
+        database.write().unwrap_or_else(|err| err.into_inner())
+    }
+
+    fn committed_snapshot(&self) -> Database {
+        self.engine
+            .database
             .read()
             .unwrap_or_else(|err| err.into_inner())
             .clone()
     }
 
-    pub(crate) fn database_read(&self) -> std::sync::RwLockReadGuard<'_, Database> {
-        self.database.read().unwrap_or_else(|err| err.into_inner())
+    pub(crate) fn storage(&self) -> Option<&Arc<dyn StorageEngine>> {
+        self.engine.storage.as_ref()
     }
 
-    pub(crate) fn database_write(&self) -> std::sync::RwLockWriteGuard<'_, Database> {
-        self.database.write().unwrap_or_else(|err| err.into_inner())
+    fn attachments(&self) -> std::sync::MutexGuard<'_, attach::Attachments> {
+        self.engine
+            .attached
+            .lock()
+            .unwrap_or_else(|err| err.into_inner())
+    }
+
+    fn attach_options(&self) -> &attach::AttachOptions {
+        &self.engine.attach_options
+    }
+
+    fn writer_guard(&self) -> std::sync::MutexGuard<'_, ()> {
+        self.engine
+            .writer_lock
+            .lock()
+            .unwrap_or_else(|err| err.into_inner())
+    }
+
+    fn commit_log(&self) -> std::sync::MutexGuard<'_, CommitLog> {
+        self.engine
+            .commits
+            .lock()
+            .unwrap_or_else(|err| err.into_inner())
+    }
+
+    /// Takes the next AUTO_INCREMENT value for `column` of `table`, whose
+    /// largest value in this session's snapshot is `current_max`; see
+    /// [`AutoIncrements`].
+    pub(crate) fn next_auto_increment(&self, table: &str, column: &str, current_max: i64) -> i64 {
+        self.engine
+            .auto_increments
+            .lock()
+            .unwrap_or_else(|err| err.into_inner())
+            .next_value(table, column, current_max)
     }
 
-    fn statement_guard(&self) -> std::sync::MutexGuard<'_, ()> {
-        self.statement_lock
+    fn transaction_state(&self) -> std::sync::MutexGuard<'_, TransactionState> {
+        self.transaction
             .lock()
             .unwrap_or_else(|err| err.into_inner())
     }
 
+    fn view(&self) -> View {
+        *self.view.lock().unwrap_or_else(|err| err.into_inner())
+    }
+
+    fn set_view(&self, view: View) {
+        *self.view.lock().unwrap_or_else(|err| err.into_inner()) = view;
+    }
+
+    /// The commit sequence number this session's transaction snapshot was
+    /// taken at, if a transaction is open.
+    fn snapshot(&self) -> Option<u64> {
+        match self.view() {
+            View::Transaction(seq) => Some(seq),
+            View::Committed | View::Statement => None,
+        }
+    }
+
+    fn begin_statement(&self) {
+        *self
+            .workspace
+            .write()
+            .unwrap_or_else(|err| err.into_inner()) = self.committed_snapshot();
+        self.set_view(View::Statement);
+    }
+
+    /// Drops the statement snapshot, unless the statement opened a
+    /// transaction.
+    fn end_statement(&self) {
+        if let View::Statement = self.view() {
+            self.set_view(View::Committed);
+            *self
+                .workspace
+                .write()
+                .unwrap_or_else(|err| err.into_inner()) = Database::new();
+        }
+    }
+
+    fn begin_snapshot(&self) {
+        let mut commits = self.commit_log();
+        *self
+            .workspace
+            .write()
+            .unwrap_or_else(|err| err.into_inner()) = self.committed_snapshot();
+        self.set_view(View::Transaction(commits.begin_snapshot()));
+    }
+
+    /// Drops the transaction snapshot and everything written to it.
+    fn end_snapshot(&self) {
+        if let Some(seq) = self.snapshot() {
+            self.set_view(View::Committed);
+            self.commit_log().end_snapshot(seq);
+        }
+        *self
+            .workspace
+            .write()
+            .unwrap_or_else(|err| err.into_inner()) = Database::new();
+        *self.transaction_state() = TransactionState::default();
+    }
+
+    /// Moves the transaction snapshot up to the latest commit, keeping the
+    /// transaction's own writes, so a `READ COMMITTED` statement sees every
+    /// commit made before it started.
+    ///
+    /// A commit that wrote the same rows as the transaction would fail its
+    /// `COMMIT`, so the transaction is discarded right away in that case.
+    fn refresh_snapshot(&self) -> Result<(), RustqlError> {
+        let Some(seq) = self.snapshot() else {
+            return Ok(());
+        };
+        let writes = self.with_wal_state(|state| state.pending_write_set());
+        let mut commits = self.commit_log();
+        if !commits.has_commits_since(seq) {
+            return Ok(());
+        }
+        if let Some(object) = commits.conflict_since(seq, &writes) {
+            drop(commits);
+            self.discard_transaction();
+            return Err(mvcc::serialization_failure(&object));
+        }
+
+        let mut refreshed = self.committed_snapshot();
+        let mut workspace = self
+            .workspace
+            .write()
+            .unwrap_or_else(|err| err.into_inner());
+        let deferred = self.transaction_state().deferred_checks.clone();
+        match mvcc::merge_transaction(&mut refreshed, &workspace, &writes, &deferred) {
+            Ok((_, renumbered)) => {
+                *workspace = refreshed;
+                self.with_wal_state_mut(|state| state.renumber_rows(&renumbered));
+                commits.end_snapshot(seq);
+                self.set_view(View::Transaction(commits.begin_snapshot()));
+                Ok(())
+            }
+            Err(err) => {
+                drop((workspace, commits));
+                if matches!(err, RustqlError::SerializationFailure(_)) {
+                    self.discard_transaction();
+                }
+                Err(err)
+            }
+        }
+    }
+
+    /// Makes `database` the committed database, records its writes so open
+    /// transactions can detect conflicts with them, and passes its row
+    /// changes on to subscribers. The caller holds the writer lock and has
+    /// already saved `database`.
+    fn publish(&self, database: Database, writes: WriteSet, changes: Vec<RowChange>) {
+        let mut commits = self.commit_log();
+        *self
+            .engine
+            .database
+            .write()
+            .unwrap_or_else(|err| err.into_inner()) = database;
+        if !writes.tables.is_empty() {
+            self.engine
+                .auto_increments
+                .lock()
+                .unwrap_or_else(|err| err.into_inner())
+                .reset(&writes.tables);
+        }
+        let seq = commits.record_commit(writes);
+        if !changes.is_empty() {
+            self.engine.changes.publish(seq, changes);
+        }
+    }
+
+    /// The row changes of this session's log that lead to `database`, if
+    /// anyone subscribed to them. Taken before `database` is saved, so that
+    /// a failure to read them leaves nothing committed.
+    fn row_changes(
+        &self,
+        database: &Database,
+        renumbered: &RowIdMap,
+    ) -> Result<Vec<RowChange>, RustqlError> {
+        if !self.engine.changes.has_subscribers() {
+            return Ok(Vec::new());
+        }
+        self.with_wal_state(|state| state.pending_row_changes(database, renumbered))
+    }
+
+    /// Commits the statement snapshot a write statement outside a
+    /// transaction has saved.
+    fn publish_statement(&self, writes: WriteSet, changes: Vec<RowChange>) {
+        let mut database = std::mem::take(
+            &mut *self
+                .workspace
+                .write()
+                .unwrap_or_else(|err| err.into_inner()),
+        );
+        self.page_out(&mut database);
+        self.publish(database, writes, changes);
+    }
+
+    /// Pages out the tables of `db`, which has just been saved, so that the
+    /// committed database only keeps their schema in memory and reads their
+    /// rows back from the files that store them.
+    fn page_out(&self, db: &mut Database) {
+        attach::page_out(self.engine.pager.as_ref(), &mut self.attachments(), db);
+    }
+
+    /// Makes the writes of this session's transaction part of the committed
+    /// database and saves them. The caller holds the writer lock, so no other
+    /// commit can land in between.
+    ///
+    /// Fails with [`RustqlError::SerializationFailure`] when a commit made
+    /// after the snapshot or a prepared transaction changed the same rows or
+    /// objects, when a prepared transaction could no longer commit after it,
+    /// or, for a `SERIALIZABLE` transaction that wrote, when a commit changed
+    /// a table or view it read, and with
+    /// [`RustqlError::ConstraintViolation`] when a deferred constraint does
+    /// not hold; the transaction is discarded in those cases. A storage error
+    /// leaves it open.
+    fn commit_snapshot(&self, writes: WriteSet, changes: ChangeSet) -> Result<(), RustqlError> {
+        let Some(seq) = self.snapshot() else {
+            return Ok(());
+        };
+        let has_commits = self.check_commit(seq, &writes)?;
+        let (mut database, changes, renumbered) =
+            self.merge_snapshot(&writes, changes, has_commits)?;
+        let row_changes = self.row_changes(&database, &renumbered)?;
+        self.persist_changes(&database, &changes)?;
+        self.page_out(&mut database);
+        self.publish(database, writes, row_changes);
+        Ok(())
+    }
+
+    /// Saves the writes of this session's transaction as the prepared
+    /// transaction `gid` instead of committing them. `COMMIT` would fail in
+    /// the same cases and discard the transaction. The caller holds the
+    /// writer lock.
+    fn prepare_snapshot(
+        &self,
+        gid: &str,
+        writes: WriteSet,
+        changes: ChangeSet,
+    ) -> Result<(), RustqlError> {
+        let Some(seq) = self.snapshot() else {
+            return Ok(());
+        };
+        if self
+            .engine
+            .database
+            .read()
+            .unwrap_or_else(|err| err.into_inner())
+            .prepared_transaction(gid)
+            .is_some()
+        {
+            return Err(RustqlError::TransactionError(format!(
+                "Transaction identifier '{}' is already in use",
+                gid
+            )));
+        }
+        attach::check_prepared_writes(&self.attachments(), &writes)?;
+        let has_commits = self.check_commit(seq, &writes)?;
+        self.merge_snapshot(&writes, changes, has_commits)?;
+
+        let prepared = {
+            let workspace = self.workspace.read().unwrap_or_else(|err| err.into_inner());
+            PreparedTransaction {
+                gid: gid.to_string(),
+                prepared_at: expr::current_datetime(),
+                changes: mvcc::transaction_changes(&workspace, &writes)?,
+                row_changes: self.with_wal_state(|state| {
+                    state.pending_row_changes(&workspace, &RowIdMap::new())
+                })?,
+                writes,
+            }
+        };
+        let mut database = self.committed_snapshot();
+        database.insert_prepared_transaction(prepared);
+        let mut changes = ChangeSet::new();
+        changes.record_prepared(gid);
+        self.persist_changes(&database, &changes)?;
+        self.page_out(&mut database);
+        self.publish(database, WriteSet::default(), Vec::new());
+        Ok(())
+    }
+
+    /// Commits the prepared transaction `gid`, or rolls it back when `commit`
+    /// is false. The caller holds the writer lock.
+    ///
+    /// Its writes are merged into the committed database the way `COMMIT`
+    /// merges a transaction's. Commits made since `PREPARE TRANSACTION` were
+    /// refused if they touched the same rows or objects or left it unable to
+    /// commit, such as by inserting the same key, so the merge does not fail
+    /// on them.
+    fn finish_prepared(&self, gid: &str, commit: bool) -> Result<(), RustqlError> {
+        let mut database = self.committed_snapshot();
+        let Some(prepared) = database.remove_prepared_transaction(gid) else {
+            return Err(RustqlError::TransactionError(format!(
+                "Prepared transaction '{}' does not exist",
+                gid
+            )));
+        };
+        let mut changes = ChangeSet::new();
+        let mut writes = WriteSet::default();
+        let mut row_changes = Vec::new();
+        if commit {
+            let renumbered;
+            (changes, renumbered) = mvcc::merge_transaction(
+                &mut database,
+                &prepared.changes,
+                &prepared.writes,
+                &BTreeSet::new(),
+            )?;
+            writes = prepared.writes.clone();
+            if self.engine.changes.has_subscribers() {
+                row_changes = prepared.row_changes.clone();
+                wal::renumber_row_changes(&mut row_changes, &renumbered);
+            }
+        }
+        changes.record_prepared(gid);
+        self.persist_changes(&database, &changes)?;
+        self.page_out(&mut database);
+        self.publish(database, writes, row_changes);
+        Ok(())
+    }
+
+    /// Checks that this session's transaction, which wrote `writes`, can
+    /// commit, and returns whether commits landed since its snapshot at
+    /// `seq`. The transaction is discarded when it cannot.
+    fn check_commit(&self, seq: u64, writes: &WriteSet) -> Result<bool, RustqlError> {
+        let checked = {
+            let commits = self.commit_log();
+            let transaction = self.transaction_state();
+            if let Some(object) = commits.conflict_since(seq, writes) {
+                Err(mvcc::serialization_failure(&object))
+            } else if let Some(object) =
+                (transaction.isolation_level == IsolationLevel::Serializable && !writes.is_empty())
+                    .then(|| commits.read_conflict_since(seq, &transaction.reads))
+                    .flatten()
+            {
+                // A transaction that only read can always be ordered at its
+                // snapshot, before the commits it did not see.
+                Err(mvcc::read_dependency_failure(&object))
+            } else {
+                let committed = self
+                    .engine
+                    .database
+                    .read()
+                    .unwrap_or_else(|err| err.into_inner());
+                mvcc::check_prepared_conflicts(&committed, writes)
+                    .map(|()| commits.has_commits_since(seq))
+            }
+        };
+        if checked.is_err() {
+            self.discard_transaction();
+        }
+        checked
+    }
+
+    /// Builds the committed database with this session's transaction merged
+    /// in and runs the constraint checks it deferred against it. A
+    /// serialization failure or a deferred constraint that does not hold
+    /// discards the transaction.
+    fn merge_snapshot(
+        &self,
+        writes: &WriteSet,
+        changes: ChangeSet,
+        has_commits: bool,
+    ) -> Result<(Database, ChangeSet, RowIdMap), RustqlError> {
+        let deferred = self.transaction_state().deferred_checks.clone();
+        let merged = {
+            let workspace = self.workspace.read().unwrap_or_else(|err| err.into_inner());
+            if has_commits {
+                let mut committed = self.committed_snapshot();
+                mvcc::merge_transaction(&mut committed, &workspace, writes, &deferred)
+                    .map(|(changes, renumbered)| (committed, changes, renumbered))
+            } else {
+                // Preparing a transaction or resolving a prepared one is not
+                // a commit, so the snapshot may list other prepared
+                // transactions than the committed database.
+                let mut database = workspace.clone();
+                database.copy_prepared_transactions(
+                    &self
+                        .engine
+                        .database
+                        .read()
+                        .unwrap_or_else(|err| err.into_inner()),
+                );
+                Ok((database, changes, RowIdMap::new()))
+            }
+        };
+        // Deferred checks see the commits merged in, which may have added
+        // the rows a deferred foreign key was waiting for.
+        let checked = merged.and_then(|(database, changes, renumbered)| {
+            if !deferred.is_empty() {
+                let row_changes =
+                    self.with_wal_state(|state| state.pending_row_changes(&database, &renumbered))?;
+                check::validate_constraints(&database, &row_changes, &deferred)?;
+            }
+            if !writes.is_empty() {
+                mvcc::check_prepared_commits(&database)?;
+            }
+            Ok((database, changes, renumbered))
+        });
+        if let Err(RustqlError::SerializationFailure(_) | RustqlError::ConstraintViolation { .. }) =
+            &checked
+        {
+            self.discard_transaction();
+        }
+        checked
+    }
+
+    /// Whether the check of a constraint declared with `deferrable` waits for
+    /// `COMMIT`. If it does, `table`, whose rows the check covers, is noted
+    /// for `COMMIT` to check.
+    pub(crate) fn defer_check(&self, deferrable: Deferrable, table: &str) -> bool {
+        if deferrable == Deferrable::No || self.snapshot().is_none() {
+            return false;
+        }
+        let mut transaction = self.transaction_state();
+        if !transaction.defers(deferrable) {
+            return false;
+        }
+        if !transaction.deferred_checks.contains(table) {
+            transaction.deferred_checks.insert(table.to_string());
+        }
+        true
+    }
+
+    /// Runs the constraint checks the transaction deferred so far against its
+    /// snapshot.
+    fn run_deferred_checks(&self) -> Result<(), RustqlError> {
+        let tables = self.transaction_state().deferred_checks.clone();
+        if tables.is_empty() {
+            return Ok(());
+        }
+        {
+            let workspace = self.workspace.read().unwrap_or_else(|err| err.into_inner());
+            let row_changes = self
+                .with_wal_state(|state| state.pending_row_changes(&workspace, &RowIdMap::new()))?;
+            check::validate_constraints(&workspace, &row_changes, &tables)?;
+        }
+        self.transaction_state().deferred_checks.clear();
+        Ok(())
+    }
+
+    fn discard_transaction(&self) {
+        self.with_wal_state_mut(|state| state.reset());
+        self.end_snapshot();
+        let _ = self.clear_transaction_persistence();
+    }
+
     fn persist_database(&self, db: &Database) -> Result<(), RustqlError> {
-        match &self.storage {
-            Some(storage) => storage.save(db),
-            None => Ok(()),
+        self.persist(db, None)
+    }
+
+    fn persist_changes(&self, db: &Database, changes: &ChangeSet) -> Result<(), RustqlError> {
+        self.persist(db, Some(changes))
+    }
+
+    /// Saves `db`, with the changes in `changes` or whole, to the engine's
+    /// storage and the files attached to it.
+    fn persist(&self, db: &Database, changes: Option<&ChangeSet>) -> Result<(), RustqlError> {
+        let mut attached = self.attachments();
+        if !attached.is_empty() {
+            let previous = self
+                .engine
+                .database
+                .read()
+                .unwrap_or_else(|err| err.into_inner());
+            return attach::persist(self.storage(), &mut attached, db, &previous, changes);
+        }
+        match (&self.engine.storage, changes) {
+            (Some(storage), Some(changes)) => storage.save_changes(db, changes),
+            (Some(storage), None) => storage.save(db),
+            (None, _) => Ok(()),
         }
     }
 
+    /// The part of `db` the engine's own storage holds, without the objects
+    /// of attached files.
+    fn stored_database(&self, db: &Database) -> Database {
+        attach::main_database(db, &self.attachments())
+    }
+
     fn begin_transaction_persistence(&self) -> Result<(), RustqlError> {
-        match &self.storage {
+        match &self.engine.storage {
             Some(storage) => storage.begin_transaction(),
             None => Ok(()),
         }
     }
 
-    fn prepare_commit_persistence(&self, db: &Database) -> Result<(), RustqlError> {
-        match &self.storage {
-            Some(storage) => storage.prepare_commit(db),
+    fn checkpoint_persistence(&self) -> Result<(), RustqlError> {
+        match &self.engine.storage {
+            Some(storage) => storage.checkpoint(),
             None => Ok(()),
         }
     }
 
+    /// Writes the committed database to a new B-tree file. In-memory engines
+    /// have no stored copy, so the committed database is written instead.
+    fn backup_persistence(&self, path: &Path) -> Result<(), RustqlError> {
+        match &self.engine.storage {
+            Some(storage) => storage.backup_to(path),
+            None => write_snapshot_backup(path, &self.stored_database(&self.committed_snapshot())),
+        }
+    }
+
+    /// Checks the stored file, then the tables and indexes as this session
+    /// sees them, including changes of its open transaction.
+    fn check_integrity(&self) -> Result<Vec<IntegrityProblem>, RustqlError> {
+        let mut problems = match &self.engine.storage {
+            Some(storage) => storage.check_integrity()?,
+            None => Vec::new(),
+        };
+        problems.extend(check::check_database(&self.database_read())?);
+        Ok(problems)
+    }
+
     fn clear_transaction_persistence(&self) -> Result<(), RustqlError> {
-        match &self.storage {
+        match &self.engine.storage {
             Some(storage) => storage.clear_transaction(),
             None => Ok(()),
         }
@@ -107,6 +739,15 @@ impl ExecutionContext {
     }
 }
 
+impl Drop for ExecutionContext {
+    /// A session closed inside a transaction rolls it back.
+    fn drop(&mut self) {
+        if self.snapshot().is_some() {
+            self.discard_transaction();
+        }
+    }
+}
+
 pub(crate) fn get_database_read(
     context: &ExecutionContext,
 ) -> std::sync::RwLockReadGuard<'_, Database> {
@@ -162,11 +803,19 @@ fn infer_data_type(rows: &[Vec<Value>], idx: usize) -> DataType {
             Value::Null => None,
             Value::Integer(_) => Some(DataType::Integer),
             Value::Float(_) => Some(DataType::Float),
+            Value::Decimal(value) => Some(DataType::Decimal {
+                precision: MAX_DECIMAL_PRECISION,
+                scale: value.scale(),
+            }),
             Value::Text(_) => Some(DataType::Text),
             Value::Boolean(_) => Some(DataType::Boolean),
             Value::Date(_) => Some(DataType::Date),
             Value::Time(_) => Some(DataType::Time),
             Value::DateTime(_) => Some(DataType::DateTime),
+            Value::TimestampTz(_) => Some(DataType::TimestampTz),
+            Value::Interval(_) => Some(DataType::Interval),
+            Value::Blob(_) => Some(DataType::Blob),
+            Value::Json(_) => Some(DataType::Json),
         })
         .unwrap_or(DataType::Text)
 }
@@ -175,7 +824,46 @@ pub(crate) fn execute(
     context: &ExecutionContext,
     statement: Statement,
 ) -> Result<QueryResult, RustqlError> {
-    let _statement_guard = context.statement_guard();
+    let _watch =
+        interrupt::StatementWatch::start(&context.engine.interrupts, context.statement_timeout());
+    if context.is_following()
+        && !is_read_only(&statement)
+        && (!is_transaction_control(&statement)
+            || matches!(statement, Statement::PrepareTransaction(_)))
+    {
+        return Err(RustqlError::TransactionError(format!(
+            "Cannot execute {} on a read-only follower",
+            statement_name(&statement)
+        )));
+    }
+    let in_transaction = context.snapshot().is_some();
+    if in_transaction {
+        prepare_transaction_statement(context, &statement)?;
+    }
+    let _writer_guard =
+        takes_writer_lock(&statement, in_transaction).then(|| context.writer_guard());
+    let statement_snapshot = !in_transaction
+        && !matches!(
+            statement,
+            Statement::BeginTransaction(_)
+                | Statement::SetTransaction(_)
+                | Statement::SetConstraints { .. }
+                | Statement::SetStatementTimeout { .. }
+        );
+    if statement_snapshot {
+        context.begin_statement();
+    }
+    let result = execute_in_view(context, statement);
+    if statement_snapshot {
+        context.end_statement();
+    }
+    result
+}
+
+fn execute_in_view(
+    context: &ExecutionContext,
+    statement: Statement,
+) -> Result<QueryResult, RustqlError> {
     let statement = bind_statement_for_execution(context, statement)?;
 
     if requires_statement_savepoint(&statement) {
@@ -185,12 +873,175 @@ pub(crate) fn execute(
     execute_statement_inner(context, statement)
 }
 
+/// Applies the modes of the open transaction to a statement about to run in
+/// it.
+fn prepare_transaction_statement(
+    context: &ExecutionContext,
+    statement: &Statement,
+) -> Result<(), RustqlError> {
+    if is_transaction_control(statement) {
+        return Ok(());
+    }
+    let (isolation_level, read_only) = {
+        let mut transaction = context.transaction_state();
+        transaction.started = true;
+        (transaction.isolation_level, transaction.read_only)
+    };
+    if read_only && !is_read_only(statement) {
+        return Err(RustqlError::TransactionError(format!(
+            "Cannot execute {} in a read-only transaction",
+            statement_name(statement)
+        )));
+    }
+    if isolation_level == IsolationLevel::ReadCommitted {
+        context.refresh_snapshot()?;
+    }
+    Ok(())
+}
+
+fn is_transaction_control(statement: &Statement) -> bool {
+    matches!(
+        statement,
+        Statement::BeginTransaction(_)
+            | Statement::SetTransaction(_)
+            | Statement::SetConstraints { .. }
+            | Statement::CommitTransaction
+            | Statement::RollbackTransaction
+            | Statement::Savepoint(_)
+            | Statement::ReleaseSavepoint(_)
+            | Statement::RollbackToSavepoint(_)
+            | Statement::PrepareTransaction(_)
+    )
+}
+
+/// The SQL command a statement runs, for error messages.
+fn statement_name(statement: &Statement) -> &'static str {
+    match statement {
+        Statement::Select(_) => "SELECT",
+        Statement::Insert(_) => "INSERT",
+        Statement::Update(_) => "UPDATE",
+        Statement::Delete(_) => "DELETE",
+        Statement::Merge(_) => "MERGE",
+        Statement::CreateTable(_) => "CREATE TABLE",
+        Statement::DropTable(_) => "DROP TABLE",
+        Statement::AlterTable(_) => "ALTER TABLE",
+        Statement::CreateIndex(_) => "CREATE INDEX",
+        Statement::DropIndex(_) => "DROP INDEX",
+        Statement::TruncateTable { .. } => "TRUNCATE TABLE",
+        Statement::CreateView { .. } => "CREATE VIEW",
+        Statement::DropView { .. } => "DROP VIEW",
+        Statement::BeginTransaction(_) => "BEGIN",
+        Statement::SetTransaction(_) => "SET TRANSACTION",
+        Statement::SetConstraints { .. } => "SET CONSTRAINTS",
+        Statement::SetStatementTimeout { .. } => "SET",
+        Statement::CommitTransaction => "COMMIT",
+        Statement::RollbackTransaction => "ROLLBACK",
+        Statement::Savepoint(_) => "SAVEPOINT",
+        Statement::ReleaseSavepoint(_) => "RELEASE SAVEPOINT",
+        Statement::RollbackToSavepoint(_) => "ROLLBACK TO SAVEPOINT",
+        Statement::PrepareTransaction(_) => "PREPARE TRANSACTION",
+        Statement::CommitPrepared(_) => "COMMIT PREPARED",
+        Statement::RollbackPrepared(_) => "ROLLBACK PREPARED",
+        Statement::Explain(_) => "EXPLAIN",
+        Statement::ExplainAnalyze(_) => "EXPLAIN ANALYZE",
+        Statement::Describe(_) => "DESCRIBE",
+        Statement::ShowTables | Statement::ShowStorage => "SHOW",
+        Statement::Analyze(_) => "ANALYZE",
+        Statement::Do { .. } => "DO",
+        Statement::Checkpoint => "CHECKPOINT",
+        Statement::Backup { .. } => "BACKUP",
+        Statement::Vacuum => "VACUUM",
+        Statement::CheckDatabase => "CHECK DATABASE",
+        Statement::Attach { .. } => "ATTACH",
+        Statement::Detach(_) => "DETACH",
+    }
+}
+
+/// Whether a statement waits for other writers. Outside a transaction that
+/// is every statement that is not read-only; inside one, statements only
+/// change the transaction's own snapshot until `COMMIT`.
+fn takes_writer_lock(statement: &Statement, in_transaction: bool) -> bool {
+    match statement {
+        Statement::BeginTransaction(_)
+        | Statement::SetTransaction(_)
+        | Statement::SetConstraints { .. } => false,
+        Statement::CommitTransaction
+        | Statement::PrepareTransaction(_)
+        | Statement::Checkpoint
+        | Statement::Vacuum => true,
+        _ => !in_transaction && !is_read_only(statement),
+    }
+}
+
+/// Statements that never change the database or its storage file, and so
+/// run in parallel with each other and with writers.
+fn is_read_only(statement: &Statement) -> bool {
+    matches!(
+        statement,
+        Statement::Select(_)
+            | Statement::Explain(_)
+            | Statement::ExplainAnalyze(_)
+            | Statement::Describe(_)
+            | Statement::ShowTables
+            | Statement::ShowStorage
+            | Statement::CheckDatabase
+            | Statement::Backup { .. }
+            | Statement::SetStatementTimeout { .. }
+    )
+}
+
+/// Writes the committed database to a new B-tree file at `path`.
+pub(crate) fn backup(context: &ExecutionContext, path: &Path) -> Result<(), RustqlError> {
+    context.backup_persistence(path)
+}
+
+/// Reports every integrity problem found in storage and in the database.
+pub(crate) fn check_database(
+    context: &ExecutionContext,
+) -> Result<Vec<IntegrityProblem>, RustqlError> {
+    let _writer_guard = context.writer_guard();
+    context.check_integrity()
+}
+
+/// Rewrites the storage file encrypted with `key`, or unencrypted when `key`
+/// is `None`.
+pub(crate) fn rekey(
+    context: &ExecutionContext,
+    key: Option<EncryptionKey>,
+) -> Result<(), RustqlError> {
+    if context.is_following() {
+        return Err(RustqlError::TransactionError(
+            "Cannot rekey a read-only follower".to_string(),
+        ));
+    }
+    let _writer_guard = context.writer_guard();
+    match context.storage() {
+        Some(storage) => storage.rekey(&context.stored_database(&context.database_read()), key),
+        None => Err(RustqlError::StorageError(
+            "Encryption requires B-tree storage".to_string(),
+        )),
+    }
+}
+
+/// Binds a statement against the session's view. A `SERIALIZABLE`
+/// transaction also records every table and view the statement reads.
 fn bind_statement_for_execution(
     context: &ExecutionContext,
     statement: Statement,
 ) -> Result<Statement, RustqlError> {
     let db = get_database_read(context);
-    Ok(crate::binder::bind_statement(&*db, statement)?.into_statement())
+    let serializable = context.snapshot().is_some()
+        && context.transaction_state().isolation_level == IsolationLevel::Serializable;
+    if !serializable {
+        return Ok(crate::binder::bind_statement(&*db, statement)?.into_statement());
+    }
+    let recorder = mvcc::ReadRecorder::new(&db);
+    let bound = crate::binder::bind_statement(&recorder, statement);
+    context
+        .transaction_state()
+        .reads
+        .extend(recorder.into_reads());
+    Ok(bound?.into_statement())
 }
 
 fn execute_statement_inner(
@@ -207,13 +1058,34 @@ fn execute_statement_inner(
         Statement::AlterTable(stmt) => ddl::execute_alter_table(context, stmt),
         Statement::CreateIndex(stmt) => ddl::execute_create_index(context, stmt),
         Statement::DropIndex(stmt) => ddl::execute_drop_index(context, stmt),
-        Statement::BeginTransaction => execute_begin_transaction(context),
+        Statement::BeginTransaction(modes) => execute_begin_transaction(context, modes),
+        Statement::SetTransaction(modes) => execute_set_transaction(context, modes),
+        Statement::SetConstraints { deferred } => execute_set_constraints(context, deferred),
+        Statement::SetStatementTimeout { milliseconds } => {
+            execute_set_statement_timeout(context, milliseconds)
+        }
         Statement::CommitTransaction => execute_commit_transaction(context),
         Statement::RollbackTransaction => execute_rollback_transaction(context),
+        Statement::PrepareTransaction(gid) => execute_prepare_transaction(context, gid),
+        Statement::CommitPrepared(gid) => execute_finish_prepared(context, gid, true),
+        Statement::RollbackPrepared(gid) => execute_finish_prepared(context, gid, false),
+        Statement::Checkpoint => {
+            context.checkpoint_persistence()?;
+            Ok(command_result(CommandTag::Checkpoint, 0))
+        }
+        Statement::Backup { path } => {
+            context.backup_persistence(Path::new(&path))?;
+            Ok(command_result(CommandTag::Backup, 0))
+        }
+        Statement::Vacuum => execute_vacuum(context),
+        Statement::CheckDatabase => check::execute_check_database(context),
+        Statement::Attach { path, name } => attach::execute_attach(context, path, name),
+        Statement::Detach(name) => attach::execute_detach(context, name),
         Statement::Explain(stmt) => execute_explain(context, stmt),
         Statement::ExplainAnalyze(stmt) => execute_explain_analyze(context, stmt),
         Statement::Describe(table_name) => ddl::execute_describe(context, table_name),
         Statement::ShowTables => ddl::execute_show_tables(context),
+        Statement::ShowStorage => ddl::execute_show_storage(context),
         Statement::Savepoint(name) => execute_savepoint(context, name),
         Statement::ReleaseSavepoint(name) => execute_release_savepoint(context, name),
         Statement::RollbackToSavepoint(name) => execute_rollback_to_savepoint(context, name),
@@ -267,14 +1139,28 @@ fn execute_atomic_statement(
     match result {
         Ok(result) => {
             if savepoint.is_autocommit_statement() {
+                let writes = context.with_wal_state(|state| state.pending_write_set());
                 let persist_result = {
                     let db = get_database_read(context);
-                    context.persist_database(&db)
+                    mvcc::check_prepared_conflicts(&db, &writes)
+                        .and_then(|()| mvcc::check_prepared_commits(&db))
+                        .and_then(|()| context.row_changes(&db, &RowIdMap::new()))
+                        .and_then(|row_changes| {
+                            match context.with_wal_state(|state| state.pending_changes()) {
+                                Some(changes) => context.persist_changes(&db, &changes),
+                                None => context.persist_database(&db),
+                            }
+                            .map(|()| row_changes)
+                        })
                 };
-                if let Err(err) = persist_result {
-                    rollback_statement(context, savepoint)?;
-                    return Err(err);
-                }
+                let row_changes = match persist_result {
+                    Ok(row_changes) => row_changes,
+                    Err(err) => {
+                        rollback_statement(context, savepoint)?;
+                        return Err(err);
+                    }
+                };
+                context.publish_statement(writes, row_changes);
             }
 
             context.with_wal_state_mut(|state| state.commit_statement(savepoint))?;
@@ -305,15 +1191,57 @@ pub(crate) fn save_if_not_in_transaction(
     Ok(())
 }
 
-fn execute_begin_transaction(context: &ExecutionContext) -> Result<QueryResult, RustqlError> {
+fn execute_begin_transaction(
+    context: &ExecutionContext,
+    modes: TransactionModes,
+) -> Result<QueryResult, RustqlError> {
     context.with_wal_state_mut(|state| state.begin_transaction())?;
     if let Err(err) = context.begin_transaction_persistence() {
         context.with_wal_state_mut(|state| state.reset());
         return Err(err);
     }
+    context.begin_snapshot();
+    context.transaction_state().apply(&modes);
     Ok(command_result(CommandTag::BeginTransaction, 0))
 }
 
+fn execute_set_transaction(
+    context: &ExecutionContext,
+    modes: TransactionModes,
+) -> Result<QueryResult, RustqlError> {
+    if context.snapshot().is_none() {
+        return Err(RustqlError::TransactionError(
+            "SET TRANSACTION can only be used inside a transaction".to_string(),
+        ));
+    }
+    let mut transaction = context.transaction_state();
+    if transaction.started {
+        return Err(RustqlError::TransactionError(
+            "SET TRANSACTION must be called before any query in the transaction".to_string(),
+        ));
+    }
+    transaction.apply(&modes);
+    Ok(command_result(CommandTag::SetTransaction, 0))
+}
+
+fn execute_set_constraints(
+    context: &ExecutionContext,
+    deferred: bool,
+) -> Result<QueryResult, RustqlError> {
+    if context.snapshot().is_none() {
+        return Err(RustqlError::TransactionError(
+            "SET CONSTRAINTS can only be used inside a transaction".to_string(),
+        ));
+    }
+    if !deferred {
+        // Checks deferred so far are due as soon as the constraints become
+        // immediate.
+        context.run_deferred_checks()?;
+    }
+    context.transaction_state().constraints_deferred = Some(deferred);
+    Ok(command_result(CommandTag::SetConstraints, 0))
+}
+
 fn execute_commit_transaction(context: &ExecutionContext) -> Result<QueryResult, RustqlError> {
     if !context.with_wal_state(|state| state.is_in_transaction()) {
         return Err(RustqlError::TransactionError(
@@ -321,23 +1249,102 @@ fn execute_commit_transaction(context: &ExecutionContext) -> Result<QueryResult,
         ));
     }
 
-    let db = get_database_read(context);
-    context.prepare_commit_persistence(&db)?;
-    context.persist_database(&db)?;
+    let (writes, changes) = context.with_wal_state(|state| {
+        (
+            state.pending_write_set(),
+            state.pending_changes().unwrap_or_default(),
+        )
+    });
+    context.commit_snapshot(writes, changes)?;
     context.clear_transaction_persistence()?;
-    drop(db);
     context.with_wal_state_mut(|state| state.commit_transaction())?;
+    context.end_snapshot();
     Ok(command_result(CommandTag::CommitTransaction, 0))
 }
 
+/// Ends the transaction the way `COMMIT` would, but keeps its writes as a
+/// prepared transaction named `gid` for `COMMIT PREPARED` or `ROLLBACK
+/// PREPARED` to resolve, in this process or another.
+fn execute_prepare_transaction(
+    context: &ExecutionContext,
+    gid: String,
+) -> Result<QueryResult, RustqlError> {
+    if !context.with_wal_state(|state| state.is_in_transaction()) {
+        return Err(RustqlError::TransactionError(
+            "No transaction in progress".to_string(),
+        ));
+    }
+
+    let (writes, changes) = context.with_wal_state(|state| {
+        (
+            state.pending_write_set(),
+            state.pending_changes().unwrap_or_default(),
+        )
+    });
+    context.prepare_snapshot(&gid, writes, changes)?;
+    context.clear_transaction_persistence()?;
+    context.with_wal_state_mut(|state| state.commit_transaction())?;
+    context.end_snapshot();
+    Ok(command_result(CommandTag::PrepareTransaction, 0))
+}
+
+fn execute_finish_prepared(
+    context: &ExecutionContext,
+    gid: String,
+    commit: bool,
+) -> Result<QueryResult, RustqlError> {
+    let (name, tag) = if commit {
+        ("COMMIT PREPARED", CommandTag::CommitPrepared)
+    } else {
+        ("ROLLBACK PREPARED", CommandTag::RollbackPrepared)
+    };
+    if context.with_wal_state(|state| state.is_in_transaction()) {
+        return Err(RustqlError::TransactionError(format!(
+            "{} cannot run inside a transaction",
+            name
+        )));
+    }
+    context.finish_prepared(&gid, commit)?;
+    Ok(command_result(tag, 0))
+}
+
 fn execute_rollback_transaction(context: &ExecutionContext) -> Result<QueryResult, RustqlError> {
-    let mut db = get_database_write(context);
-    context.with_wal_state_mut(|state| state.rollback_transaction(&mut db))?;
-    drop(db);
+    if !context.with_wal_state(|state| state.is_in_transaction()) {
+        return Err(RustqlError::TransactionError(
+            "No transaction in progress".to_string(),
+        ));
+    }
+    // The transaction only ever wrote to its own snapshot.
+    context.with_wal_state_mut(|state| state.reset());
+    context.end_snapshot();
     context.clear_transaction_persistence()?;
     Ok(command_result(CommandTag::RollbackTransaction, 0))
 }
 
+fn execute_vacuum(context: &ExecutionContext) -> Result<QueryResult, RustqlError> {
+    if context.with_wal_state(|state| state.is_in_transaction()) {
+        return Err(RustqlError::TransactionError(
+            "VACUUM cannot run inside a transaction".to_string(),
+        ));
+    }
+    let stats = match context.storage() {
+        Some(storage) => storage.vacuum(&context.stored_database(&get_database_read(context)))?,
+        None => VacuumStats::default(),
+    };
+    Ok(rows_result(SelectResult {
+        headers: vec![
+            "bytes_before".to_string(),
+            "bytes_after".to_string(),
+            "bytes_reclaimed".to_string(),
+        ],
+        rows: vec![vec![
+            Value::Integer(stats.bytes_before as i64),
+            Value::Integer(stats.bytes_after as i64),
+            Value::Integer(stats.bytes_reclaimed() as i64),
+        ]],
+    }))
+}
+
 fn execute_explain(
     context: &ExecutionContext,
     stmt: SelectStatement,
@@ -371,6 +1378,18 @@ fn execute_explain_analyze(
     }))
 }
 
+fn execute_set_statement_timeout(
+    context: &ExecutionContext,
+    milliseconds: u64,
+) -> Result<QueryResult, RustqlError> {
+    *context
+        .statement_timeout
+        .lock()
+        .unwrap_or_else(|err| err.into_inner()) =
+        (milliseconds > 0).then(|| Duration::from_millis(milliseconds));
+    Ok(command_result(CommandTag::SetStatementTimeout, 0))
+}
+
 fn execute_savepoint(context: &ExecutionContext, name: String) -> Result<QueryResult, RustqlError> {
     context.with_wal_state_mut(|state| state.savepoint(&name))?;
     Ok(command_result(CommandTag::Savepoint, 0))
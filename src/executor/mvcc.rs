@@ -0,0 +1,488 @@
+use crate::ast::{Deferrable, IsolationLevel, TransactionModes};
+use crate::database::{CompositeIndex, Database, DatabaseCatalog, Index, RowId, Table, View};
+use crate::error::{IntegrityProblem, RustqlError};
+use crate::storage::ChangeSet;
+use crate::wal::{self, RowChange, WriteSet};
+use std::borrow::Cow;
+use std::cell::RefCell;
+use std::collections::{BTreeMap, BTreeSet, HashMap, VecDeque};
+
+/// New ids a merge gave the rows a transaction inserted, by table.
+pub(crate) type RowIdMap = BTreeMap<String, HashMap<RowId, RowId>>;
+
+/// The modes of a session's open transaction and what it has read.
+#[derive(Debug, Default)]
+pub(crate) struct TransactionState {
+    pub(crate) isolation_level: IsolationLevel,
+    pub(crate) read_only: bool,
+    /// Tables and views a `SERIALIZABLE` transaction has read.
+    pub(crate) reads: BTreeSet<String>,
+    /// Set by the first statement that is not transaction control; the
+    /// modes are fixed from then on.
+    pub(crate) started: bool,
+    /// The last `SET CONSTRAINTS ALL`: `Some(true)` defers every deferrable
+    /// constraint and `Some(false)` none of them.
+    pub(crate) constraints_deferred: Option<bool>,
+    /// Tables whose rows may break a constraint whose check was deferred;
+    /// `COMMIT` checks them.
+    pub(crate) deferred_checks: BTreeSet<String>,
+}
+
+impl TransactionState {
+    pub(crate) fn apply(&mut self, modes: &TransactionModes) {
+        if let Some(level) = modes.isolation_level {
+            self.isolation_level = level;
+        }
+        if let Some(read_only) = modes.read_only {
+            self.read_only = read_only;
+        }
+    }
+
+    /// Whether a constraint declared with `deferrable` is checked at
+    /// `COMMIT` rather than by each statement.
+    pub(crate) fn defers(&self, deferrable: Deferrable) -> bool {
+        match deferrable {
+            Deferrable::No => false,
+            Deferrable::InitiallyImmediate => self.constraints_deferred == Some(true),
+            Deferrable::InitiallyDeferred => self.constraints_deferred != Some(false),
+        }
+    }
+}
+
+/// A catalog that notes every table and view the binder looks up, which is
+/// every relation a statement reads.
+pub(crate) struct ReadRecorder<'a> {
+    database: &'a Database,
+    reads: RefCell<BTreeSet<String>>,
+}
+
+impl<'a> ReadRecorder<'a> {
+    pub(crate) fn new(database: &'a Database) -> Self {
+        Self {
+            database,
+            reads: RefCell::new(BTreeSet::new()),
+        }
+    }
+
+    pub(crate) fn into_reads(self) -> BTreeSet<String> {
+        self.reads.into_inner()
+    }
+
+    fn record(&self, name: &str) {
+        if !self.reads.borrow().contains(name) {
+            self.reads.borrow_mut().insert(name.to_string());
+        }
+    }
+}
+
+impl DatabaseCatalog for ReadRecorder<'_> {
+    fn get_table(&self, name: &str) -> Option<&Table> {
+        self.record(name);
+        self.database.get_table(name)
+    }
+
+    fn get_index(&self, name: &str) -> Option<&Index> {
+        self.database.get_index(name)
+    }
+
+    fn get_view(&self, name: &str) -> Option<&View> {
+        self.record(name);
+        self.database.get_view(name)
+    }
+
+    fn get_composite_index(&self, name: &str) -> Option<&CompositeIndex> {
+        self.database.get_composite_index(name)
+    }
+
+    fn indexes_iter(&self) -> Box<dyn Iterator<Item = &Index> + '_> {
+        self.database.indexes_iter()
+    }
+
+    fn composite_indexes_iter(&self) -> Box<dyn Iterator<Item = &CompositeIndex> + '_> {
+        self.database.composite_indexes_iter()
+    }
+}
+
+/// Commits made while transactions were open, kept until no open snapshot
+/// predates them.
+///
+/// Every commit that changes the database takes the next sequence number. A
+/// transaction remembers the number current at `BEGIN`; any logged commit
+/// with a higher number happened after its snapshot.
+#[derive(Debug, Default)]
+pub(crate) struct CommitLog {
+    last_seq: u64,
+    commits: VecDeque<(u64, WriteSet)>,
+    snapshots: BTreeMap<u64, usize>,
+}
+
+impl CommitLog {
+    /// Registers a snapshot of the current committed state and returns its
+    /// sequence number.
+    pub(crate) fn begin_snapshot(&mut self) -> u64 {
+        *self.snapshots.entry(self.last_seq).or_default() += 1;
+        self.last_seq
+    }
+
+    pub(crate) fn end_snapshot(&mut self, seq: u64) {
+        if let Some(count) = self.snapshots.get_mut(&seq) {
+            *count -= 1;
+            if *count == 0 {
+                self.snapshots.remove(&seq);
+            }
+        }
+        self.prune();
+    }
+
+    pub(crate) fn has_commits_since(&self, seq: u64) -> bool {
+        self.last_seq > seq
+    }
+
+    /// Describes the first write of a commit after `seq` that `writes`
+    /// conflicts with.
+    pub(crate) fn conflict_since(&self, seq: u64, writes: &WriteSet) -> Option<String> {
+        self.commits
+            .iter()
+            .filter(|(commit_seq, _)| *commit_seq > seq)
+            .find_map(|(_, committed)| writes.conflict_with(committed))
+    }
+
+    /// Describes the first table or view in `reads` that a commit after
+    /// `seq` wrote to.
+    pub(crate) fn read_conflict_since(&self, seq: u64, reads: &BTreeSet<String>) -> Option<String> {
+        self.commits
+            .iter()
+            .filter(|(commit_seq, _)| *commit_seq > seq)
+            .find_map(|(_, committed)| committed.read_conflict_with(reads))
+    }
+
+    /// Records a commit and returns its sequence number, which is the
+    /// previous one if the commit wrote nothing.
+    pub(crate) fn record_commit(&mut self, writes: WriteSet) -> u64 {
+        if writes.is_empty() {
+            return self.last_seq;
+        }
+        self.last_seq += 1;
+        if !self.snapshots.is_empty() {
+            self.commits.push_back((self.last_seq, writes));
+        }
+        self.last_seq
+    }
+
+    fn prune(&mut self) {
+        match self.snapshots.keys().next().copied() {
+            Some(oldest) => {
+                while self
+                    .commits
+                    .front()
+                    .is_some_and(|(commit_seq, _)| *commit_seq <= oldest)
+                {
+                    self.commits.pop_front();
+                }
+            }
+            None => self.commits.clear(),
+        }
+    }
+}
+
+/// The AUTO_INCREMENT values handed out so far, by table and column.
+///
+/// Values are taken from here rather than from the rows a snapshot holds, so
+/// transactions inserting into the same table at once get different values
+/// and do not clash when they commit. As with a sequence, a value is used up
+/// even if the transaction that took it rolls back.
+#[derive(Debug, Default)]
+pub(crate) struct AutoIncrements {
+    last: HashMap<(String, String), i64>,
+}
+
+impl AutoIncrements {
+    /// Takes the next value for `column` of `table`: one above both the last
+    /// value handed out and `current_max`, the largest one the caller's
+    /// snapshot holds.
+    pub(crate) fn next_value(&mut self, table: &str, column: &str, current_max: i64) -> i64 {
+        let last = self
+            .last
+            .entry((table.to_string(), column.to_string()))
+            .or_insert(current_max);
+        *last = (*last).max(current_max) + 1;
+        *last
+    }
+
+    /// Forgets the values handed out for `tables`, which a commit created,
+    /// dropped or replaced whole, so their values follow the rows again.
+    pub(crate) fn reset(&mut self, tables: &BTreeSet<String>) {
+        self.last.retain(|(table, _), _| !tables.contains(table));
+    }
+}
+
+pub(crate) fn serialization_failure(object: &str) -> RustqlError {
+    RustqlError::SerializationFailure(format!(
+        "Could not serialize transaction: {} was changed by a concurrent transaction",
+        object
+    ))
+}
+
+/// Fails when `writes` touch a row or object that a transaction prepared in
+/// `database` wrote; it holds them until it is committed or rolled back.
+pub(crate) fn check_prepared_conflicts(
+    database: &Database,
+    writes: &WriteSet,
+) -> Result<(), RustqlError> {
+    for prepared in database.prepared_transactions.values() {
+        if let Some(object) = writes.conflict_with(&prepared.writes) {
+            return Err(RustqlError::SerializationFailure(format!(
+                "Could not serialize transaction: {} was changed by prepared transaction '{}'",
+                object, prepared.gid
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// Fails when a transaction prepared in `database` could no longer be
+/// committed on top of it, such as after the writes that led to `database`
+/// inserted a key it inserted or deleted a row its rows refer to. Every
+/// writer runs this check, so `COMMIT PREPARED` never fails on such a clash.
+pub(crate) fn check_prepared_commits(database: &Database) -> Result<(), RustqlError> {
+    for prepared in database.prepared_transactions.values() {
+        let mut committed = database.clone();
+        merge_transaction(
+            &mut committed,
+            &prepared.changes,
+            &prepared.writes,
+            &BTreeSet::new(),
+        )
+        .map_err(|err| match err {
+            RustqlError::SerializationFailure(_) => RustqlError::SerializationFailure(format!(
+                "Could not serialize transaction: prepared transaction '{}' could not commit after it",
+                prepared.gid
+            )),
+            other => other,
+        })?;
+    }
+    Ok(())
+}
+
+pub(crate) fn read_dependency_failure(object: &str) -> RustqlError {
+    RustqlError::SerializationFailure(format!(
+        "Could not serialize transaction: {}, which it read, was changed by a concurrent transaction",
+        object
+    ))
+}
+
+/// Applies the writes a transaction made to its snapshot `workspace` on top
+/// of `committed`, which has moved on since the snapshot was taken, and
+/// returns the records storage has to rewrite along with the new ids of the
+/// inserted rows.
+///
+/// The caller has already checked that no newer commit touched the same
+/// rows or objects. Inserted rows get fresh ids from the committed table, so
+/// inserts from concurrent transactions never collide; tables the
+/// transaction altered are taken from the workspace whole. Constraint
+/// problems in the `deferred` tables are left for `COMMIT` to report.
+pub(crate) fn merge_transaction(
+    committed: &mut Database,
+    workspace: &Database,
+    writes: &WriteSet,
+    deferred: &BTreeSet<String>,
+) -> Result<(ChangeSet, RowIdMap), RustqlError> {
+    let mut changes = ChangeSet::new();
+    let mut renumbered = RowIdMap::new();
+
+    for name in &writes.tables {
+        match workspace.tables.get(name) {
+            Some(table) => {
+                committed.tables.insert(name.clone(), table.clone());
+            }
+            None => {
+                committed.tables.remove(name);
+            }
+        }
+        committed.indexes.retain(|_, index| index.table != *name);
+        committed
+            .composite_indexes
+            .retain(|_, index| index.table != *name);
+        for (index_name, index) in &workspace.indexes {
+            if index.table == *name {
+                committed.indexes.insert(index_name.clone(), index.clone());
+            }
+        }
+        for (index_name, index) in &workspace.composite_indexes {
+            if index.table == *name {
+                committed
+                    .composite_indexes
+                    .insert(index_name.clone(), index.clone());
+            }
+        }
+        changes.record_table(name);
+    }
+    // Tables taken whole are saved whole, so their rows are read in.
+    committed.load_paged_tables(writes.tables.iter().map(String::as_str))?;
+
+    let mut reindexed = BTreeSet::new();
+    let mut row_changes = Vec::new();
+    let row_tables: BTreeSet<&String> = writes.rows.keys().chain(writes.inserted.keys()).collect();
+    for name in row_tables {
+        if writes.tables.contains(name) {
+            continue;
+        }
+        let (Some(source), Some(target)) =
+            (workspace.tables.get(name), committed.tables.get_mut(name))
+        else {
+            continue;
+        };
+        for row_id in writes.rows.get(name).into_iter().flatten() {
+            let old_row = target.fetch_row(*row_id)?.map(Cow::into_owned);
+            let new_row = source.fetch_row(*row_id)?.map(Cow::into_owned);
+            match &new_row {
+                Some(row) => {
+                    target.set_row_by_id(*row_id, row.clone())?;
+                }
+                None => {
+                    target.remove_row_by_id(*row_id)?;
+                }
+            }
+            changes.record_row(name, *row_id);
+            row_changes.push(RowChange {
+                table: name.clone(),
+                row_id: *row_id,
+                old_row,
+                new_row,
+            });
+        }
+        for row_id in writes.inserted.get(name).into_iter().flatten() {
+            if let Some(row) = source.fetch_row(*row_id)? {
+                let row = row.into_owned();
+                let new_row_id = target.insert_row(row.clone());
+                changes.record_row(name, new_row_id);
+                row_changes.push(RowChange {
+                    table: name.clone(),
+                    row_id: new_row_id,
+                    old_row: None,
+                    new_row: Some(row),
+                });
+                renumbered
+                    .entry(name.clone())
+                    .or_default()
+                    .insert(*row_id, new_row_id);
+            }
+        }
+        reindexed.insert(name.clone());
+    }
+
+    for name in &writes.indexes {
+        committed.indexes.remove(name);
+        committed.composite_indexes.remove(name);
+        if let Some(index) = workspace.indexes.get(name) {
+            committed.indexes.insert(name.clone(), index.clone());
+            reindexed.insert(index.table.clone());
+        }
+        if let Some(index) = workspace.composite_indexes.get(name) {
+            committed
+                .composite_indexes
+                .insert(name.clone(), index.clone());
+            reindexed.insert(index.table.clone());
+        }
+        changes.record_index(name);
+    }
+    reindexed.retain(|name| !writes.tables.contains(name));
+    wal::rebuild_table_indexes(committed, &reindexed)?;
+
+    for name in &writes.views {
+        match workspace.views.get(name) {
+            Some(view) => {
+                committed.views.insert(name.clone(), view.clone());
+            }
+            None => {
+                committed.views.remove(name);
+            }
+        }
+        changes.record_view(name);
+    }
+
+    // Rows that were valid in each snapshot alone can still clash once
+    // merged, such as two inserts of the same key. Only the keys of the
+    // rows written need checking; tables taken whole are checked whole.
+    let mut problems = super::check::check_changed_rows(committed, &row_changes)?;
+    if !writes.tables.is_empty() {
+        problems.extend(super::check::check_constraints(committed, &writes.tables)?);
+    }
+    if let Some(problem) = problems.into_iter().find(|problem| {
+        !matches!(problem, IntegrityProblem::Constraint { table, .. } if deferred.contains(table))
+    }) {
+        return Err(RustqlError::SerializationFailure(format!(
+            "Could not serialize transaction: {}",
+            problem
+        )));
+    }
+    Ok((changes, renumbered))
+}
+
+/// The part of a transaction's snapshot `workspace` that
+/// [`merge_transaction`] reads for `writes`: tables created or altered whole
+/// with their indexes, only the written rows of other tables, and the
+/// indexes and views written.
+pub(crate) fn transaction_changes(
+    workspace: &Database,
+    writes: &WriteSet,
+) -> Result<Database, RustqlError> {
+    let mut changes = Database::new();
+    for name in &writes.tables {
+        if let Some(table) = workspace.tables.get(name) {
+            changes.tables.insert(name.clone(), table.clone());
+        }
+    }
+    for (name, index) in &workspace.indexes {
+        if writes.tables.contains(&index.table) || writes.indexes.contains(name) {
+            changes.indexes.insert(name.clone(), index.clone());
+        }
+    }
+    for (name, index) in &workspace.composite_indexes {
+        if writes.tables.contains(&index.table) || writes.indexes.contains(name) {
+            changes
+                .composite_indexes
+                .insert(name.clone(), index.clone());
+        }
+    }
+
+    changes.load_paged_tables(writes.tables.iter().map(String::as_str))?;
+
+    let mut written_rows: BTreeMap<&String, BTreeSet<RowId>> = BTreeMap::new();
+    for (name, row_ids) in writes.rows.iter().chain(&writes.inserted) {
+        written_rows.entry(name).or_default().extend(row_ids);
+    }
+    for (name, row_ids) in written_rows {
+        let Some(table) = workspace.tables.get(name) else {
+            continue;
+        };
+        if writes.tables.contains(name) {
+            continue;
+        }
+        let mut rows = Vec::new();
+        let mut kept_ids = Vec::new();
+        for row_id in row_ids {
+            if let Some(row) = table.fetch_row(row_id)? {
+                rows.push(row.into_owned());
+                kept_ids.push(row_id);
+            }
+        }
+        changes.tables.insert(
+            name.clone(),
+            Table::with_rows_and_ids(
+                table.columns.clone(),
+                rows,
+                kept_ids,
+                table.next_row_id,
+                table.constraints.clone(),
+            ),
+        );
+    }
+
+    for name in &writes.views {
+        if let Some(view) = workspace.views.get(name) {
+            changes.views.insert(name.clone(), view.clone());
+        }
+    }
+    Ok(changes)
+}
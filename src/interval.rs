@@ -0,0 +1,483 @@
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::cmp::Ordering;
+use std::fmt;
+
+pub(crate) const MICROS_PER_SECOND: i64 = 1_000_000;
+pub(crate) const MICROS_PER_DAY: i64 = 86_400 * MICROS_PER_SECOND;
+
+/// Days a month counts as when intervals are compared or justified.
+const DAYS_PER_MONTH: i64 = 30;
+
+/// A span of time in months, days and microseconds, kept apart because
+/// months and days vary in length: `1 mon` after January 31 is February's
+/// last day, and `1 day` is not always 24 hours of wall-clock time.
+///
+/// Comparison treats a month as 30 days and a day as 24 hours, so
+/// `1 mon` equals `30 days` while each still displays as written.
+#[derive(Debug, Clone, Copy)]
+pub struct Interval {
+    months: i32,
+    days: i32,
+    micros: i64,
+}
+
+impl Interval {
+    pub const ZERO: Interval = Interval {
+        months: 0,
+        days: 0,
+        micros: 0,
+    };
+
+    pub fn new(months: i32, days: i32, micros: i64) -> Interval {
+        Interval {
+            months,
+            days,
+            micros,
+        }
+    }
+
+    pub fn months(&self) -> i32 {
+        self.months
+    }
+
+    pub fn days(&self) -> i32 {
+        self.days
+    }
+
+    pub fn micros(&self) -> i64 {
+        self.micros
+    }
+
+    /// Reads amounts with units such as `3 days 4 hours`, `1.5 years` or
+    /// `2 mons -10 minutes`, an optional clock part such as `04:05:06.25`,
+    /// and a trailing `ago` that negates the whole interval. A number with
+    /// no unit counts seconds. Fractions carry into the next smaller field,
+    /// counting a month as 30 days.
+    pub fn parse(text: &str) -> Option<Interval> {
+        let mut words: Vec<&str> = text.split_whitespace().collect();
+        let ago = words
+            .last()
+            .is_some_and(|word| word.eq_ignore_ascii_case("ago"));
+        if ago {
+            words.pop();
+        }
+        if words.is_empty() {
+            return None;
+        }
+
+        let mut parts = Parts::default();
+        let mut index = 0;
+        while index < words.len() {
+            let word = words[index];
+            if word.contains(':') {
+                parts.micros += i128::from(parse_clock(word)?);
+                index += 1;
+                continue;
+            }
+            let (whole, fraction) = parse_amount(word)?;
+            let unit = match words.get(index + 1) {
+                Some(unit) => {
+                    index += 2;
+                    Unit::parse(unit)?
+                }
+                None => {
+                    index += 1;
+                    Unit::Second
+                }
+            };
+            parts.add(whole, fraction, unit);
+        }
+
+        let interval = parts.finish()?;
+        if ago {
+            interval.checked_neg()
+        } else {
+            Some(interval)
+        }
+    }
+
+    pub fn checked_add(&self, other: &Interval) -> Option<Interval> {
+        Some(Interval {
+            months: self.months.checked_add(other.months)?,
+            days: self.days.checked_add(other.days)?,
+            micros: self.micros.checked_add(other.micros)?,
+        })
+    }
+
+    pub fn checked_sub(&self, other: &Interval) -> Option<Interval> {
+        self.checked_add(&other.checked_neg()?)
+    }
+
+    pub fn checked_neg(&self) -> Option<Interval> {
+        Some(Interval {
+            months: self.months.checked_neg()?,
+            days: self.days.checked_neg()?,
+            micros: self.micros.checked_neg()?,
+        })
+    }
+
+    /// Each field times `factor`, exactly.
+    pub fn checked_mul_integer(&self, factor: i64) -> Option<Interval> {
+        Some(Interval {
+            months: i32::try_from(i64::from(self.months).checked_mul(factor)?).ok()?,
+            days: i32::try_from(i64::from(self.days).checked_mul(factor)?).ok()?,
+            micros: self.micros.checked_mul(factor)?,
+        })
+    }
+
+    /// Each field times `factor`, carrying fractions of months into days
+    /// and fractions of days into microseconds, so `1 mon` times `1.5`
+    /// is `1 mon 15 days`.
+    pub fn checked_mul_float(&self, factor: f64) -> Option<Interval> {
+        if !factor.is_finite() {
+            return None;
+        }
+        self.scaled(
+            f64::from(self.months) * factor,
+            f64::from(self.days) * factor,
+            self.micros as f64 * factor,
+        )
+    }
+
+    /// Each field divided by `divisor`, carrying fractions the same way
+    /// as [`Interval::checked_mul_float`]. `None` for a zero divisor.
+    pub fn checked_div_float(&self, divisor: f64) -> Option<Interval> {
+        if !divisor.is_finite() || divisor == 0.0 {
+            return None;
+        }
+        self.scaled(
+            f64::from(self.months) / divisor,
+            f64::from(self.days) / divisor,
+            self.micros as f64 / divisor,
+        )
+    }
+
+    /// Rounds the carried fractions to whole microseconds first, so that
+    /// `1 mon / 3` comes out as `10 days` rather than `9 days 24:00:00`.
+    fn scaled(&self, months: f64, days: f64, micros: f64) -> Option<Interval> {
+        let month_days = (months.fract() * (DAYS_PER_MONTH * MICROS_PER_DAY) as f64).round();
+        let days = days + month_days / MICROS_PER_DAY as f64;
+        let mut whole_days = days.trunc();
+        let mut carried = (days.fract() * MICROS_PER_DAY as f64).round();
+        if carried.abs() >= MICROS_PER_DAY as f64 {
+            whole_days += carried.signum();
+            carried -= carried.signum() * MICROS_PER_DAY as f64;
+        }
+        let micros = micros.round() + carried;
+        if !micros.is_finite() {
+            return None;
+        }
+        Parts {
+            months: months.trunc() as i128,
+            days: whole_days as i128,
+            micros: micros as i128,
+        }
+        .finish()
+    }
+
+    /// Moves each whole 24 hours into days, as `JUSTIFY_HOURS` does, and
+    /// gives the days and the time the same sign.
+    pub fn justify_hours(&self) -> Option<Interval> {
+        let mut days = i64::from(self.days) + self.micros / MICROS_PER_DAY;
+        let mut micros = self.micros % MICROS_PER_DAY;
+        (days, micros) = align_signs(days, micros, MICROS_PER_DAY);
+        Some(Interval {
+            months: self.months,
+            days: i32::try_from(days).ok()?,
+            micros,
+        })
+    }
+
+    /// Moves each whole 30 days into months, as `JUSTIFY_DAYS` does, and
+    /// gives the months and the days the same sign.
+    pub fn justify_days(&self) -> Option<Interval> {
+        let days = i64::from(self.days);
+        let mut months = i64::from(self.months) + days / DAYS_PER_MONTH;
+        let mut days = days % DAYS_PER_MONTH;
+        (months, days) = align_signs(months, days, DAYS_PER_MONTH);
+        Some(Interval {
+            months: i32::try_from(months).ok()?,
+            days: i32::try_from(days).ok()?,
+            micros: self.micros,
+        })
+    }
+
+    /// Both justifications, leaving every field with the same sign, as
+    /// `JUSTIFY_INTERVAL` does.
+    pub fn justify_interval(&self) -> Option<Interval> {
+        let days = i64::from(self.days) + self.micros / MICROS_PER_DAY;
+        let mut micros = self.micros % MICROS_PER_DAY;
+        let mut months = i64::from(self.months) + days / DAYS_PER_MONTH;
+        let mut days = days % DAYS_PER_MONTH;
+        if months > 0 && (days < 0 || (days == 0 && micros < 0)) {
+            days += DAYS_PER_MONTH;
+            months -= 1;
+        } else if months < 0 && (days > 0 || (days == 0 && micros > 0)) {
+            days -= DAYS_PER_MONTH;
+            months += 1;
+        }
+        (days, micros) = align_signs(days, micros, MICROS_PER_DAY);
+        Some(Interval {
+            months: i32::try_from(months).ok()?,
+            days: i32::try_from(days).ok()?,
+            micros,
+        })
+    }
+
+    /// The length in microseconds that comparison uses.
+    pub(crate) fn comparison_micros(&self) -> i128 {
+        (i128::from(self.months) * i128::from(DAYS_PER_MONTH) + i128::from(self.days))
+            * i128::from(MICROS_PER_DAY)
+            + i128::from(self.micros)
+    }
+}
+
+/// Moves one `unit` from `larger` into `smaller` when their signs differ.
+fn align_signs(larger: i64, smaller: i64, unit: i64) -> (i64, i64) {
+    if larger > 0 && smaller < 0 {
+        (larger - 1, smaller + unit)
+    } else if larger < 0 && smaller > 0 {
+        (larger + 1, smaller - unit)
+    } else {
+        (larger, smaller)
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Unit {
+    Microsecond,
+    Millisecond,
+    Second,
+    Minute,
+    Hour,
+    Day,
+    Week,
+    Month,
+    Year,
+    Decade,
+}
+
+impl Unit {
+    fn parse(word: &str) -> Option<Unit> {
+        Some(match word.to_ascii_lowercase().as_str() {
+            "microsecond" | "microseconds" | "us" | "usec" | "usecs" => Unit::Microsecond,
+            "millisecond" | "milliseconds" | "ms" | "msec" | "msecs" => Unit::Millisecond,
+            "second" | "seconds" | "sec" | "secs" | "s" => Unit::Second,
+            "minute" | "minutes" | "min" | "mins" | "m" => Unit::Minute,
+            "hour" | "hours" | "hr" | "hrs" | "h" => Unit::Hour,
+            "day" | "days" | "d" => Unit::Day,
+            "week" | "weeks" | "w" => Unit::Week,
+            "month" | "months" | "mon" | "mons" => Unit::Month,
+            "year" | "years" | "yr" | "yrs" | "y" => Unit::Year,
+            "decade" | "decades" => Unit::Decade,
+            _ => return None,
+        })
+    }
+}
+
+/// Fields summed while parsing, wide enough not to overflow before the
+/// final range check.
+#[derive(Default)]
+struct Parts {
+    months: i128,
+    days: i128,
+    micros: i128,
+}
+
+impl Parts {
+    fn add(&mut self, whole: i64, fraction: f64, unit: Unit) {
+        let whole = i128::from(whole);
+        let micros = |per_unit: i64| {
+            whole * i128::from(per_unit) + (fraction * per_unit as f64).round() as i128
+        };
+        match unit {
+            Unit::Microsecond => self.micros += micros(1),
+            Unit::Millisecond => self.micros += micros(1_000),
+            Unit::Second => self.micros += micros(MICROS_PER_SECOND),
+            Unit::Minute => self.micros += micros(60 * MICROS_PER_SECOND),
+            Unit::Hour => self.micros += micros(3_600 * MICROS_PER_SECOND),
+            Unit::Day => self.add_days(whole, fraction),
+            Unit::Week => self.add_days(whole * 7, fraction * 7.0),
+            Unit::Month => self.add_months(whole, fraction),
+            Unit::Year => self.add_months(whole * 12, fraction * 12.0),
+            Unit::Decade => self.add_months(whole * 120, fraction * 120.0),
+        }
+    }
+
+    fn add_months(&mut self, whole: i128, fraction: f64) {
+        let carried = fraction.trunc();
+        self.months += whole + carried as i128;
+        self.add_days(0, (fraction - carried) * DAYS_PER_MONTH as f64);
+    }
+
+    fn add_days(&mut self, whole: i128, fraction: f64) {
+        let carried = fraction.trunc();
+        self.days += whole + carried as i128;
+        self.micros += ((fraction - carried) * MICROS_PER_DAY as f64).round() as i128;
+    }
+
+    fn finish(self) -> Option<Interval> {
+        Some(Interval {
+            months: i32::try_from(self.months).ok()?,
+            days: i32::try_from(self.days).ok()?,
+            micros: i64::try_from(self.micros).ok()?,
+        })
+    }
+}
+
+/// An optionally signed decimal number split into its whole part and its
+/// fraction, which share the sign.
+fn parse_amount(word: &str) -> Option<(i64, f64)> {
+    let (negative, digits) = match word.as_bytes().first()? {
+        b'-' => (true, &word[1..]),
+        b'+' => (false, &word[1..]),
+        _ => (false, word),
+    };
+    let (whole, fraction) = digits.split_once('.').unwrap_or((digits, ""));
+    if (whole.is_empty() && fraction.is_empty())
+        || !whole
+            .bytes()
+            .chain(fraction.bytes())
+            .all(|b| b.is_ascii_digit())
+    {
+        return None;
+    }
+    let whole: i64 = if whole.is_empty() {
+        0
+    } else {
+        whole.parse().ok()?
+    };
+    let fraction: f64 = if fraction.is_empty() {
+        0.0
+    } else {
+        format!("0.{}", fraction).parse().ok()?
+    };
+    if negative {
+        Some((-whole, -fraction))
+    } else {
+        Some((whole, fraction))
+    }
+}
+
+/// Microseconds in a clock part such as `-04:05`, `100:00:00` or
+/// `04:05:06.25`.
+fn parse_clock(word: &str) -> Option<i64> {
+    let (negative, clock) = match word.as_bytes().first()? {
+        b'-' => (true, &word[1..]),
+        b'+' => (false, &word[1..]),
+        _ => (false, word),
+    };
+    let mut fields = clock.split(':');
+    let hours = fields.next()?;
+    let minutes = fields.next()?;
+    let seconds = fields.next().unwrap_or("0");
+    if fields.next().is_some()
+        || hours.is_empty()
+        || !hours.bytes().all(|b| b.is_ascii_digit())
+        || minutes.len() != 2
+        || !minutes.bytes().all(|b| b.is_ascii_digit())
+    {
+        return None;
+    }
+    let hours: i64 = hours.parse().ok()?;
+    let minutes: i64 = minutes.parse().ok()?;
+    let (whole_seconds, fraction) = seconds.split_once('.').unwrap_or((seconds, ""));
+    if whole_seconds.is_empty()
+        || !whole_seconds
+            .bytes()
+            .chain(fraction.bytes())
+            .all(|b| b.is_ascii_digit())
+    {
+        return None;
+    }
+    let whole_seconds: i64 = whole_seconds.parse().ok()?;
+    if minutes >= 60 || whole_seconds >= 60 {
+        return None;
+    }
+    let fraction_micros: i64 = format!("{:0<6}", &fraction[..fraction.len().min(6)])
+        .parse()
+        .ok()?;
+    let micros = hours
+        .checked_mul(3_600 * MICROS_PER_SECOND)?
+        .checked_add((minutes * 60 + whole_seconds) * MICROS_PER_SECOND + fraction_micros)?;
+    Some(if negative { -micros } else { micros })
+}
+
+impl PartialEq for Interval {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Interval {}
+
+impl PartialOrd for Interval {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Interval {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.comparison_micros().cmp(&other.comparison_micros())
+    }
+}
+
+impl fmt::Display for Interval {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut parts = Vec::new();
+        let years = self.months / 12;
+        let months = self.months % 12;
+        for (amount, singular, plural) in [
+            (years, "year", "years"),
+            (months, "mon", "mons"),
+            (self.days, "day", "days"),
+        ] {
+            if amount != 0 {
+                let unit = if amount == 1 { singular } else { plural };
+                parts.push(format!("{} {}", amount, unit));
+            }
+        }
+
+        if self.micros != 0 || parts.is_empty() {
+            let sign = if self.micros < 0 {
+                "-"
+            } else if self.months < 0 || self.days < 0 {
+                "+"
+            } else {
+                ""
+            };
+            let micros = self.micros.unsigned_abs();
+            let seconds = micros / MICROS_PER_SECOND as u64;
+            let mut clock = format!(
+                "{}{:02}:{:02}:{:02}",
+                sign,
+                seconds / 3_600,
+                seconds / 60 % 60,
+                seconds % 60
+            );
+            let fraction = micros % MICROS_PER_SECOND as u64;
+            if fraction != 0 {
+                let digits = format!("{:06}", fraction);
+                clock.push('.');
+                clock.push_str(digits.trim_end_matches('0'));
+            }
+            parts.push(clock);
+        }
+        f.write_str(&parts.join(" "))
+    }
+}
+
+impl Serialize for Interval {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for Interval {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let text = String::deserialize(deserializer)?;
+        Interval::parse(&text)
+            .ok_or_else(|| serde::de::Error::custom(format!("invalid interval '{}'", text)))
+    }
+}
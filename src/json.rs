@@ -0,0 +1,385 @@
+use crate::ast::{DataType, Value};
+use crate::error::RustqlError;
+use serde_json::{Map, Number, Value as JsonValue};
+
+/// Largest magnitude below which every integer is exact as an `f64`.
+const EXACT_F64_INTEGER_LIMIT: f64 = 9_007_199_254_740_992.0;
+
+/// Parses `text` as a JSON document in normalized form.
+pub(crate) fn parse(text: &str) -> Result<JsonValue, RustqlError> {
+    let json = serde_json::from_str(text)
+        .map_err(|err| RustqlError::TypeMismatch(format!("Invalid JSON '{}': {}", text, err)))?;
+    Ok(normalize(json))
+}
+
+/// Writes integral numbers such as `1.0` or `1e2` as integers, so equal
+/// numbers store alike. Objects already keep their members sorted by key,
+/// with the last of any duplicate key kept.
+fn normalize(json: JsonValue) -> JsonValue {
+    match json {
+        JsonValue::Number(number) => JsonValue::Number(normalize_number(number)),
+        JsonValue::Array(items) => JsonValue::Array(items.into_iter().map(normalize).collect()),
+        JsonValue::Object(members) => JsonValue::Object(
+            members
+                .into_iter()
+                .map(|(key, value)| (key, normalize(value)))
+                .collect(),
+        ),
+        other => other,
+    }
+}
+
+fn normalize_number(number: Number) -> Number {
+    match number.as_f64() {
+        Some(f) if number.is_f64() && f.fract() == 0.0 && f.abs() < EXACT_F64_INTEGER_LIMIT => {
+            Number::from(f as i64)
+        }
+        _ => number,
+    }
+}
+
+/// The `JSON` value holding `json`.
+pub(crate) fn to_value(json: &JsonValue) -> Value {
+    Value::Json(json.to_string())
+}
+
+/// The document in `value`: a `JSON` value, or `TEXT` parsed as JSON.
+/// `None` for NULL.
+pub(crate) fn operand(value: &Value, context: &str) -> Result<Option<JsonValue>, RustqlError> {
+    match value {
+        Value::Null => Ok(None),
+        Value::Json(text) | Value::Text(text) => parse(text).map(Some),
+        other => Err(RustqlError::TypeMismatch(format!(
+            "{} requires a JSON value, got {}",
+            context, other
+        ))),
+    }
+}
+
+/// `value` as JSON: NULL is `null`, numbers and booleans keep their type,
+/// `JSON` values nest as documents, and anything else is a string of its
+/// display form.
+pub(crate) fn from_sql(value: &Value) -> JsonValue {
+    match value {
+        Value::Null => JsonValue::Null,
+        Value::Integer(n) => JsonValue::from(*n),
+        Value::Float(f) => {
+            Number::from_f64(*f).map_or(JsonValue::Null, |n| JsonValue::Number(normalize_number(n)))
+        }
+        Value::Decimal(d) => parse(&d.to_string()).unwrap_or(JsonValue::Null),
+        Value::Boolean(b) => JsonValue::Bool(*b),
+        Value::Json(text) => parse(text).unwrap_or(JsonValue::Null),
+        other => JsonValue::String(other.to_string()),
+    }
+}
+
+/// `json` as the closest SQL value: `null` is NULL, numbers are INTEGER
+/// when they fit and FLOAT otherwise, strings are TEXT, and arrays and
+/// objects stay `JSON`.
+pub(crate) fn to_sql(json: &JsonValue) -> Value {
+    match json {
+        JsonValue::Null => Value::Null,
+        JsonValue::Bool(b) => Value::Boolean(*b),
+        JsonValue::Number(n) => match n.as_i64() {
+            Some(i) => Value::Integer(i),
+            None => Value::Float(n.as_f64().unwrap_or(f64::NAN)),
+        },
+        JsonValue::String(s) => Value::Text(s.clone()),
+        JsonValue::Array(_) | JsonValue::Object(_) => to_value(json),
+    }
+}
+
+/// `json` as `->>` returns it: strings without their quotes, `null` as
+/// NULL, and anything else as JSON text.
+pub(crate) fn to_text(json: &JsonValue) -> Value {
+    match json {
+        JsonValue::Null => Value::Null,
+        JsonValue::String(s) => Value::Text(s.clone()),
+        other => Value::Text(other.to_string()),
+    }
+}
+
+pub(crate) fn type_name(json: &JsonValue) -> &'static str {
+    match json {
+        JsonValue::Null => "null",
+        JsonValue::Bool(_) => "boolean",
+        JsonValue::Number(_) => "number",
+        JsonValue::String(_) => "string",
+        JsonValue::Array(_) => "array",
+        JsonValue::Object(_) => "object",
+    }
+}
+
+/// One step into a document: an object member or an array element.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum PathStep {
+    Key(String),
+    /// An array position; below zero counts back from the end.
+    Index(i64),
+}
+
+impl PathStep {
+    /// The step `->` takes for its right operand.
+    pub(crate) fn from_operand(value: &Value) -> Result<PathStep, RustqlError> {
+        match value {
+            Value::Text(key) => Ok(PathStep::Key(key.clone())),
+            Value::Integer(index) => Ok(PathStep::Index(*index)),
+            other => Err(RustqlError::TypeMismatch(format!(
+                "JSON field must be a text key or an integer index, got {}",
+                other
+            ))),
+        }
+    }
+}
+
+fn array_position(len: usize, index: i64) -> Option<usize> {
+    let index = if index < 0 {
+        index.checked_add(i64::try_from(len).ok()?)?
+    } else {
+        index
+    };
+    usize::try_from(index)
+        .ok()
+        .filter(|position| *position < len)
+}
+
+pub(crate) fn step<'a>(json: &'a JsonValue, step: &PathStep) -> Option<&'a JsonValue> {
+    match (json, step) {
+        (JsonValue::Object(members), PathStep::Key(key)) => members.get(key),
+        (JsonValue::Array(items), PathStep::Index(index)) => {
+            array_position(items.len(), *index).map(|position| &items[position])
+        }
+        _ => None,
+    }
+}
+
+fn step_mut<'a>(json: &'a mut JsonValue, step: &PathStep) -> Option<&'a mut JsonValue> {
+    match (json, step) {
+        (JsonValue::Object(members), PathStep::Key(key)) => members.get_mut(key),
+        (JsonValue::Array(items), PathStep::Index(index)) => {
+            array_position(items.len(), *index).map(move |position| &mut items[position])
+        }
+        _ => None,
+    }
+}
+
+pub(crate) fn follow<'a>(json: &'a JsonValue, path: &[PathStep]) -> Option<&'a JsonValue> {
+    path.iter().try_fold(json, step)
+}
+
+/// Follows a `#>` path such as `{items,0,name}`. Elements index arrays
+/// when they are integers and name object members otherwise.
+pub(crate) fn follow_text_path<'a>(
+    json: &'a JsonValue,
+    path: &str,
+) -> Result<Option<&'a JsonValue>, RustqlError> {
+    let elements = path
+        .trim()
+        .strip_prefix('{')
+        .and_then(|rest| rest.strip_suffix('}'))
+        .ok_or_else(|| {
+            RustqlError::TypeMismatch(format!(
+                "Invalid JSON path '{}'; expected elements in braces such as '{{a,0}}'",
+                path
+            ))
+        })?;
+    if elements.trim().is_empty() {
+        return Ok(Some(json));
+    }
+
+    let mut current = json;
+    for element in elements.split(',') {
+        let element = element.trim();
+        let element = element
+            .strip_prefix('"')
+            .and_then(|rest| rest.strip_suffix('"'))
+            .unwrap_or(element);
+        let path_step = match (current, element.parse::<i64>()) {
+            (JsonValue::Array(_), Ok(index)) => PathStep::Index(index),
+            _ => PathStep::Key(element.to_string()),
+        };
+        match step(current, &path_step) {
+            Some(next) => current = next,
+            None => return Ok(None),
+        }
+    }
+    Ok(Some(current))
+}
+
+/// Parses a path such as `$.items[0].name`, as JSON_EXTRACT and JSON_SET
+/// take. Keys with other characters are written in double quotes, as in
+/// `$."first name"`.
+pub(crate) fn parse_path(path: &str) -> Result<Vec<PathStep>, RustqlError> {
+    let invalid = || RustqlError::TypeMismatch(format!("Invalid JSON path '{}'", path));
+    let mut rest = path.trim().strip_prefix('$').ok_or_else(invalid)?;
+    let mut steps = Vec::new();
+    while !rest.is_empty() {
+        if let Some(after) = rest.strip_prefix('.') {
+            if let Some(quoted) = after.strip_prefix('"') {
+                let end = quoted.find('"').ok_or_else(invalid)?;
+                steps.push(PathStep::Key(quoted[..end].to_string()));
+                rest = &quoted[end + 1..];
+            } else {
+                let end = after.find(['.', '[']).unwrap_or(after.len());
+                if end == 0 {
+                    return Err(invalid());
+                }
+                steps.push(PathStep::Key(after[..end].to_string()));
+                rest = &after[end..];
+            }
+        } else if let Some(after) = rest.strip_prefix('[') {
+            let end = after.find(']').ok_or_else(invalid)?;
+            let index = after[..end].trim().parse().map_err(|_| invalid())?;
+            steps.push(PathStep::Index(index));
+            rest = &after[end + 1..];
+        } else {
+            return Err(invalid());
+        }
+    }
+    Ok(steps)
+}
+
+/// Puts `value` at `path`, replacing what is there. When only the last
+/// step is missing it adds a new object member, or an element just past
+/// the end of an array; any other missing step leaves `json` unchanged.
+pub(crate) fn set_path(json: &mut JsonValue, path: &[PathStep], value: JsonValue) {
+    let Some((last, parents)) = path.split_last() else {
+        *json = value;
+        return;
+    };
+    let mut target = json;
+    for parent in parents {
+        let Some(next) = step_mut(target, parent) else {
+            return;
+        };
+        target = next;
+    }
+
+    match (target, last) {
+        (JsonValue::Object(members), PathStep::Key(key)) => {
+            members.insert(key.clone(), value);
+        }
+        (JsonValue::Array(items), PathStep::Index(index)) => {
+            if let Some(position) = array_position(items.len(), *index) {
+                items[position] = value;
+            } else if usize::try_from(*index).is_ok_and(|index| index == items.len()) {
+                items.push(value);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Whether `container` holds everything in `contained`, as `@>` tests.
+/// Each member of a contained object must be contained in the same member
+/// of the container, and each element of a contained array in some
+/// element of the container. An array also contains a scalar it has as an
+/// element; other scalars must be equal.
+pub(crate) fn contains(container: &JsonValue, contained: &JsonValue) -> bool {
+    match (container, contained) {
+        (JsonValue::Object(outer), JsonValue::Object(inner)) => inner.iter().all(|(key, value)| {
+            outer
+                .get(key)
+                .is_some_and(|candidate| contains(candidate, value))
+        }),
+        (JsonValue::Array(outer), JsonValue::Array(inner)) => inner
+            .iter()
+            .all(|value| outer.iter().any(|candidate| contains(candidate, value))),
+        (JsonValue::Array(outer), scalar)
+            if !matches!(scalar, JsonValue::Array(_) | JsonValue::Object(_)) =>
+        {
+            outer.contains(scalar)
+        }
+        _ => container == contained,
+    }
+}
+
+/// JSON_BUILD_OBJECT over alternating keys and values. Keys become their
+/// text form and may not be NULL.
+pub(crate) fn build_object(args: &[Value]) -> Result<Value, RustqlError> {
+    if !args.len().is_multiple_of(2) {
+        return Err(RustqlError::TypeMismatch(
+            "JSON_BUILD_OBJECT expects alternating keys and values".to_string(),
+        ));
+    }
+    let mut members = Map::new();
+    for pair in args.chunks(2) {
+        let key = match &pair[0] {
+            Value::Null => {
+                return Err(RustqlError::TypeMismatch(
+                    "JSON object keys cannot be NULL".to_string(),
+                ));
+            }
+            Value::Text(key) => key.clone(),
+            other => other.to_string(),
+        };
+        members.insert(key, from_sql(&pair[1]));
+    }
+    Ok(to_value(&JsonValue::Object(members)))
+}
+
+/// JSON_AGG: an array of `values` with NULLs as JSON `null`, or NULL if
+/// there are no values.
+pub(crate) fn aggregate_array(values: &[Value]) -> Value {
+    if values.is_empty() {
+        Value::Null
+    } else {
+        to_value(&JsonValue::Array(values.iter().map(from_sql).collect()))
+    }
+}
+
+/// JSON_OBJECT_AGG, merging the one-member objects built for each row. A
+/// later row replaces the value of an earlier row with the same key.
+pub(crate) fn aggregate_object(values: &[Value]) -> Value {
+    let mut members = Map::new();
+    let mut any = false;
+    for value in values {
+        if let Value::Json(text) = value
+            && let Ok(JsonValue::Object(object)) = parse(text)
+        {
+            members.extend(object);
+            any = true;
+        }
+    }
+    if any {
+        to_value(&JsonValue::Object(members))
+    } else {
+        Value::Null
+    }
+}
+
+/// The output columns of the `json_each` and `json_array_elements` table
+/// functions, or `None` for any other name.
+pub(crate) fn table_function_columns(name: &str) -> Option<Vec<(&'static str, DataType)>> {
+    match name {
+        "json_each" => Some(vec![("key", DataType::Text), ("value", DataType::Json)]),
+        "json_array_elements" => Some(vec![("value", DataType::Json)]),
+        _ => None,
+    }
+}
+
+/// The rows `json_each` or `json_array_elements` produce for `document`:
+/// one per object member or array element, none for NULL.
+pub(crate) fn table_function_rows(
+    name: &str,
+    document: &Value,
+) -> Result<Vec<Vec<Value>>, RustqlError> {
+    let context = name.to_ascii_uppercase();
+    let Some(json) = operand(document, &context)? else {
+        return Ok(Vec::new());
+    };
+    match (name, json) {
+        ("json_each", JsonValue::Object(members)) => Ok(members
+            .iter()
+            .map(|(key, value)| vec![Value::Text(key.clone()), to_value(value)])
+            .collect()),
+        ("json_array_elements", JsonValue::Array(items)) => {
+            Ok(items.iter().map(|item| vec![to_value(item)]).collect())
+        }
+        (_, other) => Err(RustqlError::TypeMismatch(format!(
+            "{} cannot expand a JSON {}",
+            context,
+            type_name(&other)
+        ))),
+    }
+}
@@ -1,3 +1,5 @@
+use crate::ast::Value;
+use crate::decimal::Decimal;
 use crate::error::{RustqlError, SourceLocation, SourceSpan};
 
 #[derive(Debug, Clone, PartialEq)]
@@ -59,6 +61,7 @@ pub enum Token {
     Date,
     Time,
     DateTime,
+    Interval,
     Foreign,
     Key,
     References,
@@ -152,6 +155,11 @@ pub enum Token {
     DateAdd,
     Datediff,
     Truncate,
+    Checkpoint,
+    Backup,
+    Vacuum,
+    Storage,
+    Database,
     View,
     Conflict,
     Do,
@@ -227,11 +235,32 @@ pub enum Token {
     Cbrt,
     Gcd,
     Lcm,
+    Encode,
+    Decode,
+    JsonExtract,
+    JsonSet,
+    JsonArrayLength,
+    JsonTypeof,
+    JsonBuildObject,
+    JsonEach,
+    JsonArrayElements,
+    JsonAgg,
+    JsonObjectAgg,
+    JustifyDays,
+    JustifyHours,
+    JustifyInterval,
 
     Identifier(String),
     Number(i64),
     Float(f64),
+    Decimal(Decimal),
     StringLiteral(String),
+    /// A hex literal such as `X'CAFE'`.
+    Blob(Vec<u8>),
+    /// A `$1`-style placeholder, numbered from one.
+    Parameter(usize),
+    /// The value bound to a placeholder by [`bind_parameters`].
+    BoundValue(Value),
 
     LeftParen,
     RightParen,
@@ -250,6 +279,15 @@ pub enum Token {
     Minus,
     Divide,
 
+    /// `->`, a JSON member or element.
+    Arrow,
+    /// `->>`, a JSON member or element as text.
+    LongArrow,
+    /// `#>`, the JSON at a path.
+    HashArrow,
+    /// `@>`, JSON containment.
+    AtArrow,
+
     Eof,
 }
 
@@ -308,11 +346,19 @@ pub fn tokenize(input: &str) -> Result<Vec<Token>, RustqlError> {
             '-' => {
                 chars.next();
                 if let Some(&next_ch) = chars.peek() {
-                    if next_ch.is_ascii_digit() {
+                    if next_ch == '>' {
+                        chars.next();
+                        if let Some(&'>') = chars.peek() {
+                            tokens.push(Token::LongArrow);
+                            chars.next();
+                        } else {
+                            tokens.push(Token::Arrow);
+                        }
+                    } else if next_ch.is_ascii_digit() {
                         let num = read_number(&mut chars);
                         let literal = format!("-{}", num);
                         if num.contains('.') {
-                            tokens.push(Token::Float(parse_float_literal(&literal)?));
+                            tokens.push(parse_fractional_literal(&literal)?);
                         } else {
                             tokens.push(Token::Number(parse_integer_literal(&literal)?));
                         }
@@ -369,6 +415,30 @@ pub fn tokenize(input: &str) -> Result<Vec<Token>, RustqlError> {
                     ));
                 }
             }
+            '#' => {
+                chars.next();
+                if let Some(&'>') = chars.peek() {
+                    tokens.push(Token::HashArrow);
+                    chars.next();
+                    can_extend_last_identifier_with_dot = false;
+                } else {
+                    return Err(RustqlError::ParseError(
+                        "Unexpected character: #".to_string(),
+                    ));
+                }
+            }
+            '@' => {
+                chars.next();
+                if let Some(&'>') = chars.peek() {
+                    tokens.push(Token::AtArrow);
+                    chars.next();
+                    can_extend_last_identifier_with_dot = false;
+                } else {
+                    return Err(RustqlError::ParseError(
+                        "Unexpected character: @".to_string(),
+                    ));
+                }
+            }
             '!' => {
                 chars.next();
                 if let Some(&'=') = chars.peek() {
@@ -407,7 +477,7 @@ pub fn tokenize(input: &str) -> Result<Vec<Token>, RustqlError> {
             _ if ch.is_ascii_digit() => {
                 let num = read_number(&mut chars);
                 if num.contains('.') {
-                    tokens.push(Token::Float(parse_float_literal(&num)?));
+                    tokens.push(parse_fractional_literal(&num)?);
                 } else {
                     tokens.push(Token::Number(parse_integer_literal(&num)?));
                 }
@@ -415,6 +485,16 @@ pub fn tokenize(input: &str) -> Result<Vec<Token>, RustqlError> {
             }
             _ if ch.is_ascii_alphabetic() || ch == '_' => {
                 let ident = read_identifier(&mut chars);
+                if ident.eq_ignore_ascii_case("x") && chars.peek() == Some(&'\'') {
+                    chars.next();
+                    let digits = read_string(&mut chars, '\'')?;
+                    let bytes = crate::blob::decode_hex(&digits).ok_or_else(|| {
+                        RustqlError::ParseError(format!("Invalid hex literal: X'{}'", digits))
+                    })?;
+                    tokens.push(Token::Blob(bytes));
+                    can_extend_last_identifier_with_dot = false;
+                    continue;
+                }
                 tokens.push(match_keyword(&ident));
                 can_extend_last_identifier_with_dot =
                     matches!(tokens.last(), Some(Token::Identifier(_)));
@@ -449,6 +529,20 @@ pub fn tokenize(input: &str) -> Result<Vec<Token>, RustqlError> {
                     can_extend_last_identifier_with_dot = false;
                 }
             }
+            '$' => {
+                chars.next();
+                let digits = read_identifier(&mut chars);
+                match digits.parse::<usize>() {
+                    Ok(number) if number > 0 => tokens.push(Token::Parameter(number)),
+                    _ => {
+                        return Err(RustqlError::ParseError(format!(
+                            "Invalid parameter: ${}",
+                            digits
+                        )));
+                    }
+                }
+                can_extend_last_identifier_with_dot = false;
+            }
             _ => {
                 return Err(RustqlError::ParseError(format!(
                     "Unexpected character: {}",
@@ -550,10 +644,10 @@ impl<'a> SpanCursor<'a> {
                     }
                 }
             }
-            return;
+        } else {
+            self.consume_identifier_part();
         }
 
-        self.consume_identifier_part();
         while self.peek() == Some('.')
             && self
                 .peek_next()
@@ -597,6 +691,31 @@ impl<'a> SpanCursor<'a> {
     }
 }
 
+/// Replaces each `$n` placeholder with the `n`th of `values`.
+pub fn bind_parameters(
+    tokens: Vec<SpannedToken>,
+    values: &[Value],
+) -> Result<Vec<SpannedToken>, RustqlError> {
+    tokens
+        .into_iter()
+        .map(|spanned| match spanned.token {
+            Token::Parameter(number) => {
+                let value = values
+                    .get(number - 1)
+                    .ok_or_else(|| RustqlError::ParseErrorAt {
+                        message: format!("No value bound for parameter ${}", number),
+                        span: spanned.span,
+                    })?;
+                Ok(SpannedToken {
+                    token: Token::BoundValue(value.clone()),
+                    span: spanned.span,
+                })
+            }
+            _ => Ok(spanned),
+        })
+        .collect()
+}
+
 fn assign_spans(input: &str, tokens: Vec<Token>) -> Vec<SpannedToken> {
     let mut cursor = SpanCursor::new(input);
     tokens
@@ -607,8 +726,18 @@ fn assign_spans(input: &str, tokens: Vec<Token>) -> Vec<SpannedToken> {
             match &token {
                 Token::Eof => {}
                 Token::Identifier(_) => cursor.consume_identifier_token(),
-                Token::Number(_) | Token::Float(_) => cursor.consume_number_token(),
+                Token::Number(_) | Token::Float(_) | Token::Decimal(_) => {
+                    cursor.consume_number_token()
+                }
                 Token::StringLiteral(_) => cursor.consume_string_token(),
+                Token::Blob(_) => {
+                    cursor.consume_fixed("X");
+                    cursor.consume_string_token();
+                }
+                Token::Parameter(_) => {
+                    cursor.consume_fixed("$");
+                    cursor.consume_identifier_part();
+                }
                 _ => {
                     if let Some(text) = fixed_token_text(&token) {
                         cursor.consume_fixed(text);
@@ -645,6 +774,10 @@ fn fixed_token_text(token: &Token) -> Option<&'static str> {
         Token::GreaterThanOrEqual => Some(">="),
         Token::Concat => Some("||"),
         Token::DoubleColon => Some("::"),
+        Token::Arrow => Some("->"),
+        Token::LongArrow => Some("->>"),
+        Token::HashArrow => Some("#>"),
+        Token::AtArrow => Some("@>"),
         _ => None,
     }
 }
@@ -721,6 +854,16 @@ fn parse_integer_literal(literal: &str) -> Result<i64, RustqlError> {
         .map_err(|_| RustqlError::ParseError(format!("Invalid integer literal: {}", literal)))
 }
 
+/// A literal with a point is a float unless a float cannot hold all of its
+/// digits, as with `12345678901234567.89`; then it is an exact decimal.
+fn parse_fractional_literal(literal: &str) -> Result<Token, RustqlError> {
+    let value = parse_float_literal(literal)?;
+    match Decimal::parse(literal) {
+        Some(exact) if Decimal::from_f64(value) != Some(exact) => Ok(Token::Decimal(exact)),
+        _ => Ok(Token::Float(value)),
+    }
+}
+
 fn parse_float_literal(literal: &str) -> Result<f64, RustqlError> {
     let value = literal
         .parse::<f64>()
@@ -825,6 +968,7 @@ fn match_keyword(ident: &str) -> Token {
         "DATE" => Token::Date,
         "TIME" => Token::Time,
         "DATETIME" => Token::DateTime,
+        "INTERVAL" => Token::Interval,
         "FOREIGN" => Token::Foreign,
         "KEY" => Token::Key,
         "REFERENCES" => Token::References,
@@ -917,6 +1061,11 @@ fn match_keyword(ident: &str) -> Token {
         "DATE_ADD" | "DATEADD" => Token::DateAdd,
         "DATEDIFF" | "DATE_DIFF" => Token::Datediff,
         "TRUNCATE" => Token::Truncate,
+        "CHECKPOINT" => Token::Checkpoint,
+        "BACKUP" => Token::Backup,
+        "VACUUM" => Token::Vacuum,
+        "STORAGE" => Token::Storage,
+        "DATABASE" => Token::Database,
         "VIEW" => Token::View,
         "CONFLICT" => Token::Conflict,
         "DO" => Token::Do,
@@ -981,6 +1130,20 @@ fn match_keyword(ident: &str) -> Token {
         "STORED" => Token::Stored,
         "INITCAP" => Token::Initcap,
         "SPLIT_PART" => Token::SplitPart,
+        "ENCODE" => Token::Encode,
+        "DECODE" => Token::Decode,
+        "JSON_EXTRACT" => Token::JsonExtract,
+        "JSON_SET" => Token::JsonSet,
+        "JSON_ARRAY_LENGTH" => Token::JsonArrayLength,
+        "JSON_TYPEOF" => Token::JsonTypeof,
+        "JSON_BUILD_OBJECT" => Token::JsonBuildObject,
+        "JSON_EACH" => Token::JsonEach,
+        "JSON_ARRAY_ELEMENTS" => Token::JsonArrayElements,
+        "JSON_AGG" => Token::JsonAgg,
+        "JSON_OBJECT_AGG" => Token::JsonObjectAgg,
+        "JUSTIFY_DAYS" => Token::JustifyDays,
+        "JUSTIFY_HOURS" => Token::JustifyHours,
+        "JUSTIFY_INTERVAL" => Token::JustifyInterval,
         "TRANSLATE" => Token::Translate,
         "REGEXP_MATCH" => Token::RegexpMatch,
         "REGEXP_REPLACE" => Token::RegexpReplace,
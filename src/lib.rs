@@ -1,7 +1,9 @@
 //! Stable public API for embedding RustQL.
 //!
 //! The default public surface is the engine, session, query result/value types,
-//! and errors. Parser, AST, catalog, lexer, and planner internals are available
+//! and errors, plus the [`StorageEngine`] trait for plugging in a storage
+//! backend through [`StorageMode::Custom`] and the [`Database`] snapshots it
+//! stores. Parser, AST, catalog, lexer, and planner internals are available
 //! only with the `testing-api` feature.
 
 #[cfg(feature = "testing-api")]
@@ -25,9 +27,14 @@ pub mod binder;
 #[allow(dead_code)]
 pub(crate) mod binder;
 
+mod blob;
+mod decimal;
 pub mod engine;
 pub mod error;
 mod executor;
+mod interval;
+mod json;
+mod migrate;
 
 #[cfg(feature = "testing-api")]
 #[doc(hidden)]
@@ -63,12 +70,18 @@ mod storage;
 mod wal;
 
 pub use ast::{DataType, Value};
+pub use decimal::Decimal;
 pub use engine::{
-    ColumnMeta, CommandResult, CommandTag, Engine, EngineOptions, ExplainAnalyzeResult, PlanTree,
-    QueryResult, Row, RowBatch, Session, StorageMode,
+    ChangeEvent, ChangeKind, ChangeStream, ColumnMeta, CommandResult, CommandTag, Engine,
+    EngineOptions, ExplainAnalyzeResult, InterruptHandle, PlanTree, QueryResult, Row, RowBatch,
+    Session, StorageMode,
+};
+pub use error::{ConstraintKind, IntegrityProblem, Result, RustqlError};
+pub use interval::Interval;
+pub use migrate::{MigrationReport, migrate};
+pub use storage::{
+    ChangeSet, EncryptionKey, LogReceiver, LogSender, PageUsage, PageUsageKind, ReplicationSource,
+    ReplicationTarget, StorageEngine, VacuumStats, log_channel,
 };
-pub use error::{ConstraintKind, Result, RustqlError};
 
-#[cfg(feature = "testing-api")]
-#[doc(hidden)]
 pub use database::Database;
@@ -1,9 +1,24 @@
 use rustql::Value;
-use rustql::{CommandTag, Engine, ExplainAnalyzeResult, QueryResult, RowBatch};
+use rustql::{
+    CommandTag, Engine, EngineOptions, ExplainAnalyzeResult, QueryResult, RowBatch, StorageMode,
+};
+use std::fs::File;
 use std::io::{self, IsTerminal, Read, Write};
+use std::path::Path;
 use std::process::ExitCode;
 
 fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    match args.as_slice() {
+        [flag, path] if flag == "--check" => return check_file(Path::new(path)),
+        [flag, ..] if flag == "--check" => {
+            eprintln!("Usage: rustql --check <file>");
+            return ExitCode::FAILURE;
+        }
+        [command, rest @ ..] if command == "migrate" => return migrate_files(rest),
+        _ => {}
+    }
+
     let engine = match Engine::from_env() {
         Ok(engine) => engine,
         Err(e) => {
@@ -67,6 +82,103 @@ fn main() -> ExitCode {
     ExitCode::SUCCESS
 }
 
+/// Runs `CHECK DATABASE` on a storage file and prints each problem. Fails
+/// when the file cannot be opened or has problems.
+fn check_file(path: &Path) -> ExitCode {
+    let options = match storage_options_for(path) {
+        Ok(options) => options,
+        Err(e) => {
+            eprintln!("Error: Cannot read '{}': {}", path.display(), e);
+            return ExitCode::FAILURE;
+        }
+    };
+    let problems = match Engine::open(options).and_then(|engine| engine.check_database()) {
+        Ok(problems) => problems,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            return ExitCode::FAILURE;
+        }
+    };
+    if problems.is_empty() {
+        println!("No problems found in '{}'", path.display());
+        return ExitCode::SUCCESS;
+    }
+    for problem in &problems {
+        println!("{}: {}", problem.kind_name(), problem);
+    }
+    println!("{} problems found in '{}'", problems.len(), path.display());
+    ExitCode::FAILURE
+}
+
+const MIGRATE_USAGE: &str =
+    "Usage: rustql migrate --from <json|btree>:<file> --to <json|btree>:<file>";
+
+/// Runs `rustql migrate --from json:data.json --to btree:data.db`, copying a
+/// database into a new storage file and checking the copy.
+fn migrate_files(args: &[String]) -> ExitCode {
+    let (mut from, mut to) = (None, None);
+    for pair in args.chunks(2) {
+        let slot = match pair[0].as_str() {
+            "--from" => &mut from,
+            "--to" => &mut to,
+            _ => {
+                eprintln!("{}", MIGRATE_USAGE);
+                return ExitCode::FAILURE;
+            }
+        };
+        *slot = pair.get(1).map(|spec| parse_storage_spec(spec));
+    }
+    let (Some(Some(from)), Some(Some(to))) = (from, to) else {
+        eprintln!("{}", MIGRATE_USAGE);
+        return ExitCode::FAILURE;
+    };
+
+    match rustql::migrate(&from, &to) {
+        Ok(report) => {
+            println!(
+                "Migrated {} tables ({} rows), {} indexes and {} views",
+                report.tables.len(),
+                report.total_rows(),
+                report.indexes,
+                report.views
+            );
+            ExitCode::SUCCESS
+        }
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+/// Parses `json:<file>` or `btree:<file>`.
+fn parse_storage_spec(spec: &str) -> Option<StorageMode> {
+    let (kind, path) = spec.split_once(':')?;
+    if path.is_empty() {
+        return None;
+    }
+    let path = path.into();
+    if kind.eq_ignore_ascii_case("json") {
+        Some(StorageMode::Json { path })
+    } else if kind.eq_ignore_ascii_case("btree") {
+        Some(StorageMode::BTree { path })
+    } else {
+        None
+    }
+}
+
+/// Opens B-tree files by their header and anything else as JSON.
+fn storage_options_for(path: &Path) -> io::Result<EngineOptions> {
+    let mut magic = [0u8; 8];
+    let mut file = File::open(path)?;
+    let is_btree = file.read_exact(&mut magic).is_ok() && &magic == b"RSTQLBT\0";
+    Ok(if is_btree {
+        EngineOptions::btree(path)
+    } else {
+        EngineOptions::json(path)
+    })
+}
+
 fn render_results(results: &[QueryResult]) -> String {
     results
         .iter()
@@ -102,17 +214,27 @@ fn render_command(tag: CommandTag, affected: u64) -> String {
         CommandTag::Update => format!("UPDATE {}", affected),
         CommandTag::Delete => format!("DELETE {}", affected),
         CommandTag::BeginTransaction => "BEGIN".to_string(),
+        CommandTag::SetTransaction => "SET TRANSACTION".to_string(),
+        CommandTag::SetConstraints => "SET CONSTRAINTS".to_string(),
+        CommandTag::SetStatementTimeout => "SET".to_string(),
         CommandTag::CommitTransaction => "COMMIT".to_string(),
         CommandTag::RollbackTransaction => "ROLLBACK".to_string(),
         CommandTag::Savepoint => "SAVEPOINT".to_string(),
         CommandTag::ReleaseSavepoint => "RELEASE SAVEPOINT".to_string(),
         CommandTag::RollbackToSavepoint => "ROLLBACK TO SAVEPOINT".to_string(),
+        CommandTag::PrepareTransaction => "PREPARE TRANSACTION".to_string(),
+        CommandTag::CommitPrepared => "COMMIT PREPARED".to_string(),
+        CommandTag::RollbackPrepared => "ROLLBACK PREPARED".to_string(),
         CommandTag::Analyze => format!("ANALYZE {}", affected),
         CommandTag::TruncateTable => "TRUNCATE TABLE".to_string(),
         CommandTag::CreateView => "CREATE VIEW".to_string(),
         CommandTag::DropView => "DROP VIEW".to_string(),
         CommandTag::Merge => format!("MERGE {}", affected),
         CommandTag::Do => format!("DO {}", affected),
+        CommandTag::Checkpoint => "CHECKPOINT".to_string(),
+        CommandTag::Backup => "BACKUP".to_string(),
+        CommandTag::Attach => "ATTACH".to_string(),
+        CommandTag::Detach => "DETACH".to_string(),
     }
 }
 
@@ -0,0 +1,199 @@
+//! One-way conversion of a database between storage files.
+//!
+//! [`migrate`] reads every table, index and view from a JSON or B-tree file
+//! and writes them to a new file in either format. B-tree sources may use
+//! any supported version of the format, including version 2 files; the
+//! target is always written in the current version. `rustql migrate` runs
+//! the same conversion from the command line.
+
+use crate::database::{Database, SharedMap};
+use crate::engine::StorageMode;
+use crate::error::RustqlError;
+use crate::storage::{
+    BTreeStorageEngine, JsonStorageEngine, StorageEngine, journal_path_for, lock_path_for,
+};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// What [`migrate`] wrote to the target, after checking it against the
+/// source.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MigrationReport {
+    /// Row count of each table, by table name.
+    pub tables: BTreeMap<String, usize>,
+    /// Number of single-column and composite indexes.
+    pub indexes: usize,
+    /// Number of views.
+    pub views: usize,
+}
+
+impl MigrationReport {
+    pub fn total_rows(&self) -> usize {
+        self.tables.values().sum()
+    }
+}
+
+/// Copies the database stored at `from` into a new file at `to`.
+///
+/// Both sides must be [`StorageMode::Json`] or [`StorageMode::BTree`]. The
+/// target must not exist yet, and neither may a B-tree redo log beside it.
+/// Once written, the target is read back and its row counts and index
+/// entries are compared with the source; if they differ the target is
+/// removed again and an error is returned. The source is never modified,
+/// apart from B-tree recovery of a transaction interrupted by a crash.
+pub fn migrate(from: &StorageMode, to: &StorageMode) -> Result<MigrationReport, RustqlError> {
+    let source_path = storage_path(from, "source")?;
+    let target_path = storage_path(to, "target")?;
+    if !source_path.exists() {
+        return Err(RustqlError::StorageError(format!(
+            "Migration source '{}' does not exist",
+            source_path.display()
+        )));
+    }
+    if let Some(existing) = target_artifacts(to, target_path)
+        .into_iter()
+        .find(|path| path.exists())
+    {
+        return Err(RustqlError::StorageError(format!(
+            "Migration target '{}' already exists",
+            existing.display()
+        )));
+    }
+
+    let mut db = open_storage(from, source_path).load()?;
+    db.load_all_paged_tables()?;
+
+    let target = open_storage(to, target_path);
+    target.save(&db)?;
+    let verified = target
+        .load()
+        .and_then(|mut copy| {
+            copy.load_all_paged_tables()?;
+            Ok(copy)
+        })
+        .and_then(|copy| verify_migration(&db, &copy));
+    if verified.is_err() {
+        for path in target_artifacts(to, target_path) {
+            let _ = fs::remove_file(path);
+        }
+        let _ = fs::remove_file(lock_path_for(target_path));
+    }
+    verified
+}
+
+#[allow(deprecated)]
+fn storage_path<'a>(mode: &'a StorageMode, side: &str) -> Result<&'a Path, RustqlError> {
+    match mode {
+        StorageMode::Json { path } | StorageMode::BTree { path } | StorageMode::Disk { path } => {
+            Ok(path)
+        }
+        StorageMode::Memory | StorageMode::Custom(_) => Err(RustqlError::StorageError(format!(
+            "Migration {} must be a JSON or B-tree file",
+            side
+        ))),
+    }
+}
+
+fn open_storage(mode: &StorageMode, path: &Path) -> Box<dyn StorageEngine> {
+    match mode {
+        StorageMode::Json { .. } => Box::new(JsonStorageEngine::new(path)),
+        _ => Box::new(BTreeStorageEngine::new(path)),
+    }
+}
+
+/// Files a migration to `mode` would create.
+fn target_artifacts(mode: &StorageMode, path: &Path) -> Vec<PathBuf> {
+    match mode {
+        StorageMode::Json { .. } => vec![path.to_path_buf()],
+        _ => vec![path.to_path_buf(), journal_path_for(path)],
+    }
+}
+
+/// Compares the tables and indexes read back from the target with the
+/// source they were written from.
+fn verify_migration(source: &Database, target: &Database) -> Result<MigrationReport, RustqlError> {
+    let mut report = MigrationReport {
+        indexes: source.indexes.len() + source.composite_indexes.len(),
+        views: source.views.len(),
+        ..MigrationReport::default()
+    };
+
+    for (name, table) in &source.tables {
+        let copied = target.tables.get(name).map_or(0, |copy| copy.rows.len());
+        if copied != table.rows.len() {
+            return Err(mismatch(format!(
+                "table '{}' has {} rows in the source but {} in the target",
+                name,
+                table.rows.len(),
+                copied
+            )));
+        }
+        report.tables.insert(name.clone(), table.rows.len());
+    }
+    if let Some(extra) = target
+        .tables
+        .keys()
+        .find(|name| !source.tables.contains_key(*name))
+    {
+        return Err(mismatch(format!(
+            "table '{}' appeared in the target",
+            extra
+        )));
+    }
+
+    if target.indexes.len() + target.composite_indexes.len() != report.indexes {
+        return Err(mismatch(format!(
+            "the source has {} indexes but the target has {}",
+            report.indexes,
+            target.indexes.len() + target.composite_indexes.len()
+        )));
+    }
+    for (name, index) in &source.indexes {
+        let copied = target.indexes.get(name).map(|copy| &copy.entries);
+        if copied.map(sorted_entries) != Some(sorted_entries(&index.entries)) {
+            return Err(index_mismatch(name));
+        }
+    }
+    for (name, index) in &source.composite_indexes {
+        let copied = target.composite_indexes.get(name).map(|copy| &copy.entries);
+        if copied.map(sorted_entries) != Some(sorted_entries(&index.entries)) {
+            return Err(index_mismatch(name));
+        }
+    }
+
+    if target.views.len() != report.views {
+        return Err(mismatch(format!(
+            "the source has {} views but the target has {}",
+            report.views,
+            target.views.len()
+        )));
+    }
+    Ok(report)
+}
+
+/// Index entries with each key's row ids in order, since storage formats
+/// may list the rows sharing a key in a different order.
+fn sorted_entries<K: Clone + Ord, R: Clone + Ord>(
+    entries: &SharedMap<K, Vec<R>>,
+) -> BTreeMap<K, Vec<R>> {
+    entries
+        .iter()
+        .map(|(key, row_ids)| {
+            let mut row_ids = row_ids.clone();
+            row_ids.sort();
+            (key.clone(), row_ids)
+        })
+        .collect()
+}
+
+fn index_mismatch(name: &str) -> RustqlError {
+    mismatch(format!(
+        "index '{}' has different entries in the source and the target",
+        name
+    ))
+}
+
+fn mismatch(detail: String) -> RustqlError {
+    RustqlError::StorageError(format!("Migration check failed: {}", detail))
+}
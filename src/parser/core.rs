@@ -56,6 +56,20 @@ impl Parser {
         token
     }
 
+    /// Whether the current token is the identifier `word`, for keywords
+    /// that are not reserved.
+    pub(super) fn is_word(&self, word: &str) -> bool {
+        matches!(self.current_token(), Token::Identifier(name) if name.eq_ignore_ascii_case(word))
+    }
+
+    pub(super) fn consume_word(&mut self, word: &str) -> bool {
+        let matched = self.is_word(word);
+        if matched {
+            self.advance();
+        }
+        matched
+    }
+
     pub(super) fn parse_statement(&mut self) -> Result<Statement, RustqlError> {
         match self.current_token() {
             Token::Explain => self.parse_explain(),
@@ -68,14 +82,30 @@ impl Parser {
             Token::Drop => self.parse_drop(),
             Token::Alter => self.parse_alter(),
             Token::Begin => self.parse_begin_transaction(),
+            Token::Set => self.parse_set(),
             Token::Commit => self.parse_commit_transaction(),
             Token::Rollback => self.parse_rollback(),
+            Token::Identifier(word) if word.eq_ignore_ascii_case("PREPARE") => {
+                self.parse_prepare_transaction()
+            }
+            Token::Identifier(word) if word.eq_ignore_ascii_case("ATTACH") => self.parse_attach(),
+            Token::Identifier(word) if word.eq_ignore_ascii_case("DETACH") => self.parse_detach(),
             Token::Savepoint => self.parse_savepoint(),
             Token::Release => self.parse_release_savepoint(),
             Token::Describe => self.parse_describe(),
             Token::Show => self.parse_show(),
             Token::Analyze => self.parse_analyze(),
             Token::Truncate => self.parse_truncate(),
+            Token::Checkpoint => {
+                self.advance();
+                Ok(Statement::Checkpoint)
+            }
+            Token::Backup => self.parse_backup(),
+            Token::Vacuum => {
+                self.advance();
+                Ok(Statement::Vacuum)
+            }
+            Token::Check => self.parse_check_database(),
             Token::Merge => self.parse_merge(),
             Token::Do => self.parse_do_block(),
             _ => Err(RustqlError::ParseError(format!(
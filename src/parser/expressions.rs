@@ -1,4 +1,5 @@
 use super::*;
+use crate::interval::Interval;
 
 impl Parser {
     pub(super) fn parse_case_expression(&mut self) -> Result<Expression, RustqlError> {
@@ -43,6 +44,40 @@ impl Parser {
         })
     }
 
+    /// `INTERVAL '3 days 4 hours'`, or `INTERVAL '3' DAY` with the unit
+    /// written after the quoted amount.
+    pub(super) fn parse_interval_literal(&mut self) -> Result<Expression, RustqlError> {
+        self.consume(Token::Interval)?;
+        let Token::StringLiteral(text) = self.advance() else {
+            return Err(RustqlError::ParseError(
+                "Expected a quoted interval after INTERVAL".to_string(),
+            ));
+        };
+        let unit = match self.current_token() {
+            Token::Year => Some("years"),
+            Token::Month => Some("months"),
+            Token::Week => Some("weeks"),
+            Token::Day => Some("days"),
+            Token::Identifier(name) => match name.to_uppercase().as_str() {
+                "HOUR" => Some("hours"),
+                "MINUTE" => Some("minutes"),
+                "SECOND" => Some("seconds"),
+                _ => None,
+            },
+            _ => None,
+        };
+        let text = match unit {
+            Some(unit) => {
+                self.advance();
+                format!("{} {}", text, unit)
+            }
+            None => text,
+        };
+        Interval::parse(&text)
+            .map(|interval| Expression::Value(Value::Interval(interval)))
+            .ok_or_else(|| RustqlError::ParseError(format!("Invalid interval '{}'", text)))
+    }
+
     pub(super) fn parse_scalar_function(&mut self) -> Result<Expression, RustqlError> {
         let func_type = match self.advance() {
             Token::Upper => ScalarFunctionType::Upper,
@@ -108,6 +143,18 @@ impl Parser {
             Token::Lcm => ScalarFunctionType::Lcm,
             Token::Initcap => ScalarFunctionType::Initcap,
             Token::SplitPart => ScalarFunctionType::SplitPart,
+            Token::Encode => ScalarFunctionType::Encode,
+            Token::Decode => ScalarFunctionType::Decode,
+            Token::JsonExtract => ScalarFunctionType::JsonExtract,
+            Token::JsonSet => ScalarFunctionType::JsonSet,
+            Token::JsonArrayLength => ScalarFunctionType::JsonArrayLength,
+            Token::JsonTypeof => ScalarFunctionType::JsonTypeof,
+            Token::JustifyDays => ScalarFunctionType::JustifyDays,
+            Token::JustifyHours => ScalarFunctionType::JustifyHours,
+            Token::JustifyInterval => ScalarFunctionType::JustifyInterval,
+            // AGE stays an identifier so columns can still be named `age`.
+            Token::Identifier(name) if name.eq_ignore_ascii_case("AGE") => ScalarFunctionType::Age,
+            Token::JsonBuildObject => ScalarFunctionType::JsonBuildObject,
             Token::Translate => ScalarFunctionType::Translate,
             Token::RegexpMatch => ScalarFunctionType::RegexpMatch,
             Token::RegexpReplace => ScalarFunctionType::RegexpReplace,
@@ -199,6 +246,8 @@ impl Parser {
             Token::Mode => AggregateFunctionType::Mode,
             Token::PercentileCont => AggregateFunctionType::PercentileCont,
             Token::PercentileDisc => AggregateFunctionType::PercentileDisc,
+            Token::JsonAgg => AggregateFunctionType::JsonAgg,
+            Token::JsonObjectAgg => AggregateFunctionType::JsonObjectAgg,
             _ => {
                 return Err(RustqlError::ParseError(
                     "Expected aggregate function".to_string(),
@@ -284,6 +333,15 @@ impl Parser {
                     }
                 }
             }
+            AggregateFunctionType::JsonObjectAgg => {
+                let key = self.parse_expression()?;
+                self.consume(Token::Comma)?;
+                let value = self.parse_expression()?;
+                Box::new(Expression::ScalarFunction {
+                    name: ScalarFunctionType::JsonBuildObject,
+                    args: vec![key, value],
+                })
+            }
             _ => {
                 if *self.current_token() == Token::Star {
                     self.advance();
@@ -775,6 +833,10 @@ impl Parser {
                 Token::Plus => BinaryOperator::Plus,
                 Token::Minus => BinaryOperator::Minus,
                 Token::Concat => BinaryOperator::Concat,
+                Token::Arrow => BinaryOperator::JsonField,
+                Token::LongArrow => BinaryOperator::JsonFieldText,
+                Token::HashArrow => BinaryOperator::JsonPath,
+                Token::AtArrow => BinaryOperator::JsonContains,
                 _ => break,
             };
 
@@ -833,16 +895,32 @@ impl Parser {
     }
 
     pub(super) fn parse_primary(&mut self) -> Result<Expression, RustqlError> {
-        let expr = self.parse_primary_inner()?;
-        if *self.current_token() == Token::DoubleColon {
-            self.advance();
-            let data_type = self.parse_data_type()?;
-            return Ok(Expression::Cast {
-                expr: Box::new(expr),
-                data_type,
-            });
+        let mut expr = self.parse_primary_inner()?;
+        loop {
+            if *self.current_token() == Token::DoubleColon {
+                self.advance();
+                let data_type = self.parse_data_type()?;
+                expr = Expression::Cast {
+                    expr: Box::new(expr),
+                    data_type,
+                };
+            } else if self.is_word("AT")
+                && self.tokens.get(self.current + 1) == Some(&Token::Time)
+                && matches!(
+                    self.tokens.get(self.current + 2),
+                    Some(Token::Identifier(word)) if word.eq_ignore_ascii_case("ZONE")
+                )
+            {
+                self.current += 3;
+                let zone = self.parse_primary_inner()?;
+                expr = Expression::ScalarFunction {
+                    name: ScalarFunctionType::Timezone,
+                    args: vec![zone, expr],
+                };
+            } else {
+                return Ok(expr);
+            }
         }
-        Ok(expr)
     }
 
     pub(super) fn parse_primary_inner(&mut self) -> Result<Expression, RustqlError> {
@@ -918,9 +996,36 @@ impl Parser {
             | Token::Lcm
             | Token::Initcap
             | Token::SplitPart
+            | Token::Encode
+            | Token::Decode
+            | Token::JsonExtract
+            | Token::JsonSet
+            | Token::JsonArrayLength
+            | Token::JsonTypeof
+            | Token::JsonBuildObject
+            | Token::JustifyDays
+            | Token::JustifyHours
+            | Token::JustifyInterval
             | Token::Translate
             | Token::RegexpMatch
             | Token::RegexpReplace => self.parse_scalar_function(),
+            Token::Interval => {
+                if matches!(
+                    self.tokens.get(self.current + 1),
+                    Some(Token::StringLiteral(_))
+                ) {
+                    self.parse_interval_literal()
+                } else {
+                    self.advance();
+                    Ok(Expression::Column("interval".to_string()))
+                }
+            }
+            Token::Identifier(_)
+                if self.is_word("AGE")
+                    && self.tokens.get(self.current + 1) == Some(&Token::LeftParen) =>
+            {
+                self.parse_scalar_function()
+            }
             Token::Left | Token::Right => {
                 if self.current + 1 < self.tokens.len()
                     && self.tokens[self.current + 1] == Token::LeftParen
@@ -947,7 +1052,9 @@ impl Parser {
             | Token::Median
             | Token::Mode
             | Token::PercentileCont
-            | Token::PercentileDisc => {
+            | Token::PercentileDisc
+            | Token::JsonAgg
+            | Token::JsonObjectAgg => {
                 let agg = self.parse_aggregate_function()?;
                 if let Column::Function(func) = agg {
                     Ok(Expression::Function(func))
@@ -987,6 +1094,19 @@ impl Parser {
                 self.advance();
                 Ok(Expression::Value(Value::Float(f)))
             }
+            Token::Decimal(d) => {
+                self.advance();
+                Ok(Expression::Value(Value::Decimal(d)))
+            }
+            Token::Blob(bytes) => {
+                self.advance();
+                Ok(Expression::Value(Value::Blob(bytes)))
+            }
+            Token::BoundValue(value) => {
+                self.advance();
+                Ok(Expression::Value(value))
+            }
+            Token::Parameter(number) => Err(unbound_parameter(number)),
             Token::StringLiteral(s) => {
                 self.advance();
                 Ok(Expression::Value(Value::Text(s)))
@@ -1021,7 +1141,8 @@ impl Parser {
             | Token::Matched
             | Token::Generated
             | Token::Always
-            | Token::Stored => {
+            | Token::Stored
+            | Token::Key => {
                 let name = token_to_string(self.current_token()).to_lowercase();
                 self.advance();
                 Ok(Expression::Column(name))
@@ -15,7 +15,8 @@ mod tokens;
 
 #[allow(unused_imports)]
 pub use api::{parse, parse_script, parse_script_spanned, parse_spanned};
-use tokens::{token_to_sql, token_to_string};
+use select_clauses::qualified_table_alias;
+use tokens::{token_to_sql, token_to_string, unbound_parameter};
 
 pub struct Parser {
     tokens: Vec<Token>,
@@ -167,7 +167,12 @@ impl Parser {
                     }
                 };
                 (alias.clone(), Some((Box::new(subquery), alias)), None, None)
-            } else if *self.current_token() == Token::GenerateSeries {
+            } else if let Some(function_name) = match self.current_token() {
+                Token::GenerateSeries => Some("generate_series"),
+                Token::JsonEach => Some("json_each"),
+                Token::JsonArrayElements => Some("json_array_elements"),
+                _ => None,
+            } {
                 self.advance();
                 self.consume(Token::LeftParen)?;
                 let mut args = Vec::new();
@@ -194,7 +199,7 @@ impl Parser {
                     None
                 };
                 let tf = TableFunction {
-                    name: "generate_series".to_string(),
+                    name: function_name.to_string(),
                     args,
                     alias: alias.clone(),
                 };
@@ -217,6 +222,7 @@ impl Parser {
                 } else {
                     None
                 };
+                let alias = alias.or_else(|| qualified_table_alias(&name));
                 (name, None, alias, None)
             }
         } else {
@@ -302,7 +308,8 @@ impl Parser {
                     }
                 } else {
                     None
-                };
+                }
+                .or_else(|| qualified_table_alias(&join_table));
 
                 let (on_expr, using_cols) =
                     if matches!(join_type, JoinType::Cross | JoinType::Natural)
@@ -605,7 +612,9 @@ impl Parser {
                     | Token::Median
                     | Token::Mode
                     | Token::PercentileCont
-                    | Token::PercentileDisc => {
+                    | Token::PercentileDisc
+                    | Token::JsonAgg
+                    | Token::JsonObjectAgg => {
                         if self.current + 1 < self.tokens.len()
                             && self.tokens[self.current + 1] == Token::LeftParen
                         {
@@ -617,7 +626,10 @@ impl Parser {
                                         | Token::Identifier(_)
                                         | Token::Number(_)
                                         | Token::Float(_)
+                                        | Token::Decimal(_)
                                         | Token::StringLiteral(_)
+                                        | Token::Blob(_)
+                                        | Token::BoundValue(_)
                                 )
                             {
                                 self.parse_aggregate_function()?
@@ -703,20 +715,7 @@ impl Parser {
                         Column::Expression { expr, alias }
                     }
                     Token::Cast => {
-                        let expr = self.parse_cast_expression()?;
-                        let alias = if *self.current_token() == Token::As {
-                            self.advance();
-                            match self.advance() {
-                                Token::Identifier(alias) => Some(alias),
-                                _ => {
-                                    return Err(RustqlError::ParseError(
-                                        "Expected alias after AS".to_string(),
-                                    ));
-                                }
-                            }
-                        } else {
-                            None
-                        };
+                        let (expr, alias) = self.parse_column_expression()?;
                         Column::Expression { expr, alias }
                     }
                     Token::Upper
@@ -781,23 +780,17 @@ impl Parser {
                     | Token::Lcm
                     | Token::Initcap
                     | Token::SplitPart
+                    | Token::Encode
+                    | Token::Decode
+                    | Token::JsonExtract
+                    | Token::JsonSet
+                    | Token::JsonArrayLength
+                    | Token::JsonTypeof
+                    | Token::JsonBuildObject
                     | Token::Translate
                     | Token::RegexpMatch
                     | Token::RegexpReplace => {
-                        let expr = self.parse_scalar_function()?;
-                        let alias = if *self.current_token() == Token::As {
-                            self.advance();
-                            match self.advance() {
-                                Token::Identifier(alias) => Some(alias),
-                                _ => {
-                                    return Err(RustqlError::ParseError(
-                                        "Expected alias after AS".to_string(),
-                                    ));
-                                }
-                            }
-                        } else {
-                            None
-                        };
+                        let (expr, alias) = self.parse_column_expression()?;
                         Column::Expression { expr, alias }
                     }
                     Token::Left | Token::Right => {
@@ -1257,3 +1250,9 @@ impl Parser {
         Ok(order_exprs)
     }
 }
+
+/// The name a table of an attached database, such as `archive.orders`, is
+/// referenced by when it has no alias: `orders`.
+pub(super) fn qualified_table_alias(table: &str) -> Option<String> {
+    table.rsplit_once('.').map(|(_, name)| name.to_string())
+}
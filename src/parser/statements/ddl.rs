@@ -1,4 +1,5 @@
 use super::*;
+use crate::decimal::MAX_DECIMAL_PRECISION;
 
 impl Parser {
     pub(crate) fn parse_create(&mut self) -> Result<Statement, RustqlError> {
@@ -199,6 +200,7 @@ impl Parser {
                 table_constraints.push(crate::ast::TableConstraint::Unique {
                     name: None,
                     columns: cols,
+                    deferrable: self.parse_deferrable()?,
                 });
                 if *self.current_token() == Token::Comma {
                     self.advance();
@@ -266,6 +268,7 @@ impl Parser {
                     table_constraints.push(crate::ast::TableConstraint::Unique {
                         name: Some(constraint_name),
                         columns: cols,
+                        deferrable: self.parse_deferrable()?,
                     });
                 } else {
                     return Err(RustqlError::ParseError(
@@ -309,7 +312,16 @@ impl Parser {
 
             if *self.current_token() == Token::Unique {
                 self.advance();
-                unique = true;
+                // A deferrable key is checked as a table constraint, which
+                // is where deferred checks live.
+                match self.parse_deferrable()? {
+                    crate::ast::Deferrable::No => unique = true,
+                    deferrable => table_constraints.push(crate::ast::TableConstraint::Unique {
+                        name: None,
+                        columns: vec![name.clone()],
+                        deferrable,
+                    }),
+                }
             }
 
             if *self.current_token() == Token::Default {
@@ -437,6 +449,7 @@ impl Parser {
                     referenced_column: ref_column,
                     on_delete,
                     on_update,
+                    deferrable: self.parse_deferrable()?,
                 })
             } else {
                 None
@@ -506,18 +519,75 @@ impl Parser {
         Ok((columns, table_constraints))
     }
 
+    /// Parses the `[NOT] DEFERRABLE` and `INITIALLY {DEFERRED | IMMEDIATE}`
+    /// clauses that may follow a `UNIQUE` or `REFERENCES` constraint, in
+    /// either order.
+    fn parse_deferrable(&mut self) -> Result<crate::ast::Deferrable, RustqlError> {
+        let mut deferrable = None;
+        let mut initially_deferred = None;
+        loop {
+            let not_deferrable = *self.current_token() == Token::Not
+                && matches!(
+                    self.tokens.get(self.current + 1),
+                    Some(Token::Identifier(word)) if word.eq_ignore_ascii_case("DEFERRABLE")
+                );
+            if not_deferrable || self.is_word("DEFERRABLE") {
+                if not_deferrable {
+                    self.advance();
+                }
+                self.advance();
+                if deferrable.replace(!not_deferrable).is_some() {
+                    return Err(RustqlError::ParseError(
+                        "DEFERRABLE or NOT DEFERRABLE given more than once".to_string(),
+                    ));
+                }
+            } else if self.consume_word("INITIALLY") {
+                let deferred = if self.consume_word("DEFERRED") {
+                    true
+                } else if self.consume_word("IMMEDIATE") {
+                    false
+                } else {
+                    return Err(RustqlError::ParseError(
+                        "Expected DEFERRED or IMMEDIATE after INITIALLY".to_string(),
+                    ));
+                };
+                if initially_deferred.replace(deferred).is_some() {
+                    return Err(RustqlError::ParseError(
+                        "INITIALLY given more than once".to_string(),
+                    ));
+                }
+            } else {
+                break;
+            }
+        }
+        match (deferrable, initially_deferred) {
+            (Some(false), Some(true)) => Err(RustqlError::ParseError(
+                "A constraint declared INITIALLY DEFERRED must be DEFERRABLE".to_string(),
+            )),
+            (_, Some(true)) => Ok(crate::ast::Deferrable::InitiallyDeferred),
+            (Some(true), _) => Ok(crate::ast::Deferrable::InitiallyImmediate),
+            _ => Ok(crate::ast::Deferrable::No),
+        }
+    }
+
     pub(crate) fn parse_data_type(&mut self) -> Result<DataType, RustqlError> {
         match self.advance() {
             Token::Boolean => Ok(DataType::Boolean),
             Token::Date => Ok(DataType::Date),
             Token::Time => Ok(DataType::Time),
             Token::DateTime => Ok(DataType::DateTime),
+            Token::Interval => Ok(DataType::Interval),
             Token::Identifier(name) => match name.to_uppercase().as_str() {
                 "INT" | "INTEGER" => Ok(DataType::Integer),
                 "FLOAT" | "REAL" | "DOUBLE" => Ok(DataType::Float),
                 "TEXT" | "VARCHAR" | "STRING" => Ok(DataType::Text),
                 "BOOL" => Ok(DataType::Boolean),
-                "DATETIME" | "TIMESTAMP" => Ok(DataType::DateTime),
+                "DATETIME" => Ok(DataType::DateTime),
+                "TIMESTAMP" => self.parse_timestamp_time_zone(),
+                "TIMESTAMPTZ" => Ok(DataType::TimestampTz),
+                "DECIMAL" | "NUMERIC" | "DEC" => self.parse_decimal_type_modifiers(),
+                "BLOB" | "BYTEA" => Ok(DataType::Blob),
+                "JSON" | "JSONB" => Ok(DataType::Json),
                 _ => Err(RustqlError::ParseError(format!(
                     "Unknown data type: {}",
                     name
@@ -527,6 +597,70 @@ impl Parser {
         }
     }
 
+    /// The optional `WITH TIME ZONE` or `WITHOUT TIME ZONE` after `TIMESTAMP`.
+    fn parse_timestamp_time_zone(&mut self) -> Result<DataType, RustqlError> {
+        let with_time_zone = match self.current_token() {
+            Token::With => true,
+            Token::Identifier(word) if word.eq_ignore_ascii_case("WITHOUT") => false,
+            _ => return Ok(DataType::DateTime),
+        };
+        if self.tokens.get(self.current + 1) != Some(&Token::Time) {
+            return Ok(DataType::DateTime);
+        }
+        self.current += 2;
+        if !self.consume_word("ZONE") {
+            return Err(RustqlError::ParseError(
+                "Expected ZONE after TIMESTAMP WITH TIME".to_string(),
+            ));
+        }
+        Ok(if with_time_zone {
+            DataType::TimestampTz
+        } else {
+            DataType::DateTime
+        })
+    }
+
+    fn parse_decimal_type_modifiers(&mut self) -> Result<DataType, RustqlError> {
+        if *self.current_token() != Token::LeftParen {
+            return Ok(DataType::Decimal {
+                precision: MAX_DECIMAL_PRECISION,
+                scale: 0,
+            });
+        }
+        self.advance();
+        let precision = self.parse_decimal_type_modifier("precision")?;
+        let scale = if *self.current_token() == Token::Comma {
+            self.advance();
+            self.parse_decimal_type_modifier("scale")?
+        } else {
+            0
+        };
+        self.consume(Token::RightParen)?;
+        if precision == 0 || precision > MAX_DECIMAL_PRECISION {
+            return Err(RustqlError::ParseError(format!(
+                "DECIMAL precision must be between 1 and {}",
+                MAX_DECIMAL_PRECISION
+            )));
+        }
+        if scale > precision {
+            return Err(RustqlError::ParseError(
+                "DECIMAL scale must not exceed its precision".to_string(),
+            ));
+        }
+        Ok(DataType::Decimal { precision, scale })
+    }
+
+    fn parse_decimal_type_modifier(&mut self, what: &str) -> Result<u32, RustqlError> {
+        match self.advance() {
+            Token::Number(n) => u32::try_from(n)
+                .map_err(|_| RustqlError::ParseError(format!("Invalid DECIMAL {}: {}", what, n))),
+            _ => Err(RustqlError::ParseError(format!(
+                "Expected DECIMAL {}",
+                what
+            ))),
+        }
+    }
+
     pub(crate) fn parse_drop(&mut self) -> Result<Statement, RustqlError> {
         self.consume(Token::Drop)?;
 
@@ -679,6 +813,7 @@ impl Parser {
                             AlterOperation::AddConstraint(crate::ast::TableConstraint::Unique {
                                 name: Some(constraint_name),
                                 columns: cols,
+                                deferrable: self.parse_deferrable()?,
                             })
                         } else {
                             return Err(RustqlError::ParseError(
@@ -735,6 +870,7 @@ impl Parser {
                         AlterOperation::AddConstraint(crate::ast::TableConstraint::Unique {
                             name: None,
                             columns: cols,
+                            deferrable: self.parse_deferrable()?,
                         })
                     }
                     _ => {
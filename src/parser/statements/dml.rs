@@ -179,6 +179,10 @@ impl Parser {
             Token::False => Ok(Value::Boolean(false)),
             Token::Number(n) => Ok(Value::Integer(n)),
             Token::Float(f) => Ok(Value::Float(f)),
+            Token::Decimal(d) => Ok(Value::Decimal(d)),
+            Token::Blob(bytes) => Ok(Value::Blob(bytes)),
+            Token::BoundValue(value) => Ok(value),
+            Token::Parameter(number) => Err(unbound_parameter(number)),
             Token::StringLiteral(s) => Ok(Value::Text(s)),
             _ => Err(RustqlError::ParseError("Expected value".to_string())),
         }
@@ -216,7 +220,8 @@ impl Parser {
                 }
             } else {
                 None
-            };
+            }
+            .or_else(|| qualified_table_alias(&from_table));
             let mut joins = Vec::new();
             loop {
                 let join_type = if *self.current_token() == Token::Left {
@@ -257,7 +262,8 @@ impl Parser {
                         }
                     } else {
                         None
-                    };
+                    }
+                    .or_else(|| qualified_table_alias(&join_table));
                     self.consume(Token::On)?;
                     let on_expr = self.parse_expression()?;
                     joins.push(Join {
@@ -368,7 +374,8 @@ impl Parser {
                 }
             } else {
                 None
-            };
+            }
+            .or_else(|| qualified_table_alias(&using_table));
             let mut joins = Vec::new();
             loop {
                 let join_type = if *self.current_token() == Token::Left {
@@ -408,7 +415,8 @@ impl Parser {
                         }
                     } else {
                         None
-                    };
+                    }
+                    .or_else(|| qualified_table_alias(&join_table));
                     self.consume(Token::On)?;
                     let on_expr = self.parse_expression()?;
                     joins.push(Join {
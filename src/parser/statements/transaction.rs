@@ -6,11 +6,149 @@ impl Parser {
         if *self.current_token() == Token::Transaction {
             self.advance();
         }
-        Ok(Statement::BeginTransaction)
+        let modes = self.parse_transaction_modes()?;
+        Ok(Statement::BeginTransaction(modes))
+    }
+
+    pub(crate) fn parse_set(&mut self) -> Result<Statement, RustqlError> {
+        self.consume(Token::Set)?;
+        if self.consume_word("CONSTRAINTS") {
+            return self.parse_set_constraints();
+        }
+        if self.consume_word("STATEMENT_TIMEOUT") {
+            return self.parse_set_statement_timeout();
+        }
+        if *self.current_token() != Token::Transaction {
+            return Err(RustqlError::ParseError(
+                "SET must be followed by TRANSACTION, CONSTRAINTS or statement_timeout".to_string(),
+            ));
+        }
+        self.advance();
+        let modes = self.parse_transaction_modes()?;
+        if modes == TransactionModes::default() {
+            return Err(RustqlError::ParseError(
+                "Expected ISOLATION LEVEL, READ ONLY or READ WRITE after SET TRANSACTION"
+                    .to_string(),
+            ));
+        }
+        Ok(Statement::SetTransaction(modes))
+    }
+
+    fn parse_set_constraints(&mut self) -> Result<Statement, RustqlError> {
+        if *self.current_token() != Token::All {
+            return Err(RustqlError::ParseError(
+                "Expected ALL after SET CONSTRAINTS".to_string(),
+            ));
+        }
+        self.advance();
+        let deferred = if self.consume_word("DEFERRED") {
+            true
+        } else if self.consume_word("IMMEDIATE") {
+            false
+        } else {
+            return Err(RustqlError::ParseError(
+                "Expected DEFERRED or IMMEDIATE after SET CONSTRAINTS ALL".to_string(),
+            ));
+        };
+        Ok(Statement::SetConstraints { deferred })
+    }
+
+    /// Parses `= value` or `TO value` after `SET statement_timeout`. The value
+    /// is `DEFAULT`, a number of milliseconds, or a string holding a number
+    /// with an optional unit of `ms`, `s`, `min` or `h`. Zero turns the
+    /// timeout off.
+    fn parse_set_statement_timeout(&mut self) -> Result<Statement, RustqlError> {
+        if matches!(self.current_token(), Token::Equal | Token::To) {
+            self.advance();
+        } else {
+            return Err(RustqlError::ParseError(
+                "Expected = or TO after SET statement_timeout".to_string(),
+            ));
+        }
+        let milliseconds = match self.current_token().clone() {
+            Token::Default => 0,
+            Token::Number(value) if value >= 0 => value as u64,
+            Token::StringLiteral(text) => parse_timeout_milliseconds(&text)?,
+            _ => {
+                return Err(RustqlError::ParseError(
+                    "Expected a duration such as '5s' or a number of milliseconds for statement_timeout"
+                        .to_string(),
+                ));
+            }
+        };
+        self.advance();
+        Ok(Statement::SetStatementTimeout { milliseconds })
+    }
+
+    /// Parses `ISOLATION LEVEL ...`, `READ ONLY` and `READ WRITE`, separated
+    /// by spaces or commas. The words are not reserved, so they are matched
+    /// as identifiers.
+    fn parse_transaction_modes(&mut self) -> Result<TransactionModes, RustqlError> {
+        let mut modes = TransactionModes::default();
+        loop {
+            if self.consume_word("ISOLATION") {
+                if !self.consume_word("LEVEL") {
+                    return Err(RustqlError::ParseError(
+                        "Expected LEVEL after ISOLATION".to_string(),
+                    ));
+                }
+                let level = if self.consume_word("SERIALIZABLE") {
+                    IsolationLevel::Serializable
+                } else if self.consume_word("REPEATABLE") && self.consume_word("READ") {
+                    IsolationLevel::RepeatableRead
+                } else if self.consume_word("READ")
+                    && (self.consume_word("COMMITTED") || self.consume_word("UNCOMMITTED"))
+                {
+                    IsolationLevel::ReadCommitted
+                } else {
+                    return Err(RustqlError::ParseError(
+                        "Expected SERIALIZABLE, REPEATABLE READ, READ COMMITTED or READ UNCOMMITTED after ISOLATION LEVEL".to_string(),
+                    ));
+                };
+                if modes.isolation_level.replace(level).is_some() {
+                    return Err(RustqlError::ParseError(
+                        "Isolation level given more than once".to_string(),
+                    ));
+                }
+            } else if self.consume_word("READ") {
+                let read_only = if *self.current_token() == Token::Only {
+                    self.advance();
+                    true
+                } else if self.consume_word("WRITE") {
+                    false
+                } else {
+                    return Err(RustqlError::ParseError(
+                        "Expected ONLY or WRITE after READ".to_string(),
+                    ));
+                };
+                if modes.read_only.replace(read_only).is_some() {
+                    return Err(RustqlError::ParseError(
+                        "READ ONLY or READ WRITE given more than once".to_string(),
+                    ));
+                }
+            } else if modes == TransactionModes::default() {
+                return Ok(modes);
+            } else {
+                return Err(RustqlError::ParseError(
+                    "Expected a transaction mode after ','".to_string(),
+                ));
+            }
+
+            if *self.current_token() == Token::Comma {
+                self.advance();
+            } else if !self.is_word("ISOLATION") && !self.is_word("READ") {
+                return Ok(modes);
+            }
+        }
     }
 
     pub(crate) fn parse_commit_transaction(&mut self) -> Result<Statement, RustqlError> {
         self.consume(Token::Commit)?;
+        if self.consume_word("PREPARED") {
+            return Ok(Statement::CommitPrepared(
+                self.parse_transaction_gid("COMMIT PREPARED")?,
+            ));
+        }
         if *self.current_token() == Token::Transaction {
             self.advance();
         }
@@ -19,6 +157,11 @@ impl Parser {
 
     pub(crate) fn parse_rollback(&mut self) -> Result<Statement, RustqlError> {
         self.consume(Token::Rollback)?;
+        if self.consume_word("PREPARED") {
+            return Ok(Statement::RollbackPrepared(
+                self.parse_transaction_gid("ROLLBACK PREPARED")?,
+            ));
+        }
         if *self.current_token() == Token::Transaction {
             self.advance();
             return Ok(Statement::RollbackTransaction);
@@ -41,6 +184,24 @@ impl Parser {
         Ok(Statement::RollbackTransaction)
     }
 
+    pub(crate) fn parse_prepare_transaction(&mut self) -> Result<Statement, RustqlError> {
+        self.advance();
+        self.consume(Token::Transaction)?;
+        Ok(Statement::PrepareTransaction(
+            self.parse_transaction_gid("PREPARE TRANSACTION")?,
+        ))
+    }
+
+    fn parse_transaction_gid(&mut self, after: &str) -> Result<String, RustqlError> {
+        match self.advance() {
+            Token::StringLiteral(gid) => Ok(gid),
+            _ => Err(RustqlError::ParseError(format!(
+                "Expected transaction identifier string after {}",
+                after
+            ))),
+        }
+    }
+
     pub(crate) fn parse_savepoint(&mut self) -> Result<Statement, RustqlError> {
         self.consume(Token::Savepoint)?;
         let name = match self.advance() {
@@ -70,3 +231,27 @@ impl Parser {
         Ok(Statement::ReleaseSavepoint(name))
     }
 }
+
+fn parse_timeout_milliseconds(text: &str) -> Result<u64, RustqlError> {
+    let text = text.trim();
+    let digits = text
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(text.len());
+    let (value, unit) = text.split_at(digits);
+    let multiplier = match unit.trim().to_ascii_lowercase().as_str() {
+        "" | "ms" => 1,
+        "s" => 1_000,
+        "min" => 60_000,
+        "h" => 3_600_000,
+        _ => 0,
+    };
+    match value.parse::<u64>() {
+        Ok(value) if multiplier > 0 => value.checked_mul(multiplier).ok_or_else(|| {
+            RustqlError::ParseError(format!("statement_timeout '{}' is too large", text))
+        }),
+        _ => Err(RustqlError::ParseError(format!(
+            "Invalid statement_timeout '{}'; expected a number with an optional unit of ms, s, min or h",
+            text
+        ))),
+    }
+}
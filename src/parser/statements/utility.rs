@@ -42,16 +42,32 @@ impl Parser {
 
     pub(crate) fn parse_show(&mut self) -> Result<Statement, RustqlError> {
         self.consume(Token::Show)?;
-        if *self.current_token() == Token::Tables {
-            self.advance();
-            Ok(Statement::ShowTables)
-        } else {
-            Err(RustqlError::ParseError(
-                "SHOW must be followed by TABLES".to_string(),
-            ))
+        match self.current_token() {
+            Token::Tables => {
+                self.advance();
+                Ok(Statement::ShowTables)
+            }
+            Token::Storage => {
+                self.advance();
+                Ok(Statement::ShowStorage)
+            }
+            _ => Err(RustqlError::ParseError(
+                "SHOW must be followed by TABLES or STORAGE".to_string(),
+            )),
         }
     }
 
+    pub(crate) fn parse_check_database(&mut self) -> Result<Statement, RustqlError> {
+        self.consume(Token::Check)?;
+        if *self.current_token() != Token::Database {
+            return Err(RustqlError::ParseError(
+                "CHECK must be followed by DATABASE".to_string(),
+            ));
+        }
+        self.advance();
+        Ok(Statement::CheckDatabase)
+    }
+
     pub(crate) fn parse_analyze(&mut self) -> Result<Statement, RustqlError> {
         self.consume(Token::Analyze)?;
         let table_name = match self.advance() {
@@ -81,6 +97,58 @@ impl Parser {
         Ok(Statement::TruncateTable { table_name })
     }
 
+    pub(crate) fn parse_backup(&mut self) -> Result<Statement, RustqlError> {
+        self.consume(Token::Backup)?;
+        self.consume(Token::To)?;
+        let path = match self.advance() {
+            Token::StringLiteral(path) => path,
+            _ => {
+                return Err(RustqlError::ParseError(
+                    "Expected file path string after BACKUP TO".to_string(),
+                ));
+            }
+        };
+        Ok(Statement::Backup { path })
+    }
+
+    /// Parses `ATTACH [DATABASE] 'path' AS name`.
+    pub(crate) fn parse_attach(&mut self) -> Result<Statement, RustqlError> {
+        self.advance();
+        if *self.current_token() == Token::Database {
+            self.advance();
+        }
+        let path = match self.advance() {
+            Token::StringLiteral(path) => path,
+            _ => {
+                return Err(RustqlError::ParseError(
+                    "Expected file path string after ATTACH".to_string(),
+                ));
+            }
+        };
+        self.consume(Token::As)?;
+        let name = self.parse_attached_name("AS")?;
+        Ok(Statement::Attach { path, name })
+    }
+
+    /// Parses `DETACH [DATABASE] name`.
+    pub(crate) fn parse_detach(&mut self) -> Result<Statement, RustqlError> {
+        self.advance();
+        if *self.current_token() == Token::Database {
+            self.advance();
+        }
+        Ok(Statement::Detach(self.parse_attached_name("DETACH")?))
+    }
+
+    fn parse_attached_name(&mut self, after: &str) -> Result<String, RustqlError> {
+        match self.advance() {
+            Token::Identifier(name) if !name.contains('.') => Ok(name),
+            _ => Err(RustqlError::ParseError(format!(
+                "Expected database name after {}",
+                after
+            ))),
+        }
+    }
+
     pub(crate) fn parse_do_block(&mut self) -> Result<Statement, RustqlError> {
         self.consume(Token::Do)?;
         self.consume(Token::Begin)?;
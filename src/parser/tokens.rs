@@ -5,6 +5,8 @@ pub(super) fn token_to_string(tok: &Token) -> String {
         Token::Identifier(s) => quote_identifier(s),
         Token::Number(n) => n.to_string(),
         Token::Float(f) => f.to_string(),
+        Token::Decimal(d) => d.to_string(),
+        Token::Blob(bytes) => format!("X'{}'", crate::blob::encode_hex(bytes)),
         Token::StringLiteral(s) => quote_string_literal(s),
         Token::Equal => "=".to_string(),
         Token::NotEqual => "<>".to_string(),
@@ -16,6 +18,10 @@ pub(super) fn token_to_string(tok: &Token) -> String {
         Token::Minus => "-".to_string(),
         Token::Star => "*".to_string(),
         Token::Divide => "/".to_string(),
+        Token::Arrow => "->".to_string(),
+        Token::LongArrow => "->>".to_string(),
+        Token::HashArrow => "#>".to_string(),
+        Token::AtArrow => "@>".to_string(),
         Token::And => "AND".to_string(),
         Token::Or => "OR".to_string(),
         Token::Not => "NOT".to_string(),
@@ -94,6 +100,20 @@ pub(super) fn token_to_string(tok: &Token) -> String {
         Token::Lcm => "LCM".to_string(),
         Token::Initcap => "INITCAP".to_string(),
         Token::SplitPart => "SPLIT_PART".to_string(),
+        Token::Encode => "ENCODE".to_string(),
+        Token::Decode => "DECODE".to_string(),
+        Token::JsonExtract => "JSON_EXTRACT".to_string(),
+        Token::JsonSet => "JSON_SET".to_string(),
+        Token::JsonArrayLength => "JSON_ARRAY_LENGTH".to_string(),
+        Token::JsonTypeof => "JSON_TYPEOF".to_string(),
+        Token::JsonBuildObject => "JSON_BUILD_OBJECT".to_string(),
+        Token::JsonEach => "JSON_EACH".to_string(),
+        Token::JsonArrayElements => "JSON_ARRAY_ELEMENTS".to_string(),
+        Token::JsonAgg => "JSON_AGG".to_string(),
+        Token::JsonObjectAgg => "JSON_OBJECT_AGG".to_string(),
+        Token::JustifyDays => "JUSTIFY_DAYS".to_string(),
+        Token::JustifyHours => "JUSTIFY_HOURS".to_string(),
+        Token::JustifyInterval => "JUSTIFY_INTERVAL".to_string(),
         Token::Translate => "TRANSLATE".to_string(),
         Token::RegexpMatch => "REGEXP_MATCH".to_string(),
         Token::RegexpReplace => "REGEXP_REPLACE".to_string(),
@@ -106,7 +126,11 @@ pub(super) fn token_to_sql(tok: &Token) -> String {
         Token::Identifier(s) => quote_identifier(s),
         Token::Number(n) => n.to_string(),
         Token::Float(f) => f.to_string(),
+        Token::Decimal(d) => d.to_string(),
+        Token::Blob(bytes) => format!("X'{}'", crate::blob::encode_hex(bytes)),
         Token::StringLiteral(s) => quote_string_literal(s),
+        Token::Parameter(number) => format!("${}", number),
+        Token::BoundValue(value) => value_to_sql(value),
         Token::LeftParen => "(".to_string(),
         Token::RightParen => ")".to_string(),
         Token::Comma => ",".to_string(),
@@ -123,6 +147,10 @@ pub(super) fn token_to_sql(tok: &Token) -> String {
         Token::GreaterThan => ">".to_string(),
         Token::GreaterThanOrEqual => ">=".to_string(),
         Token::Concat => "||".to_string(),
+        Token::Arrow => "->".to_string(),
+        Token::LongArrow => "->>".to_string(),
+        Token::HashArrow => "#>".to_string(),
+        Token::AtArrow => "@>".to_string(),
         Token::Select => "SELECT".to_string(),
         Token::Exists => "EXISTS".to_string(),
         Token::Distinct => "DISTINCT".to_string(),
@@ -179,6 +207,7 @@ pub(super) fn token_to_sql(tok: &Token) -> String {
         Token::Date => "DATE".to_string(),
         Token::Time => "TIME".to_string(),
         Token::DateTime => "DATETIME".to_string(),
+        Token::Interval => "INTERVAL".to_string(),
         Token::Foreign => "FOREIGN".to_string(),
         Token::Key => "KEY".to_string(),
         Token::References => "REFERENCES".to_string(),
@@ -259,6 +288,11 @@ pub(super) fn token_to_sql(tok: &Token) -> String {
         Token::DateAdd => "DATE_ADD".to_string(),
         Token::Datediff => "DATEDIFF".to_string(),
         Token::Truncate => "TRUNCATE".to_string(),
+        Token::Checkpoint => "CHECKPOINT".to_string(),
+        Token::Backup => "BACKUP".to_string(),
+        Token::Vacuum => "VACUUM".to_string(),
+        Token::Storage => "STORAGE".to_string(),
+        Token::Database => "DATABASE".to_string(),
         Token::View => "VIEW".to_string(),
         Token::ILike => "ILIKE".to_string(),
         Token::Intersect => "INTERSECT".to_string(),
@@ -328,10 +362,58 @@ pub(super) fn token_to_sql(tok: &Token) -> String {
         Token::Row => "ROW".to_string(),
         Token::DoubleColon => "::".to_string(),
         Token::GenerateSeries => "GENERATE_SERIES".to_string(),
+        Token::JsonExtract => "JSON_EXTRACT".to_string(),
+        Token::JsonSet => "JSON_SET".to_string(),
+        Token::JsonArrayLength => "JSON_ARRAY_LENGTH".to_string(),
+        Token::JsonTypeof => "JSON_TYPEOF".to_string(),
+        Token::JsonBuildObject => "JSON_BUILD_OBJECT".to_string(),
+        Token::JsonEach => "JSON_EACH".to_string(),
+        Token::JsonArrayElements => "JSON_ARRAY_ELEMENTS".to_string(),
+        Token::JsonAgg => "JSON_AGG".to_string(),
+        Token::JsonObjectAgg => "JSON_OBJECT_AGG".to_string(),
+        Token::JustifyDays => "JUSTIFY_DAYS".to_string(),
+        Token::JustifyHours => "JUSTIFY_HOURS".to_string(),
+        Token::JustifyInterval => "JUSTIFY_INTERVAL".to_string(),
         _ => format!("{:?}", tok),
     }
 }
 
+pub(super) fn unbound_parameter(number: usize) -> RustqlError {
+    RustqlError::ParseError(format!("No value bound for parameter ${}", number))
+}
+
+/// A literal that reads back as `value`, casting where the lexer has no
+/// literal of its type.
+fn value_to_sql(value: &Value) -> String {
+    match value {
+        Value::Null => "NULL".to_string(),
+        Value::Integer(n) => n.to_string(),
+        Value::Float(f) => format!("{:?}", f),
+        Value::Decimal(d) => format!(
+            "CAST('{}' AS {})",
+            d,
+            DataType::Decimal {
+                precision: crate::decimal::MAX_DECIMAL_PRECISION,
+                scale: d.scale(),
+            }
+        ),
+        Value::Text(s) => quote_string_literal(s),
+        Value::Boolean(b) => if *b { "TRUE" } else { "FALSE" }.to_string(),
+        Value::Date(s) => format!("CAST({} AS DATE)", quote_string_literal(s)),
+        Value::Time(s) => format!("CAST({} AS TIME)", quote_string_literal(s)),
+        Value::DateTime(s) => format!("CAST({} AS DATETIME)", quote_string_literal(s)),
+        Value::TimestampTz(_) => format!(
+            "CAST({} AS TIMESTAMPTZ)",
+            quote_string_literal(&value.to_string())
+        ),
+        Value::Interval(interval) => {
+            format!("INTERVAL {}", quote_string_literal(&interval.to_string()))
+        }
+        Value::Blob(bytes) => format!("X'{}'", crate::blob::encode_hex(bytes)),
+        Value::Json(json) => format!("CAST({} AS JSON)", quote_string_literal(json)),
+    }
+}
+
 fn quote_string_literal(value: &str) -> String {
     let mut quoted = String::with_capacity(value.len() + 2);
     quoted.push('\'');
@@ -7,6 +7,7 @@ use crate::executor::expr::{
     compare_values_same_type, evaluate_expression, evaluate_value_expression_with_db,
     row_has_finite_numeric_value, rows_equal_for_sql_identity,
 };
+use crate::executor::interrupt::check_interrupt;
 use crate::planner::{self, PlanNode};
 use std::cmp::Ordering;
 use std::collections::{BTreeMap, HashSet};
@@ -1,5 +1,8 @@
 use super::*;
+use crate::decimal::Decimal;
 use crate::error::QueryClause;
+use crate::executor::aggregate::decimal_sum;
+use crate::executor::expr::decimal_out_of_range;
 use std::collections::BTreeSet;
 
 struct PreparedAggregateInput {
@@ -171,7 +174,7 @@ impl<'a> PlanExecutor<'a> {
 
         if let Some(grouping_sets) = grouping_sets {
             for set in grouping_sets {
-                let groups = self.build_aggregate_groups(&input, set, &column_defs);
+                let groups = self.build_aggregate_groups(&input, set, &column_defs)?;
 
                 for group in groups {
                     let mut result_row = Vec::with_capacity(group_by.len() + aggregates.len());
@@ -216,7 +219,7 @@ impl<'a> PlanExecutor<'a> {
                 }
             }
         } else {
-            let groups = self.build_aggregate_groups(&input, group_by, &column_defs);
+            let groups = self.build_aggregate_groups(&input, group_by, &column_defs)?;
 
             for group in groups {
                 let mut result_row = group.key.clone();
@@ -255,9 +258,10 @@ impl<'a> PlanExecutor<'a> {
         input: &'row ExecutionResult,
         exprs: &[Expression],
         columns: &[ColumnDefinition],
-    ) -> Vec<AggregateGroup<'row>> {
+    ) -> Result<Vec<AggregateGroup<'row>>, RustqlError> {
         let mut groups = AggregateGroupCollection::new();
         for row in &input.rows {
+            check_interrupt()?;
             let key: Vec<Value> = exprs
                 .iter()
                 .map(|expr| {
@@ -272,7 +276,7 @@ impl<'a> PlanExecutor<'a> {
             groups.insert_empty_group(Vec::new());
         }
 
-        groups.into_groups()
+        Ok(groups.into_groups())
     }
 
     fn compute_group_aggregate_values(
@@ -328,6 +332,7 @@ impl<'a> PlanExecutor<'a> {
         let mut seen = agg.distinct.then(AggregateDistinctTracker::new);
 
         for row in rows {
+            check_interrupt()?;
             if let Some(filter_expr) = agg.filter.as_deref()
                 && !self.evaluate_expression(filter_expr, columns, row)?
             {
@@ -340,7 +345,7 @@ impl<'a> PlanExecutor<'a> {
             }
 
             let value = self.evaluate_value_expression(&agg.expr, columns, row)?;
-            if matches!(value, Value::Null) {
+            if matches!(value, Value::Null) && !keeps_null_inputs(agg) {
                 continue;
             }
 
@@ -374,6 +379,9 @@ impl<'a> PlanExecutor<'a> {
                 }
             }
             AggregateFunctionType::Sum => {
+                if let Some(sum) = decimal_sum(&input.values)? {
+                    return Ok(Value::Decimal(sum));
+                }
                 let mut sum = 0.0f64;
                 let mut has_value = false;
                 for value in &input.values {
@@ -386,6 +394,10 @@ impl<'a> PlanExecutor<'a> {
                             sum += *f;
                             has_value = true;
                         }
+                        Value::Decimal(d) => {
+                            sum += d.to_f64();
+                            has_value = true;
+                        }
                         _ => {
                             return Err(RustqlError::AggregateError(
                                 "SUM requires numeric values".to_string(),
@@ -400,6 +412,13 @@ impl<'a> PlanExecutor<'a> {
                 }
             }
             AggregateFunctionType::Avg => {
+                if let Some(sum) = decimal_sum(&input.values)? {
+                    let count = Decimal::from_i64(input.values.len() as i64);
+                    return sum
+                        .checked_div(&count)
+                        .map(Value::Decimal)
+                        .ok_or_else(decimal_out_of_range);
+                }
                 let mut sum = 0.0f64;
                 let mut count = 0i64;
                 for value in &input.values {
@@ -412,6 +431,10 @@ impl<'a> PlanExecutor<'a> {
                             sum += *f;
                             count += 1;
                         }
+                        Value::Decimal(d) => {
+                            sum += d.to_f64();
+                            count += 1;
+                        }
                         _ => {
                             return Err(RustqlError::AggregateError(
                                 "AVG requires numeric values".to_string(),
@@ -605,6 +628,10 @@ impl<'a> PlanExecutor<'a> {
                 let idx = ((frac * values.len() as f64).ceil() as usize).saturating_sub(1);
                 Ok(values[idx.min(values.len() - 1)].clone())
             }
+            AggregateFunctionType::JsonAgg => Ok(crate::json::aggregate_array(&input.values)),
+            AggregateFunctionType::JsonObjectAgg => {
+                Ok(crate::json::aggregate_object(&input.values))
+            }
         }
     }
 
@@ -757,7 +784,15 @@ fn numeric_distinct_value(value: &Value) -> Option<f64> {
 }
 
 fn aggregate_input_signature_matches(left: &AggregateFunction, right: &AggregateFunction) -> bool {
-    left.expr == right.expr && left.distinct == right.distinct && left.filter == right.filter
+    left.expr == right.expr
+        && left.distinct == right.distinct
+        && left.filter == right.filter
+        && keeps_null_inputs(left) == keeps_null_inputs(right)
+}
+
+/// JSON_AGG turns NULL inputs into JSON `null` where other aggregates skip them.
+fn keeps_null_inputs(agg: &AggregateFunction) -> bool {
+    matches!(agg.function, AggregateFunctionType::JsonAgg)
 }
 
 fn numeric_values(values: &[Value], error: &str) -> Result<Vec<f64>, RustqlError> {
@@ -766,6 +801,7 @@ fn numeric_values(values: &[Value], error: &str) -> Result<Vec<f64>, RustqlError
         .map(|value| match value {
             Value::Integer(i) => Ok(*i as f64),
             Value::Float(f) => Ok(*f),
+            Value::Decimal(d) => Ok(d.to_f64()),
             _ => Err(RustqlError::AggregateError(error.to_string())),
         })
         .collect()
@@ -775,11 +811,14 @@ fn format_value_string(value: &Value) -> String {
     match value {
         Value::Integer(n) => n.to_string(),
         Value::Float(f) => format!("{}", f),
+        Value::Decimal(d) => d.to_string(),
         Value::Text(s) => s.clone(),
         Value::Boolean(b) => b.to_string(),
         Value::Date(d) => d.clone(),
         Value::Time(t) => t.clone(),
         Value::DateTime(dt) => dt.clone(),
+        Value::TimestampTz(_) | Value::Interval(_) | Value::Blob(_) => value.to_string(),
+        Value::Json(json) => json.clone(),
         Value::Null => "NULL".to_string(),
     }
 }
@@ -10,6 +10,7 @@ impl<'a> PlanExecutor<'a> {
         let columns = column_definitions_from_names(&input.columns);
 
         for row in input.rows {
+            check_interrupt()?;
             let include = self.evaluate_expression(condition, &columns, &row)?;
             if include {
                 filtered_rows.push(row);
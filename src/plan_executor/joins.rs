@@ -18,6 +18,7 @@ impl<'a> PlanExecutor<'a> {
         for left_row in &left.rows {
             let mut has_match = false;
             for (right_idx, right_row) in right.rows.iter().enumerate() {
+                check_interrupt()?;
                 let combined_row = combine_rows(left_row, right_row);
 
                 let include = if matches!(join_type, JoinType::Cross) {
@@ -77,6 +78,7 @@ impl<'a> PlanExecutor<'a> {
         let combined_columns = combined_column_definitions(&left.columns, right_columns);
 
         for left_row in &left.rows {
+            check_interrupt()?;
             scoped_db.update_temp_row(left_row);
 
             let subquery_result = match execute_planned_select(&scoped_db, &rewritten_subquery) {
@@ -139,6 +141,7 @@ impl<'a> PlanExecutor<'a> {
         let mut numeric_table: BTreeMap<NumericJoinKey, Vec<usize>> = BTreeMap::new();
         let mut non_numeric_table: BTreeMap<NonNumericJoinKey, Vec<usize>> = BTreeMap::new();
         for (row_idx, row) in build.rows.iter().enumerate() {
+            check_interrupt()?;
             if build_key_idx < row.len() {
                 match join_key(&row[build_key_idx]) {
                     Some(JoinKey::Numeric(key)) => {
@@ -164,6 +167,7 @@ impl<'a> PlanExecutor<'a> {
         };
 
         for probe_row in &probe.rows {
+            check_interrupt()?;
             if probe_key_idx < probe_row.len() {
                 match join_key(&probe_row[probe_key_idx]) {
                     Some(JoinKey::Numeric(probe_key)) => {
@@ -210,6 +214,7 @@ impl<'a> PlanExecutor<'a> {
         joined_rows: &mut Vec<Vec<Value>>,
     ) -> Result<(), RustqlError> {
         for &build_row_idx in build_row_indices {
+            check_interrupt()?;
             let build_row = &context.build.rows[build_row_idx];
             let combined_row = if context.left_is_build {
                 combine_rows(build_row, probe_row)
@@ -319,6 +324,9 @@ enum NonNumericJoinKey {
     Date(String),
     Time(String),
     DateTime(String),
+    Interval(i128),
+    Blob(Vec<u8>),
+    Json(String),
 }
 
 enum JoinKey {
@@ -332,12 +340,23 @@ fn join_key(value: &Value) -> Option<JoinKey> {
         Value::Integer(value) => Some(JoinKey::Numeric(NumericJoinKey(*value as f64))),
         Value::Float(value) if value.is_finite() => Some(JoinKey::Numeric(NumericJoinKey(*value))),
         Value::Float(_) => None,
+        // Candidates are checked against the join condition, so rounding a
+        // decimal key only widens its bucket.
+        Value::Decimal(value) => Some(JoinKey::Numeric(NumericJoinKey(value.to_f64()))),
         Value::Text(value) => Some(JoinKey::NonNumeric(NonNumericJoinKey::Text(value.clone()))),
         Value::Boolean(value) => Some(JoinKey::NonNumeric(NonNumericJoinKey::Boolean(*value))),
         Value::Date(value) => Some(JoinKey::NonNumeric(NonNumericJoinKey::Date(value.clone()))),
         Value::Time(value) => Some(JoinKey::NonNumeric(NonNumericJoinKey::Time(value.clone()))),
-        Value::DateTime(value) => Some(JoinKey::NonNumeric(NonNumericJoinKey::DateTime(
-            value.clone(),
+        // A DATETIME equals the TIMESTAMPTZ with the same UTC text.
+        Value::DateTime(value) | Value::TimestampTz(value) => Some(JoinKey::NonNumeric(
+            NonNumericJoinKey::DateTime(value.clone()),
+        )),
+        // Intervals that compare equal, such as `1 mon` and `30 days`, share
+        // a bucket.
+        Value::Interval(value) => Some(JoinKey::NonNumeric(NonNumericJoinKey::Interval(
+            value.comparison_micros(),
         ))),
+        Value::Blob(value) => Some(JoinKey::NonNumeric(NonNumericJoinKey::Blob(value.clone()))),
+        Value::Json(value) => Some(JoinKey::NonNumeric(NonNumericJoinKey::Json(value.clone()))),
     }
 }
@@ -11,6 +11,7 @@ impl<'a> PlanExecutor<'a> {
             .rows
             .into_iter()
             .map(|row| {
+                check_interrupt()?;
                 let keys = order_by
                     .iter()
                     .map(|order_expr| {
@@ -119,6 +120,7 @@ impl<'a> PlanExecutor<'a> {
         let mut rows = Vec::new();
 
         for row in input.rows {
+            check_interrupt()?;
             let key: Vec<Value> = distinct_on
                 .iter()
                 .map(|expr| {
@@ -100,6 +100,7 @@ impl<'a> PlanExecutor<'a> {
         let scalar_outer_columns = scalar_outer_scope_columns(&result.columns, select_stmt);
         let mut projected_rows = Vec::new();
         for (row_idx, row) in result.rows.iter().enumerate() {
+            check_interrupt()?;
             let mut projected_row = Vec::new();
             let mut aggregate_offset = result.columns.len().saturating_sub(aggregate_count);
             let mut window_offset = result.columns.len();
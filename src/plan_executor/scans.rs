@@ -1,5 +1,7 @@
 use super::*;
 use crate::database::{CompositeIndex, Index, Table, View};
+use crate::executor::expr::{add_interval, micros_to_datetime, temporal_micros};
+use crate::interval::Interval;
 
 impl<'a> PlanExecutor<'a> {
     pub(super) fn execute_values_scan(
@@ -28,6 +30,7 @@ impl<'a> PlanExecutor<'a> {
 
         let mut rows = Vec::with_capacity(values.len());
         for value_row in values {
+            check_interrupt()?;
             let row: Vec<Value> = value_row
                 .iter()
                 .map(|expr| self.evaluate_value_expression(expr, &empty_columns, &empty_row))
@@ -61,7 +64,8 @@ impl<'a> PlanExecutor<'a> {
 
         let mut rows = Vec::new();
 
-        for row in &table.rows {
+        table.for_each_row(|_, row| {
+            check_interrupt()?;
             let include = if let Some(filter_expr) = filter {
                 self.evaluate_expression(filter_expr, &table.columns, row)?
             } else {
@@ -69,9 +73,10 @@ impl<'a> PlanExecutor<'a> {
             };
 
             if include {
-                rows.push(row.clone());
+                rows.push(row.to_vec());
             }
-        }
+            Ok(true)
+        })?;
 
         let columns = qualify_column_names(&table.columns, output_label);
 
@@ -97,23 +102,24 @@ impl<'a> PlanExecutor<'a> {
             {
                 crate::executor::ddl::get_indexed_rows(self.db, table, &index_usage)?
             } else {
-                self.all_row_ids_for_index(index_name)?
+                self.all_row_ids_for_index(table, index_name)?
             }
         } else {
-            self.all_row_ids_for_index(index_name)?
+            self.all_row_ids_for_index(table, index_name)?
         };
 
         let mut rows = Vec::new();
         for row_id in row_ids {
-            if let Some(row) = table.row_by_id(row_id) {
+            check_interrupt()?;
+            if let Some(row) = table.fetch_row(row_id)? {
                 let include = if let Some(filter_expr) = filter {
-                    self.evaluate_expression(filter_expr, &table.columns, row)?
+                    self.evaluate_expression(filter_expr, &table.columns, &row)?
                 } else {
                     true
                 };
 
                 if include {
-                    rows.push(row.clone());
+                    rows.push(row.into_owned());
                 }
             }
         }
@@ -123,10 +129,14 @@ impl<'a> PlanExecutor<'a> {
         Ok(ExecutionResult { columns, rows })
     }
 
-    fn all_row_ids_for_index(&self, index_name: &str) -> Result<HashSet<RowId>, RustqlError> {
+    fn all_row_ids_for_index(
+        &self,
+        table: &Table,
+        index_name: &str,
+    ) -> Result<HashSet<RowId>, RustqlError> {
         if let Some(index) = self.db.get_index(index_name) {
             let mut row_ids = HashSet::new();
-            for rows in index.entries.values() {
+            for rows in crate::executor::ddl::index_entries(self.db, table, index)?.values() {
                 row_ids.extend(rows.iter().copied());
             }
             return Ok(row_ids);
@@ -134,7 +144,8 @@ impl<'a> PlanExecutor<'a> {
 
         if let Some(index) = self.db.get_composite_index(index_name) {
             let mut row_ids = HashSet::new();
-            for rows in index.entries.values() {
+            let entries = crate::executor::ddl::composite_index_entries(self.db, table, index)?;
+            for rows in entries.values() {
                 row_ids.extend(rows.iter().copied());
             }
             return Ok(row_ids);
@@ -168,6 +179,14 @@ impl<'a> PlanExecutor<'a> {
                     &empty_row,
                 )? {
                     Value::Integer(value) => value,
+                    start @ (Value::Date(_) | Value::DateTime(_)) => {
+                        return self.execute_temporal_series(
+                            function,
+                            &start,
+                            output_label,
+                            filter,
+                        );
+                    }
                     _ => {
                         return Err(RustqlError::TypeMismatch(
                             "GENERATE_SERIES arguments must be integers".to_string(),
@@ -206,7 +225,7 @@ impl<'a> PlanExecutor<'a> {
                 };
 
                 if step == 0 {
-                    return Err(RustqlError::Internal(
+                    return Err(RustqlError::TypeMismatch(
                         "GENERATE_SERIES step cannot be zero".to_string(),
                     ));
                 }
@@ -232,6 +251,7 @@ impl<'a> PlanExecutor<'a> {
                 let mut current = start;
                 if step > 0 {
                     while current <= stop {
+                        check_interrupt()?;
                         let row = vec![Value::Integer(current)];
                         let include = if let Some(filter_expr) = filter {
                             self.evaluate_expression(filter_expr, &columns, &row)?
@@ -248,6 +268,7 @@ impl<'a> PlanExecutor<'a> {
                     }
                 } else {
                     while current >= stop {
+                        check_interrupt()?;
                         let row = vec![Value::Integer(current)];
                         let include = if let Some(filter_expr) = filter {
                             self.evaluate_expression(filter_expr, &columns, &row)?
@@ -269,6 +290,50 @@ impl<'a> PlanExecutor<'a> {
                     rows,
                 })
             }
+            "json_each" | "json_array_elements" => {
+                let [document] = function.args.as_slice() else {
+                    return Err(RustqlError::TypeMismatch(format!(
+                        "{} expects 1 argument",
+                        function.name.to_uppercase()
+                    )));
+                };
+                let document = self.evaluate_value_expression(document, &[], &[])?;
+                let columns: Vec<ColumnDefinition> =
+                    crate::json::table_function_columns(&function.name)
+                        .unwrap_or_default()
+                        .into_iter()
+                        .map(|(name, data_type)| ColumnDefinition {
+                            name: qualified_column_name(output_label, name),
+                            data_type,
+                            nullable: true,
+                            primary_key: false,
+                            unique: false,
+                            default_value: None,
+                            foreign_key: None,
+                            check: None,
+                            auto_increment: false,
+                            generated: None,
+                        })
+                        .collect();
+
+                let mut rows = Vec::new();
+                for row in crate::json::table_function_rows(&function.name, &document)? {
+                    check_interrupt()?;
+                    let include = if let Some(filter_expr) = filter {
+                        self.evaluate_expression(filter_expr, &columns, &row)?
+                    } else {
+                        true
+                    };
+                    if include {
+                        rows.push(row);
+                    }
+                }
+
+                Ok(ExecutionResult {
+                    columns: columns.into_iter().map(|column| column.name).collect(),
+                    rows,
+                })
+            }
             other => Err(RustqlError::Internal(format!(
                 "Unsupported table function in plan executor: {}",
                 other
@@ -276,6 +341,107 @@ impl<'a> PlanExecutor<'a> {
         }
     }
 
+    /// GENERATE_SERIES over dates or datetimes, adding the interval step
+    /// to each value in turn.
+    fn execute_temporal_series(
+        &self,
+        function: &TableFunction,
+        start: &Value,
+        output_label: Option<&str>,
+        filter: Option<&Expression>,
+    ) -> Result<ExecutionResult, RustqlError> {
+        let invalid_arguments = || {
+            RustqlError::TypeMismatch(
+                "GENERATE_SERIES over dates requires a date or datetime stop and an interval step"
+                    .to_string(),
+            )
+        };
+        let (Some(stop), Some(step)) = (function.args.get(1), function.args.get(2)) else {
+            return Err(invalid_arguments());
+        };
+        let stop = self.evaluate_value_expression(stop, &[], &[])?;
+        let Value::Interval(step) = self.evaluate_value_expression(step, &[], &[])? else {
+            return Err(invalid_arguments());
+        };
+        let (Some(start), Some(stop)) = (temporal_micros(start), temporal_micros(&stop)) else {
+            return Err(invalid_arguments());
+        };
+        if step == Interval::ZERO {
+            return Err(RustqlError::TypeMismatch(
+                "GENERATE_SERIES step cannot be zero".to_string(),
+            ));
+        }
+        let ascending = step > Interval::ZERO;
+
+        let column_name = qualified_column_name(
+            output_label,
+            function.alias.as_deref().unwrap_or("generate_series"),
+        );
+        let columns = vec![ColumnDefinition {
+            name: column_name.clone(),
+            data_type: DataType::DateTime,
+            nullable: false,
+            primary_key: false,
+            unique: false,
+            default_value: None,
+            foreign_key: None,
+            check: None,
+            auto_increment: false,
+            generated: None,
+        }];
+
+        // Each value is `start + n * step` rather than the previous value
+        // plus `step`, so a month step that clips to a short month's end
+        // goes back to the start's day of the month afterwards.
+        let mut rows = Vec::new();
+        let mut previous = None;
+        for n in 0i64.. {
+            let Some(current) = step
+                .checked_mul_integer(n)
+                .and_then(|offset| add_interval(start, &offset))
+            else {
+                break;
+            };
+            if if ascending {
+                current > stop
+            } else {
+                current < stop
+            } {
+                break;
+            }
+            // A step such as `1 mon -29 days` can stall or turn back at a
+            // month end.
+            if previous.is_some_and(|previous| {
+                if ascending {
+                    current <= previous
+                } else {
+                    current >= previous
+                }
+            }) {
+                break;
+            }
+            previous = Some(current);
+            check_interrupt()?;
+            let Some(datetime) = micros_to_datetime(current) else {
+                break;
+            };
+            let row = vec![Value::DateTime(datetime)];
+            let include = if let Some(filter_expr) = filter {
+                self.evaluate_expression(filter_expr, &columns, &row)?
+            } else {
+                true
+            };
+            if include {
+                rows.push(row);
+            }
+        }
+
+        Ok(ExecutionResult {
+            columns: vec![column_name],
+            rows,
+        })
+    }
+
     pub(super) fn execute_source_scan(
         &self,
         input: &PlanNode,
@@ -319,6 +485,7 @@ impl<'a> PlanExecutor<'a> {
 
         let mut converged = false;
         for _ in 0..MAX_RECURSIVE_CTE_ITERATIONS {
+            check_interrupt()?;
             if working_rows.is_empty() {
                 converged = true;
                 break;
@@ -1,4 +1,5 @@
 use super::*;
+use crate::decimal::MAX_DECIMAL_PRECISION;
 
 pub(super) fn column_names_match(candidate: &str, reference: &str) -> bool {
     candidate == reference
@@ -118,11 +119,19 @@ fn value_data_type(value: &Value) -> Option<DataType> {
         Value::Null => None,
         Value::Integer(_) => Some(DataType::Integer),
         Value::Float(_) => Some(DataType::Float),
+        Value::Decimal(value) => Some(DataType::Decimal {
+            precision: MAX_DECIMAL_PRECISION,
+            scale: value.scale(),
+        }),
         Value::Text(_) => Some(DataType::Text),
         Value::Boolean(_) => Some(DataType::Boolean),
         Value::Date(_) => Some(DataType::Date),
         Value::Time(_) => Some(DataType::Time),
         Value::DateTime(_) => Some(DataType::DateTime),
+        Value::TimestampTz(_) => Some(DataType::TimestampTz),
+        Value::Interval(_) => Some(DataType::Interval),
+        Value::Blob(_) => Some(DataType::Blob),
+        Value::Json(_) => Some(DataType::Json),
     }
 }
 
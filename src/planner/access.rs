@@ -76,31 +76,32 @@ impl<'a> QueryPlanner<'a> {
         output_label: Option<String>,
         where_clause: Option<&Expression>,
     ) -> Result<PlanNode, RustqlError> {
-        match function.name.as_str() {
-            "generate_series" => {
-                let input_rows = self
-                    .estimate_generate_series_rows(function)
-                    .unwrap_or(DEFAULT_GENERATE_SERIES_ROWS);
-                let rows = if let Some(condition) = where_clause {
-                    (input_rows as f64 * self.estimate_selectivity(condition, input_rows)) as usize
-                } else {
-                    input_rows
-                };
-                let cost = input_rows as f64 * FUNCTION_SCAN_ROW_COST;
-
-                Ok(PlanNode::FunctionScan {
-                    function: function.clone(),
-                    output_label,
-                    filter: where_clause.cloned(),
-                    cost,
-                    rows,
-                })
+        let input_rows = match function.name.as_str() {
+            "generate_series" => self
+                .estimate_generate_series_rows(function)
+                .unwrap_or(DEFAULT_GENERATE_SERIES_ROWS),
+            "json_each" | "json_array_elements" => DEFAULT_JSON_ELEMENT_ROWS,
+            other => {
+                return Err(RustqlError::Internal(format!(
+                    "Unsupported table function in planner: {}",
+                    other
+                )));
             }
-            other => Err(RustqlError::Internal(format!(
-                "Unsupported table function in planner: {}",
-                other
-            ))),
-        }
+        };
+        let rows = if let Some(condition) = where_clause {
+            (input_rows as f64 * self.estimate_selectivity(condition, input_rows)) as usize
+        } else {
+            input_rows
+        };
+        let cost = input_rows as f64 * FUNCTION_SCAN_ROW_COST;
+
+        Ok(PlanNode::FunctionScan {
+            function: function.clone(),
+            output_label,
+            filter: where_clause.cloned(),
+            cost,
+            rows,
+        })
     }
 
     pub(super) fn plan_base_source(
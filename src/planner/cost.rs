@@ -179,17 +179,26 @@ impl<'a> QueryPlanner<'a> {
         db: &dyn DatabaseCatalog,
         stats: &TableStats,
     ) -> usize {
+        // The entries of a paged index are mostly in storage, so its lookups
+        // are estimated like the filters they answer.
+        let paged_rows = (stats.row_count as f64 * SELECTIVITY_EQUAL) as usize;
         match index_usage {
             IndexUsage::Equality { index_name, value } => db
                 .get_index(index_name)
-                .map(|index| estimate_index_entry_rows(index, value))
+                .map(|index| match index.paged {
+                    Some(_) => paged_rows,
+                    None => estimate_index_entry_rows(index, value),
+                })
                 .unwrap_or(0),
             IndexUsage::In { index_name, values } => db
                 .get_index(index_name)
                 .map(|index| {
                     values
                         .iter()
-                        .map(|value| estimate_index_entry_rows(index, value))
+                        .map(|value| match index.paged {
+                            Some(_) => paged_rows,
+                            None => estimate_index_entry_rows(index, value),
+                        })
                         .sum()
                 })
                 .unwrap_or(0),
@@ -200,7 +209,10 @@ impl<'a> QueryPlanner<'a> {
             }
             IndexUsage::CompositePrefix { index_name, values } => db
                 .get_composite_index(index_name)
-                .map(|index| estimate_composite_index_entry_rows(index, values))
+                .map(|index| match index.paged {
+                    Some(_) => paged_rows,
+                    None => estimate_composite_index_entry_rows(index, values),
+                })
                 .unwrap_or(0),
         }
     }
@@ -251,5 +263,8 @@ fn values_equal_for_index_selectivity(left: &Value, right: &Value) -> bool {
 }
 
 fn is_numeric_value(value: &Value) -> bool {
-    matches!(value, Value::Integer(_) | Value::Float(_))
+    matches!(
+        value,
+        Value::Integer(_) | Value::Float(_) | Value::Decimal(_)
+    )
 }
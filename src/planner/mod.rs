@@ -9,6 +9,7 @@ use std::cmp::Ordering;
 use std::collections::{BTreeSet, HashMap, HashSet};
 
 const DEFAULT_GENERATE_SERIES_ROWS: usize = 100;
+const DEFAULT_JSON_ELEMENT_ROWS: usize = 10;
 const DEFAULT_LATERAL_ROWS: usize = 10;
 const FUNCTION_SCAN_ROW_COST: f64 = 0.2;
 const FILTER_ROW_COST: f64 = 0.1;
@@ -201,7 +201,14 @@ impl<'a> QueryPlanner<'a> {
                     .alias
                     .clone()
                     .unwrap_or_else(|| "generate_series".to_string()),
-                data_type: DataType::Integer,
+                // The binder casts the start of a series over dates.
+                data_type: match function.args.first() {
+                    Some(Expression::Cast {
+                        data_type: DataType::DateTime,
+                        ..
+                    }) => DataType::DateTime,
+                    _ => DataType::Integer,
+                },
                 nullable: false,
                 primary_key: false,
                 unique: false,
@@ -211,6 +218,24 @@ impl<'a> QueryPlanner<'a> {
                 auto_increment: false,
                 generated: None,
             }]),
+            "json_each" | "json_array_elements" => {
+                Ok(crate::json::table_function_columns(&function.name)
+                    .unwrap_or_default()
+                    .into_iter()
+                    .map(|(name, data_type)| ColumnDefinition {
+                        name: name.to_string(),
+                        data_type,
+                        nullable: true,
+                        primary_key: false,
+                        unique: false,
+                        default_value: None,
+                        foreign_key: None,
+                        check: None,
+                        auto_increment: false,
+                        generated: None,
+                    })
+                    .collect())
+            }
             other => Err(RustqlError::Internal(format!(
                 "Unsupported table function in planner: {}",
                 other
@@ -21,7 +21,7 @@ impl<'a> QueryPlanner<'a> {
         table: &Table,
         db: &dyn DatabaseCatalog,
     ) -> TableStats {
-        let row_count = table.rows.len();
+        let row_count = table.row_count();
         let mut column_stats = HashMap::new();
 
         let has_index = db.indexes_iter().any(|idx| idx.table == table_name)
@@ -29,6 +29,16 @@ impl<'a> QueryPlanner<'a> {
                 .composite_indexes_iter()
                 .any(|idx| idx.table == table_name);
 
+        // Column statistics would need a full read of a paged table on every
+        // plan, so paged tables are costed from their row count alone.
+        if table.is_paged() {
+            return TableStats {
+                row_count,
+                column_stats,
+                has_index,
+            };
+        }
+
         for (col_idx, col_def) in table.columns.iter().enumerate() {
             if !table.rows.is_empty() {
                 let mut distinct_values = BTreeSet::new();
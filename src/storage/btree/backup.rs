@@ -0,0 +1,123 @@
+use super::super::atomic_file::{cleanup_temp_file, rename_synced, storage_temp_path};
+use super::BTreeStorageEngine;
+use super::delta::write_frames;
+use super::file::BTreeFile;
+use super::header::{VersionedFileState, read_versioned_header_with_versions};
+use super::journal::journal_path_for;
+use crate::database::Database;
+use crate::error::RustqlError;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+impl BTreeStorageEngine {
+    /// Copies the committed file contents to `dest`: the main file with every
+    /// page logged since the last checkpoint laid over it. Must be called
+    /// under the storage lock, which keeps checkpoints from moving pages
+    /// while they are copied.
+    pub(super) fn backup_locked(&self, dest: &Path) -> Result<(), RustqlError> {
+        if is_same_file(&self.data_path, dest) {
+            return Err(RustqlError::StorageError(format!(
+                "Cannot back up BTree storage file '{}' onto itself",
+                dest.display()
+            )));
+        }
+        match read_versioned_header_with_versions(
+            &self.data_path,
+            BTreeFile::MAGIC,
+            &BTreeFile::SUPPORTED_VERSIONS,
+            "BTree storage file",
+        )? {
+            VersionedFileState::Valid { .. } => {}
+            VersionedFileState::Missing | VersionedFileState::Empty => {
+                return write_snapshot_backup(dest, &Database::new());
+            }
+        }
+
+        // Records still being synced by their committers are made durable
+        // first, so the copy never holds a commit that could yet fail.
+        self.redo_log.sync_appended()?;
+        let base_file_len = fs::metadata(&self.data_path)
+            .map(|metadata| metadata.len())
+            .map_err(|e| {
+                RustqlError::StorageError(format!("Failed to get file metadata: {}", e))
+            })?;
+        let delta = self
+            .redo_log
+            .checkpoint_delta(base_file_len, self.page_cipher().as_ref())?;
+        write_backup_file(dest, |temp_path| {
+            let mut source = fs::File::open(&self.data_path).map_err(|e| {
+                RustqlError::StorageError(format!(
+                    "Failed to open BTree storage file '{}': {}",
+                    self.data_path.display(),
+                    e
+                ))
+            })?;
+            let mut copy = fs::OpenOptions::new()
+                .create_new(true)
+                .read(true)
+                .write(true)
+                .open(temp_path)
+                .map_err(|e| {
+                    RustqlError::StorageError(format!(
+                        "Failed to create backup file '{}': {}",
+                        temp_path.display(),
+                        e
+                    ))
+                })?;
+            io::copy(&mut source, &mut copy).map_err(|e| {
+                RustqlError::StorageError(format!("Failed to copy BTree storage file: {}", e))
+            })?;
+            match &delta {
+                Some(delta) => write_frames(&mut copy, delta),
+                None => copy.sync_all().map_err(|e| {
+                    RustqlError::StorageError(format!("Failed to sync backup file: {}", e))
+                }),
+            }
+        })
+    }
+}
+
+/// Writes `db` as a new B-tree file at `dest`, which must not exist yet.
+pub(crate) fn write_snapshot_backup(dest: &Path, db: &Database) -> Result<(), RustqlError> {
+    let mut db = db.clone();
+    db.load_all_paged_tables()?;
+    db.normalize_row_ids();
+    write_backup_file(dest, |temp_path| {
+        let mut file = BTreeFile::create(temp_path)?;
+        file.write_database_via_pages(&db)?;
+        file.sync_all()
+    })
+}
+
+/// Fills a temporary file next to `dest` and moves it into place once synced.
+///
+/// `dest` may be a live database, whose journal would also be replayed over
+/// the backup when it is opened, so neither may exist yet.
+fn write_backup_file(
+    dest: &Path,
+    fill: impl FnOnce(&Path) -> Result<(), RustqlError>,
+) -> Result<(), RustqlError> {
+    if let Some(existing) = [dest.to_path_buf(), journal_path_for(dest)]
+        .into_iter()
+        .find(|path| path.exists())
+    {
+        return Err(RustqlError::StorageError(format!(
+            "Backup destination '{}' already exists",
+            existing.display()
+        )));
+    }
+    let temp_path = storage_temp_path(dest);
+    let result = fill(&temp_path).and_then(|()| rename_synced(&temp_path, dest));
+    if result.is_err() {
+        cleanup_temp_file(&temp_path);
+    }
+    result
+}
+
+fn is_same_file(left: &Path, right: &Path) -> bool {
+    match (fs::canonicalize(left), fs::canonicalize(right)) {
+        (Ok(left), Ok(right)) => left == right,
+        _ => left == right,
+    }
+}
@@ -1,19 +1,21 @@
-use super::MAX_CACHE_SIZE;
 use super::page::BTreePage;
 use std::collections::{HashMap, VecDeque};
 
 pub(super) struct PageCache {
     pub(super) pages: HashMap<u64, BTreePage>,
     pub(super) access_order: VecDeque<u64>,
+    capacity: usize,
     hits: u64,
     misses: u64,
 }
 
 impl PageCache {
-    pub(super) fn new() -> Self {
+    /// Creates an LRU cache holding at most `capacity` pages (at least one).
+    pub(super) fn new(capacity: usize) -> Self {
         PageCache {
             pages: HashMap::new(),
             access_order: VecDeque::new(),
+            capacity: capacity.max(1),
             hits: 0,
             misses: 0,
         }
@@ -37,7 +39,7 @@ impl PageCache {
             self.access_order.retain(|&id| id != page_id);
             self.access_order.push_back(page_id);
         } else {
-            while self.pages.len() >= MAX_CACHE_SIZE {
+            while self.pages.len() >= self.capacity {
                 if let Some(oldest_id) = self.access_order.pop_front() {
                     self.pages.remove(&oldest_id);
                 } else {
@@ -0,0 +1,304 @@
+use super::BTreeStorageEngine;
+use super::encoding::parse_overflow_stub;
+use super::file::BTreeFile;
+use super::header::{FILE_HEADER_SIZE, VersionedFileState, read_versioned_header_with_versions};
+use super::page::{BTREE_PAGE_SIZE, BTreeEntry, BTreePage, LEAF_INLINE_DATA_FLAG, PageKind};
+use super::tree::{META_FREE_COUNT_KEY, META_FREE_LIST_KEY, META_NEXT_PAGE_KEY, META_ROOT_KEY};
+use crate::ast::Value;
+use crate::error::{IntegrityProblem, RustqlError};
+use std::collections::HashSet;
+
+/// Bounds the walk over corrupt files whose internal pages point back at
+/// their ancestors.
+const MAX_CHECK_DEPTH: usize = 64;
+
+impl BTreeStorageEngine {
+    /// Verifies every page checksum and the shape of the tree: each page
+    /// sits at its own id and is linked exactly once, keys are sorted and
+    /// fall within the range their parent routes to them, and the free list
+    /// holds the pages the meta page counts. Must be called under the storage
+    /// lock.
+    pub(super) fn check_integrity_locked(&self) -> Result<Vec<IntegrityProblem>, RustqlError> {
+        match read_versioned_header_with_versions(
+            &self.data_path,
+            BTreeFile::MAGIC,
+            &BTreeFile::SUPPORTED_VERSIONS,
+            "BTree storage file",
+        ) {
+            Ok(VersionedFileState::Valid { .. }) => {}
+            Ok(VersionedFileState::Missing | VersionedFileState::Empty) => return Ok(Vec::new()),
+            Err(e) => {
+                return Ok(vec![IntegrityProblem::StorageFile {
+                    message: e.to_string(),
+                }]);
+            }
+        }
+
+        let file_len = self.committed_file_len();
+        let pages_len = file_len.saturating_sub(FILE_HEADER_SIZE as u64);
+        let mut checker = TreeChecker {
+            engine: self,
+            file: self.open_file()?,
+            page_count: pages_len / BTREE_PAGE_SIZE as u64,
+            linked: HashSet::new(),
+            problems: Vec::new(),
+        };
+        if !pages_len.is_multiple_of(BTREE_PAGE_SIZE as u64) {
+            checker.problems.push(IntegrityProblem::StorageFile {
+                message: format!("File length {} is not a whole number of pages", file_len),
+            });
+        }
+        checker.check();
+        Ok(checker.problems)
+    }
+}
+
+struct TreeChecker<'a> {
+    engine: &'a BTreeStorageEngine,
+    file: BTreeFile,
+    page_count: u64,
+    /// Pages reached from the meta page, the tree, or the free list.
+    linked: HashSet<u64>,
+    problems: Vec<IntegrityProblem>,
+}
+
+impl TreeChecker<'_> {
+    fn check(&mut self) {
+        let Some(meta) = self.link_page(0) else {
+            return;
+        };
+        if meta.header.kind != PageKind::Meta {
+            self.structure_problem(
+                0,
+                format!(
+                    "Expected the meta page, found a {:?} page",
+                    meta.header.kind
+                ),
+            );
+            return;
+        }
+        let meta_pointer = |name: &str| {
+            meta.entries
+                .iter()
+                .find(|entry| matches!(&entry.key, Value::Text(key) if key == name))
+                .map(|entry| entry.pointer)
+        };
+        let root_page_id = meta_pointer(META_ROOT_KEY).unwrap_or(1);
+        let next_page_id = meta_pointer(META_NEXT_PAGE_KEY).unwrap_or(2);
+        let free_head = meta_pointer(META_FREE_LIST_KEY).unwrap_or(0);
+        let free_count = meta_pointer(META_FREE_COUNT_KEY).unwrap_or(0);
+
+        self.check_subtree(root_page_id, None, None, 0);
+        self.check_free_list(free_head, free_count);
+
+        for page_id in 1..next_page_id.min(self.page_count) {
+            if !self.linked.contains(&page_id) {
+                self.structure_problem(
+                    page_id,
+                    "Page is neither in the tree nor on the free list".to_string(),
+                );
+            }
+        }
+    }
+
+    fn check_subtree(
+        &mut self,
+        page_id: u64,
+        lower: Option<&Value>,
+        upper: Option<&Value>,
+        depth: usize,
+    ) {
+        if depth > MAX_CHECK_DEPTH {
+            self.structure_problem(
+                page_id,
+                "Tree is too deep; internal pages may form a cycle".to_string(),
+            );
+            return;
+        }
+        let Some(page) = self.link_page(page_id) else {
+            return;
+        };
+
+        if let Some(position) = page
+            .entries
+            .windows(2)
+            .position(|pair| pair[0].key >= pair[1].key)
+        {
+            self.structure_problem(
+                page_id,
+                format!("Keys are out of order at entry {}", position + 1),
+            );
+        }
+
+        match page.header.kind {
+            PageKind::Leaf => {
+                if let Some(entry) = page.entries.iter().find(|entry| {
+                    lower.is_some_and(|lower| &entry.key < lower)
+                        || upper.is_some_and(|upper| &entry.key >= upper)
+                }) {
+                    self.structure_problem(
+                        page_id,
+                        format!(
+                            "Key {} lies outside the range its parent routes to this page",
+                            entry.key
+                        ),
+                    );
+                }
+                // Versions 2 and 3 keep records on separate data pages.
+                if page.header.reserved & LEAF_INLINE_DATA_FLAG == 0 {
+                    for entry in &page.entries {
+                        self.link_page(entry.pointer);
+                    }
+                } else {
+                    for entry in &page.entries {
+                        if let Some(data) = &entry.inline_data {
+                            self.check_overflow_chain(page_id, data);
+                        }
+                    }
+                }
+            }
+            PageKind::Internal => {
+                if page.entries.is_empty() {
+                    self.structure_problem(page_id, "Internal page has no children".to_string());
+                }
+                // Keys below the first bound also route to the first child,
+                // so it inherits this page's lower bound.
+                for (idx, entry) in page.entries.iter().enumerate() {
+                    let child_lower = if idx == 0 { lower } else { Some(&entry.key) };
+                    let child_upper = page.entries.get(idx + 1).map(|next| &next.key).or(upper);
+                    self.check_subtree(entry.pointer, child_lower, child_upper, depth + 1);
+                }
+            }
+            PageKind::Meta | PageKind::Free | PageKind::Overflow => {
+                self.structure_problem(
+                    page_id,
+                    format!("{:?} page is linked into the tree", page.header.kind),
+                );
+            }
+        }
+    }
+
+    /// Links the overflow pages a leaf record names and checks that they
+    /// hold exactly the record's length.
+    fn check_overflow_chain(&mut self, leaf_page_id: u64, payload: &[u8]) {
+        let (len, first_page) = match parse_overflow_stub(payload) {
+            Ok(Some(stub)) => stub,
+            Ok(None) => return,
+            Err(e) => {
+                self.structure_problem(leaf_page_id, format!("Bad overflow stub: {}", e));
+                return;
+            }
+        };
+        let mut stored = 0;
+        let mut page_id = first_page;
+        while page_id != 0 {
+            let Some(page) = self.link_page(page_id) else {
+                return;
+            };
+            let next_page = match (page.header.kind, page.entries.first()) {
+                (
+                    PageKind::Overflow,
+                    Some(BTreeEntry {
+                        key: Value::Integer(next_page),
+                        inline_data: Some(chunk),
+                        ..
+                    }),
+                ) => {
+                    stored += chunk.len();
+                    *next_page as u64
+                }
+                (kind, _) => {
+                    self.structure_problem(
+                        page_id,
+                        format!("Overflow chain links to a malformed {:?} page", kind),
+                    );
+                    return;
+                }
+            };
+            page_id = next_page;
+        }
+        if stored != len {
+            self.structure_problem(
+                first_page,
+                format!(
+                    "Overflow chain holds {} bytes but its record has {}",
+                    stored, len
+                ),
+            );
+        }
+    }
+
+    fn check_free_list(&mut self, head: u64, expected_count: u64) {
+        let mut count = 0;
+        let mut page_id = head;
+        while page_id != 0 {
+            let Some(page) = self.link_page(page_id) else {
+                break;
+            };
+            if page.header.kind != PageKind::Free {
+                self.structure_problem(
+                    page_id,
+                    format!("Free list links to a {:?} page", page.header.kind),
+                );
+                break;
+            }
+            count += 1;
+            page_id = page.entries.first().map_or(0, |entry| entry.pointer);
+        }
+        if count != expected_count {
+            self.structure_problem(
+                0,
+                format!(
+                    "Free list holds {} pages but the meta page counts {}",
+                    count, expected_count
+                ),
+            );
+        }
+    }
+
+    /// Reads a page that something links to, recording it as linked. Returns
+    /// `None` after recording a problem when the link is bad or the page is
+    /// corrupt.
+    fn link_page(&mut self, page_id: u64) -> Option<BTreePage> {
+        if page_id >= self.page_count {
+            self.structure_problem(
+                page_id,
+                format!(
+                    "Page lies past the end of the file ({} pages)",
+                    self.page_count
+                ),
+            );
+            return None;
+        }
+        if !self.linked.insert(page_id) {
+            self.structure_problem(page_id, "Page is linked more than once".to_string());
+            return None;
+        }
+        let read = match self.engine.redo_log.logged_page(page_id) {
+            Some(page) => Ok(page),
+            None => self.file.read_page(page_id),
+        };
+        let page = match read {
+            Ok(page) => page,
+            Err(e) => {
+                self.problems.push(IntegrityProblem::CorruptPage {
+                    page_id,
+                    message: e.to_string(),
+                });
+                return None;
+            }
+        };
+        if page.header.page_id != page_id {
+            self.structure_problem(
+                page_id,
+                format!("Page header names page {}", page.header.page_id),
+            );
+        }
+        Some(page)
+    }
+
+    fn structure_problem(&mut self, page_id: u64, message: String) {
+        self.problems
+            .push(IntegrityProblem::PageStructure { page_id, message });
+    }
+}
@@ -0,0 +1,528 @@
+//! Page encryption for B-tree files.
+//!
+//! Encrypted files seal every page with XChaCha20-Poly1305, implemented here
+//! after RFC 8439 and the XChaCha20 draft so the crate stays free of native
+//! dependencies. A sealed page is its 24-byte nonce, the ciphertext of the
+//! page contents, and a 16-byte tag; the page id is authenticated alongside,
+//! so a page copied to another slot fails to open. The meta page slot also
+//! starts with a key-check block, which lets a wrong key be told apart from
+//! a damaged page.
+
+use super::BTreeStorageEngine;
+use super::file::BTreeFile;
+use super::header::{
+    FILE_HEADER_SIZE, HEADER_ENCRYPTED_FLAG, VersionedFileState, read_header_flags,
+    read_versioned_header_with_versions,
+};
+use super::page::{BTREE_PAGE_SIZE, BTreePage};
+use crate::database::Database;
+use crate::error::RustqlError;
+use std::collections::hash_map::RandomState;
+use std::fmt;
+use std::fs;
+use std::hash::BuildHasher;
+use std::io::{Read, Seek, SeekFrom};
+use std::sync::OnceLock;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+const NONCE_SIZE: usize = 24;
+const TAG_SIZE: usize = 16;
+/// Bytes a sealed page adds to its contents.
+pub(super) const PAGE_SEAL_OVERHEAD: usize = NONCE_SIZE + TAG_SIZE;
+
+const KEY_CHECK_PLAINTEXT: &[u8; 8] = b"RSTQLKEY";
+const KEY_CHECK_AAD: &[u8] = b"rustql key check";
+/// Size of the key-check block at the start of the meta page slot.
+pub(super) const KEY_CHECK_SIZE: usize = NONCE_SIZE + KEY_CHECK_PLAINTEXT.len() + TAG_SIZE;
+
+/// A 256-bit key for encrypting B-tree storage at rest.
+#[derive(Clone, PartialEq, Eq)]
+pub struct EncryptionKey([u8; 32]);
+
+impl EncryptionKey {
+    pub fn new(bytes: [u8; 32]) -> Self {
+        EncryptionKey(bytes)
+    }
+
+    /// Parses a key written as 64 hexadecimal digits.
+    pub fn from_hex(hex: &str) -> Result<Self, RustqlError> {
+        let hex = hex.trim();
+        let invalid = || {
+            RustqlError::StorageError("Encryption key must be 64 hexadecimal digits".to_string())
+        };
+        if hex.len() != 64 || !hex.is_ascii() {
+            return Err(invalid());
+        }
+        let mut bytes = [0u8; 32];
+        for (byte, digits) in bytes.iter_mut().zip(hex.as_bytes().chunks(2)) {
+            let digits = std::str::from_utf8(digits).map_err(|_| invalid())?;
+            *byte = u8::from_str_radix(digits, 16).map_err(|_| invalid())?;
+        }
+        Ok(EncryptionKey(bytes))
+    }
+}
+
+impl fmt::Debug for EncryptionKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("EncryptionKey(..)")
+    }
+}
+
+/// Seals and opens the pages of one encrypted file.
+#[derive(Clone)]
+pub(super) struct PageCipher {
+    key: EncryptionKey,
+}
+
+impl PageCipher {
+    pub(super) fn new(key: EncryptionKey) -> Self {
+        PageCipher { key }
+    }
+
+    pub(super) fn seal_page(&self, page: &BTreePage) -> Result<[u8; BTREE_PAGE_SIZE], RustqlError> {
+        let page_id = page.header.page_id;
+        let plain = page.to_bytes()?;
+        let mut sealed = [0u8; BTREE_PAGE_SIZE];
+        let slot_start = if page_id == 0 {
+            sealed[..KEY_CHECK_SIZE].copy_from_slice(&self.key_check_block());
+            KEY_CHECK_SIZE
+        } else {
+            0
+        };
+        let slot = &mut sealed[slot_start..];
+        let payload_len = slot.len() - PAGE_SEAL_OVERHEAD;
+        if page.encoded_size() > payload_len {
+            return Err(RustqlError::StorageError(format!(
+                "BTree page {} is too large to encrypt",
+                page_id
+            )));
+        }
+
+        let nonce = next_nonce();
+        let (nonce_out, rest) = slot.split_at_mut(NONCE_SIZE);
+        let (body, tag_out) = rest.split_at_mut(payload_len);
+        nonce_out.copy_from_slice(&nonce);
+        body.copy_from_slice(&plain[..payload_len]);
+        let tag = seal_in_place(&self.key.0, &nonce, &page_id.to_le_bytes(), body);
+        tag_out.copy_from_slice(&tag);
+        Ok(sealed)
+    }
+
+    pub(super) fn open_page(
+        &self,
+        page_id: u64,
+        sealed: &[u8; BTREE_PAGE_SIZE],
+    ) -> Result<BTreePage, RustqlError> {
+        let slot = if page_id == 0 {
+            &sealed[KEY_CHECK_SIZE..]
+        } else {
+            &sealed[..]
+        };
+        let payload_len = slot.len() - PAGE_SEAL_OVERHEAD;
+        let nonce: [u8; NONCE_SIZE] = slot[..NONCE_SIZE].try_into().expect("nonce length");
+        let tag: [u8; TAG_SIZE] = slot[NONCE_SIZE + payload_len..]
+            .try_into()
+            .expect("tag length");
+        let mut plain = [0u8; BTREE_PAGE_SIZE];
+        plain[..payload_len].copy_from_slice(&slot[NONCE_SIZE..NONCE_SIZE + payload_len]);
+        if !open_in_place(
+            &self.key.0,
+            &nonce,
+            &page_id.to_le_bytes(),
+            &mut plain[..payload_len],
+            &tag,
+        ) {
+            return Err(RustqlError::StorageError(format!(
+                "BTree page {} failed authentication",
+                page_id
+            )));
+        }
+        BTreePage::from_bytes(&plain)
+    }
+
+    fn key_check_block(&self) -> [u8; KEY_CHECK_SIZE] {
+        let nonce = next_nonce();
+        let mut body = *KEY_CHECK_PLAINTEXT;
+        let tag = seal_in_place(&self.key.0, &nonce, KEY_CHECK_AAD, &mut body);
+        let mut block = [0u8; KEY_CHECK_SIZE];
+        block[..NONCE_SIZE].copy_from_slice(&nonce);
+        block[NONCE_SIZE..NONCE_SIZE + body.len()].copy_from_slice(&body);
+        block[NONCE_SIZE + body.len()..].copy_from_slice(&tag);
+        block
+    }
+
+    /// Returns `true` when `block` was written with this cipher's key.
+    pub(super) fn key_check_matches(&self, block: &[u8; KEY_CHECK_SIZE]) -> bool {
+        let nonce: [u8; NONCE_SIZE] = block[..NONCE_SIZE].try_into().expect("nonce length");
+        let body_end = NONCE_SIZE + KEY_CHECK_PLAINTEXT.len();
+        let mut body = [0u8; KEY_CHECK_PLAINTEXT.len()];
+        body.copy_from_slice(&block[NONCE_SIZE..body_end]);
+        let tag: [u8; TAG_SIZE] = block[body_end..].try_into().expect("tag length");
+        open_in_place(&self.key.0, &nonce, KEY_CHECK_AAD, &mut body, &tag)
+            && &body == KEY_CHECK_PLAINTEXT
+    }
+}
+
+/// Returns the bytes `page` is stored as: sealed with `cipher` in an
+/// encrypted file, or plain with its checksum otherwise.
+pub(super) fn page_image(
+    page: &BTreePage,
+    cipher: Option<&PageCipher>,
+) -> Result<[u8; BTREE_PAGE_SIZE], RustqlError> {
+    match cipher {
+        Some(cipher) => cipher.seal_page(page),
+        None => page.to_bytes(),
+    }
+}
+
+impl BTreeStorageEngine {
+    /// Fails unless the file is encrypted exactly when a key is set, and
+    /// with that key. Runs before recovery, which would otherwise replay
+    /// frames the key cannot open.
+    pub(super) fn check_encryption_key_locked(&self) -> Result<(), RustqlError> {
+        match read_versioned_header_with_versions(
+            &self.data_path,
+            BTreeFile::MAGIC,
+            &BTreeFile::SUPPORTED_VERSIONS,
+            "BTree storage file",
+        )? {
+            VersionedFileState::Valid { .. } => {}
+            VersionedFileState::Missing | VersionedFileState::Empty => return Ok(()),
+        }
+        let encrypted =
+            read_header_flags(&self.data_path, "BTree storage file")? & HEADER_ENCRYPTED_FLAG != 0;
+        match (encrypted, self.page_cipher()) {
+            (false, None) => Ok(()),
+            (true, None) => Err(RustqlError::StorageError(format!(
+                "BTree storage file '{}' is encrypted; open it with its encryption key",
+                self.data_path.display()
+            ))),
+            (false, Some(_)) => Err(RustqlError::StorageError(format!(
+                "BTree storage file '{}' is not encrypted; open it without a key and rekey it",
+                self.data_path.display()
+            ))),
+            (true, Some(cipher)) => {
+                if cipher.key_check_matches(&self.read_key_check_block()?) {
+                    Ok(())
+                } else {
+                    Err(RustqlError::StorageError(format!(
+                        "Wrong encryption key for BTree storage file '{}'",
+                        self.data_path.display()
+                    )))
+                }
+            }
+        }
+    }
+
+    fn read_key_check_block(&self) -> Result<[u8; KEY_CHECK_SIZE], RustqlError> {
+        let mut block = [0u8; KEY_CHECK_SIZE];
+        fs::File::open(&self.data_path)
+            .and_then(|mut file| {
+                file.seek(SeekFrom::Start(FILE_HEADER_SIZE as u64))?;
+                file.read_exact(&mut block)
+            })
+            .map_err(|e| {
+                RustqlError::StorageError(format!(
+                    "Failed to read the key check of BTree storage file '{}': {}",
+                    self.data_path.display(),
+                    e
+                ))
+            })?;
+        Ok(block)
+    }
+
+    /// Rewrites the file for `db` sealed with `key`, or in plain pages when
+    /// `key` is `None`. Logged pages are checkpointed with the old key first;
+    /// if the rewrite fails the old key stays in use.
+    pub(super) fn rekey_locked(
+        &self,
+        db: &Database,
+        key: Option<EncryptionKey>,
+    ) -> Result<(), RustqlError> {
+        self.checkpoint_locked()?;
+        let previous = std::mem::replace(
+            &mut *self
+                .cipher
+                .write()
+                .unwrap_or_else(|poisoned| poisoned.into_inner()),
+            key.map(PageCipher::new),
+        );
+        let result = self.save_locked(db);
+        if result.is_err() {
+            *self
+                .cipher
+                .write()
+                .unwrap_or_else(|poisoned| poisoned.into_inner()) = previous;
+        }
+        result
+    }
+}
+
+/// Returns a nonce no other seal has used. Each process draws a random
+/// 128-bit prefix from the OS-seeded keys of std's hasher and counts up from
+/// there, so nonces only repeat if two processes draw the same prefix.
+fn next_nonce() -> [u8; NONCE_SIZE] {
+    static PREFIX: OnceLock<[u8; 16]> = OnceLock::new();
+    static COUNTER: AtomicU64 = AtomicU64::new(0);
+    let prefix = PREFIX.get_or_init(|| {
+        let seed = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map_or(0, |elapsed| elapsed.as_nanos());
+        let mut prefix = [0u8; 16];
+        for (half, salt) in prefix.chunks_mut(8).zip([0u8, 1]) {
+            let random = RandomState::new().hash_one((seed, std::process::id(), salt));
+            half.copy_from_slice(&random.to_le_bytes());
+        }
+        prefix
+    });
+    let mut nonce = [0u8; NONCE_SIZE];
+    nonce[..16].copy_from_slice(prefix);
+    nonce[16..].copy_from_slice(&COUNTER.fetch_add(1, Ordering::Relaxed).to_le_bytes());
+    nonce
+}
+
+/// Encrypts `data` in place with XChaCha20-Poly1305 and returns the tag.
+fn seal_in_place(
+    key: &[u8; 32],
+    nonce: &[u8; NONCE_SIZE],
+    aad: &[u8],
+    data: &mut [u8],
+) -> [u8; 16] {
+    let (subkey, chacha_nonce) = xchacha_subkey(key, nonce);
+    chacha20_xor(&subkey, &chacha_nonce, 1, data);
+    aead_tag(&subkey, &chacha_nonce, aad, data)
+}
+
+/// Checks the tag over `data` and decrypts it in place. Leaves `data`
+/// untouched and returns `false` when the tag does not match.
+fn open_in_place(
+    key: &[u8; 32],
+    nonce: &[u8; NONCE_SIZE],
+    aad: &[u8],
+    data: &mut [u8],
+    tag: &[u8; 16],
+) -> bool {
+    let (subkey, chacha_nonce) = xchacha_subkey(key, nonce);
+    let expected = aead_tag(&subkey, &chacha_nonce, aad, data);
+    // Compare without an early exit so timing does not reveal the mismatch.
+    if expected
+        .iter()
+        .zip(tag)
+        .fold(0u8, |diff, (left, right)| diff | (left ^ right))
+        != 0
+    {
+        return false;
+    }
+    chacha20_xor(&subkey, &chacha_nonce, 1, data);
+    true
+}
+
+fn xchacha_subkey(key: &[u8; 32], nonce: &[u8; NONCE_SIZE]) -> ([u8; 32], [u8; 12]) {
+    let subkey = hchacha20(key, nonce[..16].try_into().expect("nonce prefix"));
+    let mut chacha_nonce = [0u8; 12];
+    chacha_nonce[4..].copy_from_slice(&nonce[16..]);
+    (subkey, chacha_nonce)
+}
+
+/// The RFC 8439 AEAD tag: Poly1305 over the padded associated data and
+/// ciphertext and their lengths, keyed by the first block of the stream.
+pub(super) fn aead_tag(
+    key: &[u8; 32],
+    nonce: &[u8; 12],
+    aad: &[u8],
+    ciphertext: &[u8],
+) -> [u8; 16] {
+    let block = chacha20_block(key, 0, nonce);
+    let mut poly_key = [0u8; 32];
+    poly_key.copy_from_slice(&block[..32]);
+
+    let pad = |len: usize| (16 - len % 16) % 16;
+    let mut mac_data = Vec::with_capacity(
+        aad.len() + pad(aad.len()) + ciphertext.len() + pad(ciphertext.len()) + 16,
+    );
+    mac_data.extend_from_slice(aad);
+    mac_data.resize(mac_data.len() + pad(aad.len()), 0);
+    mac_data.extend_from_slice(ciphertext);
+    mac_data.resize(mac_data.len() + pad(ciphertext.len()), 0);
+    mac_data.extend_from_slice(&(aad.len() as u64).to_le_bytes());
+    mac_data.extend_from_slice(&(ciphertext.len() as u64).to_le_bytes());
+    poly1305(&poly_key, &mac_data)
+}
+
+const CHACHA_CONSTANTS: [u32; 4] = [0x6170_7865, 0x3320_646e, 0x7962_2d32, 0x6b20_6574];
+
+fn quarter_round(state: &mut [u32; 16], a: usize, b: usize, c: usize, d: usize) {
+    state[a] = state[a].wrapping_add(state[b]);
+    state[d] = (state[d] ^ state[a]).rotate_left(16);
+    state[c] = state[c].wrapping_add(state[d]);
+    state[b] = (state[b] ^ state[c]).rotate_left(12);
+    state[a] = state[a].wrapping_add(state[b]);
+    state[d] = (state[d] ^ state[a]).rotate_left(8);
+    state[c] = state[c].wrapping_add(state[d]);
+    state[b] = (state[b] ^ state[c]).rotate_left(7);
+}
+
+fn chacha_rounds(state: &mut [u32; 16]) {
+    for _ in 0..10 {
+        quarter_round(state, 0, 4, 8, 12);
+        quarter_round(state, 1, 5, 9, 13);
+        quarter_round(state, 2, 6, 10, 14);
+        quarter_round(state, 3, 7, 11, 15);
+        quarter_round(state, 0, 5, 10, 15);
+        quarter_round(state, 1, 6, 11, 12);
+        quarter_round(state, 2, 7, 8, 13);
+        quarter_round(state, 3, 4, 9, 14);
+    }
+}
+
+fn le_words<const N: usize>(bytes: &[u8]) -> [u32; N] {
+    let mut words = [0u32; N];
+    for (word, chunk) in words.iter_mut().zip(bytes.chunks_exact(4)) {
+        *word = u32::from_le_bytes(chunk.try_into().expect("word length"));
+    }
+    words
+}
+
+fn chacha20_block(key: &[u8; 32], counter: u32, nonce: &[u8; 12]) -> [u8; 64] {
+    let mut state = [0u32; 16];
+    state[..4].copy_from_slice(&CHACHA_CONSTANTS);
+    state[4..12].copy_from_slice(&le_words::<8>(key));
+    state[12] = counter;
+    state[13..].copy_from_slice(&le_words::<3>(nonce));
+
+    let mut working = state;
+    chacha_rounds(&mut working);
+    let mut block = [0u8; 64];
+    for (out, (word, initial)) in block.chunks_exact_mut(4).zip(working.iter().zip(state)) {
+        out.copy_from_slice(&word.wrapping_add(initial).to_le_bytes());
+    }
+    block
+}
+
+pub(super) fn chacha20_xor(key: &[u8; 32], nonce: &[u8; 12], first_counter: u32, data: &mut [u8]) {
+    for (index, chunk) in data.chunks_mut(64).enumerate() {
+        let block = chacha20_block(key, first_counter.wrapping_add(index as u32), nonce);
+        for (byte, stream) in chunk.iter_mut().zip(block) {
+            *byte ^= stream;
+        }
+    }
+}
+
+pub(super) fn hchacha20(key: &[u8; 32], nonce: &[u8; 16]) -> [u8; 32] {
+    let mut state = [0u32; 16];
+    state[..4].copy_from_slice(&CHACHA_CONSTANTS);
+    state[4..12].copy_from_slice(&le_words::<8>(key));
+    state[12..].copy_from_slice(&le_words::<4>(nonce));
+    chacha_rounds(&mut state);
+
+    let mut subkey = [0u8; 32];
+    for (out, word) in subkey
+        .chunks_exact_mut(4)
+        .zip(state[..4].iter().chain(&state[12..]))
+    {
+        out.copy_from_slice(&word.to_le_bytes());
+    }
+    subkey
+}
+
+/// Poly1305 with 26-bit limbs.
+pub(super) fn poly1305(key: &[u8; 32], message: &[u8]) -> [u8; 16] {
+    const MASK: u32 = 0x03ff_ffff;
+    let word = |bytes: &[u8], offset: usize| {
+        u32::from_le_bytes(bytes[offset..offset + 4].try_into().expect("word length"))
+    };
+
+    let r0 = word(key, 0) & 0x03ff_ffff;
+    let r1 = (word(key, 3) >> 2) & 0x03ff_ff03;
+    let r2 = (word(key, 6) >> 4) & 0x03ff_c0ff;
+    let r3 = (word(key, 9) >> 6) & 0x03f0_3fff;
+    let r4 = (word(key, 12) >> 8) & 0x000f_ffff;
+    let (s1, s2, s3, s4) = (r1 * 5, r2 * 5, r3 * 5, r4 * 5);
+    let mut h = [0u32; 5];
+
+    for chunk in message.chunks(16) {
+        let mut block = [0u8; 16];
+        block[..chunk.len()].copy_from_slice(chunk);
+        let high_bit = if chunk.len() == 16 {
+            1 << 24
+        } else {
+            block[chunk.len()] = 1;
+            0
+        };
+
+        h[0] += word(&block, 0) & MASK;
+        h[1] += (word(&block, 3) >> 2) & MASK;
+        h[2] += (word(&block, 6) >> 4) & MASK;
+        h[3] += (word(&block, 9) >> 6) & MASK;
+        h[4] += (word(&block, 12) >> 8) | high_bit;
+
+        let [h0, h1, h2, h3, h4] = h.map(u64::from);
+        let (r0, r1, r2, r3, r4) = (r0 as u64, r1 as u64, r2 as u64, r3 as u64, r4 as u64);
+        let (s1, s2, s3, s4) = (s1 as u64, s2 as u64, s3 as u64, s4 as u64);
+        let d0 = h0 * r0 + h1 * s4 + h2 * s3 + h3 * s2 + h4 * s1;
+        let mut d1 = h0 * r1 + h1 * r0 + h2 * s4 + h3 * s3 + h4 * s2;
+        let mut d2 = h0 * r2 + h1 * r1 + h2 * r0 + h3 * s4 + h4 * s3;
+        let mut d3 = h0 * r3 + h1 * r2 + h2 * r1 + h3 * r0 + h4 * s4;
+        let mut d4 = h0 * r4 + h1 * r3 + h2 * r2 + h3 * r1 + h4 * r0;
+
+        let mut carry = d0 >> 26;
+        h[0] = d0 as u32 & MASK;
+        d1 += carry;
+        carry = d1 >> 26;
+        h[1] = d1 as u32 & MASK;
+        d2 += carry;
+        carry = d2 >> 26;
+        h[2] = d2 as u32 & MASK;
+        d3 += carry;
+        carry = d3 >> 26;
+        h[3] = d3 as u32 & MASK;
+        d4 += carry;
+        carry = d4 >> 26;
+        h[4] = d4 as u32 & MASK;
+        h[0] += carry as u32 * 5;
+        let carry = h[0] >> 26;
+        h[0] &= MASK;
+        h[1] += carry;
+    }
+
+    // Fully carry h, then subtract p = 2^130 - 5 if h >= p.
+    for index in 1..5 {
+        let carry = h[index - 1] >> 26;
+        h[index - 1] &= MASK;
+        h[index] += carry;
+    }
+    let carry = h[4] >> 26;
+    h[4] &= MASK;
+    h[0] += carry * 5;
+    let carry = h[0] >> 26;
+    h[0] &= MASK;
+    h[1] += carry;
+
+    let mut g = [0u32; 5];
+    let mut carry = 5;
+    for index in 0..4 {
+        g[index] = h[index] + carry;
+        carry = g[index] >> 26;
+        g[index] &= MASK;
+    }
+    g[4] = h[4].wrapping_add(carry).wrapping_sub(1 << 26);
+    let use_g = (g[4] >> 31).wrapping_sub(1);
+    for index in 0..5 {
+        h[index] = (h[index] & !use_g) | (g[index] & use_g);
+    }
+
+    let words = [
+        h[0] | (h[1] << 26),
+        (h[1] >> 6) | (h[2] << 20),
+        (h[2] >> 12) | (h[3] << 14),
+        (h[3] >> 18) | (h[4] << 8),
+    ];
+    let mut tag = [0u8; 16];
+    let mut carry = 0u64;
+    for (index, out) in tag.chunks_exact_mut(4).enumerate() {
+        let sum = u64::from(words[index]) + u64::from(word(key, 16 + index * 4)) + carry;
+        out.copy_from_slice(&(sum as u32).to_le_bytes());
+        carry = sum >> 32;
+    }
+    tag
+}
@@ -0,0 +1,270 @@
+use super::BTreeStorageEngine;
+use super::cipher::page_image;
+use super::file::BTreeFile;
+use super::header::{FILE_HEADER_SIZE, VersionedFileState, read_versioned_header_with_versions};
+use super::journal::{BTreePageDelta, BTreePageFrame, TransactionJournal, storage_checksum};
+use super::page::{BTREE_PAGE_SIZE, BTreePage};
+use super::tree::PageStore;
+use super::writer::RecordWriter;
+use crate::database::{Database, ReplacedRows};
+use crate::error::RustqlError;
+use crate::storage::ChangeSet;
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::{Seek, SeekFrom, Write};
+use std::path::Path;
+
+/// Page store that reads through the engine cache and keeps every written
+/// page in memory until the change set has been fully applied.
+struct StagedPages<'a> {
+    engine: &'a BTreeStorageEngine,
+    file: BTreeFile,
+    dirty: BTreeMap<u64, BTreePage>,
+}
+
+impl PageStore for StagedPages<'_> {
+    fn read_page(&mut self, page_id: u64) -> Result<BTreePage, RustqlError> {
+        if let Some(page) = self.dirty.get(&page_id) {
+            return Ok(page.clone());
+        }
+        self.engine.read_page_through_cache(&mut self.file, page_id)
+    }
+
+    fn write_page(&mut self, page: &BTreePage) -> Result<(), RustqlError> {
+        self.dirty.insert(page.header.page_id, page.clone());
+        Ok(())
+    }
+}
+
+/// Page after-images produced by applying a change set, ready to journal.
+pub(super) struct StagedDelta {
+    pub(super) delta: BTreePageDelta,
+    pub(super) pages: BTreeMap<u64, BTreePage>,
+    /// The rows the change set replaced, for paged tables that still read
+    /// the ones before it.
+    pub(super) replaced: ReplacedRows,
+}
+
+/// What [`BTreeStorageEngine::save_changes_locked`] did with a change set.
+pub(super) enum CommitLogging {
+    /// Nothing was written; the file needs a snapshot save instead.
+    NeedsSnapshot,
+    /// The change set did not change any page.
+    Unchanged,
+    /// The change set was appended to the redo log as the record with this
+    /// sequence number, which is durable once the log has synced it.
+    Logged(u64),
+}
+
+impl BTreeStorageEngine {
+    /// Applies `changes` to the pages they touch and appends the resulting
+    /// page images to the redo log. The main file is left untouched until the
+    /// next checkpoint.
+    pub(super) fn save_changes_locked(
+        &self,
+        db: &Database,
+        changes: &ChangeSet,
+    ) -> Result<CommitLogging, RustqlError> {
+        if changes.is_empty() {
+            return Ok(CommitLogging::Unchanged);
+        }
+        if !self.redo_log.is_open() {
+            // A log or journal left by another process has to be folded into
+            // the main file before new records can be appended after it.
+            self.recover_if_needed_locked()?;
+        }
+
+        let Some(base_file_len) = self.delta_base_len_locked()? else {
+            return Ok(CommitLogging::NeedsSnapshot);
+        };
+        let base_file_len = base_file_len.max(self.redo_log.logged_file_len());
+        let Some(StagedDelta {
+            delta,
+            pages,
+            replaced,
+        }) = self.stage_page_delta_locked(db, changes, base_file_len)?
+        else {
+            return Ok(CommitLogging::Unchanged);
+        };
+        let page_ids: Vec<u64> = pages.keys().copied().collect();
+        let lsn = self.redo_log.append(&delta, pages)?;
+        self.queue_delta_locked(lsn, delta);
+        // Logged pages are read from the log until the next checkpoint, so
+        // older cached copies must not outlive it.
+        self.invalidate_pages(&page_ids);
+        self.supersede_rows(replaced);
+        Ok(CommitLogging::Logged(lsn))
+    }
+
+    /// Journals `changes` as the pages they touch, as one file's part of a
+    /// commit across attached databases that is replayed only if `record`
+    /// exists. Returns `false`, without journaling anything, when the file
+    /// has to be prepared as a whole image instead.
+    pub(super) fn prepare_linked_delta_locked(
+        &self,
+        db: &Database,
+        changes: &ChangeSet,
+        record: &Path,
+    ) -> Result<bool, RustqlError> {
+        if !self.redo_log.is_open() {
+            self.recover_if_needed_locked()?;
+        }
+        let Some(base_file_len) = self.delta_base_len_locked()? else {
+            return Ok(false);
+        };
+        if let Some(StagedDelta {
+            delta, replaced, ..
+        }) = self.stage_page_delta_locked(db, changes, base_file_len)?
+        {
+            self.write_journal_locked(&TransactionJournal::LinkedDelta {
+                delta,
+                record: record.to_path_buf(),
+            })?;
+            *self
+                .linked_replaced
+                .lock()
+                .unwrap_or_else(|err| err.into_inner()) = replaced;
+        }
+        Ok(true)
+    }
+
+    /// The length of the file page deltas apply to, or `None` when it has to
+    /// be rewritten as a snapshot. Missing, empty, and pre-version-4 files
+    /// are; the snapshot also upgrades older files to the current layout.
+    pub(super) fn delta_base_len_locked(&self) -> Result<Option<u64>, RustqlError> {
+        let current_version = match read_versioned_header_with_versions(
+            &self.data_path,
+            BTreeFile::MAGIC,
+            &BTreeFile::SUPPORTED_VERSIONS,
+            "BTree storage file",
+        )? {
+            VersionedFileState::Valid { version } => Some(version),
+            VersionedFileState::Missing | VersionedFileState::Empty => None,
+        };
+        let base_file_len = fs::metadata(&self.data_path)
+            .map(|metadata| metadata.len())
+            .unwrap_or(0);
+        if current_version != Some(BTreeFile::VERSION)
+            || base_file_len < (FILE_HEADER_SIZE + 2 * BTREE_PAGE_SIZE) as u64
+        {
+            return Ok(None);
+        }
+        Ok(Some(base_file_len))
+    }
+
+    /// Applies `changes` to staged copies of the affected pages and returns
+    /// them as a delta over the current file, or `None` when no page changed.
+    pub(super) fn stage_page_delta_locked(
+        &self,
+        db: &Database,
+        changes: &ChangeSet,
+        base_file_len: u64,
+    ) -> Result<Option<StagedDelta>, RustqlError> {
+        let mut staged = StagedPages {
+            engine: self,
+            file: self.open_file()?,
+            dirty: BTreeMap::new(),
+        };
+        let mut writer = RecordWriter::new(&mut staged)?;
+        if self.has_row_readers() {
+            writer.track_replaced_rows();
+        }
+        writer.apply_changes(db, changes)?;
+        let replaced = writer.into_replaced();
+        let StagedPages { file, dirty, .. } = staged;
+        drop(file);
+        if dirty.is_empty() {
+            return Ok(None);
+        }
+
+        let cipher = self.page_cipher();
+        let mut frames = Vec::with_capacity(dirty.len());
+        for (page_id, page) in &dirty {
+            let bytes = page_image(page, cipher.as_ref())?.to_vec();
+            frames.push(BTreePageFrame {
+                page_id: *page_id,
+                checksum: storage_checksum(&bytes),
+                bytes,
+            });
+        }
+        let written_len = dirty
+            .keys()
+            .next_back()
+            .map(|page_id| FILE_HEADER_SIZE as u64 + (page_id + 1) * BTREE_PAGE_SIZE as u64)
+            .unwrap_or(0);
+        let delta = BTreePageDelta {
+            page_size: BTREE_PAGE_SIZE,
+            base_file_len,
+            target_file_len: base_file_len.max(written_len),
+            frames,
+        };
+        Ok(Some(StagedDelta {
+            delta,
+            pages: dirty,
+            replaced,
+        }))
+    }
+
+    /// Replays a delta journal during recovery.
+    pub(super) fn apply_page_delta_locked(
+        &self,
+        delta: &BTreePageDelta,
+    ) -> Result<(), RustqlError> {
+        delta.validate()?;
+        match read_versioned_header_with_versions(
+            &self.data_path,
+            BTreeFile::MAGIC,
+            &BTreeFile::SUPPORTED_VERSIONS,
+            "BTree storage file",
+        )? {
+            VersionedFileState::Valid { version } if version == BTreeFile::VERSION => {}
+            _ => {
+                return Err(RustqlError::StorageError(
+                    "Cannot replay transaction journal: BTree storage file does not match the journal format version".to_string(),
+                ));
+            }
+        }
+        let mut file = self.open_data_file_for_delta()?;
+        write_frames(&mut file, delta)
+    }
+
+    pub(super) fn open_data_file_for_delta(&self) -> Result<fs::File, RustqlError> {
+        fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(&self.data_path)
+            .map_err(|e| {
+                RustqlError::StorageError(format!(
+                    "Failed to open BTree storage file '{}': {}",
+                    self.data_path.display(),
+                    e
+                ))
+            })
+    }
+}
+
+fn seek_page(file: &mut fs::File, page_id: u64) -> Result<(), RustqlError> {
+    let offset = FILE_HEADER_SIZE as u64 + page_id * BTREE_PAGE_SIZE as u64;
+    file.seek(SeekFrom::Start(offset))
+        .map(|_| ())
+        .map_err(|e| RustqlError::StorageError(format!("Failed to seek to BTree page: {}", e)))
+}
+
+pub(super) fn write_frames(file: &mut fs::File, delta: &BTreePageDelta) -> Result<(), RustqlError> {
+    for frame in &delta.frames {
+        seek_page(file, frame.page_id)?;
+        file.write_all(&frame.bytes)
+            .map_err(|e| RustqlError::StorageError(format!("Failed to write BTree page: {}", e)))?;
+    }
+    let current_len = file
+        .metadata()
+        .map_err(|e| RustqlError::StorageError(format!("Failed to get file metadata: {}", e)))?
+        .len();
+    if current_len < delta.target_file_len {
+        file.set_len(delta.target_file_len).map_err(|e| {
+            RustqlError::StorageError(format!("Failed to extend BTree storage file: {}", e))
+        })?;
+    }
+    file.sync_all()
+        .map_err(|e| RustqlError::StorageError(format!("Failed to sync BTree storage file: {}", e)))
+}
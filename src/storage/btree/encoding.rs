@@ -0,0 +1,915 @@
+//! Binary encoding of the records kept in B-tree leaves.
+//!
+//! Format version 5 stores every record as a binary payload: a format byte,
+//! a record kind, and the record's fields in a fixed order. Unsigned integers
+//! and lengths are LEB128 varints, signed integers are zigzag-encoded first,
+//! and text is length-prefixed UTF-8. A row holds its column count, a bitmap
+//! of the columns that are NULL, and a type tag and value for every other
+//! column. Payloads too large to keep in a leaf live on overflow pages; the
+//! leaf then holds a stub naming the first page of the chain.
+//!
+//! Versions 2 to 4 stored records as serde JSON text. JSON text starts with a
+//! printable character and binary payloads with a control byte, so the first
+//! byte tells the two apart and older records are still read.
+
+use super::records::{IndexEntryRecord, IndexTableRecord, TableStorageRecord};
+use crate::ast::{
+    ColumnDefinition, DataType, Deferrable, ForeignKeyAction, ForeignKeyConstraint,
+    GeneratedColumn, TableConstraint, Value,
+};
+use crate::blob;
+use crate::database::{CompositeIndex, Index, PreparedTransaction, RowId, SharedMap, View};
+use crate::decimal::Decimal;
+use crate::error::RustqlError;
+use crate::interval::Interval;
+use serde::de::DeserializeOwned;
+
+/// First byte of a binary record. A later revision of the encoding gets the
+/// next value.
+const RECORD_FORMAT: u8 = 0x01;
+/// First byte of a stub for a record kept on overflow pages.
+const OVERFLOW_STUB: u8 = 0x1f;
+
+const KIND_ROW: u8 = 1;
+const KIND_TABLE: u8 = 2;
+const KIND_INDEX: u8 = 3;
+const KIND_COMPOSITE_INDEX: u8 = 4;
+const KIND_VIEW: u8 = 5;
+const KIND_INDEX_ENTRY: u8 = 6;
+const KIND_PREPARED: u8 = 7;
+
+const VALUE_NULL: u8 = 0;
+const VALUE_INTEGER: u8 = 1;
+const VALUE_FLOAT: u8 = 2;
+const VALUE_TEXT: u8 = 3;
+const VALUE_FALSE: u8 = 4;
+const VALUE_TRUE: u8 = 5;
+const VALUE_DATE: u8 = 6;
+const VALUE_TIME: u8 = 7;
+const VALUE_DATETIME: u8 = 8;
+const VALUE_DECIMAL: u8 = 9;
+const VALUE_BLOB: u8 = 10;
+const VALUE_JSON: u8 = 11;
+const VALUE_INTERVAL: u8 = 12;
+const VALUE_TIMESTAMPTZ: u8 = 13;
+
+const COLUMN_NULLABLE: u8 = 0x01;
+const COLUMN_PRIMARY_KEY: u8 = 0x02;
+const COLUMN_UNIQUE: u8 = 0x04;
+const COLUMN_AUTO_INCREMENT: u8 = 0x08;
+
+/// Bits a deferrable constraint sets in the column flags of its foreign key,
+/// or in the tag of a table constraint. Older records leave them clear.
+const DEFERRABLE: u8 = 0x10;
+const INITIALLY_DEFERRED: u8 = 0x20;
+
+/// A record type that can be written as a binary payload.
+pub(super) trait EncodeRecord {
+    const KIND: u8;
+
+    fn encode_fields(&self, buf: &mut Vec<u8>) -> Result<(), RustqlError>;
+}
+
+/// A record type that can be read from a binary payload, or from the JSON
+/// text older format versions wrote.
+pub(super) trait DecodeRecord: DeserializeOwned {
+    /// Returns `true` for the record kinds this type is read from.
+    fn reads_kind(kind: u8) -> bool;
+
+    fn decode_fields(reader: &mut RecordReader<'_>) -> Result<Self, RustqlError>;
+}
+
+pub(super) fn encode_record<R: EncodeRecord + ?Sized>(record: &R) -> Result<Vec<u8>, RustqlError> {
+    let mut buf = vec![RECORD_FORMAT, R::KIND];
+    record.encode_fields(&mut buf)?;
+    Ok(buf)
+}
+
+/// Decodes a complete record payload; overflow stubs must be resolved first.
+pub(super) fn decode_record<R: DecodeRecord>(
+    payload: &[u8],
+    label: &str,
+) -> Result<R, RustqlError> {
+    let decode_error = |message: String| {
+        RustqlError::StorageError(format!("Failed to decode {}: {}", label, message))
+    };
+    match payload.first() {
+        Some(&RECORD_FORMAT) => {}
+        Some(&OVERFLOW_STUB) => {
+            return Err(decode_error(
+                "record is on overflow pages that were not read".to_string(),
+            ));
+        }
+        Some(byte) if *byte < 0x20 => {
+            return Err(decode_error(format!(
+                "unknown record format 0x{:02x}",
+                byte
+            )));
+        }
+        _ => {
+            return serde_json::from_slice(payload).map_err(|e| {
+                RustqlError::StorageError(format!("Failed to deserialize {}: {}", label, e))
+            });
+        }
+    }
+    let mut reader = RecordReader {
+        data: payload,
+        offset: 1,
+    };
+    let kind = reader.byte().map_err(|e| decode_error(e.to_string()))?;
+    if !R::reads_kind(kind) {
+        return Err(decode_error(format!("unexpected record kind {}", kind)));
+    }
+    let record = R::decode_fields(&mut reader).map_err(|e| match e {
+        RustqlError::StorageError(message) => decode_error(message),
+        other => other,
+    })?;
+    if reader.offset != payload.len() {
+        return Err(decode_error(format!(
+            "{} trailing bytes",
+            payload.len() - reader.offset
+        )));
+    }
+    Ok(record)
+}
+
+/// Builds the stub that replaces a payload of `len` bytes stored on the
+/// overflow chain starting at `first_page`.
+pub(super) fn overflow_stub(len: usize, first_page: u64) -> Vec<u8> {
+    let mut stub = vec![OVERFLOW_STUB];
+    put_varint(&mut stub, len as u64);
+    put_varint(&mut stub, first_page);
+    stub
+}
+
+/// Returns the payload length and first overflow page of a stub, or `None`
+/// for a payload stored in the leaf.
+pub(super) fn parse_overflow_stub(payload: &[u8]) -> Result<Option<(usize, u64)>, RustqlError> {
+    if payload.first() != Some(&OVERFLOW_STUB) {
+        return Ok(None);
+    }
+    let mut reader = RecordReader {
+        data: payload,
+        offset: 1,
+    };
+    let len = reader.varint()? as usize;
+    let first_page = reader.varint()?;
+    Ok(Some((len, first_page)))
+}
+
+/// Encodes index key values as the text used in index entry keys. Equal keys
+/// always produce the same text: each value is a type letter, and all but
+/// NULL add their length and text form, as in `i2:42` or `t5:hello`.
+pub(super) fn encode_key_text(values: &[Value]) -> String {
+    let mut text = String::new();
+    for value in values {
+        let (tag, payload) = match value {
+            Value::Null => {
+                text.push('n');
+                continue;
+            }
+            Value::Integer(i) => ('i', i.to_string()),
+            Value::Float(f) if *f == 0.0 => ('f', "0".to_string()),
+            Value::Float(f) => ('f', f.to_string()),
+            Value::Decimal(d) => ('x', d.normalize().to_string()),
+            Value::Text(s) => ('t', s.clone()),
+            Value::Boolean(b) => ('b', if *b { "1" } else { "0" }.to_string()),
+            Value::Date(s) => ('d', s.clone()),
+            Value::Time(s) => ('h', s.clone()),
+            Value::DateTime(s) => ('s', s.clone()),
+            Value::TimestampTz(s) => ('z', s.clone()),
+            // Justifying gives intervals that compare equal the same text.
+            Value::Interval(iv) => ('v', iv.justify_interval().unwrap_or(*iv).to_string()),
+            Value::Blob(bytes) => ('y', blob::encode_hex(bytes)),
+            Value::Json(s) => ('j', s.clone()),
+        };
+        text.push(tag);
+        text.push_str(&payload.len().to_string());
+        text.push(':');
+        text.push_str(&payload);
+    }
+    text
+}
+
+/// Reads index key values from text written by [`encode_key_text`].
+pub(super) fn decode_key_text(mut text: &str) -> Option<Vec<Value>> {
+    let mut values = Vec::new();
+    while let Some(tag) = text.chars().next() {
+        text = &text[1..];
+        if tag == 'n' {
+            values.push(Value::Null);
+            continue;
+        }
+        let (len, rest) = text.split_once(':')?;
+        let len: usize = len.parse().ok()?;
+        let payload = rest.get(..len)?;
+        text = &rest[len..];
+        values.push(match tag {
+            'i' => Value::Integer(payload.parse().ok()?),
+            'f' => Value::Float(payload.parse().ok()?),
+            'x' => Value::Decimal(Decimal::parse(payload)?),
+            't' => Value::Text(payload.to_string()),
+            'b' => Value::Boolean(payload == "1"),
+            'd' => Value::Date(payload.to_string()),
+            'h' => Value::Time(payload.to_string()),
+            's' => Value::DateTime(payload.to_string()),
+            'z' => Value::TimestampTz(payload.to_string()),
+            'v' => Value::Interval(Interval::parse(payload)?),
+            'y' => Value::Blob(blob::decode_hex(payload)?),
+            'j' => Value::Json(payload.to_string()),
+            _ => return None,
+        });
+    }
+    Some(values)
+}
+
+/// Reads the fields of a binary record in order.
+pub(super) struct RecordReader<'a> {
+    data: &'a [u8],
+    offset: usize,
+}
+
+impl RecordReader<'_> {
+    fn truncated() -> RustqlError {
+        RustqlError::StorageError("record is truncated".to_string())
+    }
+
+    fn byte(&mut self) -> Result<u8, RustqlError> {
+        let byte = *self.data.get(self.offset).ok_or_else(Self::truncated)?;
+        self.offset += 1;
+        Ok(byte)
+    }
+
+    fn bytes(&mut self, len: usize) -> Result<&[u8], RustqlError> {
+        let end = self.offset.checked_add(len).ok_or_else(Self::truncated)?;
+        let bytes = self
+            .data
+            .get(self.offset..end)
+            .ok_or_else(Self::truncated)?;
+        self.offset = end;
+        Ok(bytes)
+    }
+
+    fn varint(&mut self) -> Result<u64, RustqlError> {
+        let mut value = 0u64;
+        for shift in (0..64).step_by(7) {
+            let byte = self.byte()?;
+            value |= u64::from(byte & 0x7f) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        Err(RustqlError::StorageError(
+            "varint is longer than 64 bits".to_string(),
+        ))
+    }
+
+    fn signed(&mut self) -> Result<i64, RustqlError> {
+        let zigzag = self.varint()?;
+        Ok((zigzag >> 1) as i64 ^ -((zigzag & 1) as i64))
+    }
+
+    fn len(&mut self) -> Result<usize, RustqlError> {
+        let len = self.varint()?;
+        if len > (self.data.len() - self.offset) as u64 {
+            return Err(Self::truncated());
+        }
+        Ok(len as usize)
+    }
+
+    fn string(&mut self) -> Result<String, RustqlError> {
+        let len = self.len()?;
+        let bytes = self.bytes(len)?;
+        String::from_utf8(bytes.to_vec())
+            .map_err(|e| RustqlError::StorageError(format!("text is not UTF-8: {}", e)))
+    }
+
+    fn flag(&mut self) -> Result<bool, RustqlError> {
+        match self.byte()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            other => Err(RustqlError::StorageError(format!(
+                "invalid presence byte {}",
+                other
+            ))),
+        }
+    }
+
+    fn optional_string(&mut self) -> Result<Option<String>, RustqlError> {
+        self.flag()?.then(|| self.string()).transpose()
+    }
+
+    fn strings(&mut self) -> Result<Vec<String>, RustqlError> {
+        let count = self.len()?;
+        (0..count).map(|_| self.string()).collect()
+    }
+
+    fn value(&mut self) -> Result<Value, RustqlError> {
+        let tag = self.byte()?;
+        self.value_with_tag(tag)
+    }
+
+    fn value_with_tag(&mut self, tag: u8) -> Result<Value, RustqlError> {
+        Ok(match tag {
+            VALUE_NULL => Value::Null,
+            VALUE_INTEGER => Value::Integer(self.signed()?),
+            VALUE_FLOAT => {
+                let bytes: [u8; 8] = self.bytes(8)?.try_into().map_err(|_| Self::truncated())?;
+                Value::Float(f64::from_le_bytes(bytes))
+            }
+            VALUE_DECIMAL => {
+                let bytes: [u8; 16] = self.bytes(16)?.try_into().map_err(|_| Self::truncated())?;
+                let scale = self.byte()?;
+                Value::Decimal(
+                    Decimal::new(i128::from_le_bytes(bytes), u32::from(scale)).ok_or_else(
+                        || RustqlError::StorageError("invalid decimal value".to_string()),
+                    )?,
+                )
+            }
+            VALUE_TEXT => Value::Text(self.string()?),
+            VALUE_FALSE => Value::Boolean(false),
+            VALUE_TRUE => Value::Boolean(true),
+            VALUE_DATE => Value::Date(self.string()?),
+            VALUE_TIME => Value::Time(self.string()?),
+            VALUE_DATETIME => Value::DateTime(self.string()?),
+            VALUE_TIMESTAMPTZ => Value::TimestampTz(self.string()?),
+            VALUE_JSON => Value::Json(self.string()?),
+            VALUE_INTERVAL => {
+                let months = i32::try_from(self.signed()?);
+                let days = i32::try_from(self.signed()?);
+                let micros = self.signed()?;
+                match (months, days) {
+                    (Ok(months), Ok(days)) => Value::Interval(Interval::new(months, days, micros)),
+                    _ => {
+                        return Err(RustqlError::StorageError(
+                            "invalid interval value".to_string(),
+                        ));
+                    }
+                }
+            }
+            VALUE_BLOB => {
+                let len = self.len()?;
+                Value::Blob(self.bytes(len)?.to_vec())
+            }
+            other => {
+                return Err(RustqlError::StorageError(format!(
+                    "unknown value tag {}",
+                    other
+                )));
+            }
+        })
+    }
+}
+
+fn put_varint(buf: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        buf.push((value as u8 & 0x7f) | 0x80);
+        value >>= 7;
+    }
+    buf.push(value as u8);
+}
+
+fn put_signed(buf: &mut Vec<u8>, value: i64) {
+    put_varint(buf, ((value << 1) ^ (value >> 63)) as u64);
+}
+
+fn put_str(buf: &mut Vec<u8>, text: &str) {
+    put_varint(buf, text.len() as u64);
+    buf.extend_from_slice(text.as_bytes());
+}
+
+fn put_flag(buf: &mut Vec<u8>, flag: bool) {
+    buf.push(u8::from(flag));
+}
+
+fn put_optional_str(buf: &mut Vec<u8>, text: Option<&str>) {
+    put_flag(buf, text.is_some());
+    if let Some(text) = text {
+        put_str(buf, text);
+    }
+}
+
+fn put_strings(buf: &mut Vec<u8>, strings: &[String]) {
+    put_varint(buf, strings.len() as u64);
+    for text in strings {
+        put_str(buf, text);
+    }
+}
+
+fn put_value(buf: &mut Vec<u8>, value: &Value) {
+    match value {
+        Value::Null => buf.push(VALUE_NULL),
+        Value::Integer(i) => {
+            buf.push(VALUE_INTEGER);
+            put_signed(buf, *i);
+        }
+        Value::Float(f) => {
+            buf.push(VALUE_FLOAT);
+            buf.extend_from_slice(&f.to_le_bytes());
+        }
+        Value::Decimal(d) => {
+            buf.push(VALUE_DECIMAL);
+            buf.extend_from_slice(&d.mantissa().to_le_bytes());
+            buf.push(d.scale() as u8);
+        }
+        Value::Text(s) => {
+            buf.push(VALUE_TEXT);
+            put_str(buf, s);
+        }
+        Value::Boolean(false) => buf.push(VALUE_FALSE),
+        Value::Boolean(true) => buf.push(VALUE_TRUE),
+        Value::Date(s) => {
+            buf.push(VALUE_DATE);
+            put_str(buf, s);
+        }
+        Value::Time(s) => {
+            buf.push(VALUE_TIME);
+            put_str(buf, s);
+        }
+        Value::DateTime(s) => {
+            buf.push(VALUE_DATETIME);
+            put_str(buf, s);
+        }
+        Value::TimestampTz(s) => {
+            buf.push(VALUE_TIMESTAMPTZ);
+            put_str(buf, s);
+        }
+        Value::Interval(iv) => {
+            buf.push(VALUE_INTERVAL);
+            put_signed(buf, i64::from(iv.months()));
+            put_signed(buf, i64::from(iv.days()));
+            put_signed(buf, iv.micros());
+        }
+        Value::Blob(bytes) => {
+            buf.push(VALUE_BLOB);
+            put_varint(buf, bytes.len() as u64);
+            buf.extend_from_slice(bytes);
+        }
+        Value::Json(s) => {
+            buf.push(VALUE_JSON);
+            put_str(buf, s);
+        }
+    }
+}
+
+fn put_row(buf: &mut Vec<u8>, row: &[Value]) {
+    put_varint(buf, row.len() as u64);
+    let mut nulls = vec![0u8; row.len().div_ceil(8)];
+    for (column, value) in row.iter().enumerate() {
+        if matches!(value, Value::Null) {
+            nulls[column / 8] |= 1 << (column % 8);
+        }
+    }
+    buf.extend_from_slice(&nulls);
+    for value in row.iter().filter(|value| !matches!(value, Value::Null)) {
+        put_value(buf, value);
+    }
+}
+
+impl EncodeRecord for [Value] {
+    const KIND: u8 = KIND_ROW;
+
+    fn encode_fields(&self, buf: &mut Vec<u8>) -> Result<(), RustqlError> {
+        put_row(buf, self);
+        Ok(())
+    }
+}
+
+impl DecodeRecord for Vec<Value> {
+    fn reads_kind(kind: u8) -> bool {
+        kind == KIND_ROW
+    }
+
+    fn decode_fields(reader: &mut RecordReader<'_>) -> Result<Self, RustqlError> {
+        // NULL columns take no bytes past the bitmap, so the count is only
+        // bounded by the bitmap's length.
+        let columns = usize::try_from(reader.varint()?).map_err(|_| RecordReader::truncated())?;
+        let nulls = reader.bytes(columns.div_ceil(8))?.to_vec();
+        (0..columns)
+            .map(|column| {
+                if nulls[column / 8] & (1 << (column % 8)) != 0 {
+                    Ok(Value::Null)
+                } else {
+                    reader.value()
+                }
+            })
+            .collect()
+    }
+}
+
+impl EncodeRecord for TableStorageRecord {
+    const KIND: u8 = KIND_TABLE;
+
+    fn encode_fields(&self, buf: &mut Vec<u8>) -> Result<(), RustqlError> {
+        put_varint(buf, self.next_row_id);
+        put_flag(buf, self.row_count.is_some());
+        if let Some(row_count) = self.row_count {
+            put_varint(buf, row_count);
+        }
+        put_varint(buf, self.columns.len() as u64);
+        for column in &self.columns {
+            put_column(buf, column);
+        }
+        put_varint(buf, self.constraints.len() as u64);
+        for constraint in &self.constraints {
+            let (tag, name, columns) = match constraint {
+                TableConstraint::PrimaryKey { name, columns } => (0, name, columns),
+                TableConstraint::Unique {
+                    name,
+                    columns,
+                    deferrable,
+                } => (1 | deferrable_bits(*deferrable), name, columns),
+            };
+            buf.push(tag);
+            put_optional_str(buf, name.as_deref());
+            put_strings(buf, columns);
+        }
+        Ok(())
+    }
+}
+
+impl DecodeRecord for TableStorageRecord {
+    fn reads_kind(kind: u8) -> bool {
+        kind == KIND_TABLE
+    }
+
+    fn decode_fields(reader: &mut RecordReader<'_>) -> Result<Self, RustqlError> {
+        let next_row_id = reader.varint()?;
+        let row_count = reader.flag()?.then(|| reader.varint()).transpose()?;
+        let column_count = reader.len()?;
+        let columns = (0..column_count)
+            .map(|_| read_column(reader))
+            .collect::<Result<_, _>>()?;
+        let constraint_count = reader.len()?;
+        let constraints = (0..constraint_count)
+            .map(|_| {
+                let tag = reader.byte()?;
+                let name = reader.optional_string()?;
+                let columns = reader.strings()?;
+                match tag & !(DEFERRABLE | INITIALLY_DEFERRED) {
+                    0 => Ok(TableConstraint::PrimaryKey { name, columns }),
+                    1 => Ok(TableConstraint::Unique {
+                        name,
+                        columns,
+                        deferrable: deferrable_from_bits(tag),
+                    }),
+                    other => Err(RustqlError::StorageError(format!(
+                        "unknown table constraint {}",
+                        other
+                    ))),
+                }
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(TableStorageRecord {
+            columns,
+            constraints,
+            next_row_id,
+            row_count,
+        })
+    }
+}
+
+fn put_column(buf: &mut Vec<u8>, column: &ColumnDefinition) {
+    put_str(buf, &column.name);
+    put_data_type(buf, &column.data_type);
+    let mut flags = 0;
+    for (set, flag) in [
+        (column.nullable, COLUMN_NULLABLE),
+        (column.primary_key, COLUMN_PRIMARY_KEY),
+        (column.unique, COLUMN_UNIQUE),
+        (column.auto_increment, COLUMN_AUTO_INCREMENT),
+    ] {
+        if set {
+            flags |= flag;
+        }
+    }
+    if let Some(foreign_key) = &column.foreign_key {
+        flags |= deferrable_bits(foreign_key.deferrable);
+    }
+    buf.push(flags);
+    put_flag(buf, column.default_value.is_some());
+    if let Some(default) = &column.default_value {
+        put_value(buf, default);
+    }
+    put_flag(buf, column.foreign_key.is_some());
+    if let Some(foreign_key) = &column.foreign_key {
+        put_str(buf, &foreign_key.referenced_table);
+        put_str(buf, &foreign_key.referenced_column);
+        buf.push(foreign_key_action_tag(&foreign_key.on_delete));
+        buf.push(foreign_key_action_tag(&foreign_key.on_update));
+    }
+    put_optional_str(buf, column.check.as_deref());
+    put_flag(buf, column.generated.is_some());
+    if let Some(generated) = &column.generated {
+        put_str(buf, &generated.expr_sql);
+        put_flag(buf, generated.always);
+    }
+}
+
+fn read_column(reader: &mut RecordReader<'_>) -> Result<ColumnDefinition, RustqlError> {
+    let name = reader.string()?;
+    let data_type = read_data_type(reader)?;
+    let flags = reader.byte()?;
+    let default_value = reader.flag()?.then(|| reader.value()).transpose()?;
+    let foreign_key = if reader.flag()? {
+        Some(ForeignKeyConstraint {
+            referenced_table: reader.string()?,
+            referenced_column: reader.string()?,
+            on_delete: foreign_key_action_from_tag(reader.byte()?)?,
+            on_update: foreign_key_action_from_tag(reader.byte()?)?,
+            deferrable: deferrable_from_bits(flags),
+        })
+    } else {
+        None
+    };
+    let check = reader.optional_string()?;
+    let generated = if reader.flag()? {
+        Some(GeneratedColumn {
+            expr_sql: reader.string()?,
+            always: reader.flag()?,
+        })
+    } else {
+        None
+    };
+    Ok(ColumnDefinition {
+        name,
+        data_type,
+        nullable: flags & COLUMN_NULLABLE != 0,
+        primary_key: flags & COLUMN_PRIMARY_KEY != 0,
+        unique: flags & COLUMN_UNIQUE != 0,
+        default_value,
+        foreign_key,
+        check,
+        auto_increment: flags & COLUMN_AUTO_INCREMENT != 0,
+        generated,
+    })
+}
+
+fn deferrable_bits(deferrable: Deferrable) -> u8 {
+    match deferrable {
+        Deferrable::No => 0,
+        Deferrable::InitiallyImmediate => DEFERRABLE,
+        Deferrable::InitiallyDeferred => DEFERRABLE | INITIALLY_DEFERRED,
+    }
+}
+
+fn deferrable_from_bits(bits: u8) -> Deferrable {
+    if bits & INITIALLY_DEFERRED != 0 {
+        Deferrable::InitiallyDeferred
+    } else if bits & DEFERRABLE != 0 {
+        Deferrable::InitiallyImmediate
+    } else {
+        Deferrable::No
+    }
+}
+
+/// Writes a type tag, followed for `DECIMAL` by its precision and scale.
+fn put_data_type(buf: &mut Vec<u8>, data_type: &DataType) {
+    buf.push(match data_type {
+        DataType::Integer => 1,
+        DataType::Float => 2,
+        DataType::Text => 3,
+        DataType::Boolean => 4,
+        DataType::Date => 5,
+        DataType::Time => 6,
+        DataType::DateTime => 7,
+        DataType::Decimal { .. } => 8,
+        DataType::Blob => 9,
+        DataType::Json => 10,
+        DataType::Interval => 11,
+        DataType::TimestampTz => 12,
+    });
+    if let DataType::Decimal { precision, scale } = data_type {
+        put_varint(buf, u64::from(*precision));
+        put_varint(buf, u64::from(*scale));
+    }
+}
+
+fn read_data_type(reader: &mut RecordReader<'_>) -> Result<DataType, RustqlError> {
+    Ok(match reader.byte()? {
+        1 => DataType::Integer,
+        2 => DataType::Float,
+        3 => DataType::Text,
+        4 => DataType::Boolean,
+        5 => DataType::Date,
+        6 => DataType::Time,
+        7 => DataType::DateTime,
+        8 => DataType::Decimal {
+            precision: reader.varint()? as u32,
+            scale: reader.varint()? as u32,
+        },
+        9 => DataType::Blob,
+        10 => DataType::Json,
+        11 => DataType::Interval,
+        12 => DataType::TimestampTz,
+        other => {
+            return Err(RustqlError::StorageError(format!(
+                "unknown data type {}",
+                other
+            )));
+        }
+    })
+}
+
+fn foreign_key_action_tag(action: &ForeignKeyAction) -> u8 {
+    match action {
+        ForeignKeyAction::Restrict => 0,
+        ForeignKeyAction::Cascade => 1,
+        ForeignKeyAction::SetNull => 2,
+        ForeignKeyAction::NoAction => 3,
+    }
+}
+
+fn foreign_key_action_from_tag(tag: u8) -> Result<ForeignKeyAction, RustqlError> {
+    Ok(match tag {
+        0 => ForeignKeyAction::Restrict,
+        1 => ForeignKeyAction::Cascade,
+        2 => ForeignKeyAction::SetNull,
+        3 => ForeignKeyAction::NoAction,
+        other => {
+            return Err(RustqlError::StorageError(format!(
+                "unknown foreign key action {}",
+                other
+            )));
+        }
+    })
+}
+
+/// Partial index filters are expression trees, which have no binary form;
+/// they are kept as JSON text inside the binary definition.
+fn put_filter(
+    buf: &mut Vec<u8>,
+    filter: Option<&crate::ast::Expression>,
+) -> Result<(), RustqlError> {
+    let filter = filter.map(serde_json::to_string).transpose().map_err(|e| {
+        RustqlError::StorageError(format!("Failed to serialize index filter: {}", e))
+    })?;
+    put_optional_str(buf, filter.as_deref());
+    Ok(())
+}
+
+fn read_filter(
+    reader: &mut RecordReader<'_>,
+) -> Result<Option<crate::ast::Expression>, RustqlError> {
+    reader
+        .optional_string()?
+        .map(|filter| {
+            serde_json::from_str(&filter)
+                .map_err(|e| RustqlError::StorageError(format!("invalid index filter: {}", e)))
+        })
+        .transpose()
+}
+
+impl EncodeRecord for Index {
+    const KIND: u8 = KIND_INDEX;
+
+    /// Writes the definition only; entries are stored as records of their own.
+    fn encode_fields(&self, buf: &mut Vec<u8>) -> Result<(), RustqlError> {
+        put_str(buf, &self.name);
+        put_str(buf, &self.table);
+        put_str(buf, &self.column);
+        put_filter(buf, self.filter_expr.as_ref())
+    }
+}
+
+impl DecodeRecord for Index {
+    fn reads_kind(kind: u8) -> bool {
+        kind == KIND_INDEX
+    }
+
+    fn decode_fields(reader: &mut RecordReader<'_>) -> Result<Self, RustqlError> {
+        Ok(Index {
+            name: reader.string()?,
+            table: reader.string()?,
+            column: reader.string()?,
+            entries: SharedMap::default(),
+            filter_expr: read_filter(reader)?,
+            paged: None,
+        })
+    }
+}
+
+impl EncodeRecord for CompositeIndex {
+    const KIND: u8 = KIND_COMPOSITE_INDEX;
+
+    fn encode_fields(&self, buf: &mut Vec<u8>) -> Result<(), RustqlError> {
+        put_str(buf, &self.name);
+        put_str(buf, &self.table);
+        put_strings(buf, &self.columns);
+        put_filter(buf, self.filter_expr.as_ref())
+    }
+}
+
+impl DecodeRecord for CompositeIndex {
+    fn reads_kind(kind: u8) -> bool {
+        kind == KIND_COMPOSITE_INDEX
+    }
+
+    fn decode_fields(reader: &mut RecordReader<'_>) -> Result<Self, RustqlError> {
+        Ok(CompositeIndex {
+            name: reader.string()?,
+            table: reader.string()?,
+            columns: reader.strings()?,
+            entries: SharedMap::default(),
+            filter_expr: read_filter(reader)?,
+            paged: None,
+        })
+    }
+}
+
+impl DecodeRecord for IndexTableRecord {
+    fn reads_kind(kind: u8) -> bool {
+        kind == KIND_INDEX || kind == KIND_COMPOSITE_INDEX
+    }
+
+    /// Reads the name and table that start both kinds of index definition.
+    fn decode_fields(reader: &mut RecordReader<'_>) -> Result<Self, RustqlError> {
+        reader.string()?;
+        let table = reader.string()?;
+        reader.offset = reader.data.len();
+        Ok(IndexTableRecord { table })
+    }
+}
+
+impl EncodeRecord for View {
+    const KIND: u8 = KIND_VIEW;
+
+    fn encode_fields(&self, buf: &mut Vec<u8>) -> Result<(), RustqlError> {
+        put_str(buf, &self.name);
+        put_str(buf, &self.query_sql);
+        Ok(())
+    }
+}
+
+impl DecodeRecord for View {
+    fn reads_kind(kind: u8) -> bool {
+        kind == KIND_VIEW
+    }
+
+    fn decode_fields(reader: &mut RecordReader<'_>) -> Result<Self, RustqlError> {
+        Ok(View {
+            name: reader.string()?,
+            query_sql: reader.string()?,
+        })
+    }
+}
+
+/// A prepared transaction keeps its identifier and time in binary; what it
+/// wrote is JSON text, as it holds whole tables, indexes, and views.
+impl EncodeRecord for PreparedTransaction {
+    const KIND: u8 = KIND_PREPARED;
+
+    fn encode_fields(&self, buf: &mut Vec<u8>) -> Result<(), RustqlError> {
+        put_str(buf, &self.gid);
+        put_str(buf, &self.prepared_at);
+        let contents = serde_json::to_string(&(&self.writes, &self.changes, &self.row_changes))
+            .map_err(|e| RustqlError::StorageError(e.to_string()))?;
+        put_str(buf, &contents);
+        Ok(())
+    }
+}
+
+impl DecodeRecord for PreparedTransaction {
+    fn reads_kind(kind: u8) -> bool {
+        kind == KIND_PREPARED
+    }
+
+    fn decode_fields(reader: &mut RecordReader<'_>) -> Result<Self, RustqlError> {
+        let gid = reader.string()?;
+        let prepared_at = reader.string()?;
+        let (writes, mut changes, row_changes): (_, crate::database::Database, _) =
+            serde_json::from_str(&reader.string()?)
+                .map_err(|e| RustqlError::StorageError(e.to_string()))?;
+        changes.normalize_row_ids();
+        Ok(PreparedTransaction {
+            gid,
+            prepared_at,
+            writes,
+            changes,
+            row_changes,
+        })
+    }
+}
+
+impl EncodeRecord for IndexEntryRecord {
+    const KIND: u8 = KIND_INDEX_ENTRY;
+
+    fn encode_fields(&self, buf: &mut Vec<u8>) -> Result<(), RustqlError> {
+        put_str(buf, &self.index);
+        put_varint(buf, self.row_id.0);
+        Ok(())
+    }
+}
+
+impl DecodeRecord for IndexEntryRecord {
+    fn reads_kind(kind: u8) -> bool {
+        kind == KIND_INDEX_ENTRY
+    }
+
+    fn decode_fields(reader: &mut RecordReader<'_>) -> Result<Self, RustqlError> {
+        Ok(IndexEntryRecord {
+            index: reader.string()?,
+            row_id: RowId(reader.varint()?),
+        })
+    }
+}
@@ -1,20 +1,29 @@
 use super::BTreeStorageEngine;
+use super::cipher::{PageCipher, page_image};
+use super::encoding::{DecodeRecord, decode_record};
 use super::header::{
-    FILE_HEADER_SIZE, VersionedFileState, read_versioned_header_with_versions,
-    write_versioned_header,
+    FILE_HEADER_SIZE, HEADER_ENCRYPTED_FLAG, HEADER_RESERVED, VersionedFileState,
+    read_versioned_header_with_versions, write_versioned_header_with_flags,
 };
+use super::overflow::load_payload;
 use super::page::{BTREE_PAGE_SIZE, BTreeEntry, BTreePage, LEAF_INLINE_DATA_FLAG, PageKind};
+use super::paging::BTreeRowSource;
 use super::records::{
-    TableStorageRecord, format_row_storage_key, insert_loaded_row, parse_row_storage_key,
+    COMPOSITE_INDEX_ENTRY_KEY_PREFIX, COMPOSITE_INDEX_KEY_PREFIX, INDEX_ENTRY_KEY_PREFIX,
+    INDEX_KEY_PREFIX, IndexEntryRecord, PREPARED_KEY_PREFIX, ROW_KEY_PREFIX, SCHEMA_KEY_PREFIX,
+    TableStorageRecord, VIEW_KEY_PREFIX, insert_loaded_row, parse_composite_index_key_text,
+    parse_index_entry_key, parse_index_key_text, parse_row_storage_key,
 };
+use super::tree::{META_NEXT_PAGE_KEY, META_ROOT_KEY, PageStore, scan_pages_in_order_entries};
+use super::writer::RecordWriter;
 use crate::ast::Value;
-use crate::database::{Database, RowId};
+use crate::database::{Database, PreparedTransaction, RowId, RowSource, Table};
 use crate::error::RustqlError;
-use serde::de::DeserializeOwned;
 use std::collections::HashMap;
 use std::fs;
 use std::io::{Read, Seek, SeekFrom, Write};
 use std::path::Path;
+use std::sync::Arc;
 
 pub(super) struct CachedBTreeFile<'a> {
     pub(super) engine: &'a BTreeStorageEngine,
@@ -25,16 +34,19 @@ impl<'a> CachedBTreeFile<'a> {
         self.engine.read_page_cached(page_id)
     }
 
+    /// Reads the database catalog. Tables in current-version files whose
+    /// schema records a row count are returned paged: their rows stay on disk
+    /// and are read through the page cache when a query scans them.
     pub(super) fn read_database_via_pages(&mut self) -> Result<Database, RustqlError> {
-        match read_versioned_header_with_versions(
+        let version = match read_versioned_header_with_versions(
             &self.engine.data_path,
             BTreeFile::MAGIC,
-            &[2, BTreeFile::VERSION],
+            &BTreeFile::SUPPORTED_VERSIONS,
             "BTree storage file",
         )? {
             VersionedFileState::Missing | VersionedFileState::Empty => return Ok(Database::new()),
-            VersionedFileState::Valid { .. } => {}
-        }
+            VersionedFileState::Valid { version } => version,
+        };
 
         let file_size = fs::metadata(&self.engine.data_path)
             .map_err(|e| RustqlError::StorageError(format!("Failed to get file metadata: {}", e)))?
@@ -44,39 +56,82 @@ impl<'a> CachedBTreeFile<'a> {
             return Ok(Database::new());
         }
 
-        let meta_page = match self.read_page(0) {
-            Ok(page) => page,
-            Err(_) => return Ok(Database::new()),
-        };
+        let meta_page = self.read_page(0)?;
         let root_page_id = meta_page
             .entries
             .iter()
-            .find(|e| {
-                if let Value::Text(ref s) = e.key {
-                    s == "root"
-                } else {
-                    false
-                }
-            })
+            .find(|e| matches!(&e.key, Value::Text(key) if key == META_ROOT_KEY))
             .map(|e| e.pointer)
             .unwrap_or(1);
 
-        self.load_database_from_rows(root_page_id)
+        let row_source = (version == BTreeFile::VERSION)
+            .then(|| Arc::new(BTreeRowSource::new(self.engine)) as Arc<dyn RowSource>);
+        self.load_database_from_rows(root_page_id, row_source)
     }
 
-    fn load_database_from_rows(&self, root_page_id: u64) -> Result<Database, RustqlError> {
+    fn load_database_from_rows(
+        &self,
+        root_page_id: u64,
+        row_source: Option<Arc<dyn RowSource>>,
+    ) -> Result<Database, RustqlError> {
         let mut db = Database::new();
         let mut pending_rows: HashMap<String, Vec<(RowId, Vec<Value>)>> = HashMap::new();
+        let mut index_entries: Vec<(String, Value, RowId)> = Vec::new();
+        let mut composite_index_entries: Vec<(String, Vec<Value>, RowId)> = Vec::new();
+        let mut unpaged_tables = Vec::new();
+
+        // With a row source, skip the row and index entry records entirely
+        // and read only the catalog around them. The prefixes are listed in
+        // key order.
+        let entries = match &row_source {
+            Some(_) => {
+                let mut entries = Vec::new();
+                let mut start = None;
+                for prefix in [
+                    COMPOSITE_INDEX_ENTRY_KEY_PREFIX,
+                    INDEX_ENTRY_KEY_PREFIX,
+                    ROW_KEY_PREFIX,
+                ] {
+                    let end = Value::Text(prefix.to_string());
+                    entries.extend(self.range_scan_entries(
+                        start.as_ref(),
+                        Some(&end),
+                        root_page_id,
+                    )?);
+                    start = Some(Value::Text(format!("{prefix}{}", char::MAX)));
+                }
+                entries.extend(self.range_scan_entries(start.as_ref(), None, root_page_id)?);
+                entries
+            }
+            None => self.range_scan_entries(None, None, root_page_id)?,
+        };
 
-        for entry in self.range_scan_entries(None, None, root_page_id)? {
+        for entry in entries {
             let Value::Text(key_str) = &entry.key else {
                 continue;
             };
 
-            if let Some(table_name) = key_str.strip_prefix("schema:") {
+            if let Some(table_name) = key_str.strip_prefix(SCHEMA_KEY_PREFIX) {
                 let schema: TableStorageRecord =
                     self.read_data_from_entry(&entry, format!("schema for table {}", table_name))?;
 
+                if let (Some(source), Some(row_count)) = (&row_source, schema.row_count) {
+                    db.tables.insert(
+                        table_name.to_string(),
+                        Table::paged(
+                            schema.columns,
+                            schema.next_row_id,
+                            schema.constraints,
+                            Arc::clone(source),
+                            table_name,
+                            row_count as usize,
+                        ),
+                    );
+                    continue;
+                }
+                if row_source.is_some() {
+                    unpaged_tables.push(table_name.to_string());
+                }
                 db.tables.insert(
                     table_name.to_string(),
                     crate::database::Table::with_rows_and_ids(
@@ -98,21 +153,45 @@ impl<'a> CachedBTreeFile<'a> {
                 continue;
             }
 
-            if let Some(index_name) = key_str.strip_prefix("index:") {
+            if key_str.starts_with(INDEX_ENTRY_KEY_PREFIX) {
+                let (index, key, row_id) =
+                    self.read_index_entry(&entry, INDEX_ENTRY_KEY_PREFIX, parse_index_key_text)?;
+                index_entries.push((index, key, row_id));
+                continue;
+            }
+
+            if key_str.starts_with(COMPOSITE_INDEX_ENTRY_KEY_PREFIX) {
+                let (index, key, row_id) = self.read_index_entry(
+                    &entry,
+                    COMPOSITE_INDEX_ENTRY_KEY_PREFIX,
+                    parse_composite_index_key_text,
+                )?;
+                composite_index_entries.push((index, key, row_id));
+                continue;
+            }
+
+            if let Some(index_name) = key_str.strip_prefix(INDEX_KEY_PREFIX) {
                 let index: crate::database::Index =
                     self.read_data_from_entry(&entry, format!("index {}", index_name))?;
                 db.indexes.insert(index_name.to_string(), index);
                 continue;
             }
 
-            if let Some(index_name) = key_str.strip_prefix("cindex:") {
+            if let Some(index_name) = key_str.strip_prefix(COMPOSITE_INDEX_KEY_PREFIX) {
                 let index: crate::database::CompositeIndex =
                     self.read_data_from_entry(&entry, format!("composite index {}", index_name))?;
                 db.composite_indexes.insert(index_name.to_string(), index);
                 continue;
             }
 
-            if let Some(view_name) = key_str.strip_prefix("view:") {
+            if let Some(gid) = key_str.strip_prefix(PREPARED_KEY_PREFIX) {
+                let prepared: PreparedTransaction =
+                    self.read_data_from_entry(&entry, format!("prepared transaction {}", gid))?;
+                db.insert_prepared_transaction(prepared);
+                continue;
+            }
+
+            if let Some(view_name) = key_str.strip_prefix(VIEW_KEY_PREFIX) {
                 let view: crate::database::View =
                     self.read_data_from_entry(&entry, format!("view {}", view_name))?;
                 db.views.insert(view_name.to_string(), view);
@@ -136,6 +215,32 @@ impl<'a> CachedBTreeFile<'a> {
             }
         }
 
+        for table_name in unpaged_tables {
+            let prefix = format!("{ROW_KEY_PREFIX}{table_name}:");
+            let end = Value::Text(format!("{prefix}{}", char::MAX));
+            for entry in self.range_scan_entries(
+                Some(&Value::Text(prefix.clone())),
+                Some(&end),
+                root_page_id,
+            )? {
+                let Value::Text(key_str) = &entry.key else {
+                    continue;
+                };
+                if let Some((name, row_id, _)) = parse_row_storage_key(key_str)
+                    && name == table_name
+                {
+                    let row = self.read_data_from_entry(
+                        &entry,
+                        format!("row {} for table {}", row_id.0, table_name),
+                    )?;
+                    pending_rows
+                        .entry(table_name.clone())
+                        .or_default()
+                        .push((row_id, row));
+                }
+            }
+        }
+
         for (table_name, mut row_indices) in pending_rows {
             row_indices.sort_by_key(|(row_id, _)| *row_id);
             if let Some(table_ref) = db.tables.get_mut(&table_name) {
@@ -145,23 +250,99 @@ impl<'a> CachedBTreeFile<'a> {
             }
         }
 
+        if row_source.is_some() {
+            // The entries of an index on a paged table stay on disk with its
+            // rows. Other indexes are read in full.
+            let is_paged = |table: &str| db.tables.get(table).is_some_and(Table::is_paged);
+            let mut unpaged_indexes = Vec::new();
+            for index in db.indexes.values_mut() {
+                if is_paged(&index.table) {
+                    index.paged = Some(index.name.clone());
+                } else {
+                    unpaged_indexes.push((INDEX_ENTRY_KEY_PREFIX, index.name.clone()));
+                }
+            }
+            for index in db.composite_indexes.values_mut() {
+                if is_paged(&index.table) {
+                    index.paged = Some(index.name.clone());
+                } else {
+                    unpaged_indexes.push((COMPOSITE_INDEX_ENTRY_KEY_PREFIX, index.name.clone()));
+                }
+            }
+            for (prefix, index_name) in unpaged_indexes {
+                let start = format!("{prefix}{index_name}:");
+                let end = Value::Text(format!("{start}{}", char::MAX));
+                for entry in
+                    self.range_scan_entries(Some(&Value::Text(start)), Some(&end), root_page_id)?
+                {
+                    if prefix == INDEX_ENTRY_KEY_PREFIX {
+                        let entry = self.read_index_entry(&entry, prefix, parse_index_key_text)?;
+                        if entry.0 == index_name {
+                            index_entries.push(entry);
+                        }
+                    } else {
+                        let entry =
+                            self.read_index_entry(&entry, prefix, parse_composite_index_key_text)?;
+                        if entry.0 == index_name {
+                            composite_index_entries.push(entry);
+                        }
+                    }
+                }
+            }
+        }
+
+        for (index_name, key, row_id) in index_entries {
+            if let Some(index) = db.indexes.get_mut(&index_name) {
+                index.entries.entry(key).or_default().push(row_id);
+            }
+        }
+        for (index_name, key, row_id) in composite_index_entries {
+            if let Some(index) = db.composite_indexes.get_mut(&index_name) {
+                index.entries.entry(key).or_default().push(row_id);
+            }
+        }
+
         db.normalize_row_ids();
         Ok(db)
     }
 
-    fn read_data_from_entry<T>(
+    fn read_index_entry<K>(
+        &self,
+        entry: &BTreeEntry,
+        prefix: &str,
+        parse_key: impl FnOnce(&str) -> Option<K>,
+    ) -> Result<(String, K, RowId), RustqlError> {
+        let record: IndexEntryRecord = self.read_data_from_entry(entry, "index entry")?;
+        let key_text = match &entry.key {
+            Value::Text(key) => parse_index_entry_key(key, prefix, &record.index),
+            _ => None,
+        }
+        .ok_or_else(|| {
+            RustqlError::StorageError(format!("Malformed entry key for index {}", record.index))
+        })?;
+        let key = parse_key(key_text).ok_or_else(|| {
+            RustqlError::StorageError(format!(
+                "Failed to decode key of index {}: {}",
+                record.index, key_text
+            ))
+        })?;
+        Ok((record.index, key, record.row_id))
+    }
+
+    /// Reads a record kept in a leaf, on overflow pages, or on the data page
+    /// a version 2 or 3 leaf points at.
+    pub(super) fn read_data_from_entry<T>(
         &self,
         entry: &BTreeEntry,
         label: impl Into<String>,
     ) -> Result<T, RustqlError>
     where
-        T: DeserializeOwned,
+        T: DecodeRecord,
     {
         let label = label.into();
         if let Some(inline_data) = entry.inline_data.as_deref() {
-            return serde_json::from_str(inline_data).map_err(|e| {
-                RustqlError::StorageError(format!("Failed to deserialize {}: {}", label, e))
-            });
+            let payload = load_payload(|page_id| self.read_page(page_id), inline_data)?;
+            return decode_record(&payload, &label);
         }
         self.read_data_from_pointer(entry.pointer, label)
     }
@@ -172,7 +353,7 @@ impl<'a> CachedBTreeFile<'a> {
         label: impl Into<String>,
     ) -> Result<T, RustqlError>
     where
-        T: DeserializeOwned,
+        T: DecodeRecord,
     {
         let label = label.into();
         let data_page = self.read_page(pointer)?;
@@ -180,9 +361,7 @@ impl<'a> CachedBTreeFile<'a> {
         if let Some(entry) = data_page.entries.first()
             && let Value::Text(ref json_str) = entry.key
         {
-            return serde_json::from_str(json_str).map_err(|e| {
-                RustqlError::StorageError(format!("Failed to deserialize {}: {}", label, e))
-            });
+            return decode_record(json_str.as_bytes(), &label);
         }
 
         Err(RustqlError::StorageError(format!(
@@ -208,11 +387,16 @@ impl<'a> CachedBTreeFile<'a> {
 
 pub(super) struct BTreeFile {
     pub(super) file: std::fs::File,
+    /// Seals pages on write and opens them on read in encrypted files.
+    cipher: Option<PageCipher>,
 }
 
 impl BTreeFile {
     pub(super) const MAGIC: [u8; 8] = *b"RSTQLBT\0";
-    pub(super) const VERSION: u32 = 3;
+    pub(super) const VERSION: u32 = 5;
+    /// Versions 2 and 3 store each index as one record holding all entries,
+    /// and versions 2 to 4 store records as JSON text.
+    pub(super) const SUPPORTED_VERSIONS: [u32; 4] = [2, 3, 4, Self::VERSION];
 
     pub(super) fn open(path: &Path) -> Result<Self, RustqlError> {
         let file = std::fs::OpenOptions::new()
@@ -224,7 +408,7 @@ impl BTreeFile {
             .map_err(|e| {
                 RustqlError::StorageError(format!("Failed to open BTree storage file: {}", e))
             })?;
-        Ok(BTreeFile { file })
+        Ok(BTreeFile { file, cipher: None })
     }
 
     pub(super) fn create(path: &Path) -> Result<Self, RustqlError> {
@@ -237,7 +421,12 @@ impl BTreeFile {
             .map_err(|e| {
                 RustqlError::StorageError(format!("Failed to create BTree storage file: {}", e))
             })?;
-        Ok(BTreeFile { file })
+        Ok(BTreeFile { file, cipher: None })
+    }
+
+    pub(super) fn with_cipher(mut self, cipher: Option<PageCipher>) -> Self {
+        self.cipher = cipher;
+        self
     }
 
     pub(super) fn sync_all(&self) -> Result<(), RustqlError> {
@@ -248,7 +437,7 @@ impl BTreeFile {
 
     #[cfg(test)]
     pub(super) fn write_data_to_pointer(&mut self, data: &str) -> Result<u64, RustqlError> {
-        let page_id = self.get_next_page_id()?;
+        let page_id = self.allocate_page()?;
         let mut data_page = BTreePage::new(page_id, PageKind::Leaf);
 
         data_page.push_entry(BTreeEntry::new(Value::Text(data.to_string()), 0));
@@ -259,111 +448,32 @@ impl BTreeFile {
 
     pub(super) fn write_database_via_pages(&mut self, db: &Database) -> Result<(), RustqlError> {
         // This serializes the full logical database into a new B-tree image.
-        // It uses page primitives internally, but callers should treat it as a
-        // snapshot writer rather than an incremental mutation path.
-        write_versioned_header(
+        // Incremental saves go through `RecordWriter::apply_changes` instead.
+        let flags = match self.cipher {
+            Some(_) => HEADER_ENCRYPTED_FLAG,
+            None => HEADER_RESERVED,
+        };
+        write_versioned_header_with_flags(
             &mut self.file,
             Self::MAGIC,
             Self::VERSION,
+            flags,
             "BTree storage file",
         )?;
 
         let mut meta_page = BTreePage::new(0, PageKind::Meta);
-        meta_page.push_entry(BTreeEntry::new(Value::Text("root".to_string()), 1));
-        meta_page.push_entry(BTreeEntry::new(Value::Text("next_page_id".to_string()), 2));
+        meta_page.push_entry(BTreeEntry::new(Value::Text(META_ROOT_KEY.to_string()), 1));
+        meta_page.push_entry(BTreeEntry::new(
+            Value::Text(META_NEXT_PAGE_KEY.to_string()),
+            2,
+        ));
         self.write_page(&meta_page)?;
 
-        let root_page_id = 1;
-        let mut root_page = BTreePage::new(root_page_id, PageKind::Leaf);
+        let mut root_page = BTreePage::new(1, PageKind::Leaf);
         root_page.header.reserved = LEAF_INLINE_DATA_FLAG;
         self.write_page(&root_page)?;
 
-        let mut current_root_id = root_page_id;
-
-        for (table_name, table) in &db.tables {
-            let schema_key = Value::Text(format!("schema:{}", table_name));
-            let schema_json = serde_json::to_string(&TableStorageRecord {
-                columns: table.columns.clone(),
-                constraints: table.constraints.clone(),
-                next_row_id: table.next_row_id,
-            })
-            .map_err(|e| {
-                RustqlError::StorageError(format!(
-                    "Failed to serialize schema for table {}: {}",
-                    table_name, e
-                ))
-            })?;
-            current_root_id = self.insert_entry(
-                BTreeEntry::with_inline_data(schema_key, schema_json),
-                current_root_id,
-            )?;
-        }
-
-        for (table_name, table) in &db.tables {
-            for (row_id, row) in table.iter_rows_with_ids() {
-                let row_key = Value::Text(format_row_storage_key(table_name, row_id));
-                let row_json = serde_json::to_string(row).map_err(|e| {
-                    RustqlError::StorageError(format!(
-                        "Failed to serialize row {} for table {}: {}",
-                        row_id.0, table_name, e
-                    ))
-                })?;
-                current_root_id = self.insert_entry(
-                    BTreeEntry::with_inline_data(row_key, row_json),
-                    current_root_id,
-                )?;
-            }
-        }
-
-        for (index_name, index) in &db.indexes {
-            let index_key = Value::Text(format!("index:{}", index_name));
-            let index_json = serde_json::to_string(index).map_err(|e| {
-                RustqlError::StorageError(format!(
-                    "Failed to serialize index {}: {}",
-                    index_name, e
-                ))
-            })?;
-            current_root_id = self.insert_entry(
-                BTreeEntry::with_inline_data(index_key, index_json),
-                current_root_id,
-            )?;
-        }
-
-        for (index_name, index) in &db.composite_indexes {
-            let index_key = Value::Text(format!("cindex:{}", index_name));
-            let index_json = serde_json::to_string(index).map_err(|e| {
-                RustqlError::StorageError(format!(
-                    "Failed to serialize composite index {}: {}",
-                    index_name, e
-                ))
-            })?;
-            current_root_id = self.insert_entry(
-                BTreeEntry::with_inline_data(index_key, index_json),
-                current_root_id,
-            )?;
-        }
-
-        for (view_name, view) in &db.views {
-            let view_key = Value::Text(format!("view:{}", view_name));
-            let view_json = serde_json::to_string(view).map_err(|e| {
-                RustqlError::StorageError(format!("Failed to serialize view {}: {}", view_name, e))
-            })?;
-            current_root_id = self.insert_entry(
-                BTreeEntry::with_inline_data(view_key, view_json),
-                current_root_id,
-            )?;
-        }
-
-        let mut meta_page = self.read_page(0)?;
-        if let Some(root_entry) = meta_page
-            .entries
-            .iter_mut()
-            .find(|e| matches!(&e.key, Value::Text(value) if value == "root"))
-        {
-            root_entry.pointer = current_root_id;
-        }
-        meta_page.refresh_entry_count();
-        self.write_page(&meta_page)?;
+        RecordWriter::new(self)?.write_database(db)?;
 
         self.file.flush().map_err(|e| {
             RustqlError::StorageError(format!("Failed to flush BTree storage file: {}", e))
@@ -384,13 +494,22 @@ impl BTreeFile {
             .read_exact(&mut buf)
             .map_err(|e| RustqlError::StorageError(format!("Failed to read BTree page: {}", e)))?;
 
+        if let Some(cipher) = &self.cipher {
+            return cipher.open_page(page_id, &buf);
+        }
+        if !BTreePage::checksum_matches(&buf) {
+            return Err(RustqlError::StorageError(format!(
+                "BTree page {} checksum mismatch",
+                page_id
+            )));
+        }
         BTreePage::from_bytes(&buf)
     }
 
     pub(super) fn write_page(&mut self, page: &BTreePage) -> Result<(), RustqlError> {
         let offset = FILE_HEADER_SIZE as u64 + page.header.page_id * BTREE_PAGE_SIZE as u64;
 
-        let buf = page.to_bytes()?;
+        let buf = page_image(page, self.cipher.as_ref())?;
 
         self.file.seek(SeekFrom::Start(offset)).map_err(|e| {
             RustqlError::StorageError(format!("Failed to seek to BTree page for write: {}", e))
@@ -405,128 +524,13 @@ impl BTreeFile {
             .map_err(|e| RustqlError::StorageError(format!("Failed to flush BTree page: {}", e)))
     }
 
-    fn get_next_page_id(&mut self) -> Result<u64, RustqlError> {
-        let meta_page = self.read_page(0)?;
-
-        for entry in &meta_page.entries {
-            if let Value::Text(ref s) = entry.key
-                && s == "next_page_id"
-            {
-                let next_id = entry.pointer;
-                let mut new_meta = meta_page.clone();
-                if let Some(e) = new_meta.entries.iter_mut().find(|e| {
-                    if let Value::Text(ref s) = e.key {
-                        s == "next_page_id"
-                    } else {
-                        false
-                    }
-                }) {
-                    e.pointer = next_id + 1;
-                } else {
-                    new_meta.push_entry(BTreeEntry::new(
-                        Value::Text("next_page_id".to_string()),
-                        next_id + 1,
-                    ));
-                }
-                new_meta.refresh_entry_count();
-                self.write_page(&new_meta)?;
-                return Ok(next_id);
-            }
-        }
-
-        let next_id = 2;
-        let mut new_meta = meta_page;
-        new_meta.push_entry(BTreeEntry::new(
-            Value::Text("next_page_id".to_string()),
-            next_id + 1,
-        ));
-        self.write_page(&new_meta)?;
-        Ok(next_id)
-    }
-
     #[cfg(test)]
     pub fn search(
         &mut self,
         key: &Value,
         root_page_id: u64,
     ) -> Result<Option<(u64, usize)>, RustqlError> {
-        let mut current_page_id = root_page_id;
-
-        loop {
-            let page = self.read_page(current_page_id)?;
-
-            match page.header.kind {
-                PageKind::Leaf => {
-                    for (idx, entry) in page.entries.iter().enumerate() {
-                        if &entry.key == key {
-                            return Ok(Some((current_page_id, idx)));
-                        }
-                    }
-                    return Ok(None);
-                }
-                PageKind::Internal => {
-                    let mut found = false;
-                    for entry in &page.entries {
-                        if &entry.key > key {
-                            current_page_id = entry.pointer;
-                            found = true;
-                            break;
-                        }
-                    }
-                    if !found {
-                        if let Some(last_entry) = page.entries.last() {
-                            current_page_id = last_entry.pointer;
-                        } else {
-                            return Ok(None);
-                        }
-                    }
-                }
-                PageKind::Meta => {
-                    return Err(RustqlError::StorageError(
-                        "Cannot search in meta page".to_string(),
-                    ));
-                }
-            }
-        }
-    }
-
-    fn find_path_to_leaf(
-        &mut self,
-        key: &Value,
-        root_page_id: u64,
-    ) -> Result<Vec<u64>, RustqlError> {
-        let mut path = Vec::new();
-        let mut current_page_id = root_page_id;
-
-        loop {
-            path.push(current_page_id);
-            let page = self.read_page(current_page_id)?;
-
-            match page.header.kind {
-                PageKind::Leaf => {
-                    return Ok(path);
-                }
-                PageKind::Internal => {
-                    let mut next_page_id = None;
-                    for entry in &page.entries {
-                        if &entry.key > key {
-                            next_page_id = Some(entry.pointer);
-                            break;
-                        }
-                    }
-                    current_page_id = next_page_id
-                        .or_else(|| page.entries.last().map(|e| e.pointer))
-                        .ok_or_else(|| {
-                            RustqlError::StorageError("Invalid internal page structure".to_string())
-                        })?;
-                }
-                PageKind::Meta => {
-                    return Err(RustqlError::StorageError(
-                        "Cannot traverse meta page for insert".to_string(),
-                    ));
-                }
-            }
-        }
+        PageStore::search(self, key, root_page_id)
     }
 
     #[cfg(test)]
@@ -537,130 +541,13 @@ impl BTreeFile {
         root_page_id: u64,
     ) -> Result<u64, RustqlError> {
         self.insert_entry(BTreeEntry::new(key, data_pointer), root_page_id)
-    }
-
-    fn insert_entry(
-        &mut self,
-        new_entry: BTreeEntry,
-        root_page_id: u64,
-    ) -> Result<u64, RustqlError> {
-        let path = self.find_path_to_leaf(&new_entry.key, root_page_id)?;
-        let leaf_page_id = path.last().copied().ok_or_else(|| {
-            RustqlError::StorageError("BTree insert could not locate a leaf page".to_string())
-        })?;
-        let mut leaf_page = self.read_page(leaf_page_id)?;
-
-        for (idx, entry) in leaf_page.entries.iter().enumerate() {
-            if entry.key == new_entry.key {
-                leaf_page.entries[idx] = new_entry;
-                self.write_page(&leaf_page)?;
-                return Ok(root_page_id);
-            }
-        }
-
-        if leaf_page.can_accept_entry(&new_entry) {
-            let insert_pos = leaf_page
-                .entries
-                .binary_search_by(|e| e.key.cmp(&new_entry.key))
-                .unwrap_or_else(|pos| pos);
-            leaf_page.insert_entry(insert_pos, new_entry);
-            self.write_page(&leaf_page)?;
-            Ok(root_page_id)
-        } else {
-            self.split_and_insert_recursive(leaf_page, new_entry, path, root_page_id)
-        }
-    }
-
-    fn split_and_insert_recursive(
-        &mut self,
-        mut page: BTreePage,
-        new_entry: BTreeEntry,
-        mut path: Vec<u64>,
-        root_page_id: u64,
-    ) -> Result<u64, RustqlError> {
-        let insert_pos = page
-            .entries
-            .binary_search_by(|e| e.key.cmp(&new_entry.key))
-            .unwrap_or_else(|pos| pos);
-        page.entries.insert(insert_pos, new_entry);
-
-        let mid = page.entries.len() / 2;
-        let right_entries = page.entries.split_off(mid);
-        let split_key = right_entries[0].key.clone();
-
-        page.refresh_entry_count();
-        self.write_page(&page)?;
-
-        let right_page_id = self.get_next_page_id()?;
-        let mut right_page = BTreePage::new(right_page_id, page.header.kind);
-        right_page.header.reserved = page.header.reserved;
-        right_page.replace_entries(right_entries);
-        self.write_page(&right_page)?;
-
-        if page.header.page_id == root_page_id {
-            let new_root_id = self.get_next_page_id()?;
-            let mut new_root = BTreePage::new(new_root_id, PageKind::Internal);
-
-            let left_key = page.entries[0].key.clone();
-
-            new_root.push_entry(BTreeEntry::new(left_key, page.header.page_id));
-            new_root.push_entry(BTreeEntry::new(split_key, right_page_id));
-            self.write_page(&new_root)?;
-
-            let mut meta_page = self.read_page(0)?;
-            if let Some(root_entry) = meta_page.entries.iter_mut().find(|e| {
-                if let Value::Text(ref s) = e.key {
-                    s == "root"
-                } else {
-                    false
-                }
-            }) {
-                root_entry.pointer = new_root_id;
-            } else {
-                meta_page.push_entry(BTreeEntry::new(
-                    Value::Text("root".to_string()),
-                    new_root_id,
-                ));
-            }
-            meta_page.refresh_entry_count();
-            self.write_page(&meta_page)?;
-
-            return Ok(new_root_id);
-        }
-
-        path.pop();
-        if let Some(parent_page_id) = path.last().copied() {
-            let mut parent_page = self.read_page(parent_page_id)?;
-
-            let parent_entry = BTreeEntry::new(split_key, right_page_id);
-
-            if parent_page.can_accept_entry(&parent_entry) {
-                let insert_pos = parent_page
-                    .entries
-                    .binary_search_by(|e| e.key.cmp(&parent_entry.key))
-                    .unwrap_or_else(|pos| pos);
-                parent_page.insert_entry(insert_pos, parent_entry);
-                self.write_page(&parent_page)?;
-                Ok(root_page_id)
-            } else {
-                self.split_and_insert_recursive(parent_page, parent_entry, path, root_page_id)
-            }
-        } else {
-            Ok(root_page_id)
-        }
+            .map(|(root, _)| root)
     }
 
     #[cfg(test)]
     pub fn delete(&mut self, key: &Value, root_page_id: u64) -> Result<bool, RustqlError> {
-        match self.search(key, root_page_id)? {
-            Some((page_id, entry_idx)) => {
-                let mut page = self.read_page(page_id)?;
-                page.remove_entry(entry_idx);
-                self.write_page(&page)?;
-                Ok(true)
-            }
-            None => Ok(false),
-        }
+        self.delete_entry(key, root_page_id)
+            .map(|removed| removed.is_some())
     }
 
     #[cfg(test)]
@@ -670,89 +557,36 @@ impl BTreeFile {
         end_key: Option<&Value>,
         root_page_id: u64,
     ) -> Result<Vec<(Value, u64)>, RustqlError> {
-        scan_pages_in_order_entries(
-            |page_id| self.read_page(page_id),
-            start_key,
-            end_key,
-            root_page_id,
-        )
-        .map(|entries| {
-            entries
-                .into_iter()
-                .map(|entry| (entry.key, entry.pointer))
-                .collect()
-        })
+        self.scan_entries(start_key, end_key, root_page_id)
+            .map(|entries| {
+                entries
+                    .into_iter()
+                    .map(|entry| (entry.key, entry.pointer))
+                    .collect()
+            })
     }
 }
 
-fn scan_pages_in_order_entries<F>(
-    mut read_page: F,
-    start_key: Option<&Value>,
-    end_key: Option<&Value>,
-    root_page_id: u64,
-) -> Result<Vec<BTreeEntry>, RustqlError>
-where
-    F: FnMut(u64) -> Result<BTreePage, RustqlError>,
-{
-    let mut entries = Vec::new();
-    scan_page_in_order_recursive_entries(
-        &mut read_page,
-        root_page_id,
-        start_key,
-        end_key,
-        &mut entries,
-    )?;
-    Ok(entries)
-}
+/// Read-only page access through the engine cache, used for lookups in an
+/// existing tree.
+impl PageStore for CachedBTreeFile<'_> {
+    fn read_page(&mut self, page_id: u64) -> Result<BTreePage, RustqlError> {
+        self.engine.read_page_cached(page_id)
+    }
 
-fn scan_page_in_order_recursive_entries<F>(
-    read_page: &mut F,
-    page_id: u64,
-    start_key: Option<&Value>,
-    end_key: Option<&Value>,
-    entries: &mut Vec<BTreeEntry>,
-) -> Result<(), RustqlError>
-where
-    F: FnMut(u64) -> Result<BTreePage, RustqlError>,
-{
-    let page = read_page(page_id)?;
-
-    match page.header.kind {
-        PageKind::Leaf => {
-            for entry in page.entries {
-                if start_key.is_some_and(|start| &entry.key < start) {
-                    continue;
-                }
-                if end_key.is_some_and(|end| &entry.key > end) {
-                    break;
-                }
-                entries.push(entry);
-            }
-            Ok(())
-        }
-        PageKind::Internal => {
-            for (idx, entry) in page.entries.iter().enumerate() {
-                if end_key.is_some_and(|end| &entry.key > end) {
-                    break;
-                }
+    fn write_page(&mut self, _page: &BTreePage) -> Result<(), RustqlError> {
+        Err(RustqlError::Internal(
+            "Cannot write pages through the BTree read cache".to_string(),
+        ))
+    }
+}
 
-                let next_min = page.entries.get(idx + 1).map(|next| &next.key);
-                if start_key.is_some_and(|start| next_min.is_some_and(|min| min <= start)) {
-                    continue;
-                }
+impl PageStore for BTreeFile {
+    fn read_page(&mut self, page_id: u64) -> Result<BTreePage, RustqlError> {
+        BTreeFile::read_page(self, page_id)
+    }
 
-                scan_page_in_order_recursive_entries(
-                    read_page,
-                    entry.pointer,
-                    start_key,
-                    end_key,
-                    entries,
-                )?;
-            }
-            Ok(())
-        }
-        PageKind::Meta => Err(RustqlError::StorageError(
-            "Cannot scan entries from meta page".to_string(),
-        )),
+    fn write_page(&mut self, page: &BTreePage) -> Result<(), RustqlError> {
+        BTreeFile::write_page(self, page)
     }
 }
@@ -5,6 +5,9 @@ use std::path::Path;
 
 pub(super) const FILE_HEADER_SIZE: usize = 16;
 pub(super) const HEADER_RESERVED: u32 = 0;
+/// Set in the flags word of a B-tree header, which older versions left
+/// reserved, when every page of the file is encrypted.
+pub(super) const HEADER_ENCRYPTED_FLAG: u32 = 0x01;
 
 pub(super) enum VersionedFileState {
     Missing,
@@ -64,11 +67,35 @@ pub(super) fn read_versioned_header_with_versions(
     Ok(VersionedFileState::Valid { version })
 }
 
+/// Reads the flags word that follows the version in a file header.
+pub(super) fn read_header_flags(path: &Path, label: &str) -> Result<u32, RustqlError> {
+    let mut file = std::fs::File::open(path)
+        .map_err(|e| RustqlError::StorageError(format!("Failed to open {}: {}", label, e)))?;
+    let mut header = [0u8; FILE_HEADER_SIZE];
+    file.read_exact(&mut header).map_err(|e| {
+        RustqlError::StorageError(format!("Failed to read {} header: {}", label, e))
+    })?;
+    Ok(u32::from_le_bytes([
+        header[12], header[13], header[14], header[15],
+    ]))
+}
+
+#[cfg(test)]
 pub(super) fn write_versioned_header(
     file: &mut std::fs::File,
     magic: [u8; 8],
     version: u32,
     label: &str,
+) -> Result<(), RustqlError> {
+    write_versioned_header_with_flags(file, magic, version, HEADER_RESERVED, label)
+}
+
+pub(super) fn write_versioned_header_with_flags(
+    file: &mut std::fs::File,
+    magic: [u8; 8],
+    version: u32,
+    flags: u32,
+    label: &str,
 ) -> Result<(), RustqlError> {
     file.seek(SeekFrom::Start(0))
         .map_err(|e| RustqlError::StorageError(format!("Failed to seek {}: {}", label, e)))?;
@@ -76,7 +103,7 @@ pub(super) fn write_versioned_header(
     let mut header = Vec::with_capacity(FILE_HEADER_SIZE);
     header.extend_from_slice(&magic);
     header.extend_from_slice(&version.to_le_bytes());
-    header.extend_from_slice(&HEADER_RESERVED.to_le_bytes());
+    header.extend_from_slice(&flags.to_le_bytes());
 
     file.write_all(&header)
         .map_err(|e| RustqlError::StorageError(format!("Failed to write {} header: {}", label, e)))
@@ -7,6 +7,7 @@ use super::header::{
     FILE_HEADER_SIZE, HEADER_RESERVED, VersionedFileState, read_versioned_header_with_versions,
 };
 use super::page::BTREE_PAGE_SIZE;
+use super::redo_log::{REDO_LOG_VERSION, decode_redo_log};
 use crate::database::Database;
 use crate::error::RustqlError;
 use serde::{Deserialize, Serialize};
@@ -16,12 +17,30 @@ use std::path::{Path, PathBuf};
 
 pub(super) const JOURNAL_MAGIC: [u8; 8] = *b"RSTQLJW\0";
 pub(super) const LEGACY_JOURNAL_VERSION: u32 = 1;
-pub(super) const JOURNAL_VERSION: u32 = 2;
+/// Version 2 journals only hold `Pending` and whole-image `Committed` states.
+pub(super) const SNAPSHOT_JOURNAL_VERSION: u32 = 2;
+pub(super) const JOURNAL_VERSION: u32 = 3;
 
 #[derive(Serialize, Deserialize)]
 pub(super) enum TransactionJournal {
     Pending,
-    Committed { redo: BTreeRedoJournal },
+    Committed {
+        redo: BTreeRedoJournal,
+    },
+    Delta {
+        delta: BTreePageDelta,
+    },
+    /// One file's part of a commit across attached databases, replayed only
+    /// if the commit's `record` was written.
+    Linked {
+        redo: BTreeRedoJournal,
+        record: PathBuf,
+    },
+    /// Like `Linked`, but holding only the pages the commit changes.
+    LinkedDelta {
+        delta: BTreePageDelta,
+        record: PathBuf,
+    },
 }
 
 #[derive(Serialize, Deserialize)]
@@ -35,7 +54,23 @@ pub(super) struct BTreeRedoJournal {
     pub(super) frames: Vec<BTreePageFrame>,
 }
 
-#[derive(Serialize, Deserialize)]
+/// Page after-images over a base file: one commit in the redo log, or an
+/// in-place save in a version 3 journal.
+///
+/// The frames are full after-images, so replaying them is idempotent: a crash
+/// at any point between the journal becoming durable and the data file sync
+/// rolls forward to the same image on the next load. Unlike
+/// [`BTreeRedoJournal`] the delta does not checksum the whole file, which
+/// keeps the cost of a save proportional to the pages it touches.
+#[derive(Clone, Serialize, Deserialize)]
+pub(super) struct BTreePageDelta {
+    pub(super) page_size: usize,
+    pub(super) base_file_len: u64,
+    pub(super) target_file_len: u64,
+    pub(super) frames: Vec<BTreePageFrame>,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
 pub(super) struct BTreePageFrame {
     pub(super) page_id: u64,
     pub(super) checksum: u64,
@@ -45,12 +80,30 @@ pub(super) struct BTreePageFrame {
 #[derive(Serialize, Deserialize)]
 pub(super) enum LegacyTransactionJournal {
     Pending,
-    Committed { database: Database },
+    Committed { database: Box<Database> },
 }
 
 pub(super) enum LoadedTransactionJournal {
     Pending,
-    Committed { redo: BTreeRedoJournal },
+    Committed {
+        redo: BTreeRedoJournal,
+    },
+    Delta {
+        delta: BTreePageDelta,
+    },
+    Linked {
+        redo: BTreeRedoJournal,
+        record: PathBuf,
+    },
+    LinkedDelta {
+        delta: BTreePageDelta,
+        record: PathBuf,
+    },
+    /// The complete records of a redo log folded into one delta, or `None`
+    /// when the log holds no complete record.
+    RedoLog {
+        delta: Option<BTreePageDelta>,
+    },
 }
 
 impl BTreeRedoJournal {
@@ -139,72 +192,91 @@ impl BTreeRedoJournal {
             self.storage_header[10],
             self.storage_header[11],
         ]);
-        if storage_version != BTreeFile::VERSION {
+        if !BTreeFile::SUPPORTED_VERSIONS.contains(&storage_version) {
             return Err(RustqlError::StorageError(format!(
                 "Redo journal targets unsupported BTree storage version: {}",
                 storage_version
             )));
         }
-        if self.page_size != BTREE_PAGE_SIZE {
-            return Err(RustqlError::StorageError(format!(
-                "Redo journal page size {} does not match BTree page size {}",
-                self.page_size, BTREE_PAGE_SIZE
-            )));
-        }
-        if self.target_file_len < FILE_HEADER_SIZE as u64 {
-            return Err(RustqlError::StorageError(
-                "Redo journal target file length is shorter than the storage header".to_string(),
-            ));
-        }
+        validate_frames(&self.frames, self.page_size, self.target_file_len)
+    }
 
-        let target_payload_len = self.target_file_len - FILE_HEADER_SIZE as u64;
-        if !target_payload_len.is_multiple_of(self.page_size as u64) {
+    pub(super) fn current_matches_base(&self, bytes: &[u8]) -> bool {
+        file_identity_matches(bytes, self.base_file_len, self.base_checksum)
+    }
+
+    pub(super) fn current_matches_target(&self, bytes: &[u8]) -> bool {
+        file_identity_matches(bytes, self.target_file_len, self.target_checksum)
+    }
+}
+
+impl BTreePageDelta {
+    pub(super) fn validate(&self) -> Result<(), RustqlError> {
+        if self.target_file_len < self.base_file_len {
             return Err(RustqlError::StorageError(
-                "Redo journal target file length ends with a partial page".to_string(),
+                "Redo journal delta shrinks the BTree storage file".to_string(),
             ));
         }
+        validate_frames(&self.frames, self.page_size, self.target_file_len)
+    }
+}
 
-        let target_page_count = target_payload_len / self.page_size as u64;
-        let mut seen_pages = std::collections::HashSet::new();
-        for frame in &self.frames {
-            if !seen_pages.insert(frame.page_id) {
-                return Err(RustqlError::StorageError(format!(
-                    "Redo journal contains duplicate frame for page {}",
-                    frame.page_id
-                )));
-            }
-            if frame.page_id >= target_page_count {
-                return Err(RustqlError::StorageError(format!(
-                    "Redo journal frame page {} is outside target file",
-                    frame.page_id
-                )));
-            }
-            if frame.bytes.len() != self.page_size {
-                return Err(RustqlError::StorageError(format!(
-                    "Redo journal frame {} has invalid page length {}",
-                    frame.page_id,
-                    frame.bytes.len()
-                )));
-            }
-            let checksum = storage_checksum(&frame.bytes);
-            if checksum != frame.checksum {
-                return Err(RustqlError::StorageError(format!(
-                    "Redo journal frame {} checksum mismatch",
-                    frame.page_id
-                )));
-            }
-        }
-
-        Ok(())
+fn validate_frames(
+    frames: &[BTreePageFrame],
+    page_size: usize,
+    target_file_len: u64,
+) -> Result<(), RustqlError> {
+    if page_size != BTREE_PAGE_SIZE {
+        return Err(RustqlError::StorageError(format!(
+            "Redo journal page size {} does not match BTree page size {}",
+            page_size, BTREE_PAGE_SIZE
+        )));
+    }
+    if target_file_len < FILE_HEADER_SIZE as u64 {
+        return Err(RustqlError::StorageError(
+            "Redo journal target file length is shorter than the storage header".to_string(),
+        ));
     }
 
-    pub(super) fn current_matches_base(&self, bytes: &[u8]) -> bool {
-        file_identity_matches(bytes, self.base_file_len, self.base_checksum)
+    let target_payload_len = target_file_len - FILE_HEADER_SIZE as u64;
+    if !target_payload_len.is_multiple_of(page_size as u64) {
+        return Err(RustqlError::StorageError(
+            "Redo journal target file length ends with a partial page".to_string(),
+        ));
     }
 
-    pub(super) fn current_matches_target(&self, bytes: &[u8]) -> bool {
-        file_identity_matches(bytes, self.target_file_len, self.target_checksum)
+    let target_page_count = target_payload_len / page_size as u64;
+    let mut seen_pages = std::collections::HashSet::new();
+    for frame in frames {
+        if !seen_pages.insert(frame.page_id) {
+            return Err(RustqlError::StorageError(format!(
+                "Redo journal contains duplicate frame for page {}",
+                frame.page_id
+            )));
+        }
+        if frame.page_id >= target_page_count {
+            return Err(RustqlError::StorageError(format!(
+                "Redo journal frame page {} is outside target file",
+                frame.page_id
+            )));
+        }
+        if frame.bytes.len() != page_size {
+            return Err(RustqlError::StorageError(format!(
+                "Redo journal frame {} has invalid page length {}",
+                frame.page_id,
+                frame.bytes.len()
+            )));
+        }
+        let checksum = storage_checksum(&frame.bytes);
+        if checksum != frame.checksum {
+            return Err(RustqlError::StorageError(format!(
+                "Redo journal frame {} checksum mismatch",
+                frame.page_id
+            )));
+        }
     }
+
+    Ok(())
 }
 
 pub(super) fn storage_checksum(bytes: &[u8]) -> u64 {
@@ -242,11 +314,27 @@ pub(super) fn read_storage_file_bytes(path: &Path) -> Result<Vec<u8>, RustqlErro
     }
 }
 
+/// Encodes `journal` the way a journal file holds it: the header, then the
+/// journal as JSON.
+pub(super) fn encode_journal(journal: &TransactionJournal) -> Result<Vec<u8>, serde_json::Error> {
+    let payload = serde_json::to_vec(journal)?;
+    let mut data = Vec::with_capacity(FILE_HEADER_SIZE + payload.len());
+    data.extend_from_slice(&JOURNAL_MAGIC);
+    data.extend_from_slice(&JOURNAL_VERSION.to_le_bytes());
+    data.extend_from_slice(&HEADER_RESERVED.to_le_bytes());
+    data.extend_from_slice(&payload);
+    Ok(data)
+}
+
+pub(crate) fn journal_path_for(data_path: &Path) -> PathBuf {
+    let mut path = data_path.as_os_str().to_os_string();
+    path.push(".wal");
+    PathBuf::from(path)
+}
+
 impl BTreeStorageEngine {
     pub(super) fn journal_path(&self) -> PathBuf {
-        let mut path = self.data_path.as_os_str().to_os_string();
-        path.push(".wal");
-        PathBuf::from(path)
+        journal_path_for(&self.data_path)
     }
 
     pub(super) fn write_journal_locked(
@@ -254,19 +342,13 @@ impl BTreeStorageEngine {
         journal: &TransactionJournal,
     ) -> Result<(), RustqlError> {
         let path = self.journal_path();
-        let payload = serde_json::to_vec(journal).map_err(|e| {
+        let data = encode_journal(journal).map_err(|e| {
             RustqlError::StorageError(format!(
                 "Failed to serialize transaction journal '{}': {}",
                 path.display(),
                 e
             ))
         })?;
-        let mut data = Vec::with_capacity(FILE_HEADER_SIZE + payload.len());
-        data.extend_from_slice(&JOURNAL_MAGIC);
-        data.extend_from_slice(&JOURNAL_VERSION.to_le_bytes());
-        data.extend_from_slice(&HEADER_RESERVED.to_le_bytes());
-        data.extend_from_slice(&payload);
-
         atomic_write(&path, &data)
     }
 
@@ -277,7 +359,12 @@ impl BTreeStorageEngine {
         match read_versioned_header_with_versions(
             &path,
             JOURNAL_MAGIC,
-            &[LEGACY_JOURNAL_VERSION, JOURNAL_VERSION],
+            &[
+                LEGACY_JOURNAL_VERSION,
+                SNAPSHOT_JOURNAL_VERSION,
+                JOURNAL_VERSION,
+                REDO_LOG_VERSION,
+            ],
             "transaction journal",
         )? {
             VersionedFileState::Missing | VersionedFileState::Empty => Ok(None),
@@ -308,6 +395,11 @@ impl BTreeStorageEngine {
                         e
                     ))
                 })?;
+                if version == REDO_LOG_VERSION {
+                    return Ok(Some(LoadedTransactionJournal::RedoLog {
+                        delta: decode_redo_log(&payload),
+                    }));
+                }
                 if payload.is_empty() {
                     return Ok(None);
                 }
@@ -332,7 +424,7 @@ impl BTreeStorageEngine {
                             }
                         }
                     }
-                    JOURNAL_VERSION => {
+                    SNAPSHOT_JOURNAL_VERSION | JOURNAL_VERSION => {
                         let journal: TransactionJournal = serde_json::from_slice(&payload)
                             .map_err(|e| {
                                 RustqlError::StorageError(format!(
@@ -348,6 +440,18 @@ impl BTreeStorageEngine {
                             TransactionJournal::Committed { redo } => {
                                 Ok(Some(LoadedTransactionJournal::Committed { redo }))
                             }
+                            TransactionJournal::Delta { delta } => {
+                                Ok(Some(LoadedTransactionJournal::Delta { delta }))
+                            }
+                            TransactionJournal::Linked { redo, record } => {
+                                Ok(Some(LoadedTransactionJournal::Linked { redo, record }))
+                            }
+                            TransactionJournal::LinkedDelta { delta, record } => {
+                                Ok(Some(LoadedTransactionJournal::LinkedDelta {
+                                    delta,
+                                    record,
+                                }))
+                            }
                         }
                     }
                     other => Err(RustqlError::StorageError(format!(
@@ -368,7 +472,7 @@ impl BTreeStorageEngine {
         let result = (|| {
             let mut db = db.clone();
             db.normalize_row_ids();
-            let mut file = BTreeFile::create(&target_path)?;
+            let mut file = self.create_file(&target_path)?;
             file.write_database_via_pages(&db)?;
             file.sync_all()?;
             drop(file);
@@ -503,6 +607,15 @@ impl BTreeStorageEngine {
         result
     }
 
+    /// Removes a single-transaction journal left by [`Self::write_journal_locked`].
+    /// The redo log is left alone; only a checkpoint may drop it.
+    pub(super) fn clear_snapshot_journal_locked(&self) -> Result<(), RustqlError> {
+        if self.redo_log.is_open() {
+            return Ok(());
+        }
+        self.clear_journal_locked()
+    }
+
     pub(super) fn clear_journal_locked(&self) -> Result<(), RustqlError> {
         let path = self.journal_path();
         if !path.exists() {
@@ -531,8 +644,59 @@ impl BTreeStorageEngine {
                 self.apply_redo_journal_locked(&redo)?;
                 self.clear_journal_locked()?;
             }
+            LoadedTransactionJournal::Delta { delta } => {
+                self.apply_page_delta_locked(&delta)?;
+                self.clear_cache();
+                self.clear_journal_locked()?;
+            }
+            LoadedTransactionJournal::Linked { redo, record } => {
+                // Without its record the commit never happened in any file.
+                if record.exists() {
+                    self.apply_redo_journal_locked(&redo)?;
+                }
+                self.clear_journal_locked()?;
+            }
+            LoadedTransactionJournal::LinkedDelta { delta, record } => {
+                if record.exists() {
+                    self.apply_page_delta_locked(&delta)?;
+                    self.clear_cache();
+                }
+                self.clear_journal_locked()?;
+            }
+            LoadedTransactionJournal::RedoLog { delta } => {
+                // Records after the last complete one were never acknowledged,
+                // so the log is dropped once its complete records are applied.
+                if let Some(delta) = delta {
+                    self.apply_page_delta_locked(&delta)?;
+                    self.clear_cache();
+                }
+                self.clear_journal_locked()?;
+            }
         }
 
         Ok(())
     }
+
+    /// Applies the part of a commit across attached databases that
+    /// `prepare_linked_commit` journaled, once the commit's record exists,
+    /// and ships it to a follower.
+    pub(super) fn finish_linked_commit_locked(&self) -> Result<(), RustqlError> {
+        match self.read_journal_locked()? {
+            Some(LoadedTransactionJournal::Linked { redo, .. }) => {
+                self.apply_redo_journal_locked(&redo)?;
+                self.supersede_rows(self.take_linked_replaced());
+                self.clear_journal_locked()?;
+                self.ship_image_locked()
+            }
+            Some(LoadedTransactionJournal::LinkedDelta { delta, .. }) => {
+                self.apply_page_delta_locked(&delta)?;
+                self.clear_cache();
+                self.supersede_rows(self.take_linked_replaced());
+                self.clear_journal_locked()?;
+                self.ship_delta_locked(delta)
+            }
+            // Nothing was journaled when the commit changed no page.
+            _ => Ok(()),
+        }
+    }
 }
@@ -1,61 +1,270 @@
+mod backup;
 mod cache;
+mod check;
+mod cipher;
+mod delta;
+mod encoding;
 mod file;
 mod header;
 mod journal;
+mod overflow;
 mod page;
+mod paging;
 mod records;
+mod redo_log;
+mod shipping;
+mod tree;
+mod vacuum;
+mod writer;
 
 #[cfg(test)]
 mod tests;
 
-use super::StorageEngine;
 use super::atomic_file::{cleanup_temp_file, rename_synced, storage_temp_path};
-use crate::database::Database;
-use crate::error::RustqlError;
+use super::file_lock::StorageLock;
+use super::{ChangeSet, PageUsage, StorageEngine, VacuumStats};
+use crate::database::{Database, ReplacedRows, RowGeneration};
+use crate::error::{IntegrityProblem, RustqlError};
+use std::borrow::Cow;
 use std::collections::HashSet;
-use std::path::PathBuf;
-use std::sync::{Arc, RwLock};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::Duration;
 
 use cache::PageCache;
+use cipher::PageCipher;
+use delta::CommitLogging;
 use file::{BTreeFile, CachedBTreeFile};
 use journal::TransactionJournal;
 use page::BTreePage;
+use redo_log::RedoLog;
+use shipping::LogShipper;
 
-const MAX_CACHE_SIZE: usize = 1000;
+pub(crate) use backup::write_snapshot_backup;
+pub use cipher::EncryptionKey;
+pub(crate) use journal::journal_path_for;
+pub use redo_log::DEFAULT_CHECKPOINT_PAGES;
+pub(crate) use shipping::LogFollower;
+pub use shipping::{LogReceiver, LogSender, ReplicationSource, ReplicationTarget, log_channel};
 
-/// Snapshot-oriented B-tree storage backend.
+/// Default number of pages kept in the page cache (4 MiB of 4 KiB pages).
+pub const DEFAULT_PAGE_CACHE_SIZE: usize = 1000;
+
+/// Page-based B-tree storage backend.
+///
+/// Statements and transactions persist through [`StorageEngine::save_changes`],
+/// which applies the change set to the existing tree and appends the pages it
+/// dirtied to the redo log in the `.wal` file. A commit returns once its log
+/// record is synced; concurrent commits share syncs. Logged pages reach the
+/// main file at a checkpoint, which runs when the log holds more than the
+/// configured number of pages, on [`StorageEngine::checkpoint`], and before a
+/// snapshot save. Loading replays the complete records of a leftover log.
+///
+/// [`StorageEngine::save`] still writes a complete page image to a temporary
+/// file and atomically replaces the storage file. It is used for the first
+/// save and to upgrade files written in an older format version.
+///
+/// Pages emptied by deletes are unlinked from the tree and kept on a free
+/// list in the meta page for later allocations to reuse.
+/// [`StorageEngine::vacuum`] rewrites the file as a compact snapshot.
+///
+/// Every page ends with a checksum that reads verify, and
+/// [`StorageEngine::check_integrity`] walks the whole tree to report damaged
+/// pages and broken links.
+///
+/// [`StorageEngine::backup_to`] copies the main file with the logged pages
+/// laid over it, so a backup never waits for a checkpoint.
+///
+/// [`StorageEngine::load`] reads only the catalog and indexes of a current
+/// format file. Table rows stay on disk and are read through the page cache,
+/// which holds at most the configured number of pages.
 ///
-/// The on-disk format is page-based and versioned, but persistence is not
-/// incremental: each save normalizes the whole [`Database`], writes a complete
-/// page image to a temporary file, syncs it, and atomically replaces the
-/// storage file. The transaction journal records enough redo frames to recover
-/// a committed snapshot, not a mutation log for replaying individual writes.
+/// Other processes are kept out with an advisory lock on a `.lock` file next
+/// to the storage file, which covers the file and its `.wal` journal. Loading
+/// takes it shared, so several processes may read the same file; the first
+/// write upgrades it to exclusive. The lock is held until the engine is
+/// dropped, because the engine caches the catalog and pages. When another
+/// process holds a conflicting lock, the engine retries until the busy timeout
+/// runs out and then fails with [`RustqlError::DatabaseLocked`].
+///
+/// With [`BTreeStorageEngine::with_log_shipping`] every commit is also
+/// shipped to a follower as the page images it logged, and every snapshot
+/// save as an image of the whole file.
 pub struct BTreeStorageEngine {
     data_path: PathBuf,
     path_lock: Arc<RwLock<()>>,
+    file_lock: StorageLock,
     page_cache: Arc<RwLock<PageCache>>,
+    redo_log: Arc<RedoLog>,
+    checkpoint_pages: usize,
+    cipher: Arc<RwLock<Option<PageCipher>>>,
+    shipper: Option<Arc<LogShipper>>,
+    /// The newest generation of the stored rows, which paged tables read
+    /// through.
+    generation: Arc<Mutex<Arc<RowGeneration>>>,
+    /// The rows a prepared linked commit replaces, until it is finished.
+    linked_replaced: Arc<Mutex<ReplacedRows>>,
 }
 
 impl BTreeStorageEngine {
     pub fn new<P: Into<PathBuf>>(data_path: P) -> Self {
+        Self::with_page_cache_size(data_path, DEFAULT_PAGE_CACHE_SIZE)
+    }
+
+    /// Creates an engine whose page cache holds at most `pages` pages.
+    pub fn with_page_cache_size<P: Into<PathBuf>>(data_path: P, pages: usize) -> Self {
+        let data_path = data_path.into();
+        let redo_log = RedoLog::new(journal::journal_path_for(&data_path));
         BTreeStorageEngine {
-            data_path: data_path.into(),
+            file_lock: StorageLock::new(&data_path),
+            data_path,
             path_lock: Arc::new(RwLock::new(())),
-            page_cache: Arc::new(RwLock::new(PageCache::new())),
+            page_cache: Arc::new(RwLock::new(PageCache::new(pages))),
+            redo_log: Arc::new(redo_log),
+            checkpoint_pages: DEFAULT_CHECKPOINT_PAGES,
+            cipher: Arc::new(RwLock::new(None)),
+            shipper: None,
+            generation: Arc::default(),
+            linked_replaced: Arc::default(),
         }
     }
 
+    /// Checkpoints automatically once the redo log holds at least `pages`
+    /// distinct pages (at least one).
+    pub fn with_checkpoint_pages(mut self, pages: usize) -> Self {
+        self.checkpoint_pages = pages.max(1);
+        self
+    }
+
+    /// Waits up to `timeout` for another process to release the file before
+    /// failing with [`RustqlError::DatabaseLocked`].
+    pub fn with_busy_timeout(mut self, timeout: Duration) -> Self {
+        self.file_lock = self.file_lock.with_busy_timeout(timeout);
+        self
+    }
+
+    /// Returns a handle to the same file that shares this engine's lock and
+    /// page cache.
+    fn shared_handle(&self) -> Self {
+        BTreeStorageEngine {
+            data_path: self.data_path.clone(),
+            path_lock: Arc::clone(&self.path_lock),
+            file_lock: self.file_lock.clone(),
+            page_cache: Arc::clone(&self.page_cache),
+            redo_log: Arc::clone(&self.redo_log),
+            checkpoint_pages: self.checkpoint_pages,
+            cipher: Arc::clone(&self.cipher),
+            shipper: self.shipper.clone(),
+            generation: Arc::clone(&self.generation),
+            linked_replaced: Arc::clone(&self.linked_replaced),
+        }
+    }
+
+    /// Encrypts the file with `key`. A new file is created encrypted; an
+    /// existing file must already be encrypted with the same key, or be
+    /// converted with [`StorageEngine::rekey`].
+    pub fn with_encryption_key(self, key: EncryptionKey) -> Self {
+        *self
+            .cipher
+            .write()
+            .unwrap_or_else(|poisoned| poisoned.into_inner()) = Some(PageCipher::new(key));
+        self
+    }
+
+    fn page_cipher(&self) -> Option<PageCipher> {
+        self.cipher
+            .read()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .clone()
+    }
+
+    /// Opens the storage file to read and write pages with the current key.
+    fn open_file(&self) -> Result<BTreeFile, RustqlError> {
+        Ok(BTreeFile::open(&self.data_path)?.with_cipher(self.page_cipher()))
+    }
+
+    /// Creates a file at `path` that pages are written to with the current key.
+    fn create_file(&self, path: &Path) -> Result<BTreeFile, RustqlError> {
+        Ok(BTreeFile::create(path)?.with_cipher(self.page_cipher()))
+    }
+
+    pub(super) fn row_generation(&self) -> Arc<RowGeneration> {
+        Arc::clone(
+            &self
+                .generation
+                .lock()
+                .unwrap_or_else(|err| err.into_inner()),
+        )
+    }
+
+    /// Whether any paged table still reads the newest generation or an older
+    /// one. Without readers a commit has no replaced rows to keep. Paged
+    /// tables are only created while no commit runs, so none can appear
+    /// between this check and the commit superseding the generation.
+    pub(super) fn has_row_readers(&self) -> bool {
+        Arc::strong_count(
+            &self
+                .generation
+                .lock()
+                .unwrap_or_else(|err| err.into_inner()),
+        ) > 1
+    }
+
+    /// Records the rows a commit replaced in the row generation chain. The
+    /// caller holds the path lock, so no paged table reads the changed rows
+    /// before it can find the ones they replaced.
+    pub(super) fn supersede_rows(&self, replaced: ReplacedRows) {
+        if replaced.is_empty() {
+            return;
+        }
+        let mut generation = self
+            .generation
+            .lock()
+            .unwrap_or_else(|err| err.into_inner());
+        *generation = generation.supersede(replaced);
+    }
+
+    /// Takes the rows the prepared linked commit replaces.
+    pub(super) fn take_linked_replaced(&self) -> ReplacedRows {
+        std::mem::take(
+            &mut *self
+                .linked_replaced
+                .lock()
+                .unwrap_or_else(|err| err.into_inner()),
+        )
+    }
+
     pub(super) fn read_page_cached(&self, page_id: u64) -> Result<BTreePage, RustqlError> {
+        if let Some(page) = self.redo_log.logged_page(page_id) {
+            return Ok(page);
+        }
+        if let Some(page) = self.cached_page(page_id)? {
+            return Ok(page);
+        }
+
+        let mut file = self.open_file()?;
+        let page = file.read_page(page_id)?;
         {
             let mut cache = self.page_cache.write().map_err(|e| {
                 RustqlError::StorageError(format!("Failed to acquire cache write lock: {}", e))
             })?;
-            if let Some(page) = cache.get(&page_id) {
-                return Ok(page.clone());
-            }
+            cache.insert(page_id, page.clone());
+        }
+        Ok(page)
+    }
+
+    fn read_page_through_cache(
+        &self,
+        file: &mut BTreeFile,
+        page_id: u64,
+    ) -> Result<BTreePage, RustqlError> {
+        if let Some(page) = self.redo_log.logged_page(page_id) {
+            return Ok(page);
+        }
+        if let Some(page) = self.cached_page(page_id)? {
+            return Ok(page);
         }
 
-        let mut file = BTreeFile::open(&self.data_path)?;
         let page = file.read_page(page_id)?;
         {
             let mut cache = self.page_cache.write().map_err(|e| {
@@ -66,6 +275,13 @@ impl BTreeStorageEngine {
         Ok(page)
     }
 
+    fn cached_page(&self, page_id: u64) -> Result<Option<BTreePage>, RustqlError> {
+        let mut cache = self.page_cache.write().map_err(|e| {
+            RustqlError::StorageError(format!("Failed to acquire cache write lock: {}", e))
+        })?;
+        Ok(cache.get(&page_id).cloned())
+    }
+
     pub fn cache_stats(&self) -> (u64, u64, usize) {
         let cache = self
             .page_cache
@@ -74,6 +290,12 @@ impl BTreeStorageEngine {
         cache.stats()
     }
 
+    /// Returns the number of records appended to the redo log, the number of
+    /// log syncs, and the number of pages waiting for a checkpoint.
+    pub fn log_stats(&self) -> (u64, u64, usize) {
+        self.redo_log.stats()
+    }
+
     pub fn invalidate_page(&self, page_id: u64) {
         let mut cache = self
             .page_cache
@@ -109,13 +331,15 @@ impl BTreeStorageEngine {
 
     pub(super) fn save_locked(&self, db: &Database) -> Result<(), RustqlError> {
         // Snapshot write: build a fresh page image and atomically replace the
-        // current file. This keeps crash semantics simple, but it is not an
-        // incremental page-delta update.
+        // current file. Incremental saves go through `save_changes_locked`.
+        // Logged pages are folded in first so the log never outlives the file
+        // it was written against.
+        self.checkpoint_locked()?;
         let mut db = db.clone();
         db.normalize_row_ids();
         let temp_path = storage_temp_path(&self.data_path);
         let result = (|| {
-            let mut file = BTreeFile::create(&temp_path)?;
+            let mut file = self.create_file(&temp_path)?;
             file.write_database_via_pages(&db)?;
             file.sync_all()?;
             drop(file);
@@ -125,7 +349,8 @@ impl BTreeStorageEngine {
             cleanup_temp_file(&temp_path);
         }
         self.clear_cache();
-        result
+        result?;
+        self.ship_image_locked()
     }
 }
 
@@ -134,7 +359,16 @@ impl StorageEngine for BTreeStorageEngine {
         let _path_guard = self.path_lock.write().map_err(|e| {
             RustqlError::StorageError(format!("Failed to acquire BTree storage write lock: {}", e))
         })?;
-        self.recover_if_needed_locked()?;
+        self.file_lock.lock_shared()?;
+        self.check_encryption_key_locked()?;
+        if self.journal_path().exists() {
+            // A log left behind by the last writer is folded into the file
+            // before anything is read.
+            self.file_lock.with_exclusive(|| {
+                self.checkpoint_locked()?;
+                self.recover_if_needed_locked()
+            })?;
+        }
         let mut cached_file = CachedBTreeFile { engine: self };
         let mut db = cached_file.read_database_via_pages()?;
         db.normalize_row_ids();
@@ -142,32 +376,186 @@ impl StorageEngine for BTreeStorageEngine {
     }
 
     fn save(&self, db: &Database) -> Result<(), RustqlError> {
+        let db = with_paged_rows_loaded(db)?;
         let _path_guard = self.path_lock.write().map_err(|e| {
             RustqlError::StorageError(format!("Failed to acquire BTree storage write lock: {}", e))
         })?;
-        self.save_locked(db)
+        self.file_lock.lock_exclusive()?;
+        let replaced = self.image_replaced_rows_locked(&db)?;
+        self.save_locked(&db)?;
+        self.supersede_rows(replaced);
+        Ok(())
     }
 
-    fn begin_transaction(&self) -> Result<(), RustqlError> {
-        let _path_guard = self.path_lock.write().map_err(|e| {
-            RustqlError::StorageError(format!("Failed to acquire BTree storage write lock: {}", e))
-        })?;
-        self.write_journal_locked(&TransactionJournal::Pending)
+    fn save_changes(&self, db: &Database, changes: &ChangeSet) -> Result<(), RustqlError> {
+        self.ship_held()?;
+        let logged = {
+            let _path_guard = self.path_lock.write().map_err(|e| {
+                RustqlError::StorageError(format!(
+                    "Failed to acquire BTree storage write lock: {}",
+                    e
+                ))
+            })?;
+            self.file_lock.lock_exclusive()?;
+            self.save_changes_locked(db, changes)?
+        };
+        match logged {
+            CommitLogging::NeedsSnapshot => self.save(db),
+            CommitLogging::Unchanged => Ok(()),
+            CommitLogging::Logged(lsn) => {
+                // The sync runs without the path lock so other commits can
+                // append behind this one and share the next sync.
+                self.redo_log.sync_to(lsn)?;
+                // The commit is durable here whether or not it reaches the
+                // follower; one that fails to ship stays queued, and the
+                // next commit fails unless it goes out first.
+                self.ship_committed(lsn);
+                if self.redo_log.logged_page_count() >= self.checkpoint_pages {
+                    self.checkpoint()?;
+                }
+                Ok(())
+            }
+        }
     }
 
     fn prepare_commit(&self, db: &Database) -> Result<(), RustqlError> {
+        let db = with_paged_rows_loaded(db)?;
         let _path_guard = self.path_lock.write().map_err(|e| {
             RustqlError::StorageError(format!("Failed to acquire BTree storage write lock: {}", e))
         })?;
+        self.file_lock.lock_exclusive()?;
+        // The prepared image replaces the `.wal` file, so the log goes first.
+        self.checkpoint_locked()?;
         self.write_journal_locked(&TransactionJournal::Committed {
-            redo: self.build_redo_journal_locked(db)?,
+            redo: self.build_redo_journal_locked(&db)?,
         })
     }
 
+    fn prepare_linked_commit(
+        &self,
+        db: &Database,
+        changes: Option<&ChangeSet>,
+        record: &Path,
+    ) -> Result<(), RustqlError> {
+        if let Some(changes) = changes {
+            let _path_guard = self.path_lock.write().map_err(|e| {
+                RustqlError::StorageError(format!(
+                    "Failed to acquire BTree storage write lock: {}",
+                    e
+                ))
+            })?;
+            self.file_lock.lock_exclusive()?;
+            self.checkpoint_locked()?;
+            if self.prepare_linked_delta_locked(db, changes, record)? {
+                return Ok(());
+            }
+        }
+        let db = with_paged_rows_loaded(db)?;
+        let _path_guard = self.path_lock.write().map_err(|e| {
+            RustqlError::StorageError(format!("Failed to acquire BTree storage write lock: {}", e))
+        })?;
+        self.file_lock.lock_exclusive()?;
+        self.checkpoint_locked()?;
+        let replaced = self.image_replaced_rows_locked(&db)?;
+        self.write_journal_locked(&TransactionJournal::Linked {
+            redo: self.build_redo_journal_locked(&db)?,
+            record: record.to_path_buf(),
+        })?;
+        *self
+            .linked_replaced
+            .lock()
+            .unwrap_or_else(|err| err.into_inner()) = replaced;
+        Ok(())
+    }
+
+    fn finish_linked_commit(&self, _db: &Database) -> Result<(), RustqlError> {
+        let _path_guard = self.path_lock.write().map_err(|e| {
+            RustqlError::StorageError(format!("Failed to acquire BTree storage write lock: {}", e))
+        })?;
+        self.file_lock.lock_exclusive()?;
+        self.finish_linked_commit_locked()
+    }
+
     fn clear_transaction(&self) -> Result<(), RustqlError> {
         let _path_guard = self.path_lock.write().map_err(|e| {
             RustqlError::StorageError(format!("Failed to acquire BTree storage write lock: {}", e))
         })?;
-        self.clear_journal_locked()
+        if !self.redo_log.is_open() && self.journal_path().exists() {
+            self.file_lock.lock_exclusive()?;
+        }
+        self.take_linked_replaced();
+        self.clear_snapshot_journal_locked()
+    }
+
+    fn checkpoint(&self) -> Result<(), RustqlError> {
+        let _path_guard = self.path_lock.write().map_err(|e| {
+            RustqlError::StorageError(format!("Failed to acquire BTree storage write lock: {}", e))
+        })?;
+        self.file_lock.lock_exclusive()?;
+        self.checkpoint_locked().map(|_| ())
+    }
+
+    fn backup_to(&self, path: &Path) -> Result<(), RustqlError> {
+        if !self.redo_log.is_open() && self.journal_path().exists() {
+            let _path_guard = self.path_lock.write().map_err(|e| {
+                RustqlError::StorageError(format!(
+                    "Failed to acquire BTree storage write lock: {}",
+                    e
+                ))
+            })?;
+            self.file_lock
+                .with_exclusive(|| self.recover_if_needed_locked())?;
+        }
+        // Readers keep running during the copy; commits wait for it.
+        let _path_guard = self.path_lock.read().map_err(|e| {
+            RustqlError::StorageError(format!("Failed to acquire BTree storage read lock: {}", e))
+        })?;
+        self.file_lock.lock_shared()?;
+        self.backup_locked(path)
+    }
+
+    fn vacuum(&self, db: &Database) -> Result<VacuumStats, RustqlError> {
+        let db = with_paged_rows_loaded(db)?;
+        let _path_guard = self.path_lock.write().map_err(|e| {
+            RustqlError::StorageError(format!("Failed to acquire BTree storage write lock: {}", e))
+        })?;
+        self.file_lock.lock_exclusive()?;
+        self.vacuum_locked(&db)
+    }
+
+    fn rekey(&self, db: &Database, key: Option<EncryptionKey>) -> Result<(), RustqlError> {
+        let db = with_paged_rows_loaded(db)?;
+        let _path_guard = self.path_lock.write().map_err(|e| {
+            RustqlError::StorageError(format!("Failed to acquire BTree storage write lock: {}", e))
+        })?;
+        self.file_lock.lock_exclusive()?;
+        self.rekey_locked(&db, key)
+    }
+
+    fn page_usage(&self) -> Result<Vec<PageUsage>, RustqlError> {
+        let _path_guard = self.path_lock.read().map_err(|e| {
+            RustqlError::StorageError(format!("Failed to acquire BTree storage read lock: {}", e))
+        })?;
+        self.file_lock.lock_shared()?;
+        self.page_usage_locked()
+    }
+
+    fn check_integrity(&self) -> Result<Vec<IntegrityProblem>, RustqlError> {
+        let _path_guard = self.path_lock.read().map_err(|e| {
+            RustqlError::StorageError(format!("Failed to acquire BTree storage read lock: {}", e))
+        })?;
+        self.file_lock.lock_shared()?;
+        self.check_integrity_locked()
+    }
+}
+
+/// Snapshot writes need every row in memory. Paged rows are read before the
+/// path lock is taken, because the row source takes it for reading.
+fn with_paged_rows_loaded(db: &Database) -> Result<Cow<'_, Database>, RustqlError> {
+    if !db.has_paged_tables() {
+        return Ok(Cow::Borrowed(db));
     }
+    let mut loaded = db.clone();
+    loaded.load_all_paged_tables()?;
+    Ok(Cow::Owned(loaded))
 }
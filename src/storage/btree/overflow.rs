@@ -0,0 +1,126 @@
+use super::encoding::{overflow_stub, parse_overflow_stub};
+use super::page::{BTREE_PAGE_CAPACITY, BTREE_PAGE_HEADER_SIZE, BTreeEntry, BTreePage, PageKind};
+use super::tree::PageStore;
+use crate::ast::Value;
+use crate::error::RustqlError;
+use std::borrow::Cow;
+
+/// Records larger than this are moved to overflow pages, so a leaf always
+/// holds several records and a split can balance them.
+pub(super) const MAX_INLINE_RECORD_SIZE: usize = 1024;
+/// Bytes of a record held by one overflow page, after the page header and
+/// its single entry's integer key and data length.
+pub(super) const OVERFLOW_CHUNK_SIZE: usize = BTREE_PAGE_CAPACITY - BTREE_PAGE_HEADER_SIZE - 9 - 4;
+
+/// Returns the payload to keep in a leaf: the record itself, or a stub naming
+/// the overflow chain it was written to.
+pub(super) fn store_payload<S: PageStore + ?Sized>(
+    store: &mut S,
+    payload: Vec<u8>,
+) -> Result<Vec<u8>, RustqlError> {
+    if payload.len() <= MAX_INLINE_RECORD_SIZE {
+        return Ok(payload);
+    }
+    let chunks: Vec<&[u8]> = payload.chunks(OVERFLOW_CHUNK_SIZE).collect();
+    let page_ids = chunks
+        .iter()
+        .map(|_| store.allocate_page())
+        .collect::<Result<Vec<_>, _>>()?;
+    for (idx, chunk) in chunks.iter().enumerate() {
+        let next_page = page_ids.get(idx + 1).copied().unwrap_or(0);
+        let mut page = BTreePage::new(page_ids[idx], PageKind::Overflow);
+        page.push_entry(BTreeEntry::with_inline_data(
+            Value::Integer(next_page as i64),
+            chunk.to_vec(),
+        ));
+        store.write_page(&page)?;
+    }
+    Ok(overflow_stub(payload.len(), page_ids[0]))
+}
+
+/// Returns the full record behind a leaf payload, reading its overflow chain
+/// when the payload is a stub.
+pub(super) fn load_payload<'p, F>(
+    mut read_page: F,
+    payload: &'p [u8],
+) -> Result<Cow<'p, [u8]>, RustqlError>
+where
+    F: FnMut(u64) -> Result<BTreePage, RustqlError>,
+{
+    let Some((len, first_page)) = parse_overflow_stub(payload)? else {
+        return Ok(Cow::Borrowed(payload));
+    };
+    let mut record = Vec::with_capacity(len);
+    let mut page_id = first_page;
+    while record.len() < len {
+        let (chunk, next_page) = read_overflow_page(&mut read_page, page_id)?;
+        record.extend_from_slice(&chunk);
+        page_id = next_page;
+        if page_id == 0 {
+            break;
+        }
+    }
+    if record.len() != len {
+        return Err(RustqlError::StorageError(format!(
+            "Overflow chain starting at page {} holds {} bytes but the record has {}",
+            first_page,
+            record.len(),
+            len
+        )));
+    }
+    Ok(Cow::Owned(record))
+}
+
+/// Lists the pages of the overflow chain a leaf payload names, if any.
+pub(super) fn overflow_pages<F>(mut read_page: F, payload: &[u8]) -> Result<Vec<u64>, RustqlError>
+where
+    F: FnMut(u64) -> Result<BTreePage, RustqlError>,
+{
+    let Some((len, first_page)) = parse_overflow_stub(payload)? else {
+        return Ok(Vec::new());
+    };
+    let mut pages = Vec::new();
+    let mut page_id = first_page;
+    // A chain never has more pages than its length needs, which also stops
+    // the walk on a corrupt chain that loops.
+    while page_id != 0 && pages.len() < len.div_ceil(OVERFLOW_CHUNK_SIZE) {
+        pages.push(page_id);
+        page_id = read_overflow_page(&mut read_page, page_id)?.1;
+    }
+    Ok(pages)
+}
+
+/// Frees the overflow chain a leaf payload names, if any.
+pub(super) fn free_payload<S: PageStore + ?Sized>(
+    store: &mut S,
+    payload: &[u8],
+) -> Result<(), RustqlError> {
+    for page_id in overflow_pages(|page_id| store.read_page(page_id), payload)? {
+        store.free_page(page_id)?;
+    }
+    Ok(())
+}
+
+fn read_overflow_page<F>(read_page: &mut F, page_id: u64) -> Result<(Vec<u8>, u64), RustqlError>
+where
+    F: FnMut(u64) -> Result<BTreePage, RustqlError>,
+{
+    let page = read_page(page_id)?;
+    if page.header.kind != PageKind::Overflow {
+        return Err(RustqlError::StorageError(format!(
+            "Overflow chain links to {:?} page {}",
+            page.header.kind, page_id
+        )));
+    }
+    match page.entries.into_iter().next() {
+        Some(BTreeEntry {
+            key: Value::Integer(next_page),
+            inline_data: Some(chunk),
+            ..
+        }) => Ok((chunk, next_page as u64)),
+        _ => Err(RustqlError::StorageError(format!(
+            "Overflow page {} is malformed",
+            page_id
+        ))),
+    }
+}
@@ -1,15 +1,35 @@
+use super::cipher::PAGE_SEAL_OVERHEAD;
+use super::journal::storage_checksum;
 use crate::ast::Value;
+use crate::decimal::Decimal;
 use crate::error::RustqlError;
+use crate::interval::Interval;
 use serde::{Deserialize, Serialize};
 
 pub const BTREE_PAGE_SIZE: usize = 4096;
 pub(super) const BTREE_PAGE_HEADER_SIZE: usize = 16;
+/// Pages end with a checksum of everything before it.
+const PAGE_CHECKSUM_SIZE: usize = 4;
+const PAGE_CHECKSUM_OFFSET: usize = BTREE_PAGE_SIZE - PAGE_CHECKSUM_SIZE;
+/// Bytes available to the header and entries of a page. Room is left for
+/// the nonce and tag of an encrypted page, so every page can be sealed.
+pub(super) const BTREE_PAGE_CAPACITY: usize = PAGE_CHECKSUM_OFFSET - PAGE_SEAL_OVERHEAD;
+const PAGE_FLAGS_OFFSET: usize = 13;
+/// Set on pages that carry a checksum. Pages written before checksums were
+/// added leave the flag byte zero and are read unchecked.
+const PAGE_CHECKSUM_FLAG: u8 = 0x01;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub enum PageKind {
     Meta,
     Internal,
     Leaf,
+    /// A page on the free list. Its only entry points at the next free page.
+    Free,
+    /// Part of a record too large for a leaf. Its only entry is keyed by the
+    /// next page of the chain, or zero on the last page, and holds a chunk
+    /// of the record as inline data.
+    Overflow,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -59,7 +79,6 @@ impl BTreePage {
         self.refresh_entry_count();
     }
 
-    #[cfg(test)]
     pub(super) fn remove_entry(&mut self, index: usize) -> BTreeEntry {
         let entry = self.entries.remove(index);
         self.refresh_entry_count();
@@ -76,21 +95,21 @@ impl BTreePage {
         self.header.entry_count = self.entries.len() as u16;
     }
 
-    fn stores_inline_leaf_data(&self) -> bool {
-        self.header.kind == PageKind::Leaf && self.header.reserved & LEAF_INLINE_DATA_FLAG != 0
+    fn stores_inline_data(&self) -> bool {
+        stores_inline_data(self.header.kind, self.header.reserved)
     }
 
     pub fn can_accept_entry(&self, entry: &BTreeEntry) -> bool {
-        let current_size = self.estimated_size();
-        let added = entry.estimated_size(self.header.kind, self.header.reserved);
-        current_size + added <= BTREE_PAGE_SIZE
+        let current_size = self.encoded_size();
+        let added = entry.encoded_size(self.header.kind, self.header.reserved);
+        current_size + added <= BTREE_PAGE_CAPACITY
     }
 
-    fn estimated_size(&self) -> usize {
+    pub(super) fn encoded_size(&self) -> usize {
         let entries_size: usize = self
             .entries
             .iter()
-            .map(|e| e.estimated_size(self.header.kind, self.header.reserved))
+            .map(|e| e.encoded_size(self.header.kind, self.header.reserved))
             .sum();
         BTREE_PAGE_HEADER_SIZE + entries_size
     }
@@ -102,8 +121,30 @@ pub struct BTreeEntry {
 
     pub pointer: u64,
 
-    #[serde(default)]
-    pub inline_data: Option<String>,
+    #[serde(default, with = "legacy_inline_data")]
+    pub inline_data: Option<Vec<u8>>,
+}
+
+/// Pages from before the binary page layout hold their entries as JSON, in
+/// which inline data is a string.
+mod legacy_inline_data {
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    pub fn serialize<S>(data: &Option<Vec<u8>>, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        data.as_deref()
+            .map(String::from_utf8_lossy)
+            .serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<Option<Vec<u8>>, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        Ok(Option::<String>::deserialize(deserializer)?.map(String::into_bytes))
+    }
 }
 
 impl BTreeEntry {
@@ -115,7 +156,7 @@ impl BTreeEntry {
         }
     }
 
-    pub fn with_inline_data(key: Value, inline_data: String) -> Self {
+    pub fn with_inline_data(key: Value, inline_data: Vec<u8>) -> Self {
         BTreeEntry {
             key,
             pointer: 0,
@@ -123,15 +164,23 @@ impl BTreeEntry {
         }
     }
 
-    fn estimated_size(&self, kind: PageKind, reserved: u16) -> usize {
+    pub(super) fn encoded_size(&self, kind: PageKind, reserved: u16) -> usize {
         let key_size = match &self.key {
             Value::Null => 1,
             Value::Integer(_) => 9,
             Value::Float(_) => 9,
+            Value::Decimal(_) => 18,
+            Value::Interval(_) => 17,
             Value::Boolean(_) => 2,
-            Value::Text(s) | Value::Date(s) | Value::Time(s) | Value::DateTime(s) => 1 + s.len(),
+            Value::Text(s)
+            | Value::Date(s)
+            | Value::Time(s)
+            | Value::DateTime(s)
+            | Value::TimestampTz(s)
+            | Value::Json(s) => 5 + s.len(),
+            Value::Blob(bytes) => 5 + bytes.len(),
         };
-        let value_size = if kind == PageKind::Leaf && reserved & LEAF_INLINE_DATA_FLAG != 0 {
+        let value_size = if stores_inline_data(kind, reserved) {
             4 + self
                 .inline_data
                 .as_ref()
@@ -151,8 +200,19 @@ const TAG_BOOLEAN: u8 = 0x04;
 const TAG_DATE: u8 = 0x05;
 const TAG_TIME: u8 = 0x06;
 const TAG_DATETIME: u8 = 0x07;
+const TAG_DECIMAL: u8 = 0x08;
+const TAG_BLOB: u8 = 0x09;
+const TAG_JSON: u8 = 0x0a;
+const TAG_INTERVAL: u8 = 0x0b;
+const TAG_TIMESTAMPTZ: u8 = 0x0c;
 pub(super) const LEAF_INLINE_DATA_FLAG: u16 = 0x0001;
 
+/// Inline leaves and overflow pages keep data in their entries; other pages
+/// hold a page pointer per entry.
+fn stores_inline_data(kind: PageKind, reserved: u16) -> bool {
+    kind == PageKind::Overflow || kind == PageKind::Leaf && reserved & LEAF_INLINE_DATA_FLAG != 0
+}
+
 fn encode_value(buf: &mut Vec<u8>, value: &Value) {
     match value {
         Value::Null => buf.push(TAG_NULL),
@@ -164,6 +224,11 @@ fn encode_value(buf: &mut Vec<u8>, value: &Value) {
             buf.push(TAG_FLOAT);
             buf.extend_from_slice(&f.to_le_bytes());
         }
+        Value::Decimal(d) => {
+            buf.push(TAG_DECIMAL);
+            buf.extend_from_slice(&d.mantissa().to_le_bytes());
+            buf.push(d.scale() as u8);
+        }
         Value::Text(s) => {
             buf.push(TAG_TEXT);
             buf.extend_from_slice(&(s.len() as u32).to_le_bytes());
@@ -188,6 +253,27 @@ fn encode_value(buf: &mut Vec<u8>, value: &Value) {
             buf.extend_from_slice(&(s.len() as u32).to_le_bytes());
             buf.extend_from_slice(s.as_bytes());
         }
+        Value::TimestampTz(s) => {
+            buf.push(TAG_TIMESTAMPTZ);
+            buf.extend_from_slice(&(s.len() as u32).to_le_bytes());
+            buf.extend_from_slice(s.as_bytes());
+        }
+        Value::Interval(iv) => {
+            buf.push(TAG_INTERVAL);
+            buf.extend_from_slice(&iv.months().to_le_bytes());
+            buf.extend_from_slice(&iv.days().to_le_bytes());
+            buf.extend_from_slice(&iv.micros().to_le_bytes());
+        }
+        Value::Blob(bytes) => {
+            buf.push(TAG_BLOB);
+            buf.extend_from_slice(&(bytes.len() as u32).to_le_bytes());
+            buf.extend_from_slice(bytes);
+        }
+        Value::Json(s) => {
+            buf.push(TAG_JSON);
+            buf.extend_from_slice(&(s.len() as u32).to_le_bytes());
+            buf.extend_from_slice(s.as_bytes());
+        }
     }
 }
 
@@ -225,7 +311,41 @@ fn decode_value(data: &[u8], offset: &mut usize) -> Result<Value, RustqlError> {
             *offset += 8;
             Ok(Value::Float(val))
         }
-        TAG_TEXT | TAG_DATE | TAG_TIME | TAG_DATETIME => {
+        TAG_DECIMAL => {
+            if *offset + 17 > data.len() {
+                return Err(RustqlError::StorageError(
+                    "Truncated decimal in binary entry".to_string(),
+                ));
+            }
+            let mut bytes = [0u8; 16];
+            bytes.copy_from_slice(&data[*offset..*offset + 16]);
+            let scale = data[*offset + 16];
+            *offset += 17;
+            Decimal::new(i128::from_le_bytes(bytes), u32::from(scale))
+                .map(Value::Decimal)
+                .ok_or_else(|| {
+                    RustqlError::StorageError("Invalid decimal in binary entry".to_string())
+                })
+        }
+        TAG_INTERVAL => {
+            if *offset + 16 > data.len() {
+                return Err(RustqlError::StorageError(
+                    "Truncated interval in binary entry".to_string(),
+                ));
+            }
+            let field = &data[*offset..*offset + 16];
+            let months = i32::from_le_bytes([field[0], field[1], field[2], field[3]]);
+            let days = i32::from_le_bytes([field[4], field[5], field[6], field[7]]);
+            let mut micros = [0u8; 8];
+            micros.copy_from_slice(&field[8..]);
+            *offset += 16;
+            Ok(Value::Interval(Interval::new(
+                months,
+                days,
+                i64::from_le_bytes(micros),
+            )))
+        }
+        TAG_TEXT | TAG_DATE | TAG_TIME | TAG_DATETIME | TAG_TIMESTAMPTZ | TAG_JSON => {
             if *offset + 4 > data.len() {
                 return Err(RustqlError::StorageError(
                     "Truncated string length in binary entry".to_string(),
@@ -251,12 +371,33 @@ fn decode_value(data: &[u8], offset: &mut usize) -> Result<Value, RustqlError> {
                 TAG_DATE => Ok(Value::Date(s)),
                 TAG_TIME => Ok(Value::Time(s)),
                 TAG_DATETIME => Ok(Value::DateTime(s)),
+                TAG_TIMESTAMPTZ => Ok(Value::TimestampTz(s)),
+                TAG_JSON => Ok(Value::Json(s)),
                 other => Err(RustqlError::StorageError(format!(
                     "Invalid string-like binary entry tag: {}",
                     other
                 ))),
             }
         }
+        TAG_BLOB => {
+            if *offset + 4 > data.len() {
+                return Err(RustqlError::StorageError(
+                    "Truncated blob length in binary entry".to_string(),
+                ));
+            }
+            let mut len_bytes = [0u8; 4];
+            len_bytes.copy_from_slice(&data[*offset..*offset + 4]);
+            let len = u32::from_le_bytes(len_bytes) as usize;
+            *offset += 4;
+            if *offset + len > data.len() {
+                return Err(RustqlError::StorageError(
+                    "Truncated blob data in binary entry".to_string(),
+                ));
+            }
+            let bytes = data[*offset..*offset + len].to_vec();
+            *offset += len;
+            Ok(Value::Blob(bytes))
+        }
         TAG_BOOLEAN => {
             if *offset >= data.len() {
                 return Err(RustqlError::StorageError(
@@ -282,30 +423,34 @@ impl BTreePage {
             PageKind::Meta => 0u8,
             PageKind::Internal => 1u8,
             PageKind::Leaf => 2u8,
+            PageKind::Free => 3u8,
+            PageKind::Overflow => 4u8,
         };
 
         buf[0..8].copy_from_slice(&self.header.page_id.to_le_bytes());
         buf[8] = kind_byte;
         buf[9..11].copy_from_slice(&self.header.entry_count.to_le_bytes());
         buf[11..13].copy_from_slice(&self.header.reserved.to_le_bytes());
+        buf[PAGE_FLAGS_OFFSET] = PAGE_CHECKSUM_FLAG;
 
         let mut payload = Vec::new();
         for entry in &self.entries {
             encode_value(&mut payload, &entry.key);
-            if self.stores_inline_leaf_data() {
+            if self.stores_inline_data() {
                 let inline_data = entry.inline_data.as_ref().ok_or_else(|| {
-                    RustqlError::StorageError(
-                        "Leaf entry is missing inline data for inline leaf page".to_string(),
-                    )
+                    RustqlError::StorageError(format!(
+                        "{:?} page entry is missing its inline data",
+                        self.header.kind
+                    ))
                 })?;
                 payload.extend_from_slice(&(inline_data.len() as u32).to_le_bytes());
-                payload.extend_from_slice(inline_data.as_bytes());
+                payload.extend_from_slice(inline_data);
             } else {
                 payload.extend_from_slice(&entry.pointer.to_le_bytes());
             }
         }
 
-        if BTREE_PAGE_HEADER_SIZE + payload.len() > BTREE_PAGE_SIZE {
+        if BTREE_PAGE_HEADER_SIZE + payload.len() > PAGE_CHECKSUM_OFFSET {
             return Err(RustqlError::StorageError(
                 "BTreePage too large to fit in fixed page size".to_string(),
             ));
@@ -313,9 +458,22 @@ impl BTreePage {
 
         buf[BTREE_PAGE_HEADER_SIZE..BTREE_PAGE_HEADER_SIZE + payload.len()]
             .copy_from_slice(&payload);
+        let checksum = page_checksum(&buf);
+        buf[PAGE_CHECKSUM_OFFSET..].copy_from_slice(&checksum.to_le_bytes());
         Ok(buf)
     }
 
+    /// Returns `false` when a page that carries a checksum no longer
+    /// matches it.
+    pub(super) fn checksum_matches(buf: &[u8; BTREE_PAGE_SIZE]) -> bool {
+        if buf[PAGE_FLAGS_OFFSET] & PAGE_CHECKSUM_FLAG == 0 {
+            return true;
+        }
+        let mut stored = [0u8; PAGE_CHECKSUM_SIZE];
+        stored.copy_from_slice(&buf[PAGE_CHECKSUM_OFFSET..]);
+        u32::from_le_bytes(stored) == page_checksum(buf)
+    }
+
     pub fn from_bytes(buf: &[u8; BTREE_PAGE_SIZE]) -> Result<Self, RustqlError> {
         let page_id = u64::from_le_bytes([
             buf[0], buf[1], buf[2], buf[3], buf[4], buf[5], buf[6], buf[7],
@@ -324,6 +482,8 @@ impl BTreePage {
             0 => PageKind::Meta,
             1 => PageKind::Internal,
             2 => PageKind::Leaf,
+            3 => PageKind::Free,
+            4 => PageKind::Overflow,
             other => {
                 return Err(RustqlError::StorageError(format!(
                     "Unknown BTree page kind byte: {}",
@@ -372,7 +532,7 @@ impl BTreePage {
         let mut offset = BTREE_PAGE_HEADER_SIZE;
         for _ in 0..entry_count {
             let key = decode_value(buf, &mut offset)?;
-            if kind == PageKind::Leaf && reserved & LEAF_INLINE_DATA_FLAG != 0 {
+            if stores_inline_data(kind, reserved) {
                 if offset + 4 > buf.len() {
                     return Err(RustqlError::StorageError(
                         "Truncated inline data length in binary entry".to_string(),
@@ -387,14 +547,7 @@ impl BTreePage {
                         "Truncated inline data in binary entry".to_string(),
                     ));
                 }
-                let inline_data = std::str::from_utf8(&buf[offset..offset + len])
-                    .map_err(|e| {
-                        RustqlError::StorageError(format!(
-                            "Invalid UTF-8 in inline leaf payload: {}",
-                            e
-                        ))
-                    })?
-                    .to_owned();
+                let inline_data = buf[offset..offset + len].to_vec();
                 offset += len;
                 entries.push(BTreeEntry::with_inline_data(key, inline_data));
                 continue;
@@ -414,3 +567,7 @@ impl BTreePage {
         Ok(BTreePage { header, entries })
     }
 }
+
+fn page_checksum(buf: &[u8; BTREE_PAGE_SIZE]) -> u32 {
+    storage_checksum(&buf[..PAGE_CHECKSUM_OFFSET]) as u32
+}
@@ -0,0 +1,263 @@
+use super::BTreeStorageEngine;
+use super::file::CachedBTreeFile;
+use super::page::BTreeEntry;
+use super::records::{
+    COMPOSITE_INDEX_ENTRY_KEY_PREFIX, INDEX_ENTRY_KEY_PREFIX, IndexEntryRecord, ROW_KEY_PREFIX,
+    composite_index_key_text, format_row_storage_key, parse_composite_index_key_text,
+    parse_index_entry_key, parse_index_key_text, parse_row_storage_key,
+};
+use super::tree::{PageStore, visit_entries_in_order};
+use crate::ast::Value;
+use crate::database::{Database, ReplacedRows, RowGeneration, RowId, RowSource};
+use crate::error::RustqlError;
+use std::sync::Arc;
+
+/// Number of records read per visit to the tree during a scan. The path lock
+/// is released between batches, so scans never hold it while rows are handed
+/// to the executor.
+const ROW_SCAN_BATCH: usize = 256;
+
+/// Serves the rows and index entries of paged tables from the storage file.
+///
+/// The source shares the engine's path lock and page cache, so the pages it
+/// reads count against the configured cache size and a scan always sees the
+/// last committed image.
+pub(super) struct BTreeRowSource {
+    engine: BTreeStorageEngine,
+}
+
+struct Batch<T> {
+    items: Vec<T>,
+    last_key: Option<Value>,
+    exhausted: bool,
+}
+
+/// Reads one record of a prefix scan, or skips it by returning `None`.
+type ReadRecord<'a, T> =
+    dyn FnMut(&CachedBTreeFile<'_>, &BTreeEntry, &str) -> Result<Option<T>, RustqlError> + 'a;
+
+impl BTreeRowSource {
+    pub(super) fn new(engine: &BTreeStorageEngine) -> Self {
+        BTreeRowSource {
+            engine: engine.shared_handle(),
+        }
+    }
+
+    /// Calls `visit` with each record under `prefix` that `read` returns,
+    /// reading the records a batch at a time.
+    fn scan_prefix<T>(
+        &self,
+        prefix: &str,
+        read: &mut ReadRecord<'_, T>,
+        visit: &mut dyn FnMut(T) -> Result<bool, RustqlError>,
+    ) -> Result<(), RustqlError> {
+        let mut after = None;
+        loop {
+            let batch = {
+                let _path_guard = self.engine.path_lock.read().map_err(|e| {
+                    RustqlError::StorageError(format!(
+                        "Failed to acquire BTree storage read lock: {}",
+                        e
+                    ))
+                })?;
+                read_batch(&self.engine, prefix, after.as_ref(), ROW_SCAN_BATCH, read)?
+            };
+            for item in batch.items {
+                if !visit(item)? {
+                    return Ok(());
+                }
+            }
+            if batch.exhausted {
+                return Ok(());
+            }
+            after = batch.last_key;
+        }
+    }
+}
+
+/// Reads up to `limit` records under `prefix` that sort after `after`. The
+/// caller holds the path lock.
+fn read_batch<T>(
+    engine: &BTreeStorageEngine,
+    prefix: &str,
+    after: Option<&Value>,
+    limit: usize,
+    read: &mut ReadRecord<'_, T>,
+) -> Result<Batch<T>, RustqlError> {
+    let mut file = CachedBTreeFile { engine };
+    let root_page_id = file.root_page_id()?;
+    let start = after
+        .cloned()
+        .unwrap_or_else(|| Value::Text(prefix.to_string()));
+
+    let mut batch = Batch {
+        items: Vec::new(),
+        last_key: None,
+        exhausted: true,
+    };
+    let mut seen = 0;
+    visit_entries_in_order(
+        |page_id| file.read_page(page_id),
+        Some(&start),
+        None,
+        root_page_id,
+        |entry| {
+            if after.is_some_and(|after| &entry.key <= after) {
+                return Ok(true);
+            }
+            let Value::Text(key) = &entry.key else {
+                return Ok(false);
+            };
+            if !key.starts_with(prefix) {
+                return Ok(false);
+            }
+            if seen == limit {
+                batch.exhausted = false;
+                return Ok(false);
+            }
+            seen += 1;
+            if let Some(item) = read(&file, &entry, key)? {
+                batch.items.push(item);
+            }
+            batch.last_key = Some(entry.key);
+            Ok(true)
+        },
+    )?;
+    Ok(batch)
+}
+
+impl RowSource for BTreeRowSource {
+    fn scan_rows(
+        &self,
+        table: &str,
+        visit: &mut dyn FnMut(RowId, Vec<Value>) -> Result<bool, RustqlError>,
+    ) -> Result<(), RustqlError> {
+        // Rows of tables whose names share the prefix are skipped.
+        let mut read = |file: &CachedBTreeFile<'_>, entry: &BTreeEntry, key: &str| {
+            match parse_row_storage_key(key) {
+                Some((name, row_id, _)) if name == table => {
+                    let row = file.read_data_from_entry(
+                        entry,
+                        format!("row {} for table {}", row_id.0, table),
+                    )?;
+                    Ok(Some((row_id, row)))
+                }
+                _ => Ok(None),
+            }
+        };
+        self.scan_prefix(
+            &format!("{ROW_KEY_PREFIX}{table}:"),
+            &mut read,
+            &mut |(row_id, row)| visit(row_id, row),
+        )
+    }
+
+    fn fetch_row(&self, table: &str, row_id: RowId) -> Result<Option<Vec<Value>>, RustqlError> {
+        let _path_guard = self.engine.path_lock.read().map_err(|e| {
+            RustqlError::StorageError(format!("Failed to acquire BTree storage read lock: {}", e))
+        })?;
+        let mut file = CachedBTreeFile {
+            engine: &self.engine,
+        };
+        let root_page_id = file.root_page_id()?;
+        let key = Value::Text(format_row_storage_key(table, row_id));
+        match file.get_entry(&key, root_page_id)? {
+            Some(entry) => file
+                .read_data_from_entry(&entry, format!("row {} for table {}", row_id.0, table))
+                .map(Some),
+            None => Ok(None),
+        }
+    }
+
+    fn scan_index(
+        &self,
+        index: &str,
+        composite: bool,
+        prefix: &[Value],
+        visit: &mut dyn FnMut(Vec<Value>, RowId) -> Result<bool, RustqlError>,
+    ) -> Result<(), RustqlError> {
+        let entry_prefix = if composite {
+            COMPOSITE_INDEX_ENTRY_KEY_PREFIX
+        } else {
+            INDEX_ENTRY_KEY_PREFIX
+        };
+        // Keys are encoded value by value, so the entries whose keys start
+        // with `prefix` are the ones whose encoded keys start with its text.
+        let key_prefix = format!("{entry_prefix}{index}:{}", composite_index_key_text(prefix));
+        let mut read = |file: &CachedBTreeFile<'_>, entry: &BTreeEntry, key: &str| {
+            let record: IndexEntryRecord =
+                file.read_data_from_entry(entry, format!("entry of index {}", index))?;
+            if record.index != index {
+                return Ok(None);
+            }
+            let values = parse_index_entry_key(key, entry_prefix, index).and_then(|key_text| {
+                if composite {
+                    parse_composite_index_key_text(key_text)
+                } else {
+                    parse_index_key_text(key_text).map(|value| vec![value])
+                }
+            });
+            match values {
+                Some(values) => Ok(Some((values, record.row_id))),
+                None => Err(RustqlError::StorageError(format!(
+                    "Failed to decode key of index {}: {}",
+                    index, key
+                ))),
+            }
+        };
+        self.scan_prefix(&key_prefix, &mut read, &mut |(values, row_id)| {
+            visit(values, row_id)
+        })
+    }
+
+    fn generation(&self) -> Arc<RowGeneration> {
+        self.engine.row_generation()
+    }
+}
+
+impl BTreeStorageEngine {
+    /// A source that serves the rows this file stores, for paging out the
+    /// tables saved to it. Only current-format files can be paged from.
+    pub(crate) fn row_source(&self) -> Result<Option<Arc<dyn RowSource>>, RustqlError> {
+        let _path_guard = self.path_lock.read().map_err(|e| {
+            RustqlError::StorageError(format!("Failed to acquire BTree storage read lock: {}", e))
+        })?;
+        Ok(self
+            .delta_base_len_locked()?
+            .map(|_| Arc::new(BTreeRowSource::new(self)) as Arc<dyn RowSource>))
+    }
+
+    /// The rows a snapshot save of `db` replaces: every stored row with its
+    /// current values, and every row of `db` the file does not hold yet. Only
+    /// current-format files can have paged readers, and nothing is kept when
+    /// none reads the file. The caller holds the path lock.
+    pub(super) fn image_replaced_rows_locked(
+        &self,
+        db: &Database,
+    ) -> Result<ReplacedRows, RustqlError> {
+        let mut replaced = ReplacedRows::new();
+        if !self.has_row_readers() || self.delta_base_len_locked()?.is_none() {
+            return Ok(replaced);
+        }
+        let mut read = |file: &CachedBTreeFile<'_>, entry: &BTreeEntry, key: &str| {
+            let Some((table, row_id, _)) = parse_row_storage_key(key) else {
+                return Ok(None);
+            };
+            let row: Vec<Value> =
+                file.read_data_from_entry(entry, format!("row {} for table {}", row_id.0, table))?;
+            Ok(Some((table.to_string(), row_id, row)))
+        };
+        let batch = read_batch(self, ROW_KEY_PREFIX, None, usize::MAX, &mut read)?;
+        for (table, row_id, row) in batch.items {
+            replaced.entry(table).or_default().insert(row_id, Some(row));
+        }
+        for (name, table) in &db.tables {
+            let rows = replaced.entry(name.clone()).or_default();
+            for row_id in table.row_ids.iter() {
+                rows.entry(*row_id).or_insert(None);
+            }
+        }
+        replaced.retain(|_, rows| !rows.is_empty());
+        Ok(replaced)
+    }
+}
@@ -1,3 +1,4 @@
+use super::encoding::{decode_key_text, encode_key_text};
 use crate::ast::{ColumnDefinition, TableConstraint, Value};
 use crate::database::RowId;
 use serde::{Deserialize, Serialize};
@@ -5,12 +6,98 @@ use serde::{Deserialize, Serialize};
 pub(super) const LEGACY_ROW_KEY_PREFIX: &str = "row:";
 pub(super) const ROW_KEY_PREFIX: &str = "table_row:";
 pub(super) const ROW_ID_KEY_WIDTH: usize = 20;
+pub(super) const SCHEMA_KEY_PREFIX: &str = "schema:";
+pub(super) const INDEX_KEY_PREFIX: &str = "index:";
+pub(super) const COMPOSITE_INDEX_KEY_PREFIX: &str = "cindex:";
+pub(super) const VIEW_KEY_PREFIX: &str = "view:";
+pub(super) const PREPARED_KEY_PREFIX: &str = "prepared:";
+pub(super) const INDEX_ENTRY_KEY_PREFIX: &str = "index_entry:";
+pub(super) const COMPOSITE_INDEX_ENTRY_KEY_PREFIX: &str = "cindex_entry:";
 
 #[derive(Serialize, Deserialize)]
 pub(super) struct TableStorageRecord {
     pub(super) columns: Vec<ColumnDefinition>,
     pub(super) constraints: Vec<TableConstraint>,
     pub(super) next_row_id: u64,
+    /// Number of stored rows, which lets a table be opened without reading
+    /// them. Missing from records written before demand paging.
+    #[serde(default)]
+    pub(super) row_count: Option<u64>,
+}
+
+/// One `(key, row id)` pair of a single-column or composite index.
+///
+/// Format versions 4 and later store index definitions without their entries and gives
+/// every entry its own B-tree record, so a row change only rewrites the leaf
+/// holding that entry. The index key is part of the B-tree key; the payload
+/// names the index so keys can be split without escaping index names.
+#[derive(Serialize, Deserialize)]
+pub(super) struct IndexEntryRecord {
+    pub(super) index: String,
+    pub(super) row_id: RowId,
+}
+
+/// The fields of an index definition needed to find indexes by table.
+#[derive(Deserialize)]
+pub(super) struct IndexTableRecord {
+    pub(super) table: String,
+}
+
+pub(super) fn format_index_entry_key(
+    prefix: &str,
+    index_name: &str,
+    key_text: &str,
+    row_id: RowId,
+) -> String {
+    format!(
+        "{prefix}{index_name}:{key_text}:{:0width$}",
+        row_id.0,
+        width = ROW_ID_KEY_WIDTH
+    )
+}
+
+/// Returns the encoded index key of an entry key written by
+/// [`format_index_entry_key`].
+pub(super) fn parse_index_entry_key<'a>(
+    key: &'a str,
+    prefix: &str,
+    index_name: &str,
+) -> Option<&'a str> {
+    let rest = key.strip_prefix(prefix)?.strip_prefix(index_name)?;
+    let (key_text, _) = rest.strip_prefix(':')?.rsplit_once(':')?;
+    Some(key_text)
+}
+
+/// Encodes an index key so equal keys always produce the same record key.
+pub(super) fn index_key_text(key: &Value) -> String {
+    encode_key_text(std::slice::from_ref(key))
+}
+
+pub(super) fn composite_index_key_text(key: &[Value]) -> String {
+    encode_key_text(key)
+}
+
+/// Reads the key of a single-column index entry. Files before version 5
+/// wrote index keys as JSON.
+pub(super) fn parse_index_key_text(key_text: &str) -> Option<Value> {
+    if is_legacy_key_text(key_text) {
+        return serde_json::from_str(key_text).ok();
+    }
+    match decode_key_text(key_text)?.as_slice() {
+        [key] => Some(key.clone()),
+        _ => None,
+    }
+}
+
+pub(super) fn parse_composite_index_key_text(key_text: &str) -> Option<Vec<Value>> {
+    if is_legacy_key_text(key_text) {
+        return serde_json::from_str(key_text).ok();
+    }
+    decode_key_text(key_text)
+}
+
+fn is_legacy_key_text(key_text: &str) -> bool {
+    key_text.starts_with(['{', '[', '"'])
 }
 
 pub(super) fn format_row_storage_key(table_name: &str, row_id: RowId) -> String {
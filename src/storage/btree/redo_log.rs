@@ -0,0 +1,427 @@
+use super::super::atomic_file::sync_parent_dir;
+use super::BTreeStorageEngine;
+use super::cipher::{PageCipher, page_image};
+use super::delta::write_frames;
+use super::header::{FILE_HEADER_SIZE, HEADER_RESERVED};
+use super::journal::{BTreePageDelta, BTreePageFrame, JOURNAL_MAGIC, storage_checksum};
+use super::page::{BTREE_PAGE_SIZE, BTreePage};
+use crate::error::RustqlError;
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::io::{Seek, SeekFrom, Write};
+use std::path::PathBuf;
+use std::sync::{Condvar, Mutex, MutexGuard, RwLock};
+
+/// Journal version of the append-only redo log. Versions 1 to 3 hold a single
+/// transaction journal and are still recovered on load.
+pub(super) const REDO_LOG_VERSION: u32 = 4;
+
+/// Default number of logged pages after which a commit checkpoints the log.
+pub const DEFAULT_CHECKPOINT_PAGES: usize = 1000;
+
+/// Sequence number, base and target file length, and frame count.
+const RECORD_HEADER_SIZE: usize = 8 + 8 + 8 + 4;
+const FRAME_SIZE: usize = 8 + BTREE_PAGE_SIZE;
+const RECORD_CHECKSUM_SIZE: usize = 8;
+
+/// Append-only redo log of committed page changes, kept in the `.wal` file.
+///
+/// Every commit appends one record holding the after-images of the pages it
+/// wrote, then waits until the record is synced. Commits that append while a
+/// sync is running are made durable together by the next one, so concurrent
+/// committers share fsyncs. The main file is only written by a checkpoint;
+/// until then the logged pages are served from memory.
+pub(super) struct RedoLog {
+    path: PathBuf,
+    state: Mutex<LogState>,
+    synced: Condvar,
+    logged: RwLock<LoggedPages>,
+}
+
+struct LogState {
+    file: Option<fs::File>,
+    appended_lsn: u64,
+    durable_lsn: u64,
+    syncing: bool,
+    failure: Option<String>,
+    records: u64,
+    syncs: u64,
+}
+
+/// Latest image of every page written since the last checkpoint.
+#[derive(Default)]
+struct LoggedPages {
+    pages: HashMap<u64, BTreePage>,
+    file_len: u64,
+}
+
+impl RedoLog {
+    pub(super) fn new(path: PathBuf) -> Self {
+        RedoLog {
+            path,
+            state: Mutex::new(LogState {
+                file: None,
+                appended_lsn: 0,
+                durable_lsn: 0,
+                syncing: false,
+                failure: None,
+                records: 0,
+                syncs: 0,
+            }),
+            synced: Condvar::new(),
+            logged: RwLock::new(LoggedPages::default()),
+        }
+    }
+
+    fn lock_state(&self) -> MutexGuard<'_, LogState> {
+        self.state
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    pub(super) fn logged_page(&self, page_id: u64) -> Option<BTreePage> {
+        let logged = self
+            .logged
+            .read()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        logged.pages.get(&page_id).cloned()
+    }
+
+    pub(super) fn logged_file_len(&self) -> u64 {
+        let logged = self
+            .logged
+            .read()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        logged.file_len
+    }
+
+    pub(super) fn logged_page_count(&self) -> usize {
+        let logged = self
+            .logged
+            .read()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        logged.pages.len()
+    }
+
+    /// Returns `true` while this process has records in the log file.
+    pub(super) fn is_open(&self) -> bool {
+        self.lock_state().file.is_some()
+    }
+
+    pub(super) fn stats(&self) -> (u64, u64, usize) {
+        let (records, syncs) = {
+            let state = self.lock_state();
+            (state.records, state.syncs)
+        };
+        (records, syncs, self.logged_page_count())
+    }
+
+    fn check_usable(&self, state: &LogState) -> Result<(), RustqlError> {
+        match &state.failure {
+            Some(failure) => Err(RustqlError::StorageError(format!(
+                "Redo log '{}' is unusable after a failed sync ({}); reopen the database to recover",
+                self.path.display(),
+                failure
+            ))),
+            None => Ok(()),
+        }
+    }
+
+    /// Appends `delta` as the next record without syncing it and makes its
+    /// pages visible to readers. Must be called under the storage write lock.
+    pub(super) fn append(
+        &self,
+        delta: &BTreePageDelta,
+        pages: BTreeMap<u64, BTreePage>,
+    ) -> Result<u64, RustqlError> {
+        let mut state = self.lock_state();
+        self.check_usable(&state)?;
+        let lsn = state.appended_lsn + 1;
+        let record = encode_record(lsn, delta);
+        let file = match state.file.take() {
+            Some(file) => file,
+            None => self.create_log_file()?,
+        };
+        let file = state.file.insert(file);
+        let log_len = file
+            .metadata()
+            .map_err(|e| {
+                RustqlError::StorageError(format!(
+                    "Failed to get redo log metadata '{}': {}",
+                    self.path.display(),
+                    e
+                ))
+            })?
+            .len();
+        if let Err(e) = file.write_all(&record) {
+            let _ = file.set_len(log_len);
+            let _ = file.seek(SeekFrom::Start(log_len));
+            return Err(RustqlError::StorageError(format!(
+                "Failed to append to redo log '{}': {}",
+                self.path.display(),
+                e
+            )));
+        }
+        state.appended_lsn = lsn;
+        state.records += 1;
+        drop(state);
+
+        let mut logged = self
+            .logged
+            .write()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        logged.pages.extend(pages);
+        logged.file_len = logged.file_len.max(delta.target_file_len);
+        Ok(lsn)
+    }
+
+    fn create_log_file(&self) -> Result<fs::File, RustqlError> {
+        let mut file = fs::OpenOptions::new()
+            .create(true)
+            .truncate(true)
+            .write(true)
+            .open(&self.path)
+            .map_err(|e| {
+                RustqlError::StorageError(format!(
+                    "Failed to create redo log '{}': {}",
+                    self.path.display(),
+                    e
+                ))
+            })?;
+        let mut header = Vec::with_capacity(FILE_HEADER_SIZE);
+        header.extend_from_slice(&JOURNAL_MAGIC);
+        header.extend_from_slice(&REDO_LOG_VERSION.to_le_bytes());
+        header.extend_from_slice(&HEADER_RESERVED.to_le_bytes());
+        file.write_all(&header).map_err(|e| {
+            RustqlError::StorageError(format!(
+                "Failed to write redo log header '{}': {}",
+                self.path.display(),
+                e
+            ))
+        })?;
+        file.sync_all().map_err(|e| {
+            RustqlError::StorageError(format!(
+                "Failed to sync redo log '{}': {}",
+                self.path.display(),
+                e
+            ))
+        })?;
+        sync_parent_dir(&self.path)?;
+        Ok(file)
+    }
+
+    /// Blocks until the record `lsn` is durable. The first waiter syncs
+    /// everything appended so far; commits that arrive meanwhile wait for the
+    /// next sync instead of issuing their own.
+    pub(super) fn sync_to(&self, lsn: u64) -> Result<(), RustqlError> {
+        let mut state = self.lock_state();
+        loop {
+            self.check_usable(&state)?;
+            if state.durable_lsn >= lsn {
+                return Ok(());
+            }
+            if state.syncing {
+                state = self
+                    .synced
+                    .wait(state)
+                    .unwrap_or_else(|poisoned| poisoned.into_inner());
+                continue;
+            }
+
+            state.syncing = true;
+            let target = state.appended_lsn;
+            let file = state.file.as_ref().map(fs::File::try_clone).transpose();
+            drop(state);
+            let result = file.and_then(|file| match file {
+                Some(file) => file.sync_data(),
+                None => Ok(()),
+            });
+            state = self.lock_state();
+            state.syncing = false;
+            match result {
+                Ok(()) => {
+                    state.durable_lsn = state.durable_lsn.max(target);
+                    state.syncs += 1;
+                }
+                Err(e) => state.failure = Some(e.to_string()),
+            }
+            self.synced.notify_all();
+        }
+    }
+
+    /// Blocks until every record appended so far is durable.
+    pub(super) fn sync_appended(&self) -> Result<(), RustqlError> {
+        let lsn = self.lock_state().appended_lsn;
+        self.sync_to(lsn)
+    }
+
+    /// Returns every logged page as one delta over the main file, or `None`
+    /// when nothing has been logged since the last checkpoint.
+    pub(super) fn checkpoint_delta(
+        &self,
+        base_file_len: u64,
+        cipher: Option<&PageCipher>,
+    ) -> Result<Option<BTreePageDelta>, RustqlError> {
+        self.check_usable(&self.lock_state())?;
+        let logged = self
+            .logged
+            .read()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        if logged.pages.is_empty() {
+            return Ok(None);
+        }
+        let ordered: BTreeMap<u64, &BTreePage> =
+            logged.pages.iter().map(|(id, page)| (*id, page)).collect();
+        let mut frames = Vec::with_capacity(ordered.len());
+        for (page_id, page) in ordered {
+            let bytes = page_image(page, cipher)?.to_vec();
+            frames.push(BTreePageFrame {
+                page_id,
+                checksum: storage_checksum(&bytes),
+                bytes,
+            });
+        }
+        Ok(Some(BTreePageDelta {
+            page_size: BTREE_PAGE_SIZE,
+            base_file_len,
+            target_file_len: base_file_len.max(logged.file_len),
+            frames,
+        }))
+    }
+
+    /// Drops the log once its pages are durable in the main file.
+    fn finish_checkpoint(&self) -> Result<(), RustqlError> {
+        let mut state = self.lock_state();
+        if state.file.take().is_some() || self.path.exists() {
+            fs::remove_file(&self.path).map_err(|e| {
+                RustqlError::StorageError(format!(
+                    "Failed to remove redo log '{}': {}",
+                    self.path.display(),
+                    e
+                ))
+            })?;
+            sync_parent_dir(&self.path)?;
+        }
+        state.durable_lsn = state.appended_lsn;
+        self.synced.notify_all();
+        drop(state);
+
+        let mut logged = self
+            .logged
+            .write()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        logged.pages.clear();
+        logged.file_len = 0;
+        Ok(())
+    }
+}
+
+fn encode_record(lsn: u64, delta: &BTreePageDelta) -> Vec<u8> {
+    let mut record = Vec::with_capacity(
+        RECORD_HEADER_SIZE + delta.frames.len() * FRAME_SIZE + RECORD_CHECKSUM_SIZE,
+    );
+    record.extend_from_slice(&lsn.to_le_bytes());
+    record.extend_from_slice(&delta.base_file_len.to_le_bytes());
+    record.extend_from_slice(&delta.target_file_len.to_le_bytes());
+    record.extend_from_slice(&(delta.frames.len() as u32).to_le_bytes());
+    for frame in &delta.frames {
+        record.extend_from_slice(&frame.page_id.to_le_bytes());
+        record.extend_from_slice(&frame.bytes);
+    }
+    let checksum = storage_checksum(&record);
+    record.extend_from_slice(&checksum.to_le_bytes());
+    record
+}
+
+fn read_u64(bytes: &[u8], offset: usize) -> Option<u64> {
+    let bytes = bytes.get(offset..offset + 8)?;
+    Some(u64::from_le_bytes(bytes.try_into().ok()?))
+}
+
+/// Decodes the record at the start of `bytes`, returning its length, sequence
+/// number, and page delta. Returns `None` for a truncated or corrupt record,
+/// which marks the torn tail of a log whose last append never completed.
+fn decode_record(bytes: &[u8]) -> Option<(usize, u64, BTreePageDelta)> {
+    let lsn = read_u64(bytes, 0)?;
+    let base_file_len = read_u64(bytes, 8)?;
+    let target_file_len = read_u64(bytes, 16)?;
+    let frame_count = u32::from_le_bytes(bytes.get(24..28)?.try_into().ok()?) as usize;
+    let body_len = frame_count
+        .checked_mul(FRAME_SIZE)?
+        .checked_add(RECORD_HEADER_SIZE)?;
+    let checksum = read_u64(bytes, body_len)?;
+    if storage_checksum(&bytes[..body_len]) != checksum {
+        return None;
+    }
+
+    let mut frames = Vec::with_capacity(frame_count);
+    for index in 0..frame_count {
+        let offset = RECORD_HEADER_SIZE + index * FRAME_SIZE;
+        let page_bytes = bytes[offset + 8..offset + FRAME_SIZE].to_vec();
+        frames.push(BTreePageFrame {
+            page_id: read_u64(bytes, offset)?,
+            checksum: storage_checksum(&page_bytes),
+            bytes: page_bytes,
+        });
+    }
+    let delta = BTreePageDelta {
+        page_size: BTREE_PAGE_SIZE,
+        base_file_len,
+        target_file_len,
+        frames,
+    };
+    Some((body_len + RECORD_CHECKSUM_SIZE, lsn, delta))
+}
+
+/// Folds the complete records of a redo log into one delta holding the last
+/// image of every page. Decoding stops at the first incomplete record.
+pub(super) fn decode_redo_log(payload: &[u8]) -> Option<BTreePageDelta> {
+    let mut offset = 0;
+    let mut expected_lsn = None;
+    let mut merged: Option<BTreePageDelta> = None;
+    let mut pages = BTreeMap::new();
+    while let Some((len, lsn, delta)) = decode_record(&payload[offset..]) {
+        // Sequence numbers continue across checkpoints, so a log starts at
+        // whichever number its first record carries.
+        if expected_lsn.is_some_and(|expected| expected != lsn) {
+            break;
+        }
+        offset += len;
+        expected_lsn = Some(lsn + 1);
+        let merged = merged.get_or_insert_with(|| BTreePageDelta {
+            page_size: BTREE_PAGE_SIZE,
+            base_file_len: delta.base_file_len,
+            target_file_len: delta.base_file_len,
+            frames: Vec::new(),
+        });
+        merged.target_file_len = merged.target_file_len.max(delta.target_file_len);
+        for frame in delta.frames {
+            pages.insert(frame.page_id, frame);
+        }
+    }
+
+    let mut merged = merged?;
+    merged.frames = pages.into_values().collect();
+    Some(merged)
+}
+
+impl BTreeStorageEngine {
+    /// Writes every logged page into the main file, syncs it, and removes the
+    /// log. Returns the number of pages written.
+    pub(super) fn checkpoint_locked(&self) -> Result<usize, RustqlError> {
+        let base_file_len = fs::metadata(&self.data_path)
+            .map(|metadata| metadata.len())
+            .unwrap_or(0);
+        let Some(delta) = self
+            .redo_log
+            .checkpoint_delta(base_file_len, self.page_cipher().as_ref())?
+        else {
+            return Ok(0);
+        };
+        let mut file = self.open_data_file_for_delta()?;
+        write_frames(&mut file, &delta)?;
+        drop(file);
+        self.redo_log.finish_checkpoint()?;
+        Ok(delta.frames.len())
+    }
+}
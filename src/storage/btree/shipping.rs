@@ -0,0 +1,551 @@
+use super::super::atomic_file::{atomic_write, sync_parent_dir};
+use super::BTreeStorageEngine;
+use super::header::FILE_HEADER_SIZE;
+use super::journal::{
+    BTreePageDelta, BTreeRedoJournal, JOURNAL_MAGIC, JOURNAL_VERSION, TransactionJournal,
+    encode_journal, read_storage_file_bytes,
+};
+use crate::database::Database;
+use crate::error::RustqlError;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::Duration;
+
+/// File name extension of a shipped image of the whole storage file.
+const IMAGE_EXTENSION: &str = "image";
+/// File name extension of the pages shipped for one commit.
+const PAGES_EXTENSION: &str = "pages";
+
+/// Where a primary engine ships its commits.
+#[derive(Debug, Clone)]
+pub enum ReplicationTarget {
+    /// One file per record in this directory, which is created if needed.
+    Directory(PathBuf),
+    /// The sending half of a [`log_channel`] in the same process.
+    Channel(LogSender),
+}
+
+/// Where a follower engine reads the commits shipped by its primary.
+#[derive(Debug, Clone)]
+pub enum ReplicationSource {
+    /// The directory a primary ships to with [`ReplicationTarget::Directory`].
+    Directory(PathBuf),
+    /// The receiving half of a [`log_channel`] in the same process.
+    Channel(LogReceiver),
+}
+
+/// The primary's end of an in-process log channel.
+#[derive(Debug, Clone)]
+pub struct LogSender {
+    sender: Sender<ShippedRecord>,
+}
+
+/// The follower's end of an in-process log channel.
+#[derive(Debug, Clone)]
+pub struct LogReceiver {
+    receiver: Arc<Mutex<Receiver<ShippedRecord>>>,
+}
+
+/// Creates a channel that ships a primary's commits to a follower in the same
+/// process. Records are buffered until the follower reads them.
+pub fn log_channel() -> (LogSender, LogReceiver) {
+    let (sender, receiver) = mpsc::channel();
+    (
+        LogSender { sender },
+        LogReceiver {
+            receiver: Arc::new(Mutex::new(receiver)),
+        },
+    )
+}
+
+impl LogReceiver {
+    fn lock(&self) -> MutexGuard<'_, Receiver<ShippedRecord>> {
+        self.receiver
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+/// A shipped record: an encoded [`TransactionJournal`] holding either the
+/// whole storage file or the pages of one commit.
+#[derive(Debug)]
+struct ShippedRecord {
+    seq: u64,
+    image: bool,
+    bytes: Vec<u8>,
+}
+
+/// Ships the records of a primary engine, numbered in the order they were
+/// written.
+///
+/// A primary first ships an image of its whole file, then every commit as
+/// the page images it wrote, and another whole image whenever it rewrites
+/// the file. A follower can start at any image; records before the latest
+/// image are no longer needed and are removed from a log directory.
+///
+/// A commit is queued when it is appended to the redo log and shipped only
+/// once the log has synced it, so a follower never applies a commit the
+/// primary could still lose.
+pub(super) struct LogShipper {
+    target: ReplicationTarget,
+    state: Mutex<ShipperState>,
+}
+
+struct ShipperState {
+    next_seq: Option<u64>,
+    /// Commits appended to the redo log but not shipped yet, by their
+    /// sequence number in the log.
+    queued: BTreeMap<u64, BTreePageDelta>,
+    /// The sequence number of the last durable commit a failed attempt was
+    /// shipping; the commits up to it are still queued.
+    held: Option<u64>,
+}
+
+impl LogShipper {
+    pub(super) fn new(target: ReplicationTarget) -> Self {
+        LogShipper {
+            target,
+            state: Mutex::new(ShipperState {
+                next_seq: None,
+                queued: BTreeMap::new(),
+                held: None,
+            }),
+        }
+    }
+
+    fn lock_state(&self) -> MutexGuard<'_, ShipperState> {
+        self.state
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// Queues the pages of the commit logged as `lsn` until it is durable.
+    fn queue(&self, lsn: u64, delta: BTreePageDelta) {
+        self.lock_state().queued.insert(lsn, delta);
+    }
+
+    /// Ships the queued commits up to and including `durable_lsn`, in the
+    /// order they were logged. A commit that fails to ship stays queued,
+    /// with the ones after it, for the next call to retry.
+    fn ship_durable(&self, durable_lsn: u64) -> Result<(), RustqlError> {
+        let mut state = self.lock_state();
+        while let Some(entry) = state.queued.first_entry() {
+            if *entry.key() > durable_lsn {
+                break;
+            }
+            let journal = TransactionJournal::Delta {
+                delta: entry.get().clone(),
+            };
+            self.ship_locked(&mut state, &journal)?;
+            state.queued.pop_first();
+        }
+        if state.held.is_some_and(|held| held <= durable_lsn) {
+            state.held = None;
+        }
+        Ok(())
+    }
+
+    /// Ships the queued commits up to `durable_lsn` like
+    /// [`ship_durable`](Self::ship_durable), but holds on to a failure
+    /// instead of returning it, for [`ship_held`](Self::ship_held) to report.
+    fn ship_or_hold(&self, durable_lsn: u64) {
+        if self.ship_durable(durable_lsn).is_err() {
+            let mut state = self.lock_state();
+            state.held = Some(state.held.map_or(durable_lsn, |held| held.max(durable_lsn)));
+        }
+    }
+
+    /// Retries the durable commits a failed attempt left queued, failing if
+    /// they still cannot be shipped.
+    fn ship_held(&self) -> Result<(), RustqlError> {
+        let Some(held) = self.lock_state().held else {
+            return Ok(());
+        };
+        self.ship_durable(held).map_err(|e| {
+            RustqlError::StorageError(format!(
+                "Commits made earlier could not be shipped to the follower: {}",
+                e
+            ))
+        })
+    }
+
+    /// Ships the pages of a commit written to the file without going
+    /// through the redo log, after the commits queued before it. Those are
+    /// durable, since the checkpoint that preceded the commit folded them
+    /// into the file.
+    fn ship_unlogged(&self, delta: BTreePageDelta) -> Result<(), RustqlError> {
+        self.ship_durable(u64::MAX)?;
+        let mut state = self.lock_state();
+        self.ship_locked(&mut state, &TransactionJournal::Delta { delta })
+    }
+
+    /// Ships an image of the whole file. It holds every commit queued so
+    /// far, so they are not shipped on their own.
+    fn ship_image(&self, journal: &TransactionJournal) -> Result<(), RustqlError> {
+        let mut state = self.lock_state();
+        state.queued.clear();
+        state.held = None;
+        self.ship_locked(&mut state, journal)
+    }
+
+    fn ship_locked(
+        &self,
+        state: &mut ShipperState,
+        journal: &TransactionJournal,
+    ) -> Result<(), RustqlError> {
+        let image = matches!(journal, TransactionJournal::Committed { .. });
+        let bytes = encode_journal(journal).map_err(|e| {
+            RustqlError::StorageError(format!("Failed to serialize shipped log record: {}", e))
+        })?;
+        let seq = match state.next_seq {
+            Some(seq) => seq,
+            None => self.first_seq()?,
+        };
+        match &self.target {
+            ReplicationTarget::Directory(dir) => {
+                fs::create_dir_all(dir).map_err(|e| {
+                    RustqlError::StorageError(format!(
+                        "Failed to create log shipping directory '{}': {}",
+                        dir.display(),
+                        e
+                    ))
+                })?;
+                atomic_write(&record_path(dir, seq, image), &bytes)?;
+                if image {
+                    remove_records_before(dir, seq)?;
+                }
+            }
+            ReplicationTarget::Channel(channel) => {
+                // A follower that went away only stops receiving.
+                let _ = channel.sender.send(ShippedRecord { seq, image, bytes });
+            }
+        }
+        state.next_seq = Some(seq + 1);
+        Ok(())
+    }
+
+    /// Numbers continue after the records already in a log directory, so a
+    /// restarted primary never reuses one.
+    fn first_seq(&self) -> Result<u64, RustqlError> {
+        match &self.target {
+            ReplicationTarget::Directory(dir) => Ok(list_records(dir)?
+                .last()
+                .map(|record| record.seq + 1)
+                .unwrap_or(1)),
+            ReplicationTarget::Channel(_) => Ok(1),
+        }
+    }
+}
+
+/// A record found in a log directory.
+struct RecordFile {
+    seq: u64,
+    image: bool,
+    path: PathBuf,
+}
+
+fn record_path(dir: &Path, seq: u64, image: bool) -> PathBuf {
+    let extension = if image {
+        IMAGE_EXTENSION
+    } else {
+        PAGES_EXTENSION
+    };
+    dir.join(format!("{:020}.{}", seq, extension))
+}
+
+/// Lists the records in a log directory by sequence number, skipping files
+/// that are not records, such as those still being written.
+fn list_records(dir: &Path) -> Result<Vec<RecordFile>, RustqlError> {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(error) if error.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(error) => {
+            return Err(RustqlError::StorageError(format!(
+                "Failed to read log shipping directory '{}': {}",
+                dir.display(),
+                error
+            )));
+        }
+    };
+    let mut records = Vec::new();
+    for entry in entries.flatten() {
+        let path = entry.path();
+        let (Some(stem), Some(extension)) = (
+            path.file_stem().and_then(|stem| stem.to_str()),
+            path.extension().and_then(|extension| extension.to_str()),
+        ) else {
+            continue;
+        };
+        let image = match extension {
+            IMAGE_EXTENSION => true,
+            PAGES_EXTENSION => false,
+            _ => continue,
+        };
+        if stem.len() != 20 || !stem.bytes().all(|byte| byte.is_ascii_digit()) {
+            continue;
+        }
+        if let Ok(seq) = stem.parse() {
+            records.push(RecordFile { seq, image, path });
+        }
+    }
+    records.sort_by_key(|record| record.seq);
+    Ok(records)
+}
+
+fn remove_records_before(dir: &Path, seq: u64) -> Result<(), RustqlError> {
+    for record in list_records(dir)? {
+        if record.seq >= seq {
+            break;
+        }
+        match fs::remove_file(&record.path) {
+            Ok(()) => {}
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => {}
+            Err(error) => {
+                return Err(RustqlError::StorageError(format!(
+                    "Failed to remove shipped log record '{}': {}",
+                    record.path.display(),
+                    error
+                )));
+            }
+        }
+    }
+    sync_parent_dir(&record_path(dir, seq, true))
+}
+
+fn decode_record(bytes: &[u8]) -> Result<TransactionJournal, RustqlError> {
+    if bytes.len() < FILE_HEADER_SIZE
+        || bytes[0..8] != JOURNAL_MAGIC
+        || bytes[8..12] != JOURNAL_VERSION.to_le_bytes()
+    {
+        return Err(RustqlError::StorageError(
+            "Shipped log record has an invalid header".to_string(),
+        ));
+    }
+    serde_json::from_slice(&bytes[FILE_HEADER_SIZE..]).map_err(|e| {
+        RustqlError::StorageError(format!("Failed to decode shipped log record: {}", e))
+    })
+}
+
+/// Reads the records a primary shipped and applies them to a follower's
+/// storage file in order.
+pub(crate) struct LogFollower {
+    source: ReplicationSource,
+    /// The record to apply next, or `None` until an image has been applied.
+    next_seq: Option<u64>,
+}
+
+impl LogFollower {
+    pub(crate) fn new(source: ReplicationSource) -> Self {
+        LogFollower {
+            source,
+            next_seq: None,
+        }
+    }
+
+    /// Applies every record that has been shipped so far and follows on from
+    /// the last one applied, waiting up to `timeout` for one to arrive.
+    /// Returns whether the file changed.
+    pub(crate) fn poll(
+        &mut self,
+        storage: &BTreeStorageEngine,
+        timeout: Duration,
+    ) -> Result<bool, RustqlError> {
+        match self.source.clone() {
+            ReplicationSource::Directory(dir) => {
+                let applied = self.poll_directory(storage, &dir)?;
+                if applied || timeout.is_zero() {
+                    return Ok(applied);
+                }
+                std::thread::sleep(timeout);
+                self.poll_directory(storage, &dir)
+            }
+            ReplicationSource::Channel(channel) => {
+                let receiver = channel.lock();
+                let mut applied = false;
+                let mut next = receiver.recv_timeout(timeout).ok();
+                while let Some(record) = next {
+                    applied |= self.apply_received(storage, record)?;
+                    next = receiver.try_recv().ok();
+                }
+                Ok(applied)
+            }
+        }
+    }
+
+    fn poll_directory(
+        &mut self,
+        storage: &BTreeStorageEngine,
+        dir: &Path,
+    ) -> Result<bool, RustqlError> {
+        let records = list_records(dir)?;
+        // Start from the next record, or from the latest image when that
+        // record is gone or no image has been applied yet.
+        let start = match self.next_seq {
+            Some(next) if records.iter().any(|record| record.seq == next) => Some(next),
+            next => records
+                .iter()
+                .rev()
+                .find(|record| record.image && next.is_none_or(|next| record.seq >= next))
+                .map(|record| record.seq),
+        };
+        let Some(start) = start else {
+            return Ok(false);
+        };
+
+        let mut applied = false;
+        for record in records.iter().skip_while(|record| record.seq < start) {
+            if Some(record.seq) != self.next_seq && !(record.seq == start && record.image) {
+                break;
+            }
+            let bytes = match fs::read(&record.path) {
+                Ok(bytes) => bytes,
+                // Removed behind a newer image; the next poll starts there.
+                Err(error) if error.kind() == std::io::ErrorKind::NotFound => break,
+                Err(error) => {
+                    return Err(RustqlError::StorageError(format!(
+                        "Failed to read shipped log record '{}': {}",
+                        record.path.display(),
+                        error
+                    )));
+                }
+            };
+            storage.apply_shipped(decode_record(&bytes)?)?;
+            self.next_seq = Some(record.seq + 1);
+            applied = true;
+        }
+        Ok(applied)
+    }
+
+    fn apply_received(
+        &mut self,
+        storage: &BTreeStorageEngine,
+        record: ShippedRecord,
+    ) -> Result<bool, RustqlError> {
+        if Some(record.seq) != self.next_seq && !record.image {
+            return Ok(false);
+        }
+        storage.apply_shipped(decode_record(&record.bytes)?)?;
+        self.next_seq = Some(record.seq + 1);
+        Ok(true)
+    }
+}
+
+impl BTreeStorageEngine {
+    /// Ships every commit to `target`, where a follower opened with
+    /// [`EngineOptions::follower`](crate::EngineOptions::follower) applies
+    /// it. Whole-file rewrites ship an image of the new file.
+    pub fn with_log_shipping(mut self, target: ReplicationTarget) -> Self {
+        self.shipper = Some(Arc::new(LogShipper::new(target)));
+        self
+    }
+
+    /// Ships an image of the committed file for followers to start from,
+    /// creating the file if nothing has been stored yet.
+    pub(crate) fn ship_base_image(&self) -> Result<(), RustqlError> {
+        if self.shipper.is_none() {
+            return Ok(());
+        }
+        let _path_guard = self.path_lock.write().map_err(|e| {
+            RustqlError::StorageError(format!("Failed to acquire BTree storage write lock: {}", e))
+        })?;
+        self.file_lock.lock_exclusive()?;
+        if read_storage_file_bytes(&self.data_path)?.is_empty() {
+            // Saving a snapshot ships its image.
+            return self.save_locked(&Database::new());
+        }
+        self.checkpoint_locked()?;
+        self.ship_image_locked()
+    }
+
+    /// Ships an image of the storage file after a snapshot save.
+    pub(super) fn ship_image_locked(&self) -> Result<(), RustqlError> {
+        let Some(shipper) = &self.shipper else {
+            return Ok(());
+        };
+        let bytes = read_storage_file_bytes(&self.data_path)?;
+        shipper.ship_image(&TransactionJournal::Committed {
+            redo: BTreeRedoJournal::from_file_images(&[], &bytes)?,
+        })
+    }
+
+    /// Queues the pages of the commit just logged as `lsn` for shipping.
+    pub(super) fn queue_delta_locked(&self, lsn: u64, delta: BTreePageDelta) {
+        if let Some(shipper) = &self.shipper {
+            shipper.queue(lsn, delta);
+        }
+    }
+
+    /// Ships the pages of a commit applied to the file in place.
+    pub(super) fn ship_delta_locked(&self, delta: BTreePageDelta) -> Result<(), RustqlError> {
+        match &self.shipper {
+            Some(shipper) => shipper.ship_unlogged(delta),
+            None => Ok(()),
+        }
+    }
+
+    /// Ships the commits the redo log has made durable, up to `lsn`, after
+    /// the commit logged as `lsn` has been made. Runs without the storage
+    /// lock, so commits keep sharing syncs while a log directory is written.
+    /// The commit stands either way, so a failure is held back and reported
+    /// by [`ship_held`](Self::ship_held) when the next commit starts.
+    pub(super) fn ship_committed(&self, lsn: u64) {
+        if let Some(shipper) = &self.shipper {
+            shipper.ship_or_hold(lsn);
+        }
+    }
+
+    /// Ships the durable commits an earlier failure left queued. Fails if
+    /// they still cannot be shipped, so that no further commit is made
+    /// until the follower has caught up with the ones before it.
+    pub(super) fn ship_held(&self) -> Result<(), RustqlError> {
+        match &self.shipper {
+            Some(shipper) => shipper.ship_held(),
+            None => Ok(()),
+        }
+    }
+
+    /// Applies a record shipped by a primary to this follower's file.
+    fn apply_shipped(&self, journal: TransactionJournal) -> Result<(), RustqlError> {
+        let _path_guard = self.path_lock.write().map_err(|e| {
+            RustqlError::StorageError(format!("Failed to acquire BTree storage write lock: {}", e))
+        })?;
+        self.file_lock.lock_exclusive()?;
+        let result = match journal {
+            TransactionJournal::Committed { redo } => {
+                redo.validate()?;
+                let image = file_image(&redo);
+                if !redo.current_matches_target(&image) {
+                    return Err(RustqlError::StorageError(
+                        "Shipped BTree image does not match its checksum".to_string(),
+                    ));
+                }
+                atomic_write(&self.data_path, &image)
+            }
+            TransactionJournal::Delta { delta } => self.apply_page_delta_locked(&delta),
+            TransactionJournal::Pending => Err(RustqlError::StorageError(
+                "Shipped log record holds no pages".to_string(),
+            )),
+            TransactionJournal::Linked { .. } | TransactionJournal::LinkedDelta { .. } => {
+                Err(RustqlError::StorageError(
+                    "Shipped log record belongs to a commit across attached databases".to_string(),
+                ))
+            }
+        };
+        self.clear_cache();
+        result
+    }
+}
+
+/// Rebuilds the file a redo journal over an empty base describes.
+fn file_image(redo: &BTreeRedoJournal) -> Vec<u8> {
+    let mut image = vec![0; redo.target_file_len as usize];
+    image[..FILE_HEADER_SIZE].copy_from_slice(&redo.storage_header);
+    for frame in &redo.frames {
+        let offset = FILE_HEADER_SIZE + frame.page_id as usize * redo.page_size;
+        image[offset..offset + redo.page_size].copy_from_slice(&frame.bytes);
+    }
+    image
+}
@@ -1,16 +1,35 @@
+use super::cipher::{
+    EncryptionKey, KEY_CHECK_SIZE, PageCipher, aead_tag, chacha20_xor, hchacha20, poly1305,
+};
+use super::encoding::{decode_record, encode_record};
 use super::file::BTreeFile;
-use super::header::{FILE_HEADER_SIZE, HEADER_RESERVED, write_versioned_header};
+use super::header::{
+    FILE_HEADER_SIZE, HEADER_RESERVED, VersionedFileState, read_versioned_header_with_versions,
+    write_versioned_header,
+};
 use super::journal::{
     JOURNAL_MAGIC, JOURNAL_VERSION, LEGACY_JOURNAL_VERSION, LegacyTransactionJournal,
-    TransactionJournal,
+    LoadedTransactionJournal, TransactionJournal,
 };
 use super::page::{BTREE_PAGE_SIZE, BTreeEntry, BTreePage, LEAF_INLINE_DATA_FLAG, PageKind};
-use super::records::TableStorageRecord;
+use super::records::{
+    TableStorageRecord, composite_index_key_text, parse_composite_index_key_text,
+    parse_index_key_text,
+};
+use super::tree::PageStore;
 use super::*;
-use crate::ast::{ColumnDefinition, DataType, TableConstraint, Value};
+use crate::ast::{
+    ColumnDefinition, DataType, Deferrable, ForeignKeyAction, ForeignKeyConstraint,
+    GeneratedColumn, TableConstraint, Value,
+};
 use crate::database::{CompositeIndex, RowId, Table, View};
+use crate::decimal::Decimal;
+use crate::engine::StorageMode;
+use crate::interval::Interval;
+use crate::storage::PageUsageKind;
 use crate::storage::atomic_file::atomic_write;
 use std::collections::HashMap;
+use std::io::{Seek, SeekFrom, Write};
 use std::path::{Path, PathBuf};
 
 fn remove_storage_artifacts(path: &Path) {
@@ -18,6 +37,15 @@ fn remove_storage_artifacts(path: &Path) {
     let mut journal = path.as_os_str().to_os_string();
     journal.push(".wal");
     let _ = std::fs::remove_file(PathBuf::from(journal));
+    let _ = std::fs::remove_file(crate::storage::lock_path_for(path));
+}
+
+/// Loads a database with every table's rows read into memory, so tests can
+/// compare whole tables.
+fn load_resident(engine: &BTreeStorageEngine) -> Result<Database, RustqlError> {
+    let mut db = engine.load()?;
+    db.load_all_paged_tables()?;
+    Ok(db)
 }
 
 #[test]
@@ -69,9 +97,7 @@ fn btree_storage_round_trip() {
     engine
         .save(&db)
         .expect("failed to save via BTreeStorageEngine");
-    let loaded = engine
-        .load()
-        .expect("failed to load via BTreeStorageEngine");
+    let loaded = load_resident(&engine).expect("failed to load via BTreeStorageEngine");
 
     let users = loaded
         .tables
@@ -172,6 +198,7 @@ fn btree_storage_loads_legacy_row_keys_in_row_id_order() {
         }],
         constraints: Vec::<TableConstraint>::new(),
         next_row_id: 11,
+        row_count: None,
     })
     .expect("Failed to encode schema");
     let schema_pointer = file
@@ -226,6 +253,33 @@ fn btree_storage_loads_legacy_row_keys_in_row_id_order() {
         ]
     );
 
+    // Migrating the version 2 file writes a current one with the same rows.
+    let migrated_path = std::env::temp_dir().join("rustql_btree_legacy_rows_migrated.dat");
+    remove_storage_artifacts(&migrated_path);
+    let report = crate::migrate(
+        &StorageMode::BTree {
+            path: temp_path.clone(),
+        },
+        &StorageMode::BTree {
+            path: migrated_path.clone(),
+        },
+    )
+    .expect("Failed to migrate legacy file");
+    assert_eq!(report.tables.get("test"), Some(&2));
+    assert!(matches!(
+        read_versioned_header_with_versions(
+            &migrated_path,
+            BTreeFile::MAGIC,
+            &BTreeFile::SUPPORTED_VERSIONS,
+            "BTree storage file",
+        ),
+        Ok(VersionedFileState::Valid { version }) if version == BTreeFile::VERSION
+    ));
+    let migrated = load_resident(&BTreeStorageEngine::new(&migrated_path))
+        .expect("Failed to load migrated file");
+    assert_eq!(migrated.tables["test"].rows, table.rows);
+
+    remove_storage_artifacts(&migrated_path);
     remove_storage_artifacts(&temp_path);
 }
 
@@ -292,6 +346,56 @@ fn btree_search_insert_delete() {
     remove_storage_artifacts(&temp_path);
 }
 
+#[test]
+fn btree_deletes_that_empty_leftmost_leaves_keep_bounds_in_order() {
+    let temp_path = std::env::temp_dir().join("rustql_btree_leftmost_deletes.dat");
+    remove_storage_artifacts(&temp_path);
+
+    let mut file = BTreeFile::create(&temp_path).expect("Failed to create BTree file");
+    write_versioned_header(
+        &mut file.file,
+        BTreeFile::MAGIC,
+        BTreeFile::VERSION,
+        "BTree storage file",
+    )
+    .expect("Failed to write versioned header");
+    let mut meta_page = BTreePage::new(0, PageKind::Meta);
+    meta_page.push_entry(BTreeEntry::new(Value::Text("root".to_string()), 1));
+    file.write_page(&meta_page)
+        .expect("Failed to write meta page");
+    let mut root_page = BTreePage::new(1, PageKind::Leaf);
+    root_page.header.reserved = LEAF_INLINE_DATA_FLAG;
+    file.write_page(&root_page)
+        .expect("Failed to write root page");
+
+    // Long keys keep pages small, so the tree grows a level of internal
+    // pages below the root.
+    let key = |n: u64| Value::Text(format!("{:05}{}", n, "k".repeat(200)));
+    let mut root_id = 1;
+    let entry = |n: u64| BTreeEntry::with_inline_data(key(n), vec![0]);
+    for n in 0..2000 {
+        root_id = file.insert_entry(entry(n), root_id).unwrap().0;
+    }
+    let root_bound = file.read_page(root_id).unwrap().entries[0].key.clone();
+
+    for n in 0..1500 {
+        assert!(file.delete_entry(&key(n), root_id).unwrap().is_some());
+    }
+    assert_eq!(file.read_page(root_id).unwrap().entries[0].key, root_bound);
+    let engine = BTreeStorageEngine::new(&temp_path);
+    assert_eq!(engine.check_integrity().unwrap(), Vec::new());
+
+    for n in (0..1500).rev() {
+        root_id = file.insert_entry(entry(n), root_id).unwrap().0;
+    }
+    assert_eq!(engine.check_integrity().unwrap(), Vec::new());
+    for n in 0..2000 {
+        assert!(file.search(&key(n), root_id).unwrap().is_some());
+    }
+
+    remove_storage_artifacts(&temp_path);
+}
+
 #[test]
 fn btree_range_scan() {
     let temp_path = std::env::temp_dir().join("rustql_btree_range_test.dat");
@@ -629,19 +733,19 @@ fn btree_recovers_committed_journal_on_load() {
     engine.save(&db).expect("failed to save base database");
 
     let mut committed = db.clone();
-    committed.tables.get_mut("test").unwrap().rows = vec![vec![Value::Integer(2)]];
+    committed.tables.get_mut("test").unwrap().rows = vec![vec![Value::Integer(2)]].into();
     engine
         .prepare_commit(&committed)
         .expect("failed to write committed journal");
 
-    let loaded = engine.load().expect("failed to recover committed journal");
+    let loaded = load_resident(&engine).expect("failed to recover committed journal");
     assert_eq!(loaded.tables["test"].rows, committed.tables["test"].rows);
     assert!(
         !engine.journal_path().exists(),
         "journal should be cleared after recovery"
     );
 
-    let loaded_again = engine.load().expect("failed to reload recovered database");
+    let loaded_again = load_resident(&engine).expect("failed to reload recovered database");
     assert_eq!(
         loaded_again.tables["test"].rows,
         committed.tables["test"].rows
@@ -680,7 +784,7 @@ fn btree_committed_journal_stores_page_redo_frames() {
     engine.save(&db).expect("failed to save base database");
 
     let mut committed = db.clone();
-    committed.tables.get_mut("test").unwrap().rows = vec![vec![Value::Integer(2)]];
+    committed.tables.get_mut("test").unwrap().rows = vec![vec![Value::Integer(2)]].into();
     engine
         .prepare_commit(&committed)
         .expect("failed to prepare committed journal");
@@ -708,7 +812,12 @@ fn btree_committed_journal_stores_page_redo_frames() {
         serde_json::from_slice(payload).expect("failed to decode redo journal");
     let redo = match journal {
         TransactionJournal::Committed { redo } => redo,
-        TransactionJournal::Pending => panic!("expected committed journal"),
+        TransactionJournal::Pending
+        | TransactionJournal::Delta { .. }
+        | TransactionJournal::Linked { .. }
+        | TransactionJournal::LinkedDelta { .. } => {
+            panic!("expected committed journal")
+        }
     };
     redo.validate().expect("redo journal should validate");
     assert_eq!(redo.page_size, BTREE_PAGE_SIZE);
@@ -754,7 +863,7 @@ fn btree_committed_journal_is_cleared_when_data_file_already_reached_target() {
     engine.save(&db).expect("failed to save base database");
 
     let mut committed = db.clone();
-    committed.tables.get_mut("test").unwrap().rows = vec![vec![Value::Integer(2)]];
+    committed.tables.get_mut("test").unwrap().rows = vec![vec![Value::Integer(2)]].into();
     engine
         .prepare_commit(&committed)
         .expect("failed to prepare committed journal");
@@ -762,9 +871,7 @@ fn btree_committed_journal_is_cleared_when_data_file_already_reached_target() {
         .save(&committed)
         .expect("failed to persist committed image");
 
-    let loaded = engine
-        .load()
-        .expect("failed to load already committed image");
+    let loaded = load_resident(&engine).expect("failed to load already committed image");
     assert_eq!(loaded.tables["test"].rows, committed.tables["test"].rows);
     assert!(
         !engine.journal_path().exists(),
@@ -804,10 +911,10 @@ fn btree_recovers_legacy_v1_committed_journal_on_load() {
     engine.save(&db).expect("failed to save base database");
 
     let mut committed = db.clone();
-    committed.tables.get_mut("test").unwrap().rows = vec![vec![Value::Integer(2)]];
+    committed.tables.get_mut("test").unwrap().rows = vec![vec![Value::Integer(2)]].into();
 
     let payload = serde_json::to_vec(&LegacyTransactionJournal::Committed {
-        database: committed.clone(),
+        database: Box::new(committed.clone()),
     })
     .expect("failed to encode legacy journal");
     let mut data = Vec::with_capacity(FILE_HEADER_SIZE + payload.len());
@@ -817,9 +924,7 @@ fn btree_recovers_legacy_v1_committed_journal_on_load() {
     data.extend_from_slice(&payload);
     atomic_write(&engine.journal_path(), &data).expect("failed to write legacy journal");
 
-    let loaded = engine
-        .load()
-        .expect("failed to recover legacy committed journal");
+    let loaded = load_resident(&engine).expect("failed to recover legacy committed journal");
     assert_eq!(loaded.tables["test"].rows, committed.tables["test"].rows);
     assert!(
         !engine.journal_path().exists(),
@@ -861,7 +966,7 @@ fn btree_discards_pending_journal_on_load() {
         .write_journal_locked(&TransactionJournal::Pending)
         .expect("failed to write pending journal");
 
-    let loaded = engine.load().expect("failed to load database");
+    let loaded = load_resident(&engine).expect("failed to load database");
     assert_eq!(loaded.tables["test"].rows, db.tables["test"].rows);
     assert!(
         !engine.journal_path().exists(),
@@ -871,6 +976,84 @@ fn btree_discards_pending_journal_on_load() {
     remove_storage_artifacts(&temp_path);
 }
 
+#[test]
+fn btree_replays_linked_journal_only_with_its_commit_record() {
+    let temp_path = std::env::temp_dir().join("rustql_btree_linked_journal.dat");
+    let record = std::env::temp_dir().join("rustql_btree_linked_journal.commit");
+    remove_storage_artifacts(&temp_path);
+    let _ = std::fs::remove_file(&record);
+
+    let engine = BTreeStorageEngine::new(&temp_path);
+    let mut db = Database::new();
+    db.tables.insert("test".to_string(), numbered_table(1));
+    engine.save(&db).expect("failed to save base database");
+    let mut committed = db.clone();
+    committed
+        .tables
+        .insert("test".to_string(), numbered_table(2));
+
+    // A crash before the record was written: the commit never happened.
+    engine
+        .prepare_linked_commit(&committed, None, &record)
+        .expect("failed to prepare linked commit");
+    let loaded = load_resident(&engine).expect("failed to load database");
+    assert_eq!(loaded.tables["test"].rows, db.tables["test"].rows);
+    assert!(!engine.journal_path().exists());
+
+    // A crash after it: the commit is finished on load.
+    engine
+        .prepare_linked_commit(&committed, None, &record)
+        .expect("failed to prepare linked commit");
+    atomic_write(&record, b"committed\n").expect("failed to write commit record");
+    let loaded = load_resident(&engine).expect("failed to recover linked journal");
+    assert_eq!(loaded.tables["test"].rows, committed.tables["test"].rows);
+    assert!(!engine.journal_path().exists());
+
+    let _ = std::fs::remove_file(&record);
+    remove_storage_artifacts(&temp_path);
+}
+
+#[test]
+fn btree_prepares_linked_commits_as_the_pages_they_change() {
+    let temp_path = std::env::temp_dir().join("rustql_btree_linked_delta.dat");
+    let record = std::env::temp_dir().join("rustql_btree_linked_delta.commit");
+    remove_storage_artifacts(&temp_path);
+    let _ = std::fs::remove_file(&record);
+
+    let engine = BTreeStorageEngine::new(&temp_path);
+    let mut db = Database::new();
+    db.tables.insert("items".to_string(), numbered_table(2000));
+    engine.save(&db).expect("failed to save base database");
+    let before = db.clone();
+    let changes = insert_numbered_row(&mut db, 2001);
+
+    engine
+        .prepare_linked_commit(&db, Some(&changes), &record)
+        .expect("failed to prepare linked commit");
+    let Some(LoadedTransactionJournal::LinkedDelta { delta, .. }) =
+        engine.read_journal_locked().unwrap()
+    else {
+        panic!("expected a linked page delta");
+    };
+    assert!(delta.frames.len() < page_count(&engine, PageUsageKind::File) as usize / 4);
+    let loaded = load_resident(&engine).expect("failed to load database");
+    assert_eq!(loaded.tables["items"].rows, before.tables["items"].rows);
+
+    engine
+        .prepare_linked_commit(&db, Some(&changes), &record)
+        .expect("failed to prepare linked commit");
+    atomic_write(&record, b"committed\n").expect("failed to write commit record");
+    engine
+        .finish_linked_commit(&db)
+        .expect("failed to finish linked commit");
+    assert!(!engine.journal_path().exists());
+    let loaded = load_resident(&BTreeStorageEngine::new(&temp_path)).unwrap();
+    assert_eq!(loaded.tables["items"].rows, db.tables["items"].rows);
+
+    let _ = std::fs::remove_file(&record);
+    remove_storage_artifacts(&temp_path);
+}
+
 #[test]
 fn btree_persists_views_constraints_and_composite_indexes() {
     let temp_path = std::env::temp_dir().join("rustql_btree_metadata_roundtrip.dat");
@@ -912,6 +1095,7 @@ fn btree_persists_views_constraints_and_composite_indexes() {
             vec![TableConstraint::Unique {
                 name: Some("orders_customer_unique".to_string()),
                 columns: vec!["customer_id".to_string()],
+                deferrable: Deferrable::No,
             }],
         ),
     );
@@ -931,8 +1115,10 @@ fn btree_persists_views_constraints_and_composite_indexes() {
             entries: std::collections::BTreeMap::from([(
                 vec![Value::Integer(1), Value::Integer(10)],
                 vec![RowId(1)],
-            )]),
+            )])
+            .into(),
             filter_expr: None,
+            paged: None,
         },
     );
 
@@ -948,3 +1134,1007 @@ fn btree_persists_views_constraints_and_composite_indexes() {
 
     remove_storage_artifacts(&temp_path);
 }
+
+fn numbered_table(rows: i64) -> Table {
+    Table::new(
+        vec![
+            ColumnDefinition {
+                name: "id".to_string(),
+                data_type: DataType::Integer,
+                nullable: false,
+                primary_key: true,
+                unique: false,
+                default_value: None,
+                foreign_key: None,
+                check: None,
+                auto_increment: false,
+                generated: None,
+            },
+            ColumnDefinition {
+                name: "label".to_string(),
+                data_type: DataType::Text,
+                nullable: true,
+                primary_key: false,
+                unique: false,
+                default_value: None,
+                foreign_key: None,
+                check: None,
+                auto_increment: false,
+                generated: None,
+            },
+        ],
+        (1..=rows)
+            .map(|id| vec![Value::Integer(id), Value::Text(format!("row-{id}"))])
+            .collect(),
+        vec![],
+    )
+}
+
+#[test]
+fn btree_save_changes_rewrites_only_touched_pages() {
+    let temp_path = std::env::temp_dir().join("rustql_btree_incremental_save.dat");
+    remove_storage_artifacts(&temp_path);
+
+    let engine = BTreeStorageEngine::new(&temp_path);
+    let mut db = Database::new();
+    db.tables.insert("items".to_string(), numbered_table(1_500));
+    engine.save(&db).expect("failed to save base database");
+    let before = std::fs::read(&temp_path).expect("failed to read base file");
+    assert!(before.len() > FILE_HEADER_SIZE + 20 * BTREE_PAGE_SIZE);
+
+    let table = db.tables.get_mut("items").unwrap();
+    let row_id = table.insert_row(vec![Value::Integer(501), Value::Text("new".to_string())]);
+    table.rows[0][1] = Value::Text("changed".to_string());
+    let first_row_id = table.row_id_at(0).unwrap();
+    let mut changes = ChangeSet::new();
+    changes.record_row("items", row_id);
+    changes.record_row("items", first_row_id);
+    engine
+        .save_changes(&db, &changes)
+        .expect("failed to save changes");
+
+    assert_eq!(
+        std::fs::read(&temp_path).expect("failed to read base file"),
+        before,
+        "a commit only appends to the redo log"
+    );
+    let (records, _, logged_pages) = engine.log_stats();
+    assert_eq!(records, 1);
+    assert!(
+        logged_pages <= 6,
+        "expected a handful of logged pages, got {logged_pages}"
+    );
+
+    engine.checkpoint().expect("failed to checkpoint");
+    let after = std::fs::read(&temp_path).expect("failed to read updated file");
+    let changed_pages = before[FILE_HEADER_SIZE..]
+        .chunks(BTREE_PAGE_SIZE)
+        .zip(after[FILE_HEADER_SIZE..].chunks(BTREE_PAGE_SIZE))
+        .filter(|(old, new)| old != new)
+        .count()
+        + (after.len().saturating_sub(before.len()) / BTREE_PAGE_SIZE);
+    assert!(
+        changed_pages <= 6,
+        "expected a handful of rewritten pages, got {changed_pages}"
+    );
+    assert!(!engine.journal_path().exists());
+
+    let loaded = load_resident(&BTreeStorageEngine::new(&temp_path))
+        .expect("failed to load updated database");
+    assert_eq!(loaded.tables["items"].rows, db.tables["items"].rows);
+    assert_eq!(loaded.tables["items"].row_ids, db.tables["items"].row_ids);
+
+    remove_storage_artifacts(&temp_path);
+}
+
+#[test]
+fn btree_recovers_delta_journal_on_load() {
+    let temp_path = std::env::temp_dir().join("rustql_btree_delta_journal.dat");
+    remove_storage_artifacts(&temp_path);
+
+    let engine = BTreeStorageEngine::new(&temp_path);
+    let mut db = Database::new();
+    db.tables.insert("items".to_string(), numbered_table(200));
+    engine.save(&db).expect("failed to save base database");
+    let base_file_len = std::fs::metadata(&temp_path).unwrap().len();
+
+    let table = db.tables.get_mut("items").unwrap();
+    let mut changes = ChangeSet::new();
+    for _ in 0..100 {
+        let id = table.next_row_id as i64 + 200;
+        let row_id = table.insert_row(vec![Value::Integer(id), Value::Text("x".repeat(40))]);
+        changes.record_row("items", row_id);
+    }
+    let delta = engine
+        .stage_page_delta_locked(&db, &changes, base_file_len)
+        .expect("failed to stage delta")
+        .expect("delta should touch pages")
+        .delta;
+    assert!(delta.target_file_len > base_file_len);
+    engine
+        .write_journal_locked(&TransactionJournal::Delta { delta })
+        .expect("failed to write delta journal");
+    assert_eq!(std::fs::metadata(&temp_path).unwrap().len(), base_file_len);
+
+    let loaded = load_resident(&BTreeStorageEngine::new(&temp_path))
+        .expect("failed to recover delta journal");
+    assert_eq!(loaded.tables["items"].rows, db.tables["items"].rows);
+    assert!(!engine.journal_path().exists());
+
+    remove_storage_artifacts(&temp_path);
+}
+
+#[test]
+fn btree_load_pages_table_rows_through_bounded_cache() {
+    let temp_path = std::env::temp_dir().join("rustql_btree_paged_rows.dat");
+    remove_storage_artifacts(&temp_path);
+
+    let mut db = Database::new();
+    db.tables.insert("items".to_string(), numbered_table(2_000));
+    BTreeStorageEngine::new(&temp_path)
+        .save(&db)
+        .expect("failed to save base database");
+
+    let engine = BTreeStorageEngine::with_page_cache_size(&temp_path, 4);
+    let loaded = engine.load().expect("failed to load paged database");
+    let items = &loaded.tables["items"];
+    assert!(items.is_paged());
+    assert!(items.rows.is_empty());
+    assert_eq!(items.row_count(), 2_000);
+    assert_eq!(items.next_row_id, db.tables["items"].next_row_id);
+
+    let mut scanned = 0;
+    items
+        .for_each_row(|row_id, row| {
+            let expected = db.tables["items"].fetch_row(row_id).unwrap().unwrap();
+            assert_eq!(row, expected.as_ref());
+            scanned += 1;
+            Ok(true)
+        })
+        .expect("failed to scan paged rows");
+    assert_eq!(scanned, 2_000);
+    assert!(engine.cache_stats().2 <= 4);
+
+    let row = items
+        .fetch_row(RowId(1_234))
+        .expect("failed to fetch paged row")
+        .expect("row 1234 should exist");
+    assert_eq!(row[1], Value::Text("row-1234".to_string()));
+    assert!(items.fetch_row(RowId(9_999)).unwrap().is_none());
+
+    let mut resident = loaded.clone();
+    resident.load_all_paged_tables().unwrap();
+    assert!(!resident.tables["items"].is_paged());
+    assert_eq!(resident.tables["items"].rows, db.tables["items"].rows);
+    assert_eq!(resident.tables["items"].row_ids, db.tables["items"].row_ids);
+
+    remove_storage_artifacts(&temp_path);
+}
+
+#[test]
+fn btree_paged_scan_keeps_its_rows_when_a_commit_lands_midway() {
+    let temp_path = std::env::temp_dir().join("rustql_btree_paged_scan_commit.dat");
+    remove_storage_artifacts(&temp_path);
+
+    let mut db = Database::new();
+    db.tables.insert("items".to_string(), numbered_table(2_000));
+    BTreeStorageEngine::new(&temp_path)
+        .save(&db)
+        .expect("failed to save base database");
+
+    let engine = BTreeStorageEngine::with_page_cache_size(&temp_path, 4);
+    let snapshot = engine.load().expect("failed to load paged database");
+    let items = &snapshot.tables["items"];
+    let mut scanned = Vec::new();
+    items
+        .for_each_row(|row_id, row| {
+            if scanned.len() == 10 {
+                let mut next = snapshot.clone();
+                next.load_paged_tables(["items"]).unwrap();
+                let table = next.tables.get_mut("items").unwrap();
+                let mut changes = ChangeSet::new();
+                for id in 1_001..=2_000 {
+                    table.remove_row_by_id(RowId(id)).unwrap();
+                    changes.record_row("items", RowId(id));
+                }
+                table
+                    .set_row_by_id(
+                        RowId(500),
+                        vec![Value::Integer(500), Value::Text("changed".to_string())],
+                    )
+                    .unwrap();
+                changes.record_row("items", RowId(500));
+                engine
+                    .save_changes(&next, &changes)
+                    .expect("failed to commit during the scan");
+            }
+            scanned.push((row_id, row.to_vec()));
+            Ok(true)
+        })
+        .expect("failed to scan paged rows");
+
+    let expected: Vec<_> = db.tables["items"]
+        .iter_rows_with_ids()
+        .map(|(row_id, row)| (row_id, row.clone()))
+        .collect();
+    assert_eq!(scanned, expected);
+    let row = items
+        .fetch_row(RowId(500))
+        .expect("failed to fetch paged row")
+        .expect("row 500 should still exist in the snapshot");
+    assert_eq!(row[1], Value::Text("row-500".to_string()));
+
+    let reloaded = load_resident(&engine).expect("failed to reload database");
+    assert_eq!(reloaded.tables["items"].row_count(), 1_000);
+
+    remove_storage_artifacts(&temp_path);
+}
+
+fn insert_numbered_row(db: &mut Database, id: i64) -> ChangeSet {
+    let table = db.tables.get_mut("items").unwrap();
+    let row_id = table.insert_row(vec![Value::Integer(id), Value::Text(format!("row-{id}"))]);
+    let mut changes = ChangeSet::new();
+    changes.record_row("items", row_id);
+    changes
+}
+
+#[test]
+fn btree_recovers_redo_log_on_load() {
+    let temp_path = std::env::temp_dir().join("rustql_btree_redo_log.dat");
+    remove_storage_artifacts(&temp_path);
+
+    let engine = BTreeStorageEngine::new(&temp_path);
+    let mut db = Database::new();
+    db.tables.insert("items".to_string(), numbered_table(300));
+    engine.save(&db).expect("failed to save base database");
+    let base = std::fs::read(&temp_path).unwrap();
+
+    for id in 301..=320 {
+        let changes = insert_numbered_row(&mut db, id);
+        engine
+            .save_changes(&db, &changes)
+            .expect("failed to log changes");
+    }
+    assert_eq!(engine.log_stats().0, 20);
+    assert_eq!(std::fs::read(&temp_path).unwrap(), base);
+    engine.checkpoint().expect("failed to checkpoint");
+    assert!(!engine.journal_path().exists());
+
+    for id in 321..=340 {
+        let changes = insert_numbered_row(&mut db, id);
+        engine
+            .save_changes(&db, &changes)
+            .expect("failed to log changes");
+    }
+    assert_eq!(engine.log_stats().0, 40);
+
+    // A crash part way through the next append leaves a torn record behind.
+    let mut log = std::fs::OpenOptions::new()
+        .append(true)
+        .open(engine.journal_path())
+        .unwrap();
+    std::io::Write::write_all(&mut log, &[7u8; 100]).unwrap();
+    drop(log);
+
+    let recovered = BTreeStorageEngine::new(&temp_path);
+    let loaded = load_resident(&recovered).expect("failed to replay redo log");
+    assert_eq!(loaded.tables["items"].rows, db.tables["items"].rows);
+    assert!(!recovered.journal_path().exists());
+
+    let changes = insert_numbered_row(&mut db, 341);
+    recovered
+        .save_changes(&db, &changes)
+        .expect("failed to log after recovery");
+    let loaded = load_resident(&BTreeStorageEngine::new(&temp_path))
+        .expect("failed to reload after recovery");
+    assert_eq!(loaded.tables["items"].rows, db.tables["items"].rows);
+
+    remove_storage_artifacts(&temp_path);
+}
+
+#[test]
+fn btree_redo_log_sync_covers_every_appended_record() {
+    let temp_path = std::env::temp_dir().join("rustql_btree_group_commit.dat");
+    remove_storage_artifacts(&temp_path);
+
+    let engine = BTreeStorageEngine::new(&temp_path);
+    let mut db = Database::new();
+    db.tables.insert("items".to_string(), numbered_table(10));
+    engine.save(&db).expect("failed to save base database");
+
+    let mut lsns = Vec::new();
+    for id in 11..=13 {
+        let changes = insert_numbered_row(&mut db, id);
+        match engine.save_changes_locked(&db, &changes).unwrap() {
+            delta::CommitLogging::Logged(lsn) => lsns.push(lsn),
+            _ => panic!("expected the change set to be logged"),
+        }
+    }
+    assert_eq!(lsns, vec![1, 2, 3]);
+
+    engine.redo_log.sync_to(lsns[0]).unwrap();
+    engine.redo_log.sync_to(lsns[2]).unwrap();
+    assert_eq!(
+        engine.log_stats().1,
+        1,
+        "one sync makes every earlier append durable"
+    );
+
+    remove_storage_artifacts(&temp_path);
+}
+
+#[test]
+fn btree_ships_a_commit_once_the_redo_log_has_synced_it() {
+    let temp_path = std::env::temp_dir().join("rustql_btree_ship_after_sync.dat");
+    let log_dir = std::env::temp_dir().join("rustql_btree_ship_after_sync.log");
+    remove_storage_artifacts(&temp_path);
+    let _ = std::fs::remove_dir_all(&log_dir);
+    let shipped = || std::fs::read_dir(&log_dir).unwrap().count();
+
+    let engine = BTreeStorageEngine::new(&temp_path)
+        .with_log_shipping(ReplicationTarget::Directory(log_dir.clone()));
+    let mut db = Database::new();
+    db.tables.insert("items".to_string(), numbered_table(10));
+    engine.save(&db).expect("failed to save base database");
+    assert_eq!(shipped(), 1, "the snapshot ships an image");
+
+    let mut lsns = Vec::new();
+    for id in 11..=12 {
+        let changes = insert_numbered_row(&mut db, id);
+        match engine.save_changes_locked(&db, &changes).unwrap() {
+            delta::CommitLogging::Logged(lsn) => lsns.push(lsn),
+            _ => panic!("expected the change set to be logged"),
+        }
+    }
+    assert_eq!(shipped(), 1, "logged commits wait for their sync");
+
+    engine.redo_log.sync_to(lsns[1]).unwrap();
+    engine.ship_committed(lsns[0]);
+    assert_eq!(shipped(), 2);
+    engine.ship_committed(lsns[1]);
+    assert_eq!(shipped(), 3);
+
+    let changes = insert_numbered_row(&mut db, 13);
+    engine.save_changes(&db, &changes).unwrap();
+    assert_eq!(shipped(), 4);
+
+    remove_storage_artifacts(&temp_path);
+    let _ = std::fs::remove_dir_all(&log_dir);
+}
+
+#[test]
+fn btree_refuses_commits_while_an_earlier_one_has_not_shipped() {
+    let temp_path = std::env::temp_dir().join("rustql_btree_ship_failure.dat");
+    let log_dir = std::env::temp_dir().join("rustql_btree_ship_failure.log");
+    remove_storage_artifacts(&temp_path);
+    let _ = std::fs::remove_dir_all(&log_dir);
+    let _ = std::fs::remove_file(&log_dir);
+    let shipped = || std::fs::read_dir(&log_dir).unwrap().count();
+
+    let engine = BTreeStorageEngine::new(&temp_path)
+        .with_log_shipping(ReplicationTarget::Directory(log_dir.clone()));
+    let mut db = Database::new();
+    db.tables.insert("items".to_string(), numbered_table(10));
+    engine.save(&db).expect("failed to save base database");
+    assert_eq!(shipped(), 1);
+
+    // A file in place of the log directory makes shipping fail.
+    std::fs::remove_dir_all(&log_dir).unwrap();
+    std::fs::write(&log_dir, b"").unwrap();
+    let changes = insert_numbered_row(&mut db, 11);
+    engine
+        .save_changes(&db, &changes)
+        .expect("a durable commit stands even if it cannot be shipped");
+
+    let changes = insert_numbered_row(&mut db, 12);
+    let err = engine.save_changes(&db, &changes).unwrap_err();
+    assert!(
+        err.to_string().contains("could not be shipped"),
+        "got: {err}"
+    );
+    let loaded = engine.load().unwrap();
+    assert_eq!(
+        loaded.tables["items"].row_count(),
+        11,
+        "the refused commit is not made"
+    );
+
+    std::fs::remove_file(&log_dir).unwrap();
+    engine.save_changes(&db, &changes).unwrap();
+    assert_eq!(shipped(), 2, "both commits ship once the directory is back");
+
+    remove_storage_artifacts(&temp_path);
+    let _ = std::fs::remove_dir_all(&log_dir);
+}
+
+#[test]
+fn btree_checkpoints_when_redo_log_reaches_threshold() {
+    let temp_path = std::env::temp_dir().join("rustql_btree_auto_checkpoint.dat");
+    remove_storage_artifacts(&temp_path);
+
+    let engine = BTreeStorageEngine::new(&temp_path).with_checkpoint_pages(8);
+    let mut db = Database::new();
+    db.tables.insert("items".to_string(), numbered_table(100));
+    engine.save(&db).expect("failed to save base database");
+
+    let mut checkpointed = false;
+    for id in 101..=400 {
+        let changes = insert_numbered_row(&mut db, id);
+        engine.save_changes(&db, &changes).unwrap();
+        let (_, _, logged_pages) = engine.log_stats();
+        assert!(logged_pages < 8);
+        checkpointed |= logged_pages == 0;
+    }
+    assert!(checkpointed, "the log should have been checkpointed");
+
+    let loaded = load_resident(&BTreeStorageEngine::new(&temp_path)).unwrap();
+    assert_eq!(loaded.tables["items"].rows, db.tables["items"].rows);
+
+    remove_storage_artifacts(&temp_path);
+}
+
+fn page_count(engine: &BTreeStorageEngine, kind: PageUsageKind) -> u64 {
+    engine
+        .page_usage()
+        .unwrap()
+        .iter()
+        .filter(|usage| usage.kind == kind)
+        .map(|usage| usage.pages)
+        .sum()
+}
+
+#[test]
+fn btree_deletes_free_pages_for_reuse_and_vacuum_compacts() {
+    let temp_path = std::env::temp_dir().join("rustql_btree_free_list.dat");
+    remove_storage_artifacts(&temp_path);
+
+    let engine = BTreeStorageEngine::new(&temp_path);
+    let mut db = Database::new();
+    db.tables.insert("items".to_string(), numbered_table(2000));
+    engine.save(&db).expect("failed to save base database");
+    let file_pages = page_count(&engine, PageUsageKind::File);
+    let table_pages = page_count(&engine, PageUsageKind::Table);
+    assert_eq!(page_count(&engine, PageUsageKind::Free), 0);
+
+    let table = db.tables.get_mut("items").unwrap();
+    let deleted: Vec<RowId> = table.row_ids.iter().take(1500).copied().collect();
+    let mut changes = ChangeSet::new();
+    for row_id in deleted {
+        table.remove_row_by_id(row_id).unwrap();
+        changes.record_row("items", row_id);
+    }
+    engine.save_changes(&db, &changes).unwrap();
+    engine.checkpoint().unwrap();
+    let free_pages = page_count(&engine, PageUsageKind::Free);
+    assert!(free_pages > 0, "emptied leaves should be freed");
+    assert!(page_count(&engine, PageUsageKind::Table) < table_pages);
+    assert_eq!(page_count(&engine, PageUsageKind::File), file_pages);
+    assert_eq!(engine.check_integrity().unwrap(), Vec::new());
+
+    for id in 2001..=2600 {
+        let changes = insert_numbered_row(&mut db, id);
+        engine.save_changes(&db, &changes).unwrap();
+    }
+    engine.checkpoint().unwrap();
+    assert!(page_count(&engine, PageUsageKind::Free) < free_pages);
+    assert_eq!(
+        page_count(&engine, PageUsageKind::File),
+        file_pages,
+        "new rows should reuse freed pages before growing the file"
+    );
+    assert_eq!(engine.check_integrity().unwrap(), Vec::new());
+    let loaded = load_resident(&BTreeStorageEngine::new(&temp_path)).unwrap();
+    assert_eq!(loaded.tables["items"].rows, db.tables["items"].rows);
+
+    let stats = engine.vacuum(&db).unwrap();
+    assert!(stats.bytes_reclaimed() > 0);
+    assert_eq!(
+        stats.bytes_after,
+        std::fs::metadata(&temp_path).unwrap().len()
+    );
+    assert_eq!(page_count(&engine, PageUsageKind::Free), 0);
+    let loaded = load_resident(&BTreeStorageEngine::new(&temp_path)).unwrap();
+    assert_eq!(loaded.tables["items"].rows, db.tables["items"].rows);
+
+    remove_storage_artifacts(&temp_path);
+}
+
+#[test]
+fn btree_check_integrity_reports_corrupt_and_misplaced_pages() {
+    let temp_path = std::env::temp_dir().join("rustql_btree_check_integrity.dat");
+    remove_storage_artifacts(&temp_path);
+
+    let engine = BTreeStorageEngine::new(&temp_path);
+    let mut db = Database::new();
+    db.tables.insert("items".to_string(), numbered_table(500));
+    engine.save(&db).expect("failed to save base database");
+    assert_eq!(engine.check_integrity().unwrap(), Vec::new());
+
+    let mut bytes = std::fs::read(&temp_path).unwrap();
+    let page_count = (bytes.len() - FILE_HEADER_SIZE) / BTREE_PAGE_SIZE;
+    let corrupt_page = page_count - 1;
+    bytes[FILE_HEADER_SIZE + corrupt_page * BTREE_PAGE_SIZE + BTREE_PAGE_SIZE / 2] ^= 0xff;
+    std::fs::write(&temp_path, &bytes).unwrap();
+
+    let mut page_buf = [0u8; BTREE_PAGE_SIZE];
+    page_buf.copy_from_slice(
+        &bytes[FILE_HEADER_SIZE + corrupt_page * BTREE_PAGE_SIZE..][..BTREE_PAGE_SIZE],
+    );
+    assert!(!BTreePage::checksum_matches(&page_buf));
+
+    let engine = BTreeStorageEngine::new(&temp_path);
+    let problems = engine.check_integrity().unwrap();
+    assert!(
+        problems.iter().any(|problem| matches!(
+            problem,
+            IntegrityProblem::CorruptPage { page_id, .. } if *page_id == corrupt_page as u64
+        )),
+        "{problems:?}"
+    );
+
+    // A page written under the wrong id still passes its checksum, so only
+    // the tree walk notices it.
+    let mut file = BTreeFile::open(&temp_path).unwrap();
+    let mut moved = BTreePage::new(1, PageKind::Leaf);
+    moved.header.reserved = LEAF_INLINE_DATA_FLAG;
+    let buf = moved.to_bytes().unwrap();
+    file.file
+        .seek(SeekFrom::Start(
+            (FILE_HEADER_SIZE + corrupt_page * BTREE_PAGE_SIZE) as u64,
+        ))
+        .unwrap();
+    file.file.write_all(&buf).unwrap();
+    drop(file);
+
+    let problems = BTreeStorageEngine::new(&temp_path)
+        .check_integrity()
+        .unwrap();
+    assert!(
+        problems.iter().any(|problem| matches!(
+            problem,
+            IntegrityProblem::PageStructure { page_id, .. } if *page_id == corrupt_page as u64
+        )),
+        "{problems:?}"
+    );
+
+    remove_storage_artifacts(&temp_path);
+}
+
+#[test]
+fn btree_records_round_trip_through_binary_encoding() {
+    let row = vec![
+        Value::Integer(i64::MIN),
+        Value::Null,
+        Value::Float(-1.5),
+        Value::Decimal(Decimal::parse("-12345678901234567890.50").unwrap()),
+        Value::Text("héllo".to_string()),
+        Value::Boolean(true),
+        Value::Boolean(false),
+        Value::Null,
+        Value::Date("2024-02-29".to_string()),
+        Value::Time("12:30:00".to_string()),
+        Value::DateTime("2024-02-29 12:30:00".to_string()),
+        Value::Blob(vec![0x00, 0xff, 0x7f]),
+        Value::Json(r#"{"a":[1,"x"]}"#.to_string()),
+        Value::Interval(Interval::new(14, -3, -4_500_000)),
+        Value::TimestampTz("2024-02-29 12:30:00.125".to_string()),
+    ];
+    let encoded = encode_record(row.as_slice()).unwrap();
+    let json = serde_json::to_string(&row).unwrap();
+    assert!(encoded.len() * 2 < json.len());
+    let decoded: Vec<Value> = decode_record(&encoded, "row").unwrap();
+    assert_eq!(decoded, row);
+    let legacy: Vec<Value> = decode_record(json.as_bytes(), "row").unwrap();
+    assert_eq!(legacy, row);
+    assert!(decode_record::<Vec<Value>>(&encoded[..encoded.len() - 1], "row").is_err());
+
+    let schema = TableStorageRecord {
+        columns: vec![
+            ColumnDefinition {
+                name: "id".to_string(),
+                data_type: DataType::Integer,
+                nullable: false,
+                primary_key: true,
+                unique: false,
+                default_value: None,
+                foreign_key: None,
+                check: None,
+                auto_increment: true,
+                generated: None,
+            },
+            ColumnDefinition {
+                name: "owner".to_string(),
+                data_type: DataType::Text,
+                nullable: true,
+                primary_key: false,
+                unique: true,
+                default_value: Some(Value::Text("nobody".to_string())),
+                foreign_key: Some(ForeignKeyConstraint {
+                    referenced_table: "users".to_string(),
+                    referenced_column: "name".to_string(),
+                    on_delete: ForeignKeyAction::Cascade,
+                    on_update: ForeignKeyAction::SetNull,
+                    deferrable: Deferrable::InitiallyImmediate,
+                }),
+                check: Some("owner <> ''".to_string()),
+                auto_increment: false,
+                generated: Some(GeneratedColumn {
+                    expr_sql: "UPPER(owner)".to_string(),
+                    always: true,
+                }),
+            },
+            ColumnDefinition {
+                name: "balance".to_string(),
+                data_type: DataType::Decimal {
+                    precision: 12,
+                    scale: 2,
+                },
+                nullable: true,
+                primary_key: false,
+                unique: false,
+                default_value: None,
+                foreign_key: None,
+                check: None,
+                auto_increment: false,
+                generated: None,
+            },
+            ColumnDefinition {
+                name: "avatar".to_string(),
+                data_type: DataType::Blob,
+                nullable: true,
+                primary_key: false,
+                unique: false,
+                default_value: Some(Value::Blob(vec![0xca, 0xfe])),
+                foreign_key: None,
+                check: None,
+                auto_increment: false,
+                generated: None,
+            },
+            ColumnDefinition {
+                name: "profile".to_string(),
+                data_type: DataType::Json,
+                nullable: true,
+                primary_key: false,
+                unique: false,
+                default_value: Some(Value::Json("{}".to_string())),
+                foreign_key: None,
+                check: None,
+                auto_increment: false,
+                generated: None,
+            },
+            ColumnDefinition {
+                name: "retention".to_string(),
+                data_type: DataType::Interval,
+                nullable: true,
+                primary_key: false,
+                unique: false,
+                default_value: Some(Value::Interval(Interval::new(0, 7, 0))),
+                foreign_key: None,
+                check: None,
+                auto_increment: false,
+                generated: None,
+            },
+            ColumnDefinition {
+                name: "seen_at".to_string(),
+                data_type: DataType::TimestampTz,
+                nullable: true,
+                primary_key: false,
+                unique: false,
+                default_value: None,
+                foreign_key: None,
+                check: None,
+                auto_increment: false,
+                generated: None,
+            },
+        ],
+        constraints: vec![TableConstraint::Unique {
+            name: Some("owner_id".to_string()),
+            columns: vec!["owner".to_string(), "id".to_string()],
+            deferrable: Deferrable::InitiallyDeferred,
+        }],
+        next_row_id: 300,
+        row_count: Some(299),
+    };
+    let decoded: TableStorageRecord =
+        decode_record(&encode_record(&schema).unwrap(), "schema").unwrap();
+    assert_eq!(decoded.columns, schema.columns);
+    assert_eq!(decoded.constraints, schema.constraints);
+    assert_eq!(decoded.next_row_id, 300);
+    assert_eq!(decoded.row_count, Some(299));
+
+    let view = View {
+        name: "v".to_string(),
+        query_sql: "SELECT 1".to_string(),
+    };
+    let decoded: View = decode_record(&encode_record(&view).unwrap(), "view").unwrap();
+    assert_eq!(decoded.query_sql, view.query_sql);
+    assert!(decode_record::<TableStorageRecord>(&encode_record(&view).unwrap(), "view").is_err());
+
+    let key = [
+        Value::Text("a:1".to_string()),
+        Value::Null,
+        Value::Float(-0.0),
+        Value::Integer(-7),
+        Value::Decimal(Decimal::parse("2.50").unwrap()),
+        Value::Blob(vec![0x3a, 0x00]),
+        Value::Json(r#"{"k":"a:1"}"#.to_string()),
+        Value::Interval(Interval::new(1, 0, 0)),
+        Value::TimestampTz("2024-02-29 12:30:00".to_string()),
+    ];
+    let text = composite_index_key_text(&key);
+    assert_eq!(
+        text,
+        composite_index_key_text(&[
+            Value::Text("a:1".to_string()),
+            Value::Null,
+            Value::Float(0.0),
+            Value::Integer(-7),
+            Value::Decimal(Decimal::parse("2.5").unwrap()),
+            Value::Blob(vec![0x3a, 0x00]),
+            Value::Json(r#"{"k":"a:1"}"#.to_string()),
+            Value::Interval(Interval::new(0, 30, 0)),
+            Value::TimestampTz("2024-02-29 12:30:00".to_string()),
+        ])
+    );
+    assert_eq!(parse_composite_index_key_text(&text), Some(key.to_vec()));
+    assert_eq!(
+        parse_index_key_text(r#"{"Text":"legacy"}"#),
+        Some(Value::Text("legacy".to_string()))
+    );
+}
+
+#[test]
+fn btree_moves_large_records_to_overflow_pages() {
+    let temp_path = std::env::temp_dir().join("rustql_btree_overflow.dat");
+    remove_storage_artifacts(&temp_path);
+
+    let engine = BTreeStorageEngine::new(&temp_path);
+    let mut db = Database::new();
+    db.tables.insert("items".to_string(), numbered_table(10));
+    let table = db.tables.get_mut("items").unwrap();
+    let large_id = table.insert_row(vec![Value::Integer(11), Value::Text("x".repeat(10_000))]);
+    engine.save(&db).expect("failed to save database");
+    assert!(page_count(&engine, PageUsageKind::Table) >= 4);
+    assert_eq!(engine.check_integrity().unwrap(), Vec::new());
+    let loaded = load_resident(&BTreeStorageEngine::new(&temp_path)).unwrap();
+    assert_eq!(loaded.tables["items"].rows, db.tables["items"].rows);
+
+    // Replacing the record frees its old chain, which the new one reuses.
+    let file_pages = page_count(&engine, PageUsageKind::File);
+    let table = db.tables.get_mut("items").unwrap();
+    table
+        .set_row_by_id(
+            large_id,
+            vec![Value::Integer(11), Value::Text("y".repeat(9_000))],
+        )
+        .unwrap();
+    let mut changes = ChangeSet::new();
+    changes.record_row("items", large_id);
+    engine.save_changes(&db, &changes).unwrap();
+    engine.checkpoint().unwrap();
+    assert_eq!(page_count(&engine, PageUsageKind::File), file_pages);
+    assert_eq!(engine.check_integrity().unwrap(), Vec::new());
+
+    let table = db.tables.get_mut("items").unwrap();
+    table.remove_row_by_id(large_id).unwrap();
+    engine.save_changes(&db, &changes).unwrap();
+    engine.checkpoint().unwrap();
+    assert!(page_count(&engine, PageUsageKind::Free) >= 3);
+    assert_eq!(engine.check_integrity().unwrap(), Vec::new());
+    let loaded = load_resident(&BTreeStorageEngine::new(&temp_path)).unwrap();
+    assert_eq!(loaded.tables["items"].rows, db.tables["items"].rows);
+
+    remove_storage_artifacts(&temp_path);
+}
+
+#[test]
+fn btree_loads_version_4_json_records_and_upgrades_on_commit() {
+    let temp_path = std::env::temp_dir().join("rustql_btree_version_4.dat");
+    remove_storage_artifacts(&temp_path);
+
+    let mut file = BTreeFile::create(&temp_path).expect("failed to create BTree file");
+    write_versioned_header(&mut file.file, BTreeFile::MAGIC, 4, "BTree storage file")
+        .expect("failed to write versioned header");
+    let mut meta_page = BTreePage::new(0, PageKind::Meta);
+    meta_page.push_entry(BTreeEntry::new(Value::Text("root".to_string()), 1));
+    meta_page.push_entry(BTreeEntry::new(Value::Text("next_page_id".to_string()), 2));
+    file.write_page(&meta_page).unwrap();
+    let mut root_page = BTreePage::new(1, PageKind::Leaf);
+    root_page.header.reserved = LEAF_INLINE_DATA_FLAG;
+    file.write_page(&root_page).unwrap();
+
+    let items = numbered_table(1);
+    let index = crate::database::Index {
+        name: "items_label".to_string(),
+        table: "items".to_string(),
+        column: "label".to_string(),
+        entries: Default::default(),
+        filter_expr: None,
+        paged: None,
+    };
+    let records = [
+        (
+            "schema:items".to_string(),
+            serde_json::to_string(&TableStorageRecord {
+                columns: items.columns.clone(),
+                constraints: Vec::new(),
+                next_row_id: 2,
+                row_count: Some(1),
+            })
+            .unwrap(),
+        ),
+        (
+            format!("table_row:items:{:020}", 1),
+            serde_json::to_string(&items.rows[0]).unwrap(),
+        ),
+        (
+            "index:items_label".to_string(),
+            serde_json::to_string(&index).unwrap(),
+        ),
+        (
+            format!(
+                "index_entry:items_label:{}:{:020}",
+                r#"{"Text":"row-1"}"#, 1
+            ),
+            r#"{"index":"items_label","row_id":1}"#.to_string(),
+        ),
+    ];
+    let mut root = 1;
+    for (key, json) in records {
+        root = PageStore::insert_entry(
+            &mut file,
+            BTreeEntry::with_inline_data(Value::Text(key), json.into_bytes()),
+            root,
+        )
+        .unwrap()
+        .0;
+    }
+    drop(file);
+
+    let engine = BTreeStorageEngine::new(&temp_path);
+    let mut db = engine.load().expect("failed to load version 4 file");
+    assert!(!db.tables["items"].is_paged());
+    assert_eq!(db.tables["items"].rows, items.rows);
+    assert_eq!(
+        db.indexes["items_label"].entries[&Value::Text("row-1".to_string())],
+        vec![RowId(1)]
+    );
+
+    let changes = insert_numbered_row(&mut db, 2);
+    db.indexes
+        .get_mut("items_label")
+        .unwrap()
+        .entries
+        .insert(Value::Text("row-2".to_string()), vec![RowId(2)]);
+    engine.save_changes(&db, &changes).unwrap();
+    engine.checkpoint().unwrap();
+    assert!(matches!(
+        read_versioned_header_with_versions(
+            &temp_path,
+            BTreeFile::MAGIC,
+            &BTreeFile::SUPPORTED_VERSIONS,
+            "BTree storage file",
+        ),
+        Ok(VersionedFileState::Valid { version }) if version == BTreeFile::VERSION
+    ));
+    let loaded = load_resident(&BTreeStorageEngine::new(&temp_path)).unwrap();
+    assert_eq!(loaded.tables["items"].rows, db.tables["items"].rows);
+    assert_eq!(
+        loaded.indexes["items_label"].entries,
+        db.indexes["items_label"].entries
+    );
+    assert_eq!(engine.check_integrity().unwrap(), Vec::new());
+
+    remove_storage_artifacts(&temp_path);
+}
+
+fn hex_bytes(text: &str) -> Vec<u8> {
+    let digits: String = text.split_whitespace().collect();
+    (0..digits.len())
+        .step_by(2)
+        .map(|index| u8::from_str_radix(&digits[index..index + 2], 16).unwrap())
+        .collect()
+}
+
+#[test]
+fn poly1305_matches_rfc_8439_vector() {
+    let key: [u8; 32] =
+        hex_bytes("85d6be7857556d337f4452fe42d506a8 0103808afb0db2fd4abff6af4149f51b")
+            .try_into()
+            .unwrap();
+    assert_eq!(
+        poly1305(&key, b"Cryptographic Forum Research Group").to_vec(),
+        hex_bytes("a8061dc1305136c6c22b8baf0c0127a9")
+    );
+}
+
+#[test]
+fn chacha20_poly1305_matches_rfc_8439_vector() {
+    let key: [u8; 32] = (0x80..=0x9f).collect::<Vec<u8>>().try_into().unwrap();
+    let nonce: [u8; 12] = hex_bytes("070000004041424344454647").try_into().unwrap();
+    let aad = hex_bytes("50515253c0c1c2c3c4c5c6c7");
+    let mut data = b"Ladies and Gentlemen of the class of '99: If I could offer you only one tip for the future, sunscreen would be it.".to_vec();
+    chacha20_xor(&key, &nonce, 1, &mut data);
+    assert_eq!(
+        &data[..16],
+        hex_bytes("d31a8d34648e60db7b86afbc53ef7ec2").as_slice()
+    );
+    assert_eq!(
+        aead_tag(&key, &nonce, &aad, &data).to_vec(),
+        hex_bytes("1ae10b594f09e26a7e902ecbd0600691")
+    );
+}
+
+#[test]
+fn hchacha20_matches_xchacha_draft_vector() {
+    let key: [u8; 32] = (0x00..=0x1f).collect::<Vec<u8>>().try_into().unwrap();
+    let nonce: [u8; 16] = hex_bytes("000000090000004a0000000031415927")
+        .try_into()
+        .unwrap();
+    assert_eq!(
+        hchacha20(&key, &nonce).to_vec(),
+        hex_bytes("82413b4227b27bfed30e42508a877d73 a0f9e4d58a74a853c12ec41326d3ecdc")
+    );
+}
+
+#[test]
+fn sealed_pages_reject_a_wrong_key_and_a_moved_slot() {
+    let cipher = PageCipher::new(EncryptionKey::new([7; 32]));
+    let other = PageCipher::new(EncryptionKey::new([8; 32]));
+    let mut page = BTreePage::new(0, PageKind::Meta);
+    page.push_entry(BTreeEntry::new(Value::Text("root".to_string()), 1));
+
+    let sealed = cipher.seal_page(&page).unwrap();
+    let key_check: [u8; KEY_CHECK_SIZE] = sealed[..KEY_CHECK_SIZE].try_into().unwrap();
+    assert!(cipher.key_check_matches(&key_check));
+    assert!(!other.key_check_matches(&key_check));
+    assert_eq!(cipher.open_page(0, &sealed).unwrap().entries.len(), 1);
+    assert!(other.open_page(0, &sealed).is_err());
+    // Two seals of the same page never share a nonce.
+    assert_ne!(cipher.seal_page(&page).unwrap(), sealed);
+
+    page.header.page_id = 5;
+    let sealed = cipher.seal_page(&page).unwrap();
+    assert!(!sealed.windows(4).any(|window| window == b"root"));
+    assert!(cipher.open_page(5, &sealed).is_ok());
+    assert!(cipher.open_page(6, &sealed).is_err());
+}
+
+#[test]
+fn writes_are_refused_after_another_process_wrote_in_a_lock_gap() {
+    let temp_path = std::env::temp_dir().join("rustql_btree_lock_gap.dat");
+    remove_storage_artifacts(&temp_path);
+    BTreeStorageEngine::new(&temp_path)
+        .save(&Database::new())
+        .unwrap();
+
+    // A gap nobody else used leaves the engine free to write.
+    let engine = BTreeStorageEngine::new(&temp_path);
+    let db = engine.load().unwrap();
+    engine.file_lock.release();
+    engine.save(&db).unwrap();
+    drop(engine);
+
+    let engine = BTreeStorageEngine::new(&temp_path);
+    let db = engine.load().unwrap();
+    engine.file_lock.release();
+    StorageLock::unshared(&temp_path).lock_exclusive().unwrap();
+    let err = engine.save(&db).unwrap_err();
+    assert!(
+        matches!(&err, RustqlError::StorageError(msg) if msg.contains("changed by another process")),
+        "unexpected error: {err:?}"
+    );
+    // The refused write gave the lock back, so other processes can read.
+    StorageLock::unshared(&temp_path)
+        .with_busy_timeout(std::time::Duration::ZERO)
+        .lock_shared()
+        .unwrap();
+    drop(engine);
+
+    // Opening the file again picks up the other process's write.
+    let engine = BTreeStorageEngine::new(&temp_path);
+    let db = engine.load().unwrap();
+    engine.save(&db).unwrap();
+    drop(engine);
+    remove_storage_artifacts(&temp_path);
+}
@@ -0,0 +1,571 @@
+use super::page::{
+    BTREE_PAGE_CAPACITY, BTREE_PAGE_HEADER_SIZE, BTREE_PAGE_SIZE, BTreeEntry, BTreePage, PageKind,
+};
+use crate::ast::Value;
+use crate::error::RustqlError;
+
+pub(super) const META_ROOT_KEY: &str = "root";
+pub(super) const META_NEXT_PAGE_KEY: &str = "next_page_id";
+/// First page of the free list; every free page links to the next one, and
+/// `0` ends the list.
+pub(super) const META_FREE_LIST_KEY: &str = "free_list_head";
+pub(super) const META_FREE_COUNT_KEY: &str = "free_page_count";
+
+/// Page-level access used by the B-tree algorithms.
+///
+/// `BTreeFile` implements this directly over the storage file, while
+/// incremental saves stage pages in memory and only write the touched pages
+/// once the whole change set has been applied.
+pub(super) trait PageStore {
+    fn read_page(&mut self, page_id: u64) -> Result<BTreePage, RustqlError>;
+
+    fn write_page(&mut self, page: &BTreePage) -> Result<(), RustqlError>;
+
+    fn meta_pointer(&mut self, name: &str) -> Result<Option<u64>, RustqlError> {
+        let meta_page = self.read_page(0)?;
+        Ok(meta_page
+            .entries
+            .iter()
+            .find(|e| matches!(&e.key, Value::Text(key) if key == name))
+            .map(|e| e.pointer))
+    }
+
+    fn set_meta_pointer(&mut self, name: &str, pointer: u64) -> Result<(), RustqlError> {
+        let mut meta_page = self.read_page(0)?;
+        if let Some(entry) = meta_page
+            .entries
+            .iter_mut()
+            .find(|e| matches!(&e.key, Value::Text(key) if key == name))
+        {
+            entry.pointer = pointer;
+        } else {
+            meta_page.push_entry(BTreeEntry::new(Value::Text(name.to_string()), pointer));
+        }
+        meta_page.refresh_entry_count();
+        self.write_page(&meta_page)
+    }
+
+    fn root_page_id(&mut self) -> Result<u64, RustqlError> {
+        Ok(self.meta_pointer(META_ROOT_KEY)?.unwrap_or(1))
+    }
+
+    /// Takes a page from the free list, or grows the file when it is empty.
+    fn allocate_page(&mut self) -> Result<u64, RustqlError> {
+        if let Some(free_id) = self.meta_pointer(META_FREE_LIST_KEY)?.filter(|id| *id != 0) {
+            let free_page = self.read_page(free_id)?;
+            if free_page.header.kind != PageKind::Free {
+                return Err(RustqlError::StorageError(format!(
+                    "BTree free list points at page {} which is not free",
+                    free_id
+                )));
+            }
+            let next_free = free_page.entries.first().map_or(0, |entry| entry.pointer);
+            let free_count = self.meta_pointer(META_FREE_COUNT_KEY)?.unwrap_or(1);
+            self.set_meta_pointer(META_FREE_LIST_KEY, next_free)?;
+            self.set_meta_pointer(META_FREE_COUNT_KEY, free_count.saturating_sub(1))?;
+            return Ok(free_id);
+        }
+        let next_id = self.meta_pointer(META_NEXT_PAGE_KEY)?.unwrap_or(2);
+        self.set_meta_pointer(META_NEXT_PAGE_KEY, next_id + 1)?;
+        Ok(next_id)
+    }
+
+    /// Pushes `page_id` onto the free list so a later allocation reuses it.
+    fn free_page(&mut self, page_id: u64) -> Result<(), RustqlError> {
+        let next_free = self.meta_pointer(META_FREE_LIST_KEY)?.unwrap_or(0);
+        let free_count = self.meta_pointer(META_FREE_COUNT_KEY)?.unwrap_or(0);
+        let mut free_page = BTreePage::new(page_id, PageKind::Free);
+        free_page.push_entry(BTreeEntry::new(Value::Null, next_free));
+        self.write_page(&free_page)?;
+        self.set_meta_pointer(META_FREE_LIST_KEY, page_id)?;
+        self.set_meta_pointer(META_FREE_COUNT_KEY, free_count + 1)
+    }
+
+    fn find_path_to_leaf(
+        &mut self,
+        key: &Value,
+        root_page_id: u64,
+    ) -> Result<Vec<u64>, RustqlError> {
+        let mut path = Vec::new();
+        let mut current_page_id = root_page_id;
+
+        loop {
+            path.push(current_page_id);
+            let page = self.read_page(current_page_id)?;
+
+            match page.header.kind {
+                PageKind::Leaf => {
+                    return Ok(path);
+                }
+                PageKind::Internal => {
+                    // Internal entries hold the lower bound of each child, so
+                    // descend into the last child whose bound does not exceed
+                    // the key. Keys below every bound belong to the first child.
+                    current_page_id = page
+                        .entries
+                        .iter()
+                        .rev()
+                        .find(|entry| &entry.key <= key)
+                        .or_else(|| page.entries.first())
+                        .map(|entry| entry.pointer)
+                        .ok_or_else(|| {
+                            RustqlError::StorageError("Invalid internal page structure".to_string())
+                        })?;
+                    if path.len() > MAX_TREE_DEPTH {
+                        return Err(RustqlError::StorageError(
+                            "BTree internal pages form a cycle".to_string(),
+                        ));
+                    }
+                }
+                PageKind::Meta | PageKind::Free | PageKind::Overflow => {
+                    return Err(RustqlError::StorageError(format!(
+                        "Cannot traverse {:?} page {} as part of the tree",
+                        page.header.kind, current_page_id
+                    )));
+                }
+            }
+        }
+    }
+
+    fn search(
+        &mut self,
+        key: &Value,
+        root_page_id: u64,
+    ) -> Result<Option<(u64, usize)>, RustqlError> {
+        let path = self.find_path_to_leaf(key, root_page_id)?;
+        let Some(leaf_page_id) = path.last().copied() else {
+            return Ok(None);
+        };
+        let leaf_page = self.read_page(leaf_page_id)?;
+        Ok(leaf_page
+            .entries
+            .binary_search_by(|entry| entry.key.cmp(key))
+            .ok()
+            .map(|idx| (leaf_page_id, idx)))
+    }
+
+    fn get_entry(
+        &mut self,
+        key: &Value,
+        root_page_id: u64,
+    ) -> Result<Option<BTreeEntry>, RustqlError> {
+        match self.search(key, root_page_id)? {
+            Some((page_id, idx)) => Ok(self.read_page(page_id)?.entries.into_iter().nth(idx)),
+            None => Ok(None),
+        }
+    }
+
+    /// Inserts `new_entry`, or replaces the entry with the same key. Returns
+    /// the root page id, which changes when the root splits, and the entry
+    /// that was replaced.
+    fn insert_entry(
+        &mut self,
+        new_entry: BTreeEntry,
+        root_page_id: u64,
+    ) -> Result<(u64, Option<BTreeEntry>), RustqlError> {
+        let mut root_page_id = root_page_id;
+        let (path, mut leaf_page) = loop {
+            let path = self.find_path_to_leaf(&new_entry.key, root_page_id)?;
+            let leaf_page_id = path.last().copied().ok_or_else(|| {
+                RustqlError::StorageError("BTree insert could not locate a leaf page".to_string())
+            })?;
+            let leaf_page = self.read_page(leaf_page_id)?;
+            let new_minimum = leaf_page
+                .entries
+                .first()
+                .is_none_or(|first| new_entry.key < first.key);
+            if new_minimum
+                && let Some(root) = self.lower_first_bounds(&path, &new_entry.key, root_page_id)?
+            {
+                root_page_id = root;
+                continue;
+            }
+            break (path, leaf_page);
+        };
+        if !leaf_page.can_hold_entry(&new_entry) {
+            return Err(RustqlError::StorageError(
+                "BTree record is too large to fit in a page".to_string(),
+            ));
+        }
+
+        match leaf_page
+            .entries
+            .binary_search_by(|e| e.key.cmp(&new_entry.key))
+        {
+            Ok(idx) => {
+                let replaced = std::mem::replace(&mut leaf_page.entries[idx], new_entry);
+                if leaf_page.fits() {
+                    self.write_page(&leaf_page)?;
+                    return Ok((root_page_id, Some(replaced)));
+                }
+                let new_entry = leaf_page.remove_entry(idx);
+                let root =
+                    self.split_and_insert_recursive(leaf_page, new_entry, path, root_page_id)?;
+                Ok((root, Some(replaced)))
+            }
+            Err(insert_pos) => {
+                if leaf_page.can_accept_entry(&new_entry) {
+                    leaf_page.insert_entry(insert_pos, new_entry);
+                    self.write_page(&leaf_page)?;
+                    Ok((root_page_id, None))
+                } else {
+                    let root =
+                        self.split_and_insert_recursive(leaf_page, new_entry, path, root_page_id)?;
+                    Ok((root, None))
+                }
+            }
+        }
+    }
+
+    /// Lowers the first bound of each internal page on `path` that sits above
+    /// `key`, so every bound stays at or below the keys of its child and a
+    /// later split of that child routes its separator after it. A page that
+    /// no longer fits is split, and the new root is returned so the caller
+    /// walks the tree again.
+    fn lower_first_bounds(
+        &mut self,
+        path: &[u64],
+        key: &Value,
+        root_page_id: u64,
+    ) -> Result<Option<u64>, RustqlError> {
+        for (depth, &page_id) in path.iter().enumerate().take(path.len().saturating_sub(1)) {
+            let mut page = self.read_page(page_id)?;
+            let Some(first) = page.entries.first_mut() else {
+                continue;
+            };
+            if &first.key <= key {
+                continue;
+            }
+            first.key = key.clone();
+            if page.fits() {
+                self.write_page(&page)?;
+                continue;
+            }
+            let lowered = page.remove_entry(0);
+            let root = self.split_and_insert_recursive(
+                page,
+                lowered,
+                path[..=depth].to_vec(),
+                root_page_id,
+            )?;
+            return Ok(Some(root));
+        }
+        Ok(None)
+    }
+
+    fn split_and_insert_recursive(
+        &mut self,
+        mut page: BTreePage,
+        new_entry: BTreeEntry,
+        mut path: Vec<u64>,
+        root_page_id: u64,
+    ) -> Result<u64, RustqlError> {
+        let insert_pos = page
+            .entries
+            .binary_search_by(|e| e.key.cmp(&new_entry.key))
+            .unwrap_or_else(|pos| pos);
+        page.entries.insert(insert_pos, new_entry);
+
+        let mid = page.split_point();
+        let right_entries = page.entries.split_off(mid);
+        let split_key = right_entries[0].key.clone();
+
+        page.refresh_entry_count();
+        let right_page_id = self.allocate_page()?;
+        let mut right_page = BTreePage::new(right_page_id, page.header.kind);
+        right_page.header.reserved = page.header.reserved;
+        right_page.replace_entries(right_entries);
+        if !page.fits() || !right_page.fits() {
+            return Err(RustqlError::StorageError(
+                "BTree records are too large to split across pages".to_string(),
+            ));
+        }
+        self.write_page(&page)?;
+        self.write_page(&right_page)?;
+
+        if page.header.page_id == root_page_id {
+            let new_root_id = self.allocate_page()?;
+            let mut new_root = BTreePage::new(new_root_id, PageKind::Internal);
+
+            let left_key = page.entries[0].key.clone();
+
+            new_root.push_entry(BTreeEntry::new(left_key, page.header.page_id));
+            new_root.push_entry(BTreeEntry::new(split_key, right_page_id));
+            self.write_page(&new_root)?;
+            self.set_meta_pointer(META_ROOT_KEY, new_root_id)?;
+
+            return Ok(new_root_id);
+        }
+
+        path.pop();
+        if let Some(parent_page_id) = path.last().copied() {
+            let mut parent_page = self.read_page(parent_page_id)?;
+
+            let parent_entry = BTreeEntry::new(split_key, right_page_id);
+
+            if parent_page.can_accept_entry(&parent_entry) {
+                let insert_pos = parent_page
+                    .entries
+                    .binary_search_by(|e| e.key.cmp(&parent_entry.key))
+                    .unwrap_or_else(|pos| pos);
+                parent_page.insert_entry(insert_pos, parent_entry);
+                self.write_page(&parent_page)?;
+                Ok(root_page_id)
+            } else {
+                self.split_and_insert_recursive(parent_page, parent_entry, path, root_page_id)
+            }
+        } else {
+            Ok(root_page_id)
+        }
+    }
+
+    /// Removes `key` from its leaf and returns the removed entry. Pages are
+    /// not merged, but a leaf that loses its last entry is unlinked from its
+    /// parent and freed.
+    fn delete_entry(
+        &mut self,
+        key: &Value,
+        root_page_id: u64,
+    ) -> Result<Option<BTreeEntry>, RustqlError> {
+        let path = self.find_path_to_leaf(key, root_page_id)?;
+        let Some(leaf_page_id) = path.last().copied() else {
+            return Ok(None);
+        };
+        let mut leaf_page = self.read_page(leaf_page_id)?;
+        let Ok(entry_idx) = leaf_page
+            .entries
+            .binary_search_by(|entry| entry.key.cmp(key))
+        else {
+            return Ok(None);
+        };
+        let removed = leaf_page.remove_entry(entry_idx);
+        if leaf_page.entries.is_empty() && path.len() > 1 {
+            self.release_empty_page(leaf_page, path)?;
+        } else {
+            self.write_page(&leaf_page)?;
+        }
+        Ok(Some(removed))
+    }
+
+    /// Unlinks the emptied last page of `path` from its parent and frees it.
+    /// Parents left without children are released the same way, and a root
+    /// that loses every child becomes an empty leaf, so the root page id
+    /// never changes.
+    fn release_empty_page(
+        &mut self,
+        empty_page: BTreePage,
+        mut path: Vec<u64>,
+    ) -> Result<(), RustqlError> {
+        let leaf_reserved = empty_page.header.reserved;
+        let mut child_page_id = empty_page.header.page_id;
+        loop {
+            path.pop();
+            let Some(parent_page_id) = path.last().copied() else {
+                return Err(RustqlError::StorageError(
+                    "BTree page has no parent to unlink from".to_string(),
+                ));
+            };
+            let mut parent_page = self.read_page(parent_page_id)?;
+            let entry_idx = parent_page
+                .entries
+                .iter()
+                .position(|entry| entry.pointer == child_page_id)
+                .ok_or_else(|| {
+                    RustqlError::StorageError(format!(
+                        "BTree page {} is not linked from its parent {}",
+                        child_page_id, parent_page_id
+                    ))
+                })?;
+            let removed = parent_page.remove_entry(entry_idx);
+            self.free_page(child_page_id)?;
+            // The next child inherits the removed bound, so the parent's
+            // first bound, and every bound above it, stays unchanged.
+            if entry_idx == 0
+                && let Some(next) = parent_page.entries.first_mut()
+            {
+                next.key = removed.key;
+            }
+
+            if !parent_page.entries.is_empty() {
+                return self.write_page(&parent_page);
+            }
+            if path.len() == 1 {
+                let mut root_page = BTreePage::new(parent_page_id, PageKind::Leaf);
+                root_page.header.reserved = leaf_reserved;
+                return self.write_page(&root_page);
+            }
+            child_page_id = parent_page_id;
+        }
+    }
+
+    fn scan_entries(
+        &mut self,
+        start_key: Option<&Value>,
+        end_key: Option<&Value>,
+        root_page_id: u64,
+    ) -> Result<Vec<BTreeEntry>, RustqlError> {
+        scan_pages_in_order_entries(
+            |page_id| self.read_page(page_id),
+            start_key,
+            end_key,
+            root_page_id,
+        )
+    }
+
+    /// Returns every entry whose text key starts with `prefix`.
+    fn scan_prefix(
+        &mut self,
+        prefix: &str,
+        root_page_id: u64,
+    ) -> Result<Vec<BTreeEntry>, RustqlError> {
+        let start = Value::Text(prefix.to_string());
+        let end = Value::Text(format!("{}{}", prefix, char::MAX));
+        let mut entries = self.scan_entries(Some(&start), Some(&end), root_page_id)?;
+        entries.retain(|entry| matches!(&entry.key, Value::Text(key) if key.starts_with(prefix)));
+        Ok(entries)
+    }
+}
+
+/// Bounds recursion over corrupt files whose internal pages point back at
+/// their ancestors.
+const MAX_TREE_DEPTH: usize = 64;
+
+impl BTreePage {
+    fn fits(&self) -> bool {
+        self.encoded_size() <= BTREE_PAGE_CAPACITY
+    }
+
+    fn can_hold_entry(&self, entry: &BTreeEntry) -> bool {
+        BTREE_PAGE_HEADER_SIZE + entry.encoded_size(self.header.kind, self.header.reserved)
+            <= BTREE_PAGE_SIZE / 2
+    }
+
+    /// Picks the split index that balances the encoded bytes of both halves.
+    fn split_point(&self) -> usize {
+        let sizes: Vec<usize> = self
+            .entries
+            .iter()
+            .map(|entry| entry.encoded_size(self.header.kind, self.header.reserved))
+            .collect();
+        let total: usize = sizes.iter().sum();
+        let mut left = 0;
+        let mut best = (usize::MAX, 1);
+        for (idx, size) in sizes.iter().enumerate().take(sizes.len().saturating_sub(1)) {
+            left += size;
+            let heavier = left.max(total - left);
+            if heavier < best.0 {
+                best = (heavier, idx + 1);
+            }
+        }
+        best.1
+    }
+}
+
+pub(super) fn scan_pages_in_order_entries<F>(
+    read_page: F,
+    start_key: Option<&Value>,
+    end_key: Option<&Value>,
+    root_page_id: u64,
+) -> Result<Vec<BTreeEntry>, RustqlError>
+where
+    F: FnMut(u64) -> Result<BTreePage, RustqlError>,
+{
+    let mut entries = Vec::new();
+    visit_entries_in_order(read_page, start_key, end_key, root_page_id, |entry| {
+        entries.push(entry);
+        Ok(true)
+    })?;
+    Ok(entries)
+}
+
+/// Calls `visit` with the entries between `start_key` and `end_key` in key
+/// order, reading one page at a time. Stops early when `visit` returns
+/// `false`.
+pub(super) fn visit_entries_in_order<F, V>(
+    mut read_page: F,
+    start_key: Option<&Value>,
+    end_key: Option<&Value>,
+    root_page_id: u64,
+    mut visit: V,
+) -> Result<(), RustqlError>
+where
+    F: FnMut(u64) -> Result<BTreePage, RustqlError>,
+    V: FnMut(BTreeEntry) -> Result<bool, RustqlError>,
+{
+    visit_page_in_order(
+        &mut read_page,
+        root_page_id,
+        start_key,
+        end_key,
+        &mut visit,
+        0,
+    )
+    .map(|_| ())
+}
+
+fn visit_page_in_order<F, V>(
+    read_page: &mut F,
+    page_id: u64,
+    start_key: Option<&Value>,
+    end_key: Option<&Value>,
+    visit: &mut V,
+    depth: usize,
+) -> Result<bool, RustqlError>
+where
+    F: FnMut(u64) -> Result<BTreePage, RustqlError>,
+    V: FnMut(BTreeEntry) -> Result<bool, RustqlError>,
+{
+    if depth > MAX_TREE_DEPTH {
+        return Err(RustqlError::StorageError(
+            "BTree internal pages form a cycle".to_string(),
+        ));
+    }
+    let page = read_page(page_id)?;
+
+    match page.header.kind {
+        PageKind::Leaf => {
+            for entry in page.entries {
+                if start_key.is_some_and(|start| &entry.key < start) {
+                    continue;
+                }
+                if end_key.is_some_and(|end| &entry.key > end) {
+                    return Ok(false);
+                }
+                if !visit(entry)? {
+                    return Ok(false);
+                }
+            }
+            Ok(true)
+        }
+        PageKind::Internal => {
+            for (idx, entry) in page.entries.iter().enumerate() {
+                if idx > 0 && end_key.is_some_and(|end| &entry.key > end) {
+                    return Ok(false);
+                }
+
+                let next_min = page.entries.get(idx + 1).map(|next| &next.key);
+                if start_key.is_some_and(|start| next_min.is_some_and(|min| min <= start)) {
+                    continue;
+                }
+
+                if !visit_page_in_order(
+                    read_page,
+                    entry.pointer,
+                    start_key,
+                    end_key,
+                    visit,
+                    depth + 1,
+                )? {
+                    return Ok(false);
+                }
+            }
+            Ok(true)
+        }
+        PageKind::Meta | PageKind::Free | PageKind::Overflow => {
+            Err(RustqlError::StorageError(format!(
+                "Cannot scan entries from {:?} page {}",
+                page.header.kind, page_id
+            )))
+        }
+    }
+}
@@ -0,0 +1,154 @@
+use super::BTreeStorageEngine;
+use super::file::{BTreeFile, CachedBTreeFile};
+use super::header::{FILE_HEADER_SIZE, VersionedFileState, read_versioned_header_with_versions};
+use super::overflow::overflow_pages;
+use super::page::{BTREE_PAGE_SIZE, PageKind};
+use super::records::{
+    COMPOSITE_INDEX_ENTRY_KEY_PREFIX, COMPOSITE_INDEX_KEY_PREFIX, INDEX_ENTRY_KEY_PREFIX,
+    INDEX_KEY_PREFIX, IndexEntryRecord, SCHEMA_KEY_PREFIX, parse_row_storage_key,
+};
+use super::tree::{META_FREE_COUNT_KEY, PageStore};
+use crate::ast::Value;
+use crate::database::Database;
+use crate::error::RustqlError;
+use crate::storage::{PageUsage, PageUsageKind, VacuumStats};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+
+/// Bounds the walk over corrupt files whose internal pages point back at
+/// their ancestors.
+const MAX_USAGE_DEPTH: usize = 64;
+
+impl BTreeStorageEngine {
+    /// Length of the committed file, including pages still in the redo log.
+    pub(super) fn committed_file_len(&self) -> u64 {
+        fs::metadata(&self.data_path)
+            .map(|metadata| metadata.len())
+            .unwrap_or(0)
+            .max(self.redo_log.logged_file_len())
+    }
+
+    /// Rewrites the file as a fresh snapshot of `db`, which holds no free or
+    /// partly filled pages. Must be called under the storage write lock.
+    pub(super) fn vacuum_locked(&self, db: &Database) -> Result<VacuumStats, RustqlError> {
+        let bytes_before = self.committed_file_len();
+        self.save_locked(db)?;
+        Ok(VacuumStats {
+            bytes_before,
+            bytes_after: self.committed_file_len(),
+        })
+    }
+
+    /// Counts the pages that hold records of each table and index by walking
+    /// the tree once. A leaf shared by several objects counts for each of
+    /// them.
+    pub(super) fn page_usage_locked(&self) -> Result<Vec<PageUsage>, RustqlError> {
+        match read_versioned_header_with_versions(
+            &self.data_path,
+            BTreeFile::MAGIC,
+            &BTreeFile::SUPPORTED_VERSIONS,
+            "BTree storage file",
+        )? {
+            VersionedFileState::Valid { .. } => {}
+            VersionedFileState::Missing | VersionedFileState::Empty => return Ok(Vec::new()),
+        }
+
+        let mut file = CachedBTreeFile { engine: self };
+        let mut tables: BTreeMap<String, u64> = BTreeMap::new();
+        let mut indexes: BTreeMap<String, u64> = BTreeMap::new();
+        let mut internal_pages = 0;
+        let mut pending = vec![(file.root_page_id()?, 0)];
+        while let Some((page_id, depth)) = pending.pop() {
+            if depth > MAX_USAGE_DEPTH {
+                return Err(RustqlError::StorageError(
+                    "BTree internal pages form a cycle".to_string(),
+                ));
+            }
+            let page = file.read_page(page_id)?;
+            match page.header.kind {
+                PageKind::Internal => {
+                    internal_pages += 1;
+                    pending.extend(page.entries.iter().map(|entry| (entry.pointer, depth + 1)));
+                }
+                PageKind::Leaf => {
+                    let mut page_tables = BTreeSet::new();
+                    let mut page_indexes = BTreeSet::new();
+                    for entry in &page.entries {
+                        let Value::Text(key) = &entry.key else {
+                            continue;
+                        };
+                        let table = parse_row_storage_key(key)
+                            .map(|(table, _, _)| table)
+                            .or_else(|| key.strip_prefix(SCHEMA_KEY_PREFIX));
+                        if let Some(table) = table {
+                            // Overflow pages hold one record each, so they
+                            // count for its table alone.
+                            if let Some(data) = &entry.inline_data {
+                                let overflow =
+                                    overflow_pages(|page_id| file.read_page(page_id), data)?;
+                                *tables.entry(table.to_string()).or_default() +=
+                                    overflow.len() as u64;
+                            }
+                            page_tables.insert(table.to_string());
+                        } else if key.starts_with(INDEX_ENTRY_KEY_PREFIX)
+                            || key.starts_with(COMPOSITE_INDEX_ENTRY_KEY_PREFIX)
+                        {
+                            let record: IndexEntryRecord =
+                                file.read_data_from_entry(entry, "index entry")?;
+                            page_indexes.insert(record.index);
+                        } else if let Some(index) = key
+                            .strip_prefix(INDEX_KEY_PREFIX)
+                            .or_else(|| key.strip_prefix(COMPOSITE_INDEX_KEY_PREFIX))
+                        {
+                            page_indexes.insert(index.to_string());
+                        }
+                    }
+                    for table in page_tables {
+                        *tables.entry(table).or_default() += 1;
+                    }
+                    for index in page_indexes {
+                        *indexes.entry(index).or_default() += 1;
+                    }
+                }
+                PageKind::Meta | PageKind::Free | PageKind::Overflow => {
+                    return Err(RustqlError::StorageError(format!(
+                        "Cannot traverse {:?} page {} as part of the tree",
+                        page.header.kind, page_id
+                    )));
+                }
+            }
+        }
+
+        let free_pages = file.meta_pointer(META_FREE_COUNT_KEY)?.unwrap_or(0);
+        let file_pages = self
+            .committed_file_len()
+            .saturating_sub(FILE_HEADER_SIZE as u64)
+            / BTREE_PAGE_SIZE as u64;
+
+        let mut usage: Vec<PageUsage> = tables
+            .into_iter()
+            .map(|(name, pages)| PageUsage {
+                kind: PageUsageKind::Table,
+                name: Some(name),
+                pages,
+            })
+            .collect();
+        usage.extend(indexes.into_iter().map(|(name, pages)| PageUsage {
+            kind: PageUsageKind::Index,
+            name: Some(name),
+            pages,
+        }));
+        for (kind, pages) in [
+            (PageUsageKind::Internal, internal_pages),
+            (PageUsageKind::Free, free_pages),
+            (PageUsageKind::File, file_pages),
+        ] {
+            usage.push(PageUsage {
+                kind,
+                name: None,
+                pages,
+            });
+        }
+        Ok(usage)
+    }
+}
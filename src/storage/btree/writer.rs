@@ -0,0 +1,568 @@
+use super::encoding::{DecodeRecord, EncodeRecord, decode_record, encode_record};
+use super::overflow::{MAX_INLINE_RECORD_SIZE, free_payload, load_payload, store_payload};
+use super::page::BTreeEntry;
+use super::records::{
+    COMPOSITE_INDEX_ENTRY_KEY_PREFIX, COMPOSITE_INDEX_KEY_PREFIX, INDEX_ENTRY_KEY_PREFIX,
+    INDEX_KEY_PREFIX, IndexEntryRecord, IndexTableRecord, PREPARED_KEY_PREFIX, ROW_KEY_PREFIX,
+    SCHEMA_KEY_PREFIX, TableStorageRecord, VIEW_KEY_PREFIX, composite_index_key_text,
+    format_index_entry_key, format_row_storage_key, index_key_text, parse_row_storage_key,
+};
+use super::tree::PageStore;
+use crate::ast::Value;
+use crate::database::{
+    CompositeIndex, Database, Index, PreparedTransaction, ReplacedRows, RowId, Table, View,
+};
+use crate::error::RustqlError;
+use crate::storage::ChangeSet;
+use std::collections::BTreeSet;
+
+/// Writes logical database records into a B-tree through a [`PageStore`].
+///
+/// Snapshot writes call [`RecordWriter::write_database`] on an empty tree.
+/// Incremental saves call [`RecordWriter::apply_changes`] on the current tree
+/// so only the records named by the change set are touched.
+pub(super) struct RecordWriter<'s, S: PageStore> {
+    store: &'s mut S,
+    root: u64,
+    /// The stored rows the changes replaced, kept when paged tables still
+    /// read the rows before them.
+    replaced: Option<ReplacedRows>,
+}
+
+impl<'s, S: PageStore> RecordWriter<'s, S> {
+    pub(super) fn new(store: &'s mut S) -> Result<Self, RustqlError> {
+        let root = store.root_page_id()?;
+        Ok(RecordWriter {
+            store,
+            root,
+            replaced: None,
+        })
+    }
+
+    /// Keeps the stored rows the following changes replace.
+    pub(super) fn track_replaced_rows(&mut self) {
+        self.replaced.get_or_insert_default();
+    }
+
+    /// The rows the changes replaced, by stored table: `None` for a row they
+    /// inserted.
+    pub(super) fn into_replaced(self) -> ReplacedRows {
+        self.replaced.unwrap_or_default()
+    }
+
+    /// Records the row `row_id` held before the changes, unless an earlier
+    /// change already did.
+    fn record_replaced(&mut self, table_name: &str, row_id: RowId, row: Option<&Vec<Value>>) {
+        if let Some(replaced) = &mut self.replaced {
+            replaced
+                .entry(table_name.to_string())
+                .or_default()
+                .entry(row_id)
+                .or_insert_with(|| row.cloned());
+        }
+    }
+
+    pub(super) fn write_database(&mut self, db: &Database) -> Result<(), RustqlError> {
+        for (table_name, table) in &db.tables {
+            require_loaded(table_name, table)?;
+            self.put_schema(table_name, table)?;
+        }
+        for (table_name, table) in &db.tables {
+            for (row_id, row) in table.iter_rows_with_ids() {
+                self.put_row(table_name, row_id, row)?;
+            }
+        }
+        for index in db.indexes.values() {
+            self.put_index(index)?;
+        }
+        for index in db.composite_indexes.values() {
+            self.put_composite_index(index)?;
+        }
+        for (view_name, view) in &db.views {
+            self.put_view(view_name, view)?;
+        }
+        for prepared in db.prepared_transactions.values() {
+            self.put_prepared(prepared)?;
+        }
+        Ok(())
+    }
+
+    pub(super) fn apply_changes(
+        &mut self,
+        db: &Database,
+        changes: &ChangeSet,
+    ) -> Result<(), RustqlError> {
+        let rewritten_tables = changes.tables();
+        let mut rewritten_indexes = changes.indexes().clone();
+        if !rewritten_tables.is_empty() {
+            for (index_name, table_name) in self.stored_index_tables()? {
+                if rewritten_tables.contains(&table_name) {
+                    rewritten_indexes.insert(index_name);
+                }
+            }
+            for index in db.indexes.values() {
+                if rewritten_tables.contains(&index.table) {
+                    rewritten_indexes.insert(index.name.clone());
+                }
+            }
+            for index in db.composite_indexes.values() {
+                if rewritten_tables.contains(&index.table) {
+                    rewritten_indexes.insert(index.name.clone());
+                }
+            }
+        }
+
+        for table_name in rewritten_tables {
+            self.rewrite_table(db, table_name)?;
+        }
+
+        for (table_name, row_ids) in changes.rows() {
+            if rewritten_tables.contains(table_name) {
+                continue;
+            }
+            let Some(table) = db.tables.get(table_name) else {
+                continue;
+            };
+            self.put_schema(table_name, table)?;
+            for row_id in row_ids {
+                self.update_row(db, table_name, table, *row_id, &rewritten_indexes)?;
+            }
+        }
+
+        for table_name in changes.schemas() {
+            if rewritten_tables.contains(table_name) {
+                continue;
+            }
+            if let Some(table) = db.tables.get(table_name) {
+                self.put_schema(table_name, table)?;
+            }
+            // Index definitions name the table's columns.
+            let index_names: Vec<&String> = db
+                .indexes
+                .values()
+                .filter(|index| &index.table == table_name)
+                .map(|index| &index.name)
+                .chain(
+                    db.composite_indexes
+                        .values()
+                        .filter(|index| &index.table == table_name)
+                        .map(|index| &index.name),
+                )
+                .filter(|name| !rewritten_indexes.contains(*name))
+                .collect();
+            for index_name in index_names {
+                self.rewrite_index_definition(db, index_name)?;
+            }
+        }
+
+        for index_name in &rewritten_indexes {
+            self.rewrite_index(db, index_name)?;
+        }
+
+        for view_name in changes.views() {
+            self.delete(format!("{VIEW_KEY_PREFIX}{view_name}"))?;
+            if let Some(view) = db.views.get(view_name) {
+                self.put_view(view_name, view)?;
+            }
+        }
+
+        for gid in changes.prepared() {
+            self.delete(format!("{PREPARED_KEY_PREFIX}{gid}"))?;
+            if let Some(prepared) = db.prepared_transaction(gid) {
+                self.put_prepared(prepared)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn update_row(
+        &mut self,
+        db: &Database,
+        table_name: &str,
+        table: &Table,
+        row_id: RowId,
+        skipped_indexes: &BTreeSet<String>,
+    ) -> Result<(), RustqlError> {
+        // A paged table only holds the rows it wrote.
+        let Some(new_row) = table.written_row(row_id) else {
+            return Ok(());
+        };
+        let row_key = format_row_storage_key(table_name, row_id);
+        let old_row: Option<Vec<Value>> = self.get(
+            &row_key,
+            format!("row {} for table {}", row_id.0, table_name),
+        )?;
+        self.record_replaced(table_name, row_id, old_row.as_ref());
+        match new_row {
+            Some(row) => self.put_row(table_name, row_id, row)?,
+            None => {
+                self.delete(row_key)?;
+            }
+        }
+
+        for index in db.indexes.values() {
+            if index.table != table_name || skipped_indexes.contains(&index.name) {
+                continue;
+            }
+            let column = table
+                .columns
+                .iter()
+                .position(|col| col.name == index.column)
+                .ok_or_else(|| RustqlError::ColumnNotFound(index.column.clone()))?;
+            if let Some(old_row) = &old_row {
+                let key = old_row.get(column).cloned().unwrap_or(Value::Null);
+                self.delete(format_index_entry_key(
+                    INDEX_ENTRY_KEY_PREFIX,
+                    &index.name,
+                    &index_key_text(&key),
+                    row_id,
+                ))?;
+            }
+            if let Some(new_row) = new_row {
+                let key = new_row.get(column).cloned().unwrap_or(Value::Null);
+                if index
+                    .entries
+                    .get(&key)
+                    .is_some_and(|row_ids| row_ids.contains(&row_id))
+                {
+                    self.put_index_entry(
+                        INDEX_ENTRY_KEY_PREFIX,
+                        &index.name,
+                        &index_key_text(&key),
+                        row_id,
+                    )?;
+                }
+            }
+        }
+
+        for index in db.composite_indexes.values() {
+            if index.table != table_name || skipped_indexes.contains(&index.name) {
+                continue;
+            }
+            let columns = index
+                .columns
+                .iter()
+                .map(|name| {
+                    table
+                        .columns
+                        .iter()
+                        .position(|col| &col.name == name)
+                        .ok_or_else(|| RustqlError::ColumnNotFound(name.clone()))
+                })
+                .collect::<Result<Vec<_>, _>>()?;
+            let key_for = |row: &[Value]| -> Vec<Value> {
+                columns
+                    .iter()
+                    .map(|column| row.get(*column).cloned().unwrap_or(Value::Null))
+                    .collect()
+            };
+            if let Some(old_row) = &old_row {
+                self.delete(format_index_entry_key(
+                    COMPOSITE_INDEX_ENTRY_KEY_PREFIX,
+                    &index.name,
+                    &composite_index_key_text(&key_for(old_row)),
+                    row_id,
+                ))?;
+            }
+            if let Some(new_row) = new_row {
+                let key = key_for(new_row);
+                if index
+                    .entries
+                    .get(&key)
+                    .is_some_and(|row_ids| row_ids.contains(&row_id))
+                {
+                    self.put_index_entry(
+                        COMPOSITE_INDEX_ENTRY_KEY_PREFIX,
+                        &index.name,
+                        &composite_index_key_text(&key),
+                        row_id,
+                    )?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn rewrite_table(&mut self, db: &Database, table_name: &str) -> Result<(), RustqlError> {
+        for entry in self
+            .store
+            .scan_prefix(&format!("{ROW_KEY_PREFIX}{table_name}:"), self.root)?
+        {
+            let Value::Text(key) = &entry.key else {
+                continue;
+            };
+            let Some((table, row_id, _)) = parse_row_storage_key(key) else {
+                continue;
+            };
+            if table != table_name {
+                continue;
+            }
+            if self.replaced.is_some() {
+                let row: Vec<Value> = self
+                    .decode_entry(&entry, format!("row {} for table {}", row_id.0, table_name))?;
+                self.record_replaced(table_name, row_id, Some(&row));
+            }
+            self.delete(key.clone())?;
+        }
+        self.delete(format!("{SCHEMA_KEY_PREFIX}{table_name}"))?;
+
+        if let Some(table) = db.tables.get(table_name) {
+            require_loaded(table_name, table)?;
+            self.put_schema(table_name, table)?;
+            for (row_id, row) in table.iter_rows_with_ids() {
+                self.record_replaced(table_name, row_id, None);
+                self.put_row(table_name, row_id, row)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn rewrite_index(&mut self, db: &Database, index_name: &str) -> Result<(), RustqlError> {
+        let paged = db
+            .indexes
+            .get(index_name)
+            .is_some_and(|index| index.paged.is_some())
+            || db
+                .composite_indexes
+                .get(index_name)
+                .is_some_and(|index| index.paged.is_some());
+        if paged {
+            return self.rewrite_index_definition(db, index_name);
+        }
+        self.delete(format!("{INDEX_KEY_PREFIX}{index_name}"))?;
+        self.delete(format!("{COMPOSITE_INDEX_KEY_PREFIX}{index_name}"))?;
+        for prefix in [INDEX_ENTRY_KEY_PREFIX, COMPOSITE_INDEX_ENTRY_KEY_PREFIX] {
+            for entry in self
+                .store
+                .scan_prefix(&format!("{prefix}{index_name}:"), self.root)?
+            {
+                let record: IndexEntryRecord =
+                    self.decode_entry(&entry, format!("entry of index {}", index_name))?;
+                if record.index == index_name {
+                    self.delete_key(&entry.key)?;
+                }
+            }
+        }
+
+        if let Some(index) = db.indexes.get(index_name) {
+            self.put_index(index)?;
+        }
+        if let Some(index) = db.composite_indexes.get(index_name) {
+            self.put_composite_index(index)?;
+        }
+        Ok(())
+    }
+
+    /// Rewrites the definition of an index whose entries stay paged with its
+    /// table, leaving the stored entries in place.
+    fn rewrite_index_definition(
+        &mut self,
+        db: &Database,
+        index_name: &str,
+    ) -> Result<(), RustqlError> {
+        if let Some(index) = db.indexes.get(index_name) {
+            self.put(format!("{INDEX_KEY_PREFIX}{index_name}"), index, || {
+                format!("index {}", index_name)
+            })?;
+        }
+        if let Some(index) = db.composite_indexes.get(index_name) {
+            self.put(
+                format!("{COMPOSITE_INDEX_KEY_PREFIX}{index_name}"),
+                index,
+                || format!("composite index {}", index_name),
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Lists `(index name, table name)` for every index definition on disk.
+    fn stored_index_tables(&mut self) -> Result<Vec<(String, String)>, RustqlError> {
+        let mut indexes = Vec::new();
+        for prefix in [INDEX_KEY_PREFIX, COMPOSITE_INDEX_KEY_PREFIX] {
+            for entry in self.store.scan_prefix(prefix, self.root)? {
+                let Value::Text(key) = &entry.key else {
+                    continue;
+                };
+                let Some(index_name) = key.strip_prefix(prefix) else {
+                    continue;
+                };
+                let record: IndexTableRecord =
+                    self.decode_entry(&entry, format!("index {}", index_name))?;
+                indexes.push((index_name.to_string(), record.table));
+            }
+        }
+        Ok(indexes)
+    }
+
+    fn put_schema(&mut self, table_name: &str, table: &Table) -> Result<(), RustqlError> {
+        self.put(
+            format!("{SCHEMA_KEY_PREFIX}{table_name}"),
+            &TableStorageRecord {
+                columns: table.columns.clone(),
+                constraints: table.constraints.clone(),
+                next_row_id: table.next_row_id,
+                row_count: Some(table.row_count() as u64),
+            },
+            || format!("schema for table {}", table_name),
+        )
+    }
+
+    fn put_row(
+        &mut self,
+        table_name: &str,
+        row_id: RowId,
+        row: &[Value],
+    ) -> Result<(), RustqlError> {
+        self.put(format_row_storage_key(table_name, row_id), row, || {
+            format!("row {} for table {}", row_id.0, table_name)
+        })
+    }
+
+    fn put_index(&mut self, index: &Index) -> Result<(), RustqlError> {
+        // The definition record leaves out the entries, which are stored as
+        // records of their own.
+        self.put(format!("{INDEX_KEY_PREFIX}{}", index.name), index, || {
+            format!("index {}", index.name)
+        })?;
+        for (key, row_ids) in &index.entries {
+            let key_text = index_key_text(key);
+            for row_id in row_ids {
+                self.put_index_entry(INDEX_ENTRY_KEY_PREFIX, &index.name, &key_text, *row_id)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn put_composite_index(&mut self, index: &CompositeIndex) -> Result<(), RustqlError> {
+        self.put(
+            format!("{COMPOSITE_INDEX_KEY_PREFIX}{}", index.name),
+            index,
+            || format!("composite index {}", index.name),
+        )?;
+        for (key, row_ids) in &index.entries {
+            let key_text = composite_index_key_text(key);
+            for row_id in row_ids {
+                self.put_index_entry(
+                    COMPOSITE_INDEX_ENTRY_KEY_PREFIX,
+                    &index.name,
+                    &key_text,
+                    *row_id,
+                )?;
+            }
+        }
+        Ok(())
+    }
+
+    fn put_index_entry(
+        &mut self,
+        prefix: &str,
+        index_name: &str,
+        key_text: &str,
+        row_id: RowId,
+    ) -> Result<(), RustqlError> {
+        self.put(
+            format_index_entry_key(prefix, index_name, key_text, row_id),
+            &IndexEntryRecord {
+                index: index_name.to_string(),
+                row_id,
+            },
+            || format!("entry of index {}", index_name),
+        )
+    }
+
+    fn put_view(&mut self, view_name: &str, view: &View) -> Result<(), RustqlError> {
+        self.put(format!("{VIEW_KEY_PREFIX}{view_name}"), view, || {
+            format!("view {}", view_name)
+        })
+    }
+
+    fn put_prepared(&mut self, prepared: &PreparedTransaction) -> Result<(), RustqlError> {
+        self.put(
+            format!("{PREPARED_KEY_PREFIX}{}", prepared.gid),
+            prepared,
+            || format!("prepared transaction {}", prepared.gid),
+        )
+    }
+
+    /// Stores `record` under `key`, on overflow pages when it is too large to
+    /// keep in a leaf. The overflow pages of a replaced record are freed.
+    fn put<T, L>(&mut self, key: String, record: &T, label: L) -> Result<(), RustqlError>
+    where
+        T: EncodeRecord + ?Sized,
+        L: FnOnce() -> String,
+    {
+        let payload = encode_record(record).map_err(|e| {
+            RustqlError::StorageError(format!("Failed to serialize {}: {}", label(), e))
+        })?;
+        let key = Value::Text(key);
+        if payload.len() > MAX_INLINE_RECORD_SIZE {
+            // Free the old record's chain first so the new one can reuse it.
+            self.delete_key(&key)?;
+        }
+        let payload = store_payload(self.store, payload)?;
+        let (root, replaced) = self
+            .store
+            .insert_entry(BTreeEntry::with_inline_data(key, payload), self.root)?;
+        self.root = root;
+        self.free_overflow(replaced)
+    }
+
+    fn get<T: DecodeRecord>(
+        &mut self,
+        key: &str,
+        label: impl Into<String>,
+    ) -> Result<Option<T>, RustqlError> {
+        match self
+            .store
+            .get_entry(&Value::Text(key.to_string()), self.root)?
+        {
+            Some(entry) => self.decode_entry(&entry, label).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    fn delete(&mut self, key: String) -> Result<bool, RustqlError> {
+        self.delete_key(&Value::Text(key))
+    }
+
+    fn delete_key(&mut self, key: &Value) -> Result<bool, RustqlError> {
+        let removed = self.store.delete_entry(key, self.root)?;
+        let deleted = removed.is_some();
+        self.free_overflow(removed)?;
+        Ok(deleted)
+    }
+
+    fn free_overflow(&mut self, entry: Option<BTreeEntry>) -> Result<(), RustqlError> {
+        match entry.and_then(|entry| entry.inline_data) {
+            Some(payload) => free_payload(self.store, &payload),
+            None => Ok(()),
+        }
+    }
+
+    fn decode_entry<T: DecodeRecord>(
+        &mut self,
+        entry: &BTreeEntry,
+        label: impl Into<String>,
+    ) -> Result<T, RustqlError> {
+        let label = label.into();
+        let payload = entry.inline_data.as_deref().ok_or_else(|| {
+            RustqlError::StorageError(format!("Record for {} is missing inline data", label))
+        })?;
+        let payload = load_payload(|page_id| self.store.read_page(page_id), payload)?;
+        decode_record(&payload, &label)
+    }
+}
+
+/// Row records are written from memory, so a table whose rows are still paged
+/// out must be loaded before any of them can be rewritten.
+fn require_loaded(table_name: &str, table: &Table) -> Result<(), RustqlError> {
+    if table.is_paged() {
+        return Err(RustqlError::Internal(format!(
+            "Table '{}' must be loaded before its rows are written",
+            table_name
+        )));
+    }
+    Ok(())
+}
@@ -0,0 +1,130 @@
+use crate::database::RowId;
+use std::collections::{BTreeMap, BTreeSet};
+
+/// Logical records touched since the last successful save.
+///
+/// Storage engines that can update their on-disk image in place use this to
+/// limit a save to the affected rows, tables, indexes, views, and prepared
+/// transactions. Engines that only write snapshots can ignore it and persist
+/// the whole database.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ChangeSet {
+    rows: BTreeMap<String, BTreeSet<RowId>>,
+    schemas: BTreeSet<String>,
+    tables: BTreeSet<String>,
+    indexes: BTreeSet<String>,
+    views: BTreeSet<String>,
+    prepared: BTreeSet<String>,
+}
+
+impl ChangeSet {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records an inserted, updated, or deleted row.
+    pub fn record_row(&mut self, table: &str, row_id: RowId) {
+        self.rows
+            .entry(table.to_string())
+            .or_default()
+            .insert(row_id);
+    }
+
+    /// Records a change to table metadata that leaves its rows untouched.
+    pub fn record_schema(&mut self, table: &str) {
+        self.schemas.insert(table.to_string());
+    }
+
+    /// Records a table whose schema, rows, and indexes must all be rewritten.
+    pub fn record_table(&mut self, table: &str) {
+        self.tables.insert(table.to_string());
+    }
+
+    pub fn record_index(&mut self, index: &str) {
+        self.indexes.insert(index.to_string());
+    }
+
+    pub fn record_view(&mut self, view: &str) {
+        self.views.insert(view.to_string());
+    }
+
+    /// Records a prepared transaction that was added or resolved.
+    pub fn record_prepared(&mut self, gid: &str) {
+        self.prepared.insert(gid.to_string());
+    }
+
+    pub fn rows(&self) -> &BTreeMap<String, BTreeSet<RowId>> {
+        &self.rows
+    }
+
+    pub fn schemas(&self) -> &BTreeSet<String> {
+        &self.schemas
+    }
+
+    pub fn tables(&self) -> &BTreeSet<String> {
+        &self.tables
+    }
+
+    pub fn indexes(&self) -> &BTreeSet<String> {
+        &self.indexes
+    }
+
+    pub fn views(&self) -> &BTreeSet<String> {
+        &self.views
+    }
+
+    pub fn prepared(&self) -> &BTreeSet<String> {
+        &self.prepared
+    }
+
+    /// Moves the records of the objects another file stores into a change
+    /// set of their own, under the names they have in that file.
+    /// `table_name` maps table and view names and `index_name` index names,
+    /// returning `None` for objects this set keeps.
+    pub(crate) fn split_off(
+        &mut self,
+        table_name: impl Fn(&str) -> Option<String>,
+        index_name: impl Fn(&str) -> Option<String>,
+    ) -> ChangeSet {
+        fn split(
+            names: &mut BTreeSet<String>,
+            rename: &impl Fn(&str) -> Option<String>,
+        ) -> BTreeSet<String> {
+            let mut moved = BTreeSet::new();
+            names.retain(|name| match rename(name) {
+                Some(renamed) => {
+                    moved.insert(renamed);
+                    false
+                }
+                None => true,
+            });
+            moved
+        }
+
+        let mut rows = BTreeMap::new();
+        self.rows.retain(|table, ids| match table_name(table) {
+            Some(renamed) => {
+                rows.insert(renamed, std::mem::take(ids));
+                false
+            }
+            None => true,
+        });
+        ChangeSet {
+            rows,
+            schemas: split(&mut self.schemas, &table_name),
+            tables: split(&mut self.tables, &table_name),
+            indexes: split(&mut self.indexes, &index_name),
+            views: split(&mut self.views, &table_name),
+            prepared: BTreeSet::new(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rows.is_empty()
+            && self.schemas.is_empty()
+            && self.tables.is_empty()
+            && self.indexes.is_empty()
+            && self.views.is_empty()
+            && self.prepared.is_empty()
+    }
+}
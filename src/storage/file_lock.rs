@@ -0,0 +1,369 @@
+use crate::error::RustqlError;
+use std::collections::HashMap;
+use std::fs::{File, OpenOptions};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, LazyLock, Mutex, MutexGuard, Weak};
+use std::time::{Duration, Instant};
+
+/// Default time to wait for another process to release a storage file.
+pub const DEFAULT_BUSY_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Longest pause between two attempts to take a busy lock.
+const MAX_RETRY_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Lock files held by this process, by path. Every engine opened on the same
+/// file shares one lock, so engines in one process never lock each other out;
+/// the in-process locks of the storage engines order their work.
+static HELD_LOCKS: LazyLock<Mutex<HashMap<PathBuf, Weak<HeldLock>>>> =
+    LazyLock::new(|| Mutex::new(HashMap::new()));
+
+/// Returns the path of the lock file guarding the storage file at `data_path`.
+pub(crate) fn lock_path_for(data_path: &Path) -> PathBuf {
+    let mut path = data_path.as_os_str().to_os_string();
+    path.push(".lock");
+    PathBuf::from(path)
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord)]
+enum LockMode {
+    #[default]
+    Unlocked,
+    Shared,
+    Exclusive,
+}
+
+struct HeldLock {
+    state: Mutex<LockState>,
+}
+
+#[derive(Default)]
+struct LockState {
+    file: Option<File>,
+    mode: LockMode,
+    /// Set once the file has been written through this lock; it then stays
+    /// exclusive until it is dropped.
+    keep_exclusive: bool,
+    /// The write count the lock file held when this process last took the
+    /// lock from unlocked, or `None` before it first did.
+    version: Option<u64>,
+    /// Set when the write count moved while this process had let go of the
+    /// lock, such as between two attempts to upgrade it: another process
+    /// wrote the file, and what this one cached from it is out of date.
+    changed_elsewhere: bool,
+}
+
+/// Advisory lock that keeps other processes from using a storage file at
+/// the same time.
+///
+/// The lock is taken on a `.lock` file next to the storage file rather than
+/// on the storage file itself, because saves replace the storage file and its
+/// journal by renaming new files over them. It guards both.
+///
+/// An engine takes the lock shared when it loads the file and exclusive
+/// before its first write, and keeps it until the last handle to the file in
+/// this process is dropped: the engine caches the catalog and pages, so
+/// another process may not change the file while it is open. Only one
+/// process writes a file at a time, and while it is open no other process
+/// may read it either. A busy lock is
+/// retried until the busy timeout runs out, then the operation fails with
+/// [`RustqlError::DatabaseLocked`].
+///
+/// Neither platform turns a shared lock into an exclusive one or back in
+/// place, so another process can take the lock while this one changes
+/// modes. Every process that writes the file counts the write in the lock
+/// file, and a process that finds the count moved while it was not holding
+/// the lock refuses to write until the file is opened again.
+#[derive(Clone)]
+pub(crate) struct StorageLock {
+    data_path: PathBuf,
+    lock_path: PathBuf,
+    busy_timeout: Duration,
+    held: Arc<HeldLock>,
+}
+
+impl StorageLock {
+    pub(crate) fn new(data_path: &Path) -> Self {
+        let lock_path = lock_path_for(data_path);
+        let mut held_locks = HELD_LOCKS
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        held_locks.retain(|_, held| held.strong_count() > 0);
+        let key = registry_key(&lock_path);
+        let held = match held_locks.get(&key).and_then(Weak::upgrade) {
+            Some(held) => held,
+            None => {
+                let held = Arc::new(HeldLock {
+                    state: Mutex::new(LockState::default()),
+                });
+                held_locks.insert(key, Arc::downgrade(&held));
+                held
+            }
+        };
+        StorageLock {
+            data_path: data_path.to_path_buf(),
+            lock_path,
+            busy_timeout: DEFAULT_BUSY_TIMEOUT,
+            held,
+        }
+    }
+
+    pub(crate) fn with_busy_timeout(mut self, timeout: Duration) -> Self {
+        self.busy_timeout = timeout;
+        self
+    }
+
+    /// Takes the lock for reading, unless this process already holds it.
+    pub(crate) fn lock_shared(&self) -> Result<(), RustqlError> {
+        self.acquire(&mut self.state(), LockMode::Shared)
+    }
+
+    /// Takes the lock for writing and keeps it exclusive from now on. Other
+    /// processes holding it, even shared, keep it from being granted.
+    ///
+    /// Fails with [`RustqlError::StorageError`], and gives the lock back,
+    /// when another process wrote the file while this one was not holding
+    /// the lock.
+    pub(crate) fn lock_exclusive(&self) -> Result<(), RustqlError> {
+        let mut state = self.state();
+        self.acquire(&mut state, LockMode::Exclusive)?;
+        if state.keep_exclusive {
+            return Ok(());
+        }
+        if state.changed_elsewhere {
+            self.return_to_shared(&mut state)?;
+            return Err(RustqlError::StorageError(format!(
+                "Storage file '{}' was changed by another process since it was opened; open it again to write to it",
+                self.data_path.display()
+            )));
+        }
+        self.count_write(&mut state)?;
+        state.keep_exclusive = true;
+        Ok(())
+    }
+
+    /// Runs `write` under an exclusive lock, then returns to a shared lock
+    /// unless the file was written through this lock before. Loading uses it
+    /// to recover a file without locking out other readers afterwards.
+    pub(crate) fn with_exclusive<T>(
+        &self,
+        write: impl FnOnce() -> Result<T, RustqlError>,
+    ) -> Result<T, RustqlError> {
+        {
+            let mut state = self.state();
+            self.acquire(&mut state, LockMode::Exclusive)?;
+            self.count_write(&mut state)?;
+        }
+        let result = write();
+        self.return_to_shared(&mut self.state())?;
+        result
+    }
+
+    /// A lock on the same file that is not shared with the other handles in
+    /// this process, so a test can act as another process.
+    #[cfg(test)]
+    pub(crate) fn unshared(data_path: &Path) -> Self {
+        StorageLock {
+            data_path: data_path.to_path_buf(),
+            lock_path: lock_path_for(data_path),
+            busy_timeout: DEFAULT_BUSY_TIMEOUT,
+            held: Arc::new(HeldLock {
+                state: Mutex::new(LockState::default()),
+            }),
+        }
+    }
+
+    /// Lets go of the lock the way an upgrade does between two attempts.
+    #[cfg(test)]
+    pub(crate) fn release(&self) {
+        let mut state = self.state();
+        if let Some(file) = &state.file {
+            sys::unlock(file).unwrap();
+        }
+        state.mode = LockMode::Unlocked;
+    }
+
+    fn state(&self) -> MutexGuard<'_, LockState> {
+        self.held
+            .state
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    fn acquire(&self, state: &mut LockState, mode: LockMode) -> Result<(), RustqlError> {
+        if state.mode >= mode {
+            return Ok(());
+        }
+        if state.file.is_none() {
+            state.file = Some(self.open_lock_file()?);
+        }
+        let LockState {
+            file,
+            mode: held,
+            version,
+            changed_elsewhere,
+            ..
+        } = state;
+        let file = file.as_ref().expect("lock file was just opened");
+
+        let deadline = Instant::now() + self.busy_timeout;
+        let mut interval = Duration::from_millis(1);
+        loop {
+            if *held == LockMode::Shared {
+                // Neither platform upgrades a lock in place, so the shared
+                // lock is given up for each attempt and taken back after a
+                // failed one.
+                sys::unlock(file).map_err(|e| self.lock_error(e))?;
+                *held = LockMode::Unlocked;
+            }
+            let granted = match mode {
+                LockMode::Exclusive => sys::try_lock_exclusive(file),
+                _ => sys::try_lock_shared(file),
+            }
+            .map_err(|e| self.lock_error(e))?;
+            if granted {
+                *held = mode;
+                return self.read_version(file, version, changed_elsewhere);
+            }
+            if mode == LockMode::Exclusive
+                && sys::try_lock_shared(file).map_err(|e| self.lock_error(e))?
+            {
+                *held = LockMode::Shared;
+                self.read_version(file, version, changed_elsewhere)?;
+            }
+
+            let now = Instant::now();
+            if now >= deadline {
+                return Err(RustqlError::DatabaseLocked(
+                    self.data_path.display().to_string(),
+                ));
+            }
+            std::thread::sleep(interval.min(deadline - now));
+            interval = (interval * 2).min(MAX_RETRY_INTERVAL);
+        }
+    }
+
+    /// Turns an exclusive lock the file was not written through back into a
+    /// shared one.
+    fn return_to_shared(&self, state: &mut LockState) -> Result<(), RustqlError> {
+        if state.mode != LockMode::Exclusive || state.keep_exclusive {
+            return Ok(());
+        }
+        let file = state.file.as_ref().expect("a held lock has a file");
+        if sys::downgrade(file).map_err(|e| self.lock_error(e))? {
+            state.mode = LockMode::Shared;
+            self.read_version(file, &mut state.version, &mut state.changed_elsewhere)
+        } else {
+            state.mode = LockMode::Unlocked;
+            self.acquire(state, LockMode::Shared)
+        }
+    }
+
+    /// Reads the write count from the lock file, just taken from unlocked,
+    /// and notes whether it moved since this process last held the lock.
+    fn read_version(
+        &self,
+        file: &File,
+        version: &mut Option<u64>,
+        changed_elsewhere: &mut bool,
+    ) -> Result<(), RustqlError> {
+        let mut bytes = [0; 8];
+        let mut reader = file;
+        let read = reader
+            .seek(SeekFrom::Start(0))
+            .and_then(|_| reader.read_exact(&mut bytes));
+        let current = match read {
+            Ok(()) => u64::from_le_bytes(bytes),
+            // A lock file no writer has counted in yet.
+            Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => 0,
+            Err(err) => return Err(self.lock_error(err)),
+        };
+        if version.is_some_and(|seen| seen != current) {
+            *changed_elsewhere = true;
+        }
+        *version = Some(current);
+        Ok(())
+    }
+
+    /// Counts a write in the lock file, held exclusive, so that processes
+    /// waiting for the lock see the file changed.
+    fn count_write(&self, state: &mut LockState) -> Result<(), RustqlError> {
+        let file = state.file.as_ref().expect("a held lock has a file");
+        let next = state.version.unwrap_or(0) + 1;
+        let mut writer = file;
+        writer
+            .seek(SeekFrom::Start(0))
+            .and_then(|_| writer.write_all(&next.to_le_bytes()))
+            .map_err(|e| self.lock_error(e))?;
+        state.version = Some(next);
+        Ok(())
+    }
+
+    fn open_lock_file(&self) -> Result<File, RustqlError> {
+        OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(&self.lock_path)
+            .map_err(|e| self.lock_error(e))
+    }
+
+    fn lock_error(&self, e: io::Error) -> RustqlError {
+        RustqlError::StorageError(format!(
+            "Failed to lock storage file '{}': {}",
+            self.lock_path.display(),
+            e
+        ))
+    }
+}
+
+/// Spells `path` the same way however it was given, so engines opened
+/// through different relative paths share one lock.
+fn registry_key(path: &Path) -> PathBuf {
+    let parent = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    match (parent.canonicalize(), path.file_name()) {
+        (Ok(parent), Some(name)) => parent.join(name),
+        _ => std::path::absolute(path).unwrap_or_else(|_| path.to_path_buf()),
+    }
+}
+
+/// Non-blocking wrappers over std's file locks, which report a lock held
+/// elsewhere as `Ok(false)`.
+mod sys {
+    use std::fs::{File, TryLockError};
+    use std::io;
+
+    fn granted(result: Result<(), TryLockError>) -> io::Result<bool> {
+        match result {
+            Ok(()) => Ok(true),
+            Err(TryLockError::WouldBlock) => Ok(false),
+            Err(TryLockError::Error(err)) if err.kind() == io::ErrorKind::Interrupted => Ok(false),
+            Err(TryLockError::Error(err)) => Err(err),
+        }
+    }
+
+    pub(super) fn try_lock_shared(file: &File) -> io::Result<bool> {
+        granted(file.try_lock_shared())
+    }
+
+    pub(super) fn try_lock_exclusive(file: &File) -> io::Result<bool> {
+        granted(file.try_lock())
+    }
+
+    pub(super) fn unlock(file: &File) -> io::Result<()> {
+        file.unlock()
+    }
+
+    /// Turns a held exclusive lock into a shared one. std leaves taking a
+    /// second lock through the same handle unspecified, so the exclusive
+    /// lock is released first; another process may take the lock in
+    /// between, and the caller then waits for it like any busy lock.
+    pub(super) fn downgrade(file: &File) -> io::Result<bool> {
+        file.unlock()?;
+        try_lock_shared(file)
+    }
+}
@@ -1,23 +1,84 @@
-use super::StorageEngine;
-use super::atomic_file::atomic_write;
+use super::atomic_file::{atomic_write, sync_parent_dir};
+use super::file_lock::StorageLock;
+use super::{ChangeSet, StorageEngine};
 use crate::database::Database;
 use crate::error::RustqlError;
+use serde::{Deserialize, Serialize};
 use std::fs;
 use std::path::{Path, PathBuf};
 use std::sync::{Arc, RwLock};
+use std::time::Duration;
 
+/// Storage backend that keeps the whole database in one JSON file and
+/// rewrites it on every save.
+///
+/// The file is locked against other processes as described for
+/// [`BTreeStorageEngine`](super::BTreeStorageEngine).
 pub struct JsonStorageEngine {
     path: PathBuf,
     lock: Arc<RwLock<()>>,
+    file_lock: StorageLock,
 }
 
 impl JsonStorageEngine {
     pub fn new<P: Into<PathBuf>>(path: P) -> Self {
+        let path = path.into();
         JsonStorageEngine {
-            path: path.into(),
+            file_lock: StorageLock::new(&path),
+            path,
             lock: Arc::new(RwLock::new(())),
         }
     }
+
+    /// Where [`StorageEngine::prepare_linked_commit`] keeps the file it
+    /// would write until the commit finishes.
+    fn linked_commit_path(&self) -> PathBuf {
+        let mut path = self.path.as_os_str().to_os_string();
+        path.push(".linked");
+        PathBuf::from(path)
+    }
+
+    /// Writes the file a linked commit left behind if its record exists,
+    /// and drops it either way.
+    fn recover_linked_commit(&self) -> Result<(), RustqlError> {
+        let linked_path = self.linked_commit_path();
+        let linked: LinkedCommit = fs::read(&linked_path)
+            .map_err(|e| e.to_string())
+            .and_then(|data| serde_json::from_slice(&data).map_err(|e| e.to_string()))
+            .map_err(|e| {
+                RustqlError::StorageError(format!(
+                    "Failed to read linked commit '{}': {}",
+                    linked_path.display(),
+                    e
+                ))
+            })?;
+        if linked.record.exists() {
+            atomic_write(&self.path, linked.data.as_bytes())?;
+        }
+        self.clear_linked_commit()
+    }
+
+    fn clear_linked_commit(&self) -> Result<(), RustqlError> {
+        let linked_path = self.linked_commit_path();
+        if !linked_path.exists() {
+            return Ok(());
+        }
+        fs::remove_file(&linked_path).map_err(|e| {
+            RustqlError::StorageError(format!(
+                "Failed to remove linked commit '{}': {}",
+                linked_path.display(),
+                e
+            ))
+        })?;
+        sync_parent_dir(&linked_path)
+    }
+
+    /// Waits up to `timeout` for another process to release the file before
+    /// failing with [`RustqlError::DatabaseLocked`].
+    pub fn with_busy_timeout(mut self, timeout: Duration) -> Self {
+        self.file_lock = self.file_lock.with_busy_timeout(timeout);
+        self
+    }
 }
 
 impl StorageEngine for JsonStorageEngine {
@@ -25,6 +86,11 @@ impl StorageEngine for JsonStorageEngine {
         let _guard = self.lock.read().map_err(|e| {
             RustqlError::StorageError(format!("Failed to acquire JSON storage read lock: {}", e))
         })?;
+        self.file_lock.lock_shared()?;
+        if self.linked_commit_path().exists() {
+            self.file_lock
+                .with_exclusive(|| self.recover_linked_commit())?;
+        }
         if Path::new(&self.path).exists() {
             let data = fs::read_to_string(&self.path).map_err(|e| {
                 RustqlError::StorageError(format!(
@@ -57,12 +123,44 @@ impl StorageEngine for JsonStorageEngine {
         let _guard = self.lock.write().map_err(|e| {
             RustqlError::StorageError(format!("Failed to acquire JSON storage write lock: {}", e))
         })?;
-        let mut db = db.clone();
-        db.normalize_row_ids();
-        let data = serde_json::to_string_pretty(&db).map_err(|e| {
-            RustqlError::StorageError(format!("Failed to serialize database: {}", e))
-        })?;
+        self.file_lock.lock_exclusive()?;
+        let data = db.to_json()?;
         atomic_write(&self.path, data.as_bytes())?;
         Ok(())
     }
+
+    fn prepare_linked_commit(
+        &self,
+        db: &Database,
+        _changes: Option<&ChangeSet>,
+        record: &Path,
+    ) -> Result<(), RustqlError> {
+        let _guard = self.lock.write().map_err(|e| {
+            RustqlError::StorageError(format!("Failed to acquire JSON storage write lock: {}", e))
+        })?;
+        self.file_lock.lock_exclusive()?;
+        let linked = LinkedCommit {
+            record: record.to_path_buf(),
+            data: db.to_json()?,
+        };
+        let data = serde_json::to_vec(&linked).map_err(|e| {
+            RustqlError::StorageError(format!("Failed to serialize linked commit: {}", e))
+        })?;
+        atomic_write(&self.linked_commit_path(), &data)
+    }
+
+    fn clear_transaction(&self) -> Result<(), RustqlError> {
+        let _guard = self.lock.write().map_err(|e| {
+            RustqlError::StorageError(format!("Failed to acquire JSON storage write lock: {}", e))
+        })?;
+        self.clear_linked_commit()
+    }
+}
+
+/// The file a commit across attached databases would write, kept next to
+/// the JSON file until every file of the commit has been saved.
+#[derive(Serialize, Deserialize)]
+struct LinkedCommit {
+    record: PathBuf,
+    data: String,
 }
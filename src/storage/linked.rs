@@ -0,0 +1,67 @@
+use super::atomic_file::{atomic_write, sync_parent_dir};
+use super::{ChangeSet, StorageEngine};
+use crate::database::Database;
+use crate::error::RustqlError;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Saves each database in `parts` to its storage as one commit: after a
+/// crash, either every file holds its new database or none does.
+///
+/// Every storage first prepares its part, with the changes it holds or whole
+/// when they are `None`, with [`StorageEngine::prepare_linked_commit`].
+/// Writing the commit record next to `anchor` then decides the commit, after
+/// which the parts are saved with [`StorageEngine::finish_linked_commit`],
+/// the prepared state is cleared and the record removed. A storage opened
+/// while its part is still prepared replays it only if the record exists.
+///
+/// A failure before the record is written undoes the preparations and leaves
+/// every file as it was. Once the record exists a failure is still reported,
+/// but the files that were not saved finish the commit when they are next
+/// opened, and the record stays behind.
+pub(crate) fn commit_linked(
+    parts: &[(&dyn StorageEngine, &Database, Option<&ChangeSet>)],
+    anchor: &Path,
+) -> Result<(), RustqlError> {
+    let record = commit_record_path(anchor);
+    for (index, (storage, db, changes)) in parts.iter().enumerate() {
+        if let Err(err) = storage.prepare_linked_commit(db, *changes, &record) {
+            for (storage, _, _) in &parts[..index] {
+                let _ = storage.clear_transaction();
+            }
+            return Err(err);
+        }
+    }
+    if let Err(err) = atomic_write(&record, b"committed\n") {
+        for (storage, _, _) in parts {
+            let _ = storage.clear_transaction();
+        }
+        return Err(err);
+    }
+
+    for (storage, db, _) in parts {
+        storage.finish_linked_commit(db)?;
+        storage.clear_transaction()?;
+    }
+    fs::remove_file(&record).map_err(|e| {
+        RustqlError::StorageError(format!(
+            "Failed to remove commit record '{}': {}",
+            record.display(),
+            e
+        ))
+    })?;
+    sync_parent_dir(&record)
+}
+
+/// A record name no earlier commit used, so that a record left behind by a
+/// crash can never decide a later commit.
+fn commit_record_path(anchor: &Path) -> PathBuf {
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_nanos())
+        .unwrap_or_default();
+    let mut path = anchor.as_os_str().to_os_string();
+    path.push(format!(".commit-{}-{}", std::process::id(), nanos));
+    PathBuf::from(path)
+}
@@ -1,27 +1,174 @@
 use crate::database::Database;
-use crate::error::RustqlError;
+use crate::error::{IntegrityProblem, RustqlError};
+use std::path::Path;
 
 mod atomic_file;
 mod btree;
+mod changes;
+mod file_lock;
 mod json;
+mod linked;
 
-pub use btree::BTreeStorageEngine;
+pub use btree::{
+    BTreeStorageEngine, DEFAULT_CHECKPOINT_PAGES, DEFAULT_PAGE_CACHE_SIZE, EncryptionKey,
+    LogReceiver, LogSender, ReplicationSource, ReplicationTarget, log_channel,
+};
+pub(crate) use btree::{LogFollower, journal_path_for, write_snapshot_backup};
+pub use changes::ChangeSet;
+pub use file_lock::DEFAULT_BUSY_TIMEOUT;
+pub(crate) use file_lock::lock_path_for;
 pub use json::JsonStorageEngine;
+pub(crate) use linked::commit_linked;
 
+/// What a group of storage pages holds, as listed by `SHOW STORAGE`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PageUsageKind {
+    /// Pages holding rows or the schema of a table.
+    Table,
+    /// Pages holding the definition or entries of an index.
+    Index,
+    /// Interior pages of the tree.
+    Internal,
+    /// Pages on the free list, waiting to be reused.
+    Free,
+    /// Every page in the file.
+    File,
+}
+
+/// Number of pages used for one object or purpose.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PageUsage {
+    pub kind: PageUsageKind,
+    /// The table or index name; `None` for the file-wide kinds.
+    pub name: Option<String>,
+    pub pages: u64,
+}
+
+/// Storage size before and after `VACUUM`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct VacuumStats {
+    pub bytes_before: u64,
+    pub bytes_after: u64,
+}
+
+impl VacuumStats {
+    pub fn bytes_reclaimed(&self) -> u64 {
+        self.bytes_before.saturating_sub(self.bytes_after)
+    }
+}
+
+/// A storage backend for an [`Engine`](crate::Engine).
+///
+/// Built-in backends are chosen by [`StorageMode`](crate::StorageMode); other
+/// backends implement this trait and are passed as
+/// [`StorageMode::Custom`](crate::StorageMode::Custom). An engine calls
+/// [`load`](Self::load) once when it opens, then persists every commit: each
+/// statement outside a transaction, and `COMMIT` for the transaction as a
+/// whole, calls [`save_changes`](Self::save_changes) with the complete
+/// database. If that fails, the statement or `COMMIT` returns the error and
+/// its changes are not committed. `BEGIN` calls [`begin_transaction`](Self::begin_transaction) and
+/// both `COMMIT` and `ROLLBACK` end with
+/// [`clear_transaction`](Self::clear_transaction). Only
+/// [`load`](Self::load) and [`save`](Self::save) are required; the other
+/// methods have defaults that suit a backend storing whole snapshots.
 pub trait StorageEngine: Send + Sync {
+    /// Returns the committed database, or [`Database::new`] when nothing has
+    /// been stored yet.
     fn load(&self) -> Result<Database, RustqlError>;
 
+    /// Stores `db` as the committed database, replacing what was stored.
     fn save(&self, db: &Database) -> Result<(), RustqlError>;
 
+    /// Persists `db` after the logical changes in `changes`.
+    ///
+    /// The default writes a full snapshot. Engines that update pages in place
+    /// override this to touch only the records named by the change set.
+    fn save_changes(&self, db: &Database, _changes: &ChangeSet) -> Result<(), RustqlError> {
+        self.save(db)
+    }
+
     fn begin_transaction(&self) -> Result<(), RustqlError> {
         Ok(())
     }
 
+    /// Makes a commit of `db` recoverable before it is saved. Backends whose
+    /// saves are atomic have nothing to do.
     fn prepare_commit(&self, _db: &Database) -> Result<(), RustqlError> {
         Ok(())
     }
 
+    /// Makes a commit of `db` that also changes other files recoverable
+    /// before it is saved, like [`prepare_commit`](Self::prepare_commit),
+    /// except that the commit is only recovered if the file at `record`
+    /// exists by then. `changes` names what the commit changed, like for
+    /// [`save_changes`](Self::save_changes), or is `None` to save `db`
+    /// whole. An engine writes `record` once every file has been prepared,
+    /// finishes each of them with
+    /// [`finish_linked_commit`](Self::finish_linked_commit), then clears
+    /// their transactions and removes `record`.
+    ///
+    /// The default fails, so a backend that does not override it can only
+    /// commit changes to its own file.
+    fn prepare_linked_commit(
+        &self,
+        _db: &Database,
+        _changes: Option<&ChangeSet>,
+        _record: &Path,
+    ) -> Result<(), RustqlError> {
+        Err(RustqlError::StorageError(
+            "This storage cannot commit together with attached databases".to_string(),
+        ))
+    }
+
+    /// Saves the commit of `db` prepared by
+    /// [`prepare_linked_commit`](Self::prepare_linked_commit) once its record
+    /// exists. The default saves `db` whole.
+    fn finish_linked_commit(&self, db: &Database) -> Result<(), RustqlError> {
+        self.save(db)
+    }
+
     fn clear_transaction(&self) -> Result<(), RustqlError> {
         Ok(())
     }
+
+    /// Folds any logged changes into the main storage file. Engines without a
+    /// log have nothing to do.
+    fn checkpoint(&self) -> Result<(), RustqlError> {
+        Ok(())
+    }
+
+    /// Writes the committed database to a new B-tree file at `path`, which
+    /// must not exist yet, nor a redo log beside it. The default loads the stored database and
+    /// writes it as a snapshot; it never sees uncommitted changes, because
+    /// those are only handed to storage at commit.
+    fn backup_to(&self, path: &Path) -> Result<(), RustqlError> {
+        write_snapshot_backup(path, &self.load()?)
+    }
+
+    /// Rewrites storage for `db` without unused space. Engines that rewrite
+    /// their whole file on every save have nothing to reclaim.
+    fn vacuum(&self, _db: &Database) -> Result<VacuumStats, RustqlError> {
+        Ok(VacuumStats::default())
+    }
+
+    /// Rewrites storage for `db` encrypted with `key`, or unencrypted when
+    /// `key` is `None`. Only B-tree files can be encrypted.
+    fn rekey(&self, _db: &Database, _key: Option<EncryptionKey>) -> Result<(), RustqlError> {
+        Err(RustqlError::StorageError(
+            "Encryption requires B-tree storage".to_string(),
+        ))
+    }
+
+    /// Reports how many pages each table and index occupies. Engines that do
+    /// not store pages report nothing.
+    fn page_usage(&self) -> Result<Vec<PageUsage>, RustqlError> {
+        Ok(Vec::new())
+    }
+
+    /// Checks the stored file for damage below the level of tables and rows,
+    /// such as bad page checksums or a malformed tree. Engines that keep no
+    /// page structure report nothing.
+    fn check_integrity(&self) -> Result<Vec<IntegrityProblem>, RustqlError> {
+        Ok(Vec::new())
+    }
 }
@@ -1,7 +1,10 @@
 use crate::ast::{ColumnDefinition, TableConstraint, Value};
-use crate::database::{CompositeIndex, Database, Index, RowId, Table};
+use crate::database::{CompositeIndex, Database, Index, RowId, SharedMap, Table};
 use crate::error::RustqlError;
-use std::collections::HashMap;
+use crate::storage::ChangeSet;
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
 
 #[derive(Debug, Clone)]
 pub enum WalEntry {
@@ -17,7 +20,6 @@ pub enum WalEntry {
     DeleteRow {
         table: String,
         row_id: RowId,
-        position: usize,
         old_row: Vec<Value>,
     },
     CreateTable {
@@ -25,11 +27,7 @@ pub enum WalEntry {
     },
     DropTable {
         name: String,
-        columns: Vec<ColumnDefinition>,
-        rows: Vec<Vec<Value>>,
-        row_ids: Vec<RowId>,
-        next_row_id: u64,
-        constraints: Vec<TableConstraint>,
+        table: Table,
         indexes: Vec<Index>,
         composite_indexes: Vec<CompositeIndex>,
     },
@@ -58,9 +56,9 @@ pub enum WalEntry {
     },
     TruncateTable {
         name: String,
-        old_rows: Vec<Vec<Value>>,
-        old_row_ids: Vec<RowId>,
-        old_next_row_id: u64,
+        table: Table,
+        indexes: Vec<Index>,
+        composite_indexes: Vec<CompositeIndex>,
     },
     CreateView {
         name: String,
@@ -83,12 +81,139 @@ pub enum WalEntry {
     },
 }
 
-#[derive(Debug, Default)]
+#[derive(Debug, Clone, Default)]
 pub struct WalLog {
     entries: Vec<WalEntry>,
     savepoints: HashMap<String, usize>,
 }
 
+/// The rows and objects a transaction wrote, in the detail commits compare to
+/// find conflicting writes.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WriteSet {
+    /// Rows that existed before the transaction and were updated or deleted.
+    pub rows: BTreeMap<String, BTreeSet<RowId>>,
+    /// Rows the transaction inserted, under the ids its snapshot gave them.
+    pub inserted: BTreeMap<String, BTreeSet<RowId>>,
+    /// Tables created, dropped, truncated, or altered, under every name they
+    /// had.
+    pub tables: BTreeSet<String>,
+    pub indexes: BTreeSet<String>,
+    pub views: BTreeSet<String>,
+}
+
+impl WriteSet {
+    pub fn is_empty(&self) -> bool {
+        self.rows.is_empty()
+            && self.inserted.is_empty()
+            && self.tables.is_empty()
+            && self.indexes.is_empty()
+            && self.views.is_empty()
+    }
+
+    fn record_row(&mut self, table: &str, row_id: RowId) {
+        if self
+            .inserted
+            .get(table)
+            .is_some_and(|inserted| inserted.contains(&row_id))
+        {
+            return;
+        }
+        self.rows
+            .entry(table.to_string())
+            .or_default()
+            .insert(row_id);
+    }
+
+    fn touches_table(&self, table: &str) -> bool {
+        self.tables.contains(table)
+            || self.rows.contains_key(table)
+            || self.inserted.contains_key(table)
+    }
+
+    /// Describes an object both write sets changed, if any. Inserts never
+    /// conflict with each other; every other write to the same row, and any
+    /// write to a table the other side altered, does.
+    pub fn conflict_with(&self, other: &WriteSet) -> Option<String> {
+        if let Some(table) = self
+            .tables
+            .iter()
+            .find(|table| other.touches_table(table))
+            .or_else(|| other.tables.iter().find(|table| self.touches_table(table)))
+        {
+            return Some(format!("table '{}'", table));
+        }
+        for (table, row_ids) in &self.rows {
+            if let Some(other_row_ids) = other.rows.get(table)
+                && let Some(row_id) = row_ids.intersection(other_row_ids).next()
+            {
+                return Some(format!("row {} of table '{}'", row_id.0, table));
+            }
+        }
+        if let Some(index) = self.indexes.intersection(&other.indexes).next() {
+            return Some(format!("index '{}'", index));
+        }
+        if let Some(view) = self.views.intersection(&other.views).next() {
+            return Some(format!("view '{}'", view));
+        }
+        None
+    }
+
+    /// Describes a table or view in `reads` these writes changed, if any.
+    pub fn read_conflict_with(&self, reads: &BTreeSet<String>) -> Option<String> {
+        reads.iter().find_map(|name| {
+            if self.touches_table(name) {
+                Some(format!("table '{}'", name))
+            } else if self.views.contains(name) {
+                Some(format!("view '{}'", name))
+            } else {
+                None
+            }
+        })
+    }
+}
+
+/// A row inserted, updated or deleted by a logged statement. `old_row` is
+/// `None` for an insert and `new_row` for a deletion.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RowChange {
+    pub table: String,
+    pub row_id: RowId,
+    pub old_row: Option<Vec<Value>>,
+    pub new_row: Option<Vec<Value>>,
+}
+
+impl RowChange {
+    fn new(
+        table: &str,
+        row_id: RowId,
+        old_row: Option<Vec<Value>>,
+        new_row: Option<Vec<Value>>,
+    ) -> Self {
+        Self {
+            table: table.to_string(),
+            row_id,
+            old_row,
+            new_row,
+        }
+    }
+}
+
+/// Gives the inserted rows `changes` refer to the new ids a merge gave them.
+pub(crate) fn renumber_row_changes(
+    changes: &mut [RowChange],
+    renumbered: &BTreeMap<String, HashMap<RowId, RowId>>,
+) {
+    for change in changes {
+        if let Some(row_id) = renumbered
+            .get(&change.table)
+            .and_then(|ids| ids.get(&change.row_id))
+        {
+            change.row_id = *row_id;
+        }
+    }
+}
+
 #[derive(Debug, Clone, Copy)]
 pub struct StatementSavepoint {
     position: usize,
@@ -121,6 +246,199 @@ impl WalLog {
         Ok(())
     }
 
+    /// Summarizes the logged mutations as the records storage has to rewrite.
+    pub fn changes(&self) -> ChangeSet {
+        let mut changes = ChangeSet::new();
+        for entry in &self.entries {
+            match entry {
+                WalEntry::InsertRow { table, row_id }
+                | WalEntry::UpdateRow { table, row_id, .. }
+                | WalEntry::DeleteRow { table, row_id, .. } => changes.record_row(table, *row_id),
+                WalEntry::CreateTable { name } | WalEntry::TruncateTable { name, .. } => {
+                    changes.record_table(name)
+                }
+                WalEntry::DropTable {
+                    name,
+                    indexes,
+                    composite_indexes,
+                    ..
+                } => {
+                    changes.record_table(name);
+                    for index in indexes {
+                        changes.record_index(&index.name);
+                    }
+                    for index in composite_indexes {
+                        changes.record_index(&index.name);
+                    }
+                }
+                WalEntry::CreateIndex { name } | WalEntry::DropIndex { name, .. } => {
+                    changes.record_index(name)
+                }
+                WalEntry::AlterAddColumn { table, .. }
+                | WalEntry::AlterDropColumn { table, .. } => changes.record_table(table),
+                WalEntry::AlterRenameTable { old_name, new_name } => {
+                    changes.record_table(old_name);
+                    changes.record_table(new_name);
+                }
+                WalEntry::AlterRenameColumn { table, .. }
+                | WalEntry::AlterAddConstraint { table, .. }
+                | WalEntry::AlterDropConstraint { table, .. } => changes.record_schema(table),
+                WalEntry::CreateView { name } | WalEntry::DropView { name, .. } => {
+                    changes.record_view(name)
+                }
+            }
+        }
+        changes
+    }
+
+    /// Summarizes the logged mutations as the rows and objects they wrote.
+    pub fn write_set(&self) -> WriteSet {
+        let mut writes = WriteSet::default();
+        for entry in &self.entries {
+            match entry {
+                WalEntry::InsertRow { table, row_id } => {
+                    writes
+                        .inserted
+                        .entry(table.clone())
+                        .or_default()
+                        .insert(*row_id);
+                }
+                WalEntry::UpdateRow { table, row_id, .. }
+                | WalEntry::DeleteRow { table, row_id, .. } => writes.record_row(table, *row_id),
+                WalEntry::CreateTable { name }
+                | WalEntry::DropTable { name, .. }
+                | WalEntry::TruncateTable { name, .. } => {
+                    writes.tables.insert(name.clone());
+                }
+                WalEntry::AlterAddColumn { table, .. }
+                | WalEntry::AlterDropColumn { table, .. }
+                | WalEntry::AlterRenameColumn { table, .. }
+                | WalEntry::AlterAddConstraint { table, .. }
+                | WalEntry::AlterDropConstraint { table, .. } => {
+                    writes.tables.insert(table.clone());
+                }
+                WalEntry::AlterRenameTable { old_name, new_name } => {
+                    writes.tables.insert(old_name.clone());
+                    writes.tables.insert(new_name.clone());
+                }
+                WalEntry::CreateIndex { name } | WalEntry::DropIndex { name, .. } => {
+                    writes.indexes.insert(name.clone());
+                }
+                WalEntry::CreateView { name } | WalEntry::DropView { name, .. } => {
+                    writes.views.insert(name.clone());
+                }
+            }
+        }
+        writes
+    }
+
+    /// Lists the row changes in the log in the order they were made, each
+    /// with the row's values before and after it. `database` is the state
+    /// the log led to, and `renumbered` the new ids a merge gave inserted
+    /// rows.
+    ///
+    /// Values after a change come from the next change to the same row, or
+    /// from `database` for the last one. Truncating or dropping a table
+    /// shows up as a deletion of each of its rows.
+    pub fn row_changes(
+        &self,
+        database: &Database,
+        renumbered: &BTreeMap<String, HashMap<RowId, RowId>>,
+    ) -> Result<Vec<RowChange>, RustqlError> {
+        // Walks the log backwards, keeping the values each row had after the
+        // entry being looked at, and the name its table has in `database`.
+        let mut images: RowImages = HashMap::new();
+        let mut final_names: HashMap<String, Option<String>> = HashMap::new();
+        let mut changes = Vec::new();
+        for entry in self.entries.iter().rev() {
+            match entry {
+                WalEntry::InsertRow { table, row_id } => {
+                    let new_row = row_after(&mut images, &final_names, database, table, *row_id)?;
+                    changes.push(RowChange::new(table, *row_id, None, new_row));
+                    images.insert((table.clone(), *row_id), None);
+                }
+                WalEntry::UpdateRow {
+                    table,
+                    row_id,
+                    old_row,
+                } => {
+                    let new_row = row_after(&mut images, &final_names, database, table, *row_id)?;
+                    changes.push(RowChange::new(
+                        table,
+                        *row_id,
+                        Some(old_row.clone()),
+                        new_row,
+                    ));
+                    images.insert((table.clone(), *row_id), Some(old_row.clone()));
+                }
+                WalEntry::DeleteRow {
+                    table,
+                    row_id,
+                    old_row,
+                    ..
+                } => {
+                    changes.push(RowChange::new(table, *row_id, Some(old_row.clone()), None));
+                    images.insert((table.clone(), *row_id), Some(old_row.clone()));
+                }
+                WalEntry::TruncateTable { name, table, .. }
+                | WalEntry::DropTable { name, table, .. } => {
+                    // Rows seen so far under this name belong to the table
+                    // that replaced this one.
+                    images.retain(|(table, _), _| table != name);
+                    if matches!(entry, WalEntry::DropTable { .. }) {
+                        final_names.insert(name.clone(), None);
+                    }
+                    let mut removed = Vec::with_capacity(table.row_count());
+                    table.for_each_row(|row_id, row| {
+                        removed.push((row_id, row.to_vec()));
+                        Ok(true)
+                    })?;
+                    for (row_id, row) in removed.into_iter().rev() {
+                        changes.push(RowChange::new(name, row_id, Some(row.clone()), None));
+                        images.insert((name.clone(), row_id), Some(row));
+                    }
+                }
+                WalEntry::AlterRenameTable { old_name, new_name } => {
+                    let renamed: Vec<_> = images
+                        .keys()
+                        .filter(|(table, _)| table == new_name)
+                        .cloned()
+                        .collect();
+                    for key in renamed {
+                        if let Some(image) = images.remove(&key) {
+                            images.insert((old_name.clone(), key.1), image);
+                        }
+                    }
+                    let final_name = final_names
+                        .remove(new_name)
+                        .unwrap_or_else(|| Some(new_name.clone()));
+                    final_names.insert(old_name.clone(), final_name);
+                    final_names.insert(new_name.clone(), None);
+                }
+                _ => {}
+            }
+        }
+        changes.reverse();
+        renumber_row_changes(&mut changes, renumbered);
+        Ok(changes)
+    }
+
+    /// Rewrites the ids of rows the log refers to, after a merge gave the
+    /// rows it inserted new ids.
+    pub fn renumber_rows(&mut self, renumbered: &BTreeMap<String, HashMap<RowId, RowId>>) {
+        for entry in &mut self.entries {
+            if let WalEntry::InsertRow { table, row_id }
+            | WalEntry::UpdateRow { table, row_id, .. }
+            | WalEntry::DeleteRow { table, row_id, .. } = entry
+                && let Some(new_row_id) = renumbered
+                    .get(table.as_str())
+                    .and_then(|ids| ids.get(row_id))
+            {
+                *row_id = *new_row_id;
+            }
+        }
+    }
+
     pub fn rollback_to_savepoint(
         &mut self,
         name: &str,
@@ -144,7 +462,7 @@ impl WalLog {
         }
         let entries_to_rollback: Vec<WalEntry> = self.entries.drain(position..).collect();
         for entry in entries_to_rollback.into_iter().rev() {
-            rollback_single_entry(entry, db);
+            rollback_single_entry(entry, db)?;
         }
         rebuild_all_indexes(db)?;
         Ok(())
@@ -152,167 +470,46 @@ impl WalLog {
 
     pub fn rollback(self, db: &mut Database) -> Result<(), RustqlError> {
         for entry in self.entries.into_iter().rev() {
-            match entry {
-                WalEntry::InsertRow { table, row_id } => {
-                    if let Some(t) = db.tables.get_mut(&table) {
-                        let _ = t.remove_row_by_id(row_id);
-                    }
-                }
-                WalEntry::UpdateRow {
-                    table,
-                    row_id,
-                    old_row,
-                } => {
-                    if let Some(t) = db.tables.get_mut(&table) {
-                        let _ = t.set_row_by_id(row_id, old_row);
-                    }
-                }
-                WalEntry::DeleteRow {
-                    table,
-                    row_id,
-                    position,
-                    old_row,
-                } => {
-                    if let Some(t) = db.tables.get_mut(&table) {
-                        t.insert_row_at(position, row_id, old_row);
-                    }
-                }
-                WalEntry::CreateTable { name } => {
-                    db.tables.remove(&name);
-                }
-                WalEntry::DropTable {
-                    name,
-                    columns,
-                    rows,
-                    row_ids,
-                    next_row_id,
-                    constraints,
-                    indexes,
-                    composite_indexes,
-                } => {
-                    db.tables.insert(
-                        name,
-                        Table::with_rows_and_ids(columns, rows, row_ids, next_row_id, constraints),
-                    );
-                    for index in indexes {
-                        db.indexes.insert(index.name.clone(), index);
-                    }
-                    for index in composite_indexes {
-                        db.composite_indexes.insert(index.name.clone(), index);
-                    }
-                }
-                WalEntry::CreateIndex { name } => {
-                    db.indexes.remove(&name);
-                    db.composite_indexes.remove(&name);
-                }
-                WalEntry::DropIndex {
-                    name,
-                    index,
-                    composite_index,
-                } => {
-                    if let Some(index) = index {
-                        db.indexes.insert(name.clone(), index);
-                    }
-                    if let Some(index) = *composite_index {
-                        db.composite_indexes.insert(name, index);
-                    }
-                }
-                WalEntry::AlterAddColumn {
-                    table,
-                    column_name: _,
-                } => {
-                    if let Some(t) = db.tables.get_mut(&table) {
-                        t.columns.pop();
-                        for row in &mut t.rows {
-                            row.pop();
-                        }
-                    }
-                }
-                WalEntry::AlterDropColumn {
-                    table,
-                    col_index,
-                    column,
-                    values,
-                } => {
-                    if let Some(t) = db.tables.get_mut(&table) {
-                        t.columns.insert(col_index, column);
-                        for (row, val) in t.rows.iter_mut().zip(values) {
-                            row.insert(col_index, val);
-                        }
-                    }
-                }
-                WalEntry::AlterRenameColumn {
-                    table,
-                    old_name,
-                    new_name,
-                } => {
-                    if let Some(t) = db.tables.get_mut(&table) {
-                        for col in &mut t.columns {
-                            if col.name == new_name {
-                                col.name = old_name;
-                                break;
-                            }
-                        }
-                    }
-                }
-                WalEntry::TruncateTable {
-                    name,
-                    old_rows,
-                    old_row_ids,
-                    old_next_row_id,
-                } => {
-                    if let Some(t) = db.tables.get_mut(&name) {
-                        t.rows = old_rows;
-                        t.row_ids = old_row_ids;
-                        t.next_row_id = old_next_row_id;
-                    }
-                }
-                WalEntry::CreateView { name } => {
-                    db.views.remove(&name);
-                }
-                WalEntry::DropView { name, view } => {
-                    db.views.insert(name, view);
-                }
-                WalEntry::AlterRenameTable { old_name, new_name } => {
-                    if let Some(table_data) = db.tables.remove(&new_name) {
-                        db.tables.insert(old_name.clone(), table_data);
-                    }
-                    for index in db.indexes.values_mut() {
-                        if index.table == new_name {
-                            index.table = old_name.clone();
-                        }
-                    }
-                    for ci in db.composite_indexes.values_mut() {
-                        if ci.table == new_name {
-                            ci.table = old_name.clone();
-                        }
-                    }
-                }
-                WalEntry::AlterAddConstraint { table, .. } => {
-                    if let Some(t) = db.tables.get_mut(&table) {
-                        t.constraints.pop();
-                    }
-                }
-                WalEntry::AlterDropConstraint {
-                    table, constraint, ..
-                } => {
-                    if let Some(t) = db.tables.get_mut(&table) {
-                        t.constraints.push(constraint);
-                    }
-                }
-            }
+            rollback_single_entry(entry, db)?;
         }
-
         rebuild_all_indexes(db)?;
         Ok(())
     }
 }
 
-fn rollback_single_entry(entry: WalEntry, db: &mut Database) {
+/// Values of rows at some point of a log, by table name and row id; `None`
+/// for a row that does not exist.
+type RowImages = HashMap<(String, RowId), Option<Vec<Value>>>;
+
+/// Takes the values `row_id` has at the current point of a backwards walk
+/// over a log: the ones noted by a later entry, or else the row in
+/// `database` under the name its table ends up with.
+fn row_after(
+    images: &mut RowImages,
+    final_names: &HashMap<String, Option<String>>,
+    database: &Database,
+    table: &str,
+    row_id: RowId,
+) -> Result<Option<Vec<Value>>, RustqlError> {
+    if let Some(image) = images.remove(&(table.to_string(), row_id)) {
+        return Ok(image);
+    }
+    let name = match final_names.get(table) {
+        Some(Some(name)) => name.as_str(),
+        Some(None) => return Ok(None),
+        None => table,
+    };
+    match database.tables.get(name) {
+        Some(table) => Ok(table.fetch_row(row_id)?.map(Cow::into_owned)),
+        None => Ok(None),
+    }
+}
+
+fn rollback_single_entry(entry: WalEntry, db: &mut Database) -> Result<(), RustqlError> {
     match entry {
         WalEntry::InsertRow { table, row_id } => {
             if let Some(t) = db.tables.get_mut(&table) {
-                let _ = t.remove_row_by_id(row_id);
+                t.remove_row_by_id(row_id)?;
             }
         }
         WalEntry::UpdateRow {
@@ -321,17 +518,16 @@ fn rollback_single_entry(entry: WalEntry, db: &mut Database) {
             old_row,
         } => {
             if let Some(t) = db.tables.get_mut(&table) {
-                let _ = t.set_row_by_id(row_id, old_row);
+                t.set_row_by_id(row_id, old_row)?;
             }
         }
         WalEntry::DeleteRow {
             table,
             row_id,
-            position,
             old_row,
         } => {
             if let Some(t) = db.tables.get_mut(&table) {
-                t.insert_row_at(position, row_id, old_row);
+                t.restore_row(row_id, old_row);
             }
         }
         WalEntry::CreateTable { name } => {
@@ -339,18 +535,17 @@ fn rollback_single_entry(entry: WalEntry, db: &mut Database) {
         }
         WalEntry::DropTable {
             name,
-            columns,
-            rows,
-            row_ids,
-            next_row_id,
-            constraints,
+            table,
+            indexes,
+            composite_indexes,
+        }
+        | WalEntry::TruncateTable {
+            name,
+            table,
             indexes,
             composite_indexes,
         } => {
-            db.tables.insert(
-                name,
-                Table::with_rows_and_ids(columns, rows, row_ids, next_row_id, constraints),
-            );
+            db.tables.insert(name, table);
             for index in indexes {
                 db.indexes.insert(index.name.clone(), index);
             }
@@ -412,18 +607,6 @@ fn rollback_single_entry(entry: WalEntry, db: &mut Database) {
                 }
             }
         }
-        WalEntry::TruncateTable {
-            name,
-            old_rows,
-            old_row_ids,
-            old_next_row_id,
-        } => {
-            if let Some(t) = db.tables.get_mut(&name) {
-                t.rows = old_rows;
-                t.row_ids = old_row_ids;
-                t.next_row_id = old_next_row_id;
-            }
-        }
         WalEntry::CreateView { name } => {
             db.views.remove(&name);
         }
@@ -458,34 +641,80 @@ fn rollback_single_entry(entry: WalEntry, db: &mut Database) {
             }
         }
     }
+    Ok(())
 }
 
 fn rebuild_all_indexes(db: &mut Database) -> Result<(), RustqlError> {
-    let db_snapshot = db.clone();
+    rebuild_indexes(db, |_| true)
+}
+
+/// Rebuilds the entries of every index on the named tables from their rows.
+pub(crate) fn rebuild_table_indexes(
+    db: &mut Database,
+    tables: &BTreeSet<String>,
+) -> Result<(), RustqlError> {
+    rebuild_indexes(db, |table| tables.contains(table))
+}
+
+/// Visits the rows an index on `table` keeps entries for in memory: the rows
+/// the table wrote for a paged index, every row otherwise.
+fn for_each_indexed_row<F>(table: &Table, paged: bool, mut visit: F) -> Result<(), RustqlError>
+where
+    F: FnMut(RowId, &[Value]) -> Result<(), RustqlError>,
+{
+    if paged {
+        for (row_id, row) in table.resident_rows() {
+            visit(row_id, row)?;
+        }
+        return Ok(());
+    }
+    table.for_each_row(|row_id, row| {
+        visit(row_id, row)?;
+        Ok(true)
+    })
+}
 
+fn rebuild_indexes(db: &mut Database, rebuilds: impl Fn(&str) -> bool) -> Result<(), RustqlError> {
+    let db_snapshot = db.clone();
     for index in db.indexes.values_mut() {
-        index.entries.clear();
-        if let Some(table) = db_snapshot.tables.get(&index.table)
+        if !rebuilds(&index.table) {
+            continue;
+        }
+        index.entries = SharedMap::default();
+        let table = db_snapshot.tables.get(&index.table);
+        // Only an index on a paged table can stay paged.
+        if !table.is_some_and(Table::is_paged) {
+            index.paged = None;
+        }
+        if let Some(table) = table
             && let Some(col_idx) = table.columns.iter().position(|c| c.name == index.column)
         {
-            for (row_id, row) in table.iter_rows_with_ids() {
-                if !crate::executor::ddl::row_matches_index_filter(
+            let entries = &mut index.entries;
+            for_each_indexed_row(table, index.paged.is_some(), |row_id, row| {
+                if crate::executor::ddl::row_matches_index_filter(
                     &db_snapshot,
                     table,
                     index.filter_expr.as_ref(),
                     row,
                 )? {
-                    continue;
+                    let value = row.get(col_idx).cloned().unwrap_or(Value::Null);
+                    entries.entry(value).or_default().push(row_id);
                 }
-                let value = row.get(col_idx).cloned().unwrap_or(Value::Null);
-                index.entries.entry(value).or_default().push(row_id);
-            }
+                Ok(())
+            })?;
         }
     }
 
     for index in db.composite_indexes.values_mut() {
-        index.entries.clear();
-        if let Some(table) = db_snapshot.tables.get(&index.table) {
+        if !rebuilds(&index.table) {
+            continue;
+        }
+        index.entries = SharedMap::default();
+        let table = db_snapshot.tables.get(&index.table);
+        if !table.is_some_and(Table::is_paged) {
+            index.paged = None;
+        }
+        if let Some(table) = table {
             let column_positions: Option<Vec<usize>> = index
                 .columns
                 .iter()
@@ -497,21 +726,22 @@ fn rebuild_all_indexes(db: &mut Database) -> Result<(), RustqlError> {
                 })
                 .collect();
             if let Some(column_positions) = column_positions {
-                for (row_id, row) in table.iter_rows_with_ids() {
-                    if !crate::executor::ddl::row_matches_index_filter(
+                let entries = &mut index.entries;
+                for_each_indexed_row(table, index.paged.is_some(), |row_id, row| {
+                    if crate::executor::ddl::row_matches_index_filter(
                         &db_snapshot,
                         table,
                         index.filter_expr.as_ref(),
                         row,
                     )? {
-                        continue;
+                        let key = column_positions
+                            .iter()
+                            .map(|&col_idx| row.get(col_idx).cloned().unwrap_or(Value::Null))
+                            .collect();
+                        entries.entry(key).or_default().push(row_id);
                     }
-                    let key = column_positions
-                        .iter()
-                        .map(|&col_idx| row.get(col_idx).cloned().unwrap_or(Value::Null))
-                        .collect();
-                    index.entries.entry(key).or_default().push(row_id);
-                }
+                    Ok(())
+                })?;
             }
         }
     }
@@ -570,6 +800,38 @@ impl WalState {
         self.current.is_some()
     }
 
+    /// Changes recorded by the active statement or transaction log, if any.
+    pub fn pending_changes(&self) -> Option<ChangeSet> {
+        self.current.as_ref().map(WalLog::changes)
+    }
+
+    /// Rows and objects written by the active statement or transaction log.
+    pub fn renumber_rows(&mut self, renumbered: &BTreeMap<String, HashMap<RowId, RowId>>) {
+        if let Some(log) = &mut self.current {
+            log.renumber_rows(renumbered);
+        }
+    }
+
+    pub fn pending_write_set(&self) -> WriteSet {
+        self.current
+            .as_ref()
+            .map(WalLog::write_set)
+            .unwrap_or_default()
+    }
+
+    /// Row changes of the active statement or transaction log; see
+    /// [`WalLog::row_changes`].
+    pub fn pending_row_changes(
+        &self,
+        database: &Database,
+        renumbered: &BTreeMap<String, HashMap<RowId, RowId>>,
+    ) -> Result<Vec<RowChange>, RustqlError> {
+        match &self.current {
+            Some(log) => log.row_changes(database, renumbered),
+            None => Ok(Vec::new()),
+        }
+    }
+
     pub fn record_wal_entry(&mut self, entry: WalEntry) {
         if let Some(ref mut log) = self.current {
             log.record(entry);
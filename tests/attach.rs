@@ -0,0 +1,400 @@
+mod common;
+use common::*;
+use rustql::{
+    CommandTag, Database, EncryptionKey, Engine, EngineOptions, RustqlError, StorageEngine,
+    StorageMode, Value,
+};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+/// Files left next to `path` by a commit across attached databases.
+fn commit_records(path: &Path) -> Vec<PathBuf> {
+    let prefix = format!("{}.commit-", path.file_name().unwrap().to_string_lossy());
+    fs::read_dir(path.parent().unwrap())
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|entry| {
+            entry
+                .file_name()
+                .is_some_and(|name| name.to_string_lossy().starts_with(&prefix))
+        })
+        .collect()
+}
+
+/// Writes an archive of orders to a file of its own.
+fn create_archive(options: EngineOptions) {
+    Engine::open(options)
+        .unwrap()
+        .session()
+        .execute_script(
+            "CREATE TABLE customers (id INTEGER PRIMARY KEY, name TEXT);
+             CREATE TABLE orders (
+                 id INTEGER PRIMARY KEY,
+                 customer_id INTEGER REFERENCES customers(id),
+                 total INTEGER
+             );
+             CREATE INDEX orders_customer ON orders (customer_id);
+             INSERT INTO customers VALUES (1, 'ann'), (2, 'bob');
+             INSERT INTO orders VALUES (10, 1, 5), (11, 2, 7), (12, 1, 9);",
+        )
+        .unwrap();
+}
+
+#[test]
+fn attached_tables_are_queried_by_qualified_name() {
+    let main_path = unique_temp_path("main.db");
+    let archive_path = unique_temp_path("archive.db");
+    create_archive(EngineOptions::btree(&archive_path));
+
+    let engine = Engine::open(EngineOptions::btree(&main_path)).unwrap();
+    let mut session = engine.session();
+    session
+        .execute_script(
+            "CREATE TABLE orders (id INTEGER PRIMARY KEY, customer_id INTEGER, total INTEGER);
+             INSERT INTO orders VALUES (20, 2, 3);",
+        )
+        .unwrap();
+    assert_eq!(
+        session
+            .execute_one(&format!(
+                "ATTACH DATABASE '{}' AS archive",
+                archive_path.display()
+            ))
+            .unwrap(),
+        command(CommandTag::Attach)
+    );
+
+    // An attached table goes by its own name unless it has an alias.
+    assert_eq!(
+        rows(
+            &engine,
+            "SELECT c.name, SUM(orders.total) FROM archive.orders
+             JOIN archive.customers c ON c.id = orders.customer_id
+             GROUP BY c.name ORDER BY c.name"
+        ),
+        vec![
+            vec![text("ann"), Value::Float(14.0)],
+            vec![text("bob"), Value::Float(7.0)],
+        ]
+    );
+    assert_eq!(
+        rows(
+            &engine,
+            "SELECT o.id FROM orders o
+             WHERE o.customer_id IN (SELECT id FROM archive.customers WHERE name = 'bob')
+             AND o.total < (SELECT MAX(total) FROM archive.orders)"
+        ),
+        vec![vec![Value::Integer(20)]]
+    );
+
+    session
+        .execute_one(
+            "INSERT INTO orders SELECT id, customer_id, total FROM archive.orders WHERE total > 6",
+        )
+        .unwrap();
+    assert_eq!(
+        rows(&engine, "SELECT id FROM orders ORDER BY id"),
+        vec![
+            vec![Value::Integer(11)],
+            vec![Value::Integer(12)],
+            vec![Value::Integer(20)],
+        ]
+    );
+
+    // The foreign key and index of the attached file still apply.
+    assert!(matches!(
+        session.execute_one("INSERT INTO archive.orders VALUES (13, 3, 1)"),
+        Err(RustqlError::ConstraintViolation { .. })
+    ));
+    session
+        .execute_one("DROP INDEX archive.orders_customer")
+        .unwrap();
+
+    assert_eq!(
+        session.execute_one("DETACH archive").unwrap(),
+        command(CommandTag::Detach)
+    );
+    assert!(session.execute_one("SELECT * FROM archive.orders").is_err());
+    assert_eq!(
+        rows(&engine, "SELECT COUNT(*) FROM orders"),
+        vec![vec![Value::Integer(3)]]
+    );
+
+    drop(session);
+    drop(engine);
+    cleanup_storage_files(&main_path);
+    cleanup_storage_files(&archive_path);
+}
+
+/// Attaches the file at `archive_path` as `archive` to a B-tree engine,
+/// writes to both, and checks that each file got its own objects.
+fn check_writes_are_saved_to_their_file(archive_path: &Path, archive: EngineOptions) {
+    let main_path = unique_temp_path("main.db");
+    create_archive(archive.clone());
+
+    let engine = Engine::open(EngineOptions::btree(&main_path)).unwrap();
+    engine
+        .session()
+        .execute_script(&format!(
+            "CREATE TABLE totals (customer TEXT, total INTEGER);
+             ATTACH '{}' AS archive;
+             CREATE TABLE archive.notes (note TEXT);
+             INSERT INTO archive.notes VALUES ('kept');
+             UPDATE archive.orders SET total = total * 10 WHERE customer_id = 1;
+             DELETE FROM archive.customers WHERE id = 2 AND 1 = 0;
+             INSERT INTO totals
+                 SELECT c.name, SUM(o.total) FROM archive.customers c
+                 JOIN archive.orders o ON o.customer_id = c.id
+                 GROUP BY c.name;",
+            archive_path.display()
+        ))
+        .unwrap();
+
+    // A transaction writing both files commits to both.
+    let mut session = engine.session();
+    session
+        .execute_script(
+            "BEGIN;
+             INSERT INTO totals VALUES ('both', 1);
+             INSERT INTO archive.notes VALUES ('both');
+             COMMIT;
+             BEGIN;
+             INSERT INTO totals VALUES ('neither', 1);
+             INSERT INTO archive.notes VALUES ('neither');
+             ROLLBACK;",
+        )
+        .unwrap();
+    drop(session);
+    drop(engine);
+    assert!(commit_records(archive_path).is_empty());
+
+    let engine = Engine::open(EngineOptions::btree(&main_path)).unwrap();
+    assert_eq!(
+        rows(
+            &engine,
+            "SELECT customer, total FROM totals ORDER BY customer"
+        ),
+        vec![
+            vec![text("ann"), Value::Integer(140)],
+            vec![text("bob"), Value::Integer(7)],
+            vec![text("both"), Value::Integer(1)],
+        ]
+    );
+    assert!(engine.session().execute_one("SELECT * FROM notes").is_err());
+    drop(engine);
+
+    let engine = Engine::open(archive).unwrap();
+    assert_eq!(
+        rows(&engine, "SELECT note FROM notes ORDER BY note"),
+        vec![vec![text("both")], vec![text("kept")]]
+    );
+    assert_eq!(
+        rows(&engine, "SELECT total FROM orders ORDER BY id"),
+        vec![
+            vec![Value::Integer(50)],
+            vec![Value::Integer(7)],
+            vec![Value::Integer(90)],
+        ]
+    );
+    assert!(
+        engine
+            .session()
+            .execute_one("SELECT * FROM totals")
+            .is_err()
+    );
+    drop(engine);
+    cleanup_storage_files(&main_path);
+    cleanup_storage_files(archive_path);
+}
+
+#[test]
+fn writes_are_saved_to_an_attached_btree_file() {
+    let path = unique_temp_path("archive.dat");
+    check_writes_are_saved_to_their_file(&path, EngineOptions::btree(&path));
+}
+
+#[test]
+fn writes_are_saved_to_an_attached_json_file() {
+    let path = unique_temp_path("archive.json");
+    check_writes_are_saved_to_their_file(&path, EngineOptions::json(&path));
+}
+
+#[test]
+fn encrypted_engine_attaches_files_encrypted_with_its_key() {
+    let key = EncryptionKey::new([0x5a; 32]);
+    let main_path = unique_temp_path("main.db");
+    let archive_path = unique_temp_path("archive.db");
+    let archive = EngineOptions::btree(&archive_path).with_encryption_key(key.clone());
+    create_archive(archive.clone());
+
+    let engine =
+        Engine::open(EngineOptions::btree(&main_path).with_encryption_key(key.clone())).unwrap();
+    engine
+        .session()
+        .execute_script(&format!(
+            "CREATE TABLE totals (total INTEGER);
+             ATTACH '{}' AS archive;
+             BEGIN;
+             INSERT INTO totals VALUES (1);
+             UPDATE archive.orders SET total = 0 WHERE id = 11;
+             COMMIT;",
+            archive_path.display()
+        ))
+        .unwrap();
+    drop(engine);
+    assert!(commit_records(&archive_path).is_empty());
+
+    let engine = Engine::open(archive).unwrap();
+    assert_eq!(
+        rows(&engine, "SELECT total FROM orders ORDER BY id"),
+        vec![
+            vec![Value::Integer(5)],
+            vec![Value::Integer(0)],
+            vec![Value::Integer(9)],
+        ]
+    );
+    drop(engine);
+    assert!(Engine::open(EngineOptions::btree(&archive_path)).is_err());
+    cleanup_storage_files(&main_path);
+    cleanup_storage_files(&archive_path);
+}
+
+/// Storage that only keeps a snapshot in memory and so cannot take part in
+/// a commit across files.
+#[derive(Default)]
+struct SnapshotStore {
+    snapshot: Mutex<Option<String>>,
+}
+
+impl StorageEngine for SnapshotStore {
+    fn load(&self) -> Result<Database, RustqlError> {
+        match &*self.snapshot.lock().unwrap() {
+            Some(snapshot) => Database::from_json(snapshot),
+            None => Ok(Database::new()),
+        }
+    }
+
+    fn save(&self, db: &Database) -> Result<(), RustqlError> {
+        *self.snapshot.lock().unwrap() = Some(db.to_json()?);
+        Ok(())
+    }
+}
+
+#[test]
+fn failed_commit_across_files_changes_neither() {
+    let archive_path = unique_temp_path("archive.db");
+    create_archive(EngineOptions::btree(&archive_path));
+    let store = Arc::new(SnapshotStore::default());
+    let engine = Engine::open(EngineOptions {
+        storage: StorageMode::Custom(Arc::clone(&store) as Arc<dyn StorageEngine>),
+        ..EngineOptions::default()
+    })
+    .unwrap();
+    engine
+        .session()
+        .execute_script(&format!(
+            "CREATE TABLE totals (total INTEGER);
+             ATTACH '{}' AS archive;
+             INSERT INTO archive.customers VALUES (3, 'cy');",
+            archive_path.display()
+        ))
+        .unwrap();
+
+    let mut session = engine.session();
+    session
+        .execute_script(
+            "BEGIN;
+             INSERT INTO totals VALUES (1);
+             DELETE FROM archive.orders;",
+        )
+        .unwrap();
+    assert!(matches!(
+        session.execute_one("COMMIT"),
+        Err(RustqlError::StorageError(_))
+    ));
+    session.execute_one("ROLLBACK").unwrap();
+    assert_eq!(
+        rows(&engine, "SELECT COUNT(*) FROM archive.orders"),
+        vec![vec![Value::Integer(3)]]
+    );
+    assert!(rows(&engine, "SELECT * FROM totals").is_empty());
+    drop(session);
+    drop(engine);
+
+    assert!(commit_records(&archive_path).is_empty());
+    assert!(
+        !Database::from_json(store.snapshot.lock().unwrap().as_ref().unwrap())
+            .unwrap()
+            .tables
+            .keys()
+            .any(|table| table.contains('.'))
+    );
+    let engine = Engine::open(EngineOptions::btree(&archive_path)).unwrap();
+    assert_eq!(
+        rows(&engine, "SELECT COUNT(*) FROM orders"),
+        vec![vec![Value::Integer(3)]]
+    );
+    assert_eq!(
+        rows(&engine, "SELECT name FROM customers WHERE id = 3"),
+        vec![vec![text("cy")]]
+    );
+    drop(engine);
+    cleanup_storage_files(&archive_path);
+}
+
+#[test]
+fn attach_and_detach_report_misuse() {
+    let archive_path = unique_temp_path("archive.db");
+    create_archive(EngineOptions::btree(&archive_path));
+    let attach = format!("ATTACH '{}' AS archive", archive_path.display());
+    let engine = Engine::open(EngineOptions::memory()).unwrap();
+    let mut session = engine.session();
+
+    assert!(matches!(
+        session.execute_one("DETACH archive"),
+        Err(RustqlError::StorageError(msg)) if msg.contains("not attached")
+    ));
+    assert!(session.execute_one("ATTACH 'x.db' AS a.b").is_err());
+    assert!(session.execute_one("ATTACH archive AS other").is_err());
+
+    session.execute_one("BEGIN").unwrap();
+    assert!(matches!(
+        session.execute_one(&attach),
+        Err(RustqlError::TransactionError(msg)) if msg.contains("inside a transaction")
+    ));
+    session.execute_one("ROLLBACK").unwrap();
+
+    session.execute_one(&attach).unwrap();
+    assert!(matches!(
+        session.execute_one(&attach),
+        Err(RustqlError::StorageError(msg)) if msg.contains("already attached")
+    ));
+
+    session
+        .execute_script("BEGIN; UPDATE archive.orders SET total = 0;")
+        .unwrap();
+    assert!(matches!(
+        session.execute_one("PREPARE TRANSACTION 'tx1'"),
+        Err(RustqlError::TransactionError(msg)) if msg.contains("attached database 'archive'")
+    ));
+
+    // A transaction writing to a detached database cannot commit.
+    engine.session().execute_one("DETACH archive").unwrap();
+    assert!(matches!(
+        session.execute_one("COMMIT"),
+        Err(RustqlError::SerializationFailure(_))
+    ));
+
+    // A table whose name the attachment would take blocks it.
+    session
+        .execute_one("CREATE TABLE \"archive.orders\" (id INTEGER)")
+        .unwrap();
+    assert!(matches!(
+        session.execute_one(&attach),
+        Err(RustqlError::StorageError(msg)) if msg.contains("'archive.orders' exists")
+    ));
+    drop(session);
+    drop(engine);
+    cleanup_storage_files(&archive_path);
+}
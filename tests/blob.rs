@@ -0,0 +1,235 @@
+mod common;
+use common::*;
+use rustql::{Engine, EngineOptions, QueryResult, RustqlError, Value};
+use std::fs;
+use std::path::Path;
+
+fn blob(bytes: &[u8]) -> Value {
+    Value::Blob(bytes.to_vec())
+}
+
+#[test]
+fn hex_literals_store_and_return_bytes() {
+    let engine = memory_engine();
+    let mut session = engine.session();
+    session
+        .execute_one("CREATE TABLE files (id INTEGER, data BLOB)")
+        .unwrap();
+    session
+        .execute_one("INSERT INTO files VALUES (1, X'DEADbeef'), (2, x''), (3, NULL)")
+        .unwrap();
+
+    assert_eq!(
+        rows(&engine, "SELECT data FROM files ORDER BY id"),
+        vec![
+            vec![blob(&[0xde, 0xad, 0xbe, 0xef])],
+            vec![blob(&[])],
+            vec![Value::Null],
+        ]
+    );
+    assert_eq!(
+        scalar(&engine, "SELECT CAST(data AS TEXT) FROM files WHERE id = 1"),
+        text("\\xdeadbeef")
+    );
+    assert_eq!(
+        scalar(&engine, "SELECT id FROM files WHERE data = X'deadbeef'"),
+        Value::Integer(1)
+    );
+    assert_eq!(rows(&engine, "DESCRIBE files")[1][1], text("BLOB"));
+
+    let err = session.execute_one("SELECT X'ABC'").unwrap_err();
+    assert!(err.to_string().contains("Invalid hex literal"), "{err:?}");
+}
+
+#[test]
+fn blob_functions_work_on_bytes() {
+    let engine = memory_engine();
+
+    assert_eq!(
+        scalar(&engine, "SELECT LENGTH(X'00ff10')"),
+        Value::Integer(3)
+    );
+    assert_eq!(
+        scalar(&engine, "SELECT SUBSTRING(X'0102030405', 2, 3)"),
+        blob(&[2, 3, 4])
+    );
+    assert_eq!(scalar(&engine, "SELECT SUBSTRING(X'0102', 5)"), blob(&[]));
+    assert_eq!(
+        scalar(&engine, "SELECT ENCODE(X'48656c6c6f', 'base64')"),
+        text("SGVsbG8=")
+    );
+    assert_eq!(
+        scalar(&engine, "SELECT ENCODE(X'00FF', 'HEX')"),
+        text("00ff")
+    );
+    assert_eq!(
+        scalar(&engine, "SELECT DECODE('SGVsbG8', 'base64')"),
+        blob(b"Hello")
+    );
+    assert_eq!(
+        scalar(&engine, "SELECT DECODE('cafe', 'hex')"),
+        blob(&[0xca, 0xfe])
+    );
+    assert_eq!(
+        scalar(
+            &engine,
+            "SELECT ENCODE(DECODE('AAEC/w==', 'base64'), 'hex')"
+        ),
+        text("000102ff")
+    );
+    assert_eq!(
+        scalar(&engine, "SELECT CAST('\\\\x0aff' AS BYTEA)"),
+        blob(&[0x0a, 0xff])
+    );
+    assert_eq!(scalar(&engine, "SELECT CAST('hi' AS BLOB)"), blob(b"hi"));
+    assert_eq!(
+        scalar(&engine, "SELECT X'00' || X'01'"),
+        blob(&[0x00, 0x01])
+    );
+    assert_eq!(
+        scalar(&engine, "SELECT LENGTH(X'00ff' || X'' || X'10')"),
+        Value::Integer(3)
+    );
+
+    let mut session = engine.session();
+    assert!(session.execute_one("SELECT DECODE('xyz', 'hex')").is_err());
+    assert!(session.execute_one("SELECT DECODE('AA', 'rot13')").is_err());
+    assert!(session.execute_one("SELECT ENCODE('text', 'hex')").is_err());
+}
+
+#[test]
+fn blobs_sort_bytewise() {
+    let engine = memory_engine();
+    let mut session = engine.session();
+    session
+        .execute_one("CREATE TABLE keys (name TEXT, k BLOB)")
+        .unwrap();
+    session
+        .execute_one(
+            "INSERT INTO keys VALUES ('ff', X'ff'), ('0001', X'0001'), ('00', X'00'), ('80', X'80')",
+        )
+        .unwrap();
+
+    assert_eq!(
+        rows(&engine, "SELECT name FROM keys ORDER BY k"),
+        vec![
+            vec![text("00")],
+            vec![text("0001")],
+            vec![text("80")],
+            vec![text("ff")],
+        ]
+    );
+    assert_eq!(
+        rows(
+            &engine,
+            "SELECT name FROM keys WHERE k > X'7f' ORDER BY k DESC"
+        ),
+        vec![vec![text("ff")], vec![text("80")]]
+    );
+    assert_eq!(scalar(&engine, "SELECT MAX(k) FROM keys"), blob(&[0xff]));
+}
+
+#[test]
+fn blobs_bind_as_statement_parameters() {
+    let engine = memory_engine();
+    let mut session = engine.session();
+    session
+        .execute_one("CREATE TABLE files (id INTEGER, data BLOB)")
+        .unwrap();
+    let payload: Vec<u8> = (0..=255).collect();
+    session
+        .execute_one_with_params(
+            "INSERT INTO files VALUES ($1, $2)",
+            &[Value::Integer(7), Value::Blob(payload.clone())],
+        )
+        .unwrap();
+
+    match session
+        .execute_one_with_params(
+            "SELECT id, LENGTH(data) FROM files WHERE data = $1",
+            &[Value::Blob(payload)],
+        )
+        .unwrap()
+    {
+        QueryResult::Rows(batch) => {
+            assert_eq!(
+                batch.rows,
+                vec![vec![Value::Integer(7), Value::Integer(256)]]
+            )
+        }
+        other => panic!("expected rows, got {other:?}"),
+    }
+
+    let err = session
+        .execute_one_with_params("SELECT $2", &[Value::Integer(1)])
+        .unwrap_err();
+    assert!(
+        matches!(&err, RustqlError::ParseErrorAt { message, .. } if message.contains("$2")),
+        "{err:?}"
+    );
+}
+
+fn check_blobs_survive_reopen(path: &Path, options: impl Fn() -> EngineOptions) {
+    let payload: Vec<u8> = (0..=255).cycle().take(600).collect();
+    {
+        let engine = Engine::open(options()).unwrap();
+        let mut session = engine.session();
+        session
+            .execute_one("CREATE TABLE files (id INTEGER PRIMARY KEY, data BLOB)")
+            .unwrap();
+        session
+            .execute_one("CREATE INDEX idx_data ON files (data)")
+            .unwrap();
+        session
+            .execute_one_with_params(
+                "INSERT INTO files VALUES (1, $1), (2, X'0102')",
+                &[Value::Blob(payload.clone())],
+            )
+            .unwrap();
+    }
+
+    let engine = Engine::open(options()).unwrap();
+    assert_eq!(
+        scalar(&engine, "SELECT data FROM files WHERE id = 1"),
+        Value::Blob(payload)
+    );
+    assert_eq!(
+        rows(&engine, "SELECT id FROM files WHERE data = X'0102'"),
+        vec![vec![Value::Integer(2)]]
+    );
+    drop(engine);
+    cleanup_storage_files(path);
+}
+
+#[test]
+fn blobs_survive_reopening_a_btree_file() {
+    let path = unique_temp_path("btree.db");
+    cleanup_storage_files(&path);
+    check_blobs_survive_reopen(&path, || EngineOptions::btree(&path));
+}
+
+#[test]
+fn blobs_survive_reopening_a_json_file() {
+    let path = unique_temp_path("json.db");
+    cleanup_storage_files(&path);
+    check_blobs_survive_reopen(&path, || EngineOptions::json(&path));
+
+    // JSON storage writes blobs as base64 text rather than number arrays.
+    let path = unique_temp_path("json-size.db");
+    {
+        let engine = Engine::open(EngineOptions::json(&path)).unwrap();
+        let mut session = engine.session();
+        session
+            .execute_one("CREATE TABLE files (data BLOB)")
+            .unwrap();
+        session
+            .execute_one_with_params(
+                "INSERT INTO files VALUES ($1)",
+                &[Value::Blob(vec![0xab; 3000])],
+            )
+            .unwrap();
+    }
+    let size = fs::metadata(&path).unwrap().len();
+    assert!(size < 6000, "JSON file is {size} bytes");
+    cleanup_storage_files(&path);
+}
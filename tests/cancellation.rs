@@ -0,0 +1,152 @@
+use rustql::{CommandTag, Engine, QueryResult, RustqlError, Session, Value};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
+/// A join whose condition never holds, so it runs for a long time without
+/// building up rows.
+const SLOW_JOIN: &str = "SELECT COUNT(*) FROM big a JOIN big b ON a.id + b.id < 0";
+
+/// The same join over a tenth of the rows, which takes a while but finishes.
+const SHORTER_JOIN: &str =
+    "SELECT COUNT(*) FROM (SELECT * FROM big WHERE id <= 300) a JOIN big b ON a.id + b.id < 0";
+
+fn engine_with_big_table() -> Engine {
+    let engine = Engine::in_memory().unwrap();
+    engine
+        .session()
+        .execute_script(
+            "CREATE TABLE big (id INTEGER);
+             INSERT INTO big SELECT * FROM generate_series(1, 3000);
+             CREATE TABLE copies (id INTEGER);",
+        )
+        .unwrap();
+    engine
+}
+
+fn count(session: &mut Session, table: &str) -> Value {
+    match session
+        .execute_one(&format!("SELECT COUNT(*) FROM {table}"))
+        .unwrap()
+    {
+        QueryResult::Rows(batch) => batch.rows[0][0].clone(),
+        other => panic!("expected rows, got {other:?}"),
+    }
+}
+
+#[test]
+fn statement_timeout_stops_a_long_query() {
+    let engine = engine_with_big_table();
+    let mut session = engine.session();
+
+    match session
+        .execute_one("SET statement_timeout = '50ms'")
+        .unwrap()
+    {
+        QueryResult::Command(result) => assert_eq!(result.tag, CommandTag::SetStatementTimeout),
+        other => panic!("expected a command, got {other:?}"),
+    }
+    let err = session.execute_one(SLOW_JOIN).unwrap_err();
+    assert!(
+        matches!(err, RustqlError::Timeout),
+        "unexpected error: {err:?}"
+    );
+    assert_eq!(err.to_string(), "Statement timed out");
+
+    // Short statements still finish, and the timeout only applies to the
+    // session that set it.
+    assert_eq!(count(&mut session, "big"), Value::Integer(3000));
+    let mut other = engine.session();
+    other.execute_one(SHORTER_JOIN).unwrap();
+
+    session.execute_one("SET statement_timeout TO 0").unwrap();
+    session.execute_one(SHORTER_JOIN).unwrap();
+}
+
+#[test]
+fn timed_out_write_is_rolled_back() {
+    let engine = engine_with_big_table();
+    let mut session = engine.session();
+    session
+        .execute_one("SET statement_timeout = '50ms'")
+        .unwrap();
+
+    let err = session
+        .execute_one("INSERT INTO copies SELECT a.id FROM big a JOIN big b ON a.id + b.id < 0")
+        .unwrap_err();
+    assert!(matches!(err, RustqlError::Timeout));
+    assert_eq!(count(&mut session, "copies"), Value::Integer(0));
+
+    // Inside a transaction only the statement is undone.
+    session.execute_one("BEGIN").unwrap();
+    session
+        .execute_one("INSERT INTO copies VALUES (1)")
+        .unwrap();
+    let err = session
+        .execute_one(
+            "DELETE FROM copies WHERE EXISTS (SELECT 1 FROM big a JOIN big b ON a.id + b.id < 0)",
+        )
+        .unwrap_err();
+    assert!(matches!(err, RustqlError::Timeout));
+    session.execute_one("COMMIT").unwrap();
+    assert_eq!(count(&mut engine.session(), "copies"), Value::Integer(1));
+}
+
+#[test]
+fn interrupt_handle_cancels_running_statements() {
+    let engine = engine_with_big_table();
+    let handle = engine.interrupt_handle();
+    let done = Arc::new(AtomicBool::new(false));
+    let interrupter = {
+        let done = Arc::clone(&done);
+        std::thread::spawn(move || {
+            while !done.load(Ordering::SeqCst) {
+                std::thread::sleep(Duration::from_millis(20));
+                handle.interrupt();
+            }
+        })
+    };
+
+    let mut session = engine.session();
+    let err = session.execute_one(SLOW_JOIN).unwrap_err();
+    done.store(true, Ordering::SeqCst);
+    interrupter.join().unwrap();
+    assert!(
+        matches!(err, RustqlError::Cancelled),
+        "unexpected error: {err:?}"
+    );
+    assert_eq!(err.to_string(), "Statement was cancelled");
+
+    // An interrupt only stops the statements running when it is sent.
+    engine.interrupt_handle().interrupt();
+    assert_eq!(count(&mut session, "big"), Value::Integer(3000));
+}
+
+#[test]
+fn statement_timeout_values() {
+    let engine = Engine::in_memory().unwrap();
+    let mut session = engine.session();
+    for sql in [
+        "SET statement_timeout = 5000",
+        "SET statement_timeout TO '5s'",
+        "SET statement_timeout = '2min'",
+        "SET statement_timeout = '1 h'",
+        "SET statement_timeout = '250ms'",
+        "SET statement_timeout = DEFAULT",
+    ] {
+        session.execute_one(sql).unwrap();
+    }
+    for sql in [
+        "SET statement_timeout = 'soon'",
+        "SET statement_timeout = '5 days'",
+        "SET statement_timeout = -1",
+        "SET statement_timeout 5",
+    ] {
+        assert!(session.execute_one(sql).is_err(), "{sql} should not parse");
+    }
+
+    // A read-only transaction may still change the session's timeout.
+    session.execute_one("BEGIN READ ONLY").unwrap();
+    session.execute_one("SET statement_timeout = '1s'").unwrap();
+    session.execute_one("COMMIT").unwrap();
+}
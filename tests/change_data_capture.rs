@@ -0,0 +1,206 @@
+use rustql::{ChangeEvent, ChangeKind, ChangeStream, Engine, Value};
+use std::time::Duration;
+
+fn engine_with_table() -> Engine {
+    let engine = Engine::in_memory().unwrap();
+    engine
+        .session()
+        .execute_one("CREATE TABLE items (id INTEGER PRIMARY KEY, name TEXT)")
+        .unwrap();
+    engine
+}
+
+fn drain(stream: &ChangeStream) -> Vec<ChangeEvent> {
+    std::iter::from_fn(|| stream.try_next()).collect()
+}
+
+fn row(id: i64, name: &str) -> Option<Vec<Value>> {
+    Some(vec![Value::Integer(id), Value::Text(name.to_string())])
+}
+
+#[test]
+fn streams_committed_inserts_updates_and_deletes() {
+    let engine = engine_with_table();
+    let stream = engine.subscribe_changes();
+    let mut session = engine.session();
+    session
+        .execute_script(
+            "INSERT INTO items VALUES (1, 'a'), (2, 'b');
+             UPDATE items SET name = 'c' WHERE id = 2;
+             DELETE FROM items WHERE id = 1;",
+        )
+        .unwrap();
+
+    let events = drain(&stream);
+    let summary: Vec<_> = events
+        .iter()
+        .map(|event| {
+            (
+                event.table.as_str(),
+                event.kind,
+                event.old.clone(),
+                event.new.clone(),
+            )
+        })
+        .collect();
+    assert_eq!(
+        summary,
+        vec![
+            ("items", ChangeKind::Insert, None, row(1, "a")),
+            ("items", ChangeKind::Insert, None, row(2, "b")),
+            ("items", ChangeKind::Update, row(2, "b"), row(2, "c")),
+            ("items", ChangeKind::Delete, row(1, "a"), None),
+        ]
+    );
+    assert_eq!(events[0].transaction_id, events[1].transaction_id);
+    assert!(events[1].transaction_id < events[2].transaction_id);
+    assert!(events[2].transaction_id < events[3].transaction_id);
+    assert_eq!(events[1].row_id, events[2].row_id);
+    assert_eq!(events[0].row_id, events[3].row_id);
+}
+
+#[test]
+fn transaction_changes_arrive_at_commit() {
+    let engine = engine_with_table();
+    let stream = engine.subscribe_changes();
+    let mut session = engine.session();
+
+    session
+        .execute_script(
+            "BEGIN;
+             INSERT INTO items VALUES (1, 'a');
+             UPDATE items SET name = 'b' WHERE id = 1;
+             SAVEPOINT s;
+             INSERT INTO items VALUES (2, 'x');
+             ROLLBACK TO SAVEPOINT s;
+             UPDATE items SET name = 'c' WHERE id = 1;",
+        )
+        .unwrap();
+    assert!(stream.try_next().is_none());
+
+    session.execute_one("COMMIT").unwrap();
+    let events = drain(&stream);
+    let summary: Vec<_> = events
+        .iter()
+        .map(|event| (event.kind, event.old.clone(), event.new.clone()))
+        .collect();
+    assert_eq!(
+        summary,
+        vec![
+            (ChangeKind::Insert, None, row(1, "a")),
+            (ChangeKind::Update, row(1, "a"), row(1, "b")),
+            (ChangeKind::Update, row(1, "b"), row(1, "c")),
+        ]
+    );
+    assert!(
+        events
+            .iter()
+            .all(|event| event.transaction_id == events[0].transaction_id)
+    );
+
+    session
+        .execute_script("BEGIN; DELETE FROM items; ROLLBACK;")
+        .unwrap();
+    session
+        .execute_one("UPDATE items SET name = 'c' WHERE id = 99")
+        .unwrap();
+    assert!(stream.try_next().is_none());
+}
+
+#[test]
+fn merged_commits_report_the_row_ids_they_end_up_with() {
+    let engine = engine_with_table();
+    let stream = engine.subscribe_changes();
+    let mut first = engine.session();
+    let mut second = engine.session();
+
+    first.execute_one("BEGIN").unwrap();
+    second.execute_one("BEGIN").unwrap();
+    first
+        .execute_one("INSERT INTO items VALUES (1, 'a')")
+        .unwrap();
+    second
+        .execute_one("INSERT INTO items VALUES (2, 'b')")
+        .unwrap();
+    first.execute_one("COMMIT").unwrap();
+    second.execute_one("COMMIT").unwrap();
+    first
+        .execute_one("UPDATE items SET name = 'c' WHERE id = 2")
+        .unwrap();
+
+    let events = drain(&stream);
+    assert_eq!(events.len(), 3);
+    assert_ne!(events[0].row_id, events[1].row_id);
+    assert_eq!(events[1].row_id, events[2].row_id);
+    assert_eq!(events[2].old, row(2, "b"));
+}
+
+#[test]
+fn table_level_changes() {
+    let engine = engine_with_table();
+    let mut session = engine.session();
+    session
+        .execute_one("INSERT INTO items VALUES (1, 'a'), (2, 'b')")
+        .unwrap();
+    let stream = engine.subscribe_changes();
+
+    session
+        .execute_script(
+            "BEGIN;
+             INSERT INTO items VALUES (3, 'c');
+             ALTER TABLE items RENAME TO things;
+             UPDATE things SET name = 'd' WHERE id = 3;
+             COMMIT;",
+        )
+        .unwrap();
+    let events = drain(&stream);
+    assert_eq!(events.len(), 2);
+    assert_eq!(
+        (events[0].table.as_str(), events[0].new.clone()),
+        ("items", row(3, "c"))
+    );
+    assert_eq!(
+        (events[1].table.as_str(), events[1].new.clone()),
+        ("things", row(3, "d"))
+    );
+
+    session.execute_one("TRUNCATE TABLE things").unwrap();
+    let deleted: Vec<_> = drain(&stream)
+        .into_iter()
+        .map(|event| (event.kind, event.old))
+        .collect();
+    assert_eq!(
+        deleted,
+        vec![
+            (ChangeKind::Delete, row(1, "a")),
+            (ChangeKind::Delete, row(2, "b")),
+            (ChangeKind::Delete, row(3, "d")),
+        ]
+    );
+}
+
+#[test]
+fn stream_follows_other_threads_and_ends_with_the_engine() {
+    let engine = engine_with_table();
+    let stream = engine.subscribe_changes();
+    let unread = engine.subscribe_changes();
+    drop(unread);
+
+    let writer = {
+        let mut session = engine.session();
+        std::thread::spawn(move || {
+            for id in 1..=3 {
+                session
+                    .execute_one(&format!("INSERT INTO items VALUES ({id}, 'x')"))
+                    .unwrap();
+            }
+        })
+    };
+    let first = stream.next_timeout(Duration::from_secs(10)).unwrap();
+    assert_eq!(first.new, row(1, "x"));
+    writer.join().unwrap();
+
+    drop(engine);
+    let rest: Vec<_> = stream.map(|event| event.new).collect();
+    assert_eq!(rest, vec![row(2, "x"), row(3, "x")]);
+}
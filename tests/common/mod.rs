@@ -2,21 +2,32 @@
 
 pub use rustql::CommandTag;
 use rustql::ast::{Expression, Statement, Value};
-use rustql::{CommandResult, Database, Engine, EngineOptions, QueryResult, RowBatch, StorageMode};
+use rustql::{
+    CommandResult, Database, Engine, EngineOptions, QueryResult, RowBatch, Session, StorageMode,
+};
 use std::cell::RefCell;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
 
+/// A connection to a fresh engine, shared by a test's statements so they
+/// see its transaction.
 struct TestHarness {
-    engine: Engine,
+    session: RefCell<Session>,
 }
 
 impl TestHarness {
     fn new() -> Result<Self, String> {
         let engine = Engine::open(EngineOptions {
             storage: StorageMode::Memory,
+            ..EngineOptions::default()
         })
         .map_err(|err| format!("failed to create test engine: {}", err))?;
 
-        Ok(Self { engine })
+        Ok(Self {
+            session: RefCell::new(engine.session()),
+        })
     }
 }
 
@@ -40,21 +51,21 @@ pub fn reset_database() {
 
 pub fn execute_script(sql: &str) -> Result<Vec<QueryResult>, String> {
     with_harness(|harness| {
-        let mut session = harness.engine.session();
+        let mut session = harness.session.borrow_mut();
         session.execute_script(sql).map_err(|err| err.to_string())
     })
 }
 
 pub fn execute_sql(sql: &str) -> Result<QueryResult, String> {
     with_harness(|harness| {
-        let mut session = harness.engine.session();
+        let mut session = harness.session.borrow_mut();
         session.execute_one(sql).map_err(|err| err.to_string())
     })
 }
 
 pub fn execute_statement(statement: Statement) -> Result<QueryResult, String> {
     with_harness(|harness| {
-        let mut session = harness.engine.session();
+        let mut session = harness.session.borrow_mut();
         session
             .execute_statement(statement)
             .map_err(|err| err.to_string())
@@ -71,6 +82,56 @@ pub fn insert_values(rows: Vec<Vec<Value>>) -> Vec<Vec<Expression>> {
         .collect()
 }
 
+pub fn memory_engine() -> Engine {
+    Engine::open(EngineOptions::memory()).unwrap()
+}
+
+/// The rows `sql` returns in a new session of `engine`.
+pub fn rows(engine: &Engine, sql: &str) -> Vec<Vec<Value>> {
+    match engine.session().execute_one(sql).unwrap() {
+        QueryResult::Rows(batch) => batch.rows,
+        other => panic!("expected rows, got {other:?}"),
+    }
+}
+
+pub fn scalar(engine: &Engine, sql: &str) -> Value {
+    rows(engine, sql).remove(0).remove(0)
+}
+
+pub fn text(value: &str) -> Value {
+    Value::Text(value.to_string())
+}
+
+pub fn command(tag: CommandTag) -> QueryResult {
+    QueryResult::Command(CommandResult { tag, affected: 0 })
+}
+
+/// A path in the temp directory that no other test uses, ending in `name`.
+pub fn unique_temp_path(name: &str) -> PathBuf {
+    static COUNTER: AtomicU64 = AtomicU64::new(0);
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_nanos();
+    std::env::temp_dir().join(format!(
+        "rustql-test-{}-{}-{}-{}",
+        std::process::id(),
+        timestamp,
+        COUNTER.fetch_add(1, Ordering::Relaxed),
+        name
+    ))
+}
+
+/// Removes a database file and the sidecar files the engine keeps next to it.
+pub fn cleanup_storage_files(path: &Path) {
+    fs::remove_file(path).ok();
+    for suffix in [".wal", ".lock", ".linked"] {
+        let mut sidecar = path.as_os_str().to_os_string();
+        sidecar.push(suffix);
+        fs::remove_file(PathBuf::from(sidecar)).ok();
+    }
+}
+
 pub fn query_rows(sql: &str) -> Result<RowBatch, String> {
     match execute_sql(sql)? {
         QueryResult::Rows(rows) => Ok(rows),
@@ -86,7 +147,7 @@ pub fn command_result(sql: &str) -> Result<CommandResult, String> {
 }
 
 pub fn snapshot_database() -> Result<Database, String> {
-    with_harness(|harness| Ok(harness.engine.snapshot_database()))
+    with_harness(|harness| Ok(harness.session.borrow().snapshot_database()))
 }
 
 pub fn assert_command(result: QueryResult, expected_tag: CommandTag, expected_affected: u64) {
@@ -234,6 +295,14 @@ impl QueryResultNeedle for &str {
             Value::Null => *self == "NULL",
             Value::Integer(value) => self.parse::<i64>().is_ok_and(|expected| expected == *value),
             Value::Float(value) => self.matches_float(*value),
+            Value::Decimal(value) => {
+                rustql::Decimal::parse(self).is_some_and(|expected| expected == *value)
+            }
+            Value::Interval(value) => {
+                rustql::Interval::parse(self).is_some_and(|expected| expected == *value)
+            }
+            Value::TimestampTz(_) => value.to_string().contains(*self),
+            Value::Blob(_) | Value::Json(_) => value.to_string() == *self,
             Value::Boolean(value) => self
                 .parse::<bool>()
                 .is_ok_and(|expected| expected == *value),
@@ -437,16 +506,26 @@ fn command_tag_name(tag: CommandTag) -> &'static str {
         CommandTag::Update => "Update",
         CommandTag::Delete => "Delete",
         CommandTag::BeginTransaction => "BeginTransaction",
+        CommandTag::SetTransaction => "SetTransaction",
+        CommandTag::SetConstraints => "SetConstraints",
+        CommandTag::SetStatementTimeout => "SetStatementTimeout",
         CommandTag::CommitTransaction => "CommitTransaction",
         CommandTag::RollbackTransaction => "RollbackTransaction",
         CommandTag::Savepoint => "Savepoint",
         CommandTag::ReleaseSavepoint => "ReleaseSavepoint",
         CommandTag::RollbackToSavepoint => "RollbackToSavepoint",
+        CommandTag::PrepareTransaction => "PrepareTransaction",
+        CommandTag::CommitPrepared => "CommitPrepared",
+        CommandTag::RollbackPrepared => "RollbackPrepared",
         CommandTag::Analyze => "Analyze",
         CommandTag::TruncateTable => "TruncateTable",
         CommandTag::CreateView => "CreateView",
         CommandTag::DropView => "DropView",
         CommandTag::Merge => "Merge",
         CommandTag::Do => "Do",
+        CommandTag::Checkpoint => "Checkpoint",
+        CommandTag::Backup => "Backup",
+        CommandTag::Attach => "Attach",
+        CommandTag::Detach => "Detach",
     }
 }
@@ -0,0 +1,251 @@
+mod common;
+use common::*;
+use rustql::{Decimal, Engine, EngineOptions, RustqlError, Value};
+use std::path::Path;
+
+fn decimal(text: &str) -> Value {
+    Value::Decimal(Decimal::parse(text).unwrap())
+}
+
+fn create_ledger(engine: &Engine) {
+    let mut session = engine.session();
+    session
+        .execute_one("CREATE TABLE ledger (id INTEGER PRIMARY KEY, amount DECIMAL(10,2))")
+        .unwrap();
+    session
+        .execute_one("INSERT INTO ledger VALUES (1, 0.10), (2, 0.20), (3, 19.99), (4, NULL)")
+        .unwrap();
+}
+
+#[test]
+fn money_columns_sum_and_average_exactly() {
+    let engine = memory_engine();
+    create_ledger(&engine);
+
+    assert_eq!(
+        scalar(&engine, "SELECT SUM(amount) FROM ledger"),
+        decimal("20.29")
+    );
+    assert_eq!(
+        scalar(&engine, "SELECT SUM(amount) FROM ledger WHERE id < 3"),
+        decimal("0.30")
+    );
+    assert_eq!(
+        scalar(&engine, "SELECT AVG(amount) FROM ledger WHERE id < 3"),
+        decimal("0.15")
+    );
+    assert_eq!(
+        scalar(&engine, "SELECT SUM(amount) FROM ledger WHERE id < 3").to_string(),
+        "0.30"
+    );
+}
+
+#[test]
+fn inserted_values_are_rounded_to_the_column_scale() {
+    let engine = memory_engine();
+    create_ledger(&engine);
+    let mut session = engine.session();
+    session
+        .execute_one("INSERT INTO ledger VALUES (5, 2.345), (6, '-1.005'), (7, 3)")
+        .unwrap();
+
+    assert_eq!(
+        rows(
+            &engine,
+            "SELECT CAST(amount AS TEXT) FROM ledger WHERE id > 4 ORDER BY id"
+        ),
+        vec![
+            vec![Value::Text("2.35".to_string())],
+            vec![Value::Text("-1.01".to_string())],
+            vec![Value::Text("3.00".to_string())],
+        ]
+    );
+
+    let err = session
+        .execute_one("INSERT INTO ledger VALUES (8, 123456789.5)")
+        .unwrap_err();
+    assert!(
+        matches!(&err, RustqlError::TypeMismatch(message) if message.contains("DECIMAL(10,2)")),
+        "{err:?}"
+    );
+}
+
+#[test]
+fn casts_arithmetic_and_rounding_are_exact() {
+    let engine = memory_engine();
+
+    assert_eq!(
+        scalar(&engine, "SELECT CAST('1.005' AS NUMERIC(5,2))"),
+        decimal("1.01")
+    );
+    assert_eq!(
+        scalar(&engine, "SELECT 0.1::DECIMAL(5,1) + 0.2::DECIMAL(5,1)"),
+        decimal("0.3")
+    );
+    assert_eq!(scalar(&engine, "SELECT 10::DECIMAL / 4"), decimal("2.5"));
+    assert_eq!(
+        scalar(&engine, "SELECT 1::DECIMAL / 3"),
+        decimal("0.3333333333333333")
+    );
+    assert_eq!(
+        scalar(&engine, "SELECT ROUND(CAST(2.675 AS DECIMAL(6,3)), 2)"),
+        decimal("2.68")
+    );
+    assert_eq!(
+        scalar(&engine, "SELECT FLOOR(CAST(-2.5 AS DECIMAL(3,1)))"),
+        Value::Integer(-3)
+    );
+    assert_eq!(
+        scalar(&engine, "SELECT 12345678901234567.89"),
+        decimal("12345678901234567.89")
+    );
+    assert_eq!(
+        scalar(&engine, "SELECT CAST(CAST(7.9 AS DECIMAL(3,1)) AS INTEGER)"),
+        Value::Integer(7)
+    );
+
+    let mut session = engine.session();
+    assert!(matches!(
+        session.execute_one("SELECT 1::DECIMAL / 0"),
+        Err(RustqlError::DivisionByZero)
+    ));
+    assert!(
+        session
+            .execute_one("SELECT CAST(1000 AS DECIMAL(3,0))")
+            .is_err()
+    );
+    assert!(
+        session
+            .execute_one("SELECT CAST('abc' AS DECIMAL(5,2))")
+            .is_err()
+    );
+    assert!(
+        session
+            .execute_one("SELECT CAST(1 AS DECIMAL(39,0))")
+            .is_err()
+    );
+    assert!(
+        session
+            .execute_one("SELECT CAST(1 AS DECIMAL(2,3))")
+            .is_err()
+    );
+}
+
+#[test]
+fn decimals_compare_and_sort_with_other_numbers() {
+    let engine = memory_engine();
+    let mut session = engine.session();
+    session
+        .execute_one("CREATE TABLE prices (label TEXT, price DECIMAL(6,2))")
+        .unwrap();
+    session
+        .execute_one("INSERT INTO prices VALUES ('a', 2.50), ('b', 2), ('c', 10.05), ('d', 2.5)")
+        .unwrap();
+
+    assert_eq!(
+        rows(
+            &engine,
+            "SELECT label FROM prices WHERE price = 2.5 ORDER BY label"
+        ),
+        vec![
+            vec![Value::Text("a".to_string())],
+            vec![Value::Text("d".to_string())]
+        ]
+    );
+    assert_eq!(
+        rows(
+            &engine,
+            "SELECT label FROM prices WHERE price BETWEEN 2 AND 3 ORDER BY label"
+        ),
+        vec![
+            vec![Value::Text("a".to_string())],
+            vec![Value::Text("b".to_string())],
+            vec![Value::Text("d".to_string())],
+        ]
+    );
+    assert_eq!(
+        rows(
+            &engine,
+            "SELECT label FROM prices ORDER BY price DESC, label"
+        ),
+        vec![
+            vec![Value::Text("c".to_string())],
+            vec![Value::Text("a".to_string())],
+            vec![Value::Text("d".to_string())],
+            vec![Value::Text("b".to_string())],
+        ]
+    );
+    assert_eq!(
+        scalar(&engine, "SELECT COUNT(DISTINCT price) FROM prices"),
+        Value::Integer(3)
+    );
+    assert_eq!(
+        scalar(&engine, "SELECT 0.1::DECIMAL(2,1) * 3 = 0.3"),
+        Value::Boolean(true)
+    );
+}
+
+#[test]
+fn indexed_decimal_columns_find_equal_values() {
+    let engine = memory_engine();
+    let mut session = engine.session();
+    session
+        .execute_one("CREATE TABLE accounts (id INTEGER, balance NUMERIC(12,4))")
+        .unwrap();
+    session
+        .execute_one("CREATE INDEX idx_balance ON accounts (balance)")
+        .unwrap();
+    session
+        .execute_one("INSERT INTO accounts VALUES (1, 100.5), (2, 7), (3, 100.25)")
+        .unwrap();
+
+    assert_eq!(
+        rows(&engine, "SELECT id FROM accounts WHERE balance = 100.5"),
+        vec![vec![Value::Integer(1)]]
+    );
+    assert_eq!(
+        rows(&engine, "SELECT id FROM accounts WHERE balance = 7"),
+        vec![vec![Value::Integer(2)]]
+    );
+}
+
+fn check_decimals_survive_reopen(path: &Path, options: impl Fn() -> EngineOptions) {
+    {
+        let engine = Engine::open(options()).unwrap();
+        create_ledger(&engine);
+        engine
+            .session()
+            .execute_one("CREATE INDEX idx_amount ON ledger (amount)")
+            .unwrap();
+    }
+
+    let engine = Engine::open(options()).unwrap();
+    assert_eq!(
+        scalar(&engine, "SELECT amount FROM ledger WHERE id = 3"),
+        decimal("19.99")
+    );
+    assert_eq!(
+        rows(&engine, "SELECT id FROM ledger WHERE amount = 0.2"),
+        vec![vec![Value::Integer(2)]]
+    );
+    assert_eq!(
+        rows(&engine, "DESCRIBE ledger")[1][1],
+        Value::Text("DECIMAL(10,2)".to_string())
+    );
+    drop(engine);
+    cleanup_storage_files(path);
+}
+
+#[test]
+fn decimals_survive_reopening_a_btree_file() {
+    let path = unique_temp_path("btree.db");
+    cleanup_storage_files(&path);
+    check_decimals_survive_reopen(&path, || EngineOptions::btree(&path));
+}
+
+#[test]
+fn decimals_survive_reopening_a_json_file() {
+    let path = unique_temp_path("json.db");
+    cleanup_storage_files(&path);
+    check_decimals_survive_reopen(&path, || EngineOptions::json(&path));
+}
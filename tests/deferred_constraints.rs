@@ -0,0 +1,198 @@
+mod common;
+use common::*;
+use rustql::ast::Value;
+use std::sync::Mutex;
+
+static TEST_MUTEX: Mutex<()> = Mutex::new(());
+
+fn setup_test<'a>() -> std::sync::MutexGuard<'a, ()> {
+    let guard = TEST_MUTEX.lock().unwrap();
+    reset_database();
+    guard
+}
+
+fn create_parent_and_child(deferrable: &str) {
+    execute_sql("CREATE TABLE parent (id INTEGER PRIMARY KEY)").unwrap();
+    execute_sql(&format!(
+        "CREATE TABLE child (id INTEGER, parent_id INTEGER REFERENCES parent(id) {})",
+        deferrable
+    ))
+    .unwrap();
+}
+
+#[test]
+fn test_deferred_foreign_key_is_checked_at_commit() {
+    let _guard = setup_test();
+    create_parent_and_child("DEFERRABLE INITIALLY DEFERRED");
+
+    execute_sql("BEGIN").unwrap();
+    execute_sql("INSERT INTO child VALUES (1, 10)").unwrap();
+    execute_sql("INSERT INTO parent VALUES (10)").unwrap();
+    assert_command_sql("COMMIT", CommandTag::CommitTransaction, 0);
+
+    assert_rows(
+        "SELECT id, parent_id FROM child",
+        &["id", "parent_id"],
+        vec![vec![Value::Integer(1), Value::Integer(10)]],
+    );
+}
+
+#[test]
+fn test_deferred_violation_rolls_back_the_transaction() {
+    let _guard = setup_test();
+    create_parent_and_child("DEFERRABLE INITIALLY DEFERRED");
+    execute_sql("INSERT INTO parent VALUES (1)").unwrap();
+
+    execute_sql("BEGIN").unwrap();
+    execute_sql("INSERT INTO parent VALUES (2)").unwrap();
+    execute_sql("INSERT INTO child VALUES (1, 99)").unwrap();
+    let err = execute_sql("COMMIT").unwrap_err();
+    assert!(
+        err.contains("Deferred constraint violation"),
+        "unexpected error: {err}"
+    );
+    assert!(err.contains("child"), "unexpected error: {err}");
+
+    assert_eq!(
+        execute_sql("COMMIT").unwrap_err(),
+        "No transaction in progress"
+    );
+    assert_rows(
+        "SELECT id FROM parent",
+        &["id"],
+        vec![vec![Value::Integer(1)]],
+    );
+    assert!(query_rows("SELECT * FROM child").unwrap().rows.is_empty());
+}
+
+#[test]
+fn test_deferred_foreign_key_lets_a_referenced_row_be_replaced() {
+    let _guard = setup_test();
+    create_parent_and_child("DEFERRABLE INITIALLY DEFERRED");
+    execute_sql("INSERT INTO parent VALUES (1)").unwrap();
+    execute_sql("INSERT INTO child VALUES (1, 1)").unwrap();
+
+    execute_sql("BEGIN").unwrap();
+    execute_sql("DELETE FROM parent WHERE id = 1").unwrap();
+    execute_sql("INSERT INTO parent VALUES (1)").unwrap();
+    execute_sql("COMMIT").unwrap();
+
+    execute_sql("BEGIN").unwrap();
+    execute_sql("DELETE FROM parent WHERE id = 1").unwrap();
+    assert!(execute_sql("COMMIT").is_err());
+    assert_rows(
+        "SELECT id FROM parent",
+        &["id"],
+        vec![vec![Value::Integer(1)]],
+    );
+}
+
+#[test]
+fn test_deferred_unique_allows_swapping_values() {
+    let _guard = setup_test();
+    execute_sql("CREATE TABLE slots (name TEXT, pos INTEGER UNIQUE DEFERRABLE INITIALLY DEFERRED)")
+        .unwrap();
+    execute_sql("INSERT INTO slots VALUES ('a', 1), ('b', 2)").unwrap();
+
+    execute_sql("BEGIN").unwrap();
+    execute_sql("UPDATE slots SET pos = 3 - pos").unwrap();
+    execute_sql("COMMIT").unwrap();
+    assert_rows(
+        "SELECT name, pos FROM slots ORDER BY name",
+        &["name", "pos"],
+        vec![
+            vec![Value::Text("a".to_string()), Value::Integer(2)],
+            vec![Value::Text("b".to_string()), Value::Integer(1)],
+        ],
+    );
+
+    execute_sql("BEGIN").unwrap();
+    execute_sql("UPDATE slots SET pos = 1").unwrap();
+    let err = execute_sql("COMMIT").unwrap_err();
+    assert!(err.contains("Unique"), "unexpected error: {err}");
+}
+
+#[test]
+fn test_deferrable_constraints_are_immediate_outside_transactions() {
+    let _guard = setup_test();
+    create_parent_and_child("DEFERRABLE INITIALLY DEFERRED");
+    execute_sql("CREATE TABLE slots (pos INTEGER, UNIQUE (pos) DEFERRABLE INITIALLY DEFERRED)")
+        .unwrap();
+    execute_sql("INSERT INTO slots VALUES (1)").unwrap();
+
+    let err = execute_sql("INSERT INTO child VALUES (1, 5)").unwrap_err();
+    assert!(err.contains("Foreign key constraint violation"));
+    let err = execute_sql("INSERT INTO slots VALUES (1)").unwrap_err();
+    assert!(err.contains("UNIQUE constraint violation"));
+    assert_eq!(
+        execute_sql("SET CONSTRAINTS ALL DEFERRED").unwrap_err(),
+        "SET CONSTRAINTS can only be used inside a transaction"
+    );
+}
+
+#[test]
+fn test_set_constraints_switches_deferrable_constraints() {
+    let _guard = setup_test();
+    create_parent_and_child("DEFERRABLE INITIALLY IMMEDIATE");
+
+    execute_sql("BEGIN").unwrap();
+    assert!(execute_sql("INSERT INTO child VALUES (1, 7)").is_err());
+    assert_command_sql(
+        "SET CONSTRAINTS ALL DEFERRED",
+        CommandTag::SetConstraints,
+        0,
+    );
+    execute_sql("INSERT INTO child VALUES (1, 7)").unwrap();
+
+    // Going back to immediate runs the checks deferred so far.
+    let err = execute_sql("SET CONSTRAINTS ALL IMMEDIATE").unwrap_err();
+    assert!(err.contains("Deferred constraint violation"));
+    execute_sql("INSERT INTO parent VALUES (7)").unwrap();
+    execute_sql("SET CONSTRAINTS ALL IMMEDIATE").unwrap();
+    assert!(execute_sql("INSERT INTO child VALUES (2, 8)").is_err());
+    execute_sql("COMMIT").unwrap();
+
+    assert_rows(
+        "SELECT id, parent_id FROM child",
+        &["id", "parent_id"],
+        vec![vec![Value::Integer(1), Value::Integer(7)]],
+    );
+}
+
+#[test]
+fn test_set_constraints_leaves_non_deferrable_constraints_immediate() {
+    let _guard = setup_test();
+    create_parent_and_child("");
+
+    execute_sql("BEGIN").unwrap();
+    execute_sql("SET CONSTRAINTS ALL DEFERRED").unwrap();
+    let err = execute_sql("INSERT INTO child VALUES (1, 3)").unwrap_err();
+    assert!(err.contains("Foreign key constraint violation"));
+    execute_sql("ROLLBACK").unwrap();
+}
+
+#[test]
+fn test_deferrable_clause_parsing() {
+    let _guard = setup_test();
+    execute_sql("CREATE TABLE parent (id INTEGER PRIMARY KEY)").unwrap();
+    execute_sql(
+        "CREATE TABLE a (p INTEGER REFERENCES parent(id) ON DELETE CASCADE INITIALLY DEFERRED)",
+    )
+    .unwrap();
+    execute_sql("CREATE TABLE b (p INTEGER UNIQUE NOT DEFERRABLE)").unwrap();
+    execute_sql(
+        "CREATE TABLE c (p INTEGER, CONSTRAINT c_p UNIQUE (p) INITIALLY IMMEDIATE DEFERRABLE)",
+    )
+    .unwrap();
+    execute_sql("ALTER TABLE c ADD UNIQUE (p) DEFERRABLE").unwrap();
+
+    for sql in [
+        "CREATE TABLE d (p INTEGER UNIQUE NOT DEFERRABLE INITIALLY DEFERRED)",
+        "CREATE TABLE d (p INTEGER UNIQUE DEFERRABLE DEFERRABLE)",
+        "CREATE TABLE d (p INTEGER UNIQUE INITIALLY LATER)",
+        "SET CONSTRAINTS c_p DEFERRED",
+        "SET CONSTRAINTS ALL",
+    ] {
+        assert!(execute_sql(sql).is_err(), "{sql} should not parse");
+    }
+}
@@ -1,9 +1,10 @@
 mod common;
 use common::*;
 use rustql::ast::Value;
-use rustql::{Engine, EngineOptions, QueryResult, StorageMode};
+use rustql::{Engine, EngineOptions, QueryResult, RustqlError, Session, StorageMode};
 use std::fs;
 use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::time::{SystemTime, UNIX_EPOCH};
 
 #[test]
@@ -43,36 +44,670 @@ fn transaction_state_is_per_engine() {
 }
 
 #[test]
-fn transaction_state_is_shared_by_sessions_from_one_engine() {
+fn sessions_from_one_engine_have_independent_transactions() {
     let engine = open_memory_engine();
     let mut session_a = engine.session();
     let mut session_b = engine.session();
 
     session_a
-        .execute_one("CREATE TABLE shared_tx (id INTEGER)")
+        .execute_one("CREATE TABLE own_tx (id INTEGER)")
         .unwrap();
     session_a.execute_one("BEGIN TRANSACTION").unwrap();
     session_a
-        .execute_one("INSERT INTO shared_tx VALUES (1)")
+        .execute_one("INSERT INTO own_tx VALUES (1)")
         .unwrap();
 
-    let begin_result = session_b.execute_one("BEGIN TRANSACTION");
-    assert!(begin_result.is_err(), "{begin_result:?}");
+    session_b.execute_one("BEGIN TRANSACTION").unwrap();
+    assert!(query(&mut session_b, "SELECT id FROM own_tx").is_empty());
+    session_b
+        .execute_one("INSERT INTO own_tx VALUES (2)")
+        .unwrap();
+    session_b.execute_one("ROLLBACK").unwrap();
+
+    assert_eq!(
+        query(&mut session_a, "SELECT id FROM own_tx"),
+        vec![vec![Value::Integer(1)]]
+    );
+    session_a.execute_one("COMMIT").unwrap();
+    assert_eq!(
+        query(&mut session_b, "SELECT id FROM own_tx"),
+        vec![vec![Value::Integer(1)]]
+    );
+}
+
+#[test]
+fn transactions_read_the_snapshot_taken_at_begin() {
+    let engine = open_memory_engine();
+    let mut reader = engine.session();
+    let mut writer = engine.session();
+
+    writer
+        .execute_script(
+            "CREATE TABLE snap (id INTEGER, v INTEGER); INSERT INTO snap VALUES (1, 10)",
+        )
+        .unwrap();
+    reader.execute_one("BEGIN").unwrap();
+    writer
+        .execute_script("UPDATE snap SET v = 11 WHERE id = 1; INSERT INTO snap VALUES (2, 20)")
+        .unwrap();
+
+    assert_eq!(
+        query(&mut reader, "SELECT id, v FROM snap ORDER BY id"),
+        vec![vec![Value::Integer(1), Value::Integer(10)]]
+    );
+    reader.execute_one("COMMIT").unwrap();
+    assert_eq!(
+        query(&mut reader, "SELECT id, v FROM snap ORDER BY id"),
+        vec![
+            vec![Value::Integer(1), Value::Integer(11)],
+            vec![Value::Integer(2), Value::Integer(20)],
+        ]
+    );
+}
+
+#[test]
+fn concurrent_updates_of_one_row_fail_with_a_serialization_error() {
+    let engine = open_memory_engine();
+    let mut session_a = engine.session();
+    let mut session_b = engine.session();
+
+    session_a
+        .execute_script(
+            "CREATE TABLE counter (id INTEGER, n INTEGER); INSERT INTO counter VALUES (1, 0)",
+        )
+        .unwrap();
+    session_a.execute_one("BEGIN").unwrap();
+    session_b.execute_one("BEGIN").unwrap();
+    session_a
+        .execute_one("UPDATE counter SET n = n + 1 WHERE id = 1")
+        .unwrap();
+    session_b
+        .execute_one("UPDATE counter SET n = n + 10 WHERE id = 1")
+        .unwrap();
+    session_a.execute_one("COMMIT").unwrap();
+
+    let err = session_b.execute_one("COMMIT").unwrap_err();
     assert!(
-        begin_result
-            .unwrap_err()
-            .to_string()
-            .contains("Transaction already in progress"),
-        "expected shared transaction error"
+        matches!(err, RustqlError::SerializationFailure(_)),
+        "got: {err:?}"
+    );
+    assert!(
+        err.to_string().contains("row 1 of table 'counter'"),
+        "{err}"
     );
 
-    session_b.execute_one("ROLLBACK").unwrap();
+    // The failed transaction is gone, so the session can retry.
+    session_b.execute_one("BEGIN").unwrap();
+    session_b
+        .execute_one("UPDATE counter SET n = n + 10 WHERE id = 1")
+        .unwrap();
+    session_b.execute_one("COMMIT").unwrap();
+    assert_eq!(
+        query(&mut session_a, "SELECT n FROM counter"),
+        vec![vec![Value::Integer(11)]]
+    );
+}
 
-    let result = session_a.execute_one("SELECT * FROM shared_tx").unwrap();
-    match result {
-        QueryResult::Rows(rows) => assert!(rows.rows.is_empty(), "got: {rows:?}"),
-        other => panic!("expected row result, got: {other:?}"),
+#[test]
+fn autocommit_writes_conflict_with_open_transactions() {
+    let engine = open_memory_engine();
+    let mut session_a = engine.session();
+    let mut session_b = engine.session();
+
+    session_a
+        .execute_script(
+            "CREATE TABLE items (id INTEGER, v INTEGER); INSERT INTO items VALUES (1, 0)",
+        )
+        .unwrap();
+    session_a.execute_one("BEGIN").unwrap();
+    session_a
+        .execute_one("DELETE FROM items WHERE id = 1")
+        .unwrap();
+    session_b
+        .execute_one("UPDATE items SET v = 5 WHERE id = 1")
+        .unwrap();
+
+    let err = session_a.execute_one("COMMIT").unwrap_err();
+    assert!(
+        matches!(err, RustqlError::SerializationFailure(_)),
+        "got: {err:?}"
+    );
+    assert_eq!(
+        query(&mut session_a, "SELECT v FROM items"),
+        vec![vec![Value::Integer(5)]]
+    );
+}
+
+#[test]
+fn concurrent_transactions_writing_different_rows_both_commit() {
+    let engine = open_memory_engine();
+    let mut session_a = engine.session();
+    let mut session_b = engine.session();
+
+    session_a
+        .execute_script(
+            "CREATE TABLE accounts (id INTEGER PRIMARY KEY, balance INTEGER); \
+             CREATE INDEX idx_balance ON accounts (balance); \
+             INSERT INTO accounts VALUES (1, 100), (2, 200)",
+        )
+        .unwrap();
+    session_a.execute_one("BEGIN").unwrap();
+    session_b.execute_one("BEGIN").unwrap();
+    session_a
+        .execute_script(
+            "UPDATE accounts SET balance = 150 WHERE id = 1; INSERT INTO accounts VALUES (3, 300)",
+        )
+        .unwrap();
+    session_b
+        .execute_script("DELETE FROM accounts WHERE id = 2; INSERT INTO accounts VALUES (4, 400)")
+        .unwrap();
+    session_a.execute_one("COMMIT").unwrap();
+    session_b.execute_one("COMMIT").unwrap();
+
+    assert_eq!(
+        query(
+            &mut session_a,
+            "SELECT id, balance FROM accounts ORDER BY id"
+        ),
+        vec![
+            vec![Value::Integer(1), Value::Integer(150)],
+            vec![Value::Integer(3), Value::Integer(300)],
+            vec![Value::Integer(4), Value::Integer(400)],
+        ]
+    );
+    assert_eq!(
+        query(
+            &mut session_a,
+            "SELECT id FROM accounts WHERE balance = 400"
+        ),
+        vec![vec![Value::Integer(4)]]
+    );
+    assert!(engine.check_database().unwrap().is_empty());
+}
+
+#[test]
+fn concurrent_inserts_of_one_key_fail_at_commit() {
+    let engine = open_memory_engine();
+    let mut session_a = engine.session();
+    let mut session_b = engine.session();
+
+    session_a
+        .execute_one("CREATE TABLE users (id INTEGER PRIMARY KEY, name TEXT)")
+        .unwrap();
+    session_a.execute_one("BEGIN").unwrap();
+    session_b.execute_one("BEGIN").unwrap();
+    session_a
+        .execute_one("INSERT INTO users VALUES (1, 'ann')")
+        .unwrap();
+    session_b
+        .execute_one("INSERT INTO users VALUES (1, 'bob')")
+        .unwrap();
+    session_a.execute_one("COMMIT").unwrap();
+
+    let err = session_b.execute_one("COMMIT").unwrap_err();
+    assert!(
+        matches!(err, RustqlError::SerializationFailure(_)),
+        "got: {err:?}"
+    );
+    assert_eq!(
+        query(&mut session_b, "SELECT name FROM users"),
+        vec![vec![Value::Text("ann".to_string())]]
+    );
+}
+
+#[test]
+fn concurrent_auto_increment_inserts_both_commit() {
+    let engine = open_memory_engine();
+    let mut session_a = engine.session();
+    let mut session_b = engine.session();
+
+    session_a
+        .execute_script(
+            "CREATE TABLE users (id INTEGER PRIMARY KEY AUTO_INCREMENT, name TEXT); \
+             INSERT INTO users (name) VALUES ('ann')",
+        )
+        .unwrap();
+    session_a.execute_one("BEGIN").unwrap();
+    session_b.execute_one("BEGIN").unwrap();
+    session_a
+        .execute_one("INSERT INTO users (name) VALUES ('bob'), ('cid')")
+        .unwrap();
+    session_b
+        .execute_one("INSERT INTO users (name) VALUES ('dee')")
+        .unwrap();
+    session_b.execute_one("COMMIT").unwrap();
+    session_a.execute_one("COMMIT").unwrap();
+
+    assert_eq!(
+        query(&mut session_a, "SELECT id, name FROM users ORDER BY id"),
+        vec![
+            vec![Value::Integer(1), Value::Text("ann".to_string())],
+            vec![Value::Integer(2), Value::Text("bob".to_string())],
+            vec![Value::Integer(3), Value::Text("cid".to_string())],
+            vec![Value::Integer(4), Value::Text("dee".to_string())],
+        ]
+    );
+}
+
+#[test]
+fn deleting_a_parent_another_transaction_refers_to_fails_at_commit() {
+    let engine = open_memory_engine();
+    let mut session_a = engine.session();
+    let mut session_b = engine.session();
+
+    session_a
+        .execute_script(
+            "CREATE TABLE parent (id INTEGER PRIMARY KEY); \
+             CREATE TABLE child (id INTEGER PRIMARY KEY, parent_id INTEGER REFERENCES parent(id)); \
+             CREATE INDEX idx_child_parent ON child (parent_id); \
+             INSERT INTO parent VALUES (1), (2)",
+        )
+        .unwrap();
+    session_a.execute_one("BEGIN").unwrap();
+    session_b.execute_one("BEGIN").unwrap();
+    session_a
+        .execute_one("INSERT INTO child VALUES (10, 1)")
+        .unwrap();
+    session_b
+        .execute_one("DELETE FROM parent WHERE id = 1")
+        .unwrap();
+    session_a.execute_one("COMMIT").unwrap();
+
+    let err = session_b.execute_one("COMMIT").unwrap_err();
+    assert!(
+        matches!(&err, RustqlError::SerializationFailure(message) if message.contains("'parent'.'id'")),
+        "got: {err:?}"
+    );
+    assert_eq!(
+        query(&mut session_b, "SELECT id FROM parent ORDER BY id"),
+        vec![vec![Value::Integer(1)], vec![Value::Integer(2)]]
+    );
+}
+
+#[test]
+fn sessions_run_transactions_from_several_threads() {
+    let engine = open_memory_engine();
+    engine
+        .session()
+        .execute_one("CREATE TABLE hits (worker INTEGER)")
+        .unwrap();
+
+    std::thread::scope(|scope| {
+        for worker in 0..4 {
+            let mut session = engine.session();
+            scope.spawn(move || {
+                for _ in 0..10 {
+                    session.execute_one("BEGIN").unwrap();
+                    session
+                        .execute_one(&format!("INSERT INTO hits VALUES ({worker})"))
+                        .unwrap();
+                    session.execute_one("COMMIT").unwrap();
+                }
+            });
+        }
+    });
+
+    assert_eq!(
+        query(&mut engine.session(), "SELECT COUNT(*) FROM hits"),
+        vec![vec![Value::Integer(40)]]
+    );
+}
+
+#[test]
+fn concurrent_readers_never_see_a_statement_half_done() {
+    let engine = open_memory_engine();
+    engine
+        .session()
+        .execute_script(
+            "CREATE TABLE pair (id INTEGER PRIMARY KEY, v INTEGER); INSERT INTO pair VALUES (1, 0), (2, 0)",
+        )
+        .unwrap();
+    let done = AtomicBool::new(false);
+
+    std::thread::scope(|scope| {
+        for _ in 0..3 {
+            let mut session = engine.session();
+            let done = &done;
+            scope.spawn(move || {
+                while !done.load(Ordering::Acquire) {
+                    let rows = query(&mut session, "SELECT COUNT(*), MIN(v), MAX(v) FROM pair");
+                    assert_eq!(rows[0][0], Value::Integer(2));
+                    assert_eq!(
+                        rows[0][1], rows[0][2],
+                        "saw one row updated without the other"
+                    );
+                }
+            });
+        }
+
+        let mut writer = engine.session();
+        for _ in 0..50 {
+            writer.execute_one("UPDATE pair SET v = v + 1").unwrap();
+            // The first row goes in before the duplicate key fails the statement.
+            assert!(
+                writer
+                    .execute_one("INSERT INTO pair VALUES (3, -1), (1, -1)")
+                    .is_err()
+            );
+        }
+        done.store(true, Ordering::Release);
+    });
+
+    assert_eq!(
+        query(&mut engine.session(), "SELECT MIN(v), MAX(v) FROM pair"),
+        vec![vec![Value::Integer(50), Value::Integer(50)]]
+    );
+}
+
+#[test]
+fn begin_and_set_transaction_accept_isolation_levels_and_access_modes() {
+    let engine = open_memory_engine();
+    let mut session = engine.session();
+
+    for sql in [
+        "BEGIN ISOLATION LEVEL SERIALIZABLE",
+        "BEGIN TRANSACTION ISOLATION LEVEL REPEATABLE READ",
+        "begin isolation level read committed",
+        "BEGIN ISOLATION LEVEL READ UNCOMMITTED",
+        "BEGIN READ ONLY",
+        "BEGIN READ WRITE, ISOLATION LEVEL SERIALIZABLE",
+        "BEGIN ISOLATION LEVEL SERIALIZABLE READ ONLY",
+    ] {
+        session.execute_one(sql).unwrap();
+        session.execute_one("ROLLBACK").unwrap();
+    }
+
+    session.execute_one("BEGIN").unwrap();
+    let result = session
+        .execute_one("SET TRANSACTION ISOLATION LEVEL SERIALIZABLE, READ ONLY")
+        .unwrap();
+    assert_eq!(
+        result,
+        QueryResult::Command(rustql::CommandResult {
+            tag: rustql::CommandTag::SetTransaction,
+            affected: 0,
+        })
+    );
+    session.execute_one("ROLLBACK").unwrap();
+
+    for sql in [
+        "BEGIN ISOLATION SERIALIZABLE",
+        "BEGIN ISOLATION LEVEL SNAPSHOT",
+        "BEGIN READ",
+        "BEGIN READ ONLY,",
+        "BEGIN READ ONLY READ WRITE",
+        "SET TRANSACTION",
+        "SET statement_mode = 1",
+    ] {
+        let err = session.execute_one(sql).unwrap_err();
+        assert!(
+            matches!(
+                err,
+                RustqlError::ParseError(_) | RustqlError::ParseErrorAt { .. }
+            ),
+            "{sql}: {err:?}"
+        );
+    }
+}
+
+#[test]
+fn set_transaction_must_come_first_in_a_transaction() {
+    let engine = open_memory_engine();
+    let mut session = engine.session();
+    session.execute_one("CREATE TABLE t (id INTEGER)").unwrap();
+
+    let err = session
+        .execute_one("SET TRANSACTION READ ONLY")
+        .unwrap_err();
+    assert!(
+        matches!(err, RustqlError::TransactionError(_)),
+        "got: {err:?}"
+    );
+
+    session.execute_one("BEGIN").unwrap();
+    session.execute_one("SELECT * FROM t").unwrap();
+    let err = session
+        .execute_one("SET TRANSACTION ISOLATION LEVEL SERIALIZABLE")
+        .unwrap_err();
+    assert!(err.to_string().contains("before any query"), "got: {err}");
+    session.execute_one("ROLLBACK").unwrap();
+}
+
+#[test]
+fn read_only_transactions_reject_writes() {
+    let engine = open_memory_engine();
+    let mut session = engine.session();
+    session
+        .execute_script("CREATE TABLE t (id INTEGER); INSERT INTO t VALUES (1)")
+        .unwrap();
+
+    session.execute_one("BEGIN READ ONLY").unwrap();
+    assert_eq!(
+        query(&mut session, "SELECT id FROM t"),
+        vec![vec![Value::Integer(1)]]
+    );
+    for (sql, command) in [
+        ("INSERT INTO t VALUES (2)", "INSERT"),
+        ("UPDATE t SET id = 3", "UPDATE"),
+        ("DELETE FROM t", "DELETE"),
+        ("CREATE TABLE u (id INTEGER)", "CREATE TABLE"),
+    ] {
+        let err = session.execute_one(sql).unwrap_err();
+        assert!(
+            matches!(err, RustqlError::TransactionError(_)),
+            "got: {err:?}"
+        );
+        assert_eq!(
+            err.to_string(),
+            format!("Cannot execute {command} in a read-only transaction")
+        );
+    }
+    session.execute_one("COMMIT").unwrap();
+
+    session.execute_one("BEGIN").unwrap();
+    session.execute_one("SET TRANSACTION READ ONLY").unwrap();
+    assert!(session.execute_one("INSERT INTO t VALUES (2)").is_err());
+    session.execute_one("ROLLBACK").unwrap();
+
+    session.execute_one("INSERT INTO t VALUES (2)").unwrap();
+    assert_eq!(query(&mut session, "SELECT id FROM t").len(), 2);
+}
+
+#[test]
+fn read_committed_statements_see_commits_made_before_they_start() {
+    let engine = open_memory_engine();
+    let mut reader = engine.session();
+    let mut writer = engine.session();
+    writer
+        .execute_script(
+            "CREATE TABLE t (id INTEGER PRIMARY KEY, v TEXT); INSERT INTO t VALUES (1, 'a')",
+        )
+        .unwrap();
+
+    reader
+        .execute_one("BEGIN ISOLATION LEVEL READ COMMITTED")
+        .unwrap();
+    reader.execute_one("INSERT INTO t VALUES (2, 'b')").unwrap();
+    writer.execute_one("INSERT INTO t VALUES (3, 'c')").unwrap();
+    writer
+        .execute_one("UPDATE t SET v = 'a2' WHERE id = 1")
+        .unwrap();
+
+    assert_eq!(
+        query(&mut reader, "SELECT id, v FROM t ORDER BY id"),
+        vec![
+            vec![Value::Integer(1), Value::Text("a2".to_string())],
+            vec![Value::Integer(2), Value::Text("b".to_string())],
+            vec![Value::Integer(3), Value::Text("c".to_string())],
+        ]
+    );
+    // The refresh gave the row this transaction inserted a new id; later
+    // writes and savepoints must still find it.
+    reader.execute_one("SAVEPOINT s").unwrap();
+    reader
+        .execute_one("UPDATE t SET v = 'b2' WHERE id = 2")
+        .unwrap();
+    reader.execute_one("ROLLBACK TO SAVEPOINT s").unwrap();
+    reader
+        .execute_one("UPDATE t SET v = 'b3' WHERE id = 2")
+        .unwrap();
+    writer.execute_one("DELETE FROM t WHERE id = 3").unwrap();
+    reader.execute_one("COMMIT").unwrap();
+
+    assert_eq!(
+        query(&mut writer, "SELECT id, v FROM t ORDER BY id"),
+        vec![
+            vec![Value::Integer(1), Value::Text("a2".to_string())],
+            vec![Value::Integer(2), Value::Text("b3".to_string())],
+        ]
+    );
+    assert!(engine.check_database().unwrap().is_empty());
+}
+
+#[test]
+fn read_committed_refresh_leaves_deferred_violations_to_commit() {
+    let engine = open_memory_engine();
+    let mut session = engine.session();
+    let mut writer = engine.session();
+    session
+        .execute_script(
+            "CREATE TABLE parent (id INTEGER PRIMARY KEY);
+             CREATE TABLE child (id INTEGER, parent_id INTEGER REFERENCES parent(id) DEFERRABLE INITIALLY DEFERRED)",
+        )
+        .unwrap();
+
+    session
+        .execute_one("BEGIN ISOLATION LEVEL READ COMMITTED")
+        .unwrap();
+    session
+        .execute_one("INSERT INTO child VALUES (1, 5)")
+        .unwrap();
+    writer.execute_one("INSERT INTO parent VALUES (9)").unwrap();
+    // Merging the new commit into the snapshot must not report the
+    // violation this transaction may still fix.
+    assert_eq!(query(&mut session, "SELECT id FROM parent").len(), 1);
+    writer.execute_one("INSERT INTO parent VALUES (5)").unwrap();
+    session.execute_one("COMMIT").unwrap();
+
+    assert_eq!(
+        query(&mut writer, "SELECT id, parent_id FROM child"),
+        vec![vec![Value::Integer(1), Value::Integer(5)]]
+    );
+}
+
+#[test]
+fn serializable_transactions_abort_on_write_skew() {
+    let engine = open_memory_engine();
+    engine
+        .session()
+        .execute_script(
+            "CREATE TABLE doctors (id INTEGER PRIMARY KEY, on_call BOOLEAN); \
+             INSERT INTO doctors VALUES (1, TRUE), (2, TRUE)",
+        )
+        .unwrap();
+
+    // Each transaction takes one doctor off call after checking the other
+    // is still on it. Snapshot isolation lets both commit.
+    let run = |level: &str| {
+        let mut alice = engine.session();
+        let mut bob = engine.session();
+        for (session, id) in [(&mut alice, 1), (&mut bob, 2)] {
+            session
+                .execute_one(&format!("BEGIN ISOLATION LEVEL {level}"))
+                .unwrap();
+            assert_eq!(
+                query(session, "SELECT COUNT(*) FROM doctors WHERE on_call"),
+                vec![vec![Value::Integer(2)]]
+            );
+            session
+                .execute_one(&format!(
+                    "UPDATE doctors SET on_call = FALSE WHERE id = {id}"
+                ))
+                .unwrap();
+        }
+        alice.execute_one("COMMIT").unwrap();
+        let result = bob.execute_one("COMMIT");
+        engine
+            .session()
+            .execute_one("UPDATE doctors SET on_call = TRUE")
+            .unwrap();
+        result
+    };
+
+    run("REPEATABLE READ").unwrap();
+    let err = run("SERIALIZABLE").unwrap_err();
+    assert!(
+        matches!(err, RustqlError::SerializationFailure(_)),
+        "got: {err:?}"
+    );
+    assert_eq!(
+        err.to_string(),
+        "Could not serialize transaction: table 'doctors', which it read, was changed by a concurrent transaction"
+    );
+}
+
+#[test]
+fn serializable_read_only_transactions_commit_despite_concurrent_writes() {
+    let engine = open_memory_engine();
+    let mut reader = engine.session();
+    let mut writer = engine.session();
+    writer
+        .execute_script("CREATE TABLE t (id INTEGER); INSERT INTO t VALUES (1)")
+        .unwrap();
+
+    reader
+        .execute_one("BEGIN ISOLATION LEVEL SERIALIZABLE")
+        .unwrap();
+    assert_eq!(query(&mut reader, "SELECT id FROM t").len(), 1);
+    writer.execute_one("INSERT INTO t VALUES (2)").unwrap();
+    assert_eq!(query(&mut reader, "SELECT id FROM t").len(), 1);
+    reader.execute_one("COMMIT").unwrap();
+}
+
+#[test]
+fn btree_snapshots_keep_reading_paged_rows_changed_by_later_commits() {
+    let path = unique_temp_path("db");
+    cleanup_storage_files(&path);
+    {
+        let engine = open_disk_engine(&path);
+        engine
+            .session()
+            .execute_script("CREATE TABLE paged (id INTEGER, v INTEGER); INSERT INTO paged VALUES (1, 10), (2, 20)")
+            .unwrap();
     }
+
+    let engine = open_disk_engine(&path);
+    let mut reader = engine.session();
+    let mut writer = engine.session();
+    reader.execute_one("BEGIN").unwrap();
+    writer
+        .execute_script("UPDATE paged SET v = 11 WHERE id = 1; DELETE FROM paged WHERE id = 2")
+        .unwrap();
+    assert_eq!(
+        query(&mut reader, "SELECT id, v FROM paged ORDER BY id"),
+        vec![
+            vec![Value::Integer(1), Value::Integer(10)],
+            vec![Value::Integer(2), Value::Integer(20)],
+        ]
+    );
+    reader
+        .execute_one("INSERT INTO paged VALUES (3, 30)")
+        .unwrap();
+    reader.execute_one("COMMIT").unwrap();
+    drop((reader, writer, engine));
+
+    let reloaded = open_disk_engine(&path);
+    assert_eq!(
+        query(
+            &mut reloaded.session(),
+            "SELECT id, v FROM paged ORDER BY id"
+        ),
+        vec![
+            vec![Value::Integer(1), Value::Integer(11)],
+            vec![Value::Integer(3), Value::Integer(30)],
+        ]
+    );
+    cleanup_storage_files(&path);
 }
 
 #[test]
@@ -128,9 +763,17 @@ fn disk_engine_uses_custom_path() {
     cleanup_storage_files(&path);
 }
 
+fn query(session: &mut Session, sql: &str) -> Vec<Vec<Value>> {
+    match session.execute_one(sql).unwrap() {
+        QueryResult::Rows(rows) => rows.rows,
+        other => panic!("expected row result, got: {other:?}"),
+    }
+}
+
 fn open_memory_engine() -> Engine {
     Engine::open(EngineOptions {
         storage: StorageMode::Memory,
+        ..EngineOptions::default()
     })
     .unwrap()
 }
@@ -140,6 +783,7 @@ fn open_disk_engine(path: &Path) -> Engine {
         storage: StorageMode::BTree {
             path: path.to_path_buf(),
         },
+        ..EngineOptions::default()
     })
     .unwrap()
 }
@@ -150,6 +794,10 @@ fn cleanup_storage_files(path: &Path) {
     let mut wal = path.as_os_str().to_os_string();
     wal.push(".wal");
     fs::remove_file(PathBuf::from(wal)).ok();
+
+    let mut lock = path.as_os_str().to_os_string();
+    lock.push(".lock");
+    fs::remove_file(PathBuf::from(lock)).ok();
 }
 
 fn unique_temp_path(extension: &str) -> PathBuf {
@@ -1,10 +1,10 @@
 use rustql::{
-    CommandTag, ConstraintKind, Engine, EngineOptions, QueryResult, RustqlError, StorageMode, ast,
-    lexer, parser, planner,
+    CommandTag, ConstraintKind, Database, Engine, EngineOptions, QueryResult, RustqlError,
+    StorageEngine, StorageMode, ast, lexer, parser, planner,
 };
 use std::collections::BTreeSet;
 use std::ffi::{OsStr, OsString};
-use std::sync::{Mutex, OnceLock};
+use std::sync::{Arc, Mutex, OnceLock};
 
 struct EnvVarGuard {
     key: &'static str,
@@ -64,6 +64,9 @@ fn cleanup_storage_path(path: &std::path::Path) {
     let mut journal_path = path.as_os_str().to_os_string();
     journal_path.push(".wal");
     let _ = std::fs::remove_file(std::path::PathBuf::from(journal_path));
+    let mut lock_path = path.as_os_str().to_os_string();
+    lock_path.push(".lock");
+    let _ = std::fs::remove_file(std::path::PathBuf::from(lock_path));
 }
 
 #[test]
@@ -212,6 +215,7 @@ fn engine_options_from_env_rejects_unknown_storage() {
 fn execute_one_parse_errors_include_line_and_column() {
     let engine = Engine::open(EngineOptions {
         storage: StorageMode::Memory,
+        ..EngineOptions::default()
     })
     .unwrap();
     let mut session = engine.session();
@@ -409,6 +413,7 @@ fn any_all_type_mismatch_returns_error() {
 fn arithmetic_with_null_returns_null() {
     let engine = Engine::open(EngineOptions {
         storage: StorageMode::Memory,
+        ..EngineOptions::default()
     })
     .unwrap();
     let mut session = engine.session();
@@ -427,6 +432,7 @@ fn arithmetic_with_null_returns_null() {
 fn planned_arithmetic_with_null_returns_null() {
     let engine = Engine::open(EngineOptions {
         storage: StorageMode::Memory,
+        ..EngineOptions::default()
     })
     .unwrap();
     let mut session = engine.session();
@@ -456,6 +462,7 @@ fn planned_arithmetic_with_null_returns_null() {
 fn explain_expression_projection_uses_plan() {
     let engine = Engine::open(EngineOptions {
         storage: StorageMode::Memory,
+        ..EngineOptions::default()
     })
     .unwrap();
     let mut session = engine.session();
@@ -500,6 +507,7 @@ fn explain_expression_projection_uses_plan() {
 fn explain_scalar_and_cast_projection_uses_plan() {
     let engine = Engine::open(EngineOptions {
         storage: StorageMode::Memory,
+        ..EngineOptions::default()
     })
     .unwrap();
     let mut session = engine.session();
@@ -556,6 +564,7 @@ fn explain_scalar_and_cast_projection_uses_plan() {
 fn explain_case_projection_uses_plan() {
     let engine = Engine::open(EngineOptions {
         storage: StorageMode::Memory,
+        ..EngineOptions::default()
     })
     .unwrap();
     let mut session = engine.session();
@@ -615,6 +624,7 @@ fn explain_case_projection_uses_plan() {
 fn explain_scalar_and_cast_filter_uses_plan() {
     let engine = Engine::open(EngineOptions {
         storage: StorageMode::Memory,
+        ..EngineOptions::default()
     })
     .unwrap();
     let mut session = engine.session();
@@ -664,6 +674,7 @@ fn explain_scalar_and_cast_filter_uses_plan() {
 fn explain_between_and_case_filter_uses_plan() {
     let engine = Engine::open(EngineOptions {
         storage: StorageMode::Memory,
+        ..EngineOptions::default()
     })
     .unwrap();
     let mut session = engine.session();
@@ -721,6 +732,7 @@ fn explain_between_and_case_filter_uses_plan() {
 fn explain_order_by_expression_uses_sort_plan() {
     let engine = Engine::open(EngineOptions {
         storage: StorageMode::Memory,
+        ..EngineOptions::default()
     })
     .unwrap();
     let mut session = engine.session();
@@ -780,6 +792,7 @@ fn explain_order_by_expression_uses_sort_plan() {
 fn explain_order_by_scalar_expression_uses_sort_plan() {
     let engine = Engine::open(EngineOptions {
         storage: StorageMode::Memory,
+        ..EngineOptions::default()
     })
     .unwrap();
     let mut session = engine.session();
@@ -827,6 +840,7 @@ fn explain_order_by_scalar_expression_uses_sort_plan() {
 fn order_by_projection_alias_remains_correct() {
     let engine = Engine::open(EngineOptions {
         storage: StorageMode::Memory,
+        ..EngineOptions::default()
     })
     .unwrap();
     let mut session = engine.session();
@@ -868,6 +882,7 @@ fn explicit_json_storage_persists_between_engines() {
     {
         let engine = Engine::open(EngineOptions {
             storage: StorageMode::Json { path: path.clone() },
+            ..EngineOptions::default()
         })
         .unwrap();
         let mut session = engine.session();
@@ -883,6 +898,7 @@ fn explicit_json_storage_persists_between_engines() {
 
     let engine = Engine::open(EngineOptions {
         storage: StorageMode::Json { path: path.clone() },
+        ..EngineOptions::default()
     })
     .unwrap();
     let mut session = engine.session();
@@ -909,6 +925,7 @@ fn explicit_btree_storage_persists_between_engines() {
     {
         let engine = Engine::open(EngineOptions {
             storage: StorageMode::BTree { path: path.clone() },
+            ..EngineOptions::default()
         })
         .unwrap();
         let mut session = engine.session();
@@ -924,6 +941,7 @@ fn explicit_btree_storage_persists_between_engines() {
 
     let engine = Engine::open(EngineOptions {
         storage: StorageMode::BTree { path: path.clone() },
+        ..EngineOptions::default()
     })
     .unwrap();
     let mut session = engine.session();
@@ -946,6 +964,7 @@ fn execute_select_returns_typed_rows() {
     let _guard = test_guard();
     let engine = Engine::open(EngineOptions {
         storage: StorageMode::Memory,
+        ..EngineOptions::default()
     })
     .unwrap();
     let mut session = engine.session();
@@ -977,6 +996,7 @@ fn explain_constant_select_returns_one_row_plan() {
     let _guard = test_guard();
     let engine = Engine::open(EngineOptions {
         storage: StorageMode::Memory,
+        ..EngineOptions::default()
     })
     .unwrap();
     let mut session = engine.session();
@@ -996,6 +1016,7 @@ fn execute_joined_select_returns_typed_rows() {
     let _guard = test_guard();
     let engine = Engine::open(EngineOptions {
         storage: StorageMode::Memory,
+        ..EngineOptions::default()
     })
     .unwrap();
     let mut session = engine.session();
@@ -1044,6 +1065,7 @@ fn execute_multi_join_returns_typed_rows() {
     let _guard = test_guard();
     let engine = Engine::open(EngineOptions {
         storage: StorageMode::Memory,
+        ..EngineOptions::default()
     })
     .unwrap();
     let mut session = engine.session();
@@ -1096,6 +1118,7 @@ fn execute_join_subquery_returns_typed_rows() {
     let _guard = test_guard();
     let engine = Engine::open(EngineOptions {
         storage: StorageMode::Memory,
+        ..EngineOptions::default()
     })
     .unwrap();
     let mut session = engine.session();
@@ -1146,6 +1169,7 @@ fn execute_lateral_join_returns_typed_rows() {
     let _guard = test_guard();
     let engine = Engine::open(EngineOptions {
         storage: StorageMode::Memory,
+        ..EngineOptions::default()
     })
     .unwrap();
     let mut session = engine.session();
@@ -1199,6 +1223,7 @@ fn execute_left_join_using_returns_typed_rows() {
     let _guard = test_guard();
     let engine = Engine::open(EngineOptions {
         storage: StorageMode::Memory,
+        ..EngineOptions::default()
     })
     .unwrap();
     let mut session = engine.session();
@@ -1245,6 +1270,7 @@ fn execute_join_using_multi_column_returns_typed_rows() {
     let _guard = test_guard();
     let engine = Engine::open(EngineOptions {
         storage: StorageMode::Memory,
+        ..EngineOptions::default()
     })
     .unwrap();
     let mut session = engine.session();
@@ -1293,6 +1319,7 @@ fn execute_right_join_returns_typed_rows() {
     let _guard = test_guard();
     let engine = Engine::open(EngineOptions {
         storage: StorageMode::Memory,
+        ..EngineOptions::default()
     })
     .unwrap();
     let mut session = engine.session();
@@ -1342,6 +1369,7 @@ fn execute_full_join_returns_typed_rows() {
     let _guard = test_guard();
     let engine = Engine::open(EngineOptions {
         storage: StorageMode::Memory,
+        ..EngineOptions::default()
     })
     .unwrap();
     let mut session = engine.session();
@@ -1397,6 +1425,7 @@ fn execute_aggregate_on_empty_table_returns_single_row() {
     let _guard = test_guard();
     let engine = Engine::open(EngineOptions {
         storage: StorageMode::Memory,
+        ..EngineOptions::default()
     })
     .unwrap();
     let mut session = engine.session();
@@ -1423,6 +1452,7 @@ fn execute_filtered_grouped_aggregate_returns_typed_rows() {
     let _guard = test_guard();
     let engine = Engine::open(EngineOptions {
         storage: StorageMode::Memory,
+        ..EngineOptions::default()
     })
     .unwrap();
     let mut session = engine.session();
@@ -1474,6 +1504,7 @@ fn execute_rollup_returns_typed_rows() {
     let _guard = test_guard();
     let engine = Engine::open(EngineOptions {
         storage: StorageMode::Memory,
+        ..EngineOptions::default()
     })
     .unwrap();
     let mut session = engine.session();
@@ -1549,6 +1580,7 @@ fn execute_window_row_number_returns_typed_rows() {
     let _guard = test_guard();
     let engine = Engine::open(EngineOptions {
         storage: StorageMode::Memory,
+        ..EngineOptions::default()
     })
     .unwrap();
     let mut session = engine.session();
@@ -1605,6 +1637,7 @@ fn execute_fetch_first_returns_typed_rows() {
     let _guard = test_guard();
     let engine = Engine::open(EngineOptions {
         storage: StorageMode::Memory,
+        ..EngineOptions::default()
     })
     .unwrap();
     let mut session = engine.session();
@@ -1645,6 +1678,7 @@ fn execute_fetch_with_ties_returns_typed_rows() {
     let _guard = test_guard();
     let engine = Engine::open(EngineOptions {
         storage: StorageMode::Memory,
+        ..EngineOptions::default()
     })
     .unwrap();
     let mut session = engine.session();
@@ -1684,6 +1718,7 @@ fn execute_distinct_on_returns_typed_rows() {
     let _guard = test_guard();
     let engine = Engine::open(EngineOptions {
         storage: StorageMode::Memory,
+        ..EngineOptions::default()
     })
     .unwrap();
     let mut session = engine.session();
@@ -1736,6 +1771,7 @@ fn execute_from_values_returns_typed_rows() {
     let _guard = test_guard();
     let engine = Engine::open(EngineOptions {
         storage: StorageMode::Memory,
+        ..EngineOptions::default()
     })
     .unwrap();
     let mut session = engine.session();
@@ -1771,6 +1807,7 @@ fn explain_from_values_returns_plan() {
     let _guard = test_guard();
     let engine = Engine::open(EngineOptions {
         storage: StorageMode::Memory,
+        ..EngineOptions::default()
     })
     .unwrap();
     let mut session = engine.session();
@@ -1798,6 +1835,7 @@ fn explain_from_subquery_returns_plan() {
     let _guard = test_guard();
     let engine = Engine::open(EngineOptions {
         storage: StorageMode::Memory,
+        ..EngineOptions::default()
     })
     .unwrap();
     let mut session = engine.session();
@@ -1833,6 +1871,7 @@ fn explain_join_subquery_returns_plan() {
     let _guard = test_guard();
     let engine = Engine::open(EngineOptions {
         storage: StorageMode::Memory,
+        ..EngineOptions::default()
     })
     .unwrap();
     let mut session = engine.session();
@@ -1877,6 +1916,7 @@ fn explain_lateral_join_returns_plan() {
     let _guard = test_guard();
     let engine = Engine::open(EngineOptions {
         storage: StorageMode::Memory,
+        ..EngineOptions::default()
     })
     .unwrap();
     let mut session = engine.session();
@@ -1919,6 +1959,7 @@ fn explain_cte_returns_plan() {
     let _guard = test_guard();
     let engine = Engine::open(EngineOptions {
         storage: StorageMode::Memory,
+        ..EngineOptions::default()
     })
     .unwrap();
     let mut session = engine.session();
@@ -1953,6 +1994,7 @@ fn explain_view_returns_view_scan_plan() {
     let _guard = test_guard();
     let engine = Engine::open(EngineOptions {
         storage: StorageMode::Memory,
+        ..EngineOptions::default()
     })
     .unwrap();
     let mut session = engine.session();
@@ -1984,6 +2026,7 @@ fn explain_recursive_cte_returns_recursive_cte_scan_plan() {
     let _guard = test_guard();
     let engine = Engine::open(EngineOptions {
         storage: StorageMode::Memory,
+        ..EngineOptions::default()
     })
     .unwrap();
     let mut session = engine.session();
@@ -2012,6 +2055,7 @@ fn explain_rollup_returns_plan() {
     let _guard = test_guard();
     let engine = Engine::open(EngineOptions {
         storage: StorageMode::Memory,
+        ..EngineOptions::default()
     })
     .unwrap();
     let mut session = engine.session();
@@ -2046,6 +2090,7 @@ fn execute_generate_series_returns_typed_rows() {
     let _guard = test_guard();
     let engine = Engine::open(EngineOptions {
         storage: StorageMode::Memory,
+        ..EngineOptions::default()
     })
     .unwrap();
     let mut session = engine.session();
@@ -2076,6 +2121,7 @@ fn explain_generate_series_returns_function_scan_plan() {
     let _guard = test_guard();
     let engine = Engine::open(EngineOptions {
         storage: StorageMode::Memory,
+        ..EngineOptions::default()
     })
     .unwrap();
     let mut session = engine.session();
@@ -2097,6 +2143,7 @@ fn explain_distinct_on_returns_plan() {
     let _guard = test_guard();
     let engine = Engine::open(EngineOptions {
         storage: StorageMode::Memory,
+        ..EngineOptions::default()
     })
     .unwrap();
     let mut session = engine.session();
@@ -2134,6 +2181,7 @@ fn explain_natural_join_returns_plan() {
     let _guard = test_guard();
     let engine = Engine::open(EngineOptions {
         storage: StorageMode::Memory,
+        ..EngineOptions::default()
     })
     .unwrap();
     let mut session = engine.session();
@@ -2175,6 +2223,7 @@ fn explain_full_join_returns_plan() {
     let _guard = test_guard();
     let engine = Engine::open(EngineOptions {
         storage: StorageMode::Memory,
+        ..EngineOptions::default()
     })
     .unwrap();
     let mut session = engine.session();
@@ -2216,6 +2265,7 @@ fn explain_multi_join_returns_plan() {
     let _guard = test_guard();
     let engine = Engine::open(EngineOptions {
         storage: StorageMode::Memory,
+        ..EngineOptions::default()
     })
     .unwrap();
     let mut session = engine.session();
@@ -2255,6 +2305,7 @@ fn execute_update_returns_command_tag_and_affected_rows() {
     let _guard = test_guard();
     let engine = Engine::open(EngineOptions {
         storage: StorageMode::Memory,
+        ..EngineOptions::default()
     })
     .unwrap();
     let mut session = engine.session();
@@ -2284,6 +2335,7 @@ fn execute_script_returns_typed_results() {
     let _guard = test_guard();
     let engine = Engine::open(EngineOptions {
         storage: StorageMode::Memory,
+        ..EngineOptions::default()
     })
     .unwrap();
     let mut session = engine.session();
@@ -2332,6 +2384,7 @@ fn explain_returns_same_plan_as_planner() {
     let _guard = test_guard();
     let engine = Engine::open(EngineOptions {
         storage: StorageMode::Memory,
+        ..EngineOptions::default()
     })
     .unwrap();
     let mut session = engine.session();
@@ -2369,10 +2422,12 @@ fn explain_returns_same_plan_as_planner() {
 fn separate_engines_do_not_share_state() {
     let engine_a = Engine::open(EngineOptions {
         storage: StorageMode::Memory,
+        ..EngineOptions::default()
     })
     .unwrap();
     let engine_b = Engine::open(EngineOptions {
         storage: StorageMode::Memory,
+        ..EngineOptions::default()
     })
     .unwrap();
 
@@ -2421,3 +2476,108 @@ fn separate_engines_do_not_share_state() {
         }
     });
 }
+
+/// Keeps snapshots in memory, like a stand-in for an object store, and can
+/// be told to fail saves.
+#[derive(Default)]
+struct SnapshotStore {
+    snapshot: Mutex<Option<String>>,
+    saves: std::sync::atomic::AtomicUsize,
+    fail_saves: std::sync::atomic::AtomicBool,
+}
+
+impl StorageEngine for SnapshotStore {
+    fn load(&self) -> Result<Database, RustqlError> {
+        match &*self.snapshot.lock().unwrap() {
+            Some(snapshot) => Database::from_json(snapshot),
+            None => Ok(Database::new()),
+        }
+    }
+
+    fn save(&self, db: &Database) -> Result<(), RustqlError> {
+        if self.fail_saves.load(std::sync::atomic::Ordering::SeqCst) {
+            return Err(RustqlError::StorageError(
+                "injected save failure".to_string(),
+            ));
+        }
+        *self.snapshot.lock().unwrap() = Some(db.to_json()?);
+        self.saves.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+        Ok(())
+    }
+}
+
+#[test]
+fn custom_storage_engine_persists_commits_and_reports_failures() {
+    let store = Arc::new(SnapshotStore::default());
+    let open = || {
+        Engine::open(EngineOptions {
+            storage: StorageMode::Custom(Arc::clone(&store) as Arc<dyn StorageEngine>),
+            ..EngineOptions::default()
+        })
+        .unwrap()
+    };
+
+    let engine = open();
+    engine
+        .session()
+        .execute_script(
+            "CREATE TABLE notes (id INTEGER PRIMARY KEY, body TEXT);
+             INSERT INTO notes VALUES (1, 'kept');",
+        )
+        .unwrap();
+    assert_eq!(store.saves.load(std::sync::atomic::Ordering::SeqCst), 2);
+
+    store
+        .fail_saves
+        .store(true, std::sync::atomic::Ordering::SeqCst);
+    let error = engine
+        .session()
+        .execute_one("INSERT INTO notes VALUES (2, 'lost')")
+        .unwrap_err();
+    assert!(matches!(error, RustqlError::StorageError(message) if message.contains("injected")));
+    store
+        .fail_saves
+        .store(false, std::sync::atomic::Ordering::SeqCst);
+    drop(engine);
+
+    let engine = open();
+    let QueryResult::Rows(rows) = engine
+        .session()
+        .execute_one("SELECT id, body FROM notes ORDER BY id")
+        .unwrap()
+    else {
+        panic!("expected rows");
+    };
+    assert_eq!(
+        rows.rows,
+        vec![vec![
+            ast::Value::Integer(1),
+            ast::Value::Text("kept".to_string())
+        ]]
+    );
+    assert!(format!("{:?}", StorageMode::Custom(store)).starts_with("Custom"));
+}
+
+#[test]
+fn database_snapshots_round_trip_through_json() {
+    let engine = Engine::in_memory().unwrap();
+    engine
+        .session()
+        .execute_script(
+            "CREATE TABLE items (id INTEGER PRIMARY KEY, name TEXT);
+             CREATE INDEX idx_items_name ON items (name);
+             CREATE VIEW named AS SELECT name FROM items;
+             INSERT INTO items VALUES (1, 'a'), (2, 'b');",
+        )
+        .unwrap();
+    let snapshot = engine.snapshot_database().to_json().unwrap();
+
+    let copy = Database::from_json(&snapshot).unwrap();
+    assert_eq!(copy.tables["items"].rows.len(), 2);
+    assert!(copy.indexes.contains_key("idx_items_name"));
+    assert!(copy.views.contains_key("named"));
+    assert!(matches!(
+        Database::from_json("{ not json"),
+        Err(RustqlError::StorageError(_))
+    ));
+}
@@ -0,0 +1,293 @@
+use rustql::{Engine, EngineOptions, RustqlError, StorageMode};
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output, Stdio};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// Runs `sql` through the CLI in a separate process against the file at
+/// `path`, waiting at most `busy_timeout_ms` for its lock.
+fn run_cli(storage: &str, path: &Path, busy_timeout_ms: u64, sql: &str) -> Output {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_rustql"))
+        .env("RUSTQL_STORAGE", storage)
+        .env("RUSTQL_STORAGE_PATH", path)
+        .env("RUSTQL_BUSY_TIMEOUT", busy_timeout_ms.to_string())
+        .env_remove("RUSTQL_ENCRYPTION_KEY")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(sql.as_bytes())
+        .unwrap();
+    child.wait_with_output().unwrap()
+}
+
+fn assert_locked(output: &Output) {
+    assert!(!output.status.success(), "the second process got the lock");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("is locked by another process"),
+        "unexpected error: {stderr}"
+    );
+}
+
+fn assert_succeeded(output: &Output) {
+    assert!(
+        output.status.success(),
+        "the second process failed: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+}
+
+fn open_engine(storage: StorageMode) -> Engine {
+    Engine::open(EngineOptions {
+        storage,
+        ..EngineOptions::default()
+    })
+    .unwrap()
+}
+
+fn create_table(storage: StorageMode) {
+    let engine = open_engine(storage);
+    let mut session = engine.session();
+    session
+        .execute_script("CREATE TABLE t (id INTEGER); INSERT INTO t VALUES (1)")
+        .unwrap();
+}
+
+fn cleanup_storage_files(path: &Path) {
+    fs::remove_file(path).ok();
+    for suffix in [".wal", ".lock"] {
+        let mut sidecar = path.as_os_str().to_os_string();
+        sidecar.push(suffix);
+        fs::remove_file(PathBuf::from(sidecar)).ok();
+    }
+}
+
+fn unique_temp_path(extension: &str) -> PathBuf {
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_nanos();
+    std::env::temp_dir().join(format!(
+        "rustql-locking-{}-{}.{}",
+        std::process::id(),
+        timestamp,
+        extension
+    ))
+}
+
+#[test]
+fn btree_writer_locks_out_other_processes() {
+    let path = unique_temp_path("db");
+    cleanup_storage_files(&path);
+    create_table(StorageMode::BTree { path: path.clone() });
+
+    let engine = open_engine(StorageMode::BTree { path: path.clone() });
+    engine
+        .session()
+        .execute_one("INSERT INTO t VALUES (2)")
+        .unwrap();
+
+    assert_locked(&run_cli("btree", &path, 50, "SELECT * FROM t"));
+
+    drop(engine);
+    let output = run_cli("btree", &path, 50, "SELECT COUNT(*) FROM t");
+    assert_succeeded(&output);
+    assert!(String::from_utf8_lossy(&output.stdout).contains('2'));
+    cleanup_storage_files(&path);
+}
+
+#[test]
+fn one_process_writes_a_file_at_a_time() {
+    let path = unique_temp_path("db");
+    cleanup_storage_files(&path);
+    create_table(StorageMode::BTree { path: path.clone() });
+
+    // The writer has committed and is idle, but it keeps the file until it
+    // is dropped.
+    let writer = open_engine(StorageMode::BTree { path: path.clone() });
+    writer
+        .session()
+        .execute_one("INSERT INTO t VALUES (2)")
+        .unwrap();
+    assert_locked(&run_cli("btree", &path, 50, "SELECT * FROM t"));
+    assert_locked(&run_cli("btree", &path, 50, "INSERT INTO t VALUES (3)"));
+
+    drop(writer);
+    assert_succeeded(&run_cli("btree", &path, 50, "INSERT INTO t VALUES (3)"));
+    let output = run_cli("btree", &path, 50, "SELECT COUNT(*) FROM t");
+    assert_succeeded(&output);
+    assert!(String::from_utf8_lossy(&output.stdout).contains('3'));
+    cleanup_storage_files(&path);
+}
+
+#[test]
+fn btree_readers_share_the_file_but_keep_writers_out() {
+    let path = unique_temp_path("db");
+    cleanup_storage_files(&path);
+    create_table(StorageMode::BTree { path: path.clone() });
+
+    let engine = open_engine(StorageMode::BTree { path: path.clone() });
+    engine.session().execute_one("SELECT * FROM t").unwrap();
+
+    assert_succeeded(&run_cli("btree", &path, 50, "SELECT * FROM t"));
+    assert_locked(&run_cli("btree", &path, 50, "INSERT INTO t VALUES (3)"));
+
+    // The reader in this process could not write now either, but it can
+    // once the other process is gone.
+    engine
+        .session()
+        .execute_one("INSERT INTO t VALUES (3)")
+        .unwrap();
+    drop(engine);
+    cleanup_storage_files(&path);
+}
+
+#[test]
+fn json_writer_locks_out_other_processes() {
+    let path = unique_temp_path("json");
+    cleanup_storage_files(&path);
+    create_table(StorageMode::Json { path: path.clone() });
+
+    let engine = open_engine(StorageMode::Json { path: path.clone() });
+    assert_succeeded(&run_cli("json", &path, 50, "SELECT * FROM t"));
+    engine
+        .session()
+        .execute_one("INSERT INTO t VALUES (2)")
+        .unwrap();
+    assert_locked(&run_cli("json", &path, 50, "SELECT * FROM t"));
+
+    drop(engine);
+    assert_succeeded(&run_cli("json", &path, 50, "SELECT * FROM t"));
+    cleanup_storage_files(&path);
+}
+
+#[test]
+fn busy_process_waits_for_the_lock_to_be_released() {
+    let path = unique_temp_path("db");
+    cleanup_storage_files(&path);
+    create_table(StorageMode::BTree { path: path.clone() });
+
+    let engine = open_engine(StorageMode::BTree { path: path.clone() });
+    engine
+        .session()
+        .execute_one("INSERT INTO t VALUES (2)")
+        .unwrap();
+
+    let waiting = {
+        let path = path.clone();
+        std::thread::spawn(move || run_cli("btree", &path, 30_000, "INSERT INTO t VALUES (3)"))
+    };
+    std::thread::sleep(Duration::from_millis(300));
+    drop(engine);
+    assert_succeeded(&waiting.join().unwrap());
+
+    let engine = open_engine(StorageMode::BTree { path: path.clone() });
+    let rows = engine
+        .session()
+        .execute_one("SELECT COUNT(*) FROM t")
+        .unwrap();
+    assert!(format!("{rows:?}").contains("Integer(3)"));
+    drop(engine);
+    cleanup_storage_files(&path);
+}
+
+#[test]
+fn engines_in_one_process_share_the_lock() {
+    let path = unique_temp_path("db");
+    cleanup_storage_files(&path);
+    create_table(StorageMode::BTree { path: path.clone() });
+
+    let first = open_engine(StorageMode::BTree { path: path.clone() });
+    let second =
+        Engine::open(EngineOptions::btree(&path).with_busy_timeout(Duration::ZERO)).unwrap();
+    first
+        .session()
+        .execute_one("INSERT INTO t VALUES (2)")
+        .unwrap();
+    second
+        .session()
+        .execute_one("INSERT INTO t VALUES (3)")
+        .unwrap();
+
+    drop((first, second));
+    cleanup_storage_files(&path);
+}
+
+#[test]
+fn write_fails_with_database_locked_while_another_process_reads() {
+    let path = unique_temp_path("db");
+    cleanup_storage_files(&path);
+    create_table(StorageMode::BTree { path: path.clone() });
+
+    // The CLI opens the file before it reads its script, so it holds a
+    // shared lock for as long as its input stays open.
+    let mut reader = Command::new(env!("CARGO_BIN_EXE_rustql"))
+        .env("RUSTQL_STORAGE", "btree")
+        .env("RUSTQL_STORAGE_PATH", &path)
+        .env("RUSTQL_BUSY_TIMEOUT", "30000")
+        .env_remove("RUSTQL_ENCRYPTION_KEY")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+
+    // Each probe that still gets the lock leaves a redo log the reader folds
+    // in when it opens, so opening may briefly find the file busy too.
+    let deadline = Instant::now() + Duration::from_secs(30);
+    let err = loop {
+        assert!(Instant::now() < deadline, "the reader never started");
+        let options = EngineOptions::btree(&path).with_busy_timeout(Duration::ZERO);
+        match Engine::open(options).map(|engine| {
+            engine
+                .session()
+                .execute_one("INSERT INTO t VALUES (2)")
+                .err()
+        }) {
+            Ok(Some(err)) => break err,
+            Ok(None) | Err(RustqlError::DatabaseLocked(_)) => {
+                std::thread::sleep(Duration::from_millis(20))
+            }
+            Err(err) => panic!("unexpected error: {err:?}"),
+        }
+    };
+    assert!(
+        matches!(&err, RustqlError::DatabaseLocked(locked) if locked == &path.display().to_string()),
+        "unexpected error: {err:?}"
+    );
+    assert!(err.to_string().ends_with("is locked by another process"));
+
+    reader
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(b"SELECT COUNT(*) FROM t")
+        .unwrap();
+    assert_succeeded(&reader.wait_with_output().unwrap());
+    cleanup_storage_files(&path);
+}
+
+#[test]
+fn busy_timeout_must_be_milliseconds() {
+    let path = unique_temp_path("db");
+    let output = Command::new(env!("CARGO_BIN_EXE_rustql"))
+        .env("RUSTQL_STORAGE", "btree")
+        .env("RUSTQL_STORAGE_PATH", &path)
+        .env("RUSTQL_BUSY_TIMEOUT", "soon")
+        .stdin(Stdio::null())
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+    assert!(
+        String::from_utf8_lossy(&output.stderr)
+            .contains("RUSTQL_BUSY_TIMEOUT must be a number of milliseconds")
+    );
+    assert!(!path.exists());
+}
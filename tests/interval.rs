@@ -0,0 +1,364 @@
+mod common;
+use common::*;
+use rustql::{Engine, EngineOptions, Interval, RustqlError, Value};
+use std::path::Path;
+
+fn interval(text: &str) -> Value {
+    Value::Interval(Interval::parse(text).unwrap())
+}
+
+fn datetime(value: &str) -> Value {
+    Value::DateTime(value.to_string())
+}
+
+#[test]
+fn interval_literals_parse_and_display() {
+    let engine = memory_engine();
+
+    assert_eq!(
+        rows(
+            &engine,
+            "SELECT CAST(INTERVAL '3 days 4 hours' AS TEXT), \
+             CAST(INTERVAL '1 year 2 months -5 minutes' AS TEXT), \
+             CAST(INTERVAL '1.5 days' AS TEXT), \
+             CAST(INTERVAL '2 weeks ago' AS TEXT), \
+             CAST(INTERVAL '01:30:00.25' AS TEXT), \
+             CAST(INTERVAL '90' MINUTE AS TEXT)"
+        ),
+        vec![vec![
+            text("3 days 04:00:00"),
+            text("1 year 2 mons -00:05:00"),
+            text("1 day 12:00:00"),
+            text("-14 days"),
+            text("01:30:00.25"),
+            text("01:30:00"),
+        ]]
+    );
+    assert_eq!(
+        scalar(&engine, "SELECT CAST('1 mon 1 day' AS INTERVAL)"),
+        interval("1 month 1 day")
+    );
+    assert!(
+        engine
+            .session()
+            .execute_one("SELECT INTERVAL '3 fortnights'")
+            .is_err()
+    );
+    assert!(
+        engine
+            .session()
+            .execute_one("SELECT CAST(5 AS INTERVAL)")
+            .is_err()
+    );
+}
+
+#[test]
+fn dates_and_timestamps_combine_with_intervals() {
+    let engine = memory_engine();
+    let mut session = engine.session();
+    session
+        .execute_one("CREATE TABLE moments (d DATE, t TIME, ts DATETIME)")
+        .unwrap();
+    session
+        .execute_one("INSERT INTO moments VALUES ('2024-01-31', '23:30:00', '2024-03-10 08:00:00')")
+        .unwrap();
+
+    assert_eq!(
+        rows(
+            &engine,
+            "SELECT d + INTERVAL '1 month', ts - INTERVAL '1 day 9 hours', \
+             INTERVAL '90 minutes' + ts, d + 30, d - CAST('2024-01-01' AS DATE), \
+             t + INTERVAL '45 minutes' FROM moments"
+        ),
+        vec![vec![
+            datetime("2024-02-29 00:00:00"),
+            datetime("2024-03-08 23:00:00"),
+            datetime("2024-03-10 09:30:00"),
+            Value::Date("2024-03-01".to_string()),
+            Value::Integer(30),
+            Value::Time("00:15:00".to_string()),
+        ]]
+    );
+    assert_eq!(
+        scalar(
+            &engine,
+            "SELECT ts - CAST('2024-03-07 18:30:00' AS DATETIME) FROM moments"
+        ),
+        interval("2 days 13:30:00")
+    );
+    assert_eq!(
+        rows(
+            &engine,
+            "SELECT INTERVAL '1 day' * 3, INTERVAL '1 hour' * 1.5, \
+             INTERVAL '1 mon' / 3, -INTERVAL '2 days', \
+             INTERVAL '1 day' - INTERVAL '1 hour'"
+        ),
+        vec![vec![
+            interval("3 days"),
+            interval("01:30:00"),
+            interval("10 days"),
+            interval("-2 days"),
+            interval("1 day -01:00:00"),
+        ]]
+    );
+
+    assert!(session.execute_one("SELECT d * 2 FROM moments").is_err());
+    assert!(session.execute_one("SELECT INTERVAL '1 day' + 1").is_err());
+    assert!(session.execute_one("SELECT INTERVAL '1 day' / 0").is_err());
+}
+
+#[test]
+fn intervals_compare_by_length() {
+    let engine = memory_engine();
+    let mut session = engine.session();
+    session
+        .execute_one("CREATE TABLE waits (id INTEGER, wait INTERVAL)")
+        .unwrap();
+    session
+        .execute_one(
+            "INSERT INTO waits VALUES (1, '1 mon'), (2, '29 days 23 hours'), \
+             (3, INTERVAL '30 days'), (4, '-1 hour'), (5, NULL)",
+        )
+        .unwrap();
+
+    assert_eq!(
+        scalar(&engine, "SELECT INTERVAL '1 mon' = INTERVAL '30 days'"),
+        Value::Boolean(true)
+    );
+    assert_eq!(
+        scalar(&engine, "SELECT INTERVAL '25 hours' > INTERVAL '1 day'"),
+        Value::Boolean(true)
+    );
+    assert_eq!(
+        rows(&engine, "SELECT id FROM waits ORDER BY wait, id"),
+        vec![
+            vec![Value::Integer(4)],
+            vec![Value::Integer(2)],
+            vec![Value::Integer(1)],
+            vec![Value::Integer(3)],
+            vec![Value::Integer(5)],
+        ]
+    );
+    assert_eq!(
+        rows(
+            &engine,
+            "SELECT id FROM waits WHERE wait = INTERVAL '720 hours' ORDER BY id"
+        ),
+        vec![vec![Value::Integer(1)], vec![Value::Integer(3)]]
+    );
+    assert_eq!(
+        scalar(&engine, "SELECT MAX(wait) FROM waits"),
+        interval("1 mon")
+    );
+    assert_eq!(rows(&engine, "DESCRIBE waits")[1][1], text("INTERVAL"));
+    assert!(
+        session
+            .execute_one("SELECT id FROM waits WHERE wait > 5")
+            .is_err()
+    );
+}
+
+#[test]
+fn justify_functions_normalize_fields() {
+    let engine = memory_engine();
+
+    assert_eq!(
+        rows(
+            &engine,
+            "SELECT JUSTIFY_HOURS(INTERVAL '50 hours'), \
+             JUSTIFY_DAYS(INTERVAL '65 days'), \
+             JUSTIFY_INTERVAL(INTERVAL '1 mon -1 hour'), \
+             JUSTIFY_HOURS(NULL)"
+        ),
+        vec![vec![
+            interval("2 days 02:00:00"),
+            interval("2 mons 5 days"),
+            interval("29 days 23:00:00"),
+            Value::Null,
+        ]]
+    );
+    assert_eq!(
+        scalar(
+            &engine,
+            "SELECT CAST(JUSTIFY_DAYS(INTERVAL '65 days') AS TEXT)"
+        ),
+        text("2 mons 5 days")
+    );
+    assert!(
+        engine
+            .session()
+            .execute_one("SELECT JUSTIFY_DAYS(5)")
+            .is_err()
+    );
+}
+
+#[test]
+fn age_counts_calendar_fields() {
+    let engine = memory_engine();
+    let mut session = engine.session();
+    session
+        .execute_one("CREATE TABLE people (name TEXT, age INTEGER, born DATE)")
+        .unwrap();
+    session
+        .execute_one("INSERT INTO people VALUES ('ada', 36, '1815-12-10')")
+        .unwrap();
+
+    assert_eq!(
+        rows(
+            &engine,
+            "SELECT AGE(CAST('1852-11-27' AS DATE), born), age FROM people"
+        ),
+        vec![vec![
+            interval("36 years 11 mons 17 days"),
+            Value::Integer(36)
+        ]]
+    );
+    assert_eq!(
+        scalar(
+            &engine,
+            "SELECT AGE(CAST('2024-03-01 10:00:00' AS DATETIME), CAST('2024-01-31 12:00:00' AS DATETIME))"
+        ),
+        interval("1 mon 22:00:00")
+    );
+    assert_eq!(
+        scalar(
+            &engine,
+            "SELECT AGE(CAST('2024-01-31' AS DATE), CAST('2024-03-01' AS DATE))"
+        ),
+        interval("-1 mon -1 day")
+    );
+    assert_eq!(
+        scalar(&engine, "SELECT AGE(NOW()) >= INTERVAL '0 days'"),
+        Value::Boolean(false)
+    );
+    assert_eq!(
+        scalar(
+            &engine,
+            "SELECT AGE(CAST('2000-01-01' AS DATE)) > INTERVAL '20 years'"
+        ),
+        Value::Boolean(true)
+    );
+}
+
+#[test]
+fn generate_series_steps_through_dates() {
+    let engine = memory_engine();
+
+    assert_eq!(
+        rows(
+            &engine,
+            "SELECT * FROM generate_series(CAST('2024-01-31' AS DATE), \
+             CAST('2024-04-30' AS DATE), INTERVAL '1 month')"
+        ),
+        vec![
+            vec![datetime("2024-01-31 00:00:00")],
+            vec![datetime("2024-02-29 00:00:00")],
+            vec![datetime("2024-03-31 00:00:00")],
+            vec![datetime("2024-04-30 00:00:00")],
+        ]
+    );
+    assert_eq!(
+        rows(
+            &engine,
+            "SELECT d FROM generate_series(CAST('2024-01-01 12:00:00' AS DATETIME), \
+             CAST('2024-01-01 06:00:00' AS DATETIME), INTERVAL '-150 minutes') AS d \
+             WHERE d > CAST('2024-01-01 07:00:00' AS DATETIME)"
+        ),
+        vec![
+            vec![datetime("2024-01-01 12:00:00")],
+            vec![datetime("2024-01-01 09:30:00")],
+        ]
+    );
+    assert_eq!(
+        scalar(
+            &engine,
+            "SELECT COUNT(*) FROM generate_series(CAST('2024-01-01' AS DATE), \
+             CAST('2024-12-31' AS DATE), INTERVAL '1 week')"
+        ),
+        Value::Integer(53)
+    );
+
+    let mut session = engine.session();
+    assert!(
+        session
+            .execute_one(
+                "SELECT * FROM generate_series(CAST('2024-01-01' AS DATE), CAST('2024-02-01' AS DATE))"
+            )
+            .is_err()
+    );
+    assert!(
+        session
+            .execute_one(
+                "SELECT * FROM generate_series(CAST('2024-01-01' AS DATE), CAST('2024-02-01' AS DATE), 1)"
+            )
+            .is_err()
+    );
+    let err = session
+        .execute_one(
+            "SELECT * FROM generate_series(CAST('2024-01-01' AS DATE), \
+             CAST('2024-02-01' AS DATE), INTERVAL '0 days')",
+        )
+        .unwrap_err();
+    assert!(
+        matches!(&err, RustqlError::TypeMismatch(message) if message.contains("cannot be zero")),
+        "got: {err:?}"
+    );
+}
+
+fn check_intervals_survive_reopen(path: &Path, options: impl Fn() -> EngineOptions) {
+    {
+        let engine = Engine::open(options()).unwrap();
+        let mut session = engine.session();
+        session
+            .execute_one("CREATE TABLE jobs (id INTEGER PRIMARY KEY, period INTERVAL)")
+            .unwrap();
+        session
+            .execute_one("CREATE INDEX idx_period ON jobs (period)")
+            .unwrap();
+        session
+            .execute_one(
+                "INSERT INTO jobs VALUES (1, '1 mon 2 days 03:04:05.5'), (2, '-90 minutes'), (3, NULL)",
+            )
+            .unwrap();
+    }
+
+    let engine = Engine::open(options()).unwrap();
+    assert_eq!(
+        rows(&engine, "SELECT period FROM jobs ORDER BY id"),
+        vec![
+            vec![interval("1 mon 2 days 03:04:05.5")],
+            vec![interval("-01:30:00")],
+            vec![Value::Null],
+        ]
+    );
+    assert_eq!(
+        scalar(
+            &engine,
+            "SELECT CAST(period AS TEXT) FROM jobs WHERE id = 1"
+        ),
+        text("1 mon 2 days 03:04:05.5")
+    );
+    assert_eq!(
+        scalar(
+            &engine,
+            "SELECT id FROM jobs WHERE period = INTERVAL '-1.5 hours'"
+        ),
+        Value::Integer(2)
+    );
+    drop(engine);
+    cleanup_storage_files(path);
+}
+
+#[test]
+fn intervals_survive_reopening_a_btree_file() {
+    let path = unique_temp_path("btree.db");
+    cleanup_storage_files(&path);
+    check_intervals_survive_reopen(&path, || EngineOptions::btree(&path));
+}
+
+#[test]
+fn intervals_survive_reopening_a_json_file() {
+    let path = unique_temp_path("json.db");
+    cleanup_storage_files(&path);
+    check_intervals_survive_reopen(&path, || EngineOptions::json(&path));
+}
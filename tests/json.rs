@@ -0,0 +1,375 @@
+mod common;
+use common::*;
+use rustql::{Engine, EngineOptions, QueryResult, Value};
+use std::path::Path;
+
+fn json(value: &str) -> Value {
+    Value::Json(value.to_string())
+}
+
+fn docs_engine() -> Engine {
+    let engine = memory_engine();
+    let mut session = engine.session();
+    session
+        .execute_one("CREATE TABLE docs (id INTEGER, doc JSON)")
+        .unwrap();
+    session
+        .execute_one(
+            "INSERT INTO docs VALUES \
+             (1, '{\"name\": \"ada\", \"tags\": [\"math\", \"code\"], \"meta\": {\"age\": 36}}'), \
+             (2, '{\"name\": \"alan\", \"tags\": [\"code\"], \"meta\": {\"age\": 41.0}}'), \
+             (3, NULL)",
+        )
+        .unwrap();
+    engine
+}
+
+#[test]
+fn json_columns_validate_and_normalize_documents() {
+    let engine = docs_engine();
+    let mut session = engine.session();
+
+    assert_eq!(
+        scalar(&engine, "SELECT doc FROM docs WHERE id = 2"),
+        json(r#"{"meta":{"age":41},"name":"alan","tags":["code"]}"#)
+    );
+    assert_eq!(
+        scalar(
+            &engine,
+            "SELECT id FROM docs WHERE doc = CAST('{\"tags\":[\"code\"],\"name\":\"alan\",\"meta\":{\"age\":41}}' AS JSON)"
+        ),
+        Value::Integer(2)
+    );
+    assert_eq!(rows(&engine, "DESCRIBE docs")[1][1], text("JSON"));
+
+    let err = session
+        .execute_one("INSERT INTO docs VALUES (4, '{\"name\": ')")
+        .unwrap_err();
+    assert!(err.to_string().contains("Invalid JSON"), "{err:?}");
+    assert!(session.execute_one("SELECT doc < doc FROM docs").is_err());
+}
+
+#[test]
+fn arrow_operators_navigate_documents() {
+    let engine = docs_engine();
+
+    assert_eq!(
+        rows(
+            &engine,
+            "SELECT doc -> 'name', doc ->> 'name' FROM docs ORDER BY id"
+        ),
+        vec![
+            vec![json(r#""ada""#), text("ada")],
+            vec![json(r#""alan""#), text("alan")],
+            vec![Value::Null, Value::Null],
+        ]
+    );
+    assert_eq!(
+        scalar(
+            &engine,
+            "SELECT doc -> 'tags' ->> -1 FROM docs WHERE id = 1"
+        ),
+        text("code")
+    );
+    assert_eq!(
+        scalar(
+            &engine,
+            "SELECT doc -> 'meta' -> 'age' FROM docs WHERE id = 1"
+        ),
+        json("36")
+    );
+    assert_eq!(
+        scalar(&engine, "SELECT doc #> '{tags,0}' FROM docs WHERE id = 1"),
+        json(r#""math""#)
+    );
+    assert_eq!(
+        scalar(&engine, "SELECT doc -> 'missing' FROM docs WHERE id = 1"),
+        Value::Null
+    );
+    assert_eq!(
+        rows(&engine, "SELECT id FROM docs WHERE doc ->> 'name' = 'alan'"),
+        vec![vec![Value::Integer(2)]]
+    );
+    assert_eq!(
+        rows(
+            &engine,
+            "SELECT id FROM docs WHERE doc @> '{\"tags\": [\"code\"]}' ORDER BY id"
+        ),
+        vec![vec![Value::Integer(1)], vec![Value::Integer(2)]]
+    );
+    assert_eq!(
+        rows(
+            &engine,
+            "SELECT id FROM docs WHERE doc @> '{\"tags\": [\"math\"], \"meta\": {\"age\": 36}}'"
+        ),
+        vec![vec![Value::Integer(1)]]
+    );
+    assert_eq!(
+        rows(
+            &engine,
+            "SELECT id FROM docs WHERE CAST(doc #> '{meta,age}' AS INTEGER) > 40"
+        ),
+        vec![vec![Value::Integer(2)]]
+    );
+}
+
+#[test]
+fn json_functions_read_and_build_documents() {
+    let engine = docs_engine();
+    let mut session = engine.session();
+
+    assert_eq!(
+        scalar(
+            &engine,
+            "SELECT JSON_EXTRACT(doc, '$.tags[1]') FROM docs WHERE id = 1"
+        ),
+        json(r#""code""#)
+    );
+    assert_eq!(
+        scalar(
+            &engine,
+            "SELECT JSON_EXTRACT(doc, '$.meta.height') FROM docs WHERE id = 1"
+        ),
+        Value::Null
+    );
+    assert_eq!(
+        scalar(
+            &engine,
+            "SELECT JSON_SET(doc, '$.meta.age', 37) FROM docs WHERE id = 1"
+        ),
+        json(r#"{"meta":{"age":37},"name":"ada","tags":["math","code"]}"#)
+    );
+    assert_eq!(
+        scalar(&engine, "SELECT JSON_SET('[1, 2]', '$[2]', 'three')"),
+        json(r#"[1,2,"three"]"#)
+    );
+    assert_eq!(
+        scalar(
+            &engine,
+            "SELECT JSON_ARRAY_LENGTH(doc -> 'tags') FROM docs WHERE id = 1"
+        ),
+        Value::Integer(2)
+    );
+    assert_eq!(
+        rows(
+            &engine,
+            "SELECT JSON_TYPEOF(doc -> 'meta'), JSON_TYPEOF(doc -> 'name') FROM docs WHERE id = 1"
+        ),
+        vec![vec![text("object"), text("string")]]
+    );
+    assert_eq!(
+        scalar(
+            &engine,
+            "SELECT JSON_BUILD_OBJECT('id', 7, 'ok', TRUE, 'tags', doc -> 'tags') FROM docs WHERE id = 2"
+        ),
+        json(r#"{"id":7,"ok":true,"tags":["code"]}"#)
+    );
+
+    session
+        .execute_one("UPDATE docs SET doc = JSON_SET(doc, '$.name', 'grace') WHERE id = 2")
+        .unwrap();
+    assert_eq!(
+        scalar(&engine, "SELECT doc ->> 'name' FROM docs WHERE id = 2"),
+        text("grace")
+    );
+
+    let err = session
+        .execute_one("SELECT JSON_ARRAY_LENGTH('{}')")
+        .unwrap_err();
+    assert!(err.to_string().contains("requires an array"), "{err:?}");
+    assert!(
+        session
+            .execute_one("SELECT JSON_EXTRACT('{}', 'a.b')")
+            .is_err()
+    );
+}
+
+#[test]
+fn table_functions_expand_documents_into_rows() {
+    let engine = memory_engine();
+
+    assert_eq!(
+        rows(
+            &engine,
+            "SELECT key, value FROM json_each('{\"b\": [1], \"a\": \"x\"}') ORDER BY key"
+        ),
+        vec![
+            vec![text("a"), json(r#""x""#)],
+            vec![text("b"), json("[1]")],
+        ]
+    );
+    assert_eq!(
+        rows(
+            &engine,
+            "SELECT e.value FROM json_array_elements('[1, {\"a\": 2}, null]') AS e"
+        ),
+        vec![
+            vec![json("1")],
+            vec![json(r#"{"a":2}"#)],
+            vec![json("null")]
+        ]
+    );
+    assert_eq!(
+        scalar(
+            &engine,
+            "SELECT COUNT(*) FROM json_array_elements('[1, 2, 3]') WHERE CAST(value AS INTEGER) > 1"
+        ),
+        Value::Integer(2)
+    );
+    assert!(
+        engine
+            .session()
+            .execute_one("SELECT * FROM json_each('[1]')")
+            .is_err()
+    );
+}
+
+#[test]
+fn json_aggregates_collect_groups() {
+    let engine = memory_engine();
+    let mut session = engine.session();
+    session
+        .execute_one("CREATE TABLE scores (team TEXT, player TEXT, points INTEGER)")
+        .unwrap();
+    session
+        .execute_one(
+            "INSERT INTO scores VALUES ('red', 'ann', 3), ('red', 'bob', NULL), ('blue', 'cy', 5)",
+        )
+        .unwrap();
+
+    assert_eq!(
+        rows(
+            &engine,
+            "SELECT team, JSON_AGG(points), JSON_OBJECT_AGG(player, points) FROM scores GROUP BY team ORDER BY team"
+        ),
+        vec![
+            vec![text("blue"), json("[5]"), json(r#"{"cy":5}"#)],
+            vec![
+                text("red"),
+                json("[3,null]"),
+                json(r#"{"ann":3,"bob":null}"#)
+            ],
+        ]
+    );
+    assert_eq!(
+        scalar(
+            &engine,
+            "SELECT JSON_AGG(player) FROM scores WHERE points > 100"
+        ),
+        Value::Null
+    );
+}
+
+#[test]
+fn json_agg_keeps_null_inputs() {
+    let engine = memory_engine();
+    let mut session = engine.session();
+    session
+        .execute_one("CREATE TABLE t (k TEXT, v INTEGER)")
+        .unwrap();
+    session
+        .execute_one("INSERT INTO t VALUES ('a', 1), ('b', NULL), ('c', 3)")
+        .unwrap();
+
+    assert_eq!(
+        rows(&engine, "SELECT JSON_AGG(v), SUM(v), COUNT(v) FROM t"),
+        vec![vec![
+            json("[1,null,3]"),
+            Value::Float(4.0),
+            Value::Integer(2)
+        ]]
+    );
+    match session
+        .execute_one("SELECT JSON_OBJECT_AGG(k, v) FROM t")
+        .unwrap()
+    {
+        QueryResult::Rows(batch) => {
+            assert_eq!(batch.columns[0].name, "JsonObjectAgg(k, v)");
+            assert_eq!(batch.rows, vec![vec![json(r#"{"a":1,"b":null,"c":3}"#)]]);
+        }
+        other => panic!("expected rows, got {other:?}"),
+    }
+}
+
+#[test]
+fn json_casts_convert_scalars() {
+    let engine = memory_engine();
+
+    assert_eq!(
+        scalar(
+            &engine,
+            "SELECT CAST(' [1, 2.50, {\"b\":1,\"a\":2}] ' AS JSON)"
+        ),
+        json(r#"[1,2.5,{"a":2,"b":1}]"#)
+    );
+    assert_eq!(
+        scalar(&engine, "SELECT CAST(CAST('{\"a\": 1}' AS JSON) AS TEXT)"),
+        text(r#"{"a":1}"#)
+    );
+    assert_eq!(scalar(&engine, "SELECT CAST(42 AS JSON)"), json("42"));
+    assert_eq!(
+        scalar(&engine, "SELECT CAST(CAST('17' AS JSON) AS INTEGER)"),
+        Value::Integer(17)
+    );
+    assert_eq!(
+        scalar(&engine, "SELECT CAST(CAST('true' AS JSON) AS BOOLEAN)"),
+        Value::Boolean(true)
+    );
+    assert!(
+        engine
+            .session()
+            .execute_one("SELECT CAST(CAST('[1]' AS JSON) AS INTEGER)")
+            .is_err()
+    );
+}
+
+fn check_json_survives_reopen(path: &Path, options: impl Fn() -> EngineOptions) {
+    {
+        let engine = Engine::open(options()).unwrap();
+        let mut session = engine.session();
+        session
+            .execute_one("CREATE TABLE docs (id INTEGER PRIMARY KEY, doc JSON)")
+            .unwrap();
+        session
+            .execute_one("CREATE INDEX idx_doc ON docs (doc)")
+            .unwrap();
+        session
+            .execute_one(
+                "INSERT INTO docs VALUES (1, '{\"a\": [1, 2]}'), (2, '\"plain\"'), (3, NULL)",
+            )
+            .unwrap();
+    }
+
+    let engine = Engine::open(options()).unwrap();
+    assert_eq!(
+        rows(&engine, "SELECT doc FROM docs ORDER BY id"),
+        vec![
+            vec![json(r#"{"a":[1,2]}"#)],
+            vec![json(r#""plain""#)],
+            vec![Value::Null],
+        ]
+    );
+    assert_eq!(
+        scalar(
+            &engine,
+            "SELECT id FROM docs WHERE doc = CAST('{ \"a\" : [1, 2] }' AS JSON)"
+        ),
+        Value::Integer(1)
+    );
+    drop(engine);
+    cleanup_storage_files(path);
+}
+
+#[test]
+fn json_survives_reopening_a_btree_file() {
+    let path = unique_temp_path("btree.db");
+    cleanup_storage_files(&path);
+    check_json_survives_reopen(&path, || EngineOptions::btree(&path));
+}
+
+#[test]
+fn json_survives_reopening_a_json_file() {
+    let path = unique_temp_path("json.db");
+    cleanup_storage_files(&path);
+    check_json_survives_reopen(&path, || EngineOptions::json(&path));
+}
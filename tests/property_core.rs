@@ -1,6 +1,6 @@
 use proptest::prelude::*;
 use rustql::ast::{ColumnDefinition, DataType, Expression, TableConstraint, Value};
-use rustql::database::{CompositeIndex, Database, Index, RowId, Table, View};
+use rustql::database::{CompositeIndex, Database, Index, RowId, SharedMap, SharedVec, Table, View};
 use rustql::error::{RustqlError, SourceLocation};
 use rustql::lexer::{Token, tokenize, tokenize_spanned};
 use rustql::parser::{parse_script, parse_script_spanned};
@@ -228,6 +228,91 @@ proptest! {
     }
 }
 
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(32))]
+
+    #[test]
+    fn shared_vec_matches_vec_and_leaves_clones_unchanged(
+        initial in 0usize..3000,
+        ops in prop::collection::vec((0u8..4, any::<usize>()), 0..2000),
+    ) {
+        let mut expected: Vec<usize> = (0..initial).collect();
+        let mut shared = SharedVec::from(expected.clone());
+        let snapshot = shared.clone();
+        for (op, arg) in ops {
+            match op {
+                0 => {
+                    expected.push(arg);
+                    shared.push(arg);
+                }
+                // Inserts near the front fill the first chunks until they split.
+                1 => {
+                    let position = arg % (expected.len().min(700) + 1);
+                    expected.insert(position, arg);
+                    shared.insert(position, arg);
+                }
+                2 if !expected.is_empty() => {
+                    let position = arg % expected.len();
+                    prop_assert_eq!(expected.remove(position), shared.remove(position));
+                }
+                3 if !expected.is_empty() => {
+                    let position = arg % expected.len();
+                    expected[position] = arg;
+                    shared[position] = arg;
+                }
+                _ => {}
+            }
+        }
+        prop_assert_eq!(&shared, &expected);
+        for (position, value) in expected.iter().enumerate() {
+            prop_assert_eq!(shared.get(position), Some(value));
+        }
+        let mut sorted = expected.clone();
+        sorted.sort();
+        let sorted_shared = SharedVec::from(sorted.clone());
+        for probe in [0, usize::MAX / 2, usize::MAX] {
+            prop_assert_eq!(
+                sorted_shared.binary_search(&probe).is_ok(),
+                sorted.binary_search(&probe).is_ok()
+            );
+            prop_assert_eq!(
+                sorted_shared.partition_point(|value| *value < probe),
+                sorted.partition_point(|value| *value < probe)
+            );
+        }
+        prop_assert_eq!(&snapshot, &(0..initial).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn shared_map_matches_btree_map_and_leaves_clones_unchanged(
+        initial in 0u32..3000,
+        ops in prop::collection::vec((0u8..3, 0u32..4096), 0..2000),
+    ) {
+        // Keys start four apart, so inserts between them split the chunks.
+        let initial: BTreeMap<u32, u32> = (0..initial).map(|key| (key * 4, key)).collect();
+        let mut expected = initial.clone();
+        let mut shared = SharedMap::from(initial.clone());
+        let snapshot = shared.clone();
+        for (op, key) in ops {
+            match op {
+                0 => prop_assert_eq!(expected.insert(key, key), shared.insert(key, key)),
+                1 => prop_assert_eq!(expected.remove(&key), shared.remove(&key)),
+                _ => {
+                    *expected.entry(key).or_default() += 1;
+                    *shared.entry(key).or_default() += 1;
+                }
+            }
+        }
+        prop_assert_eq!(&shared, &expected);
+        prop_assert_eq!(shared.len(), expected.len());
+        for key in 0..4096 {
+            prop_assert_eq!(shared.get(&key), expected.get(&key));
+        }
+        prop_assert!(shared.range(1000..3000).eq(expected.range(1000..3000)));
+        prop_assert_eq!(&snapshot, &initial);
+    }
+}
+
 fn arb_lexer_input() -> impl Strategy<Value = String> {
     ascii_string(LEXER_ALPHABET, 80)
 }
@@ -557,8 +642,9 @@ fn build_database(specs: Vec<TableSpec>) -> Database {
                 name: index_name,
                 table: table_name.clone(),
                 column: table.columns[0].name.clone(),
-                entries,
+                entries: entries.into(),
                 filter_expr: None,
+                paged: None,
             },
         );
 
@@ -581,8 +667,9 @@ fn build_database(specs: Vec<TableSpec>) -> Database {
                 name: composite_name,
                 table: table_name.clone(),
                 columns: composite_columns,
-                entries: composite_entries,
+                entries: composite_entries.into(),
                 filter_expr: None,
+                paged: None,
             },
         );
     }
@@ -593,6 +680,9 @@ fn build_database(specs: Vec<TableSpec>) -> Database {
 
 fn canonical_database(db: &Database) -> CanonicalDatabase {
     let mut normalized = db.clone();
+    normalized
+        .load_all_paged_tables()
+        .expect("paged table rows should load");
     normalized.normalize_row_ids();
 
     CanonicalDatabase {
@@ -604,7 +694,7 @@ fn canonical_database(db: &Database) -> CanonicalDatabase {
                     name.clone(),
                     CanonicalTable {
                         columns: table.columns.clone(),
-                        rows: table.rows.clone(),
+                        rows: table.rows.to_vec(),
                         row_ids: table.row_ids.iter().map(|row_id| row_id.0).collect(),
                         next_row_id: table.next_row_id,
                         constraints: table.constraints.clone(),
@@ -734,4 +824,7 @@ fn wal_path(path: &Path) -> PathBuf {
 fn cleanup_storage_files(path: &Path) {
     let _ = std::fs::remove_file(path);
     let _ = std::fs::remove_file(wal_path(path));
+    let mut lock = path.as_os_str().to_os_string();
+    lock.push(".lock");
+    let _ = std::fs::remove_file(PathBuf::from(lock));
 }
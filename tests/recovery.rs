@@ -1,6 +1,10 @@
 mod common;
 use common::*;
-use rustql::{Engine, EngineOptions, StorageMode};
+use rustql::storage::{BTreeStorageEngine, StorageEngine};
+use rustql::{
+    ConstraintKind, EncryptionKey, Engine, EngineOptions, IntegrityProblem, QueryResult,
+    RustqlError, StorageMode, Value,
+};
 use std::fs;
 use std::path::{Path, PathBuf};
 use std::sync::Mutex;
@@ -120,6 +124,872 @@ fn test_partial_index_filter_persists_across_reload() {
     cleanup_storage_files(&path);
 }
 
+#[test]
+fn test_incremental_btree_saves_match_reloaded_state() {
+    let _guard = test_guard();
+    let path = unique_temp_path("db");
+    cleanup_storage_files(&path);
+
+    let queries = [
+        "SELECT id, name, qty FROM recovery_items ORDER BY id",
+        "SELECT id FROM recovery_items WHERE name = 'item-42' ORDER BY id",
+        "SELECT COUNT(*) FROM recovery_items WHERE qty > 5",
+    ];
+    let engine = open_disk_engine(&path);
+    let expected: Vec<Vec<String>> = {
+        let mut session = engine.session();
+        session
+            .execute_one(
+                "CREATE TABLE recovery_items (id INTEGER PRIMARY KEY, name TEXT, qty INTEGER)",
+            )
+            .unwrap();
+        session
+            .execute_one("CREATE INDEX idx_recovery_items_name ON recovery_items(name)")
+            .unwrap();
+        for id in 1..=250 {
+            session
+                .execute_one(&format!(
+                    "INSERT INTO recovery_items VALUES ({id}, 'item-{id}', {})",
+                    id % 10
+                ))
+                .unwrap();
+        }
+        session
+            .execute_one("UPDATE recovery_items SET qty = qty + 100 WHERE id BETWEEN 70 AND 140")
+            .unwrap();
+        session
+            .execute_one("DELETE FROM recovery_items WHERE id > 220 OR id < 15")
+            .unwrap();
+        session.execute_one("BEGIN TRANSACTION").unwrap();
+        session
+            .execute_one("UPDATE recovery_items SET name = 'item-42' WHERE id = 20")
+            .unwrap();
+        session
+            .execute_one("INSERT INTO recovery_items VALUES (251, 'item-251', 9)")
+            .unwrap();
+        session.execute_one("COMMIT").unwrap();
+        queries
+            .iter()
+            .map(|sql| query_output_lines(&session.execute_one(sql).unwrap()))
+            .collect()
+    };
+    drop(engine);
+
+    let reloaded = open_disk_engine(&path);
+    let mut session = reloaded.session();
+    for (sql, expected) in queries.iter().zip(&expected) {
+        let actual = query_output_lines(&session.execute_one(sql).unwrap());
+        assert_eq!(&actual, expected, "mismatch after reload for {sql}");
+    }
+    let plan = query_output_lines(
+        &session
+            .execute_one("EXPLAIN SELECT id FROM recovery_items WHERE name = 'item-42'")
+            .unwrap(),
+    )
+    .join("\n");
+    assert!(
+        plan.contains("Index Scan using idx_recovery_items_name"),
+        "missing index use after reload: {plan}"
+    );
+    cleanup_storage_files(&path);
+}
+
+#[test]
+fn test_paged_btree_tables_serve_reads_and_writes_after_reload() {
+    let _guard = test_guard();
+    let path = unique_temp_path("db");
+    cleanup_storage_files(&path);
+
+    let queries = [
+        "SELECT id, name, qty FROM paged_items WHERE qty BETWEEN 3 AND 4 ORDER BY id",
+        "SELECT id, qty FROM paged_items WHERE name = 'item-300'",
+        "SELECT COUNT(*) FROM paged_items",
+        "SELECT o.id, i.name FROM paged_orders o JOIN paged_items i ON o.item_id = i.id ORDER BY o.id",
+    ];
+    let engine = open_disk_engine(&path);
+    let expected: Vec<Vec<String>> = {
+        let mut session = engine.session();
+        session
+            .execute_one(
+                "CREATE TABLE paged_items (id INTEGER PRIMARY KEY, name TEXT, qty INTEGER)",
+            )
+            .unwrap();
+        session
+            .execute_one(
+                "CREATE TABLE paged_orders (id INTEGER PRIMARY KEY, item_id INTEGER REFERENCES paged_items(id))",
+            )
+            .unwrap();
+        session
+            .execute_one("CREATE INDEX idx_paged_items_name ON paged_items(name)")
+            .unwrap();
+        for id in 1..=600 {
+            session
+                .execute_one(&format!(
+                    "INSERT INTO paged_items VALUES ({id}, 'item-{id}', {})",
+                    id % 7
+                ))
+                .unwrap();
+        }
+        for id in 1..=20 {
+            session
+                .execute_one(&format!(
+                    "INSERT INTO paged_orders VALUES ({id}, {})",
+                    id * 25
+                ))
+                .unwrap();
+        }
+        queries
+            .iter()
+            .map(|sql| query_output_lines(&session.execute_one(sql).unwrap()))
+            .collect()
+    };
+    drop(engine);
+
+    let open_paged =
+        |path: &Path| Engine::open(EngineOptions::btree(path).with_page_cache_size(8)).unwrap();
+    let reloaded = open_paged(&path);
+    let mut session = reloaded.session();
+    for (sql, expected) in queries.iter().zip(&expected) {
+        let actual = query_output_lines(&session.execute_one(sql).unwrap());
+        assert_eq!(&actual, expected, "mismatch on paged read for {sql}");
+    }
+
+    assert!(
+        session
+            .execute_one("DELETE FROM paged_items WHERE id = 50")
+            .is_err(),
+        "deleting a referenced row must still check the paged child table"
+    );
+    session
+        .execute_one("UPDATE paged_items SET qty = 100 WHERE id BETWEEN 10 AND 19")
+        .unwrap();
+    session
+        .execute_one("DELETE FROM paged_items WHERE id > 590")
+        .unwrap();
+    let after_writes: Vec<Vec<String>> = [
+        "SELECT COUNT(*) FROM paged_items WHERE qty = 100",
+        "SELECT COUNT(*) FROM paged_items",
+        "SELECT id FROM paged_items WHERE name = 'item-595'",
+    ]
+    .iter()
+    .map(|sql| query_output_lines(&session.execute_one(sql).unwrap()))
+    .collect();
+    assert_eq!(after_writes[0].last().map(String::as_str), Some("10"));
+    assert_eq!(after_writes[1].last().map(String::as_str), Some("590"));
+    drop(reloaded);
+
+    let reopened = open_paged(&path);
+    let mut session = reopened.session();
+    for (sql, expected) in [
+        "SELECT COUNT(*) FROM paged_items WHERE qty = 100",
+        "SELECT COUNT(*) FROM paged_items",
+        "SELECT id FROM paged_items WHERE name = 'item-595'",
+    ]
+    .iter()
+    .zip(&after_writes)
+    {
+        let actual = query_output_lines(&session.execute_one(sql).unwrap());
+        assert_eq!(&actual, expected, "mismatch after reopening for {sql}");
+    }
+    cleanup_storage_files(&path);
+}
+
+#[test]
+fn test_paged_btree_index_lookups_see_committed_writes() {
+    let _guard = test_guard();
+    let path = unique_temp_path("db");
+    cleanup_storage_files(&path);
+    {
+        let engine = open_disk_engine(&path);
+        let mut session = engine.session();
+        session
+            .execute_script(
+                "CREATE TABLE paged_prices (id INTEGER PRIMARY KEY, sku TEXT UNIQUE, price FLOAT); \
+                 CREATE INDEX idx_paged_prices_price ON paged_prices(price)",
+            )
+            .unwrap();
+        for id in 1..=200 {
+            session
+                .execute_one(&format!(
+                    "INSERT INTO paged_prices VALUES ({id}, 'sku-{id}', {})",
+                    id % 10
+                ))
+                .unwrap();
+        }
+    }
+
+    let engine = Engine::open(EngineOptions::btree(&path).with_page_cache_size(8)).unwrap();
+    let mut session = engine.session();
+    session
+        .execute_script(
+            "INSERT INTO paged_prices VALUES (201, 'sku-201', 3); \
+             UPDATE paged_prices SET price = 42 WHERE id = 13; \
+             DELETE FROM paged_prices WHERE id = 23",
+        )
+        .unwrap();
+    assert!(
+        session
+            .execute_one("INSERT INTO paged_prices VALUES (202, 'sku-5', 1)")
+            .is_err(),
+        "unique checks must see the stored rows"
+    );
+
+    let ids = |session: &mut rustql::Session, sql: &str| -> Vec<i64> {
+        match session.execute_one(sql).unwrap() {
+            QueryResult::Rows(rows) => rows
+                .rows
+                .iter()
+                .map(|row| match row[0] {
+                    Value::Integer(id) => id,
+                    ref other => panic!("expected an id, got: {other:?}"),
+                })
+                .collect(),
+            other => panic!("expected row result, got: {other:?}"),
+        }
+    };
+    let mut expected: Vec<i64> = (3..=193)
+        .step_by(10)
+        .filter(|id| ![13, 23].contains(id))
+        .collect();
+    expected.push(201);
+    assert_eq!(
+        ids(
+            &mut session,
+            "SELECT id FROM paged_prices WHERE price = 3 ORDER BY id"
+        ),
+        expected
+    );
+    assert_eq!(
+        ids(
+            &mut session,
+            "SELECT id FROM paged_prices WHERE price = 3.0 ORDER BY id"
+        ),
+        expected
+    );
+    assert_eq!(
+        ids(&mut session, "SELECT id FROM paged_prices WHERE price = 42"),
+        vec![13]
+    );
+    assert_eq!(
+        ids(
+            &mut session,
+            "SELECT id FROM paged_prices WHERE sku = 'sku-23'"
+        ),
+        Vec::<i64>::new()
+    );
+    cleanup_storage_files(&path);
+}
+
+#[test]
+fn test_checkpoint_folds_redo_log_and_reopen_replays_it() {
+    let _guard = test_guard();
+    let path = unique_temp_path("db");
+    cleanup_storage_files(&path);
+    let mut wal_path = path.as_os_str().to_os_string();
+    wal_path.push(".wal");
+    let wal_path = PathBuf::from(wal_path);
+
+    let engine = open_disk_engine(&path);
+    let mut session = engine.session();
+    session
+        .execute_one("CREATE TABLE log_items (id INTEGER PRIMARY KEY, name TEXT)")
+        .unwrap();
+    for id in 1..=40 {
+        session
+            .execute_one(&format!("INSERT INTO log_items VALUES ({id}, 'item-{id}')"))
+            .unwrap();
+    }
+    assert!(wal_path.exists(), "commits should append to the redo log");
+    let data_before_checkpoint = fs::read(&path).unwrap();
+
+    assert_command(
+        session.execute_one("CHECKPOINT").unwrap(),
+        CommandTag::Checkpoint,
+        0,
+    );
+    assert!(!wal_path.exists(), "CHECKPOINT should drop the redo log");
+    assert_ne!(fs::read(&path).unwrap(), data_before_checkpoint);
+
+    session
+        .execute_one("UPDATE log_items SET name = 'renamed' WHERE id <= 10")
+        .unwrap();
+    session
+        .execute_one("DELETE FROM log_items WHERE id > 35")
+        .unwrap();
+    assert!(wal_path.exists());
+    let expected = query_output_lines(
+        &session
+            .execute_one("SELECT id, name FROM log_items ORDER BY id")
+            .unwrap(),
+    );
+    // Dropping the engine without a checkpoint leaves the log for the next
+    // open to replay, as after a crash.
+    drop(engine);
+
+    let reopened = open_disk_engine(&path);
+    assert!(!wal_path.exists(), "opening should replay and drop the log");
+    let mut session = reopened.session();
+    let actual = query_output_lines(
+        &session
+            .execute_one("SELECT id, name FROM log_items ORDER BY id")
+            .unwrap(),
+    );
+    assert_eq!(actual, expected);
+    cleanup_storage_files(&path);
+}
+
+#[test]
+fn test_backup_to_copies_committed_state_and_restores() {
+    let _guard = test_guard();
+    let path = unique_temp_path("db");
+    let backup_path = unique_temp_path("bak");
+    cleanup_storage_files(&path);
+    cleanup_storage_files(&backup_path);
+
+    let engine = open_disk_engine(&path);
+    let mut session = engine.session();
+    session
+        .execute_one("CREATE TABLE accounts (id INTEGER PRIMARY KEY, balance INTEGER)")
+        .unwrap();
+    for id in 1..=20 {
+        session
+            .execute_one(&format!("INSERT INTO accounts VALUES ({id}, {})", id * 10))
+            .unwrap();
+    }
+    session.execute_one("CHECKPOINT").unwrap();
+    // Logged but not checkpointed commits must be part of the backup.
+    session
+        .execute_one("UPDATE accounts SET balance = 0 WHERE id <= 5")
+        .unwrap();
+    let expected = query_output_lines(
+        &session
+            .execute_one("SELECT id, balance FROM accounts ORDER BY id")
+            .unwrap(),
+    );
+
+    session.execute_one("BEGIN").unwrap();
+    session
+        .execute_one("INSERT INTO accounts VALUES (99, 990)")
+        .unwrap();
+    engine.backup_to(&backup_path).unwrap();
+    session.execute_one("COMMIT").unwrap();
+
+    let loaded = BTreeStorageEngine::new(backup_path.clone())
+        .load()
+        .expect("backup should be a valid BTree file");
+    assert!(loaded.tables.contains_key("accounts"));
+
+    let restored = open_disk_engine(&backup_path);
+    let actual = query_output_lines(
+        &restored
+            .session()
+            .execute_one("SELECT id, balance FROM accounts ORDER BY id")
+            .unwrap(),
+    );
+    assert_eq!(actual, expected);
+    drop(restored);
+
+    // The source keeps working after the backup.
+    drop(engine);
+    let reopened = open_disk_engine(&path);
+    let QueryResult::Rows(batch) = reopened
+        .session()
+        .execute_one("SELECT COUNT(*) FROM accounts")
+        .unwrap()
+    else {
+        panic!("expected rows");
+    };
+    assert_eq!(batch.rows, vec![vec![Value::Integer(21)]]);
+    cleanup_storage_files(&path);
+    cleanup_storage_files(&backup_path);
+}
+
+#[test]
+fn test_backup_to_statement_leaves_out_open_transaction() {
+    let _guard = test_guard();
+    let backup_path = unique_temp_path("bak");
+    cleanup_storage_files(&backup_path);
+
+    let engine = Engine::in_memory().unwrap();
+    let mut session = engine.session();
+    session
+        .execute_script(
+            "CREATE TABLE notes (id INTEGER PRIMARY KEY, body TEXT);
+             INSERT INTO notes VALUES (1, 'kept');
+             BEGIN;
+             INSERT INTO notes VALUES (2, 'pending');
+             UPDATE notes SET body = 'changed' WHERE id = 1;",
+        )
+        .unwrap();
+    assert!(session.execute_one("BACKUP TO notes").is_err());
+    let sql = format!("BACKUP TO '{}'", backup_path.display());
+    assert_command(session.execute_one(&sql).unwrap(), CommandTag::Backup, 0);
+    session.execute_one("COMMIT").unwrap();
+
+    let restored = open_disk_engine(&backup_path);
+    let QueryResult::Rows(batch) = restored
+        .session()
+        .execute_one("SELECT id, body FROM notes ORDER BY id")
+        .unwrap()
+    else {
+        panic!("expected rows");
+    };
+    assert_eq!(
+        batch.rows,
+        vec![vec![Value::Integer(1), Value::Text("kept".to_string())]]
+    );
+    drop(restored);
+    cleanup_storage_files(&backup_path);
+}
+
+#[test]
+fn test_backup_to_refuses_an_existing_destination() {
+    let _guard = test_guard();
+    let path = unique_temp_path("db");
+    let backup_path = unique_temp_path("bak");
+    cleanup_storage_files(&path);
+    cleanup_storage_files(&backup_path);
+
+    let live = open_disk_engine(&path);
+    live.session()
+        .execute_script("CREATE TABLE kept (id INTEGER); INSERT INTO kept VALUES (1);")
+        .unwrap();
+    let engine = Engine::in_memory().unwrap();
+    engine
+        .session()
+        .execute_one("CREATE TABLE other (id INTEGER)")
+        .unwrap();
+
+    // Backing up over a live database would destroy it and its log.
+    let err = engine.backup_to(&path).unwrap_err();
+    assert!(
+        matches!(&err, RustqlError::StorageError(msg) if msg.contains("already exists")),
+        "unexpected error: {err:?}"
+    );
+    let sql = format!("BACKUP TO '{}'", path.display());
+    assert!(engine.session().execute_one(&sql).is_err());
+    drop(live);
+    let reopened = open_disk_engine(&path);
+    let QueryResult::Rows(batch) = reopened
+        .session()
+        .execute_one("SELECT id FROM kept")
+        .unwrap()
+    else {
+        panic!("expected rows");
+    };
+    assert_eq!(batch.rows, vec![vec![Value::Integer(1)]]);
+    drop(reopened);
+
+    // A log left without its file is refused as well.
+    let mut journal = backup_path.as_os_str().to_os_string();
+    journal.push(".wal");
+    fs::write(&journal, b"log").unwrap();
+    assert!(engine.backup_to(&backup_path).is_err());
+    assert!(!backup_path.exists());
+    fs::remove_file(&journal).unwrap();
+    engine.backup_to(&backup_path).unwrap();
+
+    cleanup_storage_files(&path);
+    cleanup_storage_files(&backup_path);
+}
+
+#[test]
+fn test_backup_to_runs_alongside_writes() {
+    let _guard = test_guard();
+    let path = unique_temp_path("db");
+    cleanup_storage_files(&path);
+    let backup_paths: Vec<PathBuf> = (0..4).map(|_| unique_temp_path("bak")).collect();
+
+    let engine = open_disk_engine(&path);
+    engine
+        .session()
+        .execute_one("CREATE TABLE events (id INTEGER PRIMARY KEY)")
+        .unwrap();
+    std::thread::scope(|scope| {
+        scope.spawn(|| {
+            let mut session = engine.session();
+            for id in 1..=200 {
+                session
+                    .execute_one(&format!("INSERT INTO events VALUES ({id})"))
+                    .unwrap();
+            }
+        });
+        for backup_path in &backup_paths {
+            engine.backup_to(backup_path).unwrap();
+        }
+    });
+
+    // Every backup holds a prefix of the inserts, whatever point it caught.
+    for backup_path in &backup_paths {
+        let restored = open_disk_engine(backup_path);
+        let QueryResult::Rows(batch) = restored
+            .session()
+            .execute_one("SELECT id FROM events ORDER BY id")
+            .unwrap()
+        else {
+            panic!("expected rows");
+        };
+        let expected: Vec<Vec<Value>> = (1..=batch.rows.len() as i64)
+            .map(|id| vec![Value::Integer(id)])
+            .collect();
+        assert_eq!(batch.rows, expected);
+        drop(restored);
+        cleanup_storage_files(backup_path);
+    }
+    cleanup_storage_files(&path);
+}
+
+#[test]
+fn test_vacuum_reclaims_space_freed_by_deletes() {
+    let _guard = test_guard();
+    let path = unique_temp_path("db");
+    cleanup_storage_files(&path);
+
+    let engine = open_disk_engine(&path);
+    let mut session = engine.session();
+    session
+        .execute_one("CREATE TABLE docs (id INTEGER PRIMARY KEY, body TEXT)")
+        .unwrap();
+    session.execute_one("BEGIN").unwrap();
+    for id in 1..=600 {
+        session
+            .execute_one(&format!(
+                "INSERT INTO docs VALUES ({id}, 'document body number {id}')"
+            ))
+            .unwrap();
+    }
+    session.execute_one("COMMIT").unwrap();
+    session
+        .execute_one("DELETE FROM docs WHERE id > 50")
+        .unwrap();
+
+    let storage_pages = |session: &mut rustql::Session, kind: &str| -> i64 {
+        let QueryResult::Rows(batch) = session.execute_one("SHOW STORAGE").unwrap() else {
+            panic!("expected rows");
+        };
+        batch
+            .rows
+            .iter()
+            .filter(|row| row[0] == Value::Text(kind.to_string()))
+            .map(|row| match row[2] {
+                Value::Integer(pages) => pages,
+                _ => panic!("page counts should be integers"),
+            })
+            .sum()
+    };
+    assert!(storage_pages(&mut session, "free") > 0);
+    assert!(storage_pages(&mut session, "table") > 0);
+
+    session.execute_one("BEGIN").unwrap();
+    assert!(session.execute_one("VACUUM").is_err());
+    session.execute_one("ROLLBACK").unwrap();
+
+    let QueryResult::Rows(batch) = session.execute_one("VACUUM").unwrap() else {
+        panic!("expected rows");
+    };
+    assert_eq!(
+        batch
+            .columns
+            .iter()
+            .map(|column| column.name.as_str())
+            .collect::<Vec<_>>(),
+        vec!["bytes_before", "bytes_after", "bytes_reclaimed"]
+    );
+    let Value::Integer(reclaimed) = batch.rows[0][2] else {
+        panic!("bytes reclaimed should be an integer");
+    };
+    assert!(reclaimed > 0);
+    assert_eq!(storage_pages(&mut session, "free"), 0);
+    let expected = query_output_lines(
+        &session
+            .execute_one("SELECT id, body FROM docs ORDER BY id")
+            .unwrap(),
+    );
+    drop(engine);
+
+    let reopened = open_disk_engine(&path);
+    let actual = query_output_lines(
+        &reopened
+            .session()
+            .execute_one("SELECT id, body FROM docs ORDER BY id")
+            .unwrap(),
+    );
+    assert_eq!(actual, expected);
+    cleanup_storage_files(&path);
+}
+
+#[test]
+fn test_bulk_delete_from_indexed_table_survives_reopen() {
+    let _guard = test_guard();
+    let path = unique_temp_path("db");
+    cleanup_storage_files(&path);
+
+    let engine = open_disk_engine(&path);
+    let mut session = engine.session();
+    session
+        .execute_one("CREATE TABLE t (id INTEGER PRIMARY KEY, s TEXT)")
+        .unwrap();
+    for id in 1..=1000 {
+        session
+            .execute_one(&format!("INSERT INTO t VALUES ({id}, 'value {id}')"))
+            .unwrap();
+    }
+    session.execute_one("CREATE INDEX t_s ON t (s)").unwrap();
+    session.execute_one("DELETE FROM t WHERE id > 100").unwrap();
+    drop(engine);
+
+    let reopened = open_disk_engine(&path);
+    let mut session = reopened.session();
+    let QueryResult::Rows(count) = session.execute_one("SELECT COUNT(*) FROM t").unwrap() else {
+        panic!("expected rows");
+    };
+    assert_eq!(count.rows, vec![vec![Value::Integer(100)]]);
+    let QueryResult::Rows(problems) = session.execute_one("CHECK DATABASE").unwrap() else {
+        panic!("expected rows");
+    };
+    assert!(problems.rows.is_empty(), "{:?}", problems.rows);
+    cleanup_storage_files(&path);
+}
+
+#[test]
+fn test_check_database_reports_broken_indexes_and_constraints() {
+    let _guard = test_guard();
+    let path = unique_temp_path("db");
+    cleanup_storage_files(&path);
+
+    let engine = open_disk_engine(&path);
+    let mut session = engine.session();
+    session
+        .execute_script(
+            "CREATE TABLE owners (id INTEGER PRIMARY KEY, email TEXT UNIQUE);
+             CREATE TABLE pets (id INTEGER PRIMARY KEY, owner_id INTEGER FOREIGN KEY REFERENCES owners(id), name TEXT NOT NULL);
+             CREATE INDEX idx_pets_name ON pets (name);
+             INSERT INTO owners VALUES (1, 'a@example.com'), (2, 'b@example.com');
+             INSERT INTO pets VALUES (1, 1, 'Rex'), (2, 2, 'Tom');",
+        )
+        .unwrap();
+    let QueryResult::Rows(batch) = session.execute_one("CHECK DATABASE").unwrap() else {
+        panic!("expected rows");
+    };
+    assert!(batch.rows.is_empty(), "got: {:?}", batch.rows);
+    assert_eq!(engine.check_database().unwrap(), Vec::new());
+    drop(engine);
+
+    // Write rows past every constraint and index, as a bad restore might.
+    let storage = BTreeStorageEngine::new(&path);
+    let mut db = storage.load().unwrap();
+    db.load_all_paged_tables().unwrap();
+    db.tables.get_mut("owners").unwrap().insert_row(vec![
+        Value::Integer(3),
+        Value::Text("a@example.com".to_string()),
+    ]);
+    db.tables.get_mut("pets").unwrap().insert_row(vec![
+        Value::Integer(3),
+        Value::Integer(99),
+        Value::Null,
+    ]);
+    storage.save(&db).unwrap();
+    drop(storage);
+
+    let engine = open_disk_engine(&path);
+    let problems = engine.check_database().unwrap();
+    let has_constraint = |expected: ConstraintKind, expected_table: &str| {
+        problems.iter().any(|problem| {
+            matches!(problem, IntegrityProblem::Constraint { kind, table, .. }
+                if *kind == expected && table == expected_table)
+        })
+    };
+    assert!(
+        has_constraint(ConstraintKind::Unique, "owners"),
+        "{problems:?}"
+    );
+    assert!(
+        has_constraint(ConstraintKind::ForeignKey, "pets"),
+        "{problems:?}"
+    );
+    assert!(
+        has_constraint(ConstraintKind::NotNull, "pets"),
+        "{problems:?}"
+    );
+    assert!(
+        problems.iter().any(|problem| matches!(
+            problem,
+            IntegrityProblem::IndexEntry { index, .. } if index == "idx_pets_name"
+        )),
+        "{problems:?}"
+    );
+
+    let QueryResult::Rows(batch) = engine.session().execute_one("CHECK DATABASE").unwrap() else {
+        panic!("expected rows");
+    };
+    assert_eq!(batch.rows.len(), problems.len());
+    assert!(
+        batch
+            .rows
+            .iter()
+            .any(|row| row[0] == Value::Text("constraint".to_string())
+                && row[1] == Value::Text("owners".to_string()))
+    );
+    cleanup_storage_files(&path);
+}
+
+#[test]
+fn test_migrate_converts_json_to_btree_and_back() {
+    let _guard = test_guard();
+    let json_path = unique_temp_path("json");
+    let btree_path = unique_temp_path("db");
+    let round_trip_path = unique_temp_path("round-trip.json");
+    for path in [&json_path, &btree_path, &round_trip_path] {
+        cleanup_storage_files(path);
+    }
+
+    let engine = Engine::open(EngineOptions::json(&json_path)).unwrap();
+    engine
+        .session()
+        .execute_script(
+            "CREATE TABLE items (id INTEGER PRIMARY KEY, sku TEXT, qty INTEGER);
+             CREATE INDEX idx_items_sku ON items (sku);
+             CREATE INDEX idx_items_sku_qty ON items (sku, qty);
+             CREATE VIEW stocked AS SELECT sku FROM items WHERE qty > 0;
+             INSERT INTO items VALUES (1, 'a', 5), (2, 'b', 0), (3, 'a', 7);
+             CREATE TABLE empty_table (id INTEGER);",
+        )
+        .unwrap();
+    drop(engine);
+
+    let json = StorageMode::Json {
+        path: json_path.clone(),
+    };
+    let btree = StorageMode::BTree {
+        path: btree_path.clone(),
+    };
+    let report = rustql::migrate(&json, &btree).unwrap();
+    assert_eq!(report.tables.get("items"), Some(&3));
+    assert_eq!(report.tables.get("empty_table"), Some(&0));
+    assert_eq!(report.total_rows(), 3);
+    assert_eq!(report.indexes, 2);
+    assert_eq!(report.views, 1);
+
+    let engine = open_disk_engine(&btree_path);
+    let QueryResult::Rows(batch) = engine
+        .session()
+        .execute_one("SELECT id FROM items WHERE sku = 'a' ORDER BY id")
+        .unwrap()
+    else {
+        panic!("expected rows");
+    };
+    assert_eq!(
+        batch.rows,
+        vec![vec![Value::Integer(1)], vec![Value::Integer(3)]]
+    );
+    assert_eq!(engine.check_database().unwrap(), Vec::new());
+    drop(engine);
+
+    let round_trip = StorageMode::Json {
+        path: round_trip_path.clone(),
+    };
+    assert_eq!(rustql::migrate(&btree, &round_trip).unwrap(), report);
+
+    // An existing target is never overwritten.
+    let before = fs::read(&round_trip_path).unwrap();
+    let error = rustql::migrate(&btree, &round_trip).unwrap_err();
+    assert!(
+        error.to_string().contains("already exists"),
+        "unexpected error: {error}"
+    );
+    assert_eq!(fs::read(&round_trip_path).unwrap(), before);
+    assert!(rustql::migrate(&json, &btree).is_err());
+
+    for path in [&json_path, &btree_path, &round_trip_path] {
+        cleanup_storage_files(path);
+    }
+}
+
+#[test]
+fn test_encrypted_btree_file_needs_its_key_and_can_be_rekeyed() {
+    let _guard = test_guard();
+    let path = unique_temp_path("db");
+    cleanup_storage_files(&path);
+    let mut wal_path = path.as_os_str().to_os_string();
+    wal_path.push(".wal");
+    let wal_path = PathBuf::from(wal_path);
+    let first_key = EncryptionKey::new([0x11; 32]);
+    let second_key = EncryptionKey::from_hex(&"22".repeat(32)).unwrap();
+    let open_with = |key: Option<&EncryptionKey>| {
+        let mut options = EngineOptions::btree(&path);
+        if let Some(key) = key {
+            options = options.with_encryption_key(key.clone());
+        }
+        Engine::open(options)
+    };
+    let open_error = |key: Option<&EncryptionKey>| match open_with(key) {
+        Err(RustqlError::StorageError(message)) => message,
+        Err(other) => panic!("expected a storage error, got {other:?}"),
+        Ok(_) => panic!("opening with the wrong key should fail"),
+    };
+    let contains = |bytes: &[u8], needle: &[u8]| bytes.windows(needle.len()).any(|w| w == needle);
+
+    let engine = open_with(Some(&first_key)).unwrap();
+    let mut session = engine.session();
+    session
+        .execute_script(
+            "CREATE TABLE secrets (id INTEGER PRIMARY KEY, note TEXT);
+             INSERT INTO secrets VALUES (1, 'plaintext-marker-one');",
+        )
+        .unwrap();
+    session
+        .execute_one("INSERT INTO secrets VALUES (2, 'plaintext-marker-two')")
+        .unwrap();
+    assert!(wal_path.exists(), "the insert should be in the redo log");
+    assert!(!contains(
+        &fs::read(&wal_path).unwrap(),
+        b"plaintext-marker"
+    ));
+    assert!(!contains(&fs::read(&path).unwrap(), b"plaintext-marker"));
+    let expected = query_output_lines(
+        &session
+            .execute_one("SELECT id, note FROM secrets ORDER BY id")
+            .unwrap(),
+    );
+    // The log is left behind, so the next open replays encrypted frames.
+    drop(engine);
+
+    assert!(open_error(None).contains("is encrypted"));
+    assert!(open_error(Some(&second_key)).contains("Wrong encryption key"));
+
+    let engine = open_with(Some(&first_key)).unwrap();
+    let read_notes = |engine: &Engine| {
+        query_output_lines(
+            &engine
+                .session()
+                .execute_one("SELECT id, note FROM secrets ORDER BY id")
+                .unwrap(),
+        )
+    };
+    assert_eq!(read_notes(&engine), expected);
+    assert!(engine.check_database().unwrap().is_empty());
+
+    engine.rekey(Some(second_key.clone())).unwrap();
+    drop(engine);
+    assert!(open_error(Some(&first_key)).contains("Wrong encryption key"));
+    let engine = open_with(Some(&second_key)).unwrap();
+    assert_eq!(read_notes(&engine), expected);
+
+    engine.rekey(None).unwrap();
+    drop(engine);
+    assert!(contains(&fs::read(&path).unwrap(), b"plaintext-marker-one"));
+    assert!(open_error(Some(&second_key)).contains("not encrypted"));
+    let engine = open_with(None).unwrap();
+    assert_eq!(read_notes(&engine), expected);
+    drop(engine);
+
+    assert!(matches!(
+        Engine::open(EngineOptions::memory().with_encryption_key(first_key)),
+        Err(RustqlError::StorageError(_))
+    ));
+    cleanup_storage_files(&path);
+}
+
 #[test]
 fn json_engine_rejects_corrupt_storage_file() {
     let _guard = test_guard();
@@ -129,6 +999,7 @@ fn json_engine_rejects_corrupt_storage_file() {
 
     let result = Engine::open(EngineOptions {
         storage: StorageMode::Json { path: path.clone() },
+        ..EngineOptions::default()
     });
     let Err(error) = result else {
         panic!("expected corrupt JSON storage to fail");
@@ -152,6 +1023,7 @@ fn json_engine_rejects_empty_storage_file() {
 
     let result = Engine::open(EngineOptions {
         storage: StorageMode::Json { path: path.clone() },
+        ..EngineOptions::default()
     });
     let Err(error) = result else {
         panic!("expected empty JSON storage to fail");
@@ -169,6 +1041,7 @@ fn open_disk_engine(path: &Path) -> Engine {
         storage: StorageMode::BTree {
             path: path.to_path_buf(),
         },
+        ..EngineOptions::default()
     })
     .unwrap()
 }
@@ -179,6 +1052,10 @@ fn cleanup_storage_files(path: &Path) {
     let mut wal = path.as_os_str().to_os_string();
     wal.push(".wal");
     fs::remove_file(PathBuf::from(wal)).ok();
+
+    let mut lock = path.as_os_str().to_os_string();
+    lock.push(".lock");
+    fs::remove_file(PathBuf::from(lock)).ok();
 }
 
 fn unique_temp_path(extension: &str) -> PathBuf {
@@ -0,0 +1,263 @@
+mod common;
+use common::*;
+use rustql::{
+    Engine, EngineOptions, QueryResult, ReplicationSource, ReplicationTarget, RustqlError, Value,
+    log_channel,
+};
+use std::fs;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+fn count(engine: &Engine, table: &str) -> Option<Value> {
+    match engine
+        .session()
+        .execute_one(&format!("SELECT COUNT(*) FROM {table}"))
+    {
+        Ok(QueryResult::Rows(batch)) => Some(batch.rows[0][0].clone()),
+        _ => None,
+    }
+}
+
+/// Waits for the follower to apply the commits that give `table` `rows` rows.
+fn wait_for_rows(follower: &Engine, table: &str, rows: i64) {
+    let deadline = Instant::now() + Duration::from_secs(10);
+    while count(follower, table) != Some(Value::Integer(rows)) {
+        assert!(
+            Instant::now() < deadline,
+            "follower still has {:?} rows in {table}, expected {rows}",
+            count(follower, table)
+        );
+        std::thread::sleep(Duration::from_millis(10));
+    }
+}
+
+fn assert_rejected(engine: &Engine, sql: &str) {
+    let err = engine.session().execute_one(sql).unwrap_err();
+    assert!(
+        matches!(&err, RustqlError::TransactionError(msg) if msg.contains("read-only follower")),
+        "unexpected error for {sql}: {err:?}"
+    );
+}
+
+fn shipped_records(dir: &Path) -> Vec<String> {
+    let mut names: Vec<String> = fs::read_dir(dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+        .collect();
+    names.sort();
+    names
+}
+
+#[test]
+fn follower_applies_commits_shipped_to_a_directory() {
+    let primary_path = unique_temp_path("primary.db");
+    let follower_path = unique_temp_path("follower.db");
+    let log_dir = unique_temp_path("log");
+
+    let primary = Engine::open(
+        EngineOptions::btree(&primary_path)
+            .with_log_shipping(ReplicationTarget::Directory(log_dir.clone())),
+    )
+    .unwrap();
+    primary
+        .session()
+        .execute_script(
+            "CREATE TABLE items (id INTEGER PRIMARY KEY, name TEXT);
+             INSERT INTO items VALUES (1, 'a'), (2, 'b');",
+        )
+        .unwrap();
+
+    let follower = Engine::open(EngineOptions::follower(
+        &follower_path,
+        ReplicationSource::Directory(log_dir.clone()),
+    ))
+    .unwrap();
+    assert!(follower.is_follower());
+    assert_eq!(count(&follower, "items"), Some(Value::Integer(2)));
+
+    let mut writer = primary.session();
+    writer
+        .execute_script(
+            "BEGIN;
+             INSERT INTO items VALUES (3, 'c');
+             DELETE FROM items WHERE id = 1;
+             COMMIT;
+             CREATE INDEX items_name ON items (name);
+             INSERT INTO items SELECT generate_series + 10, 'x' FROM generate_series(1, 500);",
+        )
+        .unwrap();
+    wait_for_rows(&follower, "items", 502);
+    match follower
+        .session()
+        .execute_one("SELECT name FROM items WHERE id = 3")
+        .unwrap()
+    {
+        QueryResult::Rows(batch) => {
+            assert_eq!(batch.rows, vec![vec![Value::Text("c".to_string())]])
+        }
+        other => panic!("expected rows, got {other:?}"),
+    }
+
+    assert_rejected(&follower, "INSERT INTO items VALUES (100, 'z')");
+    assert_rejected(&follower, "CREATE TABLE other (id INTEGER)");
+    assert_rejected(&follower, "VACUUM");
+    assert_rejected(&follower, "COMMIT PREPARED 'tx'");
+    let mut reader = follower.session();
+    reader.execute_one("BEGIN").unwrap();
+    assert!(reader.execute_one("UPDATE items SET name = 'y'").is_err());
+    assert!(reader.execute_one("PREPARE TRANSACTION 'tx'").is_err());
+    reader.execute_one("SELECT * FROM items").unwrap();
+    reader.execute_one("COMMIT").unwrap();
+
+    drop((reader, writer, follower, primary));
+    for path in [&primary_path, &follower_path] {
+        cleanup_storage_files(path);
+    }
+    fs::remove_dir_all(&log_dir).ok();
+}
+
+#[test]
+fn channel_follower_is_promoted_to_accept_writes() {
+    let primary_path = unique_temp_path("primary.db");
+    let follower_path = unique_temp_path("follower.db");
+    let (sender, receiver) = log_channel();
+
+    let primary = Engine::open(
+        EngineOptions::btree(&primary_path).with_log_shipping(ReplicationTarget::Channel(sender)),
+    )
+    .unwrap();
+    let follower = Engine::open(EngineOptions::follower(
+        &follower_path,
+        ReplicationSource::Channel(receiver),
+    ))
+    .unwrap();
+
+    let mut writer = primary.session();
+    writer
+        .execute_script(
+            "CREATE TABLE items (id INTEGER PRIMARY KEY, name TEXT);
+             INSERT INTO items VALUES (1, 'a'), (2, 'b');
+             VACUUM;
+             INSERT INTO items VALUES (3, 'c');",
+        )
+        .unwrap();
+    wait_for_rows(&follower, "items", 3);
+
+    writer
+        .execute_one("INSERT INTO items VALUES (4, 'd')")
+        .unwrap();
+    follower.promote().unwrap();
+    assert!(!follower.is_follower());
+    assert_eq!(count(&follower, "items"), Some(Value::Integer(4)));
+
+    // Once promoted the follower is on its own.
+    writer
+        .execute_one("INSERT INTO items VALUES (5, 'e')")
+        .unwrap();
+    follower
+        .session()
+        .execute_one("INSERT INTO items VALUES (10, 'j')")
+        .unwrap();
+    std::thread::sleep(Duration::from_millis(100));
+    assert_eq!(count(&follower, "items"), Some(Value::Integer(5)));
+    assert!(follower.promote().is_err());
+    assert!(primary.promote().is_err());
+
+    drop(follower);
+    let reopened = Engine::open(EngineOptions::btree(&follower_path)).unwrap();
+    assert_eq!(count(&reopened, "items"), Some(Value::Integer(5)));
+
+    drop((reopened, writer, primary));
+    for path in [&primary_path, &follower_path] {
+        cleanup_storage_files(path);
+    }
+}
+
+#[test]
+fn restarted_primary_ships_a_new_image() {
+    let primary_path = unique_temp_path("primary.db");
+    let follower_path = unique_temp_path("follower.db");
+    let log_dir = unique_temp_path("log");
+    let shipping = || {
+        EngineOptions::btree(&primary_path)
+            .with_log_shipping(ReplicationTarget::Directory(log_dir.clone()))
+    };
+
+    let primary = Engine::open(shipping()).unwrap();
+    primary
+        .session()
+        .execute_script(
+            "CREATE TABLE items (id INTEGER);
+             INSERT INTO items VALUES (1);
+             INSERT INTO items VALUES (2);",
+        )
+        .unwrap();
+    assert_eq!(shipped_records(&log_dir).len(), 4);
+    drop(primary);
+
+    // Records before the new image are no longer needed.
+    let primary = Engine::open(shipping()).unwrap();
+    assert_eq!(
+        shipped_records(&log_dir),
+        vec!["00000000000000000005.image".to_string()]
+    );
+    primary
+        .session()
+        .execute_one("INSERT INTO items VALUES (3)")
+        .unwrap();
+
+    let follower = Engine::open(EngineOptions::follower(
+        &follower_path,
+        ReplicationSource::Directory(log_dir.clone()),
+    ))
+    .unwrap();
+    assert_eq!(count(&follower, "items"), Some(Value::Integer(3)));
+
+    // A restarted follower picks up where the log stands.
+    drop(follower);
+    primary
+        .session()
+        .execute_one("INSERT INTO items VALUES (4)")
+        .unwrap();
+    let follower = Engine::open(EngineOptions::follower(
+        &follower_path,
+        ReplicationSource::Directory(log_dir.clone()),
+    ))
+    .unwrap();
+    assert_eq!(count(&follower, "items"), Some(Value::Integer(4)));
+
+    drop((follower, primary));
+    for path in [&primary_path, &follower_path] {
+        cleanup_storage_files(path);
+    }
+    fs::remove_dir_all(&log_dir).ok();
+}
+
+#[test]
+fn log_shipping_requires_btree_storage() {
+    let (sender, receiver) = log_channel();
+    assert!(
+        Engine::open(
+            EngineOptions::memory().with_log_shipping(ReplicationTarget::Channel(sender.clone()))
+        )
+        .is_err()
+    );
+    assert!(
+        Engine::open(EngineOptions {
+            follow: Some(ReplicationSource::Channel(receiver.clone())),
+            ..EngineOptions::json(unique_temp_path("follower.json"))
+        })
+        .is_err()
+    );
+    assert!(
+        Engine::open(
+            EngineOptions::follower(
+                unique_temp_path("follower.db"),
+                ReplicationSource::Channel(receiver)
+            )
+            .with_log_shipping(ReplicationTarget::Channel(sender))
+        )
+        .is_err()
+    );
+    assert!(Engine::in_memory().unwrap().promote().is_err());
+}
@@ -0,0 +1,331 @@
+mod common;
+use common::*;
+use rustql::{Engine, EngineOptions, Interval, Value};
+use std::path::Path;
+
+fn time(value: &str) -> Value {
+    Value::Time(value.to_string())
+}
+
+fn datetime(value: &str) -> Value {
+    Value::DateTime(value.to_string())
+}
+
+fn timestamptz(value: &str) -> Value {
+    Value::TimestampTz(value.to_string())
+}
+
+#[test]
+fn times_keep_fractional_seconds() {
+    let engine = memory_engine();
+    let mut session = engine.session();
+    session
+        .execute_one("CREATE TABLE events (id INTEGER, at DATETIME, clock TIME)")
+        .unwrap();
+    session
+        .execute_one(
+            "INSERT INTO events VALUES \
+             (1, '2024-05-01 10:00:00.25', '10:00:00.250'), \
+             (2, '2024-05-01 10:00:00', '10:00:00'), \
+             (3, '2024-05-01 10:00:00.123456', '09:59:59.999999')",
+        )
+        .unwrap();
+
+    assert_eq!(
+        rows(&engine, "SELECT id, at, clock FROM events ORDER BY at"),
+        vec![
+            vec![
+                Value::Integer(2),
+                datetime("2024-05-01 10:00:00"),
+                time("10:00:00")
+            ],
+            vec![
+                Value::Integer(3),
+                datetime("2024-05-01 10:00:00.123456"),
+                time("09:59:59.999999")
+            ],
+            vec![
+                Value::Integer(1),
+                datetime("2024-05-01 10:00:00.25"),
+                time("10:00:00.25")
+            ],
+        ]
+    );
+    assert_eq!(
+        scalar(
+            &engine,
+            "SELECT a.at - b.at FROM events a JOIN events b ON b.id = 3 WHERE a.id = 1"
+        ),
+        Value::Interval(Interval::new(0, 0, 126_544))
+    );
+    assert_eq!(
+        rows(
+            &engine,
+            "SELECT EXTRACT(SECOND FROM at), EXTRACT(MILLISECOND FROM at), \
+             EXTRACT(MICROSECOND FROM at) FROM events WHERE id = 3"
+        ),
+        vec![vec![
+            Value::Integer(0),
+            Value::Integer(123),
+            Value::Integer(123_456)
+        ]]
+    );
+    assert_eq!(
+        scalar(&engine, "SELECT CAST(at AS TIME) FROM events WHERE id = 1"),
+        time("10:00:00.25")
+    );
+    assert_eq!(
+        scalar(
+            &engine,
+            "SELECT at + INTERVAL '0.75 seconds' FROM events WHERE id = 1"
+        ),
+        datetime("2024-05-01 10:00:01")
+    );
+
+    let err = session
+        .execute_one("SELECT CAST('10:00:00.1234567' AS TIME)")
+        .unwrap_err();
+    assert!(err.to_string().contains("HH:MM:SS[.ffffff]"), "{err:?}");
+    assert!(
+        session
+            .execute_one("SELECT CAST('2024-05-01 10:00:00.' AS DATETIME)")
+            .is_err()
+    );
+}
+
+#[test]
+fn timestamptz_input_is_stored_as_utc() {
+    let engine = memory_engine();
+    let mut session = engine.session();
+    session
+        .execute_one("CREATE TABLE logins (id INTEGER, at TIMESTAMP WITH TIME ZONE)")
+        .unwrap();
+    session
+        .execute_one(
+            "INSERT INTO logins VALUES \
+             (1, '2024-01-15 09:30:00+05:30'), \
+             (2, '2024-01-15 04:00:00.5Z'), \
+             (3, '2024-01-15 01:00:00 America/New_York'), \
+             (4, '2024-07-15 01:00:00 america/new_york'), \
+             (5, '2024-01-15 04:00:00')",
+        )
+        .unwrap();
+
+    assert_eq!(
+        rows(&engine, "SELECT id, at FROM logins ORDER BY at, id"),
+        vec![
+            vec![Value::Integer(1), timestamptz("2024-01-15 04:00:00")],
+            vec![Value::Integer(5), timestamptz("2024-01-15 04:00:00")],
+            vec![Value::Integer(2), timestamptz("2024-01-15 04:00:00.5")],
+            vec![Value::Integer(3), timestamptz("2024-01-15 06:00:00")],
+            vec![Value::Integer(4), timestamptz("2024-07-15 05:00:00")],
+        ]
+    );
+    assert_eq!(
+        scalar(&engine, "SELECT CAST(at AS TEXT) FROM logins WHERE id = 2"),
+        text("2024-01-15 04:00:00.5+00")
+    );
+    assert_eq!(rows(&engine, "DESCRIBE logins")[1][1], text("TIMESTAMPTZ"));
+    assert_eq!(
+        scalar(
+            &engine,
+            "SELECT COUNT(*) FROM logins WHERE at = CAST('2024-01-15 04:00:00' AS DATETIME)"
+        ),
+        Value::Integer(2)
+    );
+    assert_eq!(
+        scalar(
+            &engine,
+            "SELECT at + INTERVAL '1 day' FROM logins WHERE id = 3"
+        ),
+        timestamptz("2024-01-16 06:00:00")
+    );
+
+    let err = session
+        .execute_one("INSERT INTO logins VALUES (6, '2024-01-15 04:00:00 Mars/Olympus')")
+        .unwrap_err();
+    assert!(err.to_string().contains("TIMESTAMPTZ"), "{err:?}");
+}
+
+#[test]
+fn at_time_zone_converts_between_local_and_utc() {
+    let engine = memory_engine();
+    let mut session = engine.session();
+    session
+        .execute_one("CREATE TABLE instants (id INTEGER, at TIMESTAMPTZ, local DATETIME)")
+        .unwrap();
+    session
+        .execute_one(
+            "INSERT INTO instants VALUES \
+             (1, '2024-01-15 12:00:00', '2024-03-10 02:30:00'), \
+             (2, '2024-07-15 12:00:00', '2024-11-03 01:30:00'), \
+             (3, '2024-03-31 00:59:59.5', '2024-07-01 12:00:00'), \
+             (4, '2024-03-31 01:00:00', '2024-01-15 12:00:00')",
+        )
+        .unwrap();
+
+    assert_eq!(
+        rows(
+            &engine,
+            "SELECT at AT TIME ZONE 'America/New_York', at AT TIME ZONE 'Australia/Sydney', \
+             at AT TIME ZONE '+05:30' FROM instants WHERE id <= 2 ORDER BY id"
+        ),
+        vec![
+            vec![
+                datetime("2024-01-15 07:00:00"),
+                datetime("2024-01-15 23:00:00"),
+                datetime("2024-01-15 17:30:00"),
+            ],
+            vec![
+                datetime("2024-07-15 08:00:00"),
+                datetime("2024-07-15 22:00:00"),
+                datetime("2024-07-15 17:30:00"),
+            ],
+        ]
+    );
+    // Clocks in London go forward at 01:00 UTC on the last Sunday of March.
+    assert_eq!(
+        rows(
+            &engine,
+            "SELECT at AT TIME ZONE 'Europe/London' FROM instants WHERE id IN (3, 4) ORDER BY id"
+        ),
+        vec![
+            vec![datetime("2024-03-31 00:59:59.5")],
+            vec![datetime("2024-03-31 02:00:00")],
+        ]
+    );
+    // Skipped and repeated local times take the standard offset.
+    assert_eq!(
+        rows(
+            &engine,
+            "SELECT local AT TIME ZONE 'America/New_York' FROM instants ORDER BY id"
+        ),
+        vec![
+            vec![timestamptz("2024-03-10 07:30:00")],
+            vec![timestamptz("2024-11-03 06:30:00")],
+            vec![timestamptz("2024-07-01 16:00:00")],
+            vec![timestamptz("2024-01-15 17:00:00")],
+        ]
+    );
+    assert_eq!(
+        scalar(
+            &engine,
+            "SELECT (local AT TIME ZONE 'Europe/Paris') AT TIME ZONE 'Asia/Tokyo' \
+             FROM instants WHERE id = 3"
+        ),
+        datetime("2024-07-01 19:00:00")
+    );
+    assert_eq!(
+        scalar(
+            &engine,
+            "SELECT id FROM instants WHERE at AT TIME ZONE 'Asia/Kolkata' = \
+             CAST('2024-01-15 17:30:00' AS DATETIME)"
+        ),
+        Value::Integer(1)
+    );
+
+    let err = session
+        .execute_one("SELECT at AT TIME ZONE 'Mars/Olympus' FROM instants")
+        .unwrap_err();
+    assert!(err.to_string().contains("Unknown time zone"), "{err:?}");
+    assert!(
+        session
+            .execute_one("SELECT id AT TIME ZONE 'UTC' FROM instants")
+            .is_err()
+    );
+}
+
+#[test]
+fn now_returns_a_timestamptz() {
+    let engine = memory_engine();
+    let mut session = engine.session();
+
+    let Value::TimestampTz(now) = scalar(&engine, "SELECT NOW()") else {
+        panic!("NOW() should return a TIMESTAMPTZ");
+    };
+    assert!(now.starts_with("20"), "{now}");
+    assert!(matches!(
+        scalar(&engine, "SELECT NOW() AT TIME ZONE 'Europe/Berlin'"),
+        Value::DateTime(_)
+    ));
+    assert_eq!(
+        scalar(
+            &engine,
+            "SELECT NOW() > CAST('2020-01-01 00:00:00' AS DATETIME)"
+        ),
+        Value::Boolean(true)
+    );
+
+    session
+        .execute_one("CREATE TABLE audit (id INTEGER, at DATETIME)")
+        .unwrap();
+    session
+        .execute_one("INSERT INTO audit VALUES (1, NOW())")
+        .unwrap();
+    assert!(matches!(
+        scalar(&engine, "SELECT at FROM audit"),
+        Value::DateTime(_)
+    ));
+}
+
+fn check_timestamps_survive_reopen(path: &Path, options: impl Fn() -> EngineOptions) {
+    {
+        let engine = Engine::open(options()).unwrap();
+        let mut session = engine.session();
+        session
+            .execute_one(
+                "CREATE TABLE readings (id INTEGER PRIMARY KEY, at TIMESTAMPTZ, local DATETIME, clock TIME)",
+            )
+            .unwrap();
+        session
+            .execute_one("CREATE INDEX idx_at ON readings (at)")
+            .unwrap();
+        session
+            .execute_one(
+                "INSERT INTO readings VALUES \
+                 (1, '2024-02-01 08:00:00.125-03:00', '2024-02-01 08:00:00.125', '08:00:00.125'), \
+                 (2, NULL, '2024-02-01 08:00:00', '08:00:00')",
+            )
+            .unwrap();
+    }
+
+    let engine = Engine::open(options()).unwrap();
+    assert_eq!(
+        rows(&engine, "SELECT at, local, clock FROM readings ORDER BY id"),
+        vec![
+            vec![
+                timestamptz("2024-02-01 11:00:00.125"),
+                datetime("2024-02-01 08:00:00.125"),
+                time("08:00:00.125"),
+            ],
+            vec![
+                Value::Null,
+                datetime("2024-02-01 08:00:00"),
+                time("08:00:00")
+            ],
+        ]
+    );
+    assert_eq!(
+        scalar(
+            &engine,
+            "SELECT id FROM readings WHERE at = CAST('2024-02-01 11:00:00.125+00' AS TIMESTAMPTZ)"
+        ),
+        Value::Integer(1)
+    );
+    drop(engine);
+    cleanup_storage_files(path);
+}
+
+#[test]
+fn timestamps_survive_reopening_a_btree_file() {
+    let path = unique_temp_path("btree.db");
+    cleanup_storage_files(&path);
+    check_timestamps_survive_reopen(&path, || EngineOptions::btree(&path));
+}
+
+#[test]
+fn timestamps_survive_reopening_a_json_file() {
+    let path = unique_temp_path("json.db");
+    cleanup_storage_files(&path);
+    check_timestamps_survive_reopen(&path, || EngineOptions::json(&path));
+}
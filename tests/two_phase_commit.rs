@@ -0,0 +1,387 @@
+mod common;
+use common::*;
+use rustql::{ChangeEvent, ChangeKind, CommandTag, Engine, EngineOptions, RustqlError, Value};
+
+fn item_names(engine: &Engine) -> Vec<Vec<Value>> {
+    rows(engine, "SELECT name FROM items ORDER BY id")
+}
+
+fn prepared_gids(engine: &Engine) -> Vec<Vec<Value>> {
+    rows(engine, "SELECT gid FROM pg_prepared_xacts ORDER BY gid")
+}
+
+fn engine_with_items(options: EngineOptions) -> Engine {
+    let engine = Engine::open(options).unwrap();
+    engine
+        .session()
+        .execute_script(
+            "CREATE TABLE items (id INTEGER PRIMARY KEY, name TEXT);
+             INSERT INTO items VALUES (1, 'a'), (2, 'b');",
+        )
+        .unwrap();
+    engine
+}
+
+/// Prepares a transaction that inserts, updates, and deletes a row.
+fn prepare_changes(engine: &Engine, gid: &str) {
+    let mut session = engine.session();
+    session
+        .execute_script(
+            "BEGIN;
+             INSERT INTO items VALUES (3, 'c');
+             UPDATE items SET name = 'bb' WHERE id = 2;
+             DELETE FROM items WHERE id = 1;",
+        )
+        .unwrap();
+    assert_eq!(
+        session
+            .execute_one(&format!("PREPARE TRANSACTION '{gid}'"))
+            .unwrap(),
+        command(CommandTag::PrepareTransaction)
+    );
+    // The session is out of the transaction, like after COMMIT.
+    assert!(session.execute_one("COMMIT").is_err());
+}
+
+#[test]
+fn prepared_transaction_is_applied_by_commit_prepared() {
+    let engine = engine_with_items(EngineOptions::memory());
+    let stream = engine.subscribe_changes();
+    prepare_changes(&engine, "tx1");
+
+    // Nothing is visible until COMMIT PREPARED.
+    assert_eq!(item_names(&engine), vec![vec![text("a")], vec![text("b")]]);
+    assert_eq!(prepared_gids(&engine), vec![vec![text("tx1")]]);
+    assert!(stream.try_next().is_none());
+
+    // Other inserts go ahead, and the prepared insert gets the next row id.
+    engine
+        .session()
+        .execute_one("INSERT INTO items VALUES (4, 'd')")
+        .unwrap();
+    let inserted = stream.try_next().unwrap();
+
+    assert_eq!(
+        engine
+            .session()
+            .execute_one("COMMIT PREPARED 'tx1'")
+            .unwrap(),
+        command(CommandTag::CommitPrepared)
+    );
+    assert_eq!(
+        item_names(&engine),
+        vec![vec![text("bb")], vec![text("c")], vec![text("d")]]
+    );
+    assert!(prepared_gids(&engine).is_empty());
+
+    let events: Vec<ChangeEvent> = std::iter::from_fn(|| stream.try_next()).collect();
+    let kinds: Vec<_> = events.iter().map(|event| event.kind).collect();
+    assert_eq!(
+        kinds,
+        vec![ChangeKind::Insert, ChangeKind::Update, ChangeKind::Delete]
+    );
+    assert!(events[0].row_id > inserted.row_id);
+    assert!(
+        events
+            .iter()
+            .all(|event| event.transaction_id > inserted.transaction_id)
+    );
+}
+
+#[test]
+fn rollback_prepared_discards_the_transaction() {
+    let engine = engine_with_items(EngineOptions::memory());
+    prepare_changes(&engine, "tx1");
+    assert_eq!(
+        engine
+            .session()
+            .execute_one("ROLLBACK PREPARED 'tx1'")
+            .unwrap(),
+        command(CommandTag::RollbackPrepared)
+    );
+    assert_eq!(item_names(&engine), vec![vec![text("a")], vec![text("b")]]);
+    assert!(prepared_gids(&engine).is_empty());
+
+    // The rows are free again.
+    engine
+        .session()
+        .execute_one("UPDATE items SET name = 'x' WHERE id = 2")
+        .unwrap();
+}
+
+#[test]
+fn prepared_writes_hold_their_rows_until_resolved() {
+    let engine = engine_with_items(EngineOptions::memory());
+    prepare_changes(&engine, "tx1");
+
+    let held = [
+        "UPDATE items SET name = 'x' WHERE id = 2",
+        "DELETE FROM items WHERE id = 1",
+        "DROP TABLE items",
+    ];
+    for sql in held {
+        let err = engine.session().execute_one(sql).unwrap_err();
+        assert!(
+            matches!(&err, RustqlError::SerializationFailure(msg) if msg.contains("'tx1'")),
+            "unexpected error for {sql}: {err:?}"
+        );
+    }
+
+    // A transaction that started before the PREPARE fails at COMMIT.
+    let mut session = engine.session();
+    session.execute_one("BEGIN").unwrap();
+    session
+        .execute_one("INSERT INTO items VALUES (5, 'e')")
+        .unwrap();
+    session
+        .execute_one("UPDATE items SET name = 'y' WHERE id = 2")
+        .unwrap();
+    assert!(matches!(
+        session.execute_one("COMMIT"),
+        Err(RustqlError::SerializationFailure(_))
+    ));
+
+    // So does a second prepared transaction touching the same row.
+    session
+        .execute_script("BEGIN; DELETE FROM items WHERE id = 2;")
+        .unwrap();
+    assert!(matches!(
+        session.execute_one("PREPARE TRANSACTION 'tx2'"),
+        Err(RustqlError::SerializationFailure(_))
+    ));
+
+    // Rows the prepared transaction did not write are not held.
+    session
+        .execute_script("BEGIN; INSERT INTO items VALUES (6, 'f'); COMMIT;")
+        .unwrap();
+    engine
+        .session()
+        .execute_one("COMMIT PREPARED 'tx1'")
+        .unwrap();
+    assert_eq!(
+        item_names(&engine),
+        vec![vec![text("bb")], vec![text("c")], vec![text("f")]]
+    );
+}
+
+/// Prepares a transaction that inserts item 3 and a tag on item 1, then
+/// checks that writes it could not commit after are refused until COMMIT
+/// PREPARED, which then succeeds. `reopen` gets the engine back after the
+/// PREPARE, or a new one on the same storage.
+fn check_prepared_keys_are_held(engine: Engine, reopen: impl FnOnce(Engine) -> Engine) {
+    engine
+        .session()
+        .execute_script(
+            "CREATE TABLE tags (item_id INTEGER FOREIGN KEY REFERENCES items(id), tag TEXT UNIQUE);
+             BEGIN;
+             INSERT INTO items VALUES (3, 'c');
+             INSERT INTO tags VALUES (1, 'red');
+             PREPARE TRANSACTION 'g1';",
+        )
+        .unwrap();
+    let engine = reopen(engine);
+
+    let clashing = [
+        "INSERT INTO items VALUES (3, 'other')",
+        "INSERT INTO tags VALUES (2, 'red')",
+        "DELETE FROM items WHERE id = 1",
+        "BEGIN; INSERT INTO items VALUES (3, 'other'); COMMIT;",
+    ];
+    for sql in clashing {
+        let mut session = engine.session();
+        let err = session.execute_script(sql).unwrap_err();
+        assert!(
+            matches!(&err, RustqlError::SerializationFailure(msg) if msg.contains("'g1'")),
+            "unexpected error for {sql}: {err:?}"
+        );
+    }
+
+    engine
+        .session()
+        .execute_one("INSERT INTO items VALUES (4, 'd')")
+        .unwrap();
+    engine
+        .session()
+        .execute_one("COMMIT PREPARED 'g1'")
+        .unwrap();
+    assert_eq!(
+        item_names(&engine),
+        vec![
+            vec![text("a")],
+            vec![text("b")],
+            vec![text("c")],
+            vec![text("d")]
+        ]
+    );
+    assert_eq!(
+        rows(&engine, "SELECT item_id, tag FROM tags"),
+        vec![vec![Value::Integer(1), text("red")]]
+    );
+}
+
+#[test]
+fn prepared_transaction_holds_its_keys_against_other_sessions() {
+    check_prepared_keys_are_held(engine_with_items(EngineOptions::memory()), |engine| engine);
+}
+
+#[test]
+fn prepared_transaction_holds_its_keys_after_a_restart() {
+    let path = unique_temp_path("held.db");
+    check_prepared_keys_are_held(engine_with_items(EngineOptions::btree(&path)), |engine| {
+        drop(engine);
+        Engine::open(EngineOptions::btree(&path)).unwrap()
+    });
+    cleanup_storage_files(&path);
+}
+
+#[test]
+fn prepared_transactions_are_listed_in_a_catalog_view() {
+    let engine = engine_with_items(EngineOptions::memory());
+    assert!(prepared_gids(&engine).is_empty());
+
+    let mut session = engine.session();
+    session
+        .execute_script(
+            "BEGIN; INSERT INTO items VALUES (3, 'c'); PREPARE TRANSACTION 'b-tx';
+             BEGIN; CREATE TABLE other (id INTEGER); PREPARE TRANSACTION 'a-tx';",
+        )
+        .unwrap();
+    assert_eq!(
+        prepared_gids(&engine),
+        vec![vec![text("a-tx")], vec![text("b-tx")]]
+    );
+    match &rows(
+        &engine,
+        "SELECT prepared FROM pg_prepared_xacts WHERE gid = 'a-tx'",
+    )[..]
+    {
+        [row] => assert!(matches!(&row[0], Value::DateTime(at) if at.len() == 19)),
+        other => panic!("expected one prepared transaction, got {other:?}"),
+    }
+    assert!(engine.session().execute_one("SELECT * FROM other").is_err());
+
+    session
+        .execute_script("COMMIT PREPARED 'a-tx'; ROLLBACK PREPARED 'b-tx';")
+        .unwrap();
+    assert!(prepared_gids(&engine).is_empty());
+    assert_eq!(
+        rows(&engine, "SELECT COUNT(*) FROM other"),
+        vec![vec![Value::Integer(0)]]
+    );
+    assert_eq!(
+        rows(&engine, "SELECT COUNT(*) FROM items"),
+        vec![vec![Value::Integer(2)]]
+    );
+
+    // A table of the same name hides the view.
+    session
+        .execute_script(
+            "CREATE TABLE pg_prepared_xacts (gid TEXT);
+             INSERT INTO pg_prepared_xacts VALUES ('mine');",
+        )
+        .unwrap();
+    assert_eq!(prepared_gids(&engine), vec![vec![text("mine")]]);
+}
+
+#[test]
+fn two_phase_commit_statements_report_misuse() {
+    let engine = engine_with_items(EngineOptions::memory());
+    let mut session = engine.session();
+    assert!(matches!(
+        session.execute_one("PREPARE TRANSACTION 'tx1'"),
+        Err(RustqlError::TransactionError(_))
+    ));
+    assert!(matches!(
+        session.execute_one("COMMIT PREPARED 'missing'"),
+        Err(RustqlError::TransactionError(msg)) if msg.contains("does not exist")
+    ));
+    assert!(session.execute_one("ROLLBACK PREPARED 'missing'").is_err());
+    assert!(session.execute_one("PREPARE TRANSACTION tx1").is_err());
+
+    prepare_changes(&engine, "tx1");
+    session
+        .execute_script("BEGIN; INSERT INTO items VALUES (7, 'g');")
+        .unwrap();
+    assert!(matches!(
+        session.execute_one("PREPARE TRANSACTION 'tx1'"),
+        Err(RustqlError::TransactionError(msg)) if msg.contains("already in use")
+    ));
+    assert!(matches!(
+        session.execute_one("COMMIT PREPARED 'tx1'"),
+        Err(RustqlError::TransactionError(msg)) if msg.contains("inside a transaction")
+    ));
+    session.execute_one("PREPARE TRANSACTION 'tx2'").unwrap();
+    assert_eq!(
+        prepared_gids(&engine),
+        vec![vec![text("tx1")], vec![text("tx2")]]
+    );
+}
+
+/// Prepares two transactions on the storage `open` reads, then commits one
+/// and rolls back the other, each after a restart.
+fn check_prepared_transactions_survive_restarts(open: impl Fn() -> Engine) {
+    let engine = open();
+    engine
+        .session()
+        .execute_script(
+            "CREATE TABLE items (id INTEGER PRIMARY KEY, name TEXT);
+             INSERT INTO items VALUES (1, 'a'), (2, 'b');",
+        )
+        .unwrap();
+    prepare_changes(&engine, "committed");
+    engine
+        .session()
+        .execute_script(
+            "BEGIN;
+             CREATE TABLE extra (id INTEGER);
+             INSERT INTO extra VALUES (1);
+             CREATE VIEW named AS SELECT name FROM items;
+             PREPARE TRANSACTION 'rolled back';",
+        )
+        .unwrap();
+    drop(engine);
+
+    let engine = open();
+    assert_eq!(
+        prepared_gids(&engine),
+        vec![vec![text("committed")], vec![text("rolled back")]]
+    );
+    assert_eq!(item_names(&engine), vec![vec![text("a")], vec![text("b")]]);
+    engine
+        .session()
+        .execute_one("COMMIT PREPARED 'committed'")
+        .unwrap();
+    drop(engine);
+
+    let engine = open();
+    assert_eq!(prepared_gids(&engine), vec![vec![text("rolled back")]]);
+    assert_eq!(item_names(&engine), vec![vec![text("bb")], vec![text("c")]]);
+    engine
+        .session()
+        .execute_one("ROLLBACK PREPARED 'rolled back'")
+        .unwrap();
+    drop(engine);
+
+    let engine = open();
+    assert!(prepared_gids(&engine).is_empty());
+    assert!(engine.session().execute_one("SELECT * FROM extra").is_err());
+    assert!(engine.session().execute_one("SELECT * FROM named").is_err());
+}
+
+#[test]
+fn prepared_transaction_survives_a_restart_with_btree_storage() {
+    let path = unique_temp_path("items.db");
+    check_prepared_transactions_survive_restarts(|| {
+        Engine::open(EngineOptions::btree(&path)).unwrap()
+    });
+    cleanup_storage_files(&path);
+}
+
+#[test]
+fn prepared_transaction_survives_a_restart_with_json_storage() {
+    let path = unique_temp_path("items.json");
+    check_prepared_transactions_survive_restarts(|| {
+        Engine::open(EngineOptions::json(&path)).unwrap()
+    });
+    cleanup_storage_files(&path);
+}